@@ -49,6 +49,40 @@ defmodule RustyXML do
   - Boolean: `boolean()`, `not()`, `true()`, `false()`, `lang()`
   - Number: `number()`, `sum()`, `floor()`, `ceiling()`, `round()`
 
+  The `rx:` extensions parse ISO 8601 date/times so they can be compared:
+  `rx:dt(s)` returns seconds since the Unix epoch (NaN if `s` doesn't parse),
+  and `rx:dateTime-greater-than/less-than/equal(a, b)` compare two values.
+
+      RustyXML.xpath(doc, "//event[rx:dt(@at) > rx:dt('2024-01-01T00:00:00Z')]")
+
+  ## Scheduling
+
+  Queries on a parsed document run on a normal scheduler, which is fine for
+  typical documents. Once a document has more than 50,000 nodes, `xpath/2`
+  and `root/1` switch to dirty CPU scheduler variants so a long query cannot
+  block other processes. The threshold is configurable at compile time:
+
+      config :rusty_xml, dirty_node_threshold: 100_000
+
+  Parsing functions that take raw XML run on the dirty CPU scheduler, except
+  that `xpath/2` on raw XML of under 1 KB parses and queries it on a normal
+  scheduler, where it takes less time than switching schedulers would. That
+  threshold is configurable too:
+
+      config :rusty_xml, small_input_bytes: 4096
+
+  ## Concurrency
+
+  A parsed document is immutable, so the same document reference can be
+  queried from any number of processes at once. Queries take no locks and
+  do not serialize against each other; there is no need to copy or re-parse
+  a document per process. Sending the reference to another process only
+  copies the reference, and the document is freed once no process holds it.
+
+  The optional result cache (`RustyXML.Native.doc_set_result_cache/2`) and
+  attribute indexes (`RustyXML.Native.doc_build_attr_index/2`) are the one
+  exception: while in use, queries briefly lock them.
+
   ## Streaming
 
   For large files, use the streaming API:
@@ -61,17 +95,46 @@ defmodule RustyXML do
 
   alias RustyXML.Native
 
+  @dirty_node_threshold Application.compile_env(:rusty_xml, :dirty_node_threshold, 50_000)
+  @small_input_bytes Application.compile_env(:rusty_xml, :small_input_bytes, 1024)
+
+  # Subspec paths that Rust returns as maps instead of node lists
+  @map_subspecs ["@*", "./*"]
+
   # ==========================================================================
   # Types
   # ==========================================================================
 
   @type document :: Native.document_ref()
-  @type xml_node :: {:element, binary(), [{binary(), binary()}], [xml_node() | binary()]}
+  @type xml_node ::
+          {:element, binary(), [{binary(), binary()}], [xml_node() | binary()]}
+          | {:comment, binary()}
+          | {:pi, binary(), binary()}
+  @type element_map :: %{
+          name: binary(),
+          attrs: %{binary() => binary()},
+          children: [element_map()],
+          text: binary() | nil
+        }
   @type handler :: module()
   @type parse_option ::
           {:cdata_as_characters, boolean()}
           | {:expand_entity, :keep | :skip | (String.t() -> String.t())}
+          | {:text_chunk_size, pos_integer()}
+          | {:event_format, :v1 | :v2}
+          | {:duplicate_ids, boolean()}
+          | {:max_tail_size, non_neg_integer()}
+          | {:max_attributes, non_neg_integer()}
+          | {:max_elements, non_neg_integer()}
+          | {:on_limit, :error | :warn}
+          | {:cancel_token, Native.cancel_token_ref()}
+          | {:raw_text, boolean()}
+          | {:normalize, Native.normalization()}
   @type parse_options :: [parse_option()]
+  @type subspec_type :: :string | :integer | :float | :boolean | :list | :node
+  @type subspec ::
+          {atom(), SweetXpath.t() | binary()}
+          | {atom(), SweetXpath.t() | binary(), subspec_type()}
 
   # ==========================================================================
   # SweetXpath Struct (SweetXml compatible)
@@ -147,6 +210,19 @@ defmodule RustyXML do
 
     * `:lenient` - If `true`, accept malformed XML without raising.
       Useful for processing third-party or legacy XML. Default: `false`.
+    * `:normalize` - `:nfc` or `:nfd` to return text values (and compare
+      strings in XPath predicates) in that Unicode normalization form.
+      See `RustyXML.Native.doc_set_normalization/2`. Default: `:none`.
+    * `:namespaces` - If `true`, also apply the Namespaces in XML checks in
+      strict mode: prefixes must be declared, reserved prefixes used
+      correctly, and attributes such as `a:x` and `b:x`, whose prefixes are
+      bound to the same URI, are duplicates. See
+      `RustyXML.Native.validate_namespaces/1`. Ignored with `lenient: true`.
+      Default: `false`.
+    * `:precompute_text` - If `true`, lay out the string-value of every
+      element at parse time, so queries that read the text of most elements
+      slice a buffer instead of walking subtrees. See
+      `RustyXML.Native.doc_precompute_text/1`. Default: `false`.
 
   ## Examples
 
@@ -170,14 +246,256 @@ defmodule RustyXML do
   end
 
   def parse(xml, opts) when is_binary(xml) do
-    if Keyword.get(opts, :lenient, false) do
-      Native.parse(xml)
-    else
-      case Native.parse_strict(xml) do
-        {:ok, doc} -> doc
-        {:error, reason} -> raise ParseError, message: reason
+    doc =
+      cond do
+        Keyword.get(opts, :lenient, false) ->
+          case Native.parse(xml) do
+            {:error, _} = error -> unwrap_parsed(error)
+            doc -> doc
+          end
+
+        Keyword.get(opts, :namespaces, false) ->
+          xml |> Native.parse_strict_namespaces() |> unwrap_parsed()
+
+        true ->
+          xml |> Native.parse_strict() |> unwrap_parsed()
       end
+
+    doc
+    |> apply_normalization(opts)
+    |> apply_precompute_text(opts)
+  end
+
+  @doc """
+  Parse an XML fragment: content with any number of top-level elements,
+  optionally mixed with text, such as table rows cut from a larger document.
+
+  The top-level nodes become children of the document node, so absolute
+  paths address them directly (`/tr`, `//td`). A leading `<?xml ...?>`
+  declaration is ignored. Malformed content raises `RustyXML.ParseError`.
+
+  ## Options
+
+  Same as `parse/2`.
+
+  ## Examples
+
+      doc = RustyXML.parse_fragment("<tr><td>1</td></tr><tr><td>2</td></tr>")
+      RustyXML.xpath(doc, ~x"/tr/td/text()"ls)
+      #=> ["1", "2"]
+
+  """
+  @spec parse_fragment(binary() | charlist(), keyword()) :: document()
+  def parse_fragment(xml, opts \\ [])
+
+  def parse_fragment(xml, opts) when is_list(xml) do
+    parse_fragment(IO.chardata_to_string(xml), opts)
+  end
+
+  def parse_fragment(xml, opts) when is_binary(xml) do
+    xml
+    |> Native.parse_fragment(Keyword.get(opts, :lenient, false))
+    |> unwrap_parsed()
+    |> apply_normalization(opts)
+    |> apply_precompute_text(opts)
+  end
+
+  @doc """
+  Parse an XML document in strict mode, reusing the document parsed from
+  identical input earlier, by this or any other process.
+
+  Suited to payloads parsed over and over, such as configuration. Cached
+  documents are shared, so their settings can't be changed after parsing. See
+  `RustyXML.Native.parse_cached/1` and `RustyXML.Native.set_cache_capacity/1`.
+  Malformed XML raises `RustyXML.ParseError`.
+  """
+  @spec parse_cached(binary() | charlist()) :: document()
+  def parse_cached(xml) when is_list(xml), do: parse_cached(IO.chardata_to_string(xml))
+
+  def parse_cached(xml) when is_binary(xml) do
+    xml |> Native.parse_cached() |> unwrap_parsed()
+  end
+
+  @doc """
+  Parse an XML document into nested maps, one per element.
+
+  Each element becomes a map with `:name`, `:attrs` (a map of attribute
+  names to values), `:children` (child elements only, in document order)
+  and `:text`, the element's own text and CDATA joined together, or `nil`
+  when it has none. Comments and processing instructions are dropped, and
+  so is the position of text among child elements in mixed content.
+
+  The maps are built in Rust, so they work with `Access` and `get_in/2`
+  without converting a tuple tree first. Malformed XML raises
+  `RustyXML.ParseError`.
+
+  ## Options
+
+    * `:normalize` - `:nfc` or `:nfd` to return text and attribute values
+      in that Unicode normalization form. Default: `:none`.
+
+  ## Examples
+
+      map = RustyXML.parse_to_map(~s(<order id="7"><item>Tea</item></order>))
+      get_in(map, [:attrs, "id"])
+      #=> "7"
+      map.children
+      #=> [%{name: "item", attrs: %{}, children: [], text: "Tea"}]
+
+  """
+  @spec parse_to_map(binary() | charlist(), keyword()) :: element_map()
+  def parse_to_map(xml, opts \\ [])
+
+  def parse_to_map(xml, opts) when is_list(xml) do
+    parse_to_map(IO.chardata_to_string(xml), opts)
+  end
+
+  def parse_to_map(xml, opts) when is_binary(xml) do
+    xml
+    |> Native.parse_to_map(Keyword.get(opts, :normalize, :none))
+    |> unwrap_parsed()
+  end
+
+  @doc """
+  Parse XML that may have been cut off, such as a partial feed download.
+
+  Never raises for malformed content: everything before the cut point is
+  indexed, a tag cut off midway is dropped, and elements still open at the
+  end of input are closed there. Use `doc_truncated?/1` to find out whether
+  the input was complete. Records that ended before the cut are intact, so
+  a query can still extract them; the last, partial record keeps whatever
+  content it had.
+
+  ## Options
+
+  Same as `parse/2`, except `:lenient` and `:namespaces`, which don't apply.
+
+  ## Examples
+
+      doc = RustyXML.parse_salvage("<feed><entry>a</entry><entry>b</entry><entr")
+      RustyXML.doc_truncated?(doc)
+      #=> true
+      RustyXML.xpath(doc, ~x"//entry/text()"ls)
+      #=> ["a", "b"]
+
+  """
+  @spec parse_salvage(binary() | charlist(), keyword()) :: document()
+  def parse_salvage(xml, opts \\ [])
+
+  def parse_salvage(xml, opts) when is_list(xml) do
+    parse_salvage(IO.chardata_to_string(xml), opts)
+  end
+
+  def parse_salvage(xml, opts) when is_binary(xml) do
+    xml
+    |> Native.parse_salvage()
+    |> unwrap_parsed()
+    |> apply_normalization(opts)
+    |> apply_precompute_text(opts)
+  end
+
+  @doc """
+  Whether a document from `parse_salvage/2` was parsed from input that was
+  cut off. Always `false` for documents from the other parse functions.
+
+  ## Examples
+
+      RustyXML.doc_truncated?(RustyXML.parse_salvage("<feed><entry>"))
+      #=> true
+
+  """
+  @spec doc_truncated?(document()) :: boolean()
+  def doc_truncated?(doc), do: Native.doc_truncated(doc)
+
+  @doc """
+  Parse a large document in the background, reporting progress.
+
+  Returns a task handle at once. The index is built on a pool of background
+  workers, one per core, which sends the owner `{:rustyxml_parse, task,
+  {:progress, bytes_done, total}}` messages as it goes and finally
+  `{:rustyxml_parse, task, {:ok, doc}}` (or `{:error, :cancelled}`,
+  `{:error, :memory_limit}` or `{:error, {:max_depth_exceeded, limit}}`, or
+  `{:error, :internal_parser_error}` if indexing panicked). Parsing is
+  lenient, as with `parse(xml, lenient: true)`. Stop a build with
+  `parse_cancel/1`.
+
+  ## Options
+
+    * `:pid` - process receiving the messages (default: `self()`); the
+      build is abandoned if it exits
+    * `:progress_interval` - bytes scanned between progress messages
+      (default: 16 MiB)
+
+  ## Examples
+
+      task = RustyXML.parse_async(File.read!("dump.xml"))
+
+      wait = fn wait ->
+        receive do
+          {:rustyxml_parse, ^task, {:progress, done, total}} ->
+            IO.puts("\#{div(done * 100, total)}%")
+            wait.(wait)
+
+          {:rustyxml_parse, ^task, {:ok, doc}} ->
+            doc
+        end
+      end
+
+      doc = wait.(wait)
+
+  """
+  @spec parse_async(binary(), keyword()) :: Native.parse_task_ref()
+  def parse_async(xml, opts \\ []) when is_binary(xml) do
+    pid = Keyword.get(opts, :pid, self())
+    interval = Keyword.get(opts, :progress_interval, 16 * 1024 * 1024)
+    Native.parse_async(xml, pid, interval)
+  end
+
+  @doc """
+  Abandon a `parse_async/2` build. The owner receives
+  `{:rustyxml_parse, task, {:error, :cancelled}}` unless the build had
+  already finished.
+  """
+  @spec parse_cancel(Native.parse_task_ref()) :: :ok
+  def parse_cancel(task), do: Native.parse_cancel(task)
+
+  defp unwrap_parsed({:ok, doc}), do: doc
+
+  defp unwrap_parsed({:error, {:max_depth_exceeded, limit}}) do
+    raise ParseError, message: "Document nesting exceeds the maximum depth of #{limit}"
+  end
+
+  defp unwrap_parsed({:error, {:unsupported_encoding, encoding}}) do
+    raise ParseError, message: "Unsupported encoding: #{encoding}"
+  end
+
+  defp unwrap_parsed({:error, :memory_limit}) do
+    raise ParseError, message: "Parsing would exceed the NIF memory limit"
+  end
+
+  defp unwrap_parsed({:error, :internal_parser_error}) do
+    raise ParseError, message: "Internal parser error"
+  end
+
+  defp unwrap_parsed({:error, reason}), do: raise(ParseError, message: reason)
+
+  defp apply_normalization(doc, opts) do
+    case Keyword.get(opts, :normalize, :none) do
+      :none ->
+        doc
+
+      form ->
+        :ok = Native.doc_set_normalization(doc, form)
+        doc
+    end
+  end
+
+  defp apply_precompute_text(doc, opts) do
+    if Keyword.get(opts, :precompute_text, false) do
+      :ok = Native.doc_precompute_text(doc)
     end
+
+    doc
   end
 
   @doc """
@@ -192,7 +510,8 @@ defmodule RustyXML do
       {:error, reason} = RustyXML.parse_document("<1invalid/>")
 
   """
-  @spec parse_document(binary() | charlist()) :: {:ok, document()} | {:error, binary()}
+  @spec parse_document(binary() | charlist()) ::
+          {:ok, document()} | {:error, Native.parse_error()}
   def parse_document(xml) when is_binary(xml) do
     Native.parse_strict(xml)
   end
@@ -201,6 +520,302 @@ defmodule RustyXML do
     parse_document(IO.chardata_to_string(xml))
   end
 
+  @doc """
+  Parse an XML document received with a Content-Type header, returning
+  `{:ok, doc}` or `{:error, reason}`.
+
+  The charset of `content_type` decides how the bytes are decoded, unless
+  the input starts with a byte order mark; without either, the encoding
+  named in the XML declaration is used. This is the precedence RFC 7303
+  sets for XML media types. See `RustyXML.Native.parse_with_charset_hint/2`.
+
+  ## Examples
+
+      body = <<"<r>caf", 0xE9, "</r>">>
+      {:ok, doc} = RustyXML.parse_with_charset_hint(body, "text/xml; charset=iso-8859-1")
+
+  """
+  @spec parse_with_charset_hint(binary(), String.t()) ::
+          {:ok, document()} | {:error, Native.parse_error()}
+  def parse_with_charset_hint(xml, content_type)
+      when is_binary(xml) and is_binary(content_type) do
+    Native.parse_with_charset_hint(xml, content_type)
+  end
+
+  @doc """
+  Check whether a document is well-formed, without keeping it.
+
+  Runs only the validation `parse_document/1` does, stopping at the first
+  error, and builds no index, so it is cheaper than parsing when the
+  document isn't needed afterwards. The depth limit is not checked.
+
+  ## Options
+
+    * `:details` - Return `{:error, reason}` instead of `false`, with the
+      reason `parse_document/1` would give. Default: `false`.
+
+  ## Examples
+
+      RustyXML.well_formed?("<root/>")
+      #=> true
+
+      RustyXML.well_formed?("<root>", details: true)
+      #=> {:error, "..."}
+
+  """
+  @spec well_formed?(binary() | charlist(), keyword()) ::
+          boolean() | {:error, Native.parse_error()}
+  def well_formed?(xml, opts \\ [])
+
+  def well_formed?(xml, opts) when is_list(xml) do
+    well_formed?(IO.chardata_to_string(xml), opts)
+  end
+
+  def well_formed?(xml, opts) when is_binary(xml) do
+    case {Native.well_formed(xml), Keyword.get(opts, :details, false)} do
+      {:ok, _} -> true
+      {{:error, _} = error, true} -> error
+      {{:error, _}, false} -> false
+    end
+  end
+
+  @doc """
+  Check the well-formedness of a document given as a stream of chunks,
+  such as `File.stream!/2`, without keeping it in memory.
+
+  Returns `:ok` or `{:error, details}` with the message and position of
+  the first error; the stream is not read past it. See
+  `RustyXML.Native.validator_new/0` for the checks applied.
+
+  ## Examples
+
+      "huge.xml"
+      |> File.stream!(64 * 1024)
+      |> RustyXML.validate_stream()
+      #=> {:error, %{message: "Unclosed tag: <feed>", offset: 4_831_002_112,
+      #=>            line: 90_120_511, column: 1}}
+
+  """
+  @spec validate_stream(Enumerable.t()) :: :ok | {:error, Native.validation_error()}
+  def validate_stream(chunks) do
+    validator = Native.validator_new()
+
+    Enum.reduce_while(chunks, :ok, fn chunk, :ok ->
+      case Native.validator_feed(validator, IO.iodata_to_binary(chunk)) do
+        :ok -> {:cont, :ok}
+        error -> {:halt, error}
+      end
+    end)
+
+    # Errors are sticky, so finishing returns the one that stopped the loop
+    Native.validator_finish(validator)
+  end
+
+  @doc """
+  Rewrite malformed XML into a well-formed document.
+
+  Escapes stray `&` and `<`, drops control characters XML forbids, quotes
+  unquoted attribute values, and closes elements and markup left open.
+  Well-formed input is returned unchanged. Returns `{fixed, repairs}`,
+  where `repairs` lists each change as `{kind, offset}`, with a byte offset
+  into the original input, so callers can log what was fixed. See
+  `RustyXML.Native.repair/2` for the repair kinds.
+
+  ## Options
+
+    * `:root` - Wrap the content in an element of this name when it
+      doesn't have exactly one top-level element.
+
+  ## Examples
+
+      {fixed, repairs} = RustyXML.repair("<p>Fish & Chips<br></p>")
+      fixed
+      #=> "<p>Fish &amp; Chips<br></br></p>"
+      repairs
+      #=> [{:escaped_ampersand, 8}, {:closed_element, 19}]
+
+  """
+  @spec repair(binary() | charlist(), keyword()) :: {binary(), [{atom(), non_neg_integer()}]}
+  def repair(xml, opts \\ [])
+
+  def repair(xml, opts) when is_list(xml), do: repair(IO.chardata_to_string(xml), opts)
+
+  def repair(xml, opts) when is_binary(xml) do
+    Native.repair(xml, Keyword.get(opts, :root))
+  end
+
+  @doc """
+  Split a document into well-formed shards of up to `per_shard` records.
+
+  `record_xpath` selects the records. Each shard repeats the prolog and
+  wraps its records in their original envelope: the root element and any
+  other ancestors, with start tags copied as written so namespace
+  declarations and attributes carry over. Records are copied byte for
+  byte, in document order.
+
+  Raises `RustyXML.XPathError` if the expression fails or doesn't select
+  nodes.
+
+  ## Examples
+
+      doc = RustyXML.parse(~s(<export v="2"><item>a</item><item>b</item><item>c</item></export>))
+      RustyXML.split(doc, "/export/item", 2)
+      #=> [~s(<export v="2"><item>a</item><item>b</item></export>),
+      #=>  ~s(<export v="2"><item>c</item></export>)]
+
+  """
+  @spec split(document(), String.t(), pos_integer()) :: [binary()]
+  def split(doc, record_xpath, per_shard)
+      when is_binary(record_xpath) and is_integer(per_shard) and per_shard > 0 do
+    case Native.split_records(doc, record_xpath, per_shard) do
+      {:ok, shards} -> shards
+      {:error, :internal_parser_error} -> raise XPathError, message: "Internal parser error"
+      {:error, reason} -> raise XPathError, message: reason, xpath: record_xpath
+    end
+  end
+
+  @doc """
+  Replace sensitive content selected by XPath with `replacement`.
+
+  For each node the expressions select, an element's content, a text
+  node's text, or an attribute's value is replaced; an empty element
+  `<a/>` becomes `<a>replacement</a>`. The replacement is escaped for where
+  it lands, and the rest of the document is copied byte for byte from the
+  index's spans, without re-serializing. When selections nest, the
+  outermost wins.
+
+  Raises `RustyXML.XPathError` for an expression that fails, doesn't
+  select nodes, or selects something that can't be redacted (comments,
+  processing instructions, attributes picked by position), so nothing
+  selected is ever left in place.
+
+  ## Examples
+
+      doc = RustyXML.parse(~s(<pay card="4111111111111111"><ssn>078-05-1120</ssn></pay>))
+      RustyXML.redact(doc, ["//ssn", "//@card"], "***")
+      #=> ~s(<pay card="***"><ssn>***</ssn></pay>)
+
+  """
+  @spec redact(document(), [String.t()], String.t()) :: binary()
+  def redact(doc, xpaths, replacement) when is_list(xpaths) and is_binary(replacement) do
+    case Native.redact(doc, xpaths, replacement) do
+      {:ok, redacted} -> redacted
+      {:error, {path, reason}} -> raise XPathError, message: reason, xpath: path
+      {:error, :internal_parser_error} -> raise XPathError, message: "Internal parser error"
+    end
+  end
+
+  @doc """
+  Infer an XSD skeleton from one or more sample documents.
+
+  Each element name under a given parent becomes one declaration. The
+  samples decide the hierarchy (children in order of first appearance, or
+  an `xs:choice` when samples order them differently), which children are
+  optional (`minOccurs="0"`) or repeat (`maxOccurs="unbounded"`), which
+  attributes are required, and value types: `xs:boolean`, `xs:integer`,
+  `xs:decimal`, `xs:date`, `xs:dateTime`, or `xs:string` when values
+  disagree. The samples are valid against the result, which is meant as a
+  starting point to refine by hand.
+
+  Declarations use local names. The namespace of the first root element,
+  default or prefixed, becomes the target namespace; prefixed attributes
+  are left out. Only real dates and times (not `2024-02-30` or `25:00:00`)
+  count as `xs:date` and `xs:dateTime`.
+
+  ## Examples
+
+      [
+        RustyXML.parse("<order id='1'><line>2</line><line>3</line></order>"),
+        RustyXML.parse("<order id='2'/>")
+      ]
+      |> RustyXML.infer_schema()
+      |> IO.puts()
+      # <?xml version="1.0" encoding="UTF-8"?>
+      # <xs:schema xmlns:xs="http://www.w3.org/2001/XMLSchema" elementFormDefault="qualified">
+      #   <xs:element name="order">
+      #     <xs:complexType>
+      #       <xs:sequence>
+      #         <xs:element name="line" minOccurs="0" maxOccurs="unbounded" type="xs:integer"/>
+      #       </xs:sequence>
+      #       <xs:attribute name="id" type="xs:integer" use="required"/>
+      #     </xs:complexType>
+      #   </xs:element>
+      # </xs:schema>
+
+  """
+  @spec infer_schema(document() | [document()]) :: binary()
+  def infer_schema(docs) when is_list(docs), do: Native.infer_schema(docs)
+  def infer_schema(doc), do: Native.infer_schema([doc])
+
+  @doc """
+  Rewrite XML with normalized tags for deterministic output.
+
+  Attributes are sorted by name (namespace declarations first), quoted
+  with `"`, and separated by single spaces; whitespace before `>` and in
+  end tags is dropped; empty elements all take one form. Text, comments,
+  CDATA sections and the prolog are left unchanged, so documents that
+  differ only in how they were serialized come out byte-identical and can
+  be compared or cached by hash.
+
+  Malformed XML raises `RustyXML.ParseError`.
+
+  ## Options
+
+    * `:empty_elements` - `:self_closing` (`<a/>`, the default) or
+      `:expanded` (`<a></a>`).
+
+  ## Examples
+
+      RustyXML.normalize(~s(<r b='2'  a="1" ><e></e ></r>))
+      #=> ~s(<r a="1" b="2"><e/></r>)
+
+  """
+  @spec normalize(binary() | charlist(), keyword()) :: binary()
+  def normalize(xml, opts \\ [])
+
+  def normalize(xml, opts) when is_list(xml), do: normalize(IO.chardata_to_string(xml), opts)
+
+  def normalize(xml, opts) when is_binary(xml) do
+    case Native.normalize_markup(xml, Keyword.get(opts, :empty_elements, :self_closing)) do
+      {:ok, normalized} -> normalized
+      {:error, reason} -> raise ParseError, message: reason
+    end
+  end
+
+  @doc """
+  Compile a Schematron schema for `schematron_validate/2`.
+
+  The compiled schema can be reused across documents and processes. See
+  `RustyXML.Native.schematron_compile/1` for the supported subset.
+  """
+  @spec schematron_compile(binary()) ::
+          {:ok, Native.schematron_ref()} | {:error, binary()}
+  def schematron_compile(rules_xml) when is_binary(rules_xml) do
+    Native.schematron_compile(rules_xml)
+  end
+
+  @doc """
+  Validate XML or a parsed document against a compiled Schematron schema.
+
+  Returns `{:ok, failures}`, with an empty list when every assertion
+  holds and no report fires.
+
+  ## Examples
+
+      {:ok, rules} = RustyXML.schematron_compile(schema_xml)
+      {:ok, failures} = RustyXML.schematron_validate("<items><item/></items>", rules)
+      Enum.map(failures, & &1.message)
+      #=> ["Every item needs an id"]
+
+  """
+  @spec schematron_validate(binary() | document(), Native.schematron_ref()) ::
+          {:ok, [Native.schematron_failure()]} | {:error, binary()}
+  def schematron_validate(xml, rules) when is_binary(xml) do
+    schematron_validate(parse(xml), rules)
+  end
+
+  def schematron_validate(doc, rules), do: Native.schematron_validate(doc, rules)
+
   @doc """
   Execute an XPath query on XML.
 
@@ -228,34 +843,102 @@ defmodule RustyXML do
 
   # Raw XML + SweetXpath with is_value: true → optimized text extraction
   def xpath(xml, %SweetXpath{is_value: true} = spec) when is_binary(xml) do
-    result = Native.parse_and_xpath_text(xml, spec.path)
+    result =
+      if small?(xml),
+        do: Native.parse_and_xpath_text_small(xml, spec.path),
+        else: Native.parse_and_xpath_text(xml, spec.path)
+
     apply_modifiers(result, spec, xml)
   end
 
   # Raw XML + SweetXpath with is_value: false (e modifier) → element tuples
   def xpath(xml, %SweetXpath{} = spec) when is_binary(xml) do
-    result = Native.parse_and_xpath(xml, spec.path)
+    result = parse_and_query(xml, spec.path)
     apply_modifiers(result, spec, xml)
   end
 
   def xpath(xml, path) when is_binary(xml) and is_binary(path) do
-    Native.parse_and_xpath(xml, path)
+    parse_and_query(xml, path)
   end
 
   # Doc ref + SweetXpath with is_value: true → optimized text extraction
   def xpath(doc, %SweetXpath{is_value: true} = spec) do
-    result = Native.xpath_text_list(doc, spec.path)
+    result =
+      if dirty?(doc),
+        do: Native.xpath_text_list_dirty(doc, spec.path),
+        else: Native.xpath_text_list(doc, spec.path)
+
     apply_modifiers(result, spec, nil)
   end
 
   # Doc ref + SweetXpath with is_value: false (e modifier) → element tuples
   def xpath(doc, %SweetXpath{} = spec) do
-    result = Native.xpath_query(doc, spec.path)
+    result = query_doc(doc, spec.path)
     apply_modifiers(result, spec, nil)
   end
 
   def xpath(doc, path) when is_binary(path) do
-    Native.xpath_query(doc, path)
+    query_doc(doc, path)
+  end
+
+  @doc """
+  The first node an XPath expression selects in document order, or `nil`.
+
+  Cheaper than taking the head of `xpath/2`: for location paths without a
+  positional predicate on the last step, evaluation stops at the first
+  match instead of building the whole node-set. Sigil modifiers are
+  ignored. Expressions that don't select nodes return their value. Raises
+  `RustyXML.XPathError` for an invalid expression.
+
+  ## Examples
+
+      doc = RustyXML.parse("<r><a id=\"1\"/><a id=\"2\"/></r>")
+      RustyXML.xpath_first(doc, "//a")
+      #=> {:element, "a", [{"id", "1"}], []}
+
+  """
+  @spec xpath_first(document(), SweetXpath.t() | String.t()) :: term()
+  def xpath_first(doc, %SweetXpath{path: path}), do: xpath_first(doc, path)
+
+  def xpath_first(doc, path) when is_binary(path) do
+    result =
+      if dirty?(doc), do: Native.xpath_first_dirty(doc, path), else: Native.xpath_first(doc, path)
+
+    case result do
+      {:error, reason} -> raise XPathError, message: reason, xpath: path
+      value -> value
+    end
+  end
+
+  @doc """
+  Whether an XPath expression selects anything.
+
+  Stops at the first match like `xpath_first/2`, so checks against large
+  documents don't build node-sets only to test them for emptiness. True
+  for a non-empty node-set or any attribute value; other expressions give
+  their XPath boolean value. Raises `RustyXML.XPathError` for an invalid
+  expression.
+
+  ## Examples
+
+      doc = RustyXML.parse("<order><line sku=\"A\"/></order>")
+      RustyXML.xpath_exists?(doc, "//line[@sku = 'A']")
+      #=> true
+
+  """
+  @spec xpath_exists?(document(), SweetXpath.t() | String.t()) :: boolean()
+  def xpath_exists?(doc, %SweetXpath{path: path}), do: xpath_exists?(doc, path)
+
+  def xpath_exists?(doc, path) when is_binary(path) do
+    result =
+      if dirty?(doc),
+        do: Native.xpath_exists_dirty(doc, path),
+        else: Native.xpath_exists(doc, path)
+
+    case result do
+      {:error, reason} -> raise XPathError, message: reason, xpath: path
+      exists -> exists
+    end
   end
 
   @doc """
@@ -274,18 +957,41 @@ defmodule RustyXML do
       ])
       #=> [%{id: "1", name: "A"}, %{id: "2", name: "B"}]
 
+  ## Typed subspecs
+
+  A subspec may also be a `{name, xpath_spec, type}` triple, where `type` is
+  one of `:string`, `:integer`, `:float`, `:boolean`, `:list` or `:node`. The
+  conversion is done in Rust, so no Elixir-side casting is needed. Scalar
+  types use the first result and yield `nil` if it is missing or cannot be
+  converted; `:boolean` accepts `"true"`/`"false"`/`"1"`/`"0"`; `:list`
+  returns the string value of every result; `:node` returns the first node
+  as an element tuple. Sigil modifiers are ignored for typed subspecs.
+
+      RustyXML.xpath(xml, ~x"//item"l, [
+        {:id, "./@id", :integer},
+        {:name, "./name", :string}
+      ])
+      #=> [%{id: 1, name: "A"}, %{id: 2, name: "B"}]
+
+  ## Map subspecs
+
+  Two plain string subspecs return maps instead of node lists: `"@*"` maps
+  each attribute name of the node to its value, and `"./*"` maps each child
+  element name to its string-value (the first child wins when names
+  repeat).
+
+      RustyXML.xpath(xml, ~x"//item"l, attrs: "@*", fields: "./*")
+      #=> [%{attrs: %{"id" => "1"}, fields: %{"name" => "A"}}, ...]
+
   """
-  @spec xpath(binary() | document(), SweetXpath.t() | binary(), keyword()) :: term()
+  @spec xpath(binary() | document(), SweetXpath.t() | binary(), [subspec()]) :: term()
   def xpath(xml_or_doc, spec, subspecs) when is_list(subspecs) do
     parent_path = extract_path(spec)
 
     result =
       if is_binary(xml_or_doc) do
         # Raw XML: use efficient NIF that does it all in one pass
-        nif_subspecs =
-          Enum.map(subspecs, fn {key, subspec} ->
-            {Atom.to_string(key), extract_path(subspec)}
-          end)
+        nif_subspecs = Enum.map(subspecs, &to_nif_subspec/1)
 
         Native.xpath_with_subspecs(xml_or_doc, parent_path, nif_subspecs)
         |> Enum.map(&apply_subspecs_from_nif(&1, subspecs, xml_or_doc))
@@ -311,19 +1017,60 @@ defmodule RustyXML do
   defp apply_subspec_value(raw_value, _binary_spec, _xml), do: raw_value
 
   defp apply_subspecs_from_nif(nif_map, subspecs, xml) do
-    Enum.map(subspecs, fn {key, subspec} ->
-      raw_value = Map.get(nif_map, Atom.to_string(key))
-      {key, apply_subspec_value(raw_value, subspec, xml)}
+    Enum.map(subspecs, fn
+      {key, _subspec, _type} ->
+        {key, Map.get(nif_map, Atom.to_string(key))}
+
+      {key, path} when path in @map_subspecs ->
+        {key, Map.get(nif_map, Atom.to_string(key))}
+
+      {key, subspec} ->
+        raw_value = Map.get(nif_map, Atom.to_string(key))
+        {key, apply_subspec_value(raw_value, subspec, xml)}
     end)
     |> Map.new()
   end
 
   defp query_subspecs(parent_xml, subspecs) do
-    subspecs
-    |> Enum.map(fn {key, subspec} -> {key, xpath(parent_xml, subspec)} end)
-    |> Map.new()
+    rust_values = query_rust_subspecs(parent_xml, subspecs)
+
+    Map.new(subspecs, fn
+      {key, subspec} when subspec not in @map_subspecs -> {key, xpath(parent_xml, subspec)}
+      spec -> {elem(spec, 0), Map.get(rust_values, spec |> elem(0) |> Atom.to_string())}
+    end)
   end
 
+  # Typed and map subspecs are built in Rust: evaluate them all in one NIF
+  # call relative to the parent element (the root of `parent_xml`).
+  defp query_rust_subspecs(parent_xml, subspecs) do
+    typed = for subspec <- subspecs, rust_subspec?(subspec), do: to_nif_subspec(subspec)
+
+    with [_ | _] <- typed,
+         [values] <- Native.xpath_with_subspecs(parent_xml, "/*", typed) do
+      values
+    else
+      _ -> %{}
+    end
+  end
+
+  defp rust_subspec?({_key, _subspec, _type}), do: true
+  defp rust_subspec?({_key, subspec}), do: subspec in @map_subspecs
+
+  @subspec_types [:string, :integer, :float, :boolean, :list, :node]
+
+  defp to_nif_subspec({key, subspec}), do: {Atom.to_string(key), extract_path(subspec)}
+
+  defp to_nif_subspec({key, subspec, type}) when type in @subspec_types,
+    do: {Atom.to_string(key), extract_path(subspec), type}
+
+  defp to_nif_subspec({key, _subspec, type}),
+    do:
+      raise(
+        ArgumentError,
+        "invalid type #{inspect(type)} for subspec #{inspect(key)}, " <>
+          "expected one of #{inspect(@subspec_types)}"
+      )
+
   @doc """
   Execute multiple XPath queries and return as a map.
 
@@ -373,6 +1120,61 @@ defmodule RustyXML do
     if as_keyword, do: Keyword.new(result), else: Map.new(result)
   end
 
+  @doc """
+  Extract a nested map of typed values according to a declarative spec.
+
+  The whole spec is evaluated in Rust, in a single call, replacing one
+  `xpath/2` call per field. `spec` is a map from keys to fields:
+
+    * `xpath` - the string-value of the first result
+    * `{xpath, type}` - with `type` one of `:string`, `:integer`, `:float`,
+      `:boolean`, `:list` (every string-value) or `:node`
+    * `{:list, xpath, spec}` - a nested map for every node `xpath` selects
+    * `{:map, xpath, spec}` - a nested map for the first node `xpath`
+      selects, or `nil` when there is none
+
+  Top-level expressions are evaluated from the root element; nested ones
+  from the node their map is built for, so they are usually relative.
+  Values that are missing or can't be converted are `nil`. Raises
+  `RustyXML.XPathError` if an expression is invalid.
+
+  ## Examples
+
+      xml = ~s(<order><name>Tea</name><item id="1"/><item id="2"/></order>)
+
+      RustyXML.decode(xml, %{
+        name: {"//order/name", :string},
+        items: {:list, "//item", %{id: {"@id", :integer}}}
+      })
+      #=> %{name: "Tea", items: [%{id: 1}, %{id: 2}]}
+
+  """
+  @spec decode(binary() | document(), map()) :: map()
+  def decode(xml_or_doc, spec) when is_map(spec) do
+    doc = if is_binary(xml_or_doc), do: parse(xml_or_doc), else: xml_or_doc
+
+    case Native.decode(doc, spec) do
+      {:error, {path, reason}} -> raise XPathError, message: reason, xpath: path
+      result -> result
+    end
+  end
+
+  # Documents above the node threshold are queried on the dirty CPU scheduler
+  defp dirty?(doc), do: Native.doc_node_count(doc) > @dirty_node_threshold
+
+  # Raw XML this small is parsed and queried without leaving the scheduler
+  defp small?(xml), do: byte_size(xml) < @small_input_bytes
+
+  defp parse_and_query(xml, path) do
+    if small?(xml),
+      do: Native.parse_and_xpath_small(xml, path),
+      else: Native.parse_and_xpath(xml, path)
+  end
+
+  defp query_doc(doc, path) do
+    if dirty?(doc), do: Native.xpath_query_dirty(doc, path), else: Native.xpath_query(doc, path)
+  end
+
   # Evaluate a spec - handles both simple specs and nested list specs
   defp evaluate_spec(doc, _xml, %SweetXpath{} = spec) do
     xpath(doc, spec)
@@ -382,7 +1184,7 @@ defmodule RustyXML do
        when is_list(child_specs) do
     # Nested spec: first element is parent path, rest are child specs
     # Get parent nodes as raw elements (bypass value extraction)
-    parent_result = Native.parse_and_xpath(xml, parent_spec.path)
+    parent_result = parse_and_query(xml, parent_spec.path)
 
     # Ensure it's a list
     nodes =
@@ -458,7 +1260,8 @@ defmodule RustyXML do
 
   defp node_to_xml(text) when is_binary(text), do: escape_xml(text)
   defp node_to_xml({:comment, text}), do: "<!--#{text}-->"
-  defp node_to_xml({:pi, target}), do: "<?#{target}?>"
+  defp node_to_xml({:pi, target, ""}), do: "<?#{target}?>"
+  defp node_to_xml({:pi, target, data}), do: "<?#{target} #{data}?>"
   defp node_to_xml(_), do: ""
 
   defp escape_xml(text) do
@@ -503,6 +1306,77 @@ defmodule RustyXML do
     %{spec | transform: fun}
   end
 
+  @doc """
+  Lazily stream the results of an XPath query on a parsed document.
+
+  Node-set results are converted to terms in timeslice-sized batches as the
+  stream is consumed, so a query matching hundreds of thousands of nodes
+  never blocks a scheduler for long. Scalar results (counts, strings,
+  booleans) are emitted as a single element. Raises `RustyXML.XPathError`
+  if the expression is invalid.
+
+  ## Options
+
+    * `:chunk_size` - Convert exactly this many nodes per step instead of
+      timeslice-sized batches. See `RustyXML.Native.xpath_query_chunked/3`.
+
+  ## Examples
+
+      doc = RustyXML.parse(large_xml)
+
+      doc
+      |> RustyXML.stream_xpath("//item")
+      |> Stream.each(&process/1)
+      |> Stream.run()
+
+  """
+  @spec stream_xpath(document(), SweetXpath.t() | binary(), keyword()) :: Enumerable.t()
+  def stream_xpath(doc, path, opts \\ [])
+
+  def stream_xpath(doc, %SweetXpath{path: path}, opts), do: stream_xpath(doc, path, opts)
+
+  def stream_xpath(doc, path, opts) when is_binary(path) do
+    case Keyword.get(opts, :chunk_size) do
+      nil -> stream_cursor(doc, path)
+      chunk_size -> stream_chunks(doc, path, chunk_size)
+    end
+  end
+
+  defp stream_chunks(doc, path, chunk_size) do
+    Stream.unfold({:start, doc}, fn
+      {:start, doc} -> next_chunk(Native.xpath_query_chunked(doc, path, chunk_size), path)
+      :done -> nil
+      continuation -> next_chunk(Native.xpath_chunk_next(continuation), path)
+    end)
+    |> Stream.flat_map(& &1)
+  end
+
+  defp next_chunk({:error, reason}, path), do: raise(XPathError, message: reason, xpath: path)
+  defp next_chunk({nodes, next}, _path), do: {nodes, next}
+
+  defp stream_cursor(doc, path) do
+    Stream.resource(
+      fn -> Native.xpath_query_cursor(doc, path) end,
+      fn
+        {:cont, cursor} ->
+          case Native.cursor_next(cursor) do
+            {:cont, nodes} -> {nodes, {:cont, cursor}}
+            {:done, nodes} -> {nodes, :halt}
+          end
+
+        {:done, value} ->
+          {[value], :halt}
+
+        {:error, reason} ->
+          raise XPathError, message: reason, xpath: path
+
+        :halt ->
+          {:halt, :halt}
+      end,
+      fn _ -> :ok end
+    )
+  end
+
   @doc """
   Stream XML events from a file.
 
@@ -546,6 +1420,61 @@ defmodule RustyXML do
     stream_tags(source, tag, opts)
   end
 
+  @doc """
+  Count the elements named `tag` in a file, XML string or enumerable of
+  chunks, in bounded memory.
+
+  Nothing is materialized: elements are counted in Rust as the input is
+  streamed through, which makes this a cheap cardinality check for files
+  too large to parse. Accepts the `:chunk_size` option of `stream_tags/3`.
+
+  ## Examples
+
+      RustyXML.count_tags("export.xml", :record)
+      #=> 1_204_331
+
+  """
+  @spec count_tags(binary() | Enumerable.t(), atom() | binary(), keyword()) :: non_neg_integer()
+  def count_tags(source, tag, opts \\ []) do
+    RustyXML.Streaming.count_tags(source, tag, opts)
+  end
+
+  @doc """
+  Join streamed elements back into a single document under `root_name`.
+
+  The inverse of splitting a document with `stream_tags/3`: `elements` may
+  be XML binaries or the `{tag, xml}` pairs `stream_tags/3` emits. With
+  `:max_element_size`, the `{:chunk, name, data}` pieces of an oversized
+  element are joined back together, while `{:oversized, name, size}` and
+  `{:chunk_end, name, size}` markers are left out. Elements are
+  concatenated as they are, without being parsed again, so they must
+  already be well-formed. `root_attrs` are escaped; an invalid or repeated
+  attribute name raises `ArgumentError`.
+
+  ## Examples
+
+      RustyXML.wrap_elements(["<item>a</item>", "<item>b</item>"], :items, count: 2)
+      #=> ~s(<items count="2"><item>a</item><item>b</item></items>)
+
+  """
+  @spec wrap_elements(
+          Enumerable.t(),
+          atom() | String.t(),
+          [{atom() | String.t(), String.Chars.t()}]
+        ) :: binary()
+  def wrap_elements(elements, root_name, root_attrs \\ []) do
+    elements =
+      Enum.flat_map(elements, fn
+        {:chunk, _name, data} when is_binary(data) -> [data]
+        {marker, _name, _size} when marker in [:oversized, :chunk_end] -> []
+        {_tag, xml} when is_binary(xml) -> [xml]
+        xml when is_binary(xml) -> [xml]
+      end)
+
+    root_attrs = Enum.map(root_attrs, fn {name, value} -> {to_string(name), to_string(value)} end)
+    Native.wrap_elements(elements, to_string(root_name), root_attrs)
+  end
+
   @doc """
   Get the root element of a parsed document.
 
@@ -558,7 +1487,92 @@ defmodule RustyXML do
   """
   @spec root(document()) :: xml_node() | nil
   def root(doc) do
-    Native.get_root(doc)
+    if dirty?(doc), do: Native.get_root_dirty(doc), else: Native.get_root(doc)
+  end
+
+  @doc """
+  Convert a node and at most `depth` levels below it.
+
+  Elements at the limit that have children get `[{:more, node_id}]` as
+  their children; pass that id back here to expand them. Returns `nil` for
+  an unknown node id. See `RustyXML.Native.node_to_term_shallow/3`.
+
+  ## Examples
+
+      doc = RustyXML.parse("<r><a><b/></a><c/></r>")
+      RustyXML.expand(doc, 0, 1)
+      #=> {:element, "r", [], [{:element, "a", [], [{:more, 1}]}, {:element, "c", [], []}]}
+
+  """
+  @spec expand(document(), non_neg_integer(), non_neg_integer()) :: term() | nil
+  def expand(doc, node_id, depth) do
+    if dirty?(doc),
+      do: Native.node_to_term_shallow_dirty(doc, node_id, depth),
+      else: Native.node_to_term_shallow(doc, node_id, depth)
+  end
+
+  @doc """
+  Resolve an XPointer fragment identifier to node ids.
+
+  Accepts shorthand pointers (`"intro"`, matching an `xml:id` or `id`
+  attribute) and scheme-based pointers using `element()`, `xpointer()` and
+  `xpath1()`. Parts are tried left to right until one identifies nodes;
+  unknown schemes are skipped. So are `xmlns()` parts: their bindings are
+  not applied, and prefixes in expressions match the document's prefixes. Ids work with
+  `RustyXML.Native.node_outer_xml/2` and `RustyXML.Native.node_inner_xml/2`.
+
+  Returns `{:ok, []}` when the pointer identifies nothing, and
+  `{:error, reason}` when it is malformed.
+
+  ## Examples
+
+      doc = RustyXML.parse("<doc><a/><b id='x'><c/></b></doc>")
+      {:ok, [id]} = RustyXML.resolve_xpointer(doc, "element(/1/2/1)")
+      RustyXML.Native.node_outer_xml(doc, id)
+      #=> "<c/>"
+
+      {:ok, [_]} = RustyXML.resolve_xpointer(doc, "x")
+
+  """
+  @spec resolve_xpointer(document(), String.t()) ::
+          {:ok, [non_neg_integer()]} | {:error, String.t()}
+  def resolve_xpointer(doc, pointer) when is_binary(pointer),
+    do: Native.resolve_xpointer(doc, pointer)
+
+  @doc """
+  Render a parsed template, substituting `{{name}}` placeholders.
+
+  Placeholders are replaced in text content and attribute values, with
+  each value escaped for where it lands, so values never need to be
+  pre-escaped and can't inject markup. Comments, CDATA sections and the
+  markup itself are copied byte for byte. Keys may be strings or atoms;
+  values are converted with `to_string/1`. Placeholders without a value
+  are left as they are.
+
+  Parse the template once and render it as often as needed; rendering
+  does not re-parse it.
+
+  ## Options
+
+    * `:delimiters` - `{open, close}` strings marking a placeholder.
+      Defaults to `{"{{", "}}"}`.
+
+  ## Examples
+
+      template = RustyXML.parse(~s(<req id="{{id}}"><q>{{ query }}</q></req>))
+      RustyXML.render_template(template, %{id: 7, query: "a < b"})
+      #=> ~s(<req id="7"><q>a &lt; b</q></req>)
+
+      template = RustyXML.parse("<req>${id}</req>")
+      RustyXML.render_template(template, %{"id" => 7}, delimiters: {"${", "}"})
+      #=> "<req>7</req>"
+
+  """
+  @spec render_template(document(), map(), keyword()) :: binary()
+  def render_template(doc, values, opts \\ []) when is_map(values) do
+    {open, close} = Keyword.get(opts, :delimiters, {"{{", "}}"})
+    values = Map.new(values, fn {key, value} -> {to_string(key), to_string(value)} end)
+    Native.render_template(doc, values, open, close)
   end
 
   # ==========================================================================
@@ -577,6 +1591,19 @@ defmodule RustyXML do
 
     * `:cdata_as_characters` - Emit CDATA as `:characters` events (default: `false`)
     * `:expand_entity` - Accepted for Saxy API compatibility (default: `:keep`)
+    * `:raw_text` - Deliver text exactly as it appears in the source, without
+      entity decoding. `:characters` and `:cdata` data is then
+      `{raw_content, {offset, length}}` with byte positions in `xml`, and
+      attribute values are raw. Useful for signature checks and diffs that
+      need byte-exact content (default: `false`)
+    * `:normalize` - `:nfc` or `:nfd` to deliver `:characters` and `:cdata`
+      data and attribute values in that Unicode normalization form, so
+      feeds mixing composed and decomposed text compare equal. Cannot be
+      combined with `:raw_text` (default: `:none`)
+    * `:max_attributes`, `:max_elements` and `:on_limit` - As for
+      `parse_stream/4`; the document is then parsed as a single-chunk
+      stream, so `:start_document` data is `[]`. Cannot be combined with
+      `:raw_text` or `:normalize`.
 
   ## Examples
 
@@ -595,12 +1622,39 @@ defmodule RustyXML do
           {:ok, any()} | {:halt, any()} | {:error, any()}
   def parse_string(xml, handler, initial_state, opts \\ []) when is_binary(xml) do
     cdata_as_chars = Keyword.get(opts, :cdata_as_characters, false)
+    raw_text = Keyword.get(opts, :raw_text, false)
+    normalize = Keyword.get(opts, :normalize, :none)
+
+    limited? = Enum.any?([:max_attributes, :max_elements, :on_limit], &Keyword.has_key?(opts, &1))
+
+    cond do
+      raw_text and normalize != :none ->
+        raise ArgumentError, ":normalize cannot be combined with :raw_text"
+
+      limited? and (raw_text or normalize != :none) ->
+        raise ArgumentError,
+              ":max_attributes, :max_elements and :on_limit cannot be combined " <>
+                "with :raw_text or :normalize"
+
+      limited? ->
+        parse_stream([xml], handler, initial_state, opts)
+
+      true ->
+        parse_string_events(xml, handler, initial_state, cdata_as_chars, raw_text, normalize)
+    end
+  end
 
+  defp parse_string_events(xml, handler, initial_state, cdata_as_chars, raw_text, normalize) do
     try do
       # Single NIF call — sax_parse_saxy/2 does a zero-copy scan via
       # UnifiedScanner + SaxCollector, returning all events in Saxy format
       # in one pass. No EventTransformer pass needed.
-      saxy_events = Native.sax_parse_saxy(xml, cdata_as_chars)
+      saxy_events =
+        cond do
+          raw_text -> Native.sax_parse_saxy_raw(xml, cdata_as_chars)
+          normalize != :none -> Native.sax_parse_saxy_normalized(xml, cdata_as_chars, normalize)
+          true -> Native.sax_parse_saxy(xml, cdata_as_chars)
+        end
 
       prolog = extract_prolog(xml)
       state = dispatch_handler(handler, :start_document, prolog, initial_state)
@@ -630,6 +1684,49 @@ defmodule RustyXML do
   event tuple is ever live on the heap. Combined NIF + BEAM peak is ~128 KB
   for a 2.93 MB document, comparable to Saxy while running ~1.8x faster.
 
+  ## Options
+
+    * `:cdata_as_characters` - Emit CDATA as `:characters` events (default: `false`)
+    * `:text_chunk_size` - Emit text and CDATA content in pieces of at most
+      this many bytes. Content over the limit, or still open at the end of a
+      chunk, is delivered as `:characters_chunk` (or `:cdata_chunk`) events
+      with data `{binary, final?}`, so huge sections (e.g. base64 payloads)
+      never have to be buffered whole. Shorter content is still delivered as
+      regular `:characters`/`:cdata` events. Pieces never split a UTF-8
+      character, so a limit under 4 bytes can be exceeded by one character.
+      Default: unlimited.
+    * `:event_format` - Binary format used to pass events from the NIF
+      (`:v1` or `:v2`, default `:v1`). `:v2` uses varint lengths and sends
+      each element/attribute name once per chunk, which makes the per-chunk
+      binaries much smaller for repetitive documents. Handlers see the same
+      events either way.
+    * `:duplicate_ids` - Report elements whose `id` or `xml:id` repeats an
+      earlier one with a `:duplicate_id` event of `{value, occurrence}`,
+      right after the element's `:start_element`. Only a hash and a count are
+      kept per distinct value, so this works on multi-GB documents without
+      building an index. Default: `false`.
+    * `:cancel_token` - A token from `cancel_token/0`. It is checked before
+      each chunk; once `cancel/1` has been called on it, parsing stops and
+      `{:error, :cancelled}` is returned.
+    * `:max_tail_size` - Most bytes of unfinished markup held between
+      chunks. A tag still open past the limit, such as a start tag with
+      megabytes of attributes, stops parsing with
+      `{:error, {:max_tail_size_exceeded, limit}}`. `0` removes the limit.
+      Default: 64 MiB.
+    * `:max_attributes` / `:max_elements` - Most attributes per element and
+      elements per document. An element over either limit stops parsing
+      with `{:error, {:attribute_limit_exceeded, limit}}` or
+      `{:error, {:element_limit_exceeded, limit}}`. Default: unlimited.
+    * `:on_limit` - `:warn` keeps parsing past those limits in degraded
+      mode instead: the element's `:start_element` is followed by a
+      `:warning` event of `{:attribute_limit_exceeded, name, count}` or
+      `{:element_limit_exceeded, name, count}`, attributes past the limit
+      are dropped, and the element limit is reported once, by the first
+      element past it. Default: `:error`.
+
+  `:raw_text` and `:normalize` are only supported by `parse_string/4`;
+  passing them here raises `ArgumentError`.
+
   ## Examples
 
       File.stream!("large.xml", [], 64 * 1024)
@@ -639,21 +1736,68 @@ defmodule RustyXML do
   @spec parse_stream(Enumerable.t(), handler(), any(), parse_options()) ::
           {:ok, any()} | {:halt, any()} | {:error, any()}
   def parse_stream(stream, handler, initial_state, opts \\ []) do
+    if Keyword.get(opts, :raw_text, false) do
+      raise ArgumentError, ":raw_text is not supported by parse_stream/4, use parse_string/4"
+    end
+
+    if Keyword.get(opts, :normalize, :none) != :none do
+      raise ArgumentError, ":normalize is not supported by parse_stream/4, use parse_string/4"
+    end
+
     cdata_as_chars = Keyword.get(opts, :cdata_as_characters, false)
+    format = Keyword.get(opts, :event_format, :v1)
+
+    unless format in [:v1, :v2] do
+      raise ArgumentError, ":event_format must be :v1 or :v2, got: #{inspect(format)}"
+    end
+
+    on_limit = Keyword.get(opts, :on_limit, :error)
+
+    unless on_limit in [:error, :warn] do
+      raise ArgumentError, ":on_limit must be :error or :warn, got: #{inspect(on_limit)}"
+    end
 
     try do
-      parser = Native.streaming_sax_new()
+      parser =
+        case Keyword.get(opts, :text_chunk_size) do
+          nil -> Native.streaming_sax_new()
+          size -> Native.streaming_sax_new_chunked(size)
+        end
+
+      if format == :v2, do: Native.streaming_sax_set_format(parser, 2)
+
+      if Keyword.get(opts, :duplicate_ids, false),
+        do: Native.streaming_sax_track_duplicate_ids(parser)
+
+      if max_tail = Keyword.get(opts, :max_tail_size),
+        do: Native.streaming_sax_set_max_tail(parser, max_tail)
+
+      max_attributes = Keyword.get(opts, :max_attributes, 0)
+      max_elements = Keyword.get(opts, :max_elements, 0)
+
+      if max_attributes > 0 or max_elements > 0,
+        do: Native.streaming_sax_set_limits(parser, max_attributes, max_elements, on_limit)
+
       state = dispatch_handler(handler, :start_document, [], initial_state)
+      token = Keyword.get(opts, :cancel_token)
 
       state =
         Enum.reduce(stream, state, fn chunk, state ->
+          if token && Native.cancel_token_cancelled(token), do: throw(:rustyxml_cancelled)
           chunk_binary = if is_binary(chunk), do: chunk, else: IO.iodata_to_binary(chunk)
-          encoded = Native.streaming_feed_sax(parser, chunk_binary, cdata_as_chars)
-          dispatch_encoded_events(encoded, handler, state)
+
+          case Native.streaming_feed_sax(parser, chunk_binary, cdata_as_chars) do
+            {:error, reason} -> throw({:rustyxml_error, reason})
+            encoded -> dispatch_encoded(format, encoded, handler, state)
+          end
         end)
 
-      remaining = Native.streaming_finalize_sax(parser, cdata_as_chars)
-      state = dispatch_encoded_events(remaining, handler, state)
+      state =
+        case Native.streaming_finalize_sax(parser, cdata_as_chars) do
+          {:error, reason} -> throw({:rustyxml_error, reason})
+          remaining -> dispatch_encoded(format, remaining, handler, state)
+        end
+
       final_state = dispatch_handler(handler, :end_document, {}, state)
       {:ok, final_state}
     rescue
@@ -661,9 +1805,42 @@ defmodule RustyXML do
     catch
       {:sax_stop, value} -> {:ok, value}
       {:sax_halt, value} -> {:halt, value}
+      :rustyxml_cancelled -> {:error, :cancelled}
+      {:rustyxml_error, reason} -> {:error, reason}
     end
   end
 
+  @doc """
+  Create a cancellation token.
+
+  Pass it to `parse_stream/4` (`:cancel_token`),
+  `RustyXML.Native.parse_cancellable/2` or
+  `RustyXML.Native.xpath_query_cancellable/3`, and call `cancel/1` from any
+  process to stop that work: native parsing stops between tokens, XPath
+  evaluation between operations and context nodes (also on worker
+  threads), and streams before the next chunk. A
+  token stays cancelled, so one token can stop a whole batch of calls.
+
+  ## Examples
+
+      token = RustyXML.cancel_token()
+      Task.start(fn -> Process.sleep(5_000) && RustyXML.cancel(token) end)
+
+      case RustyXML.Native.xpath_query_cancellable(doc, expensive_query, token) do
+        {:error, :cancelled} -> :timeout
+        result -> result
+      end
+
+  """
+  @spec cancel_token() :: Native.cancel_token_ref()
+  def cancel_token, do: Native.cancel_token_new()
+
+  @doc """
+  Cancel the work running under `token`. Returns `:ok`.
+  """
+  @spec cancel(Native.cancel_token_ref()) :: :ok
+  def cancel(token), do: Native.cancel_token_cancel(token)
+
   @doc """
   Encode an XML element tree to a string.
 
@@ -702,6 +1879,12 @@ defmodule RustyXML do
   defp normalize_sax_event({:cdata, content}, true), do: {:characters, content}
   defp normalize_sax_event({type, data}, _cdata_as_chars), do: {type, data}
 
+  defp dispatch_encoded(:v1, encoded, handler, state),
+    do: dispatch_encoded_events(encoded, handler, state)
+
+  defp dispatch_encoded(:v2, encoded, handler, state),
+    do: dispatch_v2_events(encoded, %{}, handler, state)
+
   # Binary-encoded event dispatch for `parse_stream/4`.
   #
   # The NIF packs all SAX events from a chunk into a single binary instead of
@@ -714,6 +1897,13 @@ defmodule RustyXML do
   #   2 = end_element:   <<2, name_len::16, name>>
   #   3 = characters:    <<3, text_len::32, text>>
   #   4 = cdata:         <<4, text_len::32, text>>
+  #   5 = characters_chunk: <<5, final::8, text_len::32, text>>
+  #   6 = cdata_chunk:   <<6, final::8, text_len::32, text>>
+  #   7 = duplicate_id:  <<7, value_len::16, value, occurrence::32>>
+  #   8 = limit_warning: <<8, kind::8, name_len::16, name, count::32>>
+  #
+  # An attribute value of 0xFFFF bytes or more has its length written as
+  # <<0xFFFF::16, vlen::32>>.
   defp dispatch_encoded_events(<<>>, _handler, state), do: state
 
   # 1 = start_element
@@ -757,12 +1947,61 @@ defmodule RustyXML do
     dispatch_encoded_events(rest, handler, state)
   end
 
+  # 5 = characters_chunk
+  defp dispatch_encoded_events(
+         <<5, final, tlen::32, text::binary-size(tlen), rest::binary>>,
+         handler,
+         state
+       ) do
+    state = dispatch_handler(handler, :characters_chunk, {text, final == 1}, state)
+    dispatch_encoded_events(rest, handler, state)
+  end
+
+  # 6 = cdata_chunk
+  defp dispatch_encoded_events(
+         <<6, final, tlen::32, text::binary-size(tlen), rest::binary>>,
+         handler,
+         state
+       ) do
+    state = dispatch_handler(handler, :cdata_chunk, {text, final == 1}, state)
+    dispatch_encoded_events(rest, handler, state)
+  end
+
+  # 7 = duplicate_id
+  defp dispatch_encoded_events(
+         <<7, vlen::16, value::binary-size(vlen), occurrence::32, rest::binary>>,
+         handler,
+         state
+       ) do
+    state = dispatch_handler(handler, :duplicate_id, {value, occurrence}, state)
+    dispatch_encoded_events(rest, handler, state)
+  end
+
+  # 8 = limit_warning
+  defp dispatch_encoded_events(
+         <<8, kind, nlen::16, name::binary-size(nlen), count::32, rest::binary>>,
+         handler,
+         state
+       ) do
+    state = dispatch_handler(handler, :warning, {limit_warning(kind), name, count}, state)
+    dispatch_encoded_events(rest, handler, state)
+  end
+
   defp decode_encoded_attrs(<<count::16, rest::binary>>) do
     decode_encoded_attrs(rest, count, [])
   end
 
   defp decode_encoded_attrs(rest, 0, acc), do: {Enum.reverse(acc), rest}
 
+  defp decode_encoded_attrs(
+         <<nlen::16, name::binary-size(nlen), 0xFFFF::16, vlen::32, value::binary-size(vlen),
+           rest::binary>>,
+         count,
+         acc
+       ) do
+    decode_encoded_attrs(rest, count - 1, [{name, value} | acc])
+  end
+
   defp decode_encoded_attrs(
          <<nlen::16, name::binary-size(nlen), vlen::16, value::binary-size(vlen), rest::binary>>,
          count,
@@ -771,6 +2010,89 @@ defmodule RustyXML do
     decode_encoded_attrs(rest, count - 1, [{name, value} | acc])
   end
 
+  # v2 wire format (see `Native.streaming_sax_set_format/2`): varint
+  # lengths, and names as references into a dictionary built up while
+  # decoding one binary. `names` maps ids to the names defined so far.
+  defp dispatch_v2_events(<<>>, _names, _handler, state), do: state
+
+  defp dispatch_v2_events(<<1, rest::binary>>, names, handler, state) do
+    {name, names, rest} = decode_v2_name(rest, names)
+    {count, rest} = decode_varint(rest)
+    {attrs, names, rest} = decode_v2_attrs(rest, count, names, [])
+    state = dispatch_handler(handler, :start_element, {name, attrs}, state)
+    dispatch_v2_events(rest, names, handler, state)
+  end
+
+  defp dispatch_v2_events(<<2, rest::binary>>, names, handler, state) do
+    {name, names, rest} = decode_v2_name(rest, names)
+    state = dispatch_handler(handler, :end_element, name, state)
+    dispatch_v2_events(rest, names, handler, state)
+  end
+
+  defp dispatch_v2_events(<<type, rest::binary>>, names, handler, state) when type in [3, 4] do
+    {text, rest} = decode_v2_bytes(rest)
+    event = if type == 3, do: :characters, else: :cdata
+    state = dispatch_handler(handler, event, text, state)
+    dispatch_v2_events(rest, names, handler, state)
+  end
+
+  defp dispatch_v2_events(<<type, final, rest::binary>>, names, handler, state)
+       when type in [5, 6] do
+    {text, rest} = decode_v2_bytes(rest)
+    event = if type == 5, do: :characters_chunk, else: :cdata_chunk
+    state = dispatch_handler(handler, event, {text, final == 1}, state)
+    dispatch_v2_events(rest, names, handler, state)
+  end
+
+  defp dispatch_v2_events(<<7, rest::binary>>, names, handler, state) do
+    {value, rest} = decode_v2_bytes(rest)
+    {occurrence, rest} = decode_varint(rest)
+    state = dispatch_handler(handler, :duplicate_id, {value, occurrence}, state)
+    dispatch_v2_events(rest, names, handler, state)
+  end
+
+  defp dispatch_v2_events(<<8, kind, rest::binary>>, names, handler, state) do
+    {name, names, rest} = decode_v2_name(rest, names)
+    {count, rest} = decode_varint(rest)
+    state = dispatch_handler(handler, :warning, {limit_warning(kind), name, count}, state)
+    dispatch_v2_events(rest, names, handler, state)
+  end
+
+  defp limit_warning(1), do: :attribute_limit_exceeded
+  defp limit_warning(2), do: :element_limit_exceeded
+
+  defp decode_v2_attrs(rest, 0, names, acc), do: {Enum.reverse(acc), names, rest}
+
+  defp decode_v2_attrs(rest, count, names, acc) do
+    {name, names, rest} = decode_v2_name(rest, names)
+    {value, rest} = decode_v2_bytes(rest)
+    decode_v2_attrs(rest, count - 1, names, [{name, value} | acc])
+  end
+
+  defp decode_v2_name(<<0, rest::binary>>, names) do
+    {name, rest} = decode_v2_bytes(rest)
+    {name, Map.put(names, map_size(names) + 1, name), rest}
+  end
+
+  defp decode_v2_name(binary, names) do
+    {id, rest} = decode_varint(binary)
+    {Map.fetch!(names, id), names, rest}
+  end
+
+  defp decode_v2_bytes(binary) do
+    {len, rest} = decode_varint(binary)
+    <<bytes::binary-size(len), rest::binary>> = rest
+    {bytes, rest}
+  end
+
+  # Unsigned LEB128: 7 bits per byte, least significant group first
+  defp decode_varint(<<0::1, value::7, rest::binary>>), do: {value, rest}
+
+  defp decode_varint(<<1::1, low::7, rest::binary>>) do
+    {high, rest} = decode_varint(rest)
+    {low + high * 128, rest}
+  end
+
   defp dispatch_handler(handler, type, data, state) do
     case handler.handle_event(type, data, state) do
       {:ok, new_state} -> new_state
@@ -904,9 +2226,17 @@ defmodule RustyXML do
 
   # Extract text from a node result
   defp extract_text_value({:element, _name, _attrs, children}) do
-    Enum.map_join(children, &extract_text_value/1)
+    # Comments and PIs inside an element are not part of its string-value
+    Enum.map_join(children, fn
+      {:comment, _text} -> ""
+      {:pi, _target, _data} -> ""
+      child -> extract_text_value(child)
+    end)
   end
 
+  defp extract_text_value({:comment, text}), do: text
+  defp extract_text_value({:pi, _target, data}), do: data
+
   defp extract_text_value(text) when is_binary(text), do: text
   defp extract_text_value(list) when is_list(list), do: list
   defp extract_text_value(other), do: other
@@ -13,6 +13,17 @@ defmodule RustyXML.Handler do
       where attributes is a list of `{name, value}` string tuples.
     * `:characters` — emitted for text content. Data is a binary string.
     * `:cdata` — emitted for CDATA sections. Data is a binary string.
+    * `:characters_chunk` / `:cdata_chunk` — emitted by `RustyXML.parse_stream/4`
+      with the `:text_chunk_size` option for large text or CDATA content. Data
+      is `{binary, final?}`; the node is complete once `final?` is `true`.
+    * `:duplicate_id` — emitted by `RustyXML.parse_stream/4` with the
+      `:duplicate_ids` option, right after the `:start_element` of an element
+      whose `id` or `xml:id` value was already seen. Data is
+      `{value, occurrence}`, where `occurrence` is 2 for the first repeat.
+    * `:warning` — emitted by `RustyXML.parse_stream/4` with `on_limit: :warn`,
+      right after the `:start_element` of an element that breaks
+      `:max_attributes` or `:max_elements`. Data is
+      `{:attribute_limit_exceeded | :element_limit_exceeded, name, count}`.
     * `:end_element` — emitted for each closing tag. Data is the element name (binary).
     * `:end_document` — emitted once at the end. Data is `{}`.
 
@@ -42,6 +53,10 @@ defmodule RustyXML.Handler do
           | :start_element
           | :characters
           | :cdata
+          | :characters_chunk
+          | :cdata_chunk
+          | :duplicate_id
+          | :warning
           | :end_element
           | :end_document
 
@@ -49,6 +64,9 @@ defmodule RustyXML.Handler do
           keyword()
           | {String.t(), [{String.t(), String.t()}]}
           | String.t()
+          | {String.t(), boolean()}
+          | {String.t(), pos_integer()}
+          | {:attribute_limit_exceeded | :element_limit_exceeded, String.t(), pos_integer()}
           | {}
 
   @callback handle_event(event_type(), event_data(), state) ::
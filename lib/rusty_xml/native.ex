@@ -23,6 +23,15 @@ defmodule RustyXML.Native do
   NIFs that parse raw XML input run on the dirty CPU scheduler to avoid
   blocking BEAM schedulers. Query NIFs on pre-parsed documents run on
   normal schedulers for sub-millisecond lookups.
+
+  ## Panics
+
+  A panic in any NIF that parses input or reads a document is returned as
+  `{:error, :internal_parser_error}` rather than raised. `parse_async/3` and
+  `sax_parse_send/3` streams end with that error as their last message.
+  Functions that only create, close or cancel handles, acknowledge stream
+  batches, or read and change settings and statistics are not covered: they
+  never look at document content.
   """
 
   version = Mix.Project.config()[:version]
@@ -56,6 +65,42 @@ defmodule RustyXML.Native do
   @typedoc "Opaque reference to a streaming parser"
   @opaque parser_ref :: reference()
 
+  @typedoc "Opaque reference to a node-set being converted incrementally"
+  @opaque cursor_ref :: reference()
+
+  @typedoc "Opaque continuation of an `xpath_query_chunked/3` result"
+  @opaque continuation_ref :: reference()
+
+  @typedoc "Opaque reference to a set of documents queried together"
+  @opaque docset_ref :: reference()
+
+  @typedoc "Opaque handle tagging the messages of one `sax_parse_send/3` stream"
+  @opaque sax_stream_ref :: reference()
+
+  @typedoc "Opaque handle of a `parse_async/3` build"
+  @opaque parse_task_ref :: reference()
+
+  @typedoc "Opaque cancellation token from `cancel_token_new/0`"
+  @opaque cancel_token_ref :: reference()
+
+  @typedoc "Opaque compiled Schematron schema from `schematron_compile/1`"
+  @opaque schematron_ref :: reference()
+
+  @typedoc "Opaque streaming well-formedness validator from `validator_new/0`"
+  @opaque validator_ref :: reference()
+
+  @typedoc """
+  First well-formedness error a streaming validator found: the message
+  `parse_strict/1` uses, and the byte offset, line and column (in bytes,
+  both from 1) of the construct where it was found
+  """
+  @type validation_error :: %{
+          message: String.t(),
+          offset: non_neg_integer(),
+          line: pos_integer(),
+          column: pos_integer()
+        }
+
   @typedoc "XML event from parser"
   @type xml_event ::
           {:start_element, binary(), [{binary(), binary()}]}
@@ -65,6 +110,30 @@ defmodule RustyXML.Native do
           | {:cdata, binary()}
           | {:comment, binary()}
 
+  @typedoc """
+  Reason a document was rejected: a well-formedness message, an exceeded
+  limit, or an encoding that was detected but cannot be parsed
+  """
+  @type parse_error ::
+          binary()
+          | {:max_depth_exceeded, pos_integer()}
+          | {:unsupported_encoding, binary()}
+          | :memory_limit
+          | :internal_parser_error
+
+  @typedoc """
+  Resources used by one call of `parse_timed/1` or `xpath_query_timed/2`
+  """
+  @type call_usage :: %{
+          duration_us: non_neg_integer(),
+          peak_alloc_bytes: non_neg_integer(),
+          nodes_visited: non_neg_integer()
+        }
+
+  @typedoc "Unicode normalization form applied to returned text"
+  @type normalization :: :nfc | :nfd | :none
+  @type result_order :: :document | :reverse | :matched
+
   # ==========================================================================
   # Main Parse Path: Structural Index + XPath
   # ==========================================================================
@@ -79,13 +148,16 @@ defmodule RustyXML.Native do
 
   This is the primary parse function - uses ~4x input size memory.
 
+  UTF-16 input is converted to UTF-8 first, replacing malformed code units
+  with U+FFFD rather than failing.
+
   ## Examples
 
       doc = RustyXML.Native.parse("<root><item id=\"1\"/></root>")
       RustyXML.Native.xpath_query(doc, "//item")
 
   """
-  @spec parse(binary()) :: document_ref()
+  @spec parse(binary()) :: document_ref() | {:error, :memory_limit | :internal_parser_error}
   def parse(_xml), do: :erlang.nif_error(:nif_not_loaded)
 
   @doc """
@@ -94,7 +166,11 @@ defmodule RustyXML.Native do
   Runs on the dirty CPU scheduler since parse time scales with input size.
 
   Returns `{:ok, document_ref}` on success, or `{:error, reason}` if the
-  document is not well-formed per XML 1.0 specification.
+  document is not well-formed per XML 1.0 specification. Documents nested
+  deeper than `max_depth/0` are rejected with
+  `{:error, {:max_depth_exceeded, limit}}`. UTF-16 input is converted;
+  UTF-32 and EBCDIC input is rejected with
+  `{:error, {:unsupported_encoding, name}}` (see `detect_encoding/1`).
 
   ## Examples
 
@@ -103,350 +179,2323 @@ defmodule RustyXML.Native do
       {:error, reason} = RustyXML.Native.parse_strict("<1invalid/>")
 
   """
-  @spec parse_strict(binary()) :: {:ok, document_ref()} | {:error, binary()}
+  @spec parse_strict(binary()) :: {:ok, document_ref()} | {:error, parse_error()}
   def parse_strict(_xml), do: :erlang.nif_error(:nif_not_loaded)
 
   @doc """
-  Execute an XPath query on a parsed document.
+  Like `parse_strict/1`, also applying the Namespaces in XML 1.0 checks.
 
-  Returns the result based on the XPath expression:
-    * Node-set queries return a list of element tuples
-    * String queries return a string
-    * Number queries return a float
-    * Boolean queries return true/false
+  Attributes whose prefixes resolve to the same namespace URI and that
+  share a local name (`a:x` and `b:x` with `a` and `b` bound to one URI)
+  are rejected as duplicates, along with the violations
+  `validate_namespaces/1` reports. The first one found is the error reason.
+  """
+  @spec parse_strict_namespaces(binary()) :: {:ok, document_ref()} | {:error, parse_error()}
+  def parse_strict_namespaces(_xml), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Parse in strict mode like `parse_strict/1`, decoding the input in the
+  charset given by a Content-Type value.
+
+  The encoding is chosen by the precedence of RFC 7303: a byte order mark,
+  then the `charset` parameter of `content_type`, then the `encoding` of
+  the XML declaration. Labels are resolved as in the WHATWG Encoding
+  Standard, so `iso-8859-1` decodes as windows-1252. An unrecognised label
+  is reported as `{:error, {:unsupported_encoding, label}}`.
 
   ## Examples
 
-      doc = RustyXML.Native.parse("<root><item>text</item></root>")
-      RustyXML.Native.xpath_query(doc, "//item")
-      #=> [{:element, "item", [], ["text"]}]
+      RustyXML.Native.parse_with_charset_hint(body, "text/xml; charset=iso-8859-1")
+      #=> {:ok, doc}
 
   """
-  @spec xpath_query(document_ref(), binary()) :: term()
-  def xpath_query(_doc, _xpath), do: :erlang.nif_error(:nif_not_loaded)
+  @spec parse_with_charset_hint(binary(), String.t()) ::
+          {:ok, document_ref()} | {:error, parse_error()}
+  def parse_with_charset_hint(_xml, _content_type), do: :erlang.nif_error(:nif_not_loaded)
 
   @doc """
-  Execute an XPath query returning XML strings for node sets (fast path).
+  Check a document against the Namespaces in XML 1.0 constraints.
 
-  Instead of building nested Elixir tuples for each element, this returns
-  the serialized XML string for each node. Much faster for queries returning
-  many elements.
+  Reports every violation rather than stopping at the first:
+
+    * prefixes (other than `xml` and `xmlns`) used before being declared
+    * `xmlns:xml` bound to anything but the XML namespace, and other
+      prefixes or the default namespace bound to a reserved URI
+    * declarations of the `xmlns` prefix
+    * prefixed undeclarations such as `xmlns:a=""`
+    * attributes duplicated through prefixes bound to the same URI
+
+  Each error is a map with the element's `:path` (positions among
+  same-named siblings, e.g. `"/feed[1]/entry[3]"`) and a `:message`. The
+  input should be well-formed; a well-formedness error ends the check and
+  is reported at the element where it occurred.
 
   ## Examples
 
-      doc = RustyXML.Native.parse("<root><item>text</item></root>")
-      RustyXML.Native.xpath_query_raw(doc, "//item")
-      #=> ["<item>text</item>"]
+      RustyXML.Native.validate_namespaces(~s(<r><a:b/></r>))
+      #=> {:error, [%{path: "/r[1]/a:b[1]",
+      #=>             message: "Undeclared namespace prefix 'a' on element <a:b>"}]}
 
   """
-  @spec xpath_query_raw(document_ref(), binary()) :: [binary()] | term()
-  def xpath_query_raw(_doc, _xpath), do: :erlang.nif_error(:nif_not_loaded)
+  @spec validate_namespaces(binary()) ::
+          :ok | {:error, [%{path: String.t(), message: String.t()}]}
+  def validate_namespaces(_xml), do: :erlang.nif_error(:nif_not_loaded)
 
   @doc """
-  Execute XPath query returning text values for node sets (optimized fast path).
-
-  Instead of building nested Elixir tuples for each element, returns the
-  concatenated text content of each node as a string. Much faster for the
-  common case where `is_value: true` (no `e` modifier).
+  Check that a document is well-formed as `parse_strict/1` would, without
+  building an index or returning a document.
 
-  For non-NodeSet results (numbers, strings, booleans), returns as-is.
+  Returns `:ok`, or the `{:error, reason}` `parse_strict/1` would return,
+  stopping at the first error. The depth limit is not applied since no
+  tree is built. Runs on the dirty CPU scheduler.
   """
-  @spec xpath_text_list(document_ref(), binary()) :: [binary()] | term()
-  def xpath_text_list(_doc, _xpath), do: :erlang.nif_error(:nif_not_loaded)
+  @spec well_formed(binary()) :: :ok | {:error, parse_error()}
+  def well_formed(_xml), do: :erlang.nif_error(:nif_not_loaded)
 
   @doc """
-  Parse XML and execute an XPath query in one call.
+  Parse an XML fragment into a structural index document.
 
-  Runs on the dirty CPU scheduler since it parses raw XML input.
+  Accepts any number of top-level elements, mixed with text. They become
+  children of the document node, so XPath queries see the fragment as
+  written. Returns `{:ok, document_ref}` or `{:error, reason}` with the same
+  reasons as `parse_strict/1`; with `lenient` set, malformed content is
+  accepted as by `parse/1`.
 
-  More efficient than `parse/1` + `xpath_query/2` for single queries
-  since it doesn't create a persistent document reference.
+  Runs on the dirty CPU scheduler since parse time scales with input size.
+  """
+  @spec parse_fragment(binary(), boolean()) :: {:ok, document_ref()} | {:error, parse_error()}
+  def parse_fragment(_xml, _lenient), do: :erlang.nif_error(:nif_not_loaded)
 
-  ## Examples
+  @doc """
+  Parse input that may have been cut off, such as a partial download.
 
-      RustyXML.Native.parse_and_xpath("<root><item/></root>", "//item")
+  Indexes everything before the cut point: a tag cut off midway is dropped
+  and elements still open at the end of input are closed there, so the
+  complete records before the cut can still be queried. Check
+  `doc_truncated/1` to tell whether anything was cut. Returns
+  `{:ok, document_ref}`, or `{:error, reason}` only for an unsupported or
+  invalid encoding.
 
+  Runs on the dirty CPU scheduler since parse time scales with input size.
   """
-  @spec parse_and_xpath(binary(), binary()) :: term()
-  def parse_and_xpath(_xml, _xpath), do: :erlang.nif_error(:nif_not_loaded)
+  @spec parse_salvage(binary()) :: {:ok, document_ref()} | {:error, parse_error()}
+  def parse_salvage(_xml), do: :erlang.nif_error(:nif_not_loaded)
 
   @doc """
-  Parse and immediately query, returning text values for node sets.
+  Whether a document parsed by `parse_salvage/1` was cut off.
 
-  Optimized path for `is_value: true` — avoids building element tuples.
+  True if elements were still open at the end of input or the input ends
+  inside a tag; always false for documents from the other parse functions.
   """
-  @spec parse_and_xpath_text(binary(), binary()) :: [binary()] | term()
-  def parse_and_xpath_text(_xml, _xpath), do: :erlang.nif_error(:nif_not_loaded)
+  @spec doc_truncated(document_ref()) :: boolean()
+  def doc_truncated(_doc), do: :erlang.nif_error(:nif_not_loaded)
 
   @doc """
-  Get the root element of a parsed document.
-
-  Returns the root element as a tuple:
-  `{:element, name, attributes, children}`
+  Build a document on a background worker, reporting progress to `pid`.
+
+  Workers are a fixed pool with one thread per core; further builds wait for
+  a free worker. Returns a task handle at once, then sends:
+
+    * `{:rustyxml_parse, task, {:progress, bytes_done, total}}` each time
+      another `interval` bytes have been scanned, and once with
+      `bytes_done == total` when the scan completes
+    * `{:rustyxml_parse, task, {:ok, document_ref}}` when done, or
+      `{:rustyxml_parse, task, {:error, :cancelled}}` if `parse_cancel/1`
+      was called first, or `{:rustyxml_parse, task, {:error,
+      :internal_parser_error}}` if indexing panicked
+    * `{:rustyxml_parse, task, {:error, :memory_limit}}` or
+      `{:rustyxml_parse, task, {:error, {:max_depth_exceeded, limit}}}`
+      if the document is over `set_memory_limit/1` or `set_max_depth/1`,
+      checked as `parse_fragment/2` does
+
+  The input is indexed leniently, as by `parse/1`, and retained rather
+  than copied, after a UTF-8 BOM. UTF-16 input is converted to UTF-8 on
+  the worker, and `total` counts the converted bytes. The build is
+  abandoned if `pid` exits. Raises `ArgumentError` if `interval` is 0.
 
   ## Examples
 
-      doc = RustyXML.Native.parse("<root attr=\"value\"><child/></root>")
-      RustyXML.Native.get_root(doc)
-      #=> {:element, "root", [{"attr", "value"}], [...]}
+      task = RustyXML.Native.parse_async(xml, self(), 16 * 1024 * 1024)
+
+      receive do
+        {:rustyxml_parse, ^task, {:ok, doc}} -> doc
+      end
 
   """
-  @spec get_root(document_ref()) :: term() | nil
-  def get_root(_doc), do: :erlang.nif_error(:nif_not_loaded)
+  @spec parse_async(binary(), pid(), pos_integer()) :: parse_task_ref()
+  def parse_async(_xml, _pid, _interval), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Abandon a `parse_async/3` build.
+
+  The build stops at its next progress check and reports
+  `{:error, :cancelled}`. Has no effect on a build that already finished.
+  """
+  @spec parse_cancel(parse_task_ref()) :: :ok
+  def parse_cancel(_task), do: :erlang.nif_error(:nif_not_loaded)
 
   # ==========================================================================
-  # XPath Helpers
+  # Closing Resources
   # ==========================================================================
 
   @doc """
-  Execute parent XPath and evaluate subspecs for each result node.
-
-  Runs on the dirty CPU scheduler since it parses raw XML input.
+  Release a parsed document now instead of when its reference is garbage
+  collected.
 
-  Returns a list of maps with each subspec evaluated relative to the parent nodes.
+  Any later call with the reference, from any process, returns
+  `{:error, :closed}`. Queries already running finish first, and
+  sub-binaries of the input returned by earlier queries keep the input
+  alive. Closing twice is a no-op. Documents from `parse_cached/1` are
+  shared by every caller of the same payload, so they can't be closed and
+  return `{:error, :shared_document}`.
 
   ## Examples
 
-      xml = "<items><item><id>1</id><name>A</name></item></items>"
-      RustyXML.Native.xpath_with_subspecs(xml, "//item", [{"id", "./id/text()"}, {"name", "./name/text()"}])
-      #=> [%{id: "1", name: "A"}]
+      doc = RustyXML.Native.parse(large_xml)
+      items = RustyXML.Native.xpath_query(doc, "//item")
+      :ok = RustyXML.Native.doc_close(doc)
+      RustyXML.Native.xpath_query(doc, "//item")
+      #=> {:error, :closed}
 
   """
-  @spec xpath_with_subspecs(binary(), binary(), [{binary(), binary()}]) :: [map()]
-  def xpath_with_subspecs(_xml, _parent_xpath, _subspecs), do: :erlang.nif_error(:nif_not_loaded)
+  @spec doc_close(document_ref()) :: :ok | {:error, :shared_document}
+  def doc_close(_doc), do: :erlang.nif_error(:nif_not_loaded)
 
   @doc """
-  Execute XPath and return string value of result.
+  Release the buffers of a streaming parser from `streaming_new/0`,
+  `streaming_sax_new/0` or their variants.
 
-  Runs on the dirty CPU scheduler since it parses raw XML input.
-  For node-sets, returns text content of first node.
-
-  ## Examples
+  Any later call with the parser returns `{:error, :closed}`.
+  """
+  @spec streaming_close(parser_ref() | reference()) :: :ok
+  def streaming_close(_parser), do: :erlang.nif_error(:nif_not_loaded)
 
-      RustyXML.Native.xpath_string_value("<root>hello</root>", "//root/text()")
-      #=> "hello"
+  # ==========================================================================
+  # Shared Document Cache
+  # ==========================================================================
 
+  @doc """
+  Parse in strict mode like `parse_strict/1`, reusing the document from an
+  earlier call with identical input.
+
+  Documents are kept in a cache inside the NIF, shared by all processes and
+  keyed by the xxh3 hash of the input, holding the 16 most recently used
+  documents by default (see `set_cache_capacity/1`). A hit is compared with
+  the input byte for byte before it is returned. Every caller of the same
+  payload gets the same document, so its settings are frozen:
+  `doc_set_normalization/2`, `doc_set_result_cache/2`,
+  `doc_build_attr_index/2` and `doc_close/1` return
+  `{:error, :shared_document}` for it.
+  Malformed input is not cached.
   """
-  @spec xpath_string_value(binary(), binary()) :: binary()
-  def xpath_string_value(_xml, _xpath), do: :erlang.nif_error(:nif_not_loaded)
+  @spec parse_cached(binary()) :: {:ok, document_ref()} | {:error, parse_error()}
+  def parse_cached(_xml), do: :erlang.nif_error(:nif_not_loaded)
 
   @doc """
-  Execute XPath on document reference and return string value.
+  Set how many documents `parse_cached/1` keeps, evicting the least
+  recently used ones beyond it. `0` empties and disables the cache.
   """
-  @spec xpath_string_value_doc(document_ref(), binary()) :: binary()
-  def xpath_string_value_doc(_doc, _xpath), do: :erlang.nif_error(:nif_not_loaded)
+  @spec set_cache_capacity(non_neg_integer()) :: :ok
+  def set_cache_capacity(_capacity), do: :erlang.nif_error(:nif_not_loaded)
 
   # ==========================================================================
-  # Streaming Parser
+  # Cancellation
   # ==========================================================================
 
   @doc """
-  Create a new streaming XML parser.
+  Create an unset cancellation token.
+  """
+  @spec cancel_token_new() :: cancel_token_ref()
+  def cancel_token_new, do: :erlang.nif_error(:nif_not_loaded)
 
-  The streaming parser processes XML in chunks with bounded memory usage.
+  @doc """
+  Set a token. Work running under it stops at its next check; the token
+  stays set.
+  """
+  @spec cancel_token_cancel(cancel_token_ref()) :: :ok
+  def cancel_token_cancel(_token), do: :erlang.nif_error(:nif_not_loaded)
 
-  ## Examples
+  @doc """
+  Whether a token has been set.
+  """
+  @spec cancel_token_cancelled(cancel_token_ref()) :: boolean()
+  def cancel_token_cancelled(_token), do: :erlang.nif_error(:nif_not_loaded)
 
-      parser = RustyXML.Native.streaming_new()
-      RustyXML.Native.streaming_feed(parser, "<root>")
-      RustyXML.Native.streaming_feed(parser, "<item/></root>")
-      events = RustyXML.Native.streaming_take_events(parser, 100)
+  @doc """
+  Same as `parse/1`, abandoning the build once `token` is set.
 
+  Runs on the dirty CPU scheduler. Returns `{:ok, document_ref}`, or
+  `{:error, :cancelled}` if the token was set before the index was
+  complete (including before the call), or `{:error, :memory_limit}` like
+  `parse/1`.
   """
-  @spec streaming_new() :: parser_ref()
-  def streaming_new, do: :erlang.nif_error(:nif_not_loaded)
+  @spec parse_cancellable(binary(), cancel_token_ref()) ::
+          {:ok, document_ref()} | {:error, :cancelled | :memory_limit}
+  def parse_cancellable(_xml, _token), do: :erlang.nif_error(:nif_not_loaded)
 
   @doc """
-  Create a streaming parser with a tag filter.
+  Same as `xpath_query/2`, abandoning evaluation once `token` is set.
 
-  Only events for the specified tag name will be emitted.
-  Useful for extracting specific elements from large documents.
+  Runs on the dirty CPU scheduler. Returns `{:error, :cancelled}` if the
+  token was set before evaluation finished. Building the result terms
+  is not interrupted.
+  """
+  @spec xpath_query_cancellable(document_ref(), binary(), cancel_token_ref()) ::
+          term() | {:error, :cancelled}
+  def xpath_query_cancellable(_doc, _xpath, _token), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Detect the encoding of XML input.
+
+  Uses the byte order mark, or the pattern of null bytes in the first four
+  bytes, as in appendix F of the XML 1.0 specification. Returns
+  `{name, bom?}` where `name` is one of `"UTF-8"`, `"UTF-16LE"`,
+  `"UTF-16BE"`, `"UTF-32LE"`, `"UTF-32BE"` or `"EBCDIC"`. Input without a
+  recognizable signature is reported as UTF-8.
 
   ## Examples
 
-      parser = RustyXML.Native.streaming_new_with_filter("item")
-      RustyXML.Native.streaming_feed(parser, "<root><item/><other/></root>")
-      # Only item events will be returned
+      RustyXML.Native.detect_encoding(<<0xFF, 0xFE, ?<, 0, ?r, 0>>)
+      #=> {"UTF-16LE", true}
 
   """
-  @spec streaming_new_with_filter(binary()) :: parser_ref()
-  def streaming_new_with_filter(_tag), do: :erlang.nif_error(:nif_not_loaded)
+  @spec detect_encoding(binary()) :: {binary(), boolean()}
+  def detect_encoding(_xml), do: :erlang.nif_error(:nif_not_loaded)
 
   @doc """
-  Feed a chunk of XML data to the streaming parser.
+  Execute an XPath query on a parsed document.
 
-  Returns `{available_events, buffer_size}` on success, or
-  `{:error, :mutex_poisoned}` if the parser mutex is poisoned.
-  """
-  @spec streaming_feed(parser_ref(), binary()) ::
-          {non_neg_integer(), non_neg_integer()} | {:error, :mutex_poisoned}
-  def streaming_feed(_parser, _chunk), do: :erlang.nif_error(:nif_not_loaded)
+  Returns the result based on the XPath expression:
+    * Node-set queries return a list of element tuples
+    * String queries return a string
+    * Number queries return a float
+    * Boolean queries return true/false
 
-  @doc """
-  Take up to `max` events from the streaming parser.
+  ## Examples
+
+      doc = RustyXML.Native.parse("<root><item>text</item></root>")
+      RustyXML.Native.xpath_query(doc, "//item")
+      #=> [{:element, "item", [], ["text"]}]
 
-  Returns `{:error, :mutex_poisoned}` if the parser mutex is poisoned.
   """
-  @spec streaming_take_events(parser_ref(), non_neg_integer()) ::
-          [xml_event()] | {:error, :mutex_poisoned}
-  def streaming_take_events(_parser, _max), do: :erlang.nif_error(:nif_not_loaded)
+  @spec xpath_query(document_ref(), binary()) :: term()
+  def xpath_query(_doc, _xpath), do: :erlang.nif_error(:nif_not_loaded)
 
   @doc """
-  Finalize the streaming parser and get remaining events.
+  Same as `xpath_query/2`, but runs on the dirty CPU scheduler.
 
-  Returns `{:error, :mutex_poisoned}` if the parser mutex is poisoned.
+  Use for large documents where a query can take long enough to block a
+  normal scheduler. `RustyXML.xpath/2` picks this automatically based on
+  `doc_node_count/1`.
   """
-  @spec streaming_finalize(parser_ref()) :: [xml_event()] | {:error, :mutex_poisoned}
-  def streaming_finalize(_parser), do: :erlang.nif_error(:nif_not_loaded)
+  @spec xpath_query_dirty(document_ref(), binary()) :: term()
+  def xpath_query_dirty(_doc, _xpath), do: :erlang.nif_error(:nif_not_loaded)
 
   @doc """
-  Get streaming parser status.
+  Same as `xpath_query/2`, returning a node-set in the given order.
 
-  Returns `{available_events, buffer_size, has_pending}` on success, or
-  `{:error, :mutex_poisoned}` if the parser mutex is poisoned.
-  """
-  @spec streaming_status(parser_ref()) ::
-          {non_neg_integer(), non_neg_integer(), boolean()} | {:error, :mutex_poisoned}
-  def streaming_status(_parser), do: :erlang.nif_error(:nif_not_loaded)
+    * `:document` - document order, as `xpath_query/2` returns it
+    * `:reverse` - reverse document order
+    * `:matched` - the order the last step found the nodes in: context
+      node by context node along its axis (nearest first for reverse axes
+      such as `preceding::`), and the left side of `|` before the right.
+      Each node still appears once.
 
-  @doc """
-  Take up to `max` complete elements from the streaming parser.
+  Predicates see document order whatever the option, so `(//a)[1]` is the
+  first `a` in the document. Predicates at the end of the expression keep
+  the `:matched` order of the nodes they select: `(//c | //a)[@id]` lists
+  the `c` elements first. `:matched` bypasses the result cache.
 
-  Returns a list of XML binaries for complete elements. This is faster than
-  using events because the element strings are built in Rust without needing
-  reconstruction in Elixir.
+  ## Examples
+
+      doc = RustyXML.Native.parse("<r><a/><b/><c/></r>")
+      RustyXML.Native.xpath_query_ordered(doc, "//c | //a", :matched)
+      #=> [{:element, "c", [], []}, {:element, "a", [], []}]
 
   """
-  @spec streaming_take_elements(parser_ref(), non_neg_integer()) ::
-          [binary()] | {:error, :mutex_poisoned}
-  def streaming_take_elements(_parser, _max), do: :erlang.nif_error(:nif_not_loaded)
+  @spec xpath_query_ordered(document_ref(), binary(), result_order()) :: term()
+  def xpath_query_ordered(_doc, _xpath, _order), do: :erlang.nif_error(:nif_not_loaded)
 
   @doc """
-  Get number of available complete elements.
-
-  """
-  @spec streaming_available_elements(parser_ref()) ::
-          non_neg_integer() | {:error, :mutex_poisoned}
-  def streaming_available_elements(_parser), do: :erlang.nif_error(:nif_not_loaded)
+  Same as `xpath_query/2`, returning at most `limit` nodes of a node-set
+  after skipping the first `offset`, in document order.
 
-  # ==========================================================================
-  # SimpleForm Parsing
-  # ==========================================================================
+  The node-set is sliced before any terms are built, so fetching one page
+  of a large result costs the conversion of that page only. Results other
+  than node-sets are returned whole. Evaluating the expression still finds
+  every match, so this runs on the dirty CPU scheduler.
 
-  @doc """
-  Parse XML directly into SimpleForm `{name, attrs, children}` tree.
+  ## Examples
 
-  Bypasses the SAX event pipeline — builds the tree in Rust from the
-  structural index, decoding entities as needed.
+      # Rows 1001 to 1050
+      RustyXML.Native.xpath_query_slice(doc, "//row", 1000, 50)
 
-  Returns `{:ok, tree}` or `{:error, reason}`.
   """
-  @spec parse_to_simple_form(binary()) :: {:ok, tuple()} | {:error, binary()}
-  def parse_to_simple_form(_xml), do: :erlang.nif_error(:nif_not_loaded)
-
-  # ==========================================================================
-  # Document Accumulator (Streaming SimpleForm)
-  # ==========================================================================
+  @spec xpath_query_slice(document_ref(), binary(), non_neg_integer(), non_neg_integer()) ::
+          term()
+  def xpath_query_slice(_doc, _xpath, _offset, _limit), do: :erlang.nif_error(:nif_not_loaded)
 
   @doc """
-  Create a new document accumulator for streaming SimpleForm parsing.
-
-  Returns an opaque accumulator reference.
+  The first node an XPath query selects in document order, as `xpath_query/2`
+  would return it, or `nil` if nothing is selected.
+
+  Location paths whose last step has no positional predicate stop at the
+  first match, without building the whole node-set; a descendant scan ends
+  as soon as a node matches. Expressions that don't select nodes return
+  their value as `xpath_query/2` does. Returns `{:error, reason}` for an
+  invalid expression.
   """
-  @spec accumulator_new() :: reference()
-  def accumulator_new, do: :erlang.nif_error(:nif_not_loaded)
+  @spec xpath_first(document_ref(), binary()) :: term()
+  def xpath_first(_doc, _xpath), do: :erlang.nif_error(:nif_not_loaded)
 
   @doc """
-  Feed a chunk of data to the document accumulator.
+  Same as `xpath_first/2`, but runs on the dirty CPU scheduler.
   """
-  @spec accumulator_feed(reference(), binary()) :: :ok
-  def accumulator_feed(_acc, _chunk), do: :erlang.nif_error(:nif_not_loaded)
+  @spec xpath_first_dirty(document_ref(), binary()) :: term()
+  def xpath_first_dirty(_doc, _xpath), do: :erlang.nif_error(:nif_not_loaded)
 
   @doc """
-  Validate, index, and convert accumulated data to SimpleForm.
+  Whether an XPath query selects anything.
 
-  Returns `{:ok, tree}` or `{:error, reason}`.
+  True for a non-empty node-set or any attribute value; expressions that
+  don't select nodes give their XPath boolean value. Stops at the first
+  match like `xpath_first/2`. Returns `{:error, reason}` for an invalid
+  expression.
   """
-  @spec accumulator_to_simple_form(reference()) :: {:ok, tuple()} | {:error, binary()}
-  def accumulator_to_simple_form(_acc), do: :erlang.nif_error(:nif_not_loaded)
+  @spec xpath_exists(document_ref(), binary()) :: boolean() | {:error, binary()}
+  def xpath_exists(_doc, _xpath), do: :erlang.nif_error(:nif_not_loaded)
 
-  # ==========================================================================
-  # SAX Parsing
-  # ==========================================================================
+  @doc """
+  Same as `xpath_exists/2`, but runs on the dirty CPU scheduler.
+  """
+  @spec xpath_exists_dirty(document_ref(), binary()) :: boolean() | {:error, binary()}
+  def xpath_exists_dirty(_doc, _xpath), do: :erlang.nif_error(:nif_not_loaded)
 
   @doc """
-  Parse XML and return SAX events.
+  Number of nodes (elements and text nodes) in a parsed document.
 
-  Events are returned as tuples similar to Saxy's format.
+  Constant time; used to decide when to use the `_dirty` query variants.
   """
-  @spec sax_parse(binary()) :: [tuple()]
-  def sax_parse(_xml), do: :erlang.nif_error(:nif_not_loaded)
+  @spec doc_node_count(document_ref()) :: non_neg_integer()
+  def doc_node_count(_doc), do: :erlang.nif_error(:nif_not_loaded)
 
   @doc """
-  Parse XML and return SAX events in Saxy-compatible format.
+  Root element name and prolog declarations of a parsed document.
 
-  Events are emitted directly in Saxy format:
-  - `{:start_element, {name, attrs}}`
-  - `{:end_element, name}`
-  - `{:characters, content}`
-  - `{:cdata, content}`
+  Reads the XML declaration and DOCTYPE from the bytes before the root
+  element, without running a query. Fields the document doesn't declare
+  are `nil`; `:encoding` is the declared name as written, not the detected
+  input encoding. For fragments, `:root` is the first top-level element.
+
+  ## Examples
+
+      xml = ~s(<?xml version="1.0"?><!DOCTYPE note SYSTEM "note.dtd"><note/>)
+      doc = RustyXML.Native.parse(xml)
+      RustyXML.Native.doc_info(doc)
+      #=> %{
+      #=>   root: "note",
+      #=>   version: "1.0",
+      #=>   encoding: nil,
+      #=>   standalone: nil,
+      #=>   doctype: %{name: "note", public_id: nil, system_id: "note.dtd"}
+      #=> }
 
-  Comments and PIs are skipped. Empty elements emit start+end.
   """
-  @spec sax_parse_saxy(binary(), boolean()) :: [tuple()]
-  def sax_parse_saxy(_xml, _cdata_as_chars), do: :erlang.nif_error(:nif_not_loaded)
+  @spec doc_info(document_ref()) :: %{
+          root: binary() | nil,
+          version: binary() | nil,
+          encoding: binary() | nil,
+          standalone: boolean() | nil,
+          doctype:
+            %{name: binary(), public_id: binary() | nil, system_id: binary() | nil} | nil
+        }
+  def doc_info(_doc), do: :erlang.nif_error(:nif_not_loaded)
 
   @doc """
-  Take events from streaming parser in Saxy-compatible format.
-  """
-  @spec streaming_take_saxy_events(reference(), non_neg_integer(), boolean()) ::
-          [tuple()] | {:error, :mutex_poisoned}
-  def streaming_take_saxy_events(_parser, _max, _cdata_as_chars),
-    do: :erlang.nif_error(:nif_not_loaded)
+  Distinct element paths in the document, with how often each occurs.
 
-  # ==========================================================================
-  # Streaming SAX Parsing (chunk-by-chunk, bounded memory)
-  # ==========================================================================
+  Paths join qualified names with `/` from the root element down, and are
+  listed in order of first occurrence. Fragments have one path per
+  top-level element name. Computed in one pass over the index; runs on
+  the dirty CPU scheduler.
 
-  @doc """
-  Create a new streaming SAX parser.
+  ## Examples
+
+      doc = RustyXML.parse("<catalog><product><price/></product><product/></catalog>")
+      RustyXML.Native.doc_tag_paths(doc)
+      #=> [{"catalog", 1}, {"catalog/product", 2}, {"catalog/product/price", 1}]
 
-  Returns an opaque parser reference for use with `streaming_feed_sax/3`.
   """
-  @spec streaming_sax_new() :: reference()
-  def streaming_sax_new, do: :erlang.nif_error(:nif_not_loaded)
+  @spec doc_tag_paths(document_ref()) :: [{binary(), pos_integer()}]
+  def doc_tag_paths(_doc), do: :erlang.nif_error(:nif_not_loaded)
 
   @doc """
-  Feed a chunk and return SAX events as a compact binary.
+  Find all elements with the given tag name, in document order.
 
-  When the tail buffer is empty (common case), the NIF tokenizes the BEAM
-  binary in-place (zero copy) and writes events directly into an OwnedBinary
-  on the BEAM heap — no intermediate Rust Vec allocation. Only the
-  unprocessed tail (~100 bytes) is saved between calls.
+  Scans the structural index directly, bypassing XPath parsing and
+  evaluation. Equivalent to `xpath_query(doc, "//" <> tag_name)`: an
+  unprefixed name matches on local name, while a prefixed name such as
+  `"ns:item"` matches the qualified name as written in the document.
 
-  Format: sequence of `<<type::8, ...>>` where type 1=start, 2=end, 3=chars, 4=cdata.
-  """
-  @spec streaming_feed_sax(reference(), binary(), boolean()) :: binary()
-  def streaming_feed_sax(_parser, _chunk, _cdata_as_chars),
-    do: :erlang.nif_error(:nif_not_loaded)
+  ## Examples
 
-  @doc """
-  Finalize the streaming SAX parser, processing any remaining bytes.
+      doc = RustyXML.Native.parse("<root><item>a</item><item>b</item></root>")
+      RustyXML.Native.find_elements(doc, "item")
+      #=> [{:element, "item", [], ["a"]}, {:element, "item", [], ["b"]}]
 
-  Returns final events as a compact binary (same format as `streaming_feed_sax/3`).
   """
-  @spec streaming_finalize_sax(reference(), boolean()) :: binary()
-  def streaming_finalize_sax(_parser, _cdata_as_chars),
-    do: :erlang.nif_error(:nif_not_loaded)
-
-  # ==========================================================================
-  # Memory Tracking
-  # ==========================================================================
+  @spec find_elements(document_ref(), binary()) :: [tuple()]
+  def find_elements(_doc, _tag_name), do: :erlang.nif_error(:nif_not_loaded)
 
   @doc """
-  Get current Rust heap allocation in bytes.
+  Count elements with the given tag name.
 
-  Requires `memory_tracking` Cargo feature. Returns `0` otherwise.
+  Index scan equivalent to `count(//tag_name)` without XPath overhead.
+
+  ## Examples
+
+      doc = RustyXML.Native.parse("<root><item/><item/></root>")
+      RustyXML.Native.count_elements(doc, "item")
+      #=> 2
+
+  """
+  @spec count_elements(document_ref(), binary()) :: non_neg_integer()
+  def count_elements(_doc, _tag_name), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Execute an XPath query returning XML strings for node sets (fast path).
+
+  Instead of building nested Elixir tuples for each element, this returns
+  the serialized XML string for each node. Much faster for queries returning
+  many elements.
+
+  ## Examples
+
+      doc = RustyXML.Native.parse("<root><item>text</item></root>")
+      RustyXML.Native.xpath_query_raw(doc, "//item")
+      #=> ["<item>text</item>"]
+
+  """
+  @spec xpath_query_raw(document_ref(), binary()) :: [binary()] | term()
+  def xpath_query_raw(_doc, _xpath), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Same as `xpath_query_raw/2`, returning a node-set in the given order.
+  See `xpath_query_ordered/3`.
+  """
+  @spec xpath_query_raw_ordered(document_ref(), binary(), result_order()) :: [binary()] | term()
+  def xpath_query_raw_ordered(_doc, _xpath, _order), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Execute XPath query returning text values for node sets (optimized fast path).
+
+  Instead of building nested Elixir tuples for each element, returns the
+  concatenated text content of each node as a string. Much faster for the
+  common case where `is_value: true` (no `e` modifier).
+
+  For non-NodeSet results (numbers, strings, booleans), returns as-is.
+  """
+  @spec xpath_text_list(document_ref(), binary()) :: [binary()] | term()
+  def xpath_text_list(_doc, _xpath), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Same as `xpath_text_list/2`, but runs on the dirty CPU scheduler.
+  """
+  @spec xpath_text_list_dirty(document_ref(), binary()) :: [binary()] | term()
+  def xpath_text_list_dirty(_doc, _xpath), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Same as `xpath_text_list/2`, returning a node-set's text values in the
+  given order. See `xpath_query_ordered/3`.
+  """
+  @spec xpath_text_list_ordered(document_ref(), binary(), result_order()) :: [binary()] | term()
+  def xpath_text_list_ordered(_doc, _xpath, _order), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Pluck one attribute from every node an XPath expression selects.
+
+  Returns the value of `attr_name` for each node, in document order, without
+  converting the nodes to terms. Nodes lacking the attribute give `nil`.
+  Values without entity references share the document's memory. Returns
+  `{:error, reason}` if the expression doesn't select a node-set.
+
+  ## Examples
+
+      RustyXML.Native.xpath_attr_list(doc, "//item", "id")
+      #=> ["a1", "a2", nil]
+
+  """
+  @spec xpath_attr_list(document_ref(), binary(), binary()) ::
+          [binary() | nil] | {:error, binary()}
+  def xpath_attr_list(_doc, _xpath, _attr_name), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  String-values of an XPath result converted to `type` in Rust.
+
+  `type` is `:integer`, `:float`, `:date` or `:boolean`, read with the XML
+  Schema lexical forms (dates are `YYYY-MM-DD`; booleans are `true`,
+  `false`, `1` or `0`) after trimming whitespace. Returns
+  `{values, failures}`: `values` has `nil` wherever a string-value didn't
+  convert, and `failures` lists those as `{position, string_value}` with
+  0-based positions. Runs on the dirty CPU scheduler.
+
+  ## Examples
+
+      RustyXML.Native.xpath_values_typed(doc, "//order/qty", :integer)
+      #=> {[3, nil, 12], [{1, "n/a"}]}
+
+      RustyXML.Native.xpath_values_typed(doc, "//order/@placed", :date)
+      #=> {[~D[2024-05-01]], []}
+
+  """
+  @spec xpath_values_typed(document_ref(), binary(), :integer | :float | :date | :boolean) ::
+          {list(), [{non_neg_integer(), binary()}]} | {:error, binary()}
+  def xpath_values_typed(_doc, _xpath, _type), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Distinct string-values of an XPath result, in order of first occurrence.
+
+  Deduplicates in Rust, so only the distinct strings are converted to
+  terms. Node-sets contribute the string-value of each node; a scalar
+  result gives a one-element list. Runs on the dirty CPU scheduler.
+
+  ## Examples
+
+      RustyXML.Native.xpath_distinct_values(doc, "//order/region")
+      #=> ["EU", "US"]
+
+  """
+  @spec xpath_distinct_values(document_ref(), binary()) :: [binary()] | {:error, binary()}
+  def xpath_distinct_values(_doc, _xpath), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Group the nodes selected by `items_xpath` by a key.
+
+  `key_xpath` is evaluated relative to each node and its string-value is
+  the key; a key selecting nothing groups the node under `""`. Returns a
+  map from key to the nodes in document order, in the same form as
+  `xpath_query/2`. `items_xpath` must select a node-set. Runs on the dirty
+  CPU scheduler.
+
+  ## Examples
+
+      RustyXML.Native.xpath_group_by(doc, "//order", "region")
+      #=> %{"EU" => [{:element, "order", ...}, ...], "US" => [...]}
+
+  """
+  @spec xpath_group_by(document_ref(), binary(), binary()) ::
+          %{binary() => [term()]} | {:error, binary()}
+  def xpath_group_by(_doc, _items_xpath, _key_xpath),
+    do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Number of nodes (or attribute values) an XPath expression selects.
+
+  Counts in Rust without converting the nodes to terms, so
+  `xpath_count(doc, "//row")` on a large document returns one integer.
+  Returns `{:error, reason}` if the expression doesn't select a node-set.
+  Runs on the dirty CPU scheduler.
+  """
+  @spec xpath_count(document_ref(), binary()) :: non_neg_integer() | {:error, binary()}
+  def xpath_count(_doc, _xpath), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Sum of the number-values an XPath expression selects, as XPath's `sum()`.
+
+  Each node contributes its string-value parsed as a number. Returns `0.0`
+  for an empty node-set and `nil` if any value isn't a number. Runs on the
+  dirty CPU scheduler.
+  """
+  @spec xpath_sum(document_ref(), binary()) :: float() | nil | {:error, binary()}
+  def xpath_sum(_doc, _xpath), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Smallest number-value an XPath expression selects.
+
+  Returns `nil` for an empty node-set or if any value isn't a number. Runs
+  on the dirty CPU scheduler.
+  """
+  @spec xpath_min(document_ref(), binary()) :: float() | nil | {:error, binary()}
+  def xpath_min(_doc, _xpath), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Largest number-value an XPath expression selects. See `xpath_min/2`.
+  """
+  @spec xpath_max(document_ref(), binary()) :: float() | nil | {:error, binary()}
+  def xpath_max(_doc, _xpath), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Parse XML and execute an XPath query in one call.
+
+  Runs on the dirty CPU scheduler since it parses raw XML input.
+
+  More efficient than `parse/1` + `xpath_query/2` for single queries
+  since it doesn't create a persistent document reference.
+
+  ## Examples
+
+      RustyXML.Native.parse_and_xpath("<root><item/></root>", "//item")
+
+  """
+  @spec parse_and_xpath(binary(), binary()) :: term()
+  def parse_and_xpath(_xml, _xpath), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  `parse_and_xpath/2` on a normal scheduler.
+
+  For inputs under about 1 KB, where switching to a dirty scheduler costs
+  more than the parse and query. Larger inputs should use `parse_and_xpath/2`.
+  """
+  @spec parse_and_xpath_small(binary(), binary()) :: term()
+  def parse_and_xpath_small(_xml, _xpath), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Parse and immediately query, returning text values for node sets.
+
+  Optimized path for `is_value: true` — avoids building element tuples.
+  """
+  @spec parse_and_xpath_text(binary(), binary()) :: [binary()] | term()
+  def parse_and_xpath_text(_xml, _xpath), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  `parse_and_xpath_text/2` on a normal scheduler, for small inputs.
+  See `parse_and_xpath_small/2`.
+  """
+  @spec parse_and_xpath_text_small(binary(), binary()) :: [binary()] | term()
+  def parse_and_xpath_text_small(_xml, _xpath), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Get the root element of a parsed document.
+
+  Returns the root element as a tuple:
+  `{:element, name, attributes, children}`
+
+  Names, attribute values and text of 64 bytes or more that appear in the
+  document unchanged are sub-binaries of the document's input rather than
+  copies, as are those in `xpath_query/2` results. Holding on to one keeps
+  the whole input alive; use `:binary.copy/1` to detach it.
+
+  ## Examples
+
+      doc = RustyXML.Native.parse("<root attr=\"value\"><child/></root>")
+      RustyXML.Native.get_root(doc)
+      #=> {:element, "root", [{"attr", "value"}], [...]}
+
+  """
+  @spec get_root(document_ref()) :: term() | nil
+  def get_root(_doc), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Same as `get_root/1`, but runs on the dirty CPU scheduler.
+
+  Building the root term walks the whole tree, so large documents should
+  use this variant. `RustyXML.root/1` picks it automatically.
+  """
+  @spec get_root_dirty(document_ref()) :: term() | nil
+  def get_root_dirty(_doc), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Execute an XPath query with additional documents available to `document()`.
+
+  `docs` maps names to parsed documents; `document('name')` in the query
+  returns the root node of that document, so queries can join across
+  documents. Nothing is loaded from disk — unknown names are an error.
+  Up to 15 additional documents are supported. Runs on the dirty CPU
+  scheduler.
+
+  ## Examples
+
+      doc = RustyXML.Native.parse(~s(<orders><order ref="a"/></orders>))
+      lookup = RustyXML.Native.parse("<items><item><id>a</id>Apple</item></items>")
+
+      RustyXML.Native.xpath_query_with_docs(
+        doc,
+        "//order[@ref = document('lookup.xml')//id]",
+        %{"lookup.xml" => lookup}
+      )
+      #=> [{:element, "order", [{"ref", "a"}], []}]
+
+  """
+  @spec xpath_query_with_docs(document_ref(), binary(), %{binary() => document_ref()}) ::
+          term()
+  def xpath_query_with_docs(_doc, _xpath, _docs), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Create an empty document set.
+
+  A set holds many (typically small) documents so that one XPath query can
+  run across all of them with `docset_query/2` in a single NIF call.
+  """
+  @spec docset_new() :: docset_ref()
+  def docset_new, do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Parse a document in strict mode and add it to a set.
+
+  Returns `{:ok, doc_id}`, where `doc_id` is the document's position in the
+  set (starting at 0), or the same errors as `parse_strict/1`. Runs on the
+  dirty CPU scheduler.
+  """
+  @spec docset_add(docset_ref(), binary()) ::
+          {:ok, non_neg_integer()} | {:error, parse_error() | :mutex_poisoned}
+  def docset_add(_set, _xml), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Number of documents in a set.
+  """
+  @spec docset_size(docset_ref()) :: non_neg_integer() | {:error, :mutex_poisoned}
+  def docset_size(_set), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Run an XPath query against every document in a set.
+
+  The expression is compiled once and evaluated on all documents in
+  parallel. Returns `[{doc_id, result}]` in `doc_id` order, where `result`
+  has the same shape as `xpath_query/2`; documents where the query selects
+  an empty node-set are left out. Runs on the dirty CPU scheduler.
+
+  ## Examples
+
+      set = RustyXML.Native.docset_new()
+      {:ok, 0} = RustyXML.Native.docset_add(set, ~s(<log level="info"/>))
+      {:ok, 1} = RustyXML.Native.docset_add(set, ~s(<log level="error"/>))
+
+      RustyXML.Native.docset_query(set, "/log[@level = 'error']")
+      #=> [{1, [{:element, "log", [{"level", "error"}], []}]}]
+
+  """
+  @spec docset_query(docset_ref(), binary()) ::
+          [{non_neg_integer(), term()}] | {:error, term()}
+  def docset_query(_set, _xpath), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Enable a per-document cache of node-set query results.
+
+  Holds the node-sets of up to `capacity` distinct XPath strings (least
+  recently used first out); `0` disables the cache. Changing the capacity
+  drops existing entries. Useful when the same queries are run repeatedly
+  against a shared document, possibly from different processes.
+
+  Only the node ids are cached — results are still converted to terms on
+  every call. Scalar results (`count(...)`, strings) are never cached.
+  Documents from `parse_cached/1` are shared and return
+  `{:error, :shared_document}`.
+
+  ## Examples
+
+      doc = RustyXML.Native.parse("<root><item/></root>")
+      :ok = RustyXML.Native.doc_set_result_cache(doc, 32)
+
+  """
+  @spec doc_set_result_cache(document_ref(), non_neg_integer()) ::
+          :ok | {:error, :mutex_poisoned | :shared_document}
+  def doc_set_result_cache(_doc, _capacity), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Drop all cached results of a document, keeping the cache enabled.
+  """
+  @spec doc_clear_result_cache(document_ref()) :: :ok | {:error, :mutex_poisoned}
+  def doc_clear_result_cache(_doc), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Number of queries currently held in a document's result cache.
+  """
+  @spec doc_result_cache_size(document_ref()) :: non_neg_integer()
+  def doc_result_cache_size(_doc), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Build a hash index from the values of attribute `attr_name` to elements.
+
+  Afterwards, queries on this document of the form `//name[@attr = 'literal']`
+  and `//name[@attr]` (including `//*[...]` and `/descendant::name[...]`)
+  look the elements up in the index instead of scanning the whole document.
+  Results are identical with and without the index. Building an index that
+  already exists rebuilds it. Documents from `parse_cached/1` are shared and
+  return `{:error, :shared_document}`.
+
+  ## Examples
+
+      doc = RustyXML.Native.parse(catalog_xml)
+      :ok = RustyXML.Native.doc_build_attr_index(doc, "sku")
+      RustyXML.Native.xpath_query(doc, "//product[@sku = 'A-100']")
+
+  """
+  @spec doc_build_attr_index(document_ref(), String.t()) ::
+          :ok | {:error, :mutex_poisoned | :shared_document}
+  def doc_build_attr_index(_doc, _attr_name), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Names of the attributes indexed on a document with `doc_build_attr_index/2`.
+  """
+  @spec doc_attr_indexes(document_ref()) :: [String.t()]
+  def doc_attr_indexes(_doc), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Every occurrence of attribute `attr_name` in the document.
+
+  Returns `{element_name, value}` pairs in document order, collected in one
+  pass over the index rather than through an XPath query and node
+  conversion. The name is compared as written, so `"xlink:href"` matches
+  only that prefix. Values are entity-decoded. Runs on the dirty CPU
+  scheduler.
+
+  ## Examples
+
+      RustyXML.Native.doc_all_attributes(doc, "href")
+      #=> [{"a", "/about"}, {"link", "style.css"}]
+
+  """
+  @spec doc_all_attributes(document_ref(), String.t()) :: [{binary(), binary()}]
+  def doc_all_attributes(_doc, _attr_name), do: :erlang.nif_error(:nif_not_loaded)
+
+  # ==========================================================================
+  # Unicode Normalization
+  # ==========================================================================
+
+  @doc """
+  Return the string-values of a document's nodes in NFC or NFD.
+
+  Applies to `xpath_text_list/2`, `xpath_string_value_doc/2` and to
+  comparisons of node-sets with strings inside XPath expressions, where the
+  string is normalized too, so `//name[. = 'café']` matches both the
+  composed and the decomposed spelling, however the query spells it.
+  Attribute values are compared as written. `:none` turns normalization off.
+  Cached query results are dropped when the form changes. Documents from
+  `parse_cached/1` are shared and return `{:error, :shared_document}`.
+  """
+  @spec doc_set_normalization(document_ref(), normalization()) ::
+          :ok | {:error, :shared_document}
+  def doc_set_normalization(_doc, _form), do: :erlang.nif_error(:nif_not_loaded)
+
+  # ==========================================================================
+  # Precomputed String-Values
+  # ==========================================================================
+
+  @doc """
+  Precompute the string-value of every element in a document.
+
+  Lays out the document's decoded text once, in document order, and records
+  the range each element covers. Afterwards the string-value of an element
+  (`string(elem)`, `xpath_text_list/2`, comparisons in predicates) is a slice
+  of that buffer instead of a walk over its descendants. Costs roughly the
+  size of the document's text in memory. Calling it again does nothing.
+
+  `RustyXML.parse/2` calls this with `precompute_text: true`.
+  """
+  @spec doc_precompute_text(document_ref()) :: :ok
+  def doc_precompute_text(_doc), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Evaluate an XPath query, returning node-set results as a cursor.
+
+  Returns `{:cont, cursor}` for node-sets, to be drained with `cursor_next/1`,
+  `{:done, value}` for scalar results, or `{:error, reason}`. Converting a
+  large node-set to terms this way is spread over many short NIF calls
+  instead of one long one.
+  """
+  @spec xpath_query_cursor(document_ref(), binary()) ::
+          {:cont, cursor_ref()} | {:done, term()} | {:error, binary()}
+  def xpath_query_cursor(_doc, _xpath), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Convert the next nodes of a cursor, stopping when the timeslice is used up.
+
+  Returns `{:cont, nodes}` while nodes remain and `{:done, nodes}` for the
+  last batch. Each individual node is still converted in one piece.
+  """
+  @spec cursor_next(cursor_ref()) ::
+          {:cont, [tuple()]} | {:done, [tuple()]} | {:error, :mutex_poisoned}
+  def cursor_next(_cursor), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Evaluate an XPath query and convert the first `chunk_size` nodes.
+
+  Returns `{nodes, continuation}` when more nodes remain, or `{nodes, :done}`.
+  The continuation only holds the ids of the remaining nodes, so at most one
+  chunk of a large result exists as terms at a time; pass it to
+  `xpath_chunk_next/1` for the next chunk. Scalar results are returned as
+  `{[value], :done}`. `RustyXML.stream_xpath/3` wraps this with its
+  `:chunk_size` option.
+
+  ## Examples
+
+      {rows, cont} = RustyXML.Native.xpath_query_chunked(doc, "//row", 1000)
+      {more, :done} = RustyXML.Native.xpath_chunk_next(cont)
+
+  """
+  @spec xpath_query_chunked(document_ref(), binary(), pos_integer()) ::
+          {[term()], continuation_ref() | :done} | {:error, binary()}
+  def xpath_query_chunked(_doc, _xpath, _chunk_size), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Convert the next chunk of an `xpath_query_chunked/3` result.
+
+  Returns `{nodes, continuation}` or `{nodes, :done}`. A continuation that
+  was already exhausted returns `{[], :done}`.
+  """
+  @spec xpath_chunk_next(continuation_ref()) ::
+          {[term()], continuation_ref() | :done} | {:error, :mutex_poisoned}
+  def xpath_chunk_next(_continuation), do: :erlang.nif_error(:nif_not_loaded)
+
+  # ==========================================================================
+  # Raw XML Extraction
+  # ==========================================================================
+
+  @doc """
+  Evaluate an XPath query, returning the ids of the selected nodes.
+
+  Ids are in document order and can be passed to `node_outer_xml/2` and
+  `node_inner_xml/2` with the same document. Returns `{:error, reason}` if
+  the query fails or its result is not a node-set.
+  """
+  @spec xpath_node_ids(document_ref(), binary()) ::
+          {:ok, [non_neg_integer()]} | {:error, binary()}
+  def xpath_node_ids(_doc, _xpath), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Resolve an XPointer to the ids of the nodes it identifies.
+
+  Supports shorthand pointers and the `element()`, `xpointer()` and
+  `xpath1()` schemes; see `RustyXML.resolve_xpointer/2`. Returns
+  `{:ok, []}` if nothing is identified, `{:error, reason}` for a malformed
+  pointer or failing expression.
+  """
+  @spec resolve_xpointer(document_ref(), binary()) ::
+          {:ok, [non_neg_integer()]} | {:error, binary()}
+  def resolve_xpointer(_doc, _pointer), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Raw source bytes of a node, from its start tag through its end tag.
+
+  The result is a sub-binary of the parsed document (nothing is
+  re-serialized or copied), so entities, attribute quoting and whitespace
+  are exactly as in the input. CDATA sections, comments and processing
+  instructions include their delimiters. Returns `nil` for an unknown id.
+  """
+  @spec node_outer_xml(document_ref(), non_neg_integer()) :: binary() | nil
+  def node_outer_xml(_doc, _node_id), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Raw source bytes of a node's content, between its start and end tags.
+
+  Empty for self-closing elements. For CDATA, comments and processing
+  instructions, the content without delimiters. Returns `nil` for an
+  unknown id.
+  """
+  @spec node_inner_xml(document_ref(), non_neg_integer()) :: binary() | nil
+  def node_inner_xml(_doc, _node_id), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Number of child nodes of a node: elements, text, CDATA sections, comments
+  and processing instructions.
+
+  Read from the index without evaluating XPath; equivalent to
+  `count(node())` from the node. Returns `nil` for an unknown id.
+  """
+  @spec node_child_count(document_ref(), non_neg_integer()) :: non_neg_integer() | nil
+  def node_child_count(_doc, _node_id), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Number of nodes of every kind below a node; `count(descendant::node())`.
+
+  Returns `nil` for an unknown id.
+  """
+  @spec node_descendant_count(document_ref(), non_neg_integer()) :: non_neg_integer() | nil
+  def node_descendant_count(_doc, _node_id), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Number of ancestors of a node, counting the document node, so the root
+  element is at depth 1; `count(ancestor::node())`.
+
+  Returns `nil` for an unknown id.
+  """
+  @spec node_depth(document_ref(), non_neg_integer()) :: non_neg_integer() | nil
+  def node_depth(_doc, _node_id), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  1-based position of a node among all of its parent's child nodes;
+  `count(preceding-sibling::node()) + 1`.
+
+  Returns `nil` for the document node and unknown ids.
+
+  ## Examples
+
+      doc = RustyXML.parse("<r><a/>text<b/></r>")
+      {:ok, [b]} = RustyXML.Native.xpath_node_ids(doc, "//b")
+      RustyXML.Native.node_position(doc, b)
+      #=> 3
+
+  """
+  @spec node_position(document_ref(), non_neg_integer()) :: pos_integer() | nil
+  def node_position(_doc, _node_id), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  A node as a term, converting at most `depth` levels below it.
+
+  Terms are shaped as in `xpath_query/2`. An element at the limit that has
+  children gets `[{:more, node_id}]` as its children instead: a handle to
+  pass back here to expand it, so a viewer can show the top of a huge
+  document at once and convert the rest on demand. Returns `nil` for an
+  unknown node id.
+
+  ## Examples
+
+      doc = RustyXML.parse("<r><a><b/></a><c/></r>")
+      RustyXML.Native.node_to_term_shallow(doc, 0, 1)
+      #=> {:element, "r", [], [{:element, "a", [], [{:more, 1}]}, {:element, "c", [], []}]}
+
+  """
+  @spec node_to_term_shallow(document_ref(), non_neg_integer(), non_neg_integer()) ::
+          term() | nil
+  def node_to_term_shallow(_doc, _node_id, _depth), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Same as `node_to_term_shallow/3`, but runs on the dirty CPU scheduler.
+
+  Deep limits on large documents can convert most of the tree, so use this
+  variant there. `RustyXML.expand/3` picks it automatically.
+  """
+  @spec node_to_term_shallow_dirty(document_ref(), non_neg_integer(), non_neg_integer()) ::
+          term() | nil
+  def node_to_term_shallow_dirty(_doc, _node_id, _depth),
+    do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  A page of a node's children as terms: up to `limit` of them, starting
+  with the `offset`th (0-based).
+
+  Child elements come without their own children, which are replaced by
+  `[{:more, node_id}]` as in `node_to_term_shallow/3`, so a page costs the
+  same however large the subtrees under it are. Use `node_child_count/2`
+  for the number of pages. Returns `nil` for an unknown node id.
+
+  ## Examples
+
+      doc = RustyXML.parse("<log><e n='1'><m>a</m></e><e n='2'/><e n='3'/></log>")
+      RustyXML.Native.node_children_terms(doc, 0, 0, 2)
+      #=> [{:element, "e", [{"n", "1"}], [{:more, 1}]}, {:element, "e", [{"n", "2"}], []}]
+
+  """
+  @spec node_children_terms(
+          document_ref(),
+          non_neg_integer(),
+          non_neg_integer(),
+          non_neg_integer()
+        ) :: [term()] | nil
+  def node_children_terms(_doc, _node_id, _offset, _limit),
+    do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Copy an element's subtree into a new document.
+
+  The new document holds its own copy of the subtree's bytes and its own
+  index, so the parent can be garbage collected while work continues on the
+  (usually much smaller) extract. Namespace declarations inherited from
+  ancestors are added to the new root element so prefixes still resolve.
+  Returns `nil` for an unknown or non-element id.
+  """
+  @spec doc_extract_subtree(document_ref(), non_neg_integer()) ::
+          document_ref() | nil | {:error, :memory_limit | :internal_parser_error}
+  def doc_extract_subtree(_doc, _node_id), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Build a new document by splicing a node of `source_doc` into `target_doc`.
+
+  The source node's markup (with the namespace declarations it inherits)
+  is placed relative to `target_node_id`:
+
+    * `:before` / `:after` - as a preceding or following sibling
+    * `:first_child` / `:last_child` - inside the target element
+    * `:replace` - in place of the target
+
+  The rest of the target's bytes are copied unchanged, so entities and
+  formatting survive, and both inputs are left as they are. Returns `nil`
+  for unknown ids, a document node, a child position on a non-element
+  target, or a splice that would not leave exactly one root element: only
+  a comment or processing instruction can go before or after the root,
+  and only an element can replace it. Raises `ArgumentError` for any other
+  position.
+
+      envelope = RustyXML.parse("<env><body/></env>")
+      payload = RustyXML.parse("<data><row>1</row></data>")
+      {:ok, [body]} = RustyXML.Native.xpath_node_ids(envelope, "//body")
+      {:ok, [data]} = RustyXML.Native.xpath_node_ids(payload, "/data")
+      RustyXML.Native.doc_merge(envelope, body, payload, data, :last_child)
+      #=> document with <env><body><data><row>1</row></data></body></env>
+  """
+  @spec doc_merge(
+          document_ref(),
+          non_neg_integer(),
+          document_ref(),
+          non_neg_integer(),
+          :before | :after | :first_child | :last_child | :replace
+        ) :: document_ref() | nil | {:error, :memory_limit | :internal_parser_error}
+  def doc_merge(_target_doc, _target_node_id, _source_doc, _source_node_id, _position),
+    do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  The document as well-formed XML of at most `max_bytes` bytes.
+
+  Meant for logging oversized payloads. A document that fits is returned
+  as is. Otherwise nodes are kept in document order while they fit,
+  elements that don't fit whole are entered to keep their leading
+  children, and a `<!-- truncated -->` comment marks where content was
+  dropped before the open elements are closed. Only whole nodes are
+  dropped; the prolog goes first, unless it holds a DOCTYPE, which is
+  always kept since the content may use its entities. Returns `nil` if the
+  budget can't hold that prolog, the root element's tags and the marker.
+
+      doc = RustyXML.parse("<log><a>one</a><b><c>two</c><d>three</d></b></log>")
+      RustyXML.Native.truncate_xml(doc, 40)
+      #=> "<log><a>one</a><!-- truncated --></log>"
+  """
+  @spec truncate_xml(document_ref(), non_neg_integer()) :: binary() | nil
+  def truncate_xml(_doc, _max_bytes), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Split a document into standalone documents of up to `per_shard` of the
+  records `record_xpath` selects.
+
+  Each shard repeats the prolog and reopens the records' ancestors with
+  their original start tags, so envelope attributes and namespace
+  declarations carry over. A record nested in an earlier record travels
+  with it. Returns `{:ok, [xml]}` (empty when nothing matches), or
+  `{:error, reason}` if the expression fails or doesn't select nodes.
+  Raises `ArgumentError` if `per_shard` is 0. Runs on the dirty CPU
+  scheduler.
+  """
+  @spec split_records(document_ref(), binary(), pos_integer()) ::
+          {:ok, [binary()]} | {:error, binary() | :internal_parser_error}
+  def split_records(_doc, _record_xpath, _per_shard), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Value of an element's attribute by namespace URI and local name.
+
+  A prefixed attribute matches when its prefix is bound to `uri` by an
+  `xmlns:*` declaration on the element or an ancestor, whatever the prefix
+  is. An empty `uri` selects an unprefixed attribute. Returns `nil` if there
+  is no such attribute or the id is unknown.
+
+  ## Examples
+
+      doc = RustyXML.parse(~s(<r xmlns:x="urn:x"><e x:id="7"/></r>))
+      {:ok, [e]} = RustyXML.Native.xpath_node_ids(doc, "//e")
+      RustyXML.Native.node_attribute_ns(doc, e, "urn:x", "id")
+      #=> "7"
+
+  """
+  @spec node_attribute_ns(document_ref(), non_neg_integer(), binary(), binary()) ::
+          binary() | nil
+  def node_attribute_ns(_doc, _node_id, _uri, _local_name),
+    do: :erlang.nif_error(:nif_not_loaded)
+
+  # ==========================================================================
+  # Serialized Node Sets
+  # ==========================================================================
+
+  @doc """
+  Evaluate an XPath query and pack the selected nodes into a compact binary
+  (a 20-byte header plus 4 bytes per node).
+
+  The binary holds only node ids, so a result can be stored in ETS or sent
+  between pipeline stages cheaply and converted with
+  `nodeset_materialize/3` when needed. It is only meaningful for `doc`:
+  the header records its node counts and a hash of its bytes, so hashing
+  reads the whole document here and when materializing. Runs on the dirty
+  CPU scheduler. Returns `{:error, reason}` if the query fails or does not
+  select a node-set.
+
+  ## Examples
+
+      doc = RustyXML.Native.parse("<r><item>a</item><item>b</item></r>")
+      {:ok, packed} = RustyXML.Native.nodeset_serialize(doc, "//item")
+      :ets.insert(table, {:items, packed})
+
+  """
+  @spec nodeset_serialize(document_ref(), binary()) :: {:ok, binary()} | {:error, binary()}
+  def nodeset_serialize(_doc, _xpath), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Convert a binary from `nodeset_serialize/2` back into nodes of `doc`.
+
+  `format` is `:element` for element tuples like `xpath_query/2`, `:xml`
+  for serialized XML like `xpath_query_raw/2`, or `:text` for the
+  string-value of each node. Returns `{:error, :invalid_nodeset}` if the
+  binary is malformed or was packed from a document with other bytes.
+
+  ## Examples
+
+      RustyXML.Native.nodeset_materialize(doc, packed, :text)
+      #=> ["a", "b"]
+
+  """
+  @spec nodeset_materialize(document_ref(), binary(), :element | :xml | :text) ::
+          [term()] | {:error, :invalid_nodeset}
+  def nodeset_materialize(_doc, _packed, _format), do: :erlang.nif_error(:nif_not_loaded)
+
+  # ==========================================================================
+  # Text Search
+  # ==========================================================================
+
+  @doc """
+  Find a string in the raw text of a document.
+
+  Searches the document bytes with a SIMD substring search and maps each
+  match to the element whose text contains it through the index. Returns
+  `{element_id, offset}` for each non-overlapping match, where `offset` is
+  the byte offset of the match in the input. Element ids work with
+  `node_outer_xml/2` and `node_attribute_ns/4`.
+
+  Offsets count the UTF-8 bytes the document was indexed from, which are
+  not always the input as given: a leading byte order mark is not counted,
+  UTF-16 input is counted after conversion to UTF-8, and documents from
+  `RustyXML.parse_fragment/1` start with a wrapper start tag. Use the
+  element ids to locate matches in such documents.
+
+  The pattern is matched against the text as written: entity references
+  are not decoded, and matches in comments, PIs and tags, or spanning a
+  tag, are skipped.
+
+  ## Options
+
+    * `:attributes` - Also search attribute values, reporting the element
+      that carries them. Default: `false`.
+    * `:limit` - Stop after this many matches.
+
+  ## Examples
+
+      RustyXML.Native.doc_find_text(doc, "TODO", limit: 10)
+      #=> [{12, 4821}, {40, 9930}]
+
+  """
+  @spec doc_find_text(document_ref(), binary(), keyword()) ::
+          [{non_neg_integer(), non_neg_integer()}]
+  def doc_find_text(_doc, _pattern, _opts), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Search the text of the nodes `scope_xpath` selects with a regular expression.
+
+  Each node's string-value (its decoded text, including descendants) is
+  matched against `regex`, in Rust syntax. Returns `{node_id, ranges}` for
+  each node with at least one match, in document order, where `ranges` are
+  `{start, length}` byte ranges into the string-value. Nodes are searched
+  in parallel, and compiled regexes are cached across calls. Runs on the
+  dirty CPU scheduler.
+
+  Returns `{:error, reason}` for an invalid regex or XPath expression, or a
+  scope that doesn't select a node-set.
+
+  ## Examples
+
+      RustyXML.Native.doc_regex_search(doc, "GPL-[0-9.]+", "//license")
+      #=> [{17, [{6, 7}]}]
+
+  """
+  @spec doc_regex_search(document_ref(), binary(), binary()) ::
+          [{non_neg_integer(), [{non_neg_integer(), non_neg_integer()}]}] | {:error, binary()}
+  def doc_regex_search(_doc, _regex, _scope_xpath), do: :erlang.nif_error(:nif_not_loaded)
+
+  # ==========================================================================
+  # Malformed Input Repair
+  # ==========================================================================
+
+  @doc """
+  Rewrite malformed input into well-formed XML.
+
+  Returns `{fixed, repairs}`, where each repair is `{kind, offset}` with a
+  byte offset into the original input. `root` is an element name to wrap
+  the content in when it doesn't have exactly one top-level element, or
+  `nil`. Runs on the dirty CPU scheduler.
+
+  Repair kinds:
+
+    * `:escaped_ampersand`, `:escaped_less_than` - stray `&` or `<`
+    * `:escaped_greater_than` - the `>` of a `]]>` in text
+    * `:dropped_control_character` - a control character XML forbids,
+      anywhere in the input
+    * `:replaced_invalid_utf8` - a byte replaced with U+FFFD, or with `_`
+      in a name
+    * `:quoted_attribute_value`, `:added_attribute_value` - an unquoted
+      or missing attribute value
+    * `:dropped_duplicate_attribute`, `:dropped_invalid_attribute`
+    * `:dropped_end_tag` - an end tag matching no open element
+    * `:closed_element` - an element left open
+    * `:closed_tag`, `:closed_comment`, `:closed_cdata`,
+      `:closed_processing_instruction` - markup cut off before its end
+    * `:separated_hyphens` - a space added between hyphens in a comment
+    * `:dropped_xml_declaration` - an XML declaration not at the start
+    * `:wrapped_root` - the content was wrapped in `root`
+
+  ## Examples
+
+      RustyXML.Native.repair("<p>AT&T<br></p>", nil)
+      #=> {"<p>AT&amp;T<br></br></p>", [{:escaped_ampersand, 5}, {:closed_element, 11}]}
+
+  """
+  @spec repair(binary(), binary() | nil) :: {binary(), [{atom(), non_neg_integer()}]}
+  def repair(_input, _root), do: :erlang.nif_error(:nif_not_loaded)
+
+  # ==========================================================================
+  # Template Rendering
+  # ==========================================================================
+
+  @doc """
+  Render a parsed template, replacing `open <> name <> close` placeholders
+  in text and attribute values with the escaped `values`.
+
+  Comments, CDATA sections, processing instructions and markup are copied
+  unchanged. Whitespace around a name is ignored; placeholders without a
+  value are left in place. Raises `ArgumentError` for empty delimiters.
+  Runs on the dirty CPU scheduler.
+  """
+  @spec render_template(document_ref(), %{binary() => binary()}, binary(), binary()) ::
+          binary()
+  def render_template(_doc, _values, _open, _close), do: :erlang.nif_error(:nif_not_loaded)
+
+  # ==========================================================================
+  # Redaction
+  # ==========================================================================
+
+  @doc """
+  Replace what each XPath selects with `replacement`.
+
+  Elements lose their content (`<a/>` becomes `<a>replacement</a>`), text
+  nodes their text (CDATA sections are replaced whole), and attribute
+  steps, predicates included, redact attribute values. The replacement is
+  escaped for where it lands; everything else is copied as written.
+  Returns `{:ok, xml}`, or `{:error, {xpath, reason}}` for the first
+  expression that fails, doesn't select nodes, or selects something that
+  can't be redacted: comments, processing instructions, the document node,
+  attributes picked by position, or a filter over attributes such as
+  `(//@a)[1]`. Runs on the dirty CPU scheduler.
+  """
+  @spec redact(document_ref(), [binary()], binary()) ::
+          {:ok, binary()} | {:error, {binary(), binary()} | :internal_parser_error}
+  def redact(_doc, _xpaths, _replacement), do: :erlang.nif_error(:nif_not_loaded)
+
+  # ==========================================================================
+  # Schema Inference
+  # ==========================================================================
+
+  @doc """
+  Infer an XSD skeleton from sample documents.
+
+  See `RustyXML.infer_schema/1`. Runs on the dirty CPU scheduler.
+  """
+  @spec infer_schema([document_ref()]) :: binary()
+  def infer_schema(_docs), do: :erlang.nif_error(:nif_not_loaded)
+
+  # ==========================================================================
+  # Markup Normalization
+  # ==========================================================================
+
+  @doc """
+  Rewrite well-formed XML with normalized tags.
+
+  Sorts attributes (namespace declarations first), double-quotes values,
+  drops whitespace inside tags other than single separating spaces, and
+  writes empty elements as `<a/>` (`:self_closing`) or `<a></a>`
+  (`:expanded`). Everything outside tags is copied unchanged. Returns
+  `{:error, reason}` for malformed input. Runs on the dirty CPU scheduler.
+  """
+  @spec normalize_markup(binary(), :self_closing | :expanded) ::
+          {:ok, binary()} | {:error, binary() | :internal_parser_error}
+  def normalize_markup(_input, _empty_elements), do: :erlang.nif_error(:nif_not_loaded)
+
+  # ==========================================================================
+  # SVG Attributes
+  # ==========================================================================
+
+  @doc """
+  Read the `width`, `height` and `viewBox` of an SVG document's root
+  element without parsing the rest of it.
+
+  See `RustyXML.SVG.dimensions/1`.
+  """
+  @spec svg_dimensions(binary()) ::
+          %{
+            width: binary() | nil,
+            height: binary() | nil,
+            view_box: {float(), float(), float(), float()} | nil
+          }
+          | nil
+  def svg_dimensions(_input), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Collect the `d` attribute of every `path` element by scanning, without
+  building an index.
+
+  See `RustyXML.SVG.extract_paths/1`. Runs on the dirty CPU scheduler.
+  """
+  @spec svg_extract_paths(binary()) :: [binary()]
+  def svg_extract_paths(_input), do: :erlang.nif_error(:nif_not_loaded)
+
+  # ==========================================================================
+  # XPath Helpers
+  # ==========================================================================
+
+  @doc """
+  Execute parent XPath and evaluate subspecs for each result node.
+
+  Runs on the dirty CPU scheduler since it parses raw XML input.
+
+  Returns a list of maps with each subspec evaluated relative to the parent nodes.
+
+  Each subspec is `{key, xpath}` (raw XPath result) or `{key, xpath, type}`
+  with `type` one of `:string`, `:integer`, `:float`, `:boolean`, `:list`
+  or `:node`, converted in Rust. Scalar types yield `nil` when the result is
+  missing or not convertible.
+
+  Two untyped subspecs are shaped as maps: `"@*"` gives the node's
+  attributes as `%{name => value}`, and `"./*"` gives its child elements
+  as `%{name => string_value}`, the first child winning for repeated names.
+
+  ## Examples
+
+      xml = "<items><item><id>1</id><name>A</name></item></items>"
+      RustyXML.Native.xpath_with_subspecs(xml, "//item", [{"id", "./id/text()"}, {"name", "./name/text()"}])
+      #=> [%{id: "1", name: "A"}]
+
+      RustyXML.Native.xpath_with_subspecs(xml, "//item", [{"id", "./id", :integer}])
+      #=> [%{"id" => 1}]
+
+  """
+  @spec xpath_with_subspecs(binary(), binary(), [
+          {binary(), binary()}
+          | {binary(), binary(), :string | :integer | :float | :boolean | :list | :node}
+        ]) :: [map()]
+  def xpath_with_subspecs(_xml, _parent_xpath, _subspecs), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Extract a nested map of typed values from a document in one call.
+
+  `spec` maps keys to fields, each one of:
+
+    * `xpath` - the string-value of the first result
+    * `{xpath, type}` - converted like a typed subspec of
+      `xpath_with_subspecs/3`
+    * `{:list, xpath, spec}` - a map built from `spec` for every node
+      `xpath` selects
+    * `{:map, xpath, spec}` - a map built from `spec` for the first node
+      `xpath` selects, or `nil`
+
+  Top-level expressions are evaluated from the root element, nested ones
+  from their node. A field whose expression fails at run time is `nil`.
+  Raises `ArgumentError` for a malformed spec.
+
+  ## Examples
+
+      doc = RustyXML.Native.parse(~s(<order><name>A</name><item id="1"/></order>))
+      spec = %{name: "name", items: {:list, "item", %{id: {"@id", :integer}}}}
+      RustyXML.Native.decode(doc, spec)
+      #=> %{name: "A", items: [%{id: 1}]}
+
+  """
+  @spec decode(document_ref(), map()) :: map() | {:error, {binary(), binary()}}
+  def decode(_doc, _spec), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Execute XPath and return string value of result.
+
+  Runs on the dirty CPU scheduler since it parses raw XML input.
+  For node-sets, returns text content of first node.
+
+  ## Examples
+
+      RustyXML.Native.xpath_string_value("<root>hello</root>", "//root/text()")
+      #=> "hello"
+
+  """
+  @spec xpath_string_value(binary(), binary()) :: binary()
+  def xpath_string_value(_xml, _xpath), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Execute XPath on document reference and return string value.
+  """
+  @spec xpath_string_value_doc(document_ref(), binary()) :: binary()
+  def xpath_string_value_doc(_doc, _xpath), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Describe how an XPath expression will be evaluated.
+
+  Returns one line per compiled operation: the axis and node test of each
+  step (or the operator, function or literal), followed by the evaluation
+  strategy in brackets, such as `full-descendant-scan`, `child-walk` or
+  `attribute-compare`. Predicate sub-plans are indented under their step.
+
+  ## Examples
+
+      RustyXML.Native.xpath_explain("//item[@id = 'a']")
+      #=> {:ok, ["root [document-node]",
+      #=>        "descendant::item [full-descendant-scan]",
+      #=>        "predicate [@id = 'a'] [attribute-compare]"]}
+
+  """
+  @spec xpath_explain(binary()) :: {:ok, [binary()]} | {:error, binary()}
+  def xpath_explain(_xpath), do: :erlang.nif_error(:nif_not_loaded)
+
+  # ==========================================================================
+  # Schematron
+  # ==========================================================================
+
+  @typedoc """
+  A failed `assert` or fired `report`: its `id`, `role` and `test`, the
+  location of the context node and the message with `value-of` and `name`
+  filled in
+  """
+  @type schematron_failure :: %{
+          kind: :assert | :report,
+          id: binary() | nil,
+          role: binary() | nil,
+          test: binary(),
+          path: binary(),
+          message: binary()
+        }
+
+  @doc """
+  Compile a Schematron schema.
+
+  Supports patterns of rules with `assert` and `report` checks, whose
+  messages may use `value-of` and `name`. Schema elements are matched by
+  local name, so both the ISO and the 1.5 namespace work. A relative rule
+  context matches at any depth, as an XSLT pattern does. Abstract rules
+  are skipped; `let` variables are rejected.
+
+  Documents are queried without namespace resolution, so `<ns>`
+  declarations and prefixed names in expressions (`x:order`) are rejected
+  rather than never matching; match namespaced nodes with `local-name()`.
+
+  Runs on the dirty CPU scheduler.
+
+  ## Examples
+
+      {:ok, rules} = RustyXML.Native.schematron_compile("""
+      <schema xmlns="http://purl.oclc.org/dsdl/schematron">
+        <pattern>
+          <rule context="item">
+            <assert test="@id">Every item needs an id</assert>
+          </rule>
+        </pattern>
+      </schema>
+      """)
+
+  """
+  @spec schematron_compile(binary()) :: {:ok, schematron_ref()} | {:error, binary()}
+  def schematron_compile(_rules_xml), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Run a compiled Schematron schema over a document.
+
+  Returns every failed assertion and fired report, in pattern, rule and
+  document order. Within a pattern a node is checked only by the first
+  rule whose context matches it. `path` locates the context node like
+  `/feed[1]/entry[3]`.
+
+  Runs on the dirty CPU scheduler.
+
+  ## Examples
+
+      doc = RustyXML.Native.parse("<items><item/></items>")
+      RustyXML.Native.schematron_validate(doc, rules)
+      #=> {:ok, [%{kind: :assert, id: nil, role: nil, test: "@id",
+      #=>          path: "/items[1]/item[1]", message: "Every item needs an id"}]}
+
+  """
+  @spec schematron_validate(document_ref(), schematron_ref()) ::
+          {:ok, [schematron_failure()]} | {:error, binary()}
+  def schematron_validate(_doc, _rules), do: :erlang.nif_error(:nif_not_loaded)
+
+  # ==========================================================================
+  # Streaming Parser
+  # ==========================================================================
+
+  @doc """
+  Create a new streaming XML parser.
+
+  The streaming parser processes XML in chunks with bounded memory usage.
+
+  ## Examples
+
+      parser = RustyXML.Native.streaming_new()
+      RustyXML.Native.streaming_feed(parser, "<root>")
+      RustyXML.Native.streaming_feed(parser, "<item/></root>")
+      events = RustyXML.Native.streaming_take_events(parser, 100)
+
+  """
+  @spec streaming_new() :: parser_ref()
+  def streaming_new, do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Create a streaming parser with a tag filter.
+
+  Only events for the specified tag name will be emitted.
+  Useful for extracting specific elements from large documents.
+
+  ## Examples
+
+      parser = RustyXML.Native.streaming_new_with_filter("item")
+      RustyXML.Native.streaming_feed(parser, "<root><item/><other/></root>")
+      # Only item events will be returned
+
+  """
+  @spec streaming_new_with_filter(binary()) :: parser_ref()
+  def streaming_new_with_filter(_tag), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Create a streaming parser with a tag filter and a per-element size limit.
+
+  Target elements larger than `max_element_size` bytes are never buffered
+  whole. With `mode` set to `:skip` they are dropped and reported as
+  `{:oversized, name, size}` by `streaming_take_elements/2`. With `:chunk`
+  they are emitted as a sequence of `{:chunk, name, data}` pieces followed by
+  `{:chunk_end, name, size}`.
+
+  ## Examples
+
+      parser = RustyXML.Native.streaming_new_with_limit("item", 1_048_576, :skip)
+
+  """
+  @spec streaming_new_with_limit(binary(), pos_integer(), :skip | :chunk) :: parser_ref()
+  def streaming_new_with_limit(_tag, _max_element_size, _mode),
+    do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Create a streaming parser that only counts elements named `tag`.
+
+  Matching elements are never buffered and text is dropped as it arrives,
+  so memory stays at the tail buffer whatever the size of the input or its
+  elements. Read the count with `streaming_count_elements/1`.
+
+  ## Examples
+
+      parser = RustyXML.Native.streaming_new_counter("record")
+      RustyXML.Native.streaming_feed(parser, "<db><record/><record>x</record></db>")
+      RustyXML.Native.streaming_count_elements(parser)
+      #=> 2
+
+  """
+  @spec streaming_new_counter(binary()) :: parser_ref()
+  def streaming_new_counter(_tag), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Feed a chunk of XML data to the streaming parser.
+
+  Returns `{available_events, buffer_size}` on success. If an earlier call
+  panicked while holding the parser, the parser is reset to its starting
+  state, keeping its filter and size limit, and this call returns
+  `{:error, :parser_reset}`; the parser can then be fed a new document.
+  A chunk that would take the NIF over `set_memory_limit/1` is refused with
+  `{:error, :memory_limit}` and not buffered.
+  """
+  @spec streaming_feed(parser_ref(), binary()) ::
+          {non_neg_integer(), non_neg_integer()} | {:error, :parser_reset | :memory_limit}
+  def streaming_feed(_parser, _chunk), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Take up to `max` events from the streaming parser.
+
+  Returns `{:error, :parser_reset}` once after a panic reset the parser
+  (see `streaming_feed/2`).
+  """
+  @spec streaming_take_events(parser_ref(), non_neg_integer()) ::
+          [xml_event()] | {:error, :parser_reset}
+  def streaming_take_events(_parser, _max), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Finalize the streaming parser and get remaining events.
+
+  Returns `{:error, :parser_reset}` once after a panic reset the parser
+  (see `streaming_feed/2`).
+  """
+  @spec streaming_finalize(parser_ref()) :: [xml_event()] | {:error, :parser_reset}
+  def streaming_finalize(_parser), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Get streaming parser status.
+
+  Returns `{available_events, buffer_size, has_pending}` on success, or
+  `{:error, :parser_reset}` once after a panic reset the parser (see
+  `streaming_feed/2`).
+  """
+  @spec streaming_status(parser_ref()) ::
+          {non_neg_integer(), non_neg_integer(), boolean()} | {:error, :parser_reset}
+  def streaming_status(_parser), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Take up to `max` complete elements from the streaming parser.
+
+  Returns a list of XML binaries for complete elements. This is faster than
+  using events because the element strings are built in Rust without needing
+  reconstruction in Elixir.
+
+  Parsers created with `streaming_new_with_limit/3` also return
+  `{:oversized, name, size}`, `{:chunk, name, data}` and
+  `{:chunk_end, name, size}` tuples for elements over the size limit.
+
+  """
+  @spec streaming_take_elements(parser_ref(), non_neg_integer()) ::
+          [
+            binary()
+            | {:oversized, binary(), non_neg_integer()}
+            | {:chunk, binary(), binary()}
+            | {:chunk_end, binary(), non_neg_integer()}
+          ]
+          | {:error, :parser_reset}
+  def streaming_take_elements(_parser, _max), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Concatenate element binaries into one document under a new root element.
+
+  The elements, typically from `streaming_take_elements/2`, are copied as
+  they are without being parsed again. Attribute values are escaped.
+  Raises `ArgumentError` if the root or an attribute name isn't a valid
+  XML name. Runs on the dirty CPU scheduler.
+
+  ## Examples
+
+      RustyXML.Native.wrap_elements(["<item>a</item>", "<item>b</item>"], "items", [{"n", "2"}])
+      #=> "<items n=\"2\"><item>a</item><item>b</item></items>"
+
+  """
+  @spec wrap_elements([binary()], binary(), [{binary(), binary()}]) :: binary()
+  def wrap_elements(_elements, _root_name, _root_attrs),
+    do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Get number of available complete elements.
+
+  """
+  @spec streaming_available_elements(parser_ref()) ::
+          non_neg_integer() | {:error, :parser_reset}
+  def streaming_available_elements(_parser), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Number of target elements the streaming parser has completed so far.
+
+  Counts every element matching the parser's filter, whether or not it has
+  been taken with `streaming_take_elements/2`. With a parser from
+  `streaming_new_counter/1` this is the only result.
+  """
+  @spec streaming_count_elements(parser_ref()) :: non_neg_integer() | {:error, :parser_reset}
+  def streaming_count_elements(_parser), do: :erlang.nif_error(:nif_not_loaded)
+
+  # ==========================================================================
+  # SimpleForm Parsing
+  # ==========================================================================
+
+  @doc """
+  Parse XML directly into SimpleForm `{name, attrs, children}` tree.
+
+  Bypasses the SAX event pipeline — builds the tree in Rust from the
+  structural index, decoding entities as needed.
+
+  Returns `{:ok, tree}` or `{:error, reason}`, with the same reasons as
+  `parse_strict/1`.
+  """
+  @spec parse_to_simple_form(binary()) :: {:ok, tuple()} | {:error, parse_error()}
+  def parse_to_simple_form(_xml), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Parse XML into SimpleForm keeping text exactly as it appears in the source.
+
+  No entity decoding is done: text and CDATA children are
+  `{raw_text, {offset, length}}` with byte positions in the input, and
+  attribute values are returned raw. UTF-16 input is converted first, so
+  its text is UTF-8 and positions are in the converted document.
+  """
+  @spec parse_to_simple_form_raw(binary()) :: {:ok, tuple()} | {:error, parse_error()}
+  def parse_to_simple_form_raw(_xml), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Parse XML into SimpleForm, converting at most `max_depth` levels below
+  the root.
+
+  Elements at the limit get `[{:more, node_id}]` in place of any children,
+  where `node_id` is the element's id in the same XML parsed with
+  `parse/1`, for `node_to_term_shallow/3` to expand. `raw_text` and
+  `form` are as in `parse_to_simple_form_raw/1` and
+  `parse_to_simple_form_normalized/2`.
+  """
+  @spec parse_to_simple_form_shallow(binary(), non_neg_integer(), boolean(), normalization()) ::
+          {:ok, tuple()} | {:error, parse_error()}
+  def parse_to_simple_form_shallow(_xml, _max_depth, _raw_text, _form),
+    do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Like `parse_to_simple_form/1`, with text and attribute values in NFC or
+  NFD (`:none` leaves them as decoded).
+  """
+  @spec parse_to_simple_form_normalized(binary(), normalization()) ::
+          {:ok, tuple()} | {:error, parse_error()}
+  def parse_to_simple_form_normalized(_xml, _form), do: :erlang.nif_error(:nif_not_loaded)
+
+  # ==========================================================================
+  # Map Parsing
+  # ==========================================================================
+
+  @doc """
+  Parse XML into nested maps, one per element.
+
+  Each element becomes `%{name: name, attrs: %{name => value}, children:
+  [map], text: text}`. `children` holds child elements only, and `text` is
+  the element's own decoded text and CDATA joined together, or `nil` when
+  it has none. Comments and processing instructions are dropped. `form`
+  is `:nfc`, `:nfd` or `:none`, as in `parse_to_simple_form_normalized/2`.
+  """
+  @spec parse_to_map(binary(), normalization()) :: {:ok, map()} | {:error, parse_error()}
+  def parse_to_map(_xml, _form), do: :erlang.nif_error(:nif_not_loaded)
+
+  # ==========================================================================
+  # Document Accumulator (Streaming SimpleForm)
+  # ==========================================================================
+
+  @doc """
+  Create a new document accumulator for streaming SimpleForm parsing.
+
+  Returns an opaque accumulator reference.
+  """
+  @spec accumulator_new() :: reference()
+  def accumulator_new, do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Feed a chunk of data to the document accumulator.
+
+  A chunk that would take the NIF over `set_memory_limit/1` is refused with
+  `{:error, :memory_limit}` and not buffered.
+  """
+  @spec accumulator_feed(reference(), binary()) :: :ok | {:error, :memory_limit}
+  def accumulator_feed(_acc, _chunk), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Feed a chunk and check well-formedness of the document received so far.
+
+  Checks tag balance, root element rules, names, attribute syntax and
+  character validity for each construct as soon as it is complete, and
+  returns `{:error, reason}` (same messages as `parse_strict/1`) on the chunk
+  that makes the document malformed. Each call only checks the bytes it adds.
+  The failing chunk is not kept; after an error, later chunks are discarded
+  and the same error is returned. `accumulator_to_simple_form/1`
+  still performs full validation at the end. A chunk over the memory limit
+  is refused as by `accumulator_feed/2`.
+  """
+  @spec accumulator_feed_validated(reference(), binary()) ::
+          :ok | {:error, binary() | :memory_limit | :mutex_poisoned}
+  def accumulator_feed_validated(_acc, _chunk), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Serialize an accumulator's buffered data and validation state.
+
+  The snapshot is a self-contained binary that can be persisted and passed
+  to `accumulator_restore/1` — e.g. in a new process after a crash — to
+  continue feeding chunks without re-fetching the ones already received.
+  """
+  @spec accumulator_snapshot(reference()) :: {:ok, binary()} | {:error, :mutex_poisoned}
+  def accumulator_snapshot(_acc), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Create an accumulator from an `accumulator_snapshot/1` binary.
+
+  Returns `{:error, reason}` if the snapshot is truncated, corrupt, or from
+  an incompatible version.
+  """
+  @spec accumulator_restore(binary()) :: {:ok, reference()} | {:error, binary()}
+  def accumulator_restore(_snapshot), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Validate, index, and convert accumulated data to SimpleForm.
+
+  Returns `{:ok, tree}` or `{:error, reason}`.
+  """
+  @spec accumulator_to_simple_form(reference()) :: {:ok, tuple()} | {:error, parse_error()}
+  def accumulator_to_simple_form(_acc), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Like `accumulator_to_simple_form/1`, with text and attribute values in
+  NFC or NFD. See `parse_to_simple_form_normalized/2`.
+  """
+  @spec accumulator_to_simple_form_normalized(reference(), normalization()) ::
+          {:ok, tuple()} | {:error, parse_error()}
+  def accumulator_to_simple_form_normalized(_acc, _form),
+    do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Like `accumulator_to_simple_form/1`, with text kept as in the source.
+
+  See `parse_to_simple_form_raw/1`. Offsets are positions in the
+  concatenated chunks.
+  """
+  @spec accumulator_to_simple_form_raw(reference()) ::
+          {:ok, tuple()} | {:error, parse_error()}
+  def accumulator_to_simple_form_raw(_acc), do: :erlang.nif_error(:nif_not_loaded)
+
+  # ==========================================================================
+  # Streaming Validation
+  # ==========================================================================
+
+  @doc """
+  Create a validator that checks well-formedness as chunks arrive, without
+  keeping the document.
+
+  Only the construct in progress (a tag, comment or text run) is held
+  between chunks, so memory is bounded by the largest one rather than by
+  the input, and the search for its end picks up where the last chunk
+  left it, so each byte is scanned once. The checks are those of the
+  accumulator's early validation: tag balance, root element rules, name and
+  character validity, and attribute syntax and uniqueness. DOCTYPE
+  contents and entity declarations are not checked. Input must be UTF-8.
+
+  ## Examples
+
+      validator = RustyXML.Native.validator_new()
+      :ok = RustyXML.Native.validator_feed(validator, "<root><a>")
+      {:error, %{message: "Tag mismatch: <a> closed with </b>", line: 1, column: 10}} =
+        RustyXML.Native.validator_feed(validator, "</b>")
+
+  """
+  @spec validator_new() :: validator_ref()
+  def validator_new, do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Check the constructs a chunk completes.
+
+  Returns `:ok`, or `{:error, details}` for the first error; once an error
+  is found every later call returns it again. Runs on the dirty CPU
+  scheduler.
+  """
+  @spec validator_feed(validator_ref(), binary()) ::
+          :ok | {:error, validation_error() | :closed | :parser_reset}
+  def validator_feed(_validator, _chunk), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Check the end of input (no construct cut off, every element closed, a
+  root element present) and release the validator.
+
+  Returns `:ok` or the first error. Later calls with the validator return
+  `{:error, :closed}`.
+  """
+  @spec validator_finish(validator_ref()) ::
+          :ok | {:error, validation_error() | :closed | :parser_reset}
+  def validator_finish(_validator), do: :erlang.nif_error(:nif_not_loaded)
+
+  # ==========================================================================
+  # SAX Parsing
+  # ==========================================================================
+
+  @doc """
+  Parse XML and return SAX events.
+
+  Events are returned as tuples similar to Saxy's format. Attribute values
+  of 64 KiB or more are sub-binaries of `xml` rather than copies, so they
+  keep `xml` alive for as long as they are referenced.
+
+  Returns `{:error, :memory_limit}` if the input or the events would take
+  the NIF over `set_memory_limit/1`. Neither `set_max_depth/1` nor
+  attribute and element counts are checked; `RustyXML.parse_string/4` takes
+  `:max_attributes` and `:max_elements`.
+  """
+  @spec sax_parse(binary()) :: [tuple()] | {:error, :memory_limit | :internal_parser_error}
+  def sax_parse(_xml), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Parse XML and return the SAX events `opts` doesn't exclude.
+
+  Events are dropped while scanning, so they never reach the result, which
+  keeps the list small when only the document's structure matters.
+
+  ## Options
+
+    * `:skip_comments` - leave out `{:comment, text}` events
+    * `:skip_processing_instructions` - leave out
+      `{:processing_instruction, target, data}` events
+    * `:skip_whitespace` - leave out text made only of spaces, tabs and
+      line breaks
+    * `:max_depth` - leave out everything nested deeper than this. The root
+      element (and anything outside it) is at depth 1, the root's content
+      at depth 2.
+
+  Raises `ArgumentError` for any other option, including
+  `:max_attributes` and `:max_elements`. Limits apply as for `sax_parse/1`.
+
+  ## Examples
+
+      RustyXML.Native.sax_parse("<r>\n  <a>x</a>\n</r>", skip_whitespace: true, max_depth: 2)
+      #=> [{:start_element, "r", []}, {:start_element, "a", []}, {:end_element, "a"},
+      #=>  {:end_element, "r"}]
+
+  """
+  @spec sax_parse(binary(), keyword()) ::
+          [tuple()] | {:error, :memory_limit | :internal_parser_error}
+  def sax_parse(_xml, _opts), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Parse XML and send the SAX events to `pid` in batches.
+
+  Returns a handle immediately. Parsing runs on a pool of background
+  workers, one per core, so streams beyond that wait for a free worker. The
+  worker sends one message per `batch_size` events as they are produced:
+
+      {:rustyxml_sax, handle, {:events, events}}
+      {:rustyxml_sax, handle, :done}
+
+  or `{:rustyxml_sax, handle, {:error, :internal_parser_error}}` in place
+  of `:done` if scanning panicked.
+
+  Events have the `sax_parse/1` format and arrive in document order; a
+  batch can hold one extra event when it ends with an empty element. Only
+  one batch is built at a time, so peak term size stays bounded however
+  large the document is. Parsing stops early if `pid` exits or the stream is
+  stopped with `sax_send_cancel/1`. See `sax_parse_send_window/5` for a
+  consumer that may fall behind. Limits apply as for `sax_parse/1`.
+
+  Raises `ArgumentError` if `batch_size` is 0.
+  """
+  @spec sax_parse_send(binary(), pid(), pos_integer()) :: sax_stream_ref()
+  def sax_parse_send(_xml, _pid, _batch_size), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Like `sax_parse_send/3`, with flow control.
+
+  At most `window` batches may be unacknowledged: once that many have been
+  sent, tokenizing pauses until the consumer acknowledges some with
+  `sax_send_ack/2`. A slow consumer therefore never has more than `window`
+  batches in its mailbox, and the parser holds at most one more.
+
+  A pause that lasts longer than `timeout` milliseconds (default: 60 s)
+  ends the stream with `{:rustyxml_sax, handle, {:error, :timeout}}`, so a
+  consumer that stops acknowledging without exiting doesn't hold a worker
+  forever.
+
+      handle = Native.sax_parse_send_window(xml, self(), 500, 4)
+
+      receive do
+        {:rustyxml_sax, ^handle, {:events, events}} ->
+          handle_events(events)
+          Native.sax_send_ack(handle, 1)
+
+        {:rustyxml_sax, ^handle, :done} ->
+          :ok
+      end
+
+  Raises `ArgumentError` if `batch_size` or `window` is 0.
+  """
+  @spec sax_parse_send_window(
+          binary(),
+          pid(),
+          pos_integer(),
+          pos_integer(),
+          non_neg_integer()
+        ) :: sax_stream_ref()
+  def sax_parse_send_window(_xml, _pid, _batch_size, _window, _timeout \\ 60_000),
+    do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Acknowledge `n` batches received from a `sax_parse_send_window/5` stream.
+  """
+  @spec sax_send_ack(sax_stream_ref(), non_neg_integer()) :: :ok
+  def sax_send_ack(_handle, _n), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Stop a `sax_parse_send/3` or `sax_parse_send_window/5` stream.
+
+  The worker stops before its next batch and sends nothing more, though a
+  batch already sent may still be in the mailbox. Cancelling a finished
+  stream does nothing.
+  """
+  @spec sax_send_cancel(sax_stream_ref()) :: :ok
+  def sax_send_cancel(_handle), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Parse XML and return SAX events in Saxy-compatible format.
+
+  Events are emitted directly in Saxy format:
+  - `{:start_element, {name, attrs}}`
+  - `{:end_element, name}`
+  - `{:characters, content}`
+  - `{:cdata, content}`
+
+  Comments and PIs are skipped. Empty elements emit start+end. As in
+  `sax_parse/1`, attribute values of 64 KiB or more without entity
+  references are sub-binaries of `xml`.
+  """
+  @spec sax_parse_saxy(binary(), boolean()) :: [tuple()]
+  def sax_parse_saxy(_xml, _cdata_as_chars), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Like `sax_parse_saxy/2`, with text kept exactly as in the source.
+
+  No entity decoding is done: `:characters` and `:cdata` events carry
+  `{raw_content, {offset, length}}` with byte positions in the input, and
+  attribute values are returned raw.
+  """
+  @spec sax_parse_saxy_raw(binary(), boolean()) :: [tuple()]
+  def sax_parse_saxy_raw(_xml, _cdata_as_chars), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Like `sax_parse_saxy/2`, with `:characters` and `:cdata` content and
+  attribute values in NFC or NFD (`:none` leaves them as decoded).
+  """
+  @spec sax_parse_saxy_normalized(binary(), boolean(), normalization()) :: [tuple()]
+  def sax_parse_saxy_normalized(_xml, _cdata_as_chars, _form),
+    do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Take events from streaming parser in Saxy-compatible format.
+  """
+  @spec streaming_take_saxy_events(reference(), non_neg_integer(), boolean()) ::
+          [tuple()] | {:error, :parser_reset}
+  def streaming_take_saxy_events(_parser, _max, _cdata_as_chars),
+    do: :erlang.nif_error(:nif_not_loaded)
+
+  # ==========================================================================
+  # Streaming SAX Parsing (chunk-by-chunk, bounded memory)
+  # ==========================================================================
+
+  @doc """
+  Create a new streaming SAX parser.
+
+  Returns an opaque parser reference for use with `streaming_feed_sax/3`.
+  """
+  @spec streaming_sax_new() :: reference()
+  def streaming_sax_new, do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Create a streaming SAX parser that emits text and CDATA in bounded pieces.
+
+  Content longer than `chunk_size` bytes, or a text node or CDATA section
+  still open at the end of a fed chunk, is encoded as chunk events
+  (type 5 = characters, 6 = cdata, `<<type, final::8, len::32, data>>`)
+  instead of being buffered until the section closes.
+  """
+  @spec streaming_sax_new_chunked(pos_integer()) :: reference()
+  def streaming_sax_new_chunked(_chunk_size), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Select the binary format returned by `streaming_feed_sax/3` and
+  `streaming_finalize_sax/2`.
+
+  Version `1` (the default) uses fixed `::16` / `::32` lengths and writes
+  every element and attribute name in full. Version `2` writes lengths and
+  attribute counts as unsigned LEB128 varints and replaces names with name
+  references: `<<0, len::varint, name>>` defines the next id (1, 2, ...)
+  and `<<id::varint>>` repeats a name defined earlier in the same binary.
+  Ids are scoped to a single returned binary. Event types are unchanged:
+
+      <<1, name_ref, attr_count::varint, [name_ref, vlen::varint, value]*>>
+      <<2, name_ref>>
+      <<3 | 4, len::varint, text>>
+      <<5 | 6, final::8, len::varint, text>>
+      <<7, len::varint, value, occurrence::varint>>
+
+  Repetitive documents shrink considerably; `RustyXML.parse_stream/4` uses
+  it with `event_format: :v2`. Takes effect from the next call.
+  """
+  @spec streaming_sax_set_format(reference(), 1 | 2) :: :ok
+  def streaming_sax_set_format(_parser, _version), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Report repeated `id` and `xml:id` values seen by a streaming SAX parser.
+
+  From the next call on, an element whose ID repeats an earlier one is
+  followed, right after its start_element event, by
+  `<<7, value_len::16, value, occurrence::32>>` (v1 format), where
+  `occurrence` is 2 for the first repeat. Only a 64-bit hash and a count are
+  kept per distinct value. `RustyXML.parse_stream/4` uses it with
+  `duplicate_ids: true`.
+  """
+  @spec streaming_sax_track_duplicate_ids(reference()) :: :ok
+  def streaming_sax_track_duplicate_ids(_parser), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Limit how many bytes of unfinished markup a streaming SAX parser holds
+  between calls (`0` = unlimited, default 64 MiB).
+
+  Input after the last complete tag is kept until its `>` arrives. Once
+  more than `max_bytes` are waiting, `streaming_feed_sax/3` drops them and
+  returns `{:error, {:max_tail_size_exceeded, max_bytes}}`, so a single
+  enormous tag can't grow the buffer without bound. The parser should be
+  discarded after that error.
+  """
+  @spec streaming_sax_set_max_tail(reference(), non_neg_integer()) :: :ok
+  def streaming_sax_set_max_tail(_parser, _max_bytes), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Limit the attributes per element and the elements per document a
+  streaming SAX parser accepts (`0` = unlimited; both `0` removes the limits).
+
+  With `on_limit` `:error`, the call that meets an element breaking a limit
+  returns `{:error, {:attribute_limit_exceeded, max_attributes}}` or
+  `{:error, {:element_limit_exceeded, max_elements}}`, and the parser should
+  be discarded. With `:warn`, parsing continues in degraded mode: the
+  element's start_element event is followed by
+  `<<8, kind::8, name_len::16, name, count::32>>` (v1 format), where `kind`
+  is 1 for attributes and 2 for elements, attributes past the limit are
+  dropped, and the element limit is reported once, by the first element
+  past it. `RustyXML.parse_stream/4` uses it with `:max_attributes`,
+  `:max_elements` and `:on_limit`.
+  """
+  @spec streaming_sax_set_limits(
+          reference(),
+          non_neg_integer(),
+          non_neg_integer(),
+          :error | :warn
+        ) :: :ok
+  def streaming_sax_set_limits(_parser, _max_attributes, _max_elements, _on_limit),
+    do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Feed a chunk and return SAX events as a compact binary.
+
+  When the tail buffer is empty (common case), the NIF tokenizes the BEAM
+  binary in-place (zero copy) and writes events directly into an OwnedBinary
+  on the BEAM heap — no intermediate Rust Vec allocation. Only the
+  unprocessed tail (~100 bytes) is saved between calls.
+
+  Format: sequence of `<<type::8, ...>>` where type 1=start, 2=end, 3=chars, 4=cdata,
+  for chunked parsers 5=chars chunk, 6=cdata chunk, with
+  `streaming_sax_track_duplicate_ids/1` 7=duplicate id, and with `:warn`
+  limits from `streaming_sax_set_limits/4` 8=limit warning.
+
+  Returns `{:error, {:max_tail_size_exceeded, limit}}` when the unprocessed
+  tail outgrows `streaming_sax_set_max_tail/2`,
+  `{:error, {:attribute_limit_exceeded, limit}}` or
+  `{:error, {:element_limit_exceeded, limit}}` when an element breaks an
+  `:error` limit, and `{:error, :memory_limit}` for a chunk that would take
+  the NIF over `set_memory_limit/1`.
+  """
+  @spec streaming_feed_sax(reference(), binary(), boolean()) ::
+          binary()
+          | {:error,
+             {:max_tail_size_exceeded, pos_integer()}
+             | {:attribute_limit_exceeded | :element_limit_exceeded, pos_integer()}
+             | :memory_limit
+             | :internal_parser_error
+             | :parser_reset}
+  def streaming_feed_sax(_parser, _chunk, _cdata_as_chars),
+    do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Finalize the streaming SAX parser, processing any remaining bytes.
+
+  Returns final events as a compact binary (same format as `streaming_feed_sax/3`),
+  or the same limit errors.
+  """
+  @spec streaming_finalize_sax(reference(), boolean()) ::
+          binary()
+          | {:error,
+             {:attribute_limit_exceeded | :element_limit_exceeded, pos_integer()}
+             | :internal_parser_error
+             | :parser_reset}
+  def streaming_finalize_sax(_parser, _cdata_as_chars),
+    do: :erlang.nif_error(:nif_not_loaded)
+
+  # ==========================================================================
+  # Memory Tracking
+  # ==========================================================================
+
+  @doc """
+  Get current Rust heap allocation in bytes.
+
+  Requires `memory_tracking` Cargo feature. Returns `0` otherwise.
 
   """
   @spec get_rust_memory() :: non_neg_integer()
@@ -467,4 +2516,264 @@ defmodule RustyXML.Native do
   """
   @spec reset_rust_memory_stats() :: {non_neg_integer(), non_neg_integer()}
   def reset_rust_memory_stats, do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Heap bytes held by one document, to attribute NIF memory to documents.
+
+    * `:input` - the stored input; for zero-copy parses this is the
+      retained BEAM binary, shared with the caller's term
+    * `:index` - structural index arrays and name tables
+    * `:result_cache` - cached query results (see `doc_set_result_cache/2`)
+    * `:attr_indexes` - attribute value indexes
+    * `:decoded_text` - entity-decoded text kept by queries
+    * `:precomputed_text` - string-values from `doc_precompute_text/1`
+    * `:total` - the sum of the above
+
+  Counts are computed from capacities, so they reflect allocations rather
+  than the allocator's own overhead, and work without `memory_tracking`.
+
+  ## Examples
+
+      RustyXML.Native.doc_memory(doc)
+      #=> %{input: 48_211, index: 120_480, result_cache: 0, attr_indexes: 0,
+      #=>   decoded_text: 312, precomputed_text: 0, total: 169_003}
+
+  """
+  @spec doc_memory(document_ref()) ::
+          %{
+            input: non_neg_integer(),
+            index: non_neg_integer(),
+            result_cache: non_neg_integer(),
+            attr_indexes: non_neg_integer(),
+            decoded_text: non_neg_integer(),
+            precomputed_text: non_neg_integer(),
+            total: non_neg_integer()
+          }
+          | {:error, :closed}
+  def doc_memory(_doc), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Heap bytes held by a streaming parser from `streaming_new/0`,
+  `streaming_sax_new/0` or their variants.
+
+  `:buffer` is unprocessed input, including a target element still being
+  captured; `:pending` is events and elements not yet taken, or the ID
+  table when duplicate ID detection is on.
+  """
+  @spec streaming_memory(parser_ref() | reference()) ::
+          %{buffer: non_neg_integer(), pending: non_neg_integer(), total: non_neg_integer()}
+          | {:error, :closed | :parser_reset}
+  def streaming_memory(_parser), do: :erlang.nif_error(:nif_not_loaded)
+
+  # ==========================================================================
+  # Parser Statistics
+  # ==========================================================================
+
+  @doc """
+  Cumulative parser counters since the NIF was loaded or last reset.
+
+    * `:documents_parsed` - structural indexes built, by any parse function
+    * `:bytes_scanned` - input bytes those indexes were built from
+    * `:xpath_queries` - XPath expressions run; each expression string
+      passed to a query function counts once, subspecs included
+    * `:cache_hits` - queries answered from a document's result cache
+      (see `doc_set_result_cache/2`)
+    * `:strict_failures` - inputs rejected by strict parsing
+    * `:avg_index_build_us` - mean index build time in microseconds
+
+  Counters are process-wide atomics, cheap enough to leave on, and are
+  meant to be exported as metrics (e.g. Prometheus counters).
+
+  ## Examples
+
+      RustyXML.Native.get_parser_stats()
+      #=> %{documents_parsed: 1204, bytes_scanned: 88_140_233, xpath_queries: 5310,
+      #=>   cache_hits: 412, strict_failures: 3, avg_index_build_us: 412.7}
+
+  """
+  @spec get_parser_stats() :: %{
+          documents_parsed: non_neg_integer(),
+          bytes_scanned: non_neg_integer(),
+          xpath_queries: non_neg_integer(),
+          cache_hits: non_neg_integer(),
+          strict_failures: non_neg_integer(),
+          avg_index_build_us: float()
+        }
+  def get_parser_stats, do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Reset all counters reported by `get_parser_stats/0` to zero.
+  """
+  @spec reset_parser_stats() :: :ok
+  def reset_parser_stats, do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Report on the cache of compiled XPath expressions shared by all queries.
+
+  `:hits` and `:misses` count lookups since load or the last
+  `xpath_cache_clear/0`; `:size` is the number of expressions held and
+  `:capacity` the most it keeps (256 by default).
+
+  ## Examples
+
+      RustyXML.Native.xpath_cache_stats()
+      #=> %{hits: 10_482, misses: 37, size: 37, capacity: 256}
+
+  """
+  @spec xpath_cache_stats() :: %{
+          hits: non_neg_integer(),
+          misses: non_neg_integer(),
+          size: non_neg_integer(),
+          capacity: non_neg_integer()
+        }
+  def xpath_cache_stats, do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Drop every compiled XPath expression and zero the cache's counters. The
+  capacity is kept.
+  """
+  @spec xpath_cache_clear() :: :ok
+  def xpath_cache_clear, do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Set how many compiled XPath expressions are kept, evicting the least
+  recently used ones beyond it. `0` empties and disables the cache, so
+  every query compiles its expression again.
+  """
+  @spec set_xpath_cache_capacity(non_neg_integer()) :: :ok
+  def set_xpath_cache_capacity(_capacity), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Same as `parse/1`, also reporting what the parse cost.
+
+  Returns `{document_ref, usage}`, where `usage` holds:
+
+    * `:duration_us` - wall-clock time spent in the NIF
+    * `:peak_alloc_bytes` - most native memory held at once during the
+      call, beyond what was already allocated (the document itself
+      included); `0` when the NIF is built without `memory_tracking`
+    * `:nodes_visited` - elements and text nodes indexed
+
+  Memory is counted on the calling scheduler thread only, so concurrent
+  calls do not inflate each other's numbers. Returns `{:error,
+  :memory_limit}` like `parse/1`.
+
+  ## Examples
+
+      {doc, %{duration_us: us, peak_alloc_bytes: bytes}} =
+        RustyXML.Native.parse_timed(xml)
+
+  """
+  @spec parse_timed(binary()) :: {document_ref(), call_usage()} | {:error, :memory_limit}
+  def parse_timed(_xml), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Same as `xpath_query/2`, also reporting what the query cost.
+
+  Returns `{result, usage}` with `usage` as in `parse_timed/1`; here
+  `:nodes_visited` counts the nodes each location step examined, so it
+  grows with the work a query does rather than with its result. An invalid
+  expression gives `{{:error, reason}, usage}`.
+
+  Always runs on the dirty CPU scheduler.
+
+  ## Examples
+
+      {items, %{nodes_visited: visited}} =
+        RustyXML.Native.xpath_query_timed(doc, "//item[@id='42']")
+
+  """
+  @spec xpath_query_timed(document_ref(), binary()) :: {term(), call_usage()}
+  def xpath_query_timed(_doc, _xpath), do: :erlang.nif_error(:nif_not_loaded)
+
+  # ==========================================================================
+  # Parser Limits
+  # ==========================================================================
+
+  @doc """
+  Set the maximum element nesting depth, for all processes (`0` = unlimited).
+
+  Strict parsing (`parse_strict/1`) and SimpleForm conversion reject deeper
+  documents with `{:error, {:max_depth_exceeded, limit}}`. The default is
+  10,000. Tree conversion does not recurse on the native stack, so the limit
+  is a policy choice rather than a safety requirement; lenient `parse/1`
+  does not check it.
+  """
+  @spec set_max_depth(non_neg_integer()) :: :ok
+  def set_max_depth(_depth), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Current maximum element nesting depth (`0` = unlimited).
+  """
+  @spec max_depth() :: non_neg_integer()
+  def max_depth, do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Limit the bytes allocated by the NIF, for all processes (`0` = unlimited,
+  the default).
+
+  Parses check the limit before indexing, against the input size, and
+  again after, dropping a document whose index took the count over; a
+  streaming parser checks it before buffering each chunk. Either way the
+  call returns `{:error, :memory_limit}` rather than growing the VM until
+  the OS kills it. Memory in use is left alone, so the count can stay
+  over the limit until documents are collected or closed.
+
+  The count is the one `get_rust_memory/0` reports, so a limit needs the
+  `memory_tracking` Cargo feature (on by default); without it a non-zero
+  limit returns `{:error, :memory_tracking_disabled}`.
+
+  ## Examples
+
+      :ok = RustyXML.Native.set_memory_limit(512 * 1024 * 1024)
+      RustyXML.Native.parse_strict(huge_xml)
+      #=> {:error, :memory_limit}
+
+  """
+  @spec set_memory_limit(non_neg_integer()) :: :ok | {:error, :memory_tracking_disabled}
+  def set_memory_limit(_bytes), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Current limit on bytes allocated by the NIF (`0` = unlimited).
+  """
+  @spec memory_limit() :: non_neg_integer()
+  def memory_limit, do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Choose the XML 1.0 name rules strict parsing applies, for all processes.
+
+  `:fourth_edition` (the default) checks names against the Appendix B
+  BaseChar/Ideographic tables. `:fifth_edition` uses the much wider
+  NameStartChar/NameChar ranges of XML 1.0 Fifth Edition, which accept
+  letters from scripts added to Unicode after 2.0. Raises `ArgumentError`
+  for any other value.
+  """
+  @spec set_name_rules(:fourth_edition | :fifth_edition) :: :ok
+  def set_name_rules(_edition), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Name rules currently applied by strict parsing.
+  """
+  @spec name_rules() :: :fourth_edition | :fifth_edition
+  def name_rules, do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Choose what XPath `substring()` and `string-length()` count, for all
+  processes.
+
+  `:characters` (the default) counts Unicode code points, as XPath 1.0
+  requires, so `string-length("日本")` is 2. `:bytes` counts UTF-8 bytes,
+  for code written against byte offsets; a substring that cuts through a
+  character gets U+FFFD in its place. Results cached by
+  `doc_set_result_cache/2` under the other setting are not reused.
+  Raises `ArgumentError` for any other value.
+  """
+  @spec set_xpath_string_units(:characters | :bytes) :: :ok
+  def set_xpath_string_units(_units), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Units XPath string functions currently count.
+  """
+  @spec xpath_string_units() :: :characters | :bytes
+  def xpath_string_units, do: :erlang.nif_error(:nif_not_loaded)
 end
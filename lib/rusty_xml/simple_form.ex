@@ -20,7 +20,15 @@ defmodule RustyXML.SimpleForm do
 
   """
 
-  @type element :: {String.t(), [{String.t(), String.t()}], [element() | String.t()]}
+  @type element ::
+          {String.t(), [{String.t(), String.t()}],
+           [element() | String.t() | raw_text() | more()]}
+
+  @typedoc "Text child in `raw_text: true` mode: source bytes and their `{offset, length}`"
+  @type raw_text :: {binary(), {non_neg_integer(), non_neg_integer()}}
+
+  @typedoc "Children left unconverted by `max_depth:`, with the element's node id"
+  @type more :: {:more, non_neg_integer()}
 
   @doc """
   Parse an XML string into simple form.
@@ -30,11 +38,41 @@ defmodule RustyXML.SimpleForm do
   ## Options
 
     * `:cdata_as_characters` - Merge CDATA into text content (default: `true`)
+    * `:raw_text` - Keep text exactly as it appears in the source, without
+      entity decoding. Text children become `{raw_text, {offset, length}}`
+      with byte positions in `xml`, and attribute values are raw. UTF-16
+      input is the exception: text is converted to UTF-8 and positions are
+      in the converted document, not in `xml` (default: `false`)
+    * `:normalize` - `:nfc` or `:nfd` to return text and attribute values
+      in that Unicode normalization form. Ignored with `:raw_text`
+      (default: `:none`)
+    * `:max_depth` - Convert at most this many levels below the root.
+      Elements at the limit get `[{:more, node_id}]` in place of any
+      children, where `node_id` is the element's id in `RustyXML.parse(xml)`;
+      `RustyXML.Native.node_to_term_shallow/3` expands it (default: no limit)
 
   """
   @spec parse_string(String.t(), keyword()) :: {:ok, element()} | {:error, any()}
-  def parse_string(xml, _opts \\ []) do
-    case RustyXML.Native.parse_to_simple_form(xml) do
+  def parse_string(xml, opts \\ []) do
+    raw_text = Keyword.get(opts, :raw_text, false)
+    form = Keyword.get(opts, :normalize, :none)
+
+    result =
+      case {Keyword.get(opts, :max_depth), raw_text, form} do
+        {nil, true, _} ->
+          RustyXML.Native.parse_to_simple_form_raw(xml)
+
+        {nil, false, :none} ->
+          RustyXML.Native.parse_to_simple_form(xml)
+
+        {nil, false, form} ->
+          RustyXML.Native.parse_to_simple_form_normalized(xml, form)
+
+        {max_depth, _, _} ->
+          RustyXML.Native.parse_to_simple_form_shallow(xml, max_depth, raw_text, form)
+      end
+
+    case result do
       {:ok, tree} -> {:ok, tree}
       {:error, _} = err -> err
     end
@@ -46,6 +84,16 @@ defmodule RustyXML.SimpleForm do
   Accumulates all chunks in Rust, then validates, indexes, and builds
   the SimpleForm tree in one pass. Minimal BEAM memory during accumulation.
 
+  ## Options
+
+    * `:validate_early` - Check well-formedness as chunks arrive and stop
+      consuming the stream at the first error, instead of only after the
+      last chunk (default: `false`)
+    * `:raw_text` - Keep text as in the source, as in `parse_string/2`;
+      offsets are positions in the concatenated chunks (default: `false`)
+    * `:normalize` - Unicode normalization form of text and attribute
+      values, as in `parse_string/2` (default: `:none`)
+
   ## Examples
 
       File.stream!("large.xml", [], 64 * 1024)
@@ -54,12 +102,35 @@ defmodule RustyXML.SimpleForm do
 
   """
   @spec parse_stream(Enumerable.t(), keyword()) :: {:ok, element()} | {:error, any()}
-  def parse_stream(stream, _opts \\ []) do
+  def parse_stream(stream, opts \\ []) do
     acc = RustyXML.Native.accumulator_new()
-    Enum.each(stream, &RustyXML.Native.accumulator_feed(acc, &1))
-    RustyXML.Native.accumulator_to_simple_form(acc)
+    raw_text = Keyword.get(opts, :raw_text, false)
+    to_simple_form = to_simple_form_fun(raw_text, Keyword.get(opts, :normalize, :none))
+
+    feed =
+      if Keyword.get(opts, :validate_early, false),
+        do: &RustyXML.Native.accumulator_feed_validated/2,
+        else: &RustyXML.Native.accumulator_feed/2
+
+    stream
+    |> Enum.reduce_while(:ok, fn chunk, :ok ->
+      case feed.(acc, chunk) do
+        :ok -> {:cont, :ok}
+        {:error, _} = err -> {:halt, err}
+      end
+    end)
+    |> case do
+      :ok -> to_simple_form.(acc)
+      {:error, _} = err -> err
+    end
   end
 
+  defp to_simple_form_fun(true, _), do: &RustyXML.Native.accumulator_to_simple_form_raw/1
+  defp to_simple_form_fun(false, :none), do: &RustyXML.Native.accumulator_to_simple_form/1
+
+  defp to_simple_form_fun(false, form),
+    do: &RustyXML.Native.accumulator_to_simple_form_normalized(&1, form)
+
   # Keep the handler module for backwards compatibility if anyone
   # references it, but it's no longer used by parse_string/2.
   defmodule Handler do
@@ -13,7 +13,7 @@ defmodule RustyXML.Streaming do
   tokenizes the BEAM binary in-place (zero copy). Memory usage is bounded by:
 
     * `chunk_size` - bytes per IO read operation
-    * Maximum single element size in your data
+    * Maximum single element size in your data (see `:max_element_size`)
     * NIF + BEAM combined peak is ~128 KB for a 2.93 MB document
 
   ## Usage
@@ -62,8 +62,19 @@ defmodule RustyXML.Streaming do
   # Types
   # ==========================================================================
 
-  @typedoc "Streamed element tuple - {tag_atom, xml_string}"
-  @type streamed_element :: {atom(), binary()}
+  @typedoc """
+  Streamed element tuple - `{tag_atom, xml_string}`.
+
+  When `:max_element_size` is set, elements over the limit are reported as
+  `{:oversized, name, size}` (`on_oversized: :skip`) or streamed as
+  `{:chunk, name, data}` pieces ending with `{:chunk_end, name, size}`
+  (`on_oversized: :chunk`).
+  """
+  @type streamed_element ::
+          {atom(), binary()}
+          | {:oversized, binary(), non_neg_integer()}
+          | {:chunk, binary(), binary()}
+          | {:chunk_end, binary(), non_neg_integer()}
 
   @typedoc """
   Options for streaming functions.
@@ -76,7 +87,9 @@ defmodule RustyXML.Streaming do
   """
   @type stream_options :: [
           chunk_size: pos_integer(),
-          discard: [atom() | binary()]
+          discard: [atom() | binary()],
+          max_element_size: pos_integer(),
+          on_oversized: :skip | :chunk
         ]
 
   # ==========================================================================
@@ -107,6 +120,15 @@ defmodule RustyXML.Streaming do
     * `:discard` - List of tag names whose content should be discarded.
       Useful for skipping large unwanted sections.
 
+    * `:max_element_size` - Maximum size in bytes of a single streamed
+      element. Larger elements are never buffered whole; see `:on_oversized`.
+      Defaults to unlimited.
+
+    * `:on_oversized` - What to do with elements over `:max_element_size`.
+      `:skip` (default) drops the element and emits `{:oversized, name, size}`.
+      `:chunk` emits the element's XML as `{:chunk, name, data}` pieces
+      followed by `{:chunk_end, name, size}`.
+
   ## Examples
 
       # Stream from a file path
@@ -148,6 +170,37 @@ defmodule RustyXML.Streaming do
     stream_enumerable_elements(enumerable, tag_str, tag_atom, opts)
   end
 
+  @doc """
+  Count the elements named `tag` in a source without materializing them.
+
+  Takes the same sources as `stream_tags/3` (a file path, an XML string or
+  an enumerable of chunks) and the `:chunk_size` option. Elements are
+  counted in Rust and never buffered, so memory stays bounded by the chunk
+  size however large the file or its elements are.
+
+  ## Examples
+
+      RustyXML.Streaming.count_tags("export.xml", :record)
+      #=> 1_204_331
+
+  """
+  @spec count_tags(binary() | Enumerable.t(), binary() | atom(), keyword()) ::
+          non_neg_integer()
+  def count_tags(source, tag, opts \\ []) do
+    chunk_size = Keyword.get(opts, :chunk_size, @default_chunk_size)
+    parser = Native.streaming_new_counter(normalize_tag(tag))
+
+    chunks =
+      cond do
+        not is_binary(source) -> source
+        String.starts_with?(source, "<") -> chunk_string(source, chunk_size)
+        true -> File.stream!(source, [], chunk_size)
+      end
+
+    Enum.each(chunks, &feed!(parser, IO.iodata_to_binary(&1)))
+    Native.streaming_count_elements(parser)
+  end
+
   @doc """
   Stream from an XML string.
   """
@@ -182,15 +235,15 @@ defmodule RustyXML.Streaming do
     chunk_size = Keyword.get(opts, :chunk_size, @default_chunk_size)
 
     Stream.resource(
-      fn -> init_file_element_stream(path, tag_str, chunk_size, tag_atom) end,
+      fn -> init_file_element_stream(path, tag_str, chunk_size, tag_atom, opts) end,
       &next_element_file/1,
       &cleanup_file_stream/1
     )
   end
 
-  defp init_file_element_stream(path, tag_str, chunk_size, tag_atom) do
+  defp init_file_element_stream(path, tag_str, chunk_size, tag_atom, opts) do
     device = File.open!(path, [:read, :binary, :raw])
-    parser = Native.streaming_new_with_filter(tag_str)
+    parser = new_parser(tag_str, opts)
 
     # State: {device, parser, chunk_size, tag_atom, element_builder, complete_elements}
     # element_builder: nil | {depth, xml_acc}
@@ -224,7 +277,7 @@ defmodule RustyXML.Streaming do
       :eof ->
         # Get remaining complete elements
         elements = Native.streaming_take_elements(parser, 1000)
-        complete = Enum.map(elements, fn elem -> {tag_atom, elem} end)
+        complete = Enum.map(elements, &to_streamed_element(&1, tag_atom))
 
         # Finalize (discard events, we use complete elements)
         _events = Native.streaming_finalize(parser)
@@ -240,11 +293,11 @@ defmodule RustyXML.Streaming do
         raise RustyXML.ParseError, message: "Error reading XML file: #{inspect(reason)}"
 
       chunk when is_binary(chunk) ->
-        {_available, _buffer_size} = Native.streaming_feed(parser, chunk)
+        feed!(parser, chunk)
 
         # Use fast path: get complete elements directly (no event processing)
         elements = Native.streaming_take_elements(parser, 1000)
-        complete = Enum.map(elements, fn elem -> {tag_atom, elem} end)
+        complete = Enum.map(elements, &to_streamed_element(&1, tag_atom))
 
         if complete == [] do
           # Keep reading until we have a complete element
@@ -265,18 +318,15 @@ defmodule RustyXML.Streaming do
   # ==========================================================================
 
   defp stream_enumerable_elements(enumerable, tag_str, tag_atom, opts) do
-    # Reserved for future use
-    _ = opts
-
     Stream.resource(
-      fn -> init_enum_element_stream(enumerable, tag_str, tag_atom) end,
+      fn -> init_enum_element_stream(enumerable, tag_str, tag_atom, opts) end,
       &next_element_enum/1,
       fn _state -> :ok end
     )
   end
 
-  defp init_enum_element_stream(enumerable, tag_str, tag_atom) do
-    parser = Native.streaming_new_with_filter(tag_str)
+  defp init_enum_element_stream(enumerable, tag_str, tag_atom, opts) do
+    parser = new_parser(tag_str, opts)
 
     # Start the enumerable reducer
     iterator =
@@ -298,11 +348,11 @@ defmodule RustyXML.Streaming do
 
   defp process_enum_for_elements({:suspended, chunk, continuation}, parser, tag_atom, _builder) do
     chunk_binary = if is_binary(chunk), do: chunk, else: to_string(chunk)
-    {_available, _buffer_size} = Native.streaming_feed(parser, chunk_binary)
+    feed!(parser, chunk_binary)
 
     # Use fast path: get complete elements directly from Rust (no event processing)
     elements = Native.streaming_take_elements(parser, 1000)
-    complete = Enum.map(elements, fn elem -> {tag_atom, elem} end)
+    complete = Enum.map(elements, &to_streamed_element(&1, tag_atom))
 
     next_iterator = continuation.({:cont, nil})
 
@@ -318,7 +368,7 @@ defmodule RustyXML.Streaming do
   defp process_enum_for_elements({:done, _}, parser, tag_atom, _builder) do
     # Get any remaining complete elements
     elements = Native.streaming_take_elements(parser, 1000)
-    complete = Enum.map(elements, fn elem -> {tag_atom, elem} end)
+    complete = Enum.map(elements, &to_streamed_element(&1, tag_atom))
 
     # Finalize (discard events, we use complete elements)
     _events = Native.streaming_finalize(parser)
@@ -339,6 +389,43 @@ defmodule RustyXML.Streaming do
   # Helpers
   # ==========================================================================
 
+  defp new_parser(tag_str, opts) do
+    case Keyword.get(opts, :max_element_size) do
+      nil ->
+        Native.streaming_new_with_filter(tag_str)
+
+      max when is_integer(max) and max > 0 ->
+        mode = Keyword.get(opts, :on_oversized, :skip)
+
+        unless mode in [:skip, :chunk] do
+          raise ArgumentError,
+                ":on_oversized must be :skip or :chunk, got: #{inspect(mode)}"
+        end
+
+        Native.streaming_new_with_limit(tag_str, max, mode)
+
+      other ->
+        raise ArgumentError,
+              ":max_element_size must be a positive integer, got: #{inspect(other)}"
+    end
+  end
+
+  defp feed!(parser, chunk) do
+    case Native.streaming_feed(parser, chunk) do
+      {:error, :memory_limit} ->
+        raise RustyXML.ParseError, message: "Streaming would exceed the NIF memory limit"
+
+      {:error, reason} ->
+        raise RustyXML.ParseError, message: "Streaming parser error: #{inspect(reason)}"
+
+      {_available, _buffer_size} ->
+        :ok
+    end
+  end
+
+  defp to_streamed_element(elem, tag_atom) when is_binary(elem), do: {tag_atom, elem}
+  defp to_streamed_element(marker, _tag_atom) when is_tuple(marker), do: marker
+
   defp normalize_tag(tag) when is_atom(tag), do: Atom.to_string(tag)
   defp normalize_tag(tag) when is_binary(tag), do: tag
 
@@ -0,0 +1,54 @@
+defmodule RustyXML.SVG do
+  @moduledoc """
+  Size and path data of SVG documents, read by the scanner alone.
+
+  Neither function builds a document index, so reading a couple of
+  attributes from each of a large batch of icons costs a scan of the bytes
+  involved rather than a full parse. `dimensions/1` stops at the root start
+  tag.
+
+  Elements are matched by local name (`<svg:path>` counts), but attributes
+  are matched exactly, so a prefixed `svg:width` or `svg:d` is not read.
+  Values have entity references decoded. Input is scanned leniently:
+  malformed markup is skipped rather than reported.
+
+  ## Example
+
+      svg = ~s(<svg width="24" height="24" viewBox="0 0 24 24"><path d="M0 0h24"/></svg>)
+
+      RustyXML.SVG.dimensions(svg)
+      #=> %{width: "24", height: "24", view_box: {0.0, 0.0, 24.0, 24.0}}
+
+      RustyXML.SVG.extract_paths(svg)
+      #=> ["M0 0h24"]
+
+  """
+
+  alias RustyXML.Native
+
+  @type dimensions :: %{
+          width: binary() | nil,
+          height: binary() | nil,
+          view_box: {float(), float(), float(), float()} | nil
+        }
+
+  @doc """
+  Read the root element's `width`, `height` and `viewBox`.
+
+  `width` and `height` are returned as written, units included (`"24px"`,
+  `"100%"`). `view_box` is `{min_x, min_y, width, height}`, or `nil` unless
+  the attribute holds exactly four numbers. Missing attributes are `nil`.
+  Returns `nil` when the root element isn't `svg`.
+  """
+  @spec dimensions(binary()) :: dimensions() | nil
+  def dimensions(svg) when is_binary(svg), do: Native.svg_dimensions(svg)
+
+  @doc """
+  Return the `d` attribute of every `path` element, in document order.
+
+  Paths inside `<defs>`, `<symbol>` and nested `<svg>` elements are
+  included; `path` elements without a `d` attribute are skipped.
+  """
+  @spec extract_paths(binary()) :: [binary()]
+  def extract_paths(svg) when is_binary(svg), do: Native.svg_extract_paths(svg)
+end
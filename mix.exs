@@ -95,6 +95,9 @@ defmodule RustyXML.MixProject do
         Streaming: [
           RustyXML.Streaming
         ],
+        Formats: [
+          RustyXML.SVG
+        ],
         "Low-Level": [
           RustyXML.Native
         ]
@@ -0,0 +1,113 @@
+//! Cancellation
+//!
+//! Long-running work is run under a caller-owned flag with `run`. The index
+//! builder and the XPath evaluator poll `is_cancelled` between tokens, and
+//! between operations and context nodes, so no flag needs threading through
+//! their call chains. A poll is one thread-local read, plus a relaxed atomic
+//! load while a flag is installed.
+//!
+//! The flag is per thread, so work spread over the rayon pool passes
+//! `current()` to `run_with` in each task to stay cancellable.
+
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Error message of XPath evaluation stopped by cancellation
+pub const CANCELLED: &str = "Cancelled";
+
+thread_local! {
+    static CURRENT: RefCell<Option<Arc<AtomicBool>>> = const { RefCell::new(None) };
+}
+
+/// Run `f` with `flag` as the current thread's cancellation flag
+pub fn run<T>(flag: &Arc<AtomicBool>, f: impl FnOnce() -> T) -> T {
+    run_with(Some(flag.clone()), f)
+}
+
+/// Run `f` with `flag`, typically another thread's `current()`, installed
+/// on this thread; `None` runs it uncancellable
+pub fn run_with<T>(flag: Option<Arc<AtomicBool>>, f: impl FnOnce() -> T) -> T {
+    // Reinstates the outer flag, also when `f` unwinds
+    struct Restore(Option<Arc<AtomicBool>>);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            CURRENT.with(|current| *current.borrow_mut() = self.0.take());
+        }
+    }
+
+    let _restore = Restore(CURRENT.with(|current| current.replace(flag)));
+    f()
+}
+
+/// The flag installed on this thread, to hand to `run_with` elsewhere
+pub fn current() -> Option<Arc<AtomicBool>> {
+    CURRENT.with(|current| current.borrow().clone())
+}
+
+/// Whether the flag installed by `run` on this thread has been set
+#[inline]
+pub fn is_cancelled() -> bool {
+    CURRENT.with(|current| {
+        current
+            .borrow()
+            .as_ref()
+            .is_some_and(|flag| flag.load(Ordering::Relaxed))
+    })
+}
+
+/// `Err(CANCELLED)` once the current flag is set
+#[inline]
+pub fn check() -> Result<(), String> {
+    if is_cancelled() {
+        Err(CANCELLED.to_string())
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cancelled_work_stops_early() {
+        let input = "<r>".to_string() + &"<a/>".repeat(10_000) + "</r>";
+        let flag = Arc::new(AtomicBool::new(false));
+
+        let full = run(&flag, || {
+            crate::index::builder::build_index(input.as_bytes())
+        });
+        assert_eq!(full.element_count(), 10_001);
+
+        flag.store(true, Ordering::Relaxed);
+        let partial = run(&flag, || {
+            crate::index::builder::build_index(input.as_bytes())
+        });
+        assert!(partial.element_count() < 10_001);
+
+        let view = crate::index::IndexedDocumentView::new(&full, input.as_bytes());
+        let result = run(&flag, || crate::xpath::evaluate(&view, "count(//a)"));
+        assert_eq!(result.unwrap_err(), CANCELLED);
+
+        // Nothing is installed outside `run`
+        assert!(!is_cancelled());
+        assert!(crate::xpath::evaluate(&view, "count(//a)").is_ok());
+    }
+
+    #[test]
+    fn test_flag_reaches_rayon_workers() {
+        let input = "<r>".to_string() + &"<a/>".repeat(100) + "</r>";
+        let index = crate::index::builder::build_index(input.as_bytes());
+        let view = crate::index::IndexedDocumentView::new(&index, input.as_bytes());
+        let flag = Arc::new(AtomicBool::new(true));
+
+        let results = run(&flag, || {
+            crate::strategy::parallel::evaluate_parallel(&view, &["//a", "count(//a)"])
+        });
+        assert!(results
+            .iter()
+            .all(|result| result.as_ref().unwrap_err() == CANCELLED));
+        assert!(current().is_none());
+    }
+}
@@ -0,0 +1,176 @@
+//! W3C XML Conformance Suite
+//!
+//! Runs the xmlconf suite against the strict parse path: every `valid` test
+//! must pass `validate_strict` after encoding conversion, and every
+//! `not-wf` test must be rejected. Built only with the `xmlconf` feature:
+//!
+//! ```text
+//! ./scripts/download-xmlconf.sh
+//! cargo test --features xmlconf conformance -- --nocapture
+//! ```
+//!
+//! The suite is read from `RUSTYXML_XMLCONF`, or `test/xmlconf/xmlconf` at
+//! the project root; the test passes with a notice when it is missing.
+//! Tests that can't apply to a non-validating parser without external
+//! entities are skipped by their catalog attributes; tests known to fail
+//! are listed in `KNOWN_FAILURES` with the reason, so a regression and a
+//! fix both fail the run until the list is updated.
+
+use crate::core::encoding::convert_to_utf8;
+use crate::dom::{validate_strict, DocumentAccess, NodeKind};
+use crate::index::builder::build_index;
+use crate::index::IndexedDocumentView;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Catalogs run, with the directory their test URIs are relative to
+const CATALOGS: &[(&str, &str)] = &[
+    ("xmltest/xmltest.xml", "xmltest"),
+    ("sun/sun-valid.xml", "sun"),
+    ("sun/sun-not-wf.xml", "sun"),
+    ("oasis/oasis.xml", "oasis"),
+    ("ibm/ibm_oasis_valid.xml", "ibm"),
+    ("ibm/ibm_oasis_not-wf.xml", "ibm"),
+];
+
+/// Test ids the strict parser is known to get wrong, with the reason
+const KNOWN_FAILURES: &[(&str, &str)] = &[];
+
+/// Why a test in a catalog isn't run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Skip {
+    /// `invalid` tests need DTD validation
+    Invalid,
+    /// `error` tests cover errors a processor may report
+    OptionalError,
+    /// The test needs external entities, which are never loaded
+    ExternalEntities,
+}
+
+struct TestCase {
+    id: String,
+    kind: String,
+    entities: String,
+    path: PathBuf,
+}
+
+#[derive(Default)]
+struct Tally {
+    passed: usize,
+    failed: usize,
+    known: usize,
+}
+
+fn suite_dir() -> PathBuf {
+    match std::env::var_os("RUSTYXML_XMLCONF") {
+        Some(dir) => PathBuf::from(dir),
+        None => Path::new(env!("CARGO_MANIFEST_DIR")).join("../../test/xmlconf/xmlconf"),
+    }
+}
+
+/// The TEST entries of a catalog
+fn read_catalog(catalog: &Path, base: &Path) -> Vec<TestCase> {
+    let input = std::fs::read(catalog).unwrap_or_else(|e| panic!("{}: {}", catalog.display(), e));
+    let index = build_index(&input);
+    let view = IndexedDocumentView::new(&index, &input);
+    let Some(root) = view.root_element_id() else {
+        return Vec::new();
+    };
+    view.descendants_vec(root)
+        .into_iter()
+        .filter(|&id| view.node_kind_of(id) == NodeKind::Element)
+        .filter(|&id| view.node_name(id) == Some("TEST"))
+        .filter_map(|id| {
+            let attribute = |name| view.get_attribute(id, name).map(str::to_string);
+            Some(TestCase {
+                id: attribute("ID")?,
+                kind: attribute("TYPE")?,
+                entities: attribute("ENTITIES").unwrap_or_else(|| "none".to_string()),
+                path: base.join(attribute("URI")?),
+            })
+        })
+        .collect()
+}
+
+fn skip_reason(test: &TestCase) -> Option<Skip> {
+    match test.kind.as_str() {
+        "invalid" => Some(Skip::Invalid),
+        "error" => Some(Skip::OptionalError),
+        _ if test.entities != "none" => Some(Skip::ExternalEntities),
+        _ => None,
+    }
+}
+
+/// Whether the strict parse path accepts a document
+fn accepts(input: Vec<u8>) -> bool {
+    let Ok(bytes) = convert_to_utf8(input) else {
+        return false;
+    };
+    let bytes = bytes.strip_prefix(&[0xEF, 0xBB, 0xBF]).unwrap_or(&bytes);
+    validate_strict(bytes).is_ok()
+}
+
+#[test]
+fn test_xmlconf_suite() {
+    let dir = suite_dir();
+    if !dir.is_dir() {
+        println!("xmlconf suite not found at {}, skipping", dir.display());
+        return;
+    }
+
+    let mut tallies: BTreeMap<(&str, String), Tally> = BTreeMap::new();
+    let mut skipped: BTreeMap<Skip, usize> = BTreeMap::new();
+    let mut unexpected = Vec::new();
+    for &(catalog, base) in CATALOGS {
+        for test in read_catalog(&dir.join(catalog), &dir.join(base)) {
+            if let Some(reason) = skip_reason(&test) {
+                *skipped.entry(reason).or_default() += 1;
+                continue;
+            }
+            let input = std::fs::read(&test.path)
+                .unwrap_or_else(|e| panic!("{}: {}", test.path.display(), e));
+            let passed = accepts(input) == (test.kind == "valid");
+            let known = KNOWN_FAILURES.iter().find(|(id, _)| *id == test.id);
+            let tally = tallies.entry((base, test.kind.clone())).or_default();
+            match (passed, known) {
+                (true, None) => tally.passed += 1,
+                (false, Some(_)) => tally.known += 1,
+                (false, None) => {
+                    tally.failed += 1;
+                    unexpected.push(format!("{} ({}) fails", test.id, test.kind));
+                }
+                (true, Some((_, reason))) => {
+                    tally.passed += 1;
+                    unexpected.push(format!("{} passes, remove it ({})", test.id, reason));
+                }
+            }
+        }
+    }
+
+    println!(
+        "{:<10} {:<8} {:>6} {:>6} {:>6} {:>7}",
+        "suite", "type", "pass", "fail", "known", "rate"
+    );
+    for ((suite, kind), tally) in &tallies {
+        let total = tally.passed + tally.failed + tally.known;
+        let rate = 100.0 * tally.passed as f64 / total.max(1) as f64;
+        println!(
+            "{:<10} {:<8} {:>6} {:>6} {:>6} {:>6.1}%",
+            suite, kind, tally.passed, tally.failed, tally.known, rate
+        );
+    }
+    for (reason, count) in &skipped {
+        println!("skipped {:?}: {}", reason, count);
+    }
+
+    assert!(
+        !tallies.is_empty(),
+        "no tests found under {}",
+        dir.display()
+    );
+    assert!(
+        unexpected.is_empty(),
+        "unexpected conformance results:\n{}",
+        unexpected.join("\n")
+    );
+}
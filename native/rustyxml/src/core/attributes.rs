@@ -1,10 +1,27 @@
 //! XML Attribute Parsing
 //!
 //! Parses XML attributes from tag content.
+//!
+//! Values are borrowed from the input unless they contain entity
+//! references, and are scanned with `memchr`, so megabyte-long values
+//! (inline images, embedded attachments) cost no copy and little time.
+//! Each tag is parsed in one pass and duplicate names are found with a
+//! hash lookup, so elements with thousands of attributes (machine-generated
+//! SVG) cost time in proportion to their size.
 
 use super::entities::{decode_text, decode_text_strict};
-use memchr::memchr;
+use memchr::{memchr, memchr3};
 use std::borrow::Cow;
+use std::collections::hash_map::{Entry, HashMap};
+
+/// Up to this many attributes, duplicates are found by comparing every
+/// pair, which is faster than hashing for the common short list
+const PAIRWISE_DUPLICATE_LIMIT: usize = 16;
+
+/// Attribute values at least this long are returned to the BEAM as
+/// sub-binaries of the input rather than copied, where the input is a
+/// binary the caller passed in
+pub const LARGE_VALUE_THRESHOLD: usize = 64 * 1024;
 
 /// A parsed XML attribute
 #[derive(Debug, Clone)]
@@ -63,6 +80,56 @@ fn split_name(name: &[u8]) -> (Option<&[u8]>, &[u8]) {
     }
 }
 
+/// The attribute section of a complete tag (`<name ...>`, `<name .../>` or
+/// `<?target ...?>`): everything between the name and the closing
+/// delimiter, ready for `parse_attributes`
+pub fn tag_attributes(tag: &[u8]) -> &[u8] {
+    // Skip '<' and optional '/' or '?'
+    let mut pos = 1;
+    if matches!(tag.get(1), Some(b'/' | b'?')) {
+        pos = 2;
+    }
+    while pos < tag.len() && !matches!(tag[pos], b' ' | b'\t' | b'\n' | b'\r' | b'>' | b'/') {
+        pos += 1;
+    }
+
+    let mut end = tag.len();
+    if tag.ends_with(b"/>") || tag.ends_with(b"?>") {
+        end -= 2;
+    } else if tag.ends_with(b">") {
+        end -= 1;
+    }
+    tag.get(pos..end).unwrap_or_default()
+}
+
+/// The first attribute whose name is repeated later in `attrs`
+pub fn find_duplicate<'x, 'a>(attrs: &'x [Attribute<'a>]) -> Option<&'x Attribute<'a>> {
+    if attrs.len() <= PAIRWISE_DUPLICATE_LIMIT {
+        return attrs.iter().enumerate().find_map(|(i, attr)| {
+            attrs[i + 1..]
+                .iter()
+                .any(|other| other.name == attr.name)
+                .then_some(attr)
+        });
+    }
+
+    // Index of each name's first occurrence; the earliest one seen again is
+    // the answer, as with the pairwise search
+    let mut first: HashMap<&[u8], usize> = HashMap::with_capacity(attrs.len());
+    let mut earliest: Option<usize> = None;
+    for (i, attr) in attrs.iter().enumerate() {
+        match first.entry(attr.name.as_ref()) {
+            Entry::Occupied(seen) => {
+                earliest = Some(earliest.map_or(*seen.get(), |e| e.min(*seen.get())));
+            }
+            Entry::Vacant(slot) => {
+                slot.insert(i);
+            }
+        }
+    }
+    earliest.map(|i| &attrs[i])
+}
+
 /// Parse attributes from raw tag content (after the element name)
 ///
 /// Input should be the content between element name and '>' or '/>'
@@ -192,39 +259,28 @@ fn parse_attributes_with_validation(
         let value_start = pos;
 
         // Find closing quote
+        if !strict {
+            pos = memchr(quote, &input[pos..]).map_or(input.len(), |i| pos + i);
+        }
         while pos < input.len() && input[pos] != quote {
-            // Strict mode: check for invalid characters in attribute value
-            if strict {
-                if input[pos] == b'<' {
-                    return (attrs, Some("Attribute value cannot contain '<'"));
-                }
-                // Check for unescaped & (must be followed by entity name or #)
-                if input[pos] == b'&' {
-                    let amp_pos = pos;
-                    pos += 1;
-                    if pos < input.len() {
-                        let next = input[pos];
-                        if next == b'#' {
-                            // Character reference - skip to ;
-                            while pos < input.len() && input[pos] != b';' && input[pos] != quote {
-                                pos += 1;
-                            }
-                        } else if is_name_start_char(next) {
-                            // Named entity reference - skip to ;
-                            while pos < input.len() && input[pos] != b';' && input[pos] != quote {
-                                pos += 1;
-                            }
-                        } else {
-                            return (attrs, Some("Bare '&' not allowed in attribute value"));
-                        }
-                    } else {
-                        return (attrs, Some("Bare '&' not allowed in attribute value"));
-                    }
-                    pos = amp_pos + 1;
-                    continue;
+            // Strict mode: check for invalid characters in attribute value,
+            // jumping between the bytes that need a look
+            let Some(i) = memchr3(quote, b'<', b'&', &input[pos..]) else {
+                pos = input.len();
+                break;
+            };
+            pos += i;
+            if input[pos] == b'<' {
+                return (attrs, Some("Attribute value cannot contain '<'"));
+            }
+            // Check for unescaped & (must be followed by entity name or #)
+            if input[pos] == b'&' {
+                match input.get(pos + 1) {
+                    Some(&next) if next == b'#' || is_name_start_char(next) => {}
+                    _ => return (attrs, Some("Bare '&' not allowed in attribute value")),
                 }
+                pos += 1;
             }
-            pos += 1;
         }
 
         // Check for mismatched quotes
@@ -321,6 +377,58 @@ mod tests {
         assert_eq!(attrs.len(), 0);
     }
 
+    #[test]
+    fn test_large_value_is_borrowed() {
+        let value = "A".repeat(LARGE_VALUE_THRESHOLD * 4);
+        let input = format!(r#" src="data:image/png;base64,{value}" alt='x'"#);
+        for attrs in [
+            parse_attributes(input.as_bytes()),
+            parse_attributes_strict(input.as_bytes()).unwrap(),
+        ] {
+            assert_eq!(attrs.len(), 2);
+            assert!(matches!(attrs[0].value, Cow::Borrowed(_)));
+            assert_eq!(attrs[0].value.len(), value.len() + 22);
+            assert_eq!(attrs[1].value_str(), Some("x"));
+        }
+    }
+
+    #[test]
+    fn test_strict_value_checks() {
+        assert!(parse_attributes_strict(b" a=\"x<y\"").is_err());
+        assert!(parse_attributes_strict(b" a=\"x & y\"").is_err());
+        assert!(parse_attributes_strict(b" a=\"x&").is_err());
+        let attrs = parse_attributes_strict(b" a=\"&#60;&amp;\" b='\"'").unwrap();
+        assert_eq!(attrs[0].value_str(), Some("<&"));
+        assert_eq!(attrs[1].value_str(), Some("\""));
+    }
+
+    #[test]
+    fn test_tag_attributes() {
+        assert_eq!(tag_attributes(b"<a x='1'>"), b" x='1'");
+        assert_eq!(tag_attributes(b"<a:b x='1'/>"), b" x='1'");
+        assert_eq!(tag_attributes(b"<?xml version='1.0'?>"), b" version='1.0'");
+        assert_eq!(tag_attributes(b"<a/>"), b"");
+        assert_eq!(tag_attributes(b""), b"");
+    }
+
+    #[test]
+    fn test_find_duplicate() {
+        let name = |attrs: &[Attribute]| {
+            find_duplicate(attrs).and_then(|a| a.name_str().map(String::from))
+        };
+        assert_eq!(name(&parse_attributes(b" a='1' b='2' c='3'")), None);
+        assert_eq!(
+            name(&parse_attributes(b" a='1' b='2' b='3' a='4'")),
+            Some("a".into())
+        );
+
+        // Past the pairwise limit, with the same answer
+        let mut input: String = (0..1000).map(|i| format!(" a{i}=''")).collect();
+        assert_eq!(name(&parse_attributes(input.as_bytes())), None);
+        input.push_str(" a999='' a5=''");
+        assert_eq!(name(&parse_attributes(input.as_bytes())), Some("a5".into()));
+    }
+
     #[test]
     fn test_whitespace_handling() {
         let attrs = parse_attributes(b"  id  =  \"test\"  ");
@@ -0,0 +1,319 @@
+//! Block-wise structural character classification
+//!
+//! Classifies the input in 64-byte blocks, producing one `u64` bitmask per
+//! structural character (bit `i` set = byte `i` of the block matches), in the
+//! style of simdjson's stage 1. The scanner then finds the end of a text run
+//! or attribute value with `trailing_zeros` on a mask instead of a per-byte
+//! loop, and learns whether the run contains `&` from the same block.
+//!
+//! Backends:
+//! - SSE2 on x86_64 (always available on that target)
+//! - NEON on aarch64
+//! - Scalar fallback everywhere else
+
+use std::marker::PhantomData;
+
+/// Size of a classification block in bytes
+pub const BLOCK_SIZE: usize = 64;
+
+/// Structural character bitmasks for one 64-byte block
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BlockMasks {
+    /// `<` positions
+    pub lt: u64,
+    /// `>` positions
+    pub gt: u64,
+    /// `"` positions
+    pub dquote: u64,
+    /// `'` positions
+    pub squote: u64,
+    /// `&` positions
+    pub amp: u64,
+}
+
+impl BlockMasks {
+    /// Mask for a quote character (`"` or `'`)
+    #[inline]
+    pub fn quote(&self, quote: u8) -> u64 {
+        if quote == b'"' {
+            self.dquote
+        } else {
+            self.squote
+        }
+    }
+}
+
+/// Block classification backend
+pub trait ClassifyBlock {
+    fn classify(block: &[u8; BLOCK_SIZE]) -> BlockMasks;
+}
+
+/// Best backend for the target (SSE2, NEON, or scalar fallback)
+pub struct Simd;
+
+/// Portable scalar backend (baseline for benchmarks)
+pub struct Scalar;
+
+impl ClassifyBlock for Simd {
+    #[inline]
+    fn classify(block: &[u8; BLOCK_SIZE]) -> BlockMasks {
+        classify_block(block)
+    }
+}
+
+impl ClassifyBlock for Scalar {
+    #[inline]
+    fn classify(block: &[u8; BLOCK_SIZE]) -> BlockMasks {
+        classify_block_scalar(block)
+    }
+}
+
+/// Classify one block with the portable scalar implementation
+pub fn classify_block_scalar(block: &[u8; BLOCK_SIZE]) -> BlockMasks {
+    let mut masks = BlockMasks::default();
+    for (i, &b) in block.iter().enumerate() {
+        let bit = 1u64 << i;
+        match b {
+            b'<' => masks.lt |= bit,
+            b'>' => masks.gt |= bit,
+            b'"' => masks.dquote |= bit,
+            b'\'' => masks.squote |= bit,
+            b'&' => masks.amp |= bit,
+            _ => {}
+        }
+    }
+    masks
+}
+
+/// Classify one block using the best backend for the target
+#[inline]
+pub fn classify_block(block: &[u8; BLOCK_SIZE]) -> BlockMasks {
+    #[cfg(target_arch = "x86_64")]
+    {
+        sse2::classify_block(block)
+    }
+    #[cfg(target_arch = "aarch64")]
+    {
+        neon::classify_block(block)
+    }
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+    {
+        classify_block_scalar(block)
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod sse2 {
+    use super::{BlockMasks, BLOCK_SIZE};
+    use std::arch::x86_64::*;
+
+    #[inline]
+    pub fn classify_block(block: &[u8; BLOCK_SIZE]) -> BlockMasks {
+        // SSE2 is part of the x86_64 baseline, and every load reads 16 bytes
+        // from within the 64-byte array.
+        unsafe {
+            let ptr = block.as_ptr() as *const __m128i;
+            let chunks = [
+                _mm_loadu_si128(ptr),
+                _mm_loadu_si128(ptr.add(1)),
+                _mm_loadu_si128(ptr.add(2)),
+                _mm_loadu_si128(ptr.add(3)),
+            ];
+            BlockMasks {
+                lt: eq_mask(&chunks, b'<'),
+                gt: eq_mask(&chunks, b'>'),
+                dquote: eq_mask(&chunks, b'"'),
+                squote: eq_mask(&chunks, b'\''),
+                amp: eq_mask(&chunks, b'&'),
+            }
+        }
+    }
+
+    #[inline]
+    unsafe fn eq_mask(chunks: &[__m128i; 4], byte: u8) -> u64 {
+        let needle = _mm_set1_epi8(byte as i8);
+        let mut mask = 0u64;
+        for (i, chunk) in chunks.iter().enumerate() {
+            let bits = _mm_movemask_epi8(_mm_cmpeq_epi8(*chunk, needle)) as u16;
+            mask |= (bits as u64) << (i * 16);
+        }
+        mask
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod neon {
+    use super::{BlockMasks, BLOCK_SIZE};
+    use std::arch::aarch64::*;
+
+    #[inline]
+    pub fn classify_block(block: &[u8; BLOCK_SIZE]) -> BlockMasks {
+        // NEON is part of the aarch64 baseline, and every load reads 16 bytes
+        // from within the 64-byte array.
+        unsafe {
+            let ptr = block.as_ptr();
+            let chunks = [
+                vld1q_u8(ptr),
+                vld1q_u8(ptr.add(16)),
+                vld1q_u8(ptr.add(32)),
+                vld1q_u8(ptr.add(48)),
+            ];
+            BlockMasks {
+                lt: eq_mask(&chunks, b'<'),
+                gt: eq_mask(&chunks, b'>'),
+                dquote: eq_mask(&chunks, b'"'),
+                squote: eq_mask(&chunks, b'\''),
+                amp: eq_mask(&chunks, b'&'),
+            }
+        }
+    }
+
+    /// Compare all four chunks against `byte` and pack the result into a u64
+    /// (NEON has no movemask; weight each lane by its bit and add pairwise)
+    #[inline]
+    unsafe fn eq_mask(chunks: &[uint8x16_t; 4], byte: u8) -> u64 {
+        const WEIGHTS: [u8; 16] = [1, 2, 4, 8, 16, 32, 64, 128, 1, 2, 4, 8, 16, 32, 64, 128];
+        let weights = vld1q_u8(WEIGHTS.as_ptr());
+        let needle = vdupq_n_u8(byte);
+        let t0 = vandq_u8(vceqq_u8(chunks[0], needle), weights);
+        let t1 = vandq_u8(vceqq_u8(chunks[1], needle), weights);
+        let t2 = vandq_u8(vceqq_u8(chunks[2], needle), weights);
+        let t3 = vandq_u8(vceqq_u8(chunks[3], needle), weights);
+        let sum0 = vpaddq_u8(t0, t1);
+        let sum1 = vpaddq_u8(t2, t3);
+        let sum = vpaddq_u8(sum0, sum1);
+        let sum = vpaddq_u8(sum, sum);
+        vgetq_lane_u64(vreinterpretq_u64_u8(sum), 0)
+    }
+}
+
+/// Lazily classifies an input block by block, caching the current block
+///
+/// Scanner lookups tend to hit the same block repeatedly (short tags and
+/// text runs), so each block is classified at most once per forward pass.
+pub struct StructuralClassifier<'a, C: ClassifyBlock = Simd> {
+    input: &'a [u8],
+    /// Start offset of the cached block (usize::MAX = none)
+    block_start: usize,
+    masks: BlockMasks,
+    _backend: PhantomData<C>,
+}
+
+impl<'a, C: ClassifyBlock> StructuralClassifier<'a, C> {
+    /// Create a classifier over the input
+    pub fn new(input: &'a [u8]) -> Self {
+        Self {
+            input,
+            block_start: usize::MAX,
+            masks: BlockMasks::default(),
+            _backend: PhantomData,
+        }
+    }
+
+    /// Masks for the block starting at `block_start` (must be block-aligned)
+    #[inline]
+    fn masks_at(&mut self, block_start: usize) -> BlockMasks {
+        if self.block_start != block_start {
+            let full_block = self
+                .input
+                .get(block_start..block_start + BLOCK_SIZE)
+                .and_then(|bytes| <&[u8; BLOCK_SIZE]>::try_from(bytes).ok());
+            self.masks = if let Some(block) = full_block {
+                C::classify(block)
+            } else {
+                // Tail block: zero padding never matches a structural byte
+                let mut block = [0u8; BLOCK_SIZE];
+                let tail = &self.input[block_start..];
+                block[..tail.len()].copy_from_slice(tail);
+                C::classify(&block)
+            };
+            self.block_start = block_start;
+        }
+        self.masks
+    }
+
+    /// Find the end of a text run starting at `pos`
+    ///
+    /// Returns the position of the next `<` (or the input length) and whether
+    /// an `&` occurs in between.
+    #[inline]
+    pub fn find_text_end(&mut self, pos: usize) -> (usize, bool) {
+        let mut has_amp = false;
+        let mut block_start = pos - pos % BLOCK_SIZE;
+        let mut from = !0u64 << (pos - block_start);
+        while block_start < self.input.len() {
+            let masks = self.masks_at(block_start);
+            let lt = masks.lt & from;
+            if lt != 0 {
+                let offset = lt.trailing_zeros();
+                let before = (1u64 << offset) - 1;
+                has_amp |= masks.amp & from & before != 0;
+                return (block_start + offset as usize, has_amp);
+            }
+            has_amp |= masks.amp & from != 0;
+            block_start += BLOCK_SIZE;
+            from = !0;
+        }
+        (self.input.len(), has_amp)
+    }
+
+    /// Find the next `quote` byte (`"` or `'`) at or after `pos`
+    #[inline]
+    pub fn find_quote(&mut self, pos: usize, quote: u8) -> Option<usize> {
+        let mut block_start = pos - pos % BLOCK_SIZE;
+        let mut from = !0u64 << (pos - block_start);
+        while block_start < self.input.len() {
+            let bits = self.masks_at(block_start).quote(quote) & from;
+            if bits != 0 {
+                return Some(block_start + bits.trailing_zeros() as usize);
+            }
+            block_start += BLOCK_SIZE;
+            from = !0;
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block_of(bytes: &[u8]) -> [u8; BLOCK_SIZE] {
+        let mut block = [b'x'; BLOCK_SIZE];
+        block[..bytes.len()].copy_from_slice(bytes);
+        block
+    }
+
+    #[test]
+    fn test_backend_matches_scalar() {
+        let mut block = block_of(b"<a href=\"x\" t='y'>&amp;</a>");
+        block[63] = b'<';
+        block[16] = b'&';
+        block[47] = b'>';
+        assert_eq!(classify_block(&block), classify_block_scalar(&block));
+
+        let masks = classify_block(&block);
+        assert_eq!(masks.lt, 1 | (1 << 23) | (1 << 63));
+        assert_eq!(masks.amp, (1 << 16) | (1 << 18));
+    }
+
+    #[test]
+    fn test_find_text_end_across_blocks() {
+        let mut input = vec![b'a'; 150];
+        input[100] = b'&';
+        input[130] = b'<';
+        let mut classifier = StructuralClassifier::<Simd>::new(&input);
+        assert_eq!(classifier.find_text_end(0), (130, true));
+        assert_eq!(classifier.find_text_end(101), (130, false));
+        assert_eq!(classifier.find_text_end(131), (150, false));
+    }
+
+    #[test]
+    fn test_find_quote() {
+        let input = b"a=\"it's\" b='x\"y'";
+        let mut classifier = StructuralClassifier::<Simd>::new(input);
+        assert_eq!(classifier.find_quote(3, b'"'), Some(7));
+        assert_eq!(classifier.find_quote(12, b'\''), Some(15));
+        assert_eq!(classifier.find_quote(16, b'"'), None);
+    }
+}
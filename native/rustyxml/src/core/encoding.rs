@@ -2,6 +2,12 @@
 //!
 //! Handles detection of UTF-16 and other encodings based on BOM and XML declaration.
 //! Converts non-UTF-8 encodings to UTF-8 for parsing.
+//!
+//! Detection follows the XML 1.0 autodetection table (Appendix F): a byte
+//! order mark, or the pattern of null bytes around the first `<`. UTF-32 and
+//! EBCDIC are recognized so they can be reported, but are not converted.
+
+use std::fmt;
 
 /// Detect the encoding of XML input based on BOM or byte patterns
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -9,6 +15,9 @@ pub enum XmlEncoding {
     Utf8,
     Utf16Le,
     Utf16Be,
+    Utf32Le,
+    Utf32Be,
+    Ebcdic,
 }
 
 impl XmlEncoding {
@@ -18,6 +27,23 @@ impl XmlEncoding {
             return XmlEncoding::Utf8;
         }
 
+        if let [b0, b1, b2, b3, ..] = *input {
+            match (b0, b1, b2, b3) {
+                // UTF-32 BOMs, checked before UTF-16 since FF FE is a prefix
+                (0x00, 0x00, 0xFE, 0xFF) => return XmlEncoding::Utf32Be,
+                (0xFF, 0xFE, 0x00, 0x00) => return XmlEncoding::Utf32Le,
+                // UTF-32 without BOM: one ASCII character in four bytes
+                (0x00, 0x00, 0x00, c) if c != 0 => return XmlEncoding::Utf32Be,
+                (c, 0x00, 0x00, 0x00) if c != 0 => return XmlEncoding::Utf32Le,
+                // "<?xm" in EBCDIC
+                (0x4C, 0x6F, 0xA7, 0x94) => return XmlEncoding::Ebcdic,
+                // UTF-16 without BOM: two ASCII characters with null high bytes
+                (0x00, c, 0x00, d) if c != 0 && d != 0 => return XmlEncoding::Utf16Be,
+                (c, 0x00, d, 0x00) if c != 0 && d != 0 => return XmlEncoding::Utf16Le,
+                _ => {}
+            }
+        }
+
         // Check for BOM
         match (input[0], input[1]) {
             // UTF-16 LE BOM: 0xFF 0xFE
@@ -32,13 +58,75 @@ impl XmlEncoding {
             _ => XmlEncoding::Utf8,
         }
     }
+
+    /// Length of the byte order mark at the start of `input`, if it has one
+    /// for this encoding
+    pub fn bom_len(self, input: &[u8]) -> usize {
+        let bom: &[u8] = match self {
+            XmlEncoding::Utf8 => &[0xEF, 0xBB, 0xBF],
+            XmlEncoding::Utf16Le => &[0xFF, 0xFE],
+            XmlEncoding::Utf16Be => &[0xFE, 0xFF],
+            XmlEncoding::Utf32Le => &[0xFF, 0xFE, 0x00, 0x00],
+            XmlEncoding::Utf32Be => &[0x00, 0x00, 0xFE, 0xFF],
+            XmlEncoding::Ebcdic => &[],
+        };
+        if !bom.is_empty() && input.starts_with(bom) {
+            bom.len()
+        } else {
+            0
+        }
+    }
+
+    /// Whether input in this encoding can be converted to UTF-8 and parsed
+    pub fn is_supported(self) -> bool {
+        matches!(
+            self,
+            XmlEncoding::Utf8 | XmlEncoding::Utf16Le | XmlEncoding::Utf16Be
+        )
+    }
+
+    /// Encoding name as used in XML declarations
+    pub fn name(self) -> &'static str {
+        match self {
+            XmlEncoding::Utf8 => "UTF-8",
+            XmlEncoding::Utf16Le => "UTF-16LE",
+            XmlEncoding::Utf16Be => "UTF-16BE",
+            XmlEncoding::Utf32Le => "UTF-32LE",
+            XmlEncoding::Utf32Be => "UTF-32BE",
+            XmlEncoding::Ebcdic => "EBCDIC",
+        }
+    }
+}
+
+/// Why input could not be converted to UTF-8
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EncodingError {
+    /// The input was detected as an encoding that is not converted
+    Unsupported(XmlEncoding),
+    /// The input is malformed in its detected encoding
+    Invalid(String),
+    /// A charset label given for the input names no encoding we convert
+    UnknownCharset(String),
+}
+
+impl fmt::Display for EncodingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EncodingError::Unsupported(encoding) => {
+                write!(f, "Unsupported encoding: {}", encoding.name())
+            }
+            EncodingError::Invalid(msg) => f.write_str(msg),
+            EncodingError::UnknownCharset(label) => write!(f, "Unsupported encoding: {}", label),
+        }
+    }
 }
 
 /// Convert UTF-16 bytes to UTF-8
 ///
 /// Takes raw bytes that may be UTF-16 LE or BE encoded and converts to UTF-8.
-/// Returns the original bytes if already UTF-8 or conversion fails.
-pub fn convert_to_utf8(input: Vec<u8>) -> Result<Vec<u8>, String> {
+/// Returns the original bytes if already UTF-8; UTF-32 and EBCDIC input is
+/// rejected with `EncodingError::Unsupported`.
+pub fn convert_to_utf8(input: Vec<u8>) -> Result<Vec<u8>, EncodingError> {
     let encoding = XmlEncoding::detect(&input);
 
     match encoding {
@@ -50,8 +138,9 @@ pub fn convert_to_utf8(input: Vec<u8>) -> Result<Vec<u8>, String> {
                 Ok(input)
             }
         }
-        XmlEncoding::Utf16Le => convert_utf16_le_to_utf8(&input),
-        XmlEncoding::Utf16Be => convert_utf16_be_to_utf8(&input),
+        XmlEncoding::Utf16Le => convert_utf16_le_to_utf8(&input).map_err(EncodingError::Invalid),
+        XmlEncoding::Utf16Be => convert_utf16_be_to_utf8(&input).map_err(EncodingError::Invalid),
+        unsupported => Err(EncodingError::Unsupported(unsupported)),
     }
 }
 
@@ -109,6 +198,94 @@ fn convert_utf16_be_to_utf8(input: &[u8]) -> Result<Vec<u8>, String> {
         .map_err(|e| format!("Invalid UTF-16 BE: {}", e))
 }
 
+/// The `charset` parameter of a media type such as
+/// `text/xml; charset="iso-8859-1"`, unquoted
+pub fn charset_param(content_type: &str) -> Option<&str> {
+    content_type
+        .split(';')
+        .skip(1)
+        .find_map(|param| {
+            let (name, value) = param.split_once('=')?;
+            name.trim()
+                .eq_ignore_ascii_case("charset")
+                .then(|| value.trim().trim_matches('"'))
+        })
+        .filter(|label| !label.is_empty())
+}
+
+/// Convert `input` to UTF-8, choosing its encoding by the precedence of
+/// RFC 7303: a byte order mark, then the `charset` of `content_type`, then
+/// the encoding in the XML declaration
+///
+/// Without any of them, UTF-16 is still recognised from its null bytes and
+/// anything else is taken as UTF-8. Charset labels are resolved as in the
+/// WHATWG Encoding Standard, so `iso-8859-1` decodes as windows-1252.
+pub fn convert_with_charset_hint(
+    input: &[u8],
+    content_type: &str,
+) -> Result<Vec<u8>, EncodingError> {
+    let detected = XmlEncoding::detect(input);
+    if detected.bom_len(input) > 0 {
+        return convert_to_utf8(input.to_vec());
+    }
+    if let Some(label) = charset_param(content_type) {
+        return decode_labelled(input, label.as_bytes());
+    }
+    match crate::core::prolog::parse_prolog(input).encoding {
+        // A declaration readable as ASCII can't be right about UTF-16
+        Some(label) if detected == XmlEncoding::Utf8 && !is_utf16_label(label) => {
+            decode_labelled(input, label)
+        }
+        _ => convert_to_utf8(input.to_vec()),
+    }
+}
+
+fn is_utf16_label(label: &[u8]) -> bool {
+    encoding_rs::Encoding::for_label(label).is_some_and(|encoding| {
+        encoding == encoding_rs::UTF_16LE || encoding == encoding_rs::UTF_16BE
+    })
+}
+
+/// Decode `input` from the encoding named by a charset label
+fn decode_labelled(input: &[u8], label: &[u8]) -> Result<Vec<u8>, EncodingError> {
+    let Some(mut encoding) = encoding_rs::Encoding::for_label_no_replacement(label) else {
+        let label = String::from_utf8_lossy(label).into_owned();
+        return Err(EncodingError::UnknownCharset(label));
+    };
+    // Plain `utf-16` leaves the byte order to the content
+    if label.trim_ascii().eq_ignore_ascii_case(b"utf-16")
+        && XmlEncoding::detect(input) == XmlEncoding::Utf16Be
+    {
+        encoding = encoding_rs::UTF_16BE;
+    }
+    encoding
+        .decode_without_bom_handling_and_without_replacement(input)
+        .map(|text| text.into_owned().into_bytes())
+        .ok_or_else(|| EncodingError::Invalid(format!("Invalid {} input", encoding.name())))
+}
+
+/// Convert UTF-16 input to UTF-8 for lenient parsing, or None if it isn't
+/// UTF-16
+///
+/// Never fails: unpaired surrogates become U+FFFD and a trailing odd byte
+/// is dropped.
+pub fn convert_utf16_lossy(input: &[u8]) -> Option<Vec<u8>> {
+    let encoding = XmlEncoding::detect(input);
+    let code_unit: fn([u8; 2]) -> u16 = match encoding {
+        XmlEncoding::Utf16Le => u16::from_le_bytes,
+        XmlEncoding::Utf16Be => u16::from_be_bytes,
+        _ => return None,
+    };
+    let bytes = &input[encoding.bom_len(input)..];
+    let units = bytes
+        .chunks_exact(2)
+        .map(|chunk| code_unit([chunk[0], chunk[1]]));
+    let text: String = char::decode_utf16(units)
+        .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+        .collect();
+    Some(text.into_bytes())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -165,6 +342,113 @@ mod tests {
         assert_eq!(result, b"<r/>");
     }
 
+    #[test]
+    fn test_detect_utf16_without_bom() {
+        // "\n<" with whitespace before the root element
+        assert_eq!(
+            XmlEncoding::detect(&[0x00, b'\n', 0x00, b'<']),
+            XmlEncoding::Utf16Be
+        );
+        assert_eq!(
+            XmlEncoding::detect(&[b' ', 0x00, b'<', 0x00]),
+            XmlEncoding::Utf16Le
+        );
+    }
+
+    #[test]
+    fn test_detect_utf32_and_ebcdic() {
+        let cases: &[(&[u8], XmlEncoding, usize)] = &[
+            (&[0x00, 0x00, 0xFE, 0xFF, 0x00], XmlEncoding::Utf32Be, 4),
+            (&[0xFF, 0xFE, 0x00, 0x00, b'<'], XmlEncoding::Utf32Le, 4),
+            (&[0x00, 0x00, 0x00, b'<'], XmlEncoding::Utf32Be, 0),
+            (&[b'<', 0x00, 0x00, 0x00], XmlEncoding::Utf32Le, 0),
+            (&[0x4C, 0x6F, 0xA7, 0x94], XmlEncoding::Ebcdic, 0),
+        ];
+        for &(input, expected, bom_len) in cases {
+            let encoding = XmlEncoding::detect(input);
+            assert_eq!(encoding, expected, "{:?}", input);
+            assert_eq!(encoding.bom_len(input), bom_len);
+            assert!(!encoding.is_supported());
+            assert_eq!(
+                convert_to_utf8(input.to_vec()),
+                Err(EncodingError::Unsupported(expected))
+            );
+        }
+        assert_eq!(XmlEncoding::Utf32Be.name(), "UTF-32BE");
+    }
+
+    #[test]
+    fn test_invalid_utf16_is_not_unsupported() {
+        let odd = vec![0xFF, 0xFE, b'<', 0x00, b'r'];
+        assert!(matches!(
+            convert_to_utf8(odd),
+            Err(EncodingError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn test_lossy_utf16_conversion() {
+        // "<r>" then an unpaired high surrogate, "</r>" and a stray byte
+        let mut le = vec![0xFF, 0xFE, b'<', 0, b'r', 0, b'>', 0, 0x00, 0xD8];
+        le.extend_from_slice(&[b'<', 0, b'/', 0, b'r', 0, b'>', 0, b'x']);
+        assert_eq!(
+            convert_utf16_lossy(&le).as_deref(),
+            Some("<r>\u{FFFD}</r>".as_bytes())
+        );
+
+        let be = [0, b'<', 0, b'r', 0, b'/', 0, b'>'];
+        assert_eq!(convert_utf16_lossy(&be).as_deref(), Some(&b"<r/>"[..]));
+        assert_eq!(convert_utf16_lossy(b"<r/>"), None);
+    }
+
+    #[test]
+    fn test_charset_param() {
+        assert_eq!(charset_param("text/xml; charset=utf-8"), Some("utf-8"));
+        assert_eq!(
+            charset_param("application/xml;q=1; Charset=\"ISO-8859-1\""),
+            Some("ISO-8859-1")
+        );
+        assert_eq!(charset_param("text/xml"), None);
+        assert_eq!(charset_param("text/xml; charset="), None);
+    }
+
+    #[test]
+    fn test_charset_hint_precedence() {
+        let latin1 = b"<?xml version='1.0' encoding='ISO-8859-1'?><r>caf\xE9</r>";
+        let expected = "<?xml version='1.0' encoding='ISO-8859-1'?><r>caf\u{E9}</r>";
+
+        // The declaration applies without a charset
+        let converted = convert_with_charset_hint(latin1, "text/xml").unwrap();
+        assert_eq!(converted, expected.as_bytes());
+
+        // The charset overrides the declaration
+        let utf8 = "<?xml version='1.0' encoding='ISO-8859-1'?><r>caf\u{E9}</r>";
+        let converted = convert_with_charset_hint(utf8.as_bytes(), "text/xml; charset=utf-8");
+        assert_eq!(converted.unwrap(), utf8.as_bytes());
+        assert!(matches!(
+            convert_with_charset_hint(latin1, "text/xml; charset=utf-8"),
+            Err(EncodingError::Invalid(_))
+        ));
+
+        // A BOM overrides the charset
+        let bom = [&[0xFF, 0xFE][..], &[b'<', 0, b'r', 0, b'/', 0, b'>', 0]].concat();
+        let converted = convert_with_charset_hint(&bom, "text/xml; charset=iso-8859-1");
+        assert_eq!(converted.unwrap(), b"<r/>");
+
+        // Plain utf-16 takes its byte order from the content
+        let be = [0, b'<', 0, b'r', 0, b'/', 0, b'>'];
+        let converted = convert_with_charset_hint(&be, "text/xml; charset=UTF-16");
+        assert_eq!(converted.unwrap(), b"<r/>");
+    }
+
+    #[test]
+    fn test_unknown_charset() {
+        assert_eq!(
+            convert_with_charset_hint(b"<r/>", "text/xml; charset=klingon"),
+            Err(EncodingError::UnknownCharset("klingon".to_string()))
+        );
+    }
+
     #[test]
     fn test_utf8_passthrough() {
         let utf8 = b"<root>hello</root>".to_vec();
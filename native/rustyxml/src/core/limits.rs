@@ -0,0 +1,82 @@
+//! Process-wide parser limits
+//!
+//! Limits are global rather than per call so every entry point that builds a
+//! tree enforces the same bounds. They are read with relaxed atomics on each
+//! parse, so changing a limit affects parses started afterwards.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Default maximum element nesting depth
+pub const DEFAULT_MAX_DEPTH: usize = 10_000;
+
+static MAX_DEPTH: AtomicUsize = AtomicUsize::new(DEFAULT_MAX_DEPTH);
+
+static MEMORY_LIMIT: AtomicUsize = AtomicUsize::new(0);
+
+/// Current maximum element nesting depth (0 = unlimited)
+#[inline]
+pub fn max_depth() -> usize {
+    MAX_DEPTH.load(Ordering::Relaxed)
+}
+
+/// Set the maximum element nesting depth (0 = unlimited)
+pub fn set_max_depth(depth: usize) {
+    MAX_DEPTH.store(depth, Ordering::Relaxed);
+}
+
+/// Check a document's nesting depth against the limit
+///
+/// Returns the limit as the error so callers can report it.
+#[inline]
+pub fn check_depth(depth: usize) -> Result<(), usize> {
+    match max_depth() {
+        0 => Ok(()),
+        limit if depth > limit => Err(limit),
+        _ => Ok(()),
+    }
+}
+
+/// Current limit on bytes allocated by the NIF (0 = unlimited)
+#[inline]
+pub fn memory_limit() -> usize {
+    MEMORY_LIMIT.load(Ordering::Relaxed)
+}
+
+/// Set the limit on bytes allocated by the NIF (0 = unlimited)
+pub fn set_memory_limit(bytes: usize) {
+    MEMORY_LIMIT.store(bytes, Ordering::Relaxed);
+}
+
+/// Check that `additional` bytes fit beside the `allocated` ones under the
+/// memory limit
+///
+/// Returns the limit as the error, like `check_depth`.
+#[inline]
+pub fn check_memory(allocated: usize, additional: usize) -> Result<(), usize> {
+    match memory_limit() {
+        0 => Ok(()),
+        limit if allocated.saturating_add(additional) > limit => Err(limit),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_depth() {
+        assert_eq!(check_depth(DEFAULT_MAX_DEPTH), Ok(()));
+        assert_eq!(check_depth(DEFAULT_MAX_DEPTH + 1), Err(DEFAULT_MAX_DEPTH));
+    }
+
+    #[test]
+    fn test_check_memory() {
+        assert_eq!(check_memory(usize::MAX, 1), Ok(()));
+        set_memory_limit(1000);
+        assert_eq!(check_memory(600, 400), Ok(()));
+        assert_eq!(check_memory(600, 401), Err(1000));
+        assert_eq!(check_memory(usize::MAX, 1), Err(1000));
+        set_memory_limit(0);
+    }
+}
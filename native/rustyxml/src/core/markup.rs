@@ -0,0 +1,177 @@
+//! Markup Normalization
+//!
+//! Rewrites tags into one spelling so documents that differ only in how
+//! they were serialized come out byte-identical, and can be compared or
+//! cached by hash:
+//!
+//! - attributes sorted by name, namespace declarations first
+//! - one space before each attribute and none around `=` or before `>`
+//! - values double-quoted; a `"` in a single-quoted value becomes `&quot;`
+//! - end tags without trailing whitespace
+//! - empty elements as `<a/>`, or `<a></a>` if preferred
+//!
+//! Text, comments, CDATA sections, PIs, the prolog and entity references
+//! in attribute values are copied unchanged. Input must be well-formed.
+
+use super::tokenizer::{TokenKind, Tokenizer};
+
+/// How `normalize_markup` writes elements without content
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EmptyElements {
+    /// `<a/>`
+    #[default]
+    SelfClosing,
+    /// `<a></a>`
+    Expanded,
+}
+
+/// Rewrite well-formed XML with normalized tags, or return the
+/// well-formedness error
+pub fn normalize_markup(input: &[u8], empty: EmptyElements) -> Result<Vec<u8>, String> {
+    crate::dom::validate_strict(input)?;
+
+    let mut output = Vec::with_capacity(input.len());
+    let mut tokenizer = Tokenizer::new_strict(input);
+    let mut copied = 0;
+    // Name of the last start tag, written without its `>` until we know
+    // whether its end tag follows immediately
+    let mut open: Option<&[u8]> = None;
+    while let Some(token) = tokenizer.next_token() {
+        let (start, end) = token.span;
+        if let Some(name) = open.take() {
+            if token.kind == TokenKind::EndTag && start == copied {
+                close_empty(&mut output, name, empty);
+                copied = end;
+                continue;
+            }
+            output.push(b'>');
+        }
+        if token.kind == TokenKind::Eof {
+            break;
+        }
+        output.extend_from_slice(&input[copied..start]);
+        copied = end;
+
+        let tag = &input[start..end];
+        match token.kind {
+            TokenKind::StartTag => open = Some(write_start_tag(&mut output, tag)),
+            TokenKind::EmptyTag => {
+                let name = write_start_tag(&mut output, tag);
+                close_empty(&mut output, name, empty);
+            }
+            TokenKind::EndTag => {
+                output.extend_from_slice(b"</");
+                output.extend_from_slice(tag_name(&tag[2..]));
+                output.push(b'>');
+            }
+            _ => output.extend_from_slice(tag),
+        }
+    }
+    output.extend_from_slice(&input[copied..]);
+    Ok(output)
+}
+
+fn close_empty(output: &mut Vec<u8>, name: &[u8], empty: EmptyElements) {
+    match empty {
+        EmptyElements::SelfClosing => output.extend_from_slice(b"/>"),
+        EmptyElements::Expanded => {
+            output.extend_from_slice(b"></");
+            output.extend_from_slice(name);
+            output.push(b'>');
+        }
+    }
+}
+
+/// Write `<name attrs` (without the closing `>` or `/>`), returning the name
+fn write_start_tag<'a>(output: &mut Vec<u8>, tag: &'a [u8]) -> &'a [u8] {
+    let name = tag_name(&tag[1..]);
+    let mut pos = 1 + name.len();
+    let mut attributes: Vec<(&[u8], &[u8], u8)> = Vec::new();
+    loop {
+        pos += skip_whitespace(&tag[pos..]);
+        if matches!(tag.get(pos), None | Some(b'/' | b'>')) {
+            break;
+        }
+        let attr_name = tag_name(&tag[pos..]);
+        pos += attr_name.len();
+        pos += skip_whitespace(&tag[pos..]) + 1; // `=`
+        pos += skip_whitespace(&tag[pos..]);
+        let quote = tag[pos];
+        let value_len = memchr::memchr(quote, &tag[pos + 1..]).unwrap_or(0);
+        attributes.push((attr_name, &tag[pos + 1..pos + 1 + value_len], quote));
+        pos += value_len + 2;
+    }
+    attributes.sort_by_key(|&(name, _, _)| (!is_namespace_declaration(name), name));
+
+    output.push(b'<');
+    output.extend_from_slice(name);
+    for (attr_name, value, quote) in attributes {
+        output.push(b' ');
+        output.extend_from_slice(attr_name);
+        output.extend_from_slice(b"=\"");
+        if quote == b'"' {
+            output.extend_from_slice(value);
+        } else {
+            let mut rest = value;
+            while let Some(i) = memchr::memchr(b'"', rest) {
+                output.extend_from_slice(&rest[..i]);
+                output.extend_from_slice(b"&quot;");
+                rest = &rest[i + 1..];
+            }
+            output.extend_from_slice(rest);
+        }
+        output.push(b'"');
+    }
+    name
+}
+
+/// The name at the start of `bytes`, up to whitespace, `=`, `/` or `>`
+fn tag_name(bytes: &[u8]) -> &[u8] {
+    let len = bytes
+        .iter()
+        .position(|&b| matches!(b, b' ' | b'\t' | b'\r' | b'\n' | b'=' | b'/' | b'>'))
+        .unwrap_or(bytes.len());
+    &bytes[..len]
+}
+
+fn skip_whitespace(bytes: &[u8]) -> usize {
+    bytes
+        .iter()
+        .take_while(|&&b| matches!(b, b' ' | b'\t' | b'\r' | b'\n'))
+        .count()
+}
+
+fn is_namespace_declaration(name: &[u8]) -> bool {
+    name == b"xmlns" || name.starts_with(b"xmlns:")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn normalize(xml: &str, empty: EmptyElements) -> String {
+        String::from_utf8(normalize_markup(xml.as_bytes(), empty).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_normalize_markup() {
+        let xml = "<?xml version='1.0'?>\n<!-- <b  z='1' a='2'/> -->\
+                   <r  z = '1'\n a=\"2\" xmlns:p='urn:p' xmlns=\"urn:d\" >\
+                   <e q='say \"hi\"' p:x=\"&amp;\" ></e ><f/><g> </g><![CDATA[<x  />]]></r >";
+        assert_eq!(
+            normalize(xml, EmptyElements::SelfClosing),
+            "<?xml version='1.0'?>\n<!-- <b  z='1' a='2'/> -->\
+             <r xmlns=\"urn:d\" xmlns:p=\"urn:p\" a=\"2\" z=\"1\">\
+             <e p:x=\"&amp;\" q=\"say &quot;hi&quot;\"/><f/><g> </g><![CDATA[<x  />]]></r>"
+        );
+        assert_eq!(
+            normalize("<r><e a='1'/><f></f></r>", EmptyElements::Expanded),
+            "<r><e a=\"1\"></e><f></f></r>"
+        );
+    }
+
+    #[test]
+    fn test_normalize_markup_rejects_malformed() {
+        assert!(normalize_markup(b"<r><e></r>", EmptyElements::SelfClosing).is_err());
+    }
+}
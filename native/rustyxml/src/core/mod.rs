@@ -2,18 +2,30 @@
 //!
 //! This module contains the fundamental building blocks for XML parsing:
 //! - Scanner: SIMD-accelerated delimiter detection using memchr
+//! - Classify: 64-byte block classification of structural characters
 //! - Tokenizer: State machine for XML token extraction
 //! - Entities: XML entity decoding with Cow (zero-copy when possible)
 //! - Attributes: Attribute parsing and extraction
 //! - Encoding: UTF-16 detection and conversion to UTF-8
 //! - Unicode: XML 1.0 Unicode character class validation
+//! - Limits: process-wide parser limits (maximum nesting depth)
+//! - Normalize: NFC/NFD normalization of returned text
+//! - Prolog: XML declaration and DOCTYPE fields before the root element
 //! - DTD: DTD declaration store and post-parse validation
+//! - Repair: best-effort rewriting of malformed input into well-formed XML
+//! - Markup: normalized tag spelling for deterministic output
 //! - UnifiedScanner: ScanHandler-based scanner for Index/SAX modes
 
 pub mod attributes;
+pub mod classify;
 pub mod dtd;
 pub mod encoding;
 pub mod entities;
+pub mod limits;
+pub mod markup;
+pub mod normalize;
+pub mod prolog;
+pub mod repair;
 pub mod scanner;
 pub mod tokenizer;
 pub mod unicode;
@@ -0,0 +1,106 @@
+//! Unicode normalization of text output
+//!
+//! Text and attribute values can be returned in NFC or NFD so documents
+//! from sources that mix normalization forms compare equal downstream.
+//! ASCII and already-normalized text is returned borrowed.
+
+use std::borrow::Cow;
+use unicode_normalization::{is_nfc_quick, is_nfd_quick, IsNormalized, UnicodeNormalization};
+
+/// Normalization form applied to returned text
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Normalization {
+    /// Text is returned as in the document
+    #[default]
+    None,
+    /// Canonical composition
+    Nfc,
+    /// Canonical decomposition
+    Nfd,
+}
+
+impl Normalization {
+    /// Normalize a string, borrowing it when it is already in this form
+    pub fn apply_str(self, text: &str) -> Cow<'_, str> {
+        match self {
+            Normalization::None => Cow::Borrowed(text),
+            _ if text.is_ascii() => Cow::Borrowed(text),
+            Normalization::Nfc => match is_nfc_quick(text.chars()) {
+                IsNormalized::Yes => Cow::Borrowed(text),
+                _ => Cow::Owned(text.nfc().collect()),
+            },
+            Normalization::Nfd => match is_nfd_quick(text.chars()) {
+                IsNormalized::Yes => Cow::Borrowed(text),
+                _ => Cow::Owned(text.nfd().collect()),
+            },
+        }
+    }
+
+    /// Normalize UTF-8 bytes; invalid UTF-8 is returned unchanged
+    pub fn apply<'a>(self, bytes: Cow<'a, [u8]>) -> Cow<'a, [u8]> {
+        if self == Normalization::None || bytes.is_ascii() {
+            return bytes;
+        }
+        let Ok(text) = std::str::from_utf8(&bytes) else {
+            return bytes;
+        };
+        match self.apply_str(text) {
+            Cow::Borrowed(_) => bytes,
+            Cow::Owned(normalized) => Cow::Owned(normalized.into_bytes()),
+        }
+    }
+
+    /// Compact encoding for storing in an atomic
+    pub fn to_u8(self) -> u8 {
+        match self {
+            Normalization::None => 0,
+            Normalization::Nfc => 1,
+            Normalization::Nfd => 2,
+        }
+    }
+
+    /// Inverse of `to_u8`
+    pub fn from_u8(value: u8) -> Self {
+        match value {
+            1 => Normalization::Nfc,
+            2 => Normalization::Nfd,
+            _ => Normalization::None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COMPOSED: &str = "caf\u{e9}";
+    const DECOMPOSED: &str = "cafe\u{301}";
+
+    #[test]
+    fn test_nfc_and_nfd() {
+        assert_eq!(Normalization::Nfc.apply_str(DECOMPOSED), COMPOSED);
+        assert_eq!(Normalization::Nfd.apply_str(COMPOSED), DECOMPOSED);
+        assert_eq!(Normalization::None.apply_str(DECOMPOSED), DECOMPOSED);
+
+        assert!(matches!(
+            Normalization::Nfc.apply_str(COMPOSED),
+            Cow::Borrowed(_)
+        ));
+        assert!(matches!(
+            Normalization::Nfd.apply_str("plain"),
+            Cow::Borrowed(_)
+        ));
+    }
+
+    #[test]
+    fn test_bytes() {
+        let bytes = Normalization::Nfc.apply(Cow::Borrowed(DECOMPOSED.as_bytes()));
+        assert_eq!(bytes.as_ref(), COMPOSED.as_bytes());
+
+        let invalid: &[u8] = b"\xff\xfe";
+        assert_eq!(
+            Normalization::Nfc.apply(Cow::Borrowed(invalid)).as_ref(),
+            invalid
+        );
+    }
+}
@@ -0,0 +1,199 @@
+//! Document Prolog Inspection
+//!
+//! Reads the XML declaration and DOCTYPE from the bytes before the root
+//! element, without tokenizing the rest of the document. This is a
+//! best-effort scan: malformed constructs end it early and leave the
+//! remaining fields unset rather than failing.
+
+/// Declaration fields found in the prolog
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Prolog<'a> {
+    /// `version` from the XML declaration
+    pub version: Option<&'a [u8]>,
+    /// `encoding` from the XML declaration, as written
+    pub encoding: Option<&'a [u8]>,
+    /// `standalone` from the XML declaration (`yes` → true)
+    pub standalone: Option<bool>,
+    /// The document type declaration, if present
+    pub doctype: Option<Doctype<'a>>,
+}
+
+/// Name and external identifiers of a DOCTYPE
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Doctype<'a> {
+    pub name: &'a [u8],
+    pub public_id: Option<&'a [u8]>,
+    pub system_id: Option<&'a [u8]>,
+}
+
+/// Scan the prolog at the start of `input`
+pub fn parse_prolog(input: &[u8]) -> Prolog<'_> {
+    let mut prolog = Prolog::default();
+    let mut pos = if input.starts_with(&[0xEF, 0xBB, 0xBF]) {
+        3
+    } else {
+        0
+    };
+
+    if let Some(rest) = input[pos..].strip_prefix(b"<?xml") {
+        if rest.first().is_some_and(|&b| is_ws(b)) {
+            let Some(end) = find(rest, b"?>") else {
+                return prolog;
+            };
+            read_declaration(&rest[..end], &mut prolog);
+            pos += 5 + end + 2;
+        }
+    }
+
+    // Comments and PIs may appear before the DOCTYPE
+    loop {
+        pos = skip_ws(input, pos);
+        let rest = &input[pos..];
+        if rest.starts_with(b"<!--") {
+            match find(&rest[4..], b"-->") {
+                Some(end) => pos += 4 + end + 3,
+                None => return prolog,
+            }
+        } else if rest.starts_with(b"<?") {
+            match find(&rest[2..], b"?>") {
+                Some(end) => pos += 2 + end + 2,
+                None => return prolog,
+            }
+        } else if rest.starts_with(b"<!DOCTYPE") {
+            prolog.doctype = read_doctype(input, pos + 9);
+            return prolog;
+        } else {
+            return prolog;
+        }
+    }
+}
+
+/// Read `name="value"` pairs from the body of an XML declaration
+fn read_declaration<'a>(mut body: &'a [u8], prolog: &mut Prolog<'a>) {
+    loop {
+        body = &body[skip_ws(body, 0)..];
+        let name_end = body
+            .iter()
+            .position(|&b| b == b'=' || is_ws(b))
+            .unwrap_or(body.len());
+        if name_end == 0 {
+            return;
+        }
+        let name = &body[..name_end];
+        let after = skip_ws(body, name_end);
+        if body.get(after) != Some(&b'=') {
+            return;
+        }
+        let value_start = skip_ws(body, after + 1);
+        let Some((value, next)) = quoted(body, value_start) else {
+            return;
+        };
+        match name {
+            b"version" => prolog.version = Some(value),
+            b"encoding" => prolog.encoding = Some(value),
+            b"standalone" => prolog.standalone = Some(value == b"yes"),
+            _ => {}
+        }
+        body = &body[next..];
+    }
+}
+
+/// Read the name and external ID of a DOCTYPE starting after `<!DOCTYPE`
+fn read_doctype(input: &[u8], start: usize) -> Option<Doctype<'_>> {
+    let name_start = skip_ws(input, start);
+    let name_end = input[name_start..]
+        .iter()
+        .position(|&b| is_ws(b) || b == b'[' || b == b'>')
+        .map_or(input.len(), |i| name_start + i);
+    if name_end == name_start {
+        return None;
+    }
+    let mut doctype = Doctype {
+        name: &input[name_start..name_end],
+        ..Doctype::default()
+    };
+
+    let pos = skip_ws(input, name_end);
+    let rest = &input[pos..];
+    if rest.starts_with(b"PUBLIC") {
+        if let Some((public_id, next)) = quoted(input, skip_ws(input, pos + 6)) {
+            doctype.public_id = Some(public_id);
+            doctype.system_id = quoted(input, skip_ws(input, next)).map(|(id, _)| id);
+        }
+    } else if rest.starts_with(b"SYSTEM") {
+        doctype.system_id = quoted(input, skip_ws(input, pos + 6)).map(|(id, _)| id);
+    }
+    Some(doctype)
+}
+
+/// Value of the quoted literal at `pos` and the position after its quote
+fn quoted(input: &[u8], pos: usize) -> Option<(&[u8], usize)> {
+    let quote = *input.get(pos).filter(|&&b| b == b'"' || b == b'\'')?;
+    let len = input[pos + 1..].iter().position(|&b| b == quote)?;
+    Some((&input[pos + 1..pos + 1 + len], pos + 1 + len + 1))
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    memchr::memmem::find(haystack, needle)
+}
+
+fn skip_ws(input: &[u8], mut pos: usize) -> usize {
+    while input.get(pos).is_some_and(|&b| is_ws(b)) {
+        pos += 1;
+    }
+    pos
+}
+
+#[inline]
+fn is_ws(b: u8) -> bool {
+    matches!(b, b' ' | b'\t' | b'\n' | b'\r')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_declaration_and_public_doctype() {
+        let input = br#"<?xml version="1.0" encoding='ISO-8859-1' standalone="yes"?>
+<!-- generated -->
+<!DOCTYPE html PUBLIC "-//W3C//DTD XHTML 1.0 Strict//EN"
+  "http://www.w3.org/TR/xhtml1/DTD/xhtml1-strict.dtd">
+<html/>"#;
+        let prolog = parse_prolog(input);
+        assert_eq!(prolog.version, Some(&b"1.0"[..]));
+        assert_eq!(prolog.encoding, Some(&b"ISO-8859-1"[..]));
+        assert_eq!(prolog.standalone, Some(true));
+        let doctype = prolog.doctype.unwrap();
+        assert_eq!(doctype.name, b"html");
+        assert_eq!(
+            doctype.public_id,
+            Some(&b"-//W3C//DTD XHTML 1.0 Strict//EN"[..])
+        );
+        assert_eq!(
+            doctype.system_id,
+            Some(&b"http://www.w3.org/TR/xhtml1/DTD/xhtml1-strict.dtd"[..])
+        );
+    }
+
+    #[test]
+    fn test_missing_or_partial_prolog() {
+        assert_eq!(parse_prolog(b"<root/>"), Prolog::default());
+
+        let prolog =
+            parse_prolog(b"<!DOCTYPE note SYSTEM \"note.dtd\" [<!ELEMENT note ANY>]><note/>");
+        assert_eq!(prolog.version, None);
+        let doctype = prolog.doctype.unwrap();
+        assert_eq!(doctype.name, b"note");
+        assert_eq!(doctype.public_id, None);
+        assert_eq!(doctype.system_id, Some(&b"note.dtd"[..]));
+
+        let prolog = parse_prolog(b"\xEF\xBB\xBF<?xml version=\"1.1\"?><!DOCTYPE r><r/>");
+        assert_eq!(prolog.version, Some(&b"1.1"[..]));
+        assert_eq!(prolog.standalone, None);
+        assert_eq!(prolog.doctype.unwrap().name, b"r");
+
+        // An unterminated declaration yields nothing rather than an error
+        assert_eq!(parse_prolog(b"<?xml version=\"1.0\""), Prolog::default());
+    }
+}
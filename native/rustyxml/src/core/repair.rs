@@ -0,0 +1,747 @@
+//! Best-Effort Repair of Malformed XML
+//!
+//! Rewrites input into well-formed XML, recording each change with its byte
+//! offset in the original input:
+//!
+//! - stray `&` and `<` in text and attribute values are escaped, as is
+//!   the `>` of a `]]>` in text
+//! - control characters XML 1.0 forbids are dropped, and invalid UTF-8 is
+//!   replaced with U+FFFD (`_` in names), in comments, CDATA sections,
+//!   PIs, DOCTYPEs and names as well as in text and attribute values
+//! - unquoted attribute values are quoted, valueless attributes get their
+//!   own name as value, and repeated attributes are dropped
+//! - end tags that match no open element are dropped, and elements left
+//!   open by an end tag for an outer one, or at end of input, are closed
+//! - comments, CDATA sections, PIs and tags cut off at end of input are
+//!   terminated
+//! - `--` inside a comment, and a `-` just before its end, are separated
+//!   with a space
+//! - an XML declaration anywhere but at the start of the input is dropped
+//!
+//! Well-formed input comes back unchanged. Named entity references other
+//! than the five predefined ones are kept only when the input has a DOCTYPE
+//! that may declare them.
+
+use super::tokenizer::{is_name_char, is_name_start_char};
+
+/// What a repair changed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RepairKind {
+    EscapedAmpersand,
+    EscapedLessThan,
+    EscapedGreaterThan,
+    DroppedControlCharacter,
+    ReplacedInvalidUtf8,
+    QuotedAttributeValue,
+    AddedAttributeValue,
+    DroppedDuplicateAttribute,
+    DroppedInvalidAttribute,
+    DroppedEndTag,
+    ClosedElement,
+    ClosedTag,
+    ClosedComment,
+    ClosedCdata,
+    ClosedProcessingInstruction,
+    SeparatedHyphens,
+    DroppedXmlDeclaration,
+    WrappedRoot,
+}
+
+/// One change, at a byte offset of the original input
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Repair {
+    pub kind: RepairKind,
+    pub offset: usize,
+}
+
+/// Repair `input`, wrapping the content in a `root` element when given and
+/// the input doesn't have exactly one top-level element
+pub fn repair(input: &[u8], root: Option<&str>) -> (Vec<u8>, Vec<Repair>) {
+    let mut repairer = Repairer {
+        input,
+        pos: 0,
+        out: Vec::with_capacity(input.len() + input.len() / 16),
+        repairs: Vec::new(),
+        open: Vec::new(),
+        has_doctype: false,
+        top_level_elements: 0,
+        top_level_text: false,
+        prolog_end: None,
+    };
+    repairer.run();
+
+    let Repairer {
+        mut out,
+        mut repairs,
+        top_level_elements,
+        top_level_text,
+        prolog_end,
+        ..
+    } = repairer;
+    if let Some(root) = root {
+        if top_level_elements != 1 || top_level_text {
+            let at = prolog_end.unwrap_or(out.len());
+            let mut wrapped = Vec::with_capacity(out.len() + 2 * root.len() + 5);
+            wrapped.extend_from_slice(&out[..at]);
+            wrapped.extend_from_slice(format!("<{root}>").as_bytes());
+            wrapped.extend_from_slice(&out[at..]);
+            wrapped.extend_from_slice(format!("</{root}>").as_bytes());
+            out = wrapped;
+            repairs.insert(
+                0,
+                Repair {
+                    kind: RepairKind::WrappedRoot,
+                    offset: 0,
+                },
+            );
+        }
+    }
+    (out, repairs)
+}
+
+struct Repairer<'a> {
+    input: &'a [u8],
+    pos: usize,
+    out: Vec<u8>,
+    repairs: Vec<Repair>,
+    /// Names of the open elements, outermost first
+    open: Vec<&'a [u8]>,
+    has_doctype: bool,
+    top_level_elements: usize,
+    /// Non-whitespace text outside any element
+    top_level_text: bool,
+    /// Output length when the first element or top-level text was written
+    prolog_end: Option<usize>,
+}
+
+impl<'a> Repairer<'a> {
+    fn run(&mut self) {
+        while self.pos < self.input.len() {
+            if self.input[self.pos] == b'<' {
+                self.markup();
+            } else {
+                self.text_char(None);
+            }
+        }
+        while let Some(name) = self.open.pop() {
+            self.note(RepairKind::ClosedElement, self.input.len());
+            self.write_end_tag(name);
+        }
+    }
+
+    fn note(&mut self, kind: RepairKind, offset: usize) {
+        self.repairs.push(Repair { kind, offset });
+    }
+
+    fn rest(&self) -> &'a [u8] {
+        &self.input[self.pos..]
+    }
+
+    /// Copy one character of content, repairing it if needed
+    ///
+    /// Inside an attribute value, `quote` is the delimiter being written,
+    /// which gets escaped too.
+    fn text_char(&mut self, quote: Option<u8>) {
+        let start = self.pos;
+        let b = self.input[start];
+        if quote.is_none() && !b.is_ascii_whitespace() {
+            self.top_level_content();
+        }
+        match b {
+            b'&' => {
+                if let Some(len) = reference_len(self.rest(), self.has_doctype) {
+                    self.out.extend_from_slice(&self.input[start..start + len]);
+                    self.pos += len;
+                    return;
+                }
+                self.note(RepairKind::EscapedAmpersand, start);
+                self.out.extend_from_slice(b"&amp;");
+            }
+            b'<' => {
+                self.note(RepairKind::EscapedLessThan, start);
+                self.out.extend_from_slice(b"&lt;");
+            }
+            // `]]>` may only end a CDATA section
+            b'>' if quote.is_none() && self.out.ends_with(b"]]") => {
+                self.note(RepairKind::EscapedGreaterThan, start);
+                self.out.extend_from_slice(b"&gt;");
+            }
+            b'"' if quote == Some(b'"') => self.out.extend_from_slice(b"&quot;"),
+            b'\'' if quote == Some(b'\'') => self.out.extend_from_slice(b"&apos;"),
+            _ => return self.copy_char(),
+        }
+        self.pos += 1;
+    }
+
+    /// Copy one character as it is, except that a control character XML
+    /// forbids is dropped and invalid UTF-8 is replaced with U+FFFD
+    fn copy_char(&mut self) {
+        let start = self.pos;
+        let b = self.input[start];
+        match b {
+            b'\t' | b'\n' | b'\r' => self.out.push(b),
+            0x00..=0x1F => self.note(RepairKind::DroppedControlCharacter, start),
+            0x80.. => {
+                let end = (start + utf8_width(b)).min(self.input.len());
+                if end > start && std::str::from_utf8(&self.input[start..end]).is_ok() {
+                    self.out.extend_from_slice(&self.input[start..end]);
+                    self.pos = end;
+                    return;
+                }
+                self.note(RepairKind::ReplacedInvalidUtf8, start);
+                self.out.extend_from_slice("\u{FFFD}".as_bytes());
+            }
+            _ => self.out.push(b),
+        }
+        self.pos += 1;
+    }
+
+    /// `copy_char` up to `end`, which must not split a valid character
+    fn copy_chars(&mut self, end: usize) {
+        while self.pos < end {
+            self.copy_char();
+        }
+    }
+
+    /// Write a name, replacing each invalid UTF-8 byte with `_`, as U+FFFD
+    /// can't appear in names; replacements are recorded when `offset` gives
+    /// the name's position in the input
+    fn write_name(&mut self, name: &[u8], offset: Option<usize>) {
+        let mut i = 0;
+        while i < name.len() {
+            let width = utf8_width(name[i]).max(1);
+            match name.get(i..i + width) {
+                Some(c) if std::str::from_utf8(c).is_ok() => {
+                    self.out.extend_from_slice(c);
+                    i += width;
+                }
+                _ => {
+                    if let Some(offset) = offset {
+                        self.note(RepairKind::ReplacedInvalidUtf8, offset + i);
+                    }
+                    self.out.push(b'_');
+                    i += 1;
+                }
+            }
+        }
+    }
+
+    /// Record content outside the root element
+    fn top_level_content(&mut self) {
+        if self.open.is_empty() {
+            self.top_level_text = true;
+            self.prolog_end.get_or_insert(self.out.len());
+        }
+    }
+
+    fn markup(&mut self) {
+        let rest = self.rest();
+        let starts_name = |i: usize| rest.get(i).is_some_and(|&b| is_name_start_char(b));
+        if rest.starts_with(b"<!--") {
+            self.comment();
+        } else if rest.starts_with(b"<![CDATA[") {
+            self.top_level_content();
+            self.copy_until(b"]]>", RepairKind::ClosedCdata);
+        } else if is_xml_declaration(rest) && !matches!(&self.out[..], b"" | b"\xEF\xBB\xBF") {
+            // Only the very start of a document may declare it
+            self.note(RepairKind::DroppedXmlDeclaration, self.pos);
+            self.pos += memchr::memmem::find(rest, b"?>").map_or(rest.len(), |i| i + 2);
+        } else if rest.starts_with(b"<?") {
+            self.copy_until(b"?>", RepairKind::ClosedProcessingInstruction);
+        } else if rest.starts_with(b"<!") {
+            self.declaration();
+        } else if rest.starts_with(b"</") && starts_name(2) {
+            self.end_tag();
+        } else if starts_name(1) {
+            self.start_tag();
+        } else {
+            self.text_char(None);
+        }
+    }
+
+    /// Copy through `terminator`, adding it if the input ends first
+    fn copy_until(&mut self, terminator: &[u8], kind: RepairKind) {
+        let rest = self.rest();
+        // Search past the opening `<?`/`<!` so `<?>` doesn't end itself
+        let skip = 2.min(rest.len());
+        match memchr::memmem::find(&rest[skip..], terminator) {
+            Some(i) => self.copy_chars(self.pos + skip + i + terminator.len()),
+            None => {
+                self.copy_chars(self.input.len());
+                self.out.extend_from_slice(terminator);
+                self.note(kind, self.input.len());
+            }
+        }
+    }
+
+    /// Copy a comment, adding `-->` if the input ends first
+    ///
+    /// A comment may not contain `--` or end in `-`, so a space goes
+    /// between such hyphens and after a final one.
+    fn comment(&mut self) {
+        let body_start = self.pos + 4;
+        let rest = &self.input[body_start..];
+        let terminator = memchr::memmem::find(rest, b"-->");
+        let body = &rest[..terminator.unwrap_or(rest.len())];
+
+        let body_end = body_start + body.len();
+
+        self.out.extend_from_slice(b"<!--");
+        let out_body = self.out.len();
+        // Hyphens are checked in the output, where dropped characters no
+        // longer separate them
+        let after_hyphen = |out: &[u8]| out.len() > out_body && out.ends_with(b"-");
+        self.pos = body_start;
+        while self.pos < body_end {
+            if self.input[self.pos] == b'-' && after_hyphen(&self.out) {
+                self.note(RepairKind::SeparatedHyphens, self.pos);
+                self.out.push(b' ');
+            }
+            self.copy_char();
+        }
+        if after_hyphen(&self.out) {
+            self.note(RepairKind::SeparatedHyphens, body_end);
+            self.out.push(b' ');
+        }
+        self.out.extend_from_slice(b"-->");
+        match terminator {
+            Some(i) => self.pos = body_start + i + 3,
+            None => {
+                self.pos = self.input.len();
+                self.note(RepairKind::ClosedComment, self.pos);
+            }
+        }
+    }
+
+    /// Copy a DOCTYPE (or other `<!` declaration) with its internal subset
+    fn declaration(&mut self) {
+        let rest = self.rest();
+        self.has_doctype |= rest.starts_with(b"<!DOCTYPE");
+        let mut depth = 0usize;
+        let mut quote = None;
+        for (i, &b) in rest.iter().enumerate() {
+            match (quote, b) {
+                (Some(q), _) if b == q => quote = None,
+                (Some(_), _) => {}
+                (None, b'"' | b'\'') => quote = Some(b),
+                (None, b'[') => depth += 1,
+                (None, b']') => depth = depth.saturating_sub(1),
+                (None, b'>') if depth == 0 => {
+                    self.copy_chars(self.pos + i + 1);
+                    return;
+                }
+                _ => {}
+            }
+        }
+        self.copy_chars(self.input.len());
+        self.out.push(b'>');
+        self.note(RepairKind::ClosedTag, self.input.len());
+    }
+
+    fn end_tag(&mut self) {
+        let start = self.pos;
+        let name = &self.input[start + 2..start + 2 + name_len(&self.input[start + 2..])];
+        let after_name = start + 2 + name.len();
+        self.pos = match memchr::memchr(b'>', &self.input[after_name..]) {
+            Some(i) => after_name + i + 1,
+            None => self.input.len(),
+        };
+
+        match self.open.iter().rposition(|&open| open == name) {
+            Some(i) => {
+                while self.open.len() > i + 1 {
+                    if let Some(inner) = self.open.pop() {
+                        self.note(RepairKind::ClosedElement, start);
+                        self.write_end_tag(inner);
+                    }
+                }
+                self.open.pop();
+                self.out.extend_from_slice(b"</");
+                self.write_name(name, Some(start + 2));
+                self.out.push(b'>');
+            }
+            None => self.note(RepairKind::DroppedEndTag, start),
+        }
+    }
+
+    /// Write an end tag the input lacks
+    fn write_end_tag(&mut self, name: &[u8]) {
+        self.out.extend_from_slice(b"</");
+        self.write_name(name, None);
+        self.out.push(b'>');
+    }
+
+    fn start_tag(&mut self) {
+        let start = self.pos;
+        let name = &self.input[start + 1..start + 1 + name_len(&self.input[start + 1..])];
+        if self.open.is_empty() {
+            self.top_level_elements += 1;
+            self.prolog_end.get_or_insert(self.out.len());
+        }
+        self.out.push(b'<');
+        self.write_name(name, Some(start + 1));
+        self.pos = start + 1 + name.len();
+
+        let mut seen: Vec<&[u8]> = Vec::new();
+        let empty = loop {
+            let Some(&b) = self.input.get(self.pos) else {
+                self.note(RepairKind::ClosedTag, self.input.len());
+                break false;
+            };
+            match b {
+                b'>' => {
+                    self.pos += 1;
+                    break false;
+                }
+                b'/' if self.input.get(self.pos + 1) == Some(&b'>') => {
+                    self.pos += 2;
+                    break true;
+                }
+                // The next tag starts before this one was closed
+                b'<' => {
+                    self.note(RepairKind::ClosedTag, self.pos);
+                    break false;
+                }
+                _ if b.is_ascii_whitespace() => {
+                    self.out.push(b);
+                    self.pos += 1;
+                }
+                _ if is_name_start_char(b) => self.attribute(&mut seen),
+                _ => {
+                    self.note(RepairKind::DroppedInvalidAttribute, self.pos);
+                    self.pos += 1;
+                }
+            }
+        };
+
+        if empty {
+            self.out.extend_from_slice(b"/>");
+        } else {
+            self.out.push(b'>');
+            self.open.push(name);
+        }
+    }
+
+    fn attribute(&mut self, seen: &mut Vec<&'a [u8]>) {
+        let name_start = self.pos;
+        let name = &self.input[name_start..name_start + name_len(self.rest())];
+        self.pos += name.len();
+
+        // A repeated attribute is still consumed, then its output discarded
+        let out_mark = self.out.len();
+        let repairs_mark = self.repairs.len();
+        if !self.out.last().is_some_and(u8::is_ascii_whitespace) {
+            self.out.push(b' ');
+        }
+        self.write_name(name, Some(name_start));
+        self.out.push(b'=');
+
+        let after_name = self.pos;
+        self.skip_whitespace();
+        if self.input.get(self.pos) == Some(&b'=') {
+            self.pos += 1;
+            self.skip_whitespace();
+            match self.input.get(self.pos) {
+                Some(&quote @ (b'"' | b'\'')) => {
+                    self.pos += 1;
+                    self.out.push(quote);
+                    while self.input.get(self.pos).is_some_and(|&b| b != quote) {
+                        self.text_char(Some(quote));
+                    }
+                    // An unterminated value runs to the end of input
+                    self.pos = (self.pos + 1).min(self.input.len());
+                    self.out.push(quote);
+                }
+                _ => {
+                    self.note(RepairKind::QuotedAttributeValue, self.pos);
+                    self.out.push(b'"');
+                    while let Some(&b) = self.input.get(self.pos) {
+                        let ends_tag = b == b'/' && self.input.get(self.pos + 1) == Some(&b'>');
+                        if b.is_ascii_whitespace() || b == b'>' || b == b'<' || ends_tag {
+                            break;
+                        }
+                        self.text_char(Some(b'"'));
+                    }
+                    self.out.push(b'"');
+                }
+            }
+        } else {
+            self.pos = after_name;
+            self.note(RepairKind::AddedAttributeValue, name_start);
+            self.out.push(b'"');
+            self.out.extend_from_slice(name);
+            self.out.push(b'"');
+        }
+
+        if seen.contains(&name) {
+            // Whitespace before the dropped attribute goes with it
+            self.out.truncate(out_mark);
+            while self.out.last().is_some_and(u8::is_ascii_whitespace) {
+                self.out.pop();
+            }
+            self.repairs.truncate(repairs_mark);
+            self.note(RepairKind::DroppedDuplicateAttribute, name_start);
+        } else {
+            seen.push(name);
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self
+            .input
+            .get(self.pos)
+            .is_some_and(u8::is_ascii_whitespace)
+        {
+            self.pos += 1;
+        }
+    }
+}
+
+/// Whether `bytes` starts with an XML declaration (a PI targeting `xml`)
+fn is_xml_declaration(bytes: &[u8]) -> bool {
+    bytes.starts_with(b"<?xml")
+        && bytes
+            .get(5)
+            .is_none_or(|&b| b.is_ascii_whitespace() || b == b'?')
+}
+
+/// Length of the name at the start of `bytes` (0 if there is none)
+fn name_len(bytes: &[u8]) -> usize {
+    match bytes.first() {
+        Some(&b) if is_name_start_char(b) => {
+            1 + bytes[1..].iter().take_while(|&&b| is_name_char(b)).count()
+        }
+        _ => 0,
+    }
+}
+
+/// Length of the reference at the start of `bytes` (which begins with
+/// `&`), if it is one to keep
+fn reference_len(bytes: &[u8], allow_declared: bool) -> Option<usize> {
+    let semi = bytes.iter().take(64).position(|&b| b == b';')?;
+    let valid = match &bytes[1..semi] {
+        b"amp" | b"lt" | b"gt" | b"quot" | b"apos" => true,
+        [b'#', b'x', hex @ ..] => char_ref_allowed(hex, 16),
+        [b'#', dec @ ..] => char_ref_allowed(dec, 10),
+        name => allow_declared && !name.is_empty() && name_len(name) == name.len(),
+    };
+    valid.then_some(semi + 1)
+}
+
+/// Whether a character reference's digits name a character XML allows
+fn char_ref_allowed(digits: &[u8], radix: u32) -> bool {
+    std::str::from_utf8(digits)
+        .ok()
+        .filter(|d| !d.starts_with('+'))
+        .and_then(|d| u32::from_str_radix(d, radix).ok())
+        .and_then(char::from_u32)
+        .is_some_and(|c| {
+            matches!(c, '\t' | '\n' | '\r') || (c >= ' ' && !matches!(c, '\u{FFFE}' | '\u{FFFF}'))
+        })
+}
+
+/// Byte length of the UTF-8 sequence a lead byte starts (0 if invalid)
+fn utf8_width(b: u8) -> usize {
+    match b {
+        0xC2..=0xDF => 2,
+        0xE0..=0xEF => 3,
+        0xF0..=0xF4 => 4,
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use RepairKind::*;
+
+    fn fixed(input: &str) -> (String, Vec<RepairKind>) {
+        let (out, repairs) = repair(input.as_bytes(), None);
+        let kinds = repairs.iter().map(|r| r.kind).collect();
+        (String::from_utf8(out).unwrap(), kinds)
+    }
+
+    #[test]
+    fn test_well_formed_input_is_unchanged() {
+        let input = "<?xml version=\"1.0\"?>\n<!DOCTYPE r [<!ENTITY x \"y\">]>\n\
+            <r a='1'\n   b=\"&amp;\"><!-- c --><![CDATA[<&>]]><e />&#x41;&lt;&x;</r>\n";
+        assert_eq!(fixed(input), (input.to_string(), vec![]));
+    }
+
+    #[test]
+    fn test_escapes_stray_characters() {
+        assert_eq!(
+            fixed("<r>AT&T < 3 &nbsp; &#0; &#65;</r>"),
+            (
+                "<r>AT&amp;T &lt; 3 &amp;nbsp; &amp;#0; &#65;</r>".to_string(),
+                vec![
+                    EscapedAmpersand,
+                    EscapedLessThan,
+                    EscapedAmpersand,
+                    EscapedAmpersand
+                ]
+            )
+        );
+        assert_eq!(
+            fixed("<r>a\u{1}b\u{0}</r>"),
+            (
+                "<r>ab</r>".to_string(),
+                vec![DroppedControlCharacter, DroppedControlCharacter]
+            )
+        );
+
+        let (out, repairs) = repair(b"<r>caf\xE9!</r>", None);
+        assert_eq!(out, "<r>caf\u{FFFD}!</r>".as_bytes());
+        assert_eq!(
+            repairs,
+            vec![Repair {
+                kind: ReplacedInvalidUtf8,
+                offset: 6
+            }]
+        );
+    }
+
+    #[test]
+    fn test_fixes_attributes() {
+        assert_eq!(
+            fixed(r#"<input type=text checked value="a<b" type="x&" size=3/>"#),
+            (
+                r#"<input type="text" checked="checked" value="a&lt;b" size="3"/>"#.to_string(),
+                vec![
+                    QuotedAttributeValue,
+                    AddedAttributeValue,
+                    EscapedLessThan,
+                    DroppedDuplicateAttribute,
+                    QuotedAttributeValue
+                ]
+            )
+        );
+        assert_eq!(
+            fixed(r#"<a href='say "hi"' x=it's>x</a>"#),
+            (
+                r#"<a href='say "hi"' x="it's">x</a>"#.to_string(),
+                vec![QuotedAttributeValue]
+            )
+        );
+    }
+
+    #[test]
+    fn test_balances_elements() {
+        assert_eq!(
+            fixed("<r><p><b>bold</p><i>x</j></r>"),
+            (
+                "<r><p><b>bold</b></p><i>x</i></r>".to_string(),
+                vec![ClosedElement, DroppedEndTag, ClosedElement]
+            )
+        );
+        assert_eq!(
+            fixed("<feed><entry><title>cut"),
+            (
+                "<feed><entry><title>cut</title></entry></feed>".to_string(),
+                vec![ClosedElement, ClosedElement, ClosedElement]
+            )
+        );
+        assert_eq!(
+            fixed("<r><a x='1'<b/></r>"),
+            (
+                "<r><a x='1'><b/></a></r>".to_string(),
+                vec![ClosedTag, ClosedElement]
+            )
+        );
+        assert_eq!(
+            fixed("<r><!-- unterminated"),
+            (
+                "<r><!-- unterminated--></r>".to_string(),
+                vec![ClosedComment, ClosedElement]
+            )
+        );
+    }
+
+    #[test]
+    fn test_output_is_well_formed() {
+        use crate::dom::validate_strict;
+
+        let cases = [
+            ("<a>x]]>y</a>", "<a>x]]&gt;y</a>", vec![EscapedGreaterThan]),
+            (
+                "<!-- a -- b ---><r/>",
+                "<!-- a - - b - --><r/>",
+                vec![SeparatedHyphens, SeparatedHyphens],
+            ),
+            (
+                "<r><!-- x-",
+                "<r><!-- x- --></r>",
+                vec![SeparatedHyphens, ClosedComment, ClosedElement],
+            ),
+            (
+                "<r><?xml version=\"1.0\"?><a/></r>",
+                "<r><a/></r>",
+                vec![DroppedXmlDeclaration],
+            ),
+            (
+                "<!-- c --><?xml version='1.0'?><?xml-stylesheet href='s'?><r/>",
+                "<!-- c --><?xml-stylesheet href='s'?><r/>",
+                vec![DroppedXmlDeclaration],
+            ),
+            (
+                "<r a=']]>'><![CDATA[]]>]]></r>",
+                "<r a=']]>'><![CDATA[]]>]]&gt;</r>",
+                vec![EscapedGreaterThan],
+            ),
+        ];
+        for (input, expected, kinds) in cases {
+            let (out, repairs) = fixed(input);
+            assert_eq!((out.as_str(), repairs), (expected, kinds), "{input}");
+            assert!(validate_strict(out.as_bytes()).is_ok(), "{out}");
+        }
+
+        // The declaration is only kept at the very start
+        let input = "\u{FEFF}<?xml version=\"1.0\"?><r/>";
+        assert_eq!(fixed(input), (input.to_string(), vec![]));
+    }
+
+    #[test]
+    fn test_repairs_characters_in_markup() {
+        use crate::dom::validate_strict;
+
+        let input =
+            b"<!-- a\x01-\x02- --><?pi \x03x?><r\xFF a\xC3=\"1\"><![CDATA[\x04\xE9]]></r\xFF>";
+        let (out, repairs) = repair(input, None);
+        assert_eq!(
+            String::from_utf8(out.clone()).unwrap(),
+            "<!-- a- - --><?pi x?><r_ a_=\"1\"><![CDATA[\u{FFFD}]]></r_>"
+        );
+        assert!(validate_strict(&out).is_ok());
+
+        let repairs: Vec<(RepairKind, usize)> =
+            repairs.iter().map(|r| (r.kind, r.offset)).collect();
+        assert_eq!(
+            repairs,
+            vec![
+                (DroppedControlCharacter, 6),
+                (DroppedControlCharacter, 8),
+                (SeparatedHyphens, 9),
+                (DroppedControlCharacter, 19),
+                (ReplacedInvalidUtf8, 25),
+                (ReplacedInvalidUtf8, 28),
+                (DroppedControlCharacter, 43),
+                (ReplacedInvalidUtf8, 44),
+                (ReplacedInvalidUtf8, 51),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_wraps_multiple_roots() {
+        let (out, repairs) = repair(b"<?xml version=\"1.0\"?>\n<a/><b/>", Some("root"));
+        assert_eq!(out, b"<?xml version=\"1.0\"?>\n<root><a/><b/></root>");
+        assert_eq!(repairs[0].kind, WrappedRoot);
+
+        let (out, repairs) = repair(b"<a/>\n", Some("root"));
+        assert_eq!(out, b"<a/>\n");
+        assert!(repairs.is_empty());
+
+        let (out, _) = repair(b"just text", Some("root"));
+        assert_eq!(out, b"<root>just text</root>");
+    }
+}
@@ -2006,17 +2006,24 @@ fn is_extender(cp: u32) -> bool {
     )
 }
 
-/// Check if a Unicode codepoint is a valid XML 1.0 NameStartChar (edition 4 rules)
-/// NameStartChar = Letter | '_' | ':'
+/// Check if a Unicode codepoint is a valid XML 1.0 NameStartChar
+/// NameStartChar = Letter | '_' | ':' (edition 4 rules unless 5E is selected)
 fn is_name_start_char_codepoint(cp: u32) -> bool {
+    if let Some(c) = fifth_edition_char(cp) {
+        return super::unicode::is_name_start_char_5e(c);
+    }
     cp == 0x5F || // _
     cp == 0x3A || // :
     is_letter_codepoint(cp)
 }
 
-/// Check if a Unicode codepoint is a valid XML 1.0 NameChar (edition 4 rules)
+/// Check if a Unicode codepoint is a valid XML 1.0 NameChar
 /// NameChar = Letter | Digit | '.' | '-' | '_' | ':' | CombiningChar | Extender
+/// (edition 4 rules unless 5E is selected)
 fn is_name_char_codepoint(cp: u32) -> bool {
+    if let Some(c) = fifth_edition_char(cp) {
+        return super::unicode::is_name_char_5e(c);
+    }
     is_letter_codepoint(cp) ||
     is_digit_codepoint(cp) ||
     cp == 0x2E || // .
@@ -2027,6 +2034,14 @@ fn is_name_char_codepoint(cp: u32) -> bool {
     is_extender(cp)
 }
 
+/// `cp` as a char when Fifth Edition name rules are selected
+fn fifth_edition_char(cp: u32) -> Option<char> {
+    match super::unicode::name_rules() {
+        super::unicode::NameRules::FifthEdition => Some(char::from_u32(cp).unwrap_or('\0')),
+        super::unicode::NameRules::FourthEdition => None,
+    }
+}
+
 /// Validate attribute value content
 /// Checks that & is followed by valid entity reference, and < is not present
 /// If entity registry is provided, also validates that entities are declared and not external
@@ -2,6 +2,37 @@
 //!
 //! Implements proper character class checking per XML 1.0 Fourth Edition specification.
 //! Uses range-based lookups for Unicode code points with precise character class definitions.
+//!
+//! Fifth Edition name rules, which allow far more characters in names, can
+//! be selected process-wide with `set_name_rules`.
+
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// Which edition of XML 1.0 decides the characters allowed in names
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NameRules {
+    /// Appendix B BaseChar/Ideographic tables (the default)
+    #[default]
+    FourthEdition,
+    /// The NameStartChar/NameChar ranges of XML 1.0 Fifth Edition
+    FifthEdition,
+}
+
+static NAME_RULES: AtomicU8 = AtomicU8::new(NameRules::FourthEdition as u8);
+
+/// Name rules used by strict validation
+#[inline]
+pub fn name_rules() -> NameRules {
+    match NAME_RULES.load(Ordering::Relaxed) {
+        1 => NameRules::FifthEdition,
+        _ => NameRules::FourthEdition,
+    }
+}
+
+/// Select the name rules for parses started from now on, in every process
+pub fn set_name_rules(rules: NameRules) {
+    NAME_RULES.store(rules as u8, Ordering::Relaxed);
+}
 
 /// Check if a Unicode code point is a valid XML 1.0 Letter (BaseChar or Ideographic)
 /// This is the core of NameStartChar per XML 1.0 Fourth Edition.
@@ -149,6 +180,28 @@ pub fn is_name_char(c: char) -> bool {
         || is_extender(c)
 }
 
+/// Check if a Unicode code point is a valid NameStartChar
+/// Per XML 1.0 Fifth Edition production [4]
+#[inline]
+pub fn is_name_start_char_5e(c: char) -> bool {
+    matches!(c as u32,
+        0x3A | 0x41..=0x5A | 0x5F | 0x61..=0x7A |
+        0xC0..=0xD6 | 0xD8..=0xF6 | 0xF8..=0x2FF | 0x370..=0x37D |
+        0x37F..=0x1FFF | 0x200C..=0x200D | 0x2070..=0x218F | 0x2C00..=0x2FEF |
+        0x3001..=0xD7FF | 0xF900..=0xFDCF | 0xFDF0..=0xFFFD | 0x10000..=0xEFFFF
+    )
+}
+
+/// Check if a Unicode code point is a valid NameChar
+/// Per XML 1.0 Fifth Edition production [4a]
+#[inline]
+pub fn is_name_char_5e(c: char) -> bool {
+    is_name_start_char_5e(c)
+        || matches!(c as u32,
+            0x2D | 0x2E | 0x30..=0x39 | 0xB7 | 0x300..=0x36F | 0x203F..=0x2040
+        )
+}
+
 /// Check if a Unicode code point is a valid XML Char
 /// Per XML 1.0: #x9 | #xA | #xD | [#x20-#xD7FF] | [#xE000-#xFFFD] | [#x10000-#x10FFFF]
 #[inline]
@@ -165,6 +218,19 @@ pub fn is_xml_char(c: char) -> bool {
 /// Validate that a byte slice contains a valid XML Name
 /// Returns Ok(()) if valid, Err with description if not
 pub fn validate_name(name: &[u8]) -> Result<(), &'static str> {
+    validate_name_with(name, name_rules())
+}
+
+/// A character class predicate
+type CharClass = fn(char) -> bool;
+
+/// Validate an XML Name under the given edition's rules
+pub fn validate_name_with(name: &[u8], rules: NameRules) -> Result<(), &'static str> {
+    let (start_char, name_char): (CharClass, CharClass) = match rules {
+        NameRules::FourthEdition => (is_name_start_char, is_name_char),
+        NameRules::FifthEdition => (is_name_start_char_5e, is_name_char_5e),
+    };
+
     if name.is_empty() {
         return Err("Name cannot be empty");
     }
@@ -176,14 +242,14 @@ pub fn validate_name(name: &[u8]) -> Result<(), &'static str> {
 
     // First character must be NameStartChar
     match chars.next() {
-        Some(c) if is_name_start_char(c) => {}
+        Some(c) if start_char(c) => {}
         Some(_) => return Err("Name must start with a valid NameStartChar"),
         None => return Err("Name cannot be empty"),
     }
 
     // Remaining characters must be NameChar
     for c in chars {
-        if !is_name_char(c) {
+        if !name_char(c) {
             return Err("Name contains invalid character");
         }
     }
@@ -320,6 +386,27 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_fifth_edition_names() {
+        // Legal only under Fifth Edition: Unicode 3.1+ letters and ranges
+        // the Fourth Edition tables leave out
+        for name in [
+            "\u{0D85}\u{0DCF}",
+            "\u{1E9E}x",
+            "a\u{203F}b",
+            "\u{4E00}\u{9FA6}",
+        ] {
+            assert!(validate_name_with(name.as_bytes(), NameRules::FourthEdition).is_err());
+            assert!(validate_name_with(name.as_bytes(), NameRules::FifthEdition).is_ok());
+        }
+
+        // Still rejected under Fifth Edition
+        for name in ["1abc", "-a", "\u{00D7}", "a\u{3000}"] {
+            assert!(validate_name_with(name.as_bytes(), NameRules::FifthEdition).is_err());
+        }
+        assert!(validate_name_with("élément".as_bytes(), NameRules::FifthEdition).is_ok());
+    }
+
     #[test]
     fn test_fast_path() {
         // ASCII should use fast path
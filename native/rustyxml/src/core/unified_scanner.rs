@@ -4,8 +4,10 @@
 //! - Index building (for DOM/XPath queries)
 //! - SAX event collection (for streaming APIs)
 //!
-//! The scanner uses the existing memchr-based Scanner for byte searching.
+//! The scanner uses the existing memchr-based Scanner for byte searching, and
+//! the block classifier to find the end of text runs and attribute values.
 
+use super::classify::{ClassifyBlock, Simd, StructuralClassifier};
 use super::scanner::Scanner;
 use crate::index::Span;
 
@@ -66,12 +68,22 @@ pub trait ScanHandler {
 
     /// Called for DOCTYPE (optional, default does nothing)
     fn doctype(&mut self, _content: Span) {}
+
+    /// Checked before each token; returning true ends the scan early
+    fn should_stop(&self) -> bool {
+        false
+    }
 }
 
 /// Unified scanner that uses ScanHandler for event dispatch
-pub struct UnifiedScanner<'a> {
+///
+/// Generic over the block classification backend; the default `Simd`
+/// backend is what every parse path uses.
+pub struct UnifiedScanner<'a, C: ClassifyBlock = Simd> {
     input: &'a [u8],
     scanner: Scanner<'a>,
+    /// 64-byte block classifier for text and attribute value scanning
+    classifier: StructuralClassifier<'a, C>,
     /// Reusable attribute buffer to avoid per-element allocations
     attrs_buf: Vec<(Span, Span)>,
 }
@@ -79,16 +91,24 @@ pub struct UnifiedScanner<'a> {
 impl<'a> UnifiedScanner<'a> {
     /// Create a new unified scanner for the input
     pub fn new(input: &'a [u8]) -> Self {
+        Self::with_backend(input)
+    }
+}
+
+impl<'a, C: ClassifyBlock> UnifiedScanner<'a, C> {
+    /// Create a scanner with an explicit classification backend (for benchmarks)
+    pub fn with_backend(input: &'a [u8]) -> Self {
         Self {
             input,
             scanner: Scanner::new(input),
+            classifier: StructuralClassifier::new(input),
             attrs_buf: Vec::with_capacity(8), // Most elements have < 8 attrs
         }
     }
 
     /// Scan the entire document, calling handler methods for each token
     pub fn scan<H: ScanHandler>(&mut self, handler: &mut H) {
-        while !self.scanner.is_eof() {
+        while !self.scanner.is_eof() && !handler.should_stop() {
             // Look for next interesting character
             match self.scanner.peek() {
                 Some(b'<') => {
@@ -232,13 +252,11 @@ impl<'a> UnifiedScanner<'a> {
 
         let value_start = self.scanner.position();
         // Find closing quote
-        while let Some(c) = self.scanner.peek() {
-            if c == quote {
-                break;
-            }
-            self.scanner.advance(1);
-        }
-        let value_end = self.scanner.position();
+        let value_end = self
+            .classifier
+            .find_quote(value_start, quote)
+            .unwrap_or(self.input.len());
+        self.scanner.set_position(value_end);
 
         if self.scanner.peek() == Some(quote) {
             self.scanner.advance(1);
@@ -284,22 +302,9 @@ impl<'a> UnifiedScanner<'a> {
     /// Scan text content
     fn scan_text<H: ScanHandler>(&mut self, handler: &mut H) {
         let start = self.scanner.position();
-        let mut needs_decode = false;
-
-        while let Some(c) = self.scanner.peek() {
-            match c {
-                b'<' => break,
-                b'&' => {
-                    needs_decode = true;
-                    self.scanner.advance(1);
-                }
-                _ => {
-                    self.scanner.advance(1);
-                }
-            }
-        }
+        let (end, needs_decode) = self.classifier.find_text_end(start);
+        self.scanner.set_position(end);
 
-        let end = self.scanner.position();
         if end > start {
             let span = Span::new(start as u32, (end - start).min(u16::MAX as usize) as u16);
             handler.text(span, needs_decode);
@@ -0,0 +1,109 @@
+//! Shared Document Cache
+//!
+//! Parsed documents keyed by the 128-bit xxh3 hash of their input, so
+//! processes that parse identical payloads get the same index instead of
+//! building one each. The cache lives in the NIF, outside any process, and
+//! evicts the least recently used document beyond its capacity. The hash
+//! is not collision resistant, so callers compare a hit's input with theirs.
+//!
+//! The lock is not held while parsing: two processes missing on the same
+//! input at once both parse it, and the later insert wins.
+
+use lru::LruCache;
+use std::num::NonZeroUsize;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+/// Documents kept until `set_capacity` is called
+pub const DEFAULT_CAPACITY: usize = 16;
+
+/// An LRU map from input hashes to documents
+pub struct DocumentCache<V> {
+    state: Mutex<CacheState<V>>,
+}
+
+struct CacheState<V> {
+    capacity: usize,
+    /// Created on first insert, dropped when the capacity is set to 0
+    entries: Option<LruCache<u128, V>>,
+}
+
+impl<V: Clone> DocumentCache<V> {
+    pub const fn new(capacity: usize) -> Self {
+        DocumentCache {
+            state: Mutex::new(CacheState {
+                capacity,
+                entries: None,
+            }),
+        }
+    }
+
+    /// Every update leaves the state consistent, so a panic while the lock
+    /// was held doesn't invalidate it
+    fn state(&self) -> MutexGuard<'_, CacheState<V>> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// The cached document for `key`, marking it most recently used
+    pub fn get(&self, key: u128) -> Option<V> {
+        self.state().entries.as_mut()?.get(&key).cloned()
+    }
+
+    /// Cache `value` under `key`, unless the cache is disabled
+    pub fn insert(&self, key: u128, value: V) {
+        let mut state = self.state();
+        let Some(capacity) = NonZeroUsize::new(state.capacity) else {
+            return;
+        };
+        state
+            .entries
+            .get_or_insert_with(|| LruCache::new(capacity))
+            .put(key, value);
+    }
+
+    /// Change how many documents are kept, evicting the least recently
+    /// used ones beyond it; 0 empties and disables the cache
+    pub fn set_capacity(&self, capacity: usize) {
+        let mut state = self.state();
+        state.capacity = capacity;
+        match NonZeroUsize::new(capacity) {
+            Some(capacity) => {
+                if let Some(entries) = state.entries.as_mut() {
+                    entries.resize(capacity);
+                }
+            }
+            None => state.entries = None,
+        }
+    }
+}
+
+/// Cache key for an input
+pub fn key(input: &[u8]) -> u128 {
+    xxhash_rust::xxh3::xxh3_128(input)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lru_eviction_and_capacity() {
+        let cache = DocumentCache::new(2);
+        let (a, b, c) = (key(b"<a/>"), key(b"<b/>"), key(b"<c/>"));
+        assert_ne!(a, b);
+
+        cache.insert(a, 1);
+        cache.insert(b, 2);
+        // Using `a` makes `b` the one to evict
+        assert_eq!(cache.get(a), Some(1));
+        cache.insert(c, 3);
+        assert_eq!(cache.get(b), None);
+        assert_eq!((cache.get(a), cache.get(c)), (Some(1), Some(3)));
+
+        cache.set_capacity(1);
+        assert_eq!((cache.get(a), cache.get(c)), (None, Some(3)));
+
+        cache.set_capacity(0);
+        cache.insert(a, 1);
+        assert_eq!((cache.get(a), cache.get(c)), (None, None));
+    }
+}
@@ -535,9 +535,21 @@ impl<'a> DocumentAccess for XmlDocument<'a> {
             .get_str_with_input(node.namespace_id, self.input)
     }
 
-    fn text_content(&self, id: NodeId) -> Option<&str> {
+    fn text_content(&self, id: NodeId) -> Option<Cow<'_, str>> {
         let node = self.get_node(id)?;
         if node.is_text() || node.kind == NodeKind::CData {
+            self.strings
+                .get_str_with_input(node.name_id, self.input)
+                .map(Cow::Borrowed)
+        } else {
+            None
+        }
+    }
+
+    fn node_data(&self, id: NodeId) -> Option<&str> {
+        // PI data is not kept in the DOM, only comment content
+        let node = self.get_node(id)?;
+        if node.kind == NodeKind::Comment {
             self.strings.get_str_with_input(node.name_id, self.input)
         } else {
             None
@@ -577,6 +589,15 @@ impl<'a> DocumentAccess for XmlDocument<'a> {
 // Validation (production code)
 // =============================================================================
 
+/// Optional checks on top of XML 1.0 well-formedness
+#[derive(Debug, Default, Clone, Copy)]
+pub struct StrictOptions {
+    /// Also enforce the Namespaces in XML 1.0 constraints: declared
+    /// prefixes, reserved prefixes and URIs, and attributes that are
+    /// duplicates once their prefixes are resolved
+    pub namespaces: bool,
+}
+
 /// Validate XML is well-formed without building a DOM
 ///
 /// Performs all strict mode validation (tag matching, duplicate attributes,
@@ -585,7 +606,14 @@ impl<'a> DocumentAccess for XmlDocument<'a> {
 /// `parse_strict` before building the structural index.
 #[must_use = "validation result should be checked"]
 pub fn validate_strict(input: &[u8]) -> Result<(), String> {
+    validate_strict_with(input, StrictOptions::default())
+}
+
+/// `validate_strict` with the given optional checks
+#[must_use = "validation result should be checked"]
+pub fn validate_strict_with(input: &[u8], options: StrictOptions) -> Result<(), String> {
     let mut reader = SliceReader::new_strict(input);
+    let mut scope = super::namespaces::NamespaceScope::default();
     let mut tag_stack: Vec<Vec<u8>> = vec![];
     let mut depth = 1usize; // 1 = document level (like stack starting with doc node)
     let mut root_element_count = 0u32;
@@ -632,6 +660,10 @@ pub fn validate_strict(input: &[u8]) -> Result<(), String> {
                 if let Some(dup) = find_duplicate_attribute(&elem.attributes) {
                     return Err(format!("Duplicate attribute: {}", dup));
                 }
+                if options.namespaces {
+                    scope.push_element(&elem.attributes);
+                    check_namespaces(&elem.name, &elem.attributes, &scope)?;
+                }
 
                 tag_stack.push(elem.name.as_ref().to_vec());
                 depth += 1;
@@ -652,6 +684,11 @@ pub fn validate_strict(input: &[u8]) -> Result<(), String> {
                 if let Some(dup) = find_duplicate_attribute(&elem.attributes) {
                     return Err(format!("Duplicate attribute: {}", dup));
                 }
+                if options.namespaces {
+                    scope.push_element(&elem.attributes);
+                    check_namespaces(&elem.name, &elem.attributes, &scope)?;
+                    scope.pop_element();
+                }
             }
 
             XmlEvent::EndElement(end_elem) => {
@@ -671,6 +708,7 @@ pub fn validate_strict(input: &[u8]) -> Result<(), String> {
                         end_str
                     ));
                 }
+                scope.pop_element();
                 depth -= 1;
             }
 
@@ -759,17 +797,22 @@ pub fn validate_strict(input: &[u8]) -> Result<(), String> {
 
 /// Find duplicate attribute name (for strict mode validation)
 fn find_duplicate_attribute(attrs: &[crate::core::attributes::Attribute<'_>]) -> Option<String> {
-    if attrs.len() < 2 {
-        return None;
-    }
-    for i in 0..attrs.len() {
-        for j in (i + 1)..attrs.len() {
-            if attrs[i].name.as_ref() == attrs[j].name.as_ref() {
-                return Some(String::from_utf8_lossy(attrs[i].name.as_ref()).to_string());
-            }
-        }
+    crate::core::attributes::find_duplicate(attrs)
+        .map(|attr| String::from_utf8_lossy(attr.name.as_ref()).to_string())
+}
+
+/// Report the first namespace constraint an element violates
+fn check_namespaces(
+    name: &[u8],
+    attrs: &[crate::core::attributes::Attribute<'_>],
+    scope: &super::namespaces::NamespaceScope,
+) -> Result<(), String> {
+    let mut errors = Vec::new();
+    super::namespaces::check_element(name, attrs, scope, &mut errors);
+    match errors.into_iter().next() {
+        Some(message) => Err(message),
+        None => Ok(()),
     }
-    None
 }
 
 /// Parse DTD declarations from DOCTYPE content for validation
@@ -0,0 +1,687 @@
+//! Incremental well-formedness checks for chunked input
+//!
+//! `validate_strict` needs the whole document. `IncrementalValidator` runs a
+//! subset of the same checks — tag balance, root element rules, character
+//! and name validity, attribute syntax — over each complete construct as
+//! chunks arrive, so a malformed document fails on the chunk that breaks it
+//! instead of after the last one. Errors use the same messages as
+//! `validate_strict`, which still runs over the full document at the end.
+//!
+//! A construct cut off by the end of the buffer (a tag, comment, text run)
+//! is left for the next call; `checked` only ever advances past complete
+//! constructs. The search for its end resumes where the previous call
+//! stopped (like `strategy::streaming::BoundaryScan`), so a construct
+//! arriving over many chunks is scanned once rather than once per chunk.
+//!
+//! `StreamingValidator` runs the same checks without keeping the document:
+//! the checked prefix of its buffer is dropped after each chunk, so memory
+//! is bounded by the largest single construct rather than the input.
+
+use crate::core::attributes::{find_duplicate, parse_attributes_strict};
+use crate::core::encoding::XmlEncoding;
+use crate::core::tokenizer::{
+    validate_comment, validate_name, validate_text_content, validate_xml_chars,
+};
+use memchr::{memchr_iter, memmem, memrchr};
+
+/// Check state carried between chunks
+#[derive(Debug, Default)]
+pub struct IncrementalValidator {
+    /// Bytes of the buffer already checked (always at a construct boundary)
+    checked: usize,
+    /// Bytes dropped from the front of the buffer by `discard_checked`
+    /// (never serialized; accumulators keep their whole buffer)
+    discarded: usize,
+    /// Names of open elements
+    tag_stack: Vec<Vec<u8>>,
+    seen_root_element: bool,
+    /// Input is not UTF-8; checks are left to the final validation
+    skip: bool,
+    /// First error found; sticky so later chunks report it again
+    error: Option<String>,
+    /// How far the search for the end of the construct at `checked` got
+    /// (not serialized: a restored validator searches it again)
+    pending: PendingScan,
+}
+
+/// The kinds of construct `check_from` searches for the end of
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum Construct {
+    #[default]
+    None,
+    Text,
+    Comment,
+    CData,
+    ProcessingInstruction,
+    Declaration,
+    Tag,
+}
+
+/// Progress of the search for the end of an incomplete construct
+#[derive(Debug, Default, Clone, Copy)]
+struct PendingScan {
+    construct: Construct,
+    /// Bytes of the construct searched so far
+    scanned: usize,
+    /// Open quote of an attribute value or literal
+    quote: Option<u8>,
+    /// Open `[` of a DOCTYPE internal subset
+    bracket_depth: usize,
+}
+
+impl PendingScan {
+    /// The state to resume a search for `construct` from: this one if it
+    /// was searching for the same kind, else a fresh one
+    fn resume(&mut self, construct: Construct) -> &mut Self {
+        if self.construct != construct {
+            *self = PendingScan {
+                construct,
+                ..Self::default()
+            };
+        }
+        self
+    }
+
+    /// Search `rest` for `terminator` from `min`, resuming past what was
+    /// searched before; the offset of the terminator, if found
+    fn find(
+        &mut self,
+        construct: Construct,
+        rest: &[u8],
+        min: usize,
+        terminator: &[u8],
+    ) -> Option<usize> {
+        let scan = self.resume(construct);
+        // Back up so a terminator split across chunks is still found
+        let from = scan.scanned.saturating_sub(terminator.len() - 1).max(min);
+        match memmem::find(rest.get(from..)?, terminator) {
+            Some(i) => Some(from + i),
+            None => {
+                scan.scanned = rest.len();
+                None
+            }
+        }
+    }
+}
+
+impl IncrementalValidator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The first error found, if any
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
+    /// Bytes of the buffer already checked
+    pub fn checked(&self) -> usize {
+        self.checked
+    }
+
+    /// Whether the input isn't UTF-8, so checks were skipped
+    pub fn skipped(&self) -> bool {
+        self.skip
+    }
+
+    /// Drop the checked part of `buffer`; the next `check` gets the rest of
+    /// the input appended to what is left
+    pub fn discard_checked(&mut self, buffer: &mut Vec<u8>) {
+        buffer.drain(..self.checked);
+        self.discarded += self.checked;
+        self.checked = 0;
+    }
+
+    /// Forget the checks past the first `len` bytes, which the caller has
+    /// dropped from the buffer; an error found there is kept
+    pub fn rewind(&mut self, len: usize) {
+        if self.checked > len {
+            self.checked = len;
+        }
+        self.pending = PendingScan::default();
+    }
+
+    /// Checks for the end of input: nothing left incomplete, every element
+    /// closed, and a root element seen
+    pub fn finish(&mut self, buffer: &[u8]) -> Result<(), String> {
+        self.check(buffer)?;
+        if self.skip {
+            return Ok(());
+        }
+        let rest = &buffer[self.checked.min(buffer.len())..];
+        let is_whitespace = rest
+            .iter()
+            .all(|&b| b == b' ' || b == b'\t' || b == b'\n' || b == b'\r');
+        let result = if rest.first() == Some(&b'<') {
+            Err("Unexpected end of input".to_string())
+        } else if !is_whitespace && self.tag_stack.is_empty() {
+            if self.seen_root_element {
+                Err("Content not allowed after root element".to_string())
+            } else {
+                Err("Text content not allowed before root element".to_string())
+            }
+        } else if let Some(unclosed) = self.tag_stack.first() {
+            Err(format!(
+                "Unclosed tag: <{}>",
+                String::from_utf8_lossy(unclosed)
+            ))
+        } else if !self.seen_root_element {
+            Err("Document has no root element".to_string())
+        } else {
+            Ok(())
+        };
+        if let Err(msg) = &result {
+            self.error = Some(msg.clone());
+        }
+        result
+    }
+
+    /// Append the check state to `out` (for accumulator snapshots)
+    ///
+    /// Layout, little-endian: checked (u64), flags (u8: bit 0 root seen,
+    /// bit 1 skip), error (u32 length, `u32::MAX` = none, then bytes), open
+    /// tags (u32 count, then u32 length + bytes each).
+    pub fn write_state(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&(self.checked as u64).to_le_bytes());
+        out.push(self.seen_root_element as u8 | (self.skip as u8) << 1);
+        match &self.error {
+            Some(err) => write_bytes(out, err.as_bytes()),
+            None => out.extend_from_slice(&u32::MAX.to_le_bytes()),
+        }
+        out.extend_from_slice(&(self.tag_stack.len() as u32).to_le_bytes());
+        for name in &self.tag_stack {
+            write_bytes(out, name);
+        }
+    }
+
+    /// Read state written by `write_state`, advancing `input` past it
+    pub fn read_state(input: &mut &[u8]) -> Option<Self> {
+        let checked = usize::try_from(read_u64(input)?).ok()?;
+        let flags = *take(input, 1)?.first()?;
+        let error = match read_u32(input)? {
+            u32::MAX => None,
+            len => Some(String::from_utf8(take(input, len as usize)?.to_vec()).ok()?),
+        };
+        let count = read_u32(input)? as usize;
+        let mut tag_stack = Vec::with_capacity(count.min(1024));
+        for _ in 0..count {
+            let len = read_u32(input)? as usize;
+            tag_stack.push(take(input, len)?.to_vec());
+        }
+        Some(Self {
+            checked,
+            discarded: 0,
+            tag_stack,
+            seen_root_element: flags & 1 != 0,
+            skip: flags & 2 != 0,
+            error,
+            pending: PendingScan::default(),
+        })
+    }
+
+    /// Check the complete constructs of `buffer` not checked yet
+    ///
+    /// `buffer` must be the whole accumulated input so far (previous calls
+    /// saw a prefix of it).
+    pub fn check(&mut self, buffer: &[u8]) -> Result<(), String> {
+        if let Some(err) = &self.error {
+            return Err(err.clone());
+        }
+        if self.checked == 0 && self.discarded == 0 && !self.skip {
+            if buffer.len() < 4 {
+                // Too short to tell the encoding or skip a BOM reliably
+                return Ok(());
+            }
+            if XmlEncoding::detect(buffer) != XmlEncoding::Utf8 {
+                self.skip = true;
+            } else if buffer.starts_with(&[0xEF, 0xBB, 0xBF]) {
+                self.checked = 3;
+            }
+        }
+        if self.skip {
+            return Ok(());
+        }
+
+        match self.check_from(buffer) {
+            Ok(()) => Ok(()),
+            Err(msg) => {
+                self.error = Some(msg.clone());
+                Err(msg)
+            }
+        }
+    }
+
+    fn check_from(&mut self, buffer: &[u8]) -> Result<(), String> {
+        while self.checked < buffer.len() {
+            let rest = &buffer[self.checked..];
+            let consumed = if rest[0] == b'<' {
+                self.check_markup(rest)?
+            } else {
+                self.check_text(rest)?
+            };
+            match consumed {
+                Some(len) => {
+                    self.checked += len;
+                    self.pending = PendingScan::default();
+                }
+                None => break,
+            }
+        }
+        Ok(())
+    }
+
+    /// Check a text run; None if it may continue in the next chunk
+    fn check_text(&mut self, rest: &[u8]) -> Result<Option<usize>, String> {
+        let Some(end) = self.pending.find(Construct::Text, rest, 0, b"<") else {
+            return Ok(None);
+        };
+        let text = &rest[..end];
+        validate_xml_chars(text)?;
+        validate_text_content(text)?;
+
+        if self.tag_stack.is_empty() {
+            if text.contains(&b'&') {
+                return Err("Entity/character references not allowed at document level".to_string());
+            }
+            let is_whitespace = text
+                .iter()
+                .all(|&b| b == b' ' || b == b'\t' || b == b'\n' || b == b'\r');
+            if !is_whitespace {
+                if self.seen_root_element {
+                    return Err("Content not allowed after root element".to_string());
+                }
+                return Err("Text content not allowed before root element".to_string());
+            }
+        }
+        Ok(Some(end))
+    }
+
+    /// Check one markup construct starting at `<`; None if incomplete
+    fn check_markup(&mut self, rest: &[u8]) -> Result<Option<usize>, String> {
+        // A prefix too short to match its real construct (`<!-` of a comment,
+        // `<![CD` of a CDATA section) contains no `>`, so it falls through to
+        // a branch that reports it incomplete.
+        if rest.starts_with(b"<!--") {
+            let Some(end) = self.pending.find(Construct::Comment, rest, 4, b"-->") else {
+                return Ok(None);
+            };
+            let content = &rest[4..end];
+            validate_comment(content)?;
+            validate_xml_chars(content)?;
+            return Ok(Some(end + 3));
+        }
+
+        if rest.starts_with(b"<![CDATA[") {
+            let Some(end) = self.pending.find(Construct::CData, rest, 9, b"]]>") else {
+                return Ok(None);
+            };
+            if self.tag_stack.is_empty() {
+                return Err("CDATA section not allowed at document level".to_string());
+            }
+            validate_xml_chars(&rest[9..end])?;
+            return Ok(Some(end + 3));
+        }
+
+        if rest.starts_with(b"<!") {
+            // DOCTYPE: the final validation parses it; just find its end
+            let scan = self.pending.resume(Construct::Declaration);
+            return Ok(find_declaration_end(rest, scan).map(|end| end + 1));
+        }
+
+        if rest.starts_with(b"<?") {
+            let pi = Construct::ProcessingInstruction;
+            let Some(end) = self.pending.find(pi, rest, 2, b"?>") else {
+                return Ok(None);
+            };
+            validate_xml_chars(&rest[2..end])?;
+            return Ok(Some(end + 2));
+        }
+
+        let Some(end) = find_tag_end(rest, self.pending.resume(Construct::Tag)) else {
+            return Ok(None);
+        };
+        let tag = &rest[1..end];
+        validate_xml_chars(tag)?;
+
+        if let Some(body) = tag.strip_prefix(b"/") {
+            let name = body.trim_ascii_end();
+            self.end_element(name)?;
+        } else {
+            let (body, self_closing) = match tag.strip_suffix(b"/") {
+                Some(body) => (body, true),
+                None => (tag, false),
+            };
+            let name_len = body
+                .iter()
+                .position(|b| b.is_ascii_whitespace())
+                .unwrap_or(body.len());
+            let (name, attrs) = body.split_at(name_len);
+            self.start_element(name, attrs, self_closing)?;
+        }
+        Ok(Some(end + 1))
+    }
+
+    fn start_element(
+        &mut self,
+        name: &[u8],
+        attrs: &[u8],
+        self_closing: bool,
+    ) -> Result<(), String> {
+        validate_name(name)?;
+        if self.tag_stack.is_empty() {
+            if self.seen_root_element {
+                return Err("Content not allowed after root element".to_string());
+            }
+            self.seen_root_element = true;
+        }
+
+        let attributes = parse_attributes_strict(attrs)?;
+        if let Some(attr) = find_duplicate(&attributes) {
+            let dup = String::from_utf8_lossy(attr.name.as_ref());
+            return Err(format!("Duplicate attribute: {}", dup));
+        }
+
+        if !self_closing {
+            self.tag_stack.push(name.to_vec());
+        }
+        Ok(())
+    }
+
+    fn end_element(&mut self, name: &[u8]) -> Result<(), String> {
+        match self.tag_stack.pop() {
+            Some(start_name) if start_name == name => Ok(()),
+            Some(start_name) => Err(format!(
+                "Tag mismatch: <{}> closed with </{}>",
+                String::from_utf8_lossy(&start_name),
+                String::from_utf8_lossy(name)
+            )),
+            None => Err(format!(
+                "Unexpected end tag: </{}> without matching start tag",
+                String::from_utf8_lossy(name)
+            )),
+        }
+    }
+}
+
+/// A well-formedness error with its position in the input
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PositionedError {
+    pub message: String,
+    /// Byte offset of the construct where the error was found
+    pub offset: usize,
+    /// 1-based line of `offset`
+    pub line: usize,
+    /// 1-based column of `offset`, in bytes
+    pub column: usize,
+}
+
+/// `IncrementalValidator` over a stream, keeping only the construct in
+/// progress between chunks
+#[derive(Debug, Default)]
+pub struct StreamingValidator {
+    buffer: Vec<u8>,
+    checks: IncrementalValidator,
+    /// Newlines in the discarded input
+    lines: usize,
+    /// Offset just past the last discarded newline
+    line_start: usize,
+    error: Option<PositionedError>,
+}
+
+impl StreamingValidator {
+    /// Check a chunk, returning the first error once the input so far is
+    /// malformed; the same error is returned for every later chunk
+    pub fn feed(&mut self, chunk: &[u8]) -> Result<(), PositionedError> {
+        if let Some(error) = &self.error {
+            return Err(error.clone());
+        }
+        self.buffer.extend_from_slice(chunk);
+        let result = self.checks.check(&self.buffer);
+        self.settle(result)?;
+
+        let checked = &self.buffer[..self.checks.checked()];
+        self.lines += memchr_iter(b'\n', checked).count();
+        if let Some(newline) = memrchr(b'\n', checked) {
+            self.line_start = self.checks.discarded + newline + 1;
+        }
+        self.checks.discard_checked(&mut self.buffer);
+        Ok(())
+    }
+
+    /// Apply the end-of-input checks
+    pub fn finish(&mut self) -> Result<(), PositionedError> {
+        if let Some(error) = &self.error {
+            return Err(error.clone());
+        }
+        let result = self.checks.finish(&self.buffer);
+        self.settle(result)
+    }
+
+    /// Drop the input and checks, as if nothing had been fed
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Record a failed check with its position; non-UTF-8 input, which
+    /// the checks skip, fails as it can't be validated this way
+    fn settle(&mut self, result: Result<(), String>) -> Result<(), PositionedError> {
+        let message = match result {
+            Err(message) => message,
+            Ok(()) if self.checks.skipped() => {
+                "Streaming validation supports UTF-8 input only".to_string()
+            }
+            Ok(()) => return Ok(()),
+        };
+        let before = &self.buffer[..self.checks.checked().min(self.buffer.len())];
+        let offset = self.checks.discarded + before.len();
+        let line_start = memrchr(b'\n', before).map_or(self.line_start, |newline| {
+            self.checks.discarded + newline + 1
+        });
+        let error = PositionedError {
+            message,
+            offset,
+            line: self.lines + memchr_iter(b'\n', before).count() + 1,
+            column: offset - line_start + 1,
+        };
+        self.error = Some(error.clone());
+        Err(error)
+    }
+}
+
+fn write_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    out.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+    out.extend_from_slice(bytes);
+}
+
+/// Split `n` bytes off the front of `input`
+pub(crate) fn take<'a>(input: &mut &'a [u8], n: usize) -> Option<&'a [u8]> {
+    if input.len() < n {
+        return None;
+    }
+    let (head, tail) = input.split_at(n);
+    *input = tail;
+    Some(head)
+}
+
+fn read_u32(input: &mut &[u8]) -> Option<u32> {
+    Some(u32::from_le_bytes(take(input, 4)?.try_into().ok()?))
+}
+
+pub(crate) fn read_u64(input: &mut &[u8]) -> Option<u64> {
+    Some(u64::from_le_bytes(take(input, 8)?.try_into().ok()?))
+}
+
+/// Position of the `>` closing a tag, skipping quoted attribute values;
+/// resumes from and updates `scan`
+fn find_tag_end(rest: &[u8], scan: &mut PendingScan) -> Option<usize> {
+    let from = scan.scanned.max(1);
+    for (i, &b) in rest.iter().enumerate().skip(from) {
+        match scan.quote {
+            Some(q) if b == q => scan.quote = None,
+            Some(_) => {}
+            None if b == b'"' || b == b'\'' => scan.quote = Some(b),
+            None if b == b'>' => return Some(i),
+            None => {}
+        }
+    }
+    scan.scanned = rest.len();
+    None
+}
+
+/// Position of the `>` closing a `<!...>` declaration, skipping quoted
+/// literals and an internal subset in `[...]`; resumes from and updates
+/// `scan`
+fn find_declaration_end(rest: &[u8], scan: &mut PendingScan) -> Option<usize> {
+    let from = scan.scanned.max(2);
+    for (i, &b) in rest.iter().enumerate().skip(from) {
+        match scan.quote {
+            Some(q) if b == q => scan.quote = None,
+            Some(_) => {}
+            None => match b {
+                b'"' | b'\'' => scan.quote = Some(b),
+                b'[' => scan.bracket_depth += 1,
+                b']' => scan.bracket_depth = scan.bracket_depth.saturating_sub(1),
+                b'>' if scan.bracket_depth == 0 => return Some(i),
+                _ => {}
+            },
+        }
+    }
+    scan.scanned = rest.len();
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feed `input` in chunks of `size` bytes, returning the first error
+    fn feed_in_chunks(input: &[u8], size: usize) -> Result<(), String> {
+        let mut validator = IncrementalValidator::new();
+        for end in (size..input.len()).step_by(size).chain([input.len()]) {
+            validator.check(&input[..end])?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_well_formed_document_passes_at_any_chunk_size() {
+        let input = br#"<?xml version="1.0"?>
+<!DOCTYPE root [<!ENTITY e "x">]>
+<!-- c --><root a="1" b='>'><![CDATA[<x>]]><child/>text &e;<?pi data?></root>
+"#;
+        for size in 1..input.len() {
+            assert_eq!(feed_in_chunks(input, size), Ok(()), "chunk size {size}");
+        }
+    }
+
+    #[test]
+    fn test_incomplete_constructs_resume_their_scan() {
+        let mut validator = IncrementalValidator::new();
+        let mut input = b"<root><!-- ".to_vec();
+        validator.check(&input).unwrap();
+        for _ in 0..100 {
+            input.extend_from_slice(b" -x");
+            validator.check(&input).unwrap();
+            // Only the tail is searched again: the scan kept its place
+            assert_eq!(validator.pending.scanned, input.len() - validator.checked());
+        }
+        input.extend_from_slice(b"-");
+        validator.check(&input).unwrap();
+        input.extend_from_slice(b"-><a b='>");
+        validator.check(&input).unwrap();
+        assert_eq!(validator.pending.quote, Some(b'\''));
+        input.extend_from_slice(b"'/></root>");
+        validator.check(&input).unwrap();
+        assert_eq!(validator.finish(&input), Ok(()));
+    }
+
+    #[test]
+    fn test_fails_on_the_chunk_that_breaks_nesting() {
+        let mut validator = IncrementalValidator::new();
+        assert!(validator.check(b"<root><a>").is_ok());
+        let err = validator.check(b"<root><a></b>").unwrap_err();
+        assert_eq!(err, "Tag mismatch: <a> closed with </b>");
+        // Sticky: later chunks report the same error
+        assert_eq!(validator.check(b"<root><a></b></a>").unwrap_err(), err);
+    }
+
+    #[test]
+    fn test_state_round_trip() {
+        let input = b"<root><a x='1'><b>te";
+        let mut validator = IncrementalValidator::new();
+        validator.check(input).unwrap();
+
+        let mut state = Vec::new();
+        validator.write_state(&mut state);
+        let mut reader = &state[..];
+        let mut restored = IncrementalValidator::read_state(&mut reader).unwrap();
+        assert!(reader.is_empty());
+        assert_eq!(restored.checked(), validator.checked());
+
+        let err = restored.check(b"<root><a x='1'><b>text</a>").unwrap_err();
+        assert_eq!(err, "Tag mismatch: <b> closed with </a>");
+        assert!(IncrementalValidator::read_state(&mut &state[..5]).is_none());
+    }
+
+    #[test]
+    fn test_streaming_validator_keeps_only_the_open_construct() {
+        let mut validator = StreamingValidator::default();
+        validator.feed(b"<root>\n").unwrap();
+        for _ in 0..1000 {
+            validator.feed(b"<item a='1'>text</item>\n").unwrap();
+        }
+        validator.feed(b"<item>unfin").unwrap();
+        assert!(validator.buffer.len() <= "<item>unfin".len());
+        validator.feed(b"ished</item></root>\n").unwrap();
+        assert_eq!(validator.finish(), Ok(()));
+    }
+
+    #[test]
+    fn test_streaming_validator_reports_position() {
+        let mut validator = StreamingValidator::default();
+        validator.feed(b"<root>\n  <a>\n").unwrap();
+        let error = validator.feed(b"  </b>\n</root>").unwrap_err();
+        assert_eq!(error.message, "Tag mismatch: <a> closed with </b>");
+        assert_eq!((error.offset, error.line, error.column), (15, 3, 3));
+        assert_eq!(validator.finish().unwrap_err(), error);
+    }
+
+    #[test]
+    fn test_streaming_validator_end_of_input() {
+        let cases: [(&[u8], &str); 4] = [
+            (b"<root><a/>", "Unclosed tag: <root>"),
+            (b"<root/><!-- c", "Unexpected end of input"),
+            (b"<root/> tail", "Content not allowed after root element"),
+            (b"<!-- only a comment -->", "Document has no root element"),
+        ];
+        for (input, expected) in cases {
+            let mut validator = StreamingValidator::default();
+            validator.feed(input).unwrap();
+            assert_eq!(validator.finish().unwrap_err().message, expected);
+        }
+
+        let mut validator = StreamingValidator::default();
+        validator.feed(b"\xFF\xFE<\0r\0/\0>\0").unwrap_err();
+    }
+
+    #[test]
+    fn test_reports_strict_mode_errors() {
+        let cases: [(&[u8], &str); 5] = [
+            (b"<a x='1' x='2'/>", "Duplicate attribute: x"),
+            (b"<a/><b/>", "Content not allowed after root element"),
+            (b"junk<a/>", "Text content not allowed before root element"),
+            (
+                b"</a>",
+                "Unexpected end tag: </a> without matching start tag",
+            ),
+            (
+                b"<a>\x01</a>",
+                "Invalid XML character: control character not allowed",
+            ),
+        ];
+        for (input, expected) in cases {
+            let mut validator = IncrementalValidator::new();
+            assert_eq!(validator.check(input).unwrap_err(), expected);
+        }
+    }
+}
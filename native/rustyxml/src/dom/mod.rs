@@ -5,18 +5,38 @@
 //! - `NodeId`, `NodeKind`: compact node representation
 //! - `XmlNode`, `XmlAttribute`: node types (used by trait interface)
 //! - `validate_strict`: well-formedness validation
+//! - `NamespaceScope`: namespace bindings for namespace-aware validation
+//! - `IncrementalValidator`: early well-formedness checks for chunked input
+//! - `StreamingValidator`: the same checks over a stream, in bounded memory
 //! - `XmlDocument`: full DOM (test-only, used by XPath unit tests)
 
 pub mod document;
+pub mod incremental;
+pub mod namespaces;
 pub mod node;
 pub mod strings;
 
-pub use document::validate_strict;
+pub use document::{validate_strict, validate_strict_with, StrictOptions};
+pub use incremental::{IncrementalValidator, PositionedError, StreamingValidator};
 pub use node::{NodeId, NodeKind, XmlNode};
 
 #[cfg(test)]
 pub use document::XmlDocument;
 
+use crate::core::normalize::Normalization;
+use std::borrow::Cow;
+
+/// Result of resolving a name against a document's interned name table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NameSymbol {
+    /// Document does not intern names - compare strings instead
+    Unavailable,
+    /// Name does not occur anywhere in the document
+    Absent,
+    /// Interned symbol id
+    Symbol(u32),
+}
+
 /// Trait for document access - enables XPath to work with DOM and Index-based documents
 pub trait DocumentAccess {
     /// Get root element ID
@@ -35,8 +55,15 @@ pub trait DocumentAccess {
     /// Get namespace URI of a node (None if unavailable or no namespace)
     fn node_namespace_uri(&self, id: NodeId) -> Option<&str>;
 
-    /// Get text content of a text node
-    fn text_content(&self, id: NodeId) -> Option<&str>;
+    /// Get text content of a text node; owned only when it had to be
+    /// decoded for this call
+    fn text_content(&self, id: NodeId) -> Option<Cow<'_, str>>;
+
+    /// Get the content of a comment, or the data of a processing
+    /// instruction (the text after its target)
+    fn node_data(&self, _id: NodeId) -> Option<&str> {
+        None
+    }
 
     /// Get attribute value by name
     fn get_attribute(&self, node_id: NodeId, name: &str) -> Option<&str>;
@@ -44,12 +71,58 @@ pub trait DocumentAccess {
     /// Get all attribute names and values
     fn get_attribute_values(&self, node_id: NodeId) -> Vec<(&str, &str)>;
 
+    /// Get attribute value by namespace URI and local name
+    ///
+    /// Prefixed attributes match when their prefix resolves to `uri` through
+    /// the `xmlns:*` declarations on the element or its ancestors; an empty
+    /// `uri` matches unprefixed attributes, which are in no namespace.
+    fn get_attribute_ns(&self, node_id: NodeId, uri: &str, local_name: &str) -> Option<&str> {
+        self.get_attribute_values(node_id)
+            .into_iter()
+            .find_map(|(name, value)| match name.split_once(':') {
+                None => (uri.is_empty() && name == local_name).then_some(value),
+                Some(("xmlns", _)) => None,
+                Some((prefix, local)) => (local == local_name
+                    && self.lookup_namespace_uri(node_id, prefix) == Some(uri))
+                .then_some(value),
+            })
+    }
+
+    /// Namespace URI bound to `prefix` in scope at an element
+    ///
+    /// Walks the element and its ancestors for an `xmlns:prefix`
+    /// declaration. `xml` is always bound; an undeclaration (`xmlns:p=""`)
+    /// leaves the prefix unbound.
+    fn lookup_namespace_uri(&self, node_id: NodeId, prefix: &str) -> Option<&str> {
+        if prefix == "xml" {
+            return Some("http://www.w3.org/XML/1998/namespace");
+        }
+        let declaration = format!("xmlns:{}", prefix);
+        let mut current = Some(node_id);
+        while let Some(id) = current.filter(|&id| self.node_kind_of(id) == NodeKind::Element) {
+            if let Some(uri) = self.get_attribute(id, &declaration) {
+                return Some(uri).filter(|uri| !uri.is_empty());
+            }
+            current = self.parent_of(id);
+        }
+        None
+    }
+
     /// Iterate over children - returns collected Vec for trait object compatibility
     fn children_vec(&self, id: NodeId) -> Vec<NodeId>;
 
     /// Iterate over descendants - returns collected Vec for trait object compatibility
     fn descendants_vec(&self, id: NodeId) -> Vec<NodeId>;
 
+    /// Visit descendants in document order until `visit` returns true, and
+    /// report whether it did
+    ///
+    /// The default walks `descendants_vec`; views that can iterate lazily
+    /// stop without collecting the rest.
+    fn any_descendant(&self, id: NodeId, visit: &mut dyn FnMut(NodeId) -> bool) -> bool {
+        self.descendants_vec(id).into_iter().any(visit)
+    }
+
     // === Navigation methods for XPath axes ===
     // These work with both DOM and Index-based views
 
@@ -80,40 +153,105 @@ pub trait DocumentAccess {
     fn document_node_id(&self) -> NodeId {
         0
     }
+
+    /// Sort key placing nodes in document order
+    /// Default implementation is the id itself (DOM ids are assigned in parse order)
+    fn document_order_key(&self, id: NodeId) -> u64 {
+        id as u64
+    }
+
+    /// Get the document node of a preloaded document, for `document()`
+    /// Only multi-document views have any; single documents return None.
+    fn document_by_name(&self, _name: &str) -> Option<NodeId> {
+        None
+    }
+
+    // === Interned names (optional fast path for node tests) ===
+
+    /// Resolve a qualified or local name to its interned symbol
+    fn name_symbol(&self, _name: &str) -> NameSymbol {
+        NameSymbol::Unavailable
+    }
+
+    /// Get the interned symbol of an element's local name
+    fn node_local_name_symbol(&self, _id: NodeId) -> Option<u32> {
+        None
+    }
+
+    /// Get attribute value by interned name symbol
+    fn get_attribute_by_symbol(&self, _node_id: NodeId, _symbol: u32) -> Option<&str> {
+        None
+    }
+
+    // === Attribute value indexes (optional fast path for predicates) ===
+
+    /// Elements in document order whose attribute `name` equals `value`, or
+    /// that have the attribute at all when `value` is None
+    /// Returns None when no index was built for `name`.
+    fn attribute_index_lookup(&self, _name: &str, _value: Option<&str>) -> Option<&[NodeId]> {
+        None
+    }
+
+    /// Unicode normalization applied to node string-values
+    fn text_normalization(&self) -> Normalization {
+        Normalization::None
+    }
+
+    /// String-value of an element computed ahead of time, if available
+    fn precomputed_string_value(&self, _node_id: NodeId) -> Option<&str> {
+        None
+    }
 }
 
 /// Get the XPath string-value of a node per XPath 1.0 spec.
 ///
 /// - For text/CDATA nodes: returns the text content
 /// - For elements: concatenation of all descendant text nodes
+/// - For comments and PIs: their content (PI data excludes the target)
 /// - For other node types: empty string
 pub fn node_string_value<D: DocumentAccess>(doc: &D, node_id: NodeId) -> String {
     let kind = doc.node_kind_of(node_id);
 
-    match kind {
-        NodeKind::Text | NodeKind::CData => doc.text_content(node_id).unwrap_or("").to_string(),
-        NodeKind::Element => {
-            let mut result = String::new();
-            collect_descendant_text(doc, node_id, &mut result);
-            result
+    let value = match kind {
+        NodeKind::Text | NodeKind::CData => doc
+            .text_content(node_id)
+            .map(Cow::into_owned)
+            .unwrap_or_default(),
+        NodeKind::Comment | NodeKind::ProcessingInstruction => {
+            doc.node_data(node_id).unwrap_or("").to_string()
         }
+        NodeKind::Element => match doc.precomputed_string_value(node_id) {
+            Some(text) => text.to_string(),
+            None => {
+                let mut result = String::new();
+                collect_descendant_text(doc, node_id, &mut result);
+                result
+            }
+        },
         _ => String::new(),
+    };
+    match doc.text_normalization().apply_str(&value) {
+        Cow::Borrowed(_) => value,
+        Cow::Owned(normalized) => normalized,
     }
 }
 
-/// Recursively collect text content from all descendant text nodes.
+/// Collect text content from all descendant text nodes, in document order.
+/// Uses an explicit stack so deeply nested documents can't overflow the
+/// native stack.
 fn collect_descendant_text<D: DocumentAccess>(doc: &D, node_id: NodeId, result: &mut String) {
-    for child_id in doc.children_vec(node_id) {
-        let kind = doc.node_kind_of(child_id);
+    let mut stack: Vec<NodeId> = doc.children_vec(node_id);
+    stack.reverse();
 
-        match kind {
+    while let Some(child_id) = stack.pop() {
+        match doc.node_kind_of(child_id) {
             NodeKind::Text | NodeKind::CData => {
                 if let Some(text) = doc.text_content(child_id) {
-                    result.push_str(text);
+                    result.push_str(&text);
                 }
             }
             NodeKind::Element => {
-                collect_descendant_text(doc, child_id, result);
+                stack.extend(doc.children_vec(child_id).into_iter().rev());
             }
             _ => {}
         }
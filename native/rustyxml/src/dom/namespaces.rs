@@ -0,0 +1,384 @@
+//! Namespace Well-Formedness
+//!
+//! Bindings declared by `xmlns` attributes, scoped to the element that
+//! declares them, and the Namespaces in XML 1.0 constraints checked
+//! against them: by strict validation with `StrictOptions::namespaces`
+//! (first error only), and by `validate_namespaces`, which reports every
+//! violation with the path of the element it occurs on.
+
+use crate::core::attributes::Attribute;
+use crate::reader::events::XmlEvent;
+use crate::reader::slice::SliceReader;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+
+/// URI the `xml` prefix is bound to by definition
+pub const XML_NAMESPACE: &[u8] = b"http://www.w3.org/XML/1998/namespace";
+
+/// URI of the `xmlns` prefix, which may never be declared
+pub const XMLNS_NAMESPACE: &[u8] = b"http://www.w3.org/2000/xmlns/";
+
+/// Namespace bindings in scope at the current element
+#[derive(Default)]
+pub struct NamespaceScope {
+    /// (prefix, uri) in declaration order; the default namespace has an
+    /// empty prefix
+    bindings: Vec<(Vec<u8>, Vec<u8>)>,
+    /// Length of `bindings` before each open element's declarations
+    marks: Vec<usize>,
+}
+
+impl NamespaceScope {
+    /// Enter an element, adding the bindings its attributes declare
+    pub fn push_element(&mut self, attrs: &[Attribute<'_>]) {
+        self.marks.push(self.bindings.len());
+        for attr in attrs {
+            let name = attr.name.as_ref();
+            let prefix = if name == b"xmlns" {
+                &[][..]
+            } else if let Some(prefix) = name.strip_prefix(b"xmlns:") {
+                prefix
+            } else {
+                continue;
+            };
+            self.bindings
+                .push((prefix.to_vec(), attr.value.as_ref().to_vec()));
+        }
+    }
+
+    /// Leave the innermost element, dropping its bindings
+    pub fn pop_element(&mut self) {
+        if let Some(mark) = self.marks.pop() {
+            self.bindings.truncate(mark);
+        }
+    }
+
+    /// URI bound to `prefix` (empty for the default namespace), if any
+    ///
+    /// An undeclaration (`xmlns=""`) resolves to the empty URI.
+    pub fn resolve(&self, prefix: &[u8]) -> Option<&[u8]> {
+        if prefix == b"xml" {
+            return Some(XML_NAMESPACE);
+        }
+        self.bindings
+            .iter()
+            .rev()
+            .find(|(bound, _)| bound == prefix)
+            .map(|(_, uri)| uri.as_slice())
+    }
+}
+
+/// Find two attributes with the same local name whose different prefixes
+/// are bound to the same namespace, such as `a:x` and `b:x` when `a` and
+/// `b` both name one URI
+///
+/// Literal duplicates are left to `find_duplicate_attribute`; namespace
+/// declarations and unbound prefixes are not compared.
+pub fn find_duplicate_expanded_attribute(
+    attrs: &[Attribute<'_>],
+    scope: &NamespaceScope,
+) -> Option<String> {
+    let expanded = |attr: &Attribute<'_>| {
+        let prefix = attr.prefix.as_deref()?;
+        if prefix == b"xmlns" {
+            return None;
+        }
+        scope.resolve(prefix).filter(|uri| !uri.is_empty())
+    };
+
+    // Index of each expanded name's first occurrence. Any two different
+    // names sharing an expansion means one differs from that first one, so
+    // the earliest first occurrence seen again under another name, with the
+    // earliest such repeat, is the pair a pairwise search would report.
+    let mut first: HashMap<(&[u8], &[u8]), usize> = HashMap::new();
+    let mut found: Option<(usize, usize)> = None;
+    for (i, attr) in attrs.iter().enumerate() {
+        let Some(uri) = expanded(attr) else {
+            continue;
+        };
+        match first.entry((uri, attr.local_name.as_ref())) {
+            Entry::Occupied(seen) => {
+                let f = *seen.get();
+                if attrs[f].name != attr.name && found.is_none_or(|(e, _)| f < e) {
+                    found = Some((f, i));
+                }
+            }
+            Entry::Vacant(slot) => {
+                slot.insert(i);
+            }
+        }
+    }
+
+    let (first, second) = found.map(|(f, s)| (&attrs[f], &attrs[s]))?;
+    Some(format!(
+        "{} and {} (both {{{}}}{})",
+        String::from_utf8_lossy(&first.name),
+        String::from_utf8_lossy(&second.name),
+        String::from_utf8_lossy(expanded(first).unwrap_or_default()),
+        String::from_utf8_lossy(&first.local_name),
+    ))
+}
+
+/// Check one start tag against the namespace constraints, after its own
+/// declarations have been pushed onto `scope`
+///
+/// Pushes a message for each violation: undeclared prefixes on the element
+/// or its attributes, misuse of the reserved `xml` and `xmlns` prefixes and
+/// URIs, prefixed undeclarations (`xmlns:a=""`, allowed only in XML 1.1),
+/// and attributes duplicated through different prefixes.
+pub fn check_element(
+    name: &[u8],
+    attrs: &[Attribute<'_>],
+    scope: &NamespaceScope,
+    errors: &mut Vec<String>,
+) {
+    let lossy = |bytes: &[u8]| String::from_utf8_lossy(bytes).into_owned();
+
+    match name.iter().position(|&b| b == b':').map(|i| &name[..i]) {
+        Some(b"xmlns") => errors.push(format!(
+            "Element <{}> uses the reserved prefix xmlns",
+            lossy(name)
+        )),
+        Some(prefix) if scope.resolve(prefix).is_none_or(<[u8]>::is_empty) => errors.push(format!(
+            "Undeclared namespace prefix '{}' on element <{}>",
+            lossy(prefix),
+            lossy(name)
+        )),
+        _ => {}
+    }
+
+    for attr in attrs {
+        let attr_name = attr.name.as_ref();
+        let value = attr.value.as_ref();
+        if attr_name == b"xmlns" {
+            if value == XML_NAMESPACE || value == XMLNS_NAMESPACE {
+                errors.push(format!(
+                    "The reserved namespace {} cannot be the default namespace",
+                    lossy(value)
+                ));
+            }
+            continue;
+        }
+        match attr.prefix.as_deref() {
+            Some(b"xmlns") => {
+                let declared = attr.local_name.as_ref();
+                if declared == b"xmlns" {
+                    errors.push("The xmlns prefix must not be declared".to_string());
+                } else if declared == b"xml" {
+                    if value != XML_NAMESPACE {
+                        errors.push(format!(
+                            "The xml prefix can only be bound to {}",
+                            lossy(XML_NAMESPACE)
+                        ));
+                    }
+                } else if value.is_empty() {
+                    errors.push(format!(
+                        "Namespace prefix '{}' cannot be undeclared (xmlns:{}=\"\")",
+                        lossy(declared),
+                        lossy(declared)
+                    ));
+                } else if value == XML_NAMESPACE || value == XMLNS_NAMESPACE {
+                    errors.push(format!(
+                        "Prefix '{}' cannot be bound to the reserved namespace {}",
+                        lossy(declared),
+                        lossy(value)
+                    ));
+                }
+            }
+            Some(prefix) if scope.resolve(prefix).is_none_or(<[u8]>::is_empty) => {
+                errors.push(format!(
+                    "Undeclared namespace prefix '{}' on attribute {}",
+                    lossy(prefix),
+                    lossy(attr_name)
+                ));
+            }
+            _ => {}
+        }
+    }
+
+    if let Some(dup) = find_duplicate_expanded_attribute(attrs, scope) {
+        errors.push(format!("Duplicate attribute: {}", dup));
+    }
+}
+
+/// A namespace constraint violation and where it occurs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NamespaceError {
+    /// Path of the offending element, e.g. `/feed[1]/entry[3]`
+    pub path: String,
+    pub message: String,
+}
+
+/// Check every element of a document against the namespace constraints
+///
+/// Paths give each element's position among same-named siblings. The input
+/// is expected to be well-formed; the first error the strict reader reports
+/// is included and ends the pass.
+pub fn validate_namespaces(input: &[u8]) -> Vec<NamespaceError> {
+    let mut reader = SliceReader::new_strict(input);
+    let mut scope = NamespaceScope::default();
+    let mut errors = Vec::new();
+    let mut messages = Vec::new();
+    // Path segments of the open elements, and the names seen among the
+    // children of each (the document level first) with their counts
+    let mut path: Vec<String> = Vec::new();
+    let mut siblings: Vec<Vec<(Vec<u8>, usize)>> = vec![Vec::new()];
+
+    while let Some(event) = reader.next_event() {
+        let (elem, empty) = match event {
+            XmlEvent::StartElement(elem) => (elem, false),
+            XmlEvent::EmptyElement(elem) => (elem, true),
+            XmlEvent::EndElement(_) => {
+                scope.pop_element();
+                path.pop();
+                siblings.pop();
+                continue;
+            }
+            _ => {
+                if reader.error().is_some() {
+                    break;
+                }
+                continue;
+            }
+        };
+
+        let name = elem.name.as_ref();
+        let counts = siblings.last_mut().expect("document level is never popped");
+        let position = match counts.iter_mut().find(|(seen, _)| seen == name) {
+            Some((_, count)) => {
+                *count += 1;
+                *count
+            }
+            None => {
+                counts.push((name.to_vec(), 1));
+                1
+            }
+        };
+        path.push(format!("{}[{}]", String::from_utf8_lossy(name), position));
+
+        scope.push_element(&elem.attributes);
+        check_element(name, &elem.attributes, &scope, &mut messages);
+        let element_path = format!("/{}", path.join("/"));
+        errors.extend(messages.drain(..).map(|message| NamespaceError {
+            path: element_path.clone(),
+            message,
+        }));
+
+        if empty {
+            scope.pop_element();
+            path.pop();
+        } else {
+            siblings.push(Vec::new());
+        }
+        if reader.error().is_some() {
+            break;
+        }
+    }
+
+    if let Some(err) = reader.error() {
+        errors.push(NamespaceError {
+            path: format!("/{}", path.join("/")),
+            message: err.message.clone(),
+        });
+    }
+    errors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::attributes::parse_attributes;
+
+    #[test]
+    fn test_scope_and_expanded_duplicates() {
+        let mut scope = NamespaceScope::default();
+        let outer = parse_attributes(br#" xmlns:a="urn:x" xmlns="urn:d""#);
+        scope.push_element(&outer);
+        assert_eq!(scope.resolve(b"a"), Some(&b"urn:x"[..]));
+        assert_eq!(scope.resolve(b""), Some(&b"urn:d"[..]));
+        assert_eq!(scope.resolve(b"xml"), Some(XML_NAMESPACE));
+
+        let inner = parse_attributes(br#" xmlns:b="urn:x" a:id="1" b:id="2""#);
+        scope.push_element(&inner);
+        let dup = find_duplicate_expanded_attribute(&inner, &scope).unwrap();
+        assert_eq!(dup, "a:id and b:id (both {urn:x}id)");
+
+        // Literal repeats don't count; the earliest clashing pair is reported
+        let attrs = parse_attributes(br#" a:n="1" a:id="1" a:id="2" a:n="3" b:n="4" b:id="5""#);
+        let dup = find_duplicate_expanded_attribute(&attrs, &scope).unwrap();
+        assert_eq!(dup, "a:n and b:n (both {urn:x}n)");
+
+        // Different URIs, unbound prefixes and xmlns declarations are fine
+        let attrs = parse_attributes(br#" xmlns:c="urn:y" a:id="1" c:id="2" z:id="3""#);
+        scope.push_element(&attrs);
+        assert_eq!(find_duplicate_expanded_attribute(&attrs, &scope), None);
+        scope.pop_element();
+
+        scope.pop_element();
+        assert_eq!(scope.resolve(b"b"), None);
+        scope.pop_element();
+        assert_eq!(scope.resolve(b"a"), None);
+    }
+
+    #[test]
+    fn test_strict_validation_with_namespaces() {
+        use crate::dom::{validate_strict, validate_strict_with, StrictOptions};
+
+        let options = StrictOptions { namespaces: true };
+        let xml = br#"<r xmlns:a="urn:x" xmlns:b="urn:x"><e a:id="1" b:id="2"/></r>"#;
+        assert!(validate_strict(xml).is_ok());
+        let err = validate_strict_with(xml, options).unwrap_err();
+        assert!(
+            err.starts_with("Duplicate attribute: a:id and b:id"),
+            "{err}"
+        );
+        let err = validate_strict_with(b"<a:r/>", options).unwrap_err();
+        assert_eq!(err, "Undeclared namespace prefix 'a' on element <a:r>");
+
+        // Bindings end with the element that declares them
+        let xml = br#"<r xmlns:b="urn:x"><e xmlns:a="urn:x"/><f a:id="1" b:id="2"/></r>"#;
+        let err = validate_strict_with(xml, options).unwrap_err();
+        assert_eq!(err, "Undeclared namespace prefix 'a' on attribute a:id");
+    }
+
+    #[test]
+    fn test_validate_namespaces_reports_paths() {
+        let xml = br#"<feed xmlns:a="urn:a" xml:lang="en">
+  <entry a:id="1"/>
+  <entry xmlns:xml="urn:wrong" b:id="2"/>
+  <x:entry xmlns:xmlns="urn:n" xmlns:c=""><a:title/></x:entry>
+</feed>"#;
+        let errors = validate_namespaces(xml);
+        let found: Vec<(&str, &str)> = errors
+            .iter()
+            .map(|e| (e.path.as_str(), e.message.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (
+                    "/feed[1]/entry[2]",
+                    "The xml prefix can only be bound to http://www.w3.org/XML/1998/namespace"
+                ),
+                (
+                    "/feed[1]/entry[2]",
+                    "Undeclared namespace prefix 'b' on attribute b:id"
+                ),
+                (
+                    "/feed[1]/x:entry[1]",
+                    "Undeclared namespace prefix 'x' on element <x:entry>"
+                ),
+                (
+                    "/feed[1]/x:entry[1]",
+                    "The xmlns prefix must not be declared"
+                ),
+                (
+                    "/feed[1]/x:entry[1]",
+                    "Namespace prefix 'c' cannot be undeclared (xmlns:c=\"\")"
+                ),
+            ]
+        );
+
+        assert!(validate_namespaces(br#"<r xmlns="urn:d"><a xmlns=""/></r>"#).is_empty());
+    }
+}
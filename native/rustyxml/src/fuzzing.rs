@@ -0,0 +1,120 @@
+//! Fuzzing Entry Points
+//!
+//! The work behind `parse`, `parse_strict`, `streaming_feed_sax` and the
+//! XPath NIFs, without a BEAM environment, for the cargo-fuzz targets in
+//! `fuzz/`. Built only with the `fuzzing` feature. Each function runs the
+//! same code as its NIF up to term conversion and discards the result; the
+//! fuzzer is looking for panics, hangs and out-of-bounds reads.
+//!
+//! ```text
+//! cd native/rustyxml
+//! cargo +nightly fuzz run parse_strict
+//! ```
+
+use crate::core::attributes::parse_attributes;
+use crate::core::encoding::{convert_to_utf8, convert_utf16_lossy, XmlEncoding};
+use crate::core::entities::decode_text;
+use crate::core::tokenizer::{TokenKind, Tokenizer};
+use crate::dom::{self, DocumentAccess};
+use crate::index::builder::build_index;
+use crate::index::IndexedDocumentView;
+use crate::strategy::streaming::find_safe_boundary;
+use crate::xpath::{evaluate, XPathValue};
+
+/// Lenient parse, then the string-value of the whole document
+pub fn parse(input: &[u8]) {
+    let converted = convert_utf16_lossy(input);
+    let bytes = match &converted {
+        Some(bytes) => bytes.as_slice(),
+        None => &input[XmlEncoding::Utf8.bom_len(input)..],
+    };
+    let index = build_index(bytes);
+    let view = IndexedDocumentView::new(&index, bytes);
+    let _ = dom::node_string_value(&view, view.document_node_id());
+}
+
+/// Strict parse: encoding conversion, validation, then indexing
+pub fn parse_strict(input: &[u8]) {
+    let Ok(bytes) = convert_to_utf8(input.to_vec()) else {
+        return;
+    };
+    let bytes = bytes.strip_prefix(&[0xEF, 0xBB, 0xBF]).unwrap_or(&bytes);
+    if dom::validate_strict(bytes).is_ok() {
+        let _ = build_index(bytes);
+    }
+}
+
+/// Feed `input` to the streaming SAX tokenizer in chunks, the first byte
+/// choosing the chunk size, then finalize
+pub fn streaming_sax(input: &[u8]) {
+    let Some((&size, input)) = input.split_first() else {
+        return;
+    };
+    let mut buffer = Vec::new();
+    for chunk in input.chunks(usize::from(size).max(1)) {
+        buffer.extend_from_slice(chunk);
+        let boundary = find_safe_boundary(&buffer);
+        tokenize(&buffer[..boundary]);
+        buffer.drain(..boundary);
+    }
+    tokenize(&buffer);
+}
+
+fn tokenize(input: &[u8]) {
+    let mut tokenizer = Tokenizer::new(input);
+    while let Some(token) = tokenizer.next_token() {
+        match token.kind {
+            TokenKind::Eof => break,
+            TokenKind::StartTag | TokenKind::EmptyTag => {
+                let (start, end) = token.span;
+                if start < end && end <= input.len() {
+                    let _ = parse_attributes(&input[start..end]);
+                }
+            }
+            TokenKind::Text => {
+                if let Some(content) = token.content {
+                    let _ = decode_text(content.as_ref());
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Evaluate the query before the first NUL byte over the document after
+/// it, taking the string-value of every node selected
+pub fn xpath(input: &[u8]) {
+    let Some(split) = input.iter().position(|&b| b == 0) else {
+        return;
+    };
+    let query = String::from_utf8_lossy(&input[..split]);
+    let xml = &input[split + 1..];
+    let index = build_index(xml);
+    let view = IndexedDocumentView::new(&index, xml);
+    if let Ok(XPathValue::NodeSet(nodes)) = evaluate(&view, &query) {
+        for node in nodes {
+            let _ = dom::node_string_value(&view, node);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entry_points_run_seeds() {
+        let seeds: &[&[u8]] = &[
+            b"<r a='1'>t&amp;<![CDATA[c]]><x/></r>",
+            b"\xFF\xFE<\0r\0/\0>\0",
+            b"<r><unclosed attr=\"",
+            b"//x[1]/text()\0<r><x>1</x></r>",
+        ];
+        for seed in seeds {
+            parse(seed);
+            parse_strict(seed);
+            streaming_sax(seed);
+            xpath(seed);
+        }
+    }
+}
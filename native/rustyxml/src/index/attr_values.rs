@@ -0,0 +1,88 @@
+//! Attribute Value Index
+//!
+//! Maps the values of one attribute name to the elements carrying them, so
+//! `//item[@sku = 'X']` and `//item[@sku]` over the whole document become a
+//! hash lookup instead of a descendant scan. Built on demand per document.
+
+// Allow unused API methods - these are public for library consumers
+#![allow(dead_code)]
+
+use super::structural::StructuralIndex;
+use super::view::encode_element_id;
+use super::{map_bytes, vec_bytes};
+use crate::dom::node::NodeId;
+use std::collections::HashMap;
+
+/// Elements carrying one attribute, grouped by the attribute's value
+///
+/// Node lists are in document order. Values are compared as stored in the
+/// input, the same way the `[@name = 'literal']` predicate compares them.
+#[derive(Debug, Default)]
+pub struct AttributeValueIndex {
+    by_value: HashMap<String, Vec<NodeId>>,
+    with_attribute: Vec<NodeId>,
+}
+
+impl AttributeValueIndex {
+    /// Bytes allocated by the index
+    pub fn heap_bytes(&self) -> usize {
+        let values = self.by_value.iter();
+        map_bytes(&self.by_value)
+            + values
+                .map(|(value, ids)| value.capacity() + vec_bytes(ids))
+                .sum::<usize>()
+            + vec_bytes(&self.with_attribute)
+    }
+
+    /// Index the attribute `name` of every element in the document
+    pub fn build(index: &StructuralIndex, input: &[u8], name: &str) -> Self {
+        let mut by_value: HashMap<String, Vec<NodeId>> = HashMap::new();
+        let mut with_attribute = Vec::new();
+        for idx in 0..index.element_count() as u32 {
+            if let Some(value) = index.get_attribute(idx, name, input) {
+                let id = encode_element_id(idx);
+                with_attribute.push(id);
+                by_value.entry(value.to_string()).or_default().push(id);
+            }
+        }
+        Self {
+            by_value,
+            with_attribute,
+        }
+    }
+
+    /// Elements whose attribute equals `value`
+    pub fn lookup(&self, value: &str) -> &[NodeId] {
+        self.by_value.get(value).map_or(&[], Vec::as_slice)
+    }
+
+    /// Elements that have the attribute at all
+    pub fn with_attribute(&self) -> &[NodeId] {
+        &self.with_attribute
+    }
+
+    /// Number of distinct values
+    pub fn distinct_values(&self) -> usize {
+        self.by_value.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::builder::build_index;
+
+    #[test]
+    fn test_lookup_by_value() {
+        let input = b"<catalog><item sku=\"a\"/><item/><group><item sku=\"b\"/><item sku=\"a\"/></group></catalog>";
+        let index = build_index(input);
+        let attrs = AttributeValueIndex::build(&index, input, "sku");
+
+        assert_eq!(attrs.distinct_values(), 2);
+        assert_eq!(attrs.with_attribute().len(), 3);
+        assert_eq!(attrs.lookup("a").len(), 2);
+        assert!(attrs.lookup("a")[0] < attrs.lookup("a")[1]);
+        assert_eq!(attrs.lookup("b").len(), 1);
+        assert!(attrs.lookup("missing").is_empty());
+    }
+}
@@ -19,13 +19,14 @@ use crate::core::unified_scanner::ScanHandler;
 pub struct IndexBuilder<'a> {
     /// The index being built
     index: StructuralIndex,
-    /// Original input bytes (unused but kept for potential future use)
-    #[allow(dead_code)]
+    /// Original input bytes (used to intern names)
     input: &'a [u8],
     /// Stack of open element indices
     stack: Vec<u32>,
     /// Previous sibling element at each depth (for linking siblings)
     prev_sibling_at_depth: Vec<Option<u32>>,
+    /// When building started (for `stats`)
+    started: std::time::Instant,
 }
 
 impl<'a> IndexBuilder<'a> {
@@ -48,6 +49,7 @@ impl<'a> IndexBuilder<'a> {
             input,
             stack: Vec::with_capacity(32),
             prev_sibling_at_depth: Vec::with_capacity(32),
+            started: std::time::Instant::now(),
         }
     }
 
@@ -60,6 +62,7 @@ impl<'a> IndexBuilder<'a> {
         // Estimates are based on input size heuristics and often over-allocate
         // by 2-3x. This reclaims significant memory for long-lived documents.
         self.index.shrink_to_fit();
+        crate::stats::record_index_build(self.input.len(), self.started.elapsed());
 
         // Debug output for structural index sizing (disabled by default)
         // Enable by setting RUSTYXML_DEBUG_INDEX=1 environment variable
@@ -111,8 +114,8 @@ impl<'a> IndexBuilder<'a> {
 
     /// Get the current depth (number of open elements)
     #[inline]
-    fn current_depth(&self) -> u16 {
-        self.stack.len() as u16
+    fn current_depth(&self) -> usize {
+        self.stack.len()
     }
 
     /// Get the current parent element index
@@ -125,9 +128,12 @@ impl<'a> IndexBuilder<'a> {
     pub fn start_element(&mut self, name: Span, attrs: &[(Span, Span)], is_empty: bool) {
         let depth = self.current_depth();
         let parent = self.current_parent();
+        self.index.max_depth = self.index.max_depth.max(depth as u32 + 1);
 
-        // Create the element
-        let mut elem = IndexElement::new(name, parent, depth);
+        // Create the element (the stored depth saturates; sibling links below
+        // use the exact depth)
+        let mut elem = IndexElement::new(name, parent, depth.min(u16::MAX as usize) as u16);
+        self.index.names.push_element(name, self.input);
 
         // Add attributes
         if !attrs.is_empty() {
@@ -137,6 +143,7 @@ impl<'a> IndexBuilder<'a> {
             for (attr_name, attr_value) in attrs {
                 self.index
                     .add_attribute(IndexAttribute::new(*attr_name, *attr_value));
+                self.index.names.push_attribute(*attr_name, self.input);
             }
         }
 
@@ -153,8 +160,8 @@ impl<'a> IndexBuilder<'a> {
         }
 
         // Link siblings at this depth
-        self.ensure_depth(depth as usize);
-        if let Some(prev_idx) = self.prev_sibling_at_depth[depth as usize] {
+        self.ensure_depth(depth);
+        if let Some(prev_idx) = self.prev_sibling_at_depth[depth] {
             self.index.link_sibling(prev_idx, elem_idx);
         }
 
@@ -168,10 +175,10 @@ impl<'a> IndexBuilder<'a> {
         }
 
         // Track this as the previous sibling at this depth
-        self.prev_sibling_at_depth[depth as usize] = Some(elem_idx);
+        self.prev_sibling_at_depth[depth] = Some(elem_idx);
 
         // Clear previous siblings at deeper levels
-        for d in (depth as usize + 1)..self.prev_sibling_at_depth.len() {
+        for d in (depth + 1)..self.prev_sibling_at_depth.len() {
             self.prev_sibling_at_depth[d] = None;
         }
 
@@ -199,16 +206,11 @@ impl<'a> IndexBuilder<'a> {
                 self.index.set_last_child(parent, elem_idx);
             }
 
-            // Update last_child to actual last child element
-            if let Some(elem) = self.index.get_element(elem_idx) {
-                let depth = elem.depth;
-                if let Some(last_elem) = self
-                    .prev_sibling_at_depth
-                    .get(depth as usize + 1)
-                    .and_then(|x| *x)
-                {
-                    self.index.set_last_child(elem_idx, last_elem);
-                }
+            // Update last_child to actual last child element (after the pop,
+            // the stack length is this element's depth)
+            let depth = self.stack.len();
+            if let Some(last_elem) = self.prev_sibling_at_depth.get(depth + 1).and_then(|x| *x) {
+                self.index.set_last_child(elem_idx, last_elem);
             }
         }
     }
@@ -237,14 +239,35 @@ impl<'a> IndexBuilder<'a> {
     pub fn comment(&mut self, span: Span) {
         let parent = self.current_parent();
         let text = IndexText::comment(span.offset, span.len as usize, parent);
-        self.index.add_text(text);
+        let idx = self.index.add_text(text);
+        if parent == NO_NODE {
+            self.index.top_level.push(idx);
+        }
     }
 
     /// Handle processing instruction
     pub fn processing_instruction(&mut self, target: Span, _data: Option<Span>) {
+        // The XML declaration is not a node
+        if target.slice(self.input) == b"xml" {
+            return;
+        }
         let parent = self.current_parent();
         let text = IndexText::pi(target.offset, target.len as usize, parent);
-        self.index.add_text(text);
+        let idx = self.index.add_text(text);
+        if parent == NO_NODE {
+            self.index.top_level.push(idx);
+        }
+    }
+
+    /// Close the elements still open, innermost first
+    ///
+    /// Returns whether there were any.
+    pub fn close_open_elements(&mut self) -> bool {
+        let any_open = !self.stack.is_empty();
+        while !self.stack.is_empty() {
+            self.end_element(Span::empty());
+        }
+        any_open
     }
 
     /// Ensure prev_sibling_at_depth has capacity for the given depth
@@ -283,6 +306,10 @@ impl<'a> ScanHandler for IndexBuilder<'a> {
     fn processing_instruction(&mut self, target: Span, data: Option<Span>) {
         IndexBuilder::processing_instruction(self, target, data);
     }
+
+    fn should_stop(&self) -> bool {
+        crate::cancel::is_cancelled()
+    }
 }
 
 // ============================================================================
@@ -302,6 +329,181 @@ pub fn build_index(input: &[u8]) -> StructuralIndex {
     builder.finish()
 }
 
+/// Build a StructuralIndex, reporting progress as the scan goes
+///
+/// `progress` is called with the number of bytes scanned each time another
+/// `interval` bytes are behind the scanner; returning false abandons the
+/// build and gives `None`.
+pub fn build_index_with_progress(
+    input: &[u8],
+    interval: usize,
+    progress: impl FnMut(usize) -> bool,
+) -> Option<StructuralIndex> {
+    use crate::core::unified_scanner::UnifiedScanner;
+
+    let mut handler = ProgressHandler {
+        builder: IndexBuilder::new(input),
+        interval: interval.max(1),
+        next_report: interval.max(1),
+        progress,
+        stopped: false,
+    };
+    UnifiedScanner::new(input).scan(&mut handler);
+    (!handler.stopped).then(|| handler.builder.finish())
+}
+
+/// `IndexBuilder` wrapper behind `build_index_with_progress`
+///
+/// Progress is measured by the offset of the latest event, which is close
+/// enough: no event is more than a token away from the scanner.
+struct ProgressHandler<'a, F> {
+    builder: IndexBuilder<'a>,
+    interval: usize,
+    next_report: usize,
+    progress: F,
+    stopped: bool,
+}
+
+impl<F: FnMut(usize) -> bool> ProgressHandler<'_, F> {
+    #[inline]
+    fn reached(&mut self, span: Span) {
+        let offset = span.offset as usize;
+        if offset >= self.next_report {
+            self.stopped = !(self.progress)(offset);
+            self.next_report = offset + self.interval;
+        }
+    }
+}
+
+impl<F: FnMut(usize) -> bool> ScanHandler for ProgressHandler<'_, F> {
+    fn start_element(&mut self, name: Span, attrs: &[(Span, Span)], is_empty: bool) {
+        self.builder.start_element(name, attrs, is_empty);
+        self.reached(name);
+    }
+
+    fn end_element(&mut self, name: Span) {
+        self.builder.end_element(name);
+        self.reached(name);
+    }
+
+    fn text(&mut self, span: Span, needs_entity_decode: bool) {
+        self.builder.text(span, needs_entity_decode);
+        self.reached(span);
+    }
+
+    fn cdata(&mut self, span: Span) {
+        self.builder.cdata(span);
+        self.reached(span);
+    }
+
+    fn comment(&mut self, span: Span) {
+        self.builder.comment(span);
+        self.reached(span);
+    }
+
+    fn processing_instruction(&mut self, target: Span, data: Option<Span>) {
+        self.builder.processing_instruction(target, data);
+        self.reached(target);
+    }
+
+    fn should_stop(&self) -> bool {
+        self.stopped
+    }
+}
+
+/// Build a StructuralIndex for input that may have been cut off
+///
+/// Elements still open at the end of input are closed there, and a tag cut
+/// off midway is dropped. The index is marked `truncated` if either
+/// happened, so callers can tell a complete document from the readable
+/// part of a partial one.
+pub fn build_salvage_index(input: &[u8]) -> StructuralIndex {
+    use crate::core::unified_scanner::UnifiedScanner;
+
+    let mut builder = IndexBuilder::new(input);
+    let mut scanner = UnifiedScanner::new(input);
+    scanner.scan(&mut builder);
+    let closed = builder.close_open_elements();
+    let mut index = builder.finish();
+    // Markup without its closing `>` can only be the cut point
+    let cut_in_markup =
+        memchr::memrchr(b'<', input).is_some_and(|lt| memchr::memchr(b'>', &input[lt..]).is_none());
+    index.truncated = closed || cut_in_markup;
+    index
+}
+
+/// Name of the element `wrap_fragment` puts around fragment content
+pub const FRAGMENT_WRAPPER: &str = "rustyxml-fragment";
+
+/// Surround fragment content (several top-level elements, or mixed text
+/// and elements) with a wrapper element, so it can be validated and
+/// indexed like a document. A leading text declaration (`<?xml ...?>`) is
+/// dropped, since it may only appear at the start of an entity.
+pub fn wrap_fragment(content: &[u8]) -> Vec<u8> {
+    let content = match content.strip_prefix(b"<?xml") {
+        Some(rest) if rest.first().is_some_and(|b| b.is_ascii_whitespace()) => {
+            match memchr::memmem::find(rest, b"?>") {
+                Some(end) => &rest[end + 2..],
+                None => content,
+            }
+        }
+        _ => content,
+    };
+    let mut wrapped = Vec::with_capacity(content.len() + 2 * FRAGMENT_WRAPPER.len() + 5);
+    wrapped.push(b'<');
+    wrapped.extend_from_slice(FRAGMENT_WRAPPER.as_bytes());
+    wrapped.push(b'>');
+    wrapped.extend_from_slice(content);
+    wrapped.extend_from_slice(b"</");
+    wrapped.extend_from_slice(FRAGMENT_WRAPPER.as_bytes());
+    wrapped.push(b'>');
+    wrapped
+}
+
+/// Rewrite a well-formedness error for wrapped fragment content so it
+/// doesn't mention the wrapper
+pub fn fragment_error(msg: String) -> String {
+    let wrapper_start = format!("<{}>", FRAGMENT_WRAPPER);
+    let wrapper_end = format!("</{}>", FRAGMENT_WRAPPER);
+    if let Some(end) = msg.strip_prefix(&format!("Tag mismatch: {} closed with ", wrapper_start)) {
+        return format!("Unexpected end tag: {} without matching start tag", end);
+    }
+    if let Some(start) = msg
+        .strip_prefix("Tag mismatch: ")
+        .and_then(|rest| rest.strip_suffix(&format!(" closed with {}", wrapper_end)))
+    {
+        return format!("Unclosed tag: {}", start);
+    }
+    msg
+}
+
+/// Build a StructuralIndex for `wrap_fragment` output
+///
+/// The wrapper stays the index root, but views treat its children as
+/// children of the document node.
+pub fn build_fragment_index(wrapped: &[u8]) -> StructuralIndex {
+    let mut index = build_index(wrapped);
+    index.fragment = true;
+    // Depth limits apply to the content, not the wrapper
+    index.max_depth = index.max_depth.saturating_sub(1);
+    index
+}
+
+/// Build a StructuralIndex with an explicit block classification backend
+///
+/// Used by the benchmarks to compare the SIMD and scalar classifiers.
+#[cfg(test)]
+fn build_index_with_backend<C: crate::core::classify::ClassifyBlock>(
+    input: &[u8],
+) -> StructuralIndex {
+    use crate::core::unified_scanner::UnifiedScanner;
+
+    let mut builder = IndexBuilder::new(input);
+    let mut scanner = UnifiedScanner::<C>::with_backend(input);
+    scanner.scan(&mut builder);
+    builder.finish()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -336,6 +538,92 @@ mod tests {
         assert_eq!(root_children.len(), 2);
     }
 
+    #[test]
+    fn test_wrap_fragment() {
+        let wrapped = wrap_fragment(b"<?xml version=\"1.0\"?><a/>text<b/>");
+        assert_eq!(
+            wrapped,
+            b"<rustyxml-fragment><a/>text<b/></rustyxml-fragment>"
+        );
+        assert!(crate::dom::validate_strict(&wrapped).is_ok());
+
+        let index = build_fragment_index(&wrapped);
+        assert!(index.fragment);
+        assert_eq!(index.max_depth, 1);
+
+        let err = crate::dom::validate_strict(&wrap_fragment(b"<a></b>")).unwrap_err();
+        assert_eq!(fragment_error(err), "Tag mismatch: <a> closed with </b>");
+        let err = crate::dom::validate_strict(&wrap_fragment(b"</b>")).unwrap_err();
+        assert_eq!(
+            fragment_error(err),
+            "Unexpected end tag: </b> without matching start tag"
+        );
+        let err = crate::dom::validate_strict(&wrap_fragment(b"<a>")).unwrap_err();
+        assert_eq!(fragment_error(err), "Unclosed tag: <a>");
+    }
+
+    #[test]
+    fn test_build_salvage_index() {
+        let xml = b"<feed><entry id=\"1\"><t>a</t></entry><entry id=\"2\"><t>b";
+        let index = build_salvage_index(xml);
+        assert!(index.truncated);
+        assert_eq!(index.element_count(), 5);
+        // The cut-off entry is closed, so it has its last child linked
+        assert_eq!(index.get_element(3).unwrap().last_child, 4);
+
+        let index = build_salvage_index(b"<feed><entry id=\"1\"/><entry id=");
+        assert!(index.truncated);
+        assert_eq!(index.element_count(), 2);
+
+        assert!(!build_salvage_index(b"<feed><entry/></feed>\n").truncated);
+        assert!(!build_index(b"<feed><entry>").truncated);
+    }
+
+    #[test]
+    fn test_build_index_with_progress() {
+        let input = "<r>".to_string() + &"<item>text</item>".repeat(1000) + "</r>";
+        let input = input.as_bytes();
+
+        let mut reports = Vec::new();
+        let index = build_index_with_progress(input, 4096, |done| {
+            reports.push(done);
+            true
+        })
+        .unwrap();
+        assert_eq!(index.element_count(), build_index(input).element_count());
+        assert!((1..=input.len() / 4096).contains(&reports.len()));
+        assert!(reports.windows(2).all(|pair| pair[1] >= pair[0] + 4096));
+
+        // Stopping at the first report abandons the build
+        let mut calls = 0;
+        assert!(build_index_with_progress(input, 4096, |_| {
+            calls += 1;
+            false
+        })
+        .is_none());
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn test_build_deeper_than_u16() {
+        let depth = 70_000;
+        let mut xml = "<a>".repeat(depth);
+        xml.push_str("<x/><y/>");
+        xml.push_str(&"</a>".repeat(depth));
+        let index = build_index(xml.as_bytes());
+
+        assert_eq!(index.max_depth, depth as u32 + 1);
+        let innermost = depth as u32 - 1;
+        let x = index.get_element(innermost + 1).unwrap();
+        assert_eq!(x.parent, innermost);
+        assert_eq!(x.next_sibling, innermost + 2);
+        assert_eq!(
+            index.get_element(innermost).unwrap().last_child,
+            innermost + 2
+        );
+        assert_eq!(index.get_element(0).unwrap().next_sibling, NO_NODE);
+    }
+
     #[test]
     fn test_build_with_text() {
         let xml = b"<root>hello<child>world</child>!</root>";
@@ -344,4 +632,136 @@ mod tests {
         assert_eq!(index.element_count(), 2);
         assert!(index.text_count() >= 2);
     }
+
+    /// Benchmark: SIMD vs scalar block classification on a >=10 MB document
+    ///
+    /// Reports the scan phase on its own (what the classifier accelerates) and
+    /// the full index build. Run with:
+    ///   cargo test --release bench_build_index -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn bench_build_index_simd_vs_scalar() {
+        use crate::core::classify::{ClassifyBlock, Scalar, Simd};
+        use crate::core::unified_scanner::UnifiedScanner;
+        use std::time::Instant;
+
+        /// Handler that only touches the spans, isolating scanner cost
+        struct CountingHandler(usize);
+
+        impl ScanHandler for CountingHandler {
+            fn start_element(&mut self, name: Span, attrs: &[(Span, Span)], _is_empty: bool) {
+                self.0 += name.len as usize + attrs.len();
+            }
+            fn end_element(&mut self, _name: Span) {}
+            fn text(&mut self, span: Span, _needs_entity_decode: bool) {
+                self.0 += span.len as usize;
+            }
+            fn cdata(&mut self, _span: Span) {}
+            fn comment(&mut self, _span: Span) {}
+            fn processing_instruction(&mut self, _target: Span, _data: Option<Span>) {}
+        }
+
+        fn best_of<F: FnMut()>(mut f: F) -> f64 {
+            (0..5)
+                .map(|_| {
+                    let start = Instant::now();
+                    f();
+                    start.elapsed().as_secs_f64()
+                })
+                .fold(f64::MAX, f64::min)
+        }
+
+        fn run<C: ClassifyBlock>(label: &str, input: &[u8]) -> (f64, f64) {
+            let scan = best_of(|| {
+                let mut handler = CountingHandler(0);
+                UnifiedScanner::<C>::with_backend(input).scan(&mut handler);
+                assert!(handler.0 > 0);
+            });
+            let build = best_of(|| {
+                assert!(build_index_with_backend::<C>(input).element_count() > 0);
+            });
+            let mb = input.len() as f64 / 1_000_000.0;
+            println!(
+                "{label:>6}: scan {:.1} ms ({:.0} MB/s), build {:.1} ms ({:.0} MB/s)",
+                scan * 1000.0,
+                mb / scan,
+                build * 1000.0,
+                mb / build
+            );
+            (scan, build)
+        }
+
+        let mut xml = String::from("<catalog>\n");
+        let mut i = 0;
+        while xml.len() < 12_000_000 {
+            xml.push_str(&format!(
+                "  <item id=\"{i}\" category=\"cat{}\" href='https://example.com/items/{i}'>\n    \
+                 <name>Product &amp; accessory number {i}</name>\n    \
+                 <description>A reasonably long description of item {i} so that text \
+                 runs span more than one 64-byte block, as they do in real feeds.</description>\n  \
+                 </item>\n",
+                i % 10
+            ));
+            i += 1;
+        }
+        xml.push_str("</catalog>\n");
+        let input = xml.as_bytes();
+
+        let (scalar_scan, scalar_build) = run::<Scalar>("scalar", input);
+        let (simd_scan, simd_build) = run::<Simd>("simd", input);
+        println!(
+            "speedup on {} bytes: scan {:.2}x, build {:.2}x",
+            input.len(),
+            scalar_scan / simd_scan,
+            scalar_build / simd_build
+        );
+    }
+
+    /// Benchmark: one-shot parse and query of a sub-1 KB payload
+    ///
+    /// Tiny documents spend their time on per-document setup rather than
+    /// scanning, so this reports the cost per document. Run with:
+    ///   cargo test --release bench_small_documents -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn bench_small_documents() {
+        use crate::index::IndexedDocumentView;
+        use crate::xpath::XPathValue;
+        use std::time::Instant;
+
+        /// Best of five rounds, in nanoseconds per document
+        fn per_doc<F: FnMut()>(runs: u32, mut f: F) -> f64 {
+            (0..5)
+                .map(|_| {
+                    let start = Instant::now();
+                    for _ in 0..runs {
+                        f();
+                    }
+                    start.elapsed().as_nanos() as f64 / runs as f64
+                })
+                .fold(f64::MAX, f64::min)
+        }
+
+        let xml = "<order id=\"42\"><customer name=\"Ada\"/><items>\
+                   <item sku=\"a\" qty=\"1\">Widget</item><item sku=\"b\" qty=\"2\">Gadget</item>\
+                   </items><total>12.50</total></order>";
+        let input = xml.as_bytes();
+        let runs = 100_000;
+
+        let built = per_doc(runs, || {
+            std::hint::black_box(build_index(input));
+        });
+        let queried = per_doc(runs, || {
+            let index = build_index(input);
+            let view = IndexedDocumentView::new(&index, input);
+            let value = crate::xpath::evaluate(&view, "//item[@qty > 1]");
+            assert!(matches!(value, Ok(XPathValue::NodeSet(items)) if items.len() == 1));
+        });
+        println!(
+            "{} bytes: build {:.0} ns, build + query {:.0} ns",
+            input.len(),
+            built,
+            queried
+        );
+    }
 }
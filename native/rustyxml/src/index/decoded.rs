@@ -0,0 +1,138 @@
+//! Decoded Text Cache
+//!
+//! Text spans in the structural index are raw input bytes. Spans containing
+//! entity or character references are decoded on first access and kept
+//! here, so repeated queries over the same text nodes decode them once.
+//! The cache holds at most `DEFAULT_CAPACITY` bytes of decoded text; past
+//! that, text is decoded for each access without being kept.
+
+use super::map_bytes;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::{Arc, PoisonError, RwLock};
+
+/// Most bytes of decoded text a cache keeps
+const DEFAULT_CAPACITY: usize = 16 * 1024 * 1024;
+
+/// Lazily populated map of text index → decoded content
+///
+/// Readers share the read lock; only a miss takes the write lock. Entries
+/// are never removed or replaced while the cache is shared, which is what
+/// lets `get_or_decode` hand out references that outlive the lock guard;
+/// the cache is bounded by refusing new entries once full instead.
+pub struct TextDecodeCache {
+    entries: RwLock<Entries>,
+    capacity: usize,
+}
+
+#[derive(Default)]
+struct Entries {
+    map: HashMap<u32, Arc<str>>,
+    /// Total length of the decoded strings in `map`
+    bytes: usize,
+}
+
+impl Default for TextDecodeCache {
+    fn default() -> Self {
+        Self::with_capacity(DEFAULT_CAPACITY)
+    }
+}
+
+impl TextDecodeCache {
+    /// Cache keeping at most `capacity` bytes of decoded text
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            entries: RwLock::default(),
+            capacity,
+        }
+    }
+
+    /// Bytes held by decoded text so far
+    pub fn heap_bytes(&self) -> usize {
+        let entries = self.entries.read().unwrap_or_else(PoisonError::into_inner);
+        map_bytes(&entries.map) + entries.bytes
+    }
+
+    /// Decoded content of text node `idx`, decoding `raw` on a miss
+    ///
+    /// Borrowed from the cache, or owned when the cache is full.
+    pub fn get_or_decode(&self, idx: u32, raw: &[u8]) -> Cow<'_, str> {
+        // The map is consistent after every insert, so a poisoned lock is
+        // still safe to use
+        let cached = self
+            .entries
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .map
+            .get(&idx)
+            .map(Arc::as_ptr);
+        let ptr = match cached {
+            Some(ptr) => ptr,
+            None => {
+                let decoded = crate::core::entities::decode_text(raw);
+                let decoded = String::from_utf8_lossy(&decoded).into_owned();
+                let mut entries = self.entries.write().unwrap_or_else(PoisonError::into_inner);
+                if let Some(text) = entries.map.get(&idx) {
+                    Arc::as_ptr(text)
+                } else if entries.bytes + decoded.len() > self.capacity {
+                    return Cow::Owned(decoded);
+                } else {
+                    entries.bytes += decoded.len();
+                    Arc::as_ptr(entries.map.entry(idx).or_insert(decoded.into()))
+                }
+            }
+        };
+        // SAFETY: the string lives in the Arc's heap allocation, which does
+        // not move when the map grows. The entry is never removed or
+        // replaced until the cache is dropped, and `&self` keeps it alive
+        // for the returned lifetime.
+        Cow::Borrowed(unsafe { &*ptr })
+    }
+
+    /// Number of decoded text nodes
+    #[cfg(test)]
+    pub fn len(&self) -> usize {
+        self.entries
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .map
+            .len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decodes_once_per_node() {
+        let cache = TextDecodeCache::default();
+        assert_eq!(cache.get_or_decode(3, b"x &amp; y&#33;"), "x & y!");
+        assert_eq!(cache.len(), 1);
+
+        // A hit returns the stored value without looking at the input again
+        let first = &*cache.get_or_decode(3, b"x &amp; y&#33;") as *const str;
+        let second = &*cache.get_or_decode(3, b"ignored") as *const str;
+        assert_eq!(first, second);
+
+        assert_eq!(cache.get_or_decode(7, b"&lt;b&gt;"), "<b>");
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[test]
+    fn test_full_cache_decodes_without_keeping() {
+        let cache = TextDecodeCache::with_capacity(8);
+        assert!(matches!(
+            cache.get_or_decode(1, b"a &amp; b"),
+            Cow::Borrowed("a & b")
+        ));
+
+        // Another 5 bytes would take the cache past its capacity
+        assert!(matches!(cache.get_or_decode(2, b"c &amp; d"), Cow::Owned(ref s) if s == "c & d"));
+        assert_eq!(cache.len(), 1);
+        assert!(matches!(
+            cache.get_or_decode(1, b""),
+            Cow::Borrowed("a & b")
+        ));
+    }
+}
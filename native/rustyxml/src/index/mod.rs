@@ -17,7 +17,8 @@
 //! ├── elements: Vec<IndexElement>   # 32 bytes each
 //! ├── texts: Vec<IndexText>         # 16 bytes each
 //! ├── attributes: Vec<IndexAttribute> # 12 bytes each
-//! └── children: flat storage of ChildRef
+//! ├── children: flat storage of ChildRef
+//! └── names: NameTable              # 4-byte symbol per element/attribute
 //! ```
 //!
 //! ## Memory Comparison
@@ -28,13 +29,38 @@
 //! | Per text | String copy | 16 bytes |
 //! | String storage | ~2x input | 0 (offsets only) |
 
+pub mod attr_values;
 pub mod builder;
+pub mod decoded;
 pub mod element;
+pub mod multi;
+pub mod names;
+pub mod packed;
+pub mod paths;
+pub mod precomputed;
+pub mod search;
 pub mod span;
+pub mod split;
 pub mod structural;
+pub mod truncate;
 pub mod view;
 
 // Re-export what's needed externally
+pub use attr_values::AttributeValueIndex;
+pub use decoded::TextDecodeCache;
+pub use multi::MultiDocumentView;
+pub use precomputed::PrecomputedText;
 pub use span::Span;
 pub use structural::StructuralIndex;
 pub use view::IndexedDocumentView;
+
+/// Bytes allocated by a vector
+pub fn vec_bytes<T>(vec: &Vec<T>) -> usize {
+    vec.capacity() * std::mem::size_of::<T>()
+}
+
+/// Bytes allocated by a hash map's table, not counting what its keys and
+/// values point to (one control byte per slot)
+pub fn map_bytes<K, V>(map: &std::collections::HashMap<K, V>) -> usize {
+    map.capacity() * (std::mem::size_of::<(K, V)>() + 1)
+}
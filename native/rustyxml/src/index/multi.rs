@@ -0,0 +1,270 @@
+//! Multi-Document View
+//!
+//! Combines a main document with named, preloaded documents so a single XPath
+//! evaluation can reach all of them through `document('name')`.
+//!
+//! NodeIds of the combined view carry the document's slot in bits 27-30, on
+//! top of the single-document encoding (bit 31 = text node):
+//!
+//! ```text
+//! bit 31      bits 27-30   bits 0-26
+//! text flag   slot         element/text index (all ones = document node)
+//! ```
+//!
+//! The main document is slot 0, so its element and text ids are unchanged.
+
+// Allow unused API methods - these are public for library consumers
+#![allow(dead_code)]
+
+use super::view::IndexedDocumentView;
+use crate::core::normalize::Normalization;
+use crate::dom::node::{NodeId, NodeKind, XmlNode};
+use crate::dom::DocumentAccess;
+use std::borrow::Cow;
+
+/// Maximum number of documents in one view (main document included)
+pub const MAX_DOCUMENTS: usize = 16;
+
+const TEXT_NODE_FLAG: u32 = 0x8000_0000;
+const SLOT_SHIFT: u32 = 27;
+const SLOT_MASK: u32 = 0x7800_0000;
+const LOCAL_MASK: u32 = 0x07FF_FFFF;
+
+/// A main document plus preloaded documents addressable by name
+pub struct MultiDocumentView<'a> {
+    /// Slot 0 is the main document
+    views: Vec<IndexedDocumentView<'a>>,
+    /// Names of slots 1.., parallel to `views[1..]`
+    names: Vec<String>,
+}
+
+impl<'a> MultiDocumentView<'a> {
+    /// Combine `main` with named documents
+    ///
+    /// Fails if there are more than `MAX_DOCUMENTS - 1` extra documents, or if
+    /// a document has too many nodes for the slot encoding.
+    pub fn new(
+        main: IndexedDocumentView<'a>,
+        others: Vec<(String, IndexedDocumentView<'a>)>,
+    ) -> Result<Self, String> {
+        if others.len() >= MAX_DOCUMENTS {
+            return Err(format!(
+                "at most {} preloaded documents are supported",
+                MAX_DOCUMENTS - 1
+            ));
+        }
+
+        let mut views = Vec::with_capacity(others.len() + 1);
+        let mut names = Vec::with_capacity(others.len());
+        views.push(main);
+        for (name, view) in others {
+            views.push(view);
+            names.push(name);
+        }
+
+        for view in &views {
+            if view.index.element_count() >= LOCAL_MASK as usize
+                || view.index.text_count() > LOCAL_MASK as usize
+            {
+                return Err("document too large for cross-document queries".to_string());
+            }
+        }
+
+        Ok(Self { views, names })
+    }
+
+    /// Document node of the preloaded document registered under `name`
+    pub fn document_node(&self, name: &str) -> Option<NodeId> {
+        let slot = self.names.iter().position(|n| n == name)? + 1;
+        Some(encode(slot, self.views[slot].document_node_id()))
+    }
+
+    /// Split a combined NodeId into the owning view and its local NodeId
+    #[inline]
+    fn resolve(&self, id: NodeId) -> (usize, &IndexedDocumentView<'a>, NodeId) {
+        let slot = ((id & SLOT_MASK) >> SLOT_SHIFT) as usize;
+        // Ids are only ever produced by `encode`, so the slot is in range
+        let view = &self.views[slot.min(self.views.len() - 1)];
+        let local = if id & TEXT_NODE_FLAG == 0 && id & LOCAL_MASK == LOCAL_MASK {
+            view.document_node_id()
+        } else {
+            (id & TEXT_NODE_FLAG) | (id & LOCAL_MASK)
+        };
+        (slot, view, local)
+    }
+}
+
+/// Combine a slot and a single-document NodeId
+#[inline]
+fn encode(slot: usize, local: NodeId) -> NodeId {
+    let slot_bits = (slot as u32) << SLOT_SHIFT;
+    if local & TEXT_NODE_FLAG == 0 && local & !LOCAL_MASK != 0 {
+        // The only element-side id above LOCAL_MASK is the document node
+        slot_bits | LOCAL_MASK
+    } else {
+        (local & TEXT_NODE_FLAG) | slot_bits | (local & LOCAL_MASK)
+    }
+}
+
+impl<'a> DocumentAccess for MultiDocumentView<'a> {
+    fn root_element_id(&self) -> Option<NodeId> {
+        self.views[0].root_element_id().map(|id| encode(0, id))
+    }
+
+    fn get_node(&self, _id: NodeId) -> Option<&XmlNode> {
+        None
+    }
+
+    fn node_name(&self, id: NodeId) -> Option<&str> {
+        let (_, view, local) = self.resolve(id);
+        view.node_name(local)
+    }
+
+    fn node_local_name(&self, id: NodeId) -> Option<&str> {
+        let (_, view, local) = self.resolve(id);
+        view.node_local_name(local)
+    }
+
+    fn node_namespace_uri(&self, id: NodeId) -> Option<&str> {
+        let (_, view, local) = self.resolve(id);
+        view.node_namespace_uri(local)
+    }
+
+    fn text_content(&self, id: NodeId) -> Option<Cow<'_, str>> {
+        let (_, view, local) = self.resolve(id);
+        view.text_content(local)
+    }
+
+    fn node_data(&self, id: NodeId) -> Option<&str> {
+        let (_, view, local) = self.resolve(id);
+        view.node_data(local)
+    }
+
+    fn get_attribute(&self, node_id: NodeId, name: &str) -> Option<&str> {
+        let (_, view, local) = self.resolve(node_id);
+        view.get_attribute(local, name)
+    }
+
+    fn get_attribute_values(&self, node_id: NodeId) -> Vec<(&str, &str)> {
+        let (_, view, local) = self.resolve(node_id);
+        view.get_attribute_values(local)
+    }
+
+    fn children_vec(&self, id: NodeId) -> Vec<NodeId> {
+        let (slot, view, local) = self.resolve(id);
+        let mut children = view.children_vec(local);
+        for child in &mut children {
+            *child = encode(slot, *child);
+        }
+        children
+    }
+
+    fn descendants_vec(&self, id: NodeId) -> Vec<NodeId> {
+        let (slot, view, local) = self.resolve(id);
+        let mut descendants = view.descendants_vec(local);
+        for node in &mut descendants {
+            *node = encode(slot, *node);
+        }
+        descendants
+    }
+
+    fn parent_of(&self, id: NodeId) -> Option<NodeId> {
+        let (slot, view, local) = self.resolve(id);
+        view.parent_of(local).map(|parent| encode(slot, parent))
+    }
+
+    fn next_sibling_of(&self, id: NodeId) -> Option<NodeId> {
+        let (slot, view, local) = self.resolve(id);
+        view.next_sibling_of(local).map(|sib| encode(slot, sib))
+    }
+
+    fn prev_sibling_of(&self, id: NodeId) -> Option<NodeId> {
+        let (slot, view, local) = self.resolve(id);
+        view.prev_sibling_of(local).map(|sib| encode(slot, sib))
+    }
+
+    fn node_kind_of(&self, id: NodeId) -> NodeKind {
+        let (_, view, local) = self.resolve(id);
+        view.node_kind_of(local)
+    }
+
+    fn document_node_id(&self) -> NodeId {
+        encode(0, self.views[0].document_node_id())
+    }
+
+    fn document_order_key(&self, id: NodeId) -> u64 {
+        // Documents are ordered by slot, the main document first
+        let (slot, view, local) = self.resolve(id);
+        ((slot as u64) << 40) | view.document_order_key(local)
+    }
+
+    fn document_by_name(&self, name: &str) -> Option<NodeId> {
+        self.document_node(name)
+    }
+
+    fn text_normalization(&self) -> Normalization {
+        self.views[0].text_normalization()
+    }
+
+    fn precomputed_string_value(&self, node_id: NodeId) -> Option<&str> {
+        let (_, view, local) = self.resolve(node_id);
+        view.precomputed_string_value(local)
+    }
+
+    // Interned names are per document, so name tests fall back to comparing
+    // strings (the default `name_symbol` of `Unavailable`).
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::builder::build_index;
+    use crate::xpath::{evaluate, XPathValue};
+
+    #[test]
+    fn test_document_function_joins_across_documents() {
+        let main_input = b"<orders><order ref=\"b\"/><order ref=\"a\"/></orders>";
+        let lookup_input =
+            b"<items><item><id>a</id>Apple</item><item><id>b</id>Banana</item></items>";
+        let main_index = build_index(main_input);
+        let lookup_index = build_index(lookup_input);
+        let view = MultiDocumentView::new(
+            IndexedDocumentView::new(&main_index, main_input),
+            vec![(
+                "lookup.xml".to_string(),
+                IndexedDocumentView::new(&lookup_index, lookup_input),
+            )],
+        )
+        .unwrap();
+
+        // Main document ids are unchanged
+        let Ok(XPathValue::NodeSet(orders)) = evaluate(&view, "//order") else {
+            panic!("expected a node-set");
+        };
+        assert_eq!(orders.len(), 2);
+        assert!(orders.iter().all(|&id| id & SLOT_MASK == 0));
+
+        let result = evaluate(
+            &view,
+            "string(document('lookup.xml')//item[id = 'b']/text())",
+        )
+        .unwrap();
+        assert_eq!(result.to_string_value(), "Banana");
+
+        let result = evaluate(&view, "count(//order[@ref = document('lookup.xml')//id])").unwrap();
+        assert_eq!(result.to_number(), 2.0);
+
+        let Ok(XPathValue::NodeSet(items)) = evaluate(&view, "document('lookup.xml')/items/item")
+        else {
+            panic!("expected a node-set");
+        };
+        assert_eq!(items.len(), 2);
+        assert_eq!(view.node_name(items[0]), Some("item"));
+        assert_eq!(
+            view.parent_of(view.parent_of(items[0]).unwrap()),
+            view.document_node("lookup.xml")
+        );
+
+        assert!(evaluate(&view, "document('missing.xml')").is_err());
+    }
+}
@@ -0,0 +1,210 @@
+//! Name Table - interned element and attribute names
+//!
+//! Every distinct qualified name in the document gets a symbol id at build
+//! time, and each element/attribute records the symbol of its name. Query
+//! steps resolve their name test to a symbol once, after which matching a
+//! candidate node is a single `u32` comparison instead of a byte compare.
+//!
+//! Local names are interned in the same symbol space, so `item` and the local
+//! part of `ns:item` share one symbol.
+
+// Allow unused API methods - these are public for library consumers
+#![allow(dead_code)]
+
+use super::span::Span;
+use super::{map_bytes, vec_bytes};
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+/// Symbol id of an interned name
+pub type Symbol = u32;
+
+/// Symbols found by scanning `spans` before the hash index is built
+const LINEAR_SYMBOLS: usize = 16;
+
+/// Interned names for one document
+///
+/// Memory layout:
+/// - `spans`: first occurrence of each symbol in the input (zero-copy)
+/// - `local_of`: symbol -> symbol of its local part (itself if unprefixed)
+/// - `hash_index`: hash -> symbols with that hash (handles rare collisions);
+///   left empty until there are `LINEAR_SYMBOLS` symbols, as comparing a
+///   few names directly is cheaper than hashing them
+/// - `element_names` / `attribute_names`: parallel to the index tables
+#[derive(Debug, Default)]
+pub struct NameTable {
+    spans: Vec<Span>,
+    local_of: Vec<Symbol>,
+    hash_index: HashMap<u64, Vec<Symbol>>,
+    /// Qualified-name symbol of each element, by element index
+    element_names: Vec<Symbol>,
+    /// Qualified-name symbol of each attribute, by attribute index
+    attribute_names: Vec<Symbol>,
+}
+
+impl NameTable {
+    /// Bytes allocated by the table
+    pub fn heap_bytes(&self) -> usize {
+        vec_bytes(&self.spans)
+            + vec_bytes(&self.local_of)
+            + vec_bytes(&self.element_names)
+            + vec_bytes(&self.attribute_names)
+            + map_bytes(&self.hash_index)
+            + self.hash_index.values().map(vec_bytes).sum::<usize>()
+    }
+
+    /// Create an empty table sized for the expected element/attribute counts
+    pub fn with_capacity(elements: usize, attributes: usize) -> Self {
+        Self {
+            spans: Vec::with_capacity(LINEAR_SYMBOLS),
+            local_of: Vec::with_capacity(LINEAR_SYMBOLS),
+            hash_index: HashMap::new(),
+            element_names: Vec::with_capacity(elements),
+            attribute_names: Vec::with_capacity(attributes),
+        }
+    }
+
+    #[inline]
+    fn compute_hash(s: &[u8]) -> u64 {
+        use std::collections::hash_map::DefaultHasher;
+        let mut hasher = DefaultHasher::new();
+        s.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    fn find(&self, bytes: &[u8], input: &[u8]) -> Option<Symbol> {
+        if self.hash_index.is_empty() {
+            return self
+                .spans
+                .iter()
+                .position(|span| span.slice(input) == bytes)
+                .map(|sym| sym as Symbol);
+        }
+        let hash = Self::compute_hash(bytes);
+        self.hash_index.get(&hash)?.iter().copied().find(|&sym| {
+            self.spans
+                .get(sym as usize)
+                .is_some_and(|span| span.slice(input) == bytes)
+        })
+    }
+
+    /// Intern a name span, returning its symbol
+    fn intern(&mut self, span: Span, input: &[u8]) -> Symbol {
+        let bytes = span.slice(input);
+        if let Some(sym) = self.find(bytes, input) {
+            return sym;
+        }
+
+        let sym = self.spans.len() as Symbol;
+        self.spans.push(span);
+        self.local_of.push(sym);
+        if !self.hash_index.is_empty() {
+            let hash = Self::compute_hash(bytes);
+            self.hash_index.entry(hash).or_default().push(sym);
+        } else if self.spans.len() == LINEAR_SYMBOLS {
+            for (sym, span) in self.spans.iter().enumerate() {
+                let hash = Self::compute_hash(span.slice(input));
+                self.hash_index.entry(hash).or_default().push(sym as Symbol);
+            }
+        }
+
+        // Intern the local part too, so local-name tests resolve to one symbol
+        if let Some(pos) = bytes.iter().position(|&b| b == b':') {
+            let local = Span::new(span.offset + pos as u32 + 1, span.len - pos as u16 - 1);
+            let local_sym = self.intern(local, input);
+            self.local_of[sym as usize] = local_sym;
+        }
+        sym
+    }
+
+    /// Record the name of the next element (called in element index order)
+    pub(crate) fn push_element(&mut self, name: Span, input: &[u8]) {
+        let sym = self.intern(name, input);
+        self.element_names.push(sym);
+    }
+
+    /// Record the name of the next attribute (called in attribute index order)
+    pub(crate) fn push_attribute(&mut self, name: Span, input: &[u8]) {
+        let sym = self.intern(name, input);
+        self.attribute_names.push(sym);
+    }
+
+    pub(crate) fn shrink_to_fit(&mut self) {
+        self.spans.shrink_to_fit();
+        self.local_of.shrink_to_fit();
+        self.element_names.shrink_to_fit();
+        self.attribute_names.shrink_to_fit();
+    }
+
+    /// Look up the symbol for a name, or None if it never occurs in the document
+    pub fn lookup(&self, name: &str, input: &[u8]) -> Option<Symbol> {
+        self.find(name.as_bytes(), input)
+    }
+
+    /// Qualified-name symbol of an element
+    #[inline]
+    pub fn element_name(&self, elem_idx: u32) -> Option<Symbol> {
+        self.element_names.get(elem_idx as usize).copied()
+    }
+
+    /// Local-name symbol of an element
+    #[inline]
+    pub fn element_local_name(&self, elem_idx: u32) -> Option<Symbol> {
+        let sym = self.element_name(elem_idx)?;
+        self.local_of.get(sym as usize).copied()
+    }
+
+    /// Qualified-name symbol of an attribute
+    #[inline]
+    pub fn attribute_name(&self, attr_idx: u32) -> Option<Symbol> {
+        self.attribute_names.get(attr_idx as usize).copied()
+    }
+
+    /// Number of distinct symbols (qualified and local names)
+    pub fn len(&self) -> usize {
+        self.spans.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.spans.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_intern_dedups_and_links_local_names() {
+        let input = b"item ns:item item";
+        let mut names = NameTable::default();
+        names.push_element(Span::new(0, 4), input);
+        names.push_element(Span::new(5, 7), input);
+        names.push_element(Span::new(13, 4), input);
+
+        // "item" and "ns:item" are distinct qualified names sharing a local name
+        assert_eq!(names.len(), 2);
+        assert_eq!(names.element_name(0), names.element_name(2));
+        assert_ne!(names.element_name(0), names.element_name(1));
+        assert_eq!(names.element_local_name(1), names.element_name(0));
+
+        assert_eq!(names.lookup("item", input), names.element_name(0));
+        assert_eq!(names.lookup("ns:item", input), names.element_name(1));
+        assert_eq!(names.lookup("missing", input), None);
+    }
+
+    #[test]
+    fn test_lookup_before_and_after_hash_index() {
+        let input: String = (0..40).map(|i| format!("n{i:02} ")).collect();
+        let mut names = NameTable::default();
+        for i in 0..40 {
+            names.push_element(Span::new(i * 4, 3), input.as_bytes());
+            let again = names.lookup(&format!("n{i:02}"), input.as_bytes());
+            assert_eq!(again, Some(i));
+            assert_eq!(names.lookup("n0", input.as_bytes()), None);
+        }
+        names.push_element(Span::new(0, 3), input.as_bytes());
+        assert_eq!(names.len(), 40);
+        assert_eq!(names.element_name(40), Some(0));
+    }
+}
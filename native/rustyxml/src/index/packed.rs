@@ -0,0 +1,111 @@
+//! Packed Node Sets
+//!
+//! A node-set as a flat binary, so a query result can be parked outside
+//! the process heap (in ETS, say) and turned back into nodes later without
+//! keeping its terms alive. The layout, all little-endian:
+//!
+//! ```text
+//! "RXN2" | element count: u32 | text count: u32 | input hash: u64 | node id: u32 ...
+//! ```
+//!
+//! The counts and the xxh3 hash of the document's input stand in for its
+//! identity: unpacking against a document with other counts or other bytes,
+//! or one lacking any of the ids, fails. Hashing reads the whole input on
+//! both sides. The hash guards against mix-ups, not forgery.
+
+use super::structural::StructuralIndex;
+use super::view::{decode_node_id, is_document_node_id};
+use crate::dom::NodeId;
+
+const MAGIC: &[u8; 4] = b"RXN2";
+const HEADER_LEN: usize = 20;
+
+/// Pack `nodes`, ids of nodes in `index` (built over `input`), into a
+/// binary
+pub fn pack(index: &StructuralIndex, input: &[u8], nodes: &[NodeId]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(HEADER_LEN + nodes.len() * 4);
+    out.extend_from_slice(&header(index, input));
+    for &id in nodes {
+        out.extend_from_slice(&id.to_le_bytes());
+    }
+    out
+}
+
+/// Node ids from a binary made by `pack` for the same document, or None if
+/// it is malformed or was packed for another document
+pub fn unpack(index: &StructuralIndex, input: &[u8], bytes: &[u8]) -> Option<Vec<NodeId>> {
+    let (packed_header, ids) = bytes.split_at_checked(HEADER_LEN)?;
+    if ids.len() % 4 != 0 || packed_header != header(index, input) {
+        return None;
+    }
+    ids.chunks_exact(4)
+        .map(|chunk| {
+            let id = read_u32(chunk);
+            let (is_text, idx) = decode_node_id(id);
+            let known = if is_text {
+                (idx as usize) < index.texts.len()
+            } else {
+                is_document_node_id(id) || (idx as usize) < index.elements.len()
+            };
+            known.then_some(id)
+        })
+        .collect()
+}
+
+/// The header identifying the document `index` was built over
+fn header(index: &StructuralIndex, input: &[u8]) -> [u8; HEADER_LEN] {
+    let mut header = [0; HEADER_LEN];
+    header[..4].copy_from_slice(MAGIC);
+    header[4..8].copy_from_slice(&(index.elements.len() as u32).to_le_bytes());
+    header[8..12].copy_from_slice(&(index.texts.len() as u32).to_le_bytes());
+    header[12..].copy_from_slice(&xxhash_rust::xxh3::xxh3_64(input).to_le_bytes());
+    header
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::builder::build_index;
+    use crate::index::IndexedDocumentView;
+    use crate::xpath::{evaluate, XPathValue};
+
+    #[test]
+    fn test_pack_round_trip() {
+        let input = b"<r><a>1</a><!-- c --><a>2</a></r>";
+        let index = build_index(input);
+        let view = IndexedDocumentView::new(&index, input);
+        let Ok(XPathValue::NodeSet(nodes)) = evaluate(&view, "//a | //comment() | /") else {
+            panic!("expected a node-set");
+        };
+
+        let packed = pack(&index, input, &nodes);
+        assert_eq!(packed.len(), HEADER_LEN + 4 * nodes.len());
+        assert_eq!(unpack(&index, input, &packed), Some(nodes));
+        assert_eq!(
+            unpack(&index, input, &pack(&index, input, &[])),
+            Some(vec![])
+        );
+    }
+
+    #[test]
+    fn test_unpack_rejects_other_documents() {
+        let input: &[u8] = b"<r><a/></r>";
+        let index = build_index(input);
+        let other_input: &[u8] = b"<r><a/><b/></r>";
+        let packed = pack(&build_index(other_input), other_input, &[2]);
+
+        assert_eq!(unpack(&index, input, &packed), None);
+        assert_eq!(unpack(&index, input, &pack(&index, input, &[5])), None);
+        assert_eq!(unpack(&index, input, &packed[..HEADER_LEN - 1]), None);
+        assert_eq!(unpack(&index, input, b"not a node set"), None);
+
+        // Same shape, other bytes
+        let renamed: &[u8] = b"<s><b/></s>";
+        let packed = pack(&build_index(renamed), renamed, &[2]);
+        assert_eq!(unpack(&index, input, &packed), None);
+    }
+}
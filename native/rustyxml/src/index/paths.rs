@@ -0,0 +1,87 @@
+//! Element Paths
+//!
+//! `tag_paths` lists the distinct element paths of a document, such as
+//! `catalog/product/price`, with how often each occurs. Elements are stored
+//! in document order with their parent, so one pass over them gives every
+//! element's path from its parent's.
+
+use super::element::NO_NODE;
+use super::structural::StructuralIndex;
+use std::collections::HashMap;
+
+/// Distinct element paths and their occurrence counts, in order of first
+/// occurrence
+///
+/// Names are qualified names as written. For fragments the hidden wrapper
+/// is left out, so each top-level element starts a path.
+pub fn tag_paths(index: &StructuralIndex, input: &[u8]) -> Vec<(String, usize)> {
+    // Path id of each element (NO_NODE for the fragment wrapper)
+    let mut element_paths = Vec::with_capacity(index.elements.len());
+    // (parent path id, name) -> path id
+    let mut ids: HashMap<(u32, &[u8]), u32> = HashMap::new();
+    let mut paths: Vec<(String, usize)> = Vec::new();
+
+    for (idx, elem) in index.elements.iter().enumerate() {
+        if index.fragment && idx == 0 {
+            element_paths.push(NO_NODE);
+            continue;
+        }
+        let parent = element_paths
+            .get(elem.parent as usize)
+            .copied()
+            .unwrap_or(NO_NODE);
+        let name = elem.name.slice(input);
+        let id = *ids.entry((parent, name)).or_insert_with(|| {
+            let name = String::from_utf8_lossy(name);
+            let path = match paths.get(parent as usize) {
+                Some((prefix, _)) => format!("{}/{}", prefix, name),
+                None => name.into_owned(),
+            };
+            paths.push((path, 0));
+            (paths.len() - 1) as u32
+        });
+        paths[id as usize].1 += 1;
+        element_paths.push(id);
+    }
+    paths
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::builder::{build_fragment_index, build_index, wrap_fragment};
+
+    #[test]
+    fn test_tag_paths_count_occurrences() {
+        let xml = b"<catalog><product><name/><price/></product><product><price/>\
+                    <x:tag xmlns:x='u'/></product><meta><name/></meta></catalog>";
+        let index = build_index(xml);
+        assert_eq!(
+            tag_paths(&index, xml),
+            [
+                ("catalog".to_string(), 1),
+                ("catalog/product".to_string(), 2),
+                ("catalog/product/name".to_string(), 1),
+                ("catalog/product/price".to_string(), 2),
+                ("catalog/product/x:tag".to_string(), 1),
+                ("catalog/meta".to_string(), 1),
+                ("catalog/meta/name".to_string(), 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tag_paths_of_fragment() {
+        let input = wrap_fragment(b"<a><b/></a>text<a/><c/>");
+        let index = build_fragment_index(&input);
+        assert_eq!(
+            tag_paths(&index, &input),
+            [
+                ("a".to_string(), 2),
+                ("a/b".to_string(), 1),
+                ("c".to_string(), 1)
+            ]
+        );
+        assert!(tag_paths(&build_index(b""), b"").is_empty());
+    }
+}
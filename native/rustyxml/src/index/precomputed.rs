@@ -0,0 +1,108 @@
+//! Precomputed Element String-Values
+//!
+//! The string-value of an element is the concatenation of its descendant
+//! text, so with all text laid out once in document order every element's
+//! string-value is a contiguous range of that buffer. Building the buffer
+//! costs one walk of the tree; afterwards `string(elem)` is a slice lookup.
+
+use super::element::ChildRef;
+use super::structural::StructuralIndex;
+use super::vec_bytes;
+
+/// Decoded text of a document in document order, with each element's range
+#[derive(Debug, Default)]
+pub struct PrecomputedText {
+    buffer: String,
+    /// `[start, end)` byte offsets into `buffer`, indexed by element
+    spans: Vec<(u32, u32)>,
+}
+
+enum Step {
+    Enter(u32),
+    Exit(u32),
+    Text(u32),
+}
+
+impl PrecomputedText {
+    /// Bytes allocated by the string-values
+    pub fn heap_bytes(&self) -> usize {
+        self.buffer.capacity() + vec_bytes(&self.spans)
+    }
+
+    /// Concatenate the text and CDATA of the whole document
+    pub fn build(index: &StructuralIndex, input: &[u8]) -> Self {
+        let mut buffer = String::new();
+        let mut spans = vec![(0, 0); index.element_count()];
+
+        let mut stack: Vec<Step> = (0..index.element_count() as u32)
+            .rev()
+            .filter(|&idx| index.parent(idx).is_none())
+            .map(Step::Enter)
+            .collect();
+        while let Some(step) = stack.pop() {
+            match step {
+                Step::Enter(idx) => {
+                    spans[idx as usize].0 = buffer.len() as u32;
+                    stack.push(Step::Exit(idx));
+                    let children: Vec<ChildRef> = index.children(idx).collect();
+                    stack.extend(children.into_iter().rev().map(|child| {
+                        if child.is_text() {
+                            Step::Text(child.index())
+                        } else {
+                            Step::Enter(child.index())
+                        }
+                    }));
+                }
+                Step::Exit(idx) => spans[idx as usize].1 = buffer.len() as u32,
+                Step::Text(idx) => {
+                    let Some(text) = index.get_text(idx) else {
+                        continue;
+                    };
+                    if !text.is_text() && !text.is_cdata() {
+                        continue;
+                    }
+                    let raw = text.span.slice(input);
+                    if text.needs_decode() {
+                        let decoded = crate::core::entities::decode_text(raw);
+                        buffer.push_str(&String::from_utf8_lossy(&decoded));
+                    } else {
+                        buffer.push_str(&String::from_utf8_lossy(raw));
+                    }
+                }
+            }
+        }
+        buffer.shrink_to_fit();
+        Self { buffer, spans }
+    }
+
+    /// String-value of element `idx`
+    #[inline]
+    pub fn element_text(&self, idx: u32) -> Option<&str> {
+        let &(start, end) = self.spans.get(idx as usize)?;
+        self.buffer.get(start as usize..end as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::builder::build_index;
+
+    #[test]
+    fn test_element_ranges() {
+        let input = b"<a>x &amp; <b>y<!--no--><c>z</c></b><![CDATA[<w>]]><d/></a>";
+        let index = build_index(input);
+        let text = PrecomputedText::build(&index, input);
+
+        let id = |name: &str| {
+            (0..index.element_count() as u32)
+                .find(|&idx| index.element_name(idx, input) == Some(name))
+                .unwrap()
+        };
+        assert_eq!(text.element_text(id("a")), Some("x & yz<w>"));
+        assert_eq!(text.element_text(id("b")), Some("yz"));
+        assert_eq!(text.element_text(id("c")), Some("z"));
+        assert_eq!(text.element_text(id("d")), Some(""));
+        assert_eq!(text.element_text(99), None);
+    }
+}
@@ -0,0 +1,222 @@
+//! Text Search
+//!
+//! `find_text` looks for a byte pattern in the document input with
+//! `memchr::memmem` and maps each hit back to the element containing it
+//! through the index. Entity references are not decoded: the pattern is
+//! matched against the bytes as written.
+//!
+//! `regex_search` instead runs a regular expression over the decoded
+//! string-values of selected nodes, spreading the nodes over the rayon pool.
+
+use super::element::NO_NODE;
+use super::structural::StructuralIndex;
+use super::view::encode_element_id;
+use crate::dom::{node_string_value, DocumentAccess, NodeId};
+use lru::LruCache;
+use rayon::prelude::*;
+use regex::Regex;
+use std::num::NonZeroUsize;
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+
+/// Compiled regexes kept across calls, keyed by pattern
+const REGEX_CACHE_CAPACITY: NonZeroUsize = match NonZeroUsize::new(64) {
+    Some(n) => n,
+    None => panic!("REGEX_CACHE_CAPACITY must be non-zero"),
+};
+
+static REGEX_CACHE: Mutex<Option<LruCache<String, Arc<Regex>>>> = Mutex::new(None);
+
+/// Where matches may occur
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SearchOptions {
+    /// Also search attribute values
+    pub attributes: bool,
+    /// Stop after this many matches
+    pub limit: Option<usize>,
+}
+
+/// One occurrence of the pattern
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextMatch {
+    /// Element whose text (or attribute value) contains the match
+    pub element: NodeId,
+    /// Byte offset of the match in the input
+    pub offset: usize,
+}
+
+/// Find non-overlapping occurrences of `pattern` in text and CDATA content
+///
+/// A match must lie entirely within one text node or attribute value;
+/// occurrences in markup, comments and PIs, or spanning a tag, are skipped.
+pub fn find_text(
+    index: &StructuralIndex,
+    input: &[u8],
+    pattern: &[u8],
+    options: SearchOptions,
+) -> Vec<TextMatch> {
+    let mut matches = Vec::new();
+    if pattern.is_empty() || options.limit == Some(0) {
+        return matches;
+    }
+    for offset in memchr::memmem::find_iter(input, pattern) {
+        let end = offset + pattern.len();
+        let element = text_owner(index, offset, end).or_else(|| {
+            options
+                .attributes
+                .then(|| attribute_owner(index, offset, end))
+                .flatten()
+        });
+        if let Some(element) = element {
+            matches.push(TextMatch { element, offset });
+            if options.limit == Some(matches.len()) {
+                break;
+            }
+        }
+    }
+    matches
+}
+
+/// Parent element of the text or CDATA node spanning `start..end`
+fn text_owner(index: &StructuralIndex, start: usize, end: usize) -> Option<NodeId> {
+    // Text nodes are stored in document order, so their offsets ascend
+    let after = index
+        .texts
+        .partition_point(|text| text.span.span.offset as usize <= start);
+    let text = index.texts.get(after.checked_sub(1)?)?;
+    let text_end = text.span.span.offset as usize + text.span.len();
+    let is_content = text.is_text() || text.is_cdata();
+    (is_content && end <= text_end && text.parent != NO_NODE)
+        .then(|| encode_element_id(text.parent))
+}
+
+/// Element owning the attribute value spanning `start..end`
+fn attribute_owner(index: &StructuralIndex, start: usize, end: usize) -> Option<NodeId> {
+    // Attribute values follow their element's name, before the next element
+    let after = index
+        .elements
+        .partition_point(|elem| (elem.name.offset as usize) <= start);
+    let idx = after.checked_sub(1)? as u32;
+    index
+        .element_attributes(idx)
+        .iter()
+        .any(|attr| {
+            let value_start = attr.value.offset as usize;
+            value_start <= start && end <= value_start + attr.value.len as usize
+        })
+        .then(|| encode_element_id(idx))
+}
+
+/// Compile a regular expression, reusing earlier compilations of `pattern`
+pub fn compile_regex(pattern: &str) -> Result<Arc<Regex>, String> {
+    // A poisoned cache is skipped, not fatal
+    if let Ok(mut guard) = REGEX_CACHE.lock() {
+        let cache = guard.get_or_insert_with(|| LruCache::new(REGEX_CACHE_CAPACITY));
+        if let Some(regex) = cache.get(pattern) {
+            return Ok(Arc::clone(regex));
+        }
+    }
+    let regex = Arc::new(Regex::new(pattern).map_err(|e| e.to_string())?);
+    if let Ok(mut guard) = REGEX_CACHE.lock() {
+        let cache = guard.get_or_insert_with(|| LruCache::new(REGEX_CACHE_CAPACITY));
+        cache.put(pattern.to_string(), Arc::clone(&regex));
+    }
+    Ok(regex)
+}
+
+/// Match ranges of `regex` in the string-value of each node
+///
+/// Ranges are byte offsets into the node's decoded string-value. Nodes
+/// without a match are left out; the rest keep the order of `nodes`.
+pub fn regex_search<D: DocumentAccess + Sync>(
+    doc: &D,
+    nodes: &[NodeId],
+    regex: &Regex,
+) -> Vec<(NodeId, Vec<Range<usize>>)> {
+    nodes
+        .par_iter()
+        .filter_map(|&node| {
+            let text = node_string_value(doc, node);
+            let ranges: Vec<Range<usize>> = regex.find_iter(&text).map(|m| m.range()).collect();
+            (!ranges.is_empty()).then_some((node, ranges))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::builder::build_index;
+
+    fn names(index: &StructuralIndex, input: &[u8], matches: &[TextMatch]) -> Vec<String> {
+        matches
+            .iter()
+            .map(|m| index.element_name(m.element, input).unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_matches_map_to_containing_elements() {
+        let input = br#"<doc title="needle"><p>a needle</p><!-- needle --><q><![CDATA[needle]]><needle/></q>needle</doc>"#;
+        let index = build_index(input);
+
+        let found = find_text(&index, input, b"needle", SearchOptions::default());
+        assert_eq!(names(&index, input, &found), vec!["p", "q", "doc"]);
+        assert_eq!(&input[found[0].offset..found[0].offset + 6], b"needle");
+
+        let options = SearchOptions {
+            attributes: true,
+            limit: None,
+        };
+        let found = find_text(&index, input, b"needle", options);
+        assert_eq!(names(&index, input, &found), vec!["doc", "p", "q", "doc"]);
+
+        let options = SearchOptions {
+            attributes: true,
+            limit: Some(2),
+        };
+        assert_eq!(find_text(&index, input, b"needle", options).len(), 2);
+        assert!(find_text(&index, input, b"", SearchOptions::default()).is_empty());
+    }
+
+    #[test]
+    fn test_regex_search_over_string_values() {
+        use crate::index::{IndexedDocumentView, TextDecodeCache};
+        use crate::xpath::{evaluate, XPathValue};
+
+        let input = b"<files><f>MIT &amp; GPL-2.0</f><f>none</f><f>GPL-3.0 <b>only</b></f></files>";
+        let index = build_index(input);
+        let cache = TextDecodeCache::default();
+        let view = IndexedDocumentView::new(&index, input).with_decode_cache(&cache);
+        let Ok(XPathValue::NodeSet(files)) = evaluate(&view, "//f") else {
+            panic!("expected a node-set");
+        };
+
+        let regex = compile_regex(r"GPL-\d\.\d").unwrap();
+        let found = regex_search(&view, &files, &regex);
+        let summary: Vec<(NodeId, Vec<(usize, usize)>)> = found
+            .into_iter()
+            .map(|(node, ranges)| (node, ranges.iter().map(|r| (r.start, r.end)).collect()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![(files[0], vec![(6, 13)]), (files[2], vec![(0, 7)])]
+        );
+
+        // Compiled patterns are reused
+        assert!(Arc::ptr_eq(&regex, &compile_regex(r"GPL-\d\.\d").unwrap()));
+        assert!(compile_regex("(").is_err());
+    }
+
+    #[test]
+    fn test_match_spanning_markup_is_skipped() {
+        let input = b"<r><b>ab</b>cd</r>";
+        let index = build_index(input);
+        assert!(find_text(&index, input, b"b>c", SearchOptions::default()).is_empty());
+        assert!(find_text(&index, input, b"abcd", SearchOptions::default()).is_empty());
+        assert_eq!(
+            find_text(&index, input, b"cd", SearchOptions::default()).len(),
+            1
+        );
+    }
+}
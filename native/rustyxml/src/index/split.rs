@@ -0,0 +1,146 @@
+//! Sharding by Record
+//!
+//! `split` breaks a document into standalone documents of up to N records
+//! each. Every shard starts with the original prolog, reopens the records'
+//! ancestors with their start tags copied as written (so namespace
+//! declarations and envelope attributes carry over), holds its records
+//! byte for byte, and closes the ancestors again. Records sharing a parent
+//! share one copy of it; records under different parents (say, items in
+//! several batches) each get their own ancestor chain.
+
+use super::view::IndexedDocumentView;
+use crate::dom::{DocumentAccess, NodeId, NodeKind};
+use std::ops::Range;
+
+/// Shards of up to `per_shard` records each, in document order
+///
+/// A record inside an earlier record is left to travel with it rather than
+/// being copied twice. Document nodes are ignored.
+pub fn split(view: &IndexedDocumentView, records: &[NodeId], per_shard: usize) -> Vec<Vec<u8>> {
+    let input = view.input;
+    let document = view.document_node_id();
+    let Some(whole) = view.outer_range(document) else {
+        return Vec::new();
+    };
+    let prolog_end = view
+        .children_vec(document)
+        .into_iter()
+        .find(|&child| view.node_kind_of(child) == NodeKind::Element)
+        .and_then(|root| view.outer_range(root))
+        .map_or(whole.start, |root| root.start);
+
+    let mut kept: Vec<(NodeId, Range<usize>)> = Vec::with_capacity(records.len());
+    let mut end = 0;
+    for &record in records {
+        if record == document {
+            continue;
+        }
+        let Some(outer) = view.outer_range(record) else {
+            continue;
+        };
+        if outer.start >= end {
+            end = outer.end;
+            kept.push((record, outer));
+        }
+    }
+
+    kept.chunks(per_shard.max(1))
+        .map(|chunk| {
+            let mut shard = input[whole.start..prolog_end].to_vec();
+            let mut open: Vec<NodeId> = Vec::new();
+            for (record, outer) in chunk {
+                let chain = ancestors(view, *record);
+                let common = open
+                    .iter()
+                    .zip(&chain)
+                    .take_while(|(open, wanted)| open == wanted)
+                    .count();
+                for &ancestor in open[common..].iter().rev() {
+                    write_end_tag(view, &mut shard, ancestor);
+                }
+                open.truncate(common);
+                for &ancestor in &chain[common..] {
+                    if let (Some(outer), Some(inner)) =
+                        (view.outer_range(ancestor), view.inner_range(ancestor))
+                    {
+                        shard.extend_from_slice(&input[outer.start..inner.start]);
+                    }
+                    open.push(ancestor);
+                }
+                shard.extend_from_slice(&input[outer.clone()]);
+            }
+            for &ancestor in open.iter().rev() {
+                write_end_tag(view, &mut shard, ancestor);
+            }
+            shard
+        })
+        .collect()
+}
+
+/// Element ancestors of a node, outermost first
+fn ancestors(view: &IndexedDocumentView, node: NodeId) -> Vec<NodeId> {
+    let mut chain = Vec::new();
+    let mut current = view.parent_of(node);
+    while let Some(id) = current.filter(|&id| view.node_kind_of(id) == NodeKind::Element) {
+        chain.push(id);
+        current = view.parent_of(id);
+    }
+    chain.reverse();
+    chain
+}
+
+fn write_end_tag(view: &IndexedDocumentView, output: &mut Vec<u8>, element: NodeId) {
+    output.extend_from_slice(b"</");
+    output.extend_from_slice(view.node_name(element).unwrap_or_default().as_bytes());
+    output.push(b'>');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::builder::build_index;
+    use crate::xpath::{evaluate, XPathValue};
+
+    fn shards(xml: &str, xpath: &str, per_shard: usize) -> Vec<String> {
+        let index = build_index(xml.as_bytes());
+        let view = IndexedDocumentView::new(&index, xml.as_bytes());
+        let XPathValue::NodeSet(records) = evaluate(&view, xpath).unwrap() else {
+            panic!("expected a node-set");
+        };
+        split(&view, &records, per_shard)
+            .into_iter()
+            .map(|shard| String::from_utf8(shard).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_split_reopens_envelope() {
+        let xml = "<?xml version=\"1.0\"?>\n<x:export xmlns:x='urn:x' v='2'>\
+                   <batch n='1'><item>a</item><item>b</item></batch>\
+                   <batch n='2'><item>c</item></batch></x:export>";
+        assert_eq!(
+            shards(xml, "//item", 2),
+            [
+                "<?xml version=\"1.0\"?>\n<x:export xmlns:x='urn:x' v='2'>\
+                 <batch n='1'><item>a</item><item>b</item></batch></x:export>",
+                "<?xml version=\"1.0\"?>\n<x:export xmlns:x='urn:x' v='2'>\
+                 <batch n='2'><item>c</item></batch></x:export>",
+            ]
+        );
+        assert_eq!(
+            shards(xml, "//item[. != 'b']", 5),
+            ["<?xml version=\"1.0\"?>\n<x:export xmlns:x='urn:x' v='2'>\
+              <batch n='1'><item>a</item></batch><batch n='2'><item>c</item></batch></x:export>"]
+        );
+    }
+
+    #[test]
+    fn test_split_skips_nested_records() {
+        let xml = "<r><s><s>inner</s></s><s>two</s></r>";
+        assert_eq!(
+            shards(xml, "//s", 1),
+            ["<r><s><s>inner</s></s></r>", "<r><s>two</s></r>"]
+        );
+        assert!(shards(xml, "//missing", 1).is_empty());
+    }
+}
@@ -7,6 +7,8 @@
 #![allow(dead_code)]
 
 use super::element::{ChildRef, IndexAttribute, IndexElement, IndexText, NO_NODE};
+use super::names::{NameTable, Symbol};
+use super::vec_bytes;
 
 /// The structural index of an XML document
 ///
@@ -28,11 +30,42 @@ pub struct StructuralIndex {
     children_ranges: Vec<(u32, u32)>, // (start, count) for each element
     /// Flat storage of all children references
     children_data: Vec<ChildRef>,
+    /// Position of each element in children_data (NO_NODE if it has no
+    /// parent), so its siblings are the entries either side
+    element_slots: Vec<u32>,
+    /// Position of each text node in children_data (NO_NODE if top-level)
+    text_slots: Vec<u32>,
     /// Root element index (None if document is empty)
     pub root: Option<u32>,
+    /// Comments and PIs outside the root element (text indices, in
+    /// document order); together with the root they are the children of
+    /// the document node
+    pub top_level: Vec<u32>,
+    /// Deepest element nesting, counting the root element as 1
+    pub max_depth: u32,
+    /// The root element is a wrapper around fragment content (see
+    /// `builder::wrap_fragment`); views hide it
+    pub fragment: bool,
+    /// Built from input that was cut off (see `builder::build_salvage_index`)
+    pub truncated: bool,
+    /// Interned element/attribute names (empty if built without IndexBuilder)
+    pub names: NameTable,
 }
 
 impl StructuralIndex {
+    /// Bytes allocated by the node arrays and name table
+    pub fn heap_bytes(&self) -> usize {
+        vec_bytes(&self.elements)
+            + vec_bytes(&self.texts)
+            + vec_bytes(&self.attributes)
+            + vec_bytes(&self.children_ranges)
+            + vec_bytes(&self.children_data)
+            + vec_bytes(&self.element_slots)
+            + vec_bytes(&self.text_slots)
+            + vec_bytes(&self.top_level)
+            + self.names.heap_bytes()
+    }
+
     /// Create a new empty structural index
     pub fn new() -> Self {
         Self {
@@ -41,7 +74,14 @@ impl StructuralIndex {
             attributes: Vec::with_capacity(256),
             children_ranges: Vec::with_capacity(256),
             children_data: Vec::with_capacity(512),
+            element_slots: Vec::new(),
+            text_slots: Vec::new(),
             root: None,
+            top_level: Vec::new(),
+            max_depth: 0,
+            fragment: false,
+            truncated: false,
+            names: NameTable::default(),
         }
     }
 
@@ -55,7 +95,15 @@ impl StructuralIndex {
             children_ranges: Vec::new(),
             // children_data is rebuilt in build_children_from_parents, start empty
             children_data: Vec::new(),
+            // Slots are filled in alongside children_data
+            element_slots: Vec::new(),
+            text_slots: Vec::new(),
             root: None,
+            top_level: Vec::new(),
+            max_depth: 0,
+            fragment: false,
+            truncated: false,
+            names: NameTable::with_capacity(elements, attributes),
         }
     }
 
@@ -147,6 +195,61 @@ impl StructuralIndex {
         None
     }
 
+    /// Get attribute value by interned name symbol
+    pub fn get_attribute_by_symbol<'a>(
+        &self,
+        elem_idx: u32,
+        symbol: Symbol,
+        input: &'a [u8],
+    ) -> Option<&'a str> {
+        let elem = self.get_element(elem_idx)?;
+        let start = elem.attr_start;
+        let end = start + elem.attr_count as u32;
+        (start..end)
+            .find(|&i| self.names.attribute_name(i) == Some(symbol))
+            .and_then(|i| self.attributes.get(i as usize)?.value.as_str(input))
+    }
+
+    /// Every attribute named `name` (compared as written, prefix included)
+    /// with the index of the element carrying it, in document order
+    pub fn attribute_occurrences<'a>(
+        &'a self,
+        name: &str,
+        input: &[u8],
+    ) -> Vec<(u32, &'a IndexAttribute)> {
+        let symbol = if self.has_name_table() {
+            match self.names.lookup(name, input) {
+                Some(symbol) => Some(symbol),
+                None => return Vec::new(),
+            }
+        } else {
+            None
+        };
+        let mut found = Vec::new();
+        for (elem_idx, elem) in self.elements.iter().enumerate() {
+            let start = elem.attr_start;
+            for i in start..start + elem.attr_count as u32 {
+                let Some(attr) = self.attributes.get(i as usize) else {
+                    break;
+                };
+                let matches = match symbol {
+                    Some(symbol) => self.names.attribute_name(i) == Some(symbol),
+                    None => attr.name.slice(input) == name.as_bytes(),
+                };
+                if matches {
+                    found.push((elem_idx as u32, attr));
+                }
+            }
+        }
+        found
+    }
+
+    /// Whether element/attribute names were interned at build time
+    #[inline]
+    pub fn has_name_table(&self) -> bool {
+        !self.names.is_empty()
+    }
+
     /// Get all attribute name-value pairs for an element
     pub fn get_attribute_pairs<'a>(
         &self,
@@ -248,6 +351,26 @@ impl StructuralIndex {
         }
     }
 
+    /// Sibling `step` positions away from a node, among its parent's
+    /// children (None for nodes without a parent element)
+    pub fn sibling(&self, node: ChildRef, step: isize) -> Option<ChildRef> {
+        let (slots, parent) = if node.is_text() {
+            (&self.text_slots, self.get_text(node.index())?.parent)
+        } else {
+            (&self.element_slots, self.get_element(node.index())?.parent)
+        };
+        let slot = *slots.get(node.index() as usize)?;
+        if slot == NO_NODE {
+            return None;
+        }
+        let (start, count) = *self.children_ranges.get(parent as usize)?;
+        let pos = (slot as usize).checked_add_signed(step)?;
+        if pos < start as usize || pos >= (start + count) as usize {
+            return None;
+        }
+        self.children_data.get(pos).copied()
+    }
+
     /// Get total number of elements
     #[inline]
     pub fn element_count(&self) -> usize {
@@ -304,6 +427,24 @@ impl StructuralIndex {
             let count = children.len() as u32;
             self.children_data.extend(children);
             *range = (start, count);
+            self.element_slots.resize(self.elements.len(), NO_NODE);
+            self.text_slots.resize(self.texts.len(), NO_NODE);
+            for pos in start..start + count {
+                self.set_slot(pos);
+            }
+        }
+    }
+
+    /// Record where the child at `pos` in children_data sits
+    fn set_slot(&mut self, pos: u32) {
+        let child = self.children_data[pos as usize];
+        let slots = if child.is_text() {
+            &mut self.text_slots
+        } else {
+            &mut self.element_slots
+        };
+        if let Some(slot) = slots.get_mut(child.index() as usize) {
+            *slot = pos;
         }
     }
 
@@ -335,6 +476,9 @@ impl StructuralIndex {
         self.attributes.shrink_to_fit();
         self.children_ranges.shrink_to_fit();
         self.children_data.shrink_to_fit();
+        self.element_slots.shrink_to_fit();
+        self.text_slots.shrink_to_fit();
+        self.names.shrink_to_fit();
     }
 
     /// Build children from parent links
@@ -423,6 +567,14 @@ impl StructuralIndex {
                 });
             }
         }
+
+        // Record each child's position so sibling lookups don't have to
+        // search the parent's children
+        self.element_slots = vec![NO_NODE; num_elements];
+        self.text_slots = vec![NO_NODE; self.texts.len()];
+        for pos in 0..total {
+            self.set_slot(pos);
+        }
     }
 }
 
@@ -488,6 +640,9 @@ impl<'a> Iterator for DescendantIter<'a> {
 }
 
 /// Find elements by name (simple query)
+///
+/// Both lookups compare interned symbols when the index has a name table,
+/// falling back to byte comparison for hand-built indexes.
 impl StructuralIndex {
     /// Find all elements with the given name
     pub fn find_elements_by_name<'a>(
@@ -496,11 +651,18 @@ impl StructuralIndex {
         input: &'a [u8],
     ) -> impl Iterator<Item = u32> + 'a {
         let name_bytes = name.as_bytes();
+        let symbol = self
+            .has_name_table()
+            .then(|| self.names.lookup(name, input));
         self.elements
             .iter()
             .enumerate()
             .filter_map(move |(idx, elem)| {
-                if elem.name.slice(input) == name_bytes {
+                let matches = match symbol {
+                    Some(sym) => self.names.element_name(idx as u32) == sym,
+                    None => elem.name.slice(input) == name_bytes,
+                };
+                if matches {
                     Some(idx as u32)
                 } else {
                     None
@@ -515,10 +677,17 @@ impl StructuralIndex {
         input: &'a [u8],
     ) -> impl Iterator<Item = u32> + 'a {
         let local_bytes = local_name.as_bytes();
+        let symbol = self
+            .has_name_table()
+            .then(|| self.names.lookup(local_name, input));
         self.elements
             .iter()
             .enumerate()
             .filter_map(move |(idx, elem)| {
+                if let Some(sym) = symbol {
+                    return (self.names.element_local_name(idx as u32) == sym)
+                        .then_some(idx as u32);
+                }
                 let name = elem.name.slice(input);
                 // Check for prefix:localname or just localname
                 let local = if let Some(pos) = name.iter().position(|&b| b == b':') {
@@ -618,4 +787,20 @@ mod tests {
         assert_eq!(index.get_attribute(1, "id", input), Some("value"));
         assert_eq!(index.get_attribute(1, "missing", input), None);
     }
+
+    #[test]
+    fn test_attribute_occurrences() {
+        let input =
+            b"<r href='a'><a href='b' x:href='c' xmlns:x='u'/><img src='d'/><b href='e'/></r>";
+        let index = crate::index::builder::build_index(input);
+        let found: Vec<(u32, &[u8])> = index
+            .attribute_occurrences("href", input)
+            .into_iter()
+            .map(|(elem, attr)| (elem, attr.value.slice(input)))
+            .collect();
+        assert_eq!(found, [(0, &b"a"[..]), (1, b"b"), (3, b"e")]);
+        assert_eq!(index.attribute_occurrences("x:href", input).len(), 1);
+        assert!(index.attribute_occurrences("img", input).is_empty());
+        assert!(index.attribute_occurrences("missing", input).is_empty());
+    }
 }
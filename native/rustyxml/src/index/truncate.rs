@@ -0,0 +1,213 @@
+//! Size-Bounded Truncation
+//!
+//! `truncate` cuts a document down to a byte budget for logging: nodes are
+//! copied as written while they fit, an element that doesn't fit is entered
+//! so its leading children can still be kept, and at the first node that
+//! fits neither way a `<!-- truncated -->` comment is written and every
+//! open element is closed. Only whole nodes are dropped, so entity
+//! references and multi-byte characters are never split. A DOCTYPE is
+//! always kept, since the content may refer to the entities it declares.
+
+use super::view::IndexedDocumentView;
+use crate::dom::{DocumentAccess, NodeId, NodeKind};
+
+/// Comment written where content was dropped
+const MARKER: &[u8] = b"<!-- truncated -->";
+
+/// An element being copied, whose end tag is still owed
+struct Open<'a> {
+    children: Vec<NodeId>,
+    next: usize,
+    /// End of the element's content (where its end tag starts)
+    content_end: usize,
+    /// End of its end tag
+    end: usize,
+    /// None for the document node
+    name: Option<&'a str>,
+}
+
+/// The document as well-formed XML of at most `max_bytes` bytes
+///
+/// A document that fits is returned as it is. Otherwise the result ends
+/// with `MARKER` (inside the innermost element still open), and whitespace
+/// between nodes, a prolog without a DOCTYPE, and end tags written with
+/// extra whitespace may be dropped or respelled to fit. Returns None if the
+/// budget can't hold the DOCTYPE's prolog, the root element's tags and the
+/// marker.
+pub fn truncate(view: &IndexedDocumentView, max_bytes: usize) -> Option<Vec<u8>> {
+    let input = view.input;
+    let document = view.document_node_id();
+    let whole = view.outer_range(document)?;
+    if whole.len() <= max_bytes {
+        return Some(input[whole].to_vec());
+    }
+
+    let mut output = Vec::with_capacity(max_bytes);
+    let mut copied = whole.start;
+    // The marker plus the end tags of the open elements
+    let mut reserved = MARKER.len();
+    let mut has_root = false;
+    let root_start = view
+        .root_element_id()
+        .and_then(|root| view.outer_range(root))
+        .map_or(whole.end, |root| root.start);
+    let prolog = &input[whole.start..root_start];
+    if memchr::memmem::find(prolog, b"<!DOCTYPE").is_some() {
+        if prolog.len() + reserved > max_bytes {
+            return None;
+        }
+        output.extend_from_slice(prolog);
+        copied = root_start;
+    }
+    let mut stack = vec![Open {
+        children: view.children_vec(document),
+        next: 0,
+        content_end: whole.end,
+        end: whole.end,
+        name: None,
+    }];
+
+    while let Some(open) = stack.last_mut() {
+        let budget = max_bytes.checked_sub(output.len() + reserved)?;
+        let Some(&child) = open.children.get(open.next) else {
+            if open.content_end - copied <= budget {
+                output.extend_from_slice(&input[copied..open.content_end]);
+            }
+            if let Some(name) = open.name {
+                write_end_tag(&mut output, name);
+                reserved -= name.len() + 3;
+            }
+            copied = open.end;
+            stack.pop();
+            continue;
+        };
+        open.next += 1;
+        let at_top = stack.len() == 1;
+        let is_element = view.node_kind_of(child) == NodeKind::Element;
+
+        let outer = view.outer_range(child)?;
+        // Already copied with the prolog
+        if outer.end <= copied {
+            continue;
+        }
+        if outer.len() <= budget {
+            // Whitespace and prolog before the node go too if they fit
+            let from = if outer.end - copied <= budget {
+                copied
+            } else {
+                outer.start
+            };
+            output.extend_from_slice(&input[from..outer.end]);
+            copied = outer.end;
+            has_root |= at_top && is_element;
+            continue;
+        }
+
+        let inner = view.inner_range(child)?;
+        let name = view.node_name(child).unwrap_or_default();
+        let tags = (inner.start - outer.start) + name.len() + 3;
+        // Self-closing elements have no end tag to write
+        if !is_element || inner.start == outer.end || tags > budget {
+            break;
+        }
+        let from = if inner.start - copied + name.len() + 3 <= budget {
+            copied
+        } else {
+            outer.start
+        };
+        output.extend_from_slice(&input[from..inner.start]);
+        copied = inner.start;
+        reserved += name.len() + 3;
+        has_root |= at_top;
+        stack.push(Open {
+            children: view.children_vec(child),
+            next: 0,
+            content_end: inner.end,
+            end: outer.end,
+            name: Some(name),
+        });
+    }
+
+    if !has_root {
+        return None;
+    }
+    output.extend_from_slice(MARKER);
+    for open in stack.iter().rev() {
+        if let Some(name) = open.name {
+            write_end_tag(&mut output, name);
+        }
+    }
+    Some(output)
+}
+
+fn write_end_tag(output: &mut Vec<u8>, name: &str) {
+    output.extend_from_slice(b"</");
+    output.extend_from_slice(name.as_bytes());
+    output.push(b'>');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::builder::build_index;
+
+    const XML: &str = "<?xml version=\"1.0\"?>\n<log><a>one</a><b><c>two &amp; more</c>\
+                       <d>three</d></b><e/></log>";
+
+    fn truncated(max_bytes: usize) -> Option<String> {
+        let index = build_index(XML.as_bytes());
+        let view = IndexedDocumentView::new(&index, XML.as_bytes());
+        let output = truncate(&view, max_bytes)?;
+        assert!(
+            output.len() <= max_bytes,
+            "{} > {}",
+            output.len(),
+            max_bytes
+        );
+        assert!(crate::dom::validate_strict(&output).is_ok(), "{:?}", output);
+        Some(String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn test_truncate_to_budget() {
+        assert_eq!(truncated(XML.len()).unwrap(), XML);
+        // The prolog goes before any content does
+        assert_eq!(
+            truncated(XML.len() - 1).unwrap(),
+            "<log><a>one</a><b><c>two &amp; more</c><d>three</d></b><e/></log>\
+             <!-- truncated -->"
+        );
+        assert_eq!(
+            truncated(80).unwrap(),
+            "<?xml version=\"1.0\"?>\n<log><a>one</a><b><c><!-- truncated --></c></b></log>"
+        );
+        assert_eq!(
+            truncated(40).unwrap(),
+            "<log><a>one</a><!-- truncated --></log>"
+        );
+        assert_eq!(truncated(29).unwrap(), "<log><!-- truncated --></log>");
+        assert_eq!(truncated(28), None);
+    }
+
+    #[test]
+    fn test_truncate_keeps_doctype() {
+        let xml = "<!DOCTYPE r [<!ENTITY e \"entity\">]><!-- c --><r><a>&e;</a><b>two</b></r>";
+        let index = build_index(xml.as_bytes());
+        let view = IndexedDocumentView::new(&index, xml.as_bytes());
+        let doctype = xml.find("<r>").unwrap();
+        for max_bytes in 0..xml.len() {
+            match truncate(&view, max_bytes) {
+                Some(output) => {
+                    assert!(output.len() <= max_bytes);
+                    assert!(output.starts_with(&xml.as_bytes()[..doctype]));
+                    assert!(crate::dom::validate_strict(&output).is_ok(), "{output:?}");
+                }
+                None => assert!(max_bytes < doctype + 3 + MARKER.len() + 4),
+            }
+        }
+        assert_eq!(
+            String::from_utf8(truncate(&view, xml.len() - 1).unwrap()).unwrap(),
+            "<!DOCTYPE r [<!ENTITY e \"entity\">]><!-- c --><r><!-- truncated --></r>"
+        );
+    }
+}
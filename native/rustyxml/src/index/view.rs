@@ -3,10 +3,17 @@
 //! Provides a DocumentAccess implementation for StructuralIndex,
 //! enabling XPath evaluation on memory-efficient indexed documents.
 
+use super::attr_values::AttributeValueIndex;
+use super::decoded::TextDecodeCache;
 use super::element::{ChildRef, NO_NODE};
+use super::precomputed::PrecomputedText;
 use super::structural::StructuralIndex;
+use crate::core::normalize::Normalization;
 use crate::dom::node::{NodeId, NodeKind, XmlNode};
-use crate::dom::DocumentAccess;
+use crate::dom::{DocumentAccess, NameSymbol};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::ops::Range;
 
 /// Bit flag for encoding text node IDs
 /// High bit = 1 means text node, high bit = 0 means element
@@ -58,12 +65,361 @@ pub fn is_document_node_id(id: NodeId) -> bool {
 pub struct IndexedDocumentView<'a> {
     pub index: &'a StructuralIndex,
     pub input: &'a [u8],
+    /// Attribute value indexes built for this document, keyed by attribute name
+    pub attr_indexes: Option<&'a HashMap<String, AttributeValueIndex>>,
+    /// Unicode normalization applied to node string-values
+    pub normalization: Normalization,
+    /// Decoded content of text nodes containing entity references
+    pub decode_cache: Option<&'a TextDecodeCache>,
+    /// Element string-values laid out at parse time
+    pub precomputed: Option<&'a PrecomputedText>,
 }
 
 impl<'a> IndexedDocumentView<'a> {
     /// Create a new view
     pub fn new(index: &'a StructuralIndex, input: &'a [u8]) -> Self {
-        Self { index, input }
+        Self {
+            index,
+            input,
+            attr_indexes: None,
+            normalization: Normalization::None,
+            decode_cache: None,
+            precomputed: None,
+        }
+    }
+
+    /// Use prebuilt attribute value indexes for `[@name = 'literal']` lookups
+    pub fn with_attr_indexes(mut self, indexes: &'a HashMap<String, AttributeValueIndex>) -> Self {
+        self.attr_indexes = Some(indexes);
+        self
+    }
+
+    /// Return node string-values in the given normalization form
+    pub fn with_normalization(mut self, normalization: Normalization) -> Self {
+        self.normalization = normalization;
+        self
+    }
+
+    /// Decode entity references in text nodes, caching the results
+    ///
+    /// Without a cache, text nodes return their raw content.
+    pub fn with_decode_cache(mut self, cache: &'a TextDecodeCache) -> Self {
+        self.decode_cache = Some(cache);
+        self
+    }
+
+    /// Answer element string-values from precomputed text
+    pub fn with_precomputed_text(mut self, text: &'a PrecomputedText) -> Self {
+        self.precomputed = Some(text);
+        self
+    }
+
+    /// Byte range of a node's full markup in the input
+    ///
+    /// Elements span from `<` of the start tag to `>` of the end tag; CDATA,
+    /// comments and PIs include their delimiters. The document node covers
+    /// the whole input.
+    pub fn outer_range(&self, id: NodeId) -> Option<Range<usize>> {
+        if is_document_node_id(id) {
+            return self.document_range();
+        }
+        let (is_text, idx) = decode_node_id(id);
+        if is_text {
+            return self.text_outer_range(idx);
+        }
+        let elem = self.index.get_element(idx)?;
+        let start = (elem.name.offset as usize).checked_sub(1)?;
+        Some(start..self.element_end(idx)?)
+    }
+
+    /// Byte range of a node's content in the input
+    ///
+    /// For elements, everything between the start and end tags (empty for
+    /// `<a/>`); for other nodes, the content without delimiters.
+    pub fn inner_range(&self, id: NodeId) -> Option<Range<usize>> {
+        if is_document_node_id(id) {
+            return self.document_range();
+        }
+        let (is_text, idx) = decode_node_id(id);
+        if is_text {
+            let text = self.index.get_text(idx)?;
+            let outer = self.text_outer_range(idx)?;
+            let (open, close) = if text.is_cdata() {
+                (9, 3)
+            } else if text.is_comment() {
+                (4, 3)
+            } else if text.is_pi() {
+                (2, 2)
+            } else {
+                (0, 0)
+            };
+            return Some(outer.start + open..outer.end - close);
+        }
+        let elem = self.index.get_element(idx)?;
+        let content_start = self.start_tag_end(idx)?;
+        if elem.is_empty() {
+            return Some(content_start..content_start);
+        }
+        let end = self.element_end(idx)?;
+        let end_tag_start = self.input[..end].iter().rposition(|&b| b == b'<')?;
+        Some(content_start..end_tag_start)
+    }
+
+    /// Number of child nodes, counting text, CDATA, comments and PIs
+    ///
+    /// None for an unknown node id.
+    pub fn child_count(&self, id: NodeId) -> Option<usize> {
+        if is_document_node_id(id) {
+            return Some(self.document_children().len());
+        }
+        match self.known(id)? {
+            (false, idx) => Some(self.index.child_count(idx)),
+            (true, _) => Some(0),
+        }
+    }
+
+    /// Up to `limit` child nodes starting with the `offset`th (0-based)
+    ///
+    /// Only the page's ids are produced, however many children the node has.
+    /// None for an unknown node id.
+    pub fn children_page(&self, id: NodeId, offset: usize, limit: usize) -> Option<Vec<NodeId>> {
+        if is_document_node_id(id) {
+            let children = self.document_children();
+            return Some(children.into_iter().skip(offset).take(limit).collect());
+        }
+        let page = match self.known(id)? {
+            (false, idx) => self
+                .index
+                .children(idx)
+                .skip(offset)
+                .take(limit)
+                .map(|child| {
+                    if child.is_text() {
+                        encode_text_id(child.index())
+                    } else {
+                        encode_element_id(child.index())
+                    }
+                })
+                .collect(),
+            (true, _) => Vec::new(),
+        };
+        Some(page)
+    }
+
+    /// Number of nodes below a node, of every kind
+    ///
+    /// Elements are stored in document order, so an element's descendants
+    /// run from the next index up to where its (or an ancestor's) next
+    /// sibling starts; the text nodes among them are a run of the text array
+    /// too.
+    pub fn descendant_count(&self, id: NodeId) -> Option<usize> {
+        if is_document_node_id(id) {
+            return self
+                .document_children()
+                .into_iter()
+                .map(|child| self.descendant_count(child).map(|count| count + 1))
+                .sum();
+        }
+        let (is_text, idx) = self.known(id)?;
+        if is_text {
+            return Some(0);
+        }
+        let mut current = idx;
+        let end = loop {
+            if let Some(next) = self.index.next_sibling(current) {
+                break next;
+            }
+            match self.index.parent(current) {
+                Some(parent) => current = parent,
+                None => break self.index.element_count() as u32,
+            }
+        };
+        let offset = self.index.get_element(idx)?.name.offset;
+        let first_text = self
+            .index
+            .texts
+            .partition_point(|text| text.span.span.offset < offset);
+        let texts = self.index.texts[first_text..]
+            .iter()
+            .take_while(|text| text.parent >= idx && text.parent < end)
+            .count();
+        Some((end - idx - 1) as usize + texts)
+    }
+
+    /// Number of ancestors of a node, the document node included
+    ///
+    /// The document node is at depth 0 and the root element at depth 1.
+    pub fn depth(&self, id: NodeId) -> Option<usize> {
+        if is_document_node_id(id) {
+            return Some(0);
+        }
+        let (is_text, idx) = self.known(id)?;
+        if !is_text {
+            let depth = self.index.get_element(idx)?.depth;
+            // The stored depth saturates; count deeper elements' ancestors
+            if depth < u16::MAX {
+                // A fragment's elements sit one level below its wrapper
+                return Some(depth as usize + usize::from(!self.index.fragment));
+            }
+        }
+        let mut depth = 0;
+        let mut current = self.parent_of(id);
+        while let Some(node) = current {
+            depth += 1;
+            current = self.parent_of(node);
+        }
+        Some(depth)
+    }
+
+    /// 1-based position of a node among its parent's children, of every kind
+    ///
+    /// None for the document node and unknown node ids.
+    pub fn position(&self, id: NodeId) -> Option<usize> {
+        let (is_text, idx) = self.known(id)?;
+        let parent = if is_text {
+            self.index.get_text(idx)?.parent
+        } else {
+            self.index.get_element(idx)?.parent
+        };
+        let position = if parent == NO_NODE {
+            self.document_children()
+                .iter()
+                .position(|&child| child == id)?
+        } else {
+            let target = if is_text {
+                ChildRef::text(idx)
+            } else {
+                ChildRef::element(idx)
+            };
+            self.index
+                .children(parent)
+                .position(|child| child.raw() == target.raw())?
+        };
+        Some(position + 1)
+    }
+
+    /// Decode a node id naming an element or text node of this index
+    ///
+    /// None for the document node, out-of-range indexes and a fragment's
+    /// hidden wrapper.
+    fn known(&self, id: NodeId) -> Option<(bool, u32)> {
+        if is_document_node_id(id) {
+            return None;
+        }
+        let (is_text, idx) = decode_node_id(id);
+        let known = if is_text {
+            (idx as usize) < self.index.text_count()
+        } else {
+            (idx as usize) < self.index.element_count() && self.fragment_wrapper() != Some(idx)
+        };
+        known.then_some((is_text, idx))
+    }
+
+    /// Position just after the `>` of an element's start tag
+    fn start_tag_end(&self, idx: u32) -> Option<usize> {
+        let elem = self.index.get_element(idx)?;
+        // Attribute values may contain `>`, so search after the last one
+        let from = match self.index.element_attributes(idx).last() {
+            Some(attr) => attr.value.offset as usize + attr.value.len as usize + 1,
+            None => elem.name.offset as usize + elem.name.len as usize,
+        };
+        memchr::memchr(b'>', self.input.get(from..)?).map(|i| from + i + 1)
+    }
+
+    /// Position just after the `>` of an element's end tag (or of `/>`)
+    ///
+    /// Walks down the chain of last children first, then finds each end tag
+    /// after the end of the node it encloses, so nested elements with the
+    /// same name cannot be mistaken for each other.
+    fn element_end(&self, idx: u32) -> Option<usize> {
+        let mut chain = vec![idx];
+        let mut pos = loop {
+            let current = *chain.last()?;
+            let elem = self.index.get_element(current)?;
+            if elem.is_empty() {
+                break self.start_tag_end(current)?;
+            }
+            match self.index.children(current).last() {
+                Some(child) if child.is_element() => chain.push(child.index()),
+                Some(child) => break self.text_outer_range(child.index())?.end,
+                None => break self.start_tag_end(current)?,
+            }
+        };
+
+        for &elem_idx in chain.iter().rev() {
+            let elem = self.index.get_element(elem_idx)?;
+            if elem.is_empty() {
+                continue;
+            }
+            pos = self.end_tag_after(elem.name.slice(self.input), pos)?;
+        }
+        Some(pos)
+    }
+
+    /// Find `</name>` at or after `from`, returning the position after its `>`
+    fn end_tag_after(&self, name: &[u8], mut from: usize) -> Option<usize> {
+        loop {
+            let lt = from + memchr::memmem::find(self.input.get(from..)?, b"</")?;
+            let name_end = lt + 2 + name.len();
+            let matches = self.input.get(lt + 2..name_end) == Some(name)
+                && matches!(
+                    self.input.get(name_end),
+                    Some(b'>' | b' ' | b'\t' | b'\r' | b'\n')
+                );
+            if matches {
+                let gt = memchr::memchr(b'>', &self.input[name_end..])?;
+                return Some(name_end + gt + 1);
+            }
+            from = lt + 2;
+        }
+    }
+
+    /// Byte range of a text, CDATA, comment or PI node including delimiters
+    fn text_outer_range(&self, idx: u32) -> Option<Range<usize>> {
+        let text = self.index.get_text(idx)?;
+        let start = text.span.span.offset as usize;
+        let end = start + text.span.slice(self.input).len();
+        if text.is_cdata() {
+            Some(start.checked_sub(9)?..end + 3)
+        } else if text.is_comment() {
+            Some(start.checked_sub(4)?..end + 3)
+        } else if text.is_pi() {
+            // The span covers the target; the data runs up to `?>`
+            let close = memchr::memmem::find(self.input.get(end..)?, b"?>")?;
+            Some(start.checked_sub(2)?..end + close + 2)
+        } else {
+            Some(start..end)
+        }
+    }
+
+    /// Byte range of the document node: the whole input, or the content
+    /// inside the wrapper of a fragment
+    fn document_range(&self) -> Option<Range<usize>> {
+        match self.fragment_wrapper() {
+            Some(wrapper) => self.inner_range(encode_element_id(wrapper)),
+            None => Some(0..self.input.len()),
+        }
+    }
+
+    /// Index of the hidden wrapper element when the index holds a fragment
+    #[inline]
+    fn fragment_wrapper(&self) -> Option<u32> {
+        if self.index.fragment {
+            self.index.root
+        } else {
+            None
+        }
+    }
+
+    /// NodeId of a parent element, mapping a fragment's wrapper to the
+    /// document node
+    #[inline]
+    fn parent_node_id(&self, parent_idx: u32) -> NodeId {
+        if self.fragment_wrapper() == Some(parent_idx) {
+            DOCUMENT_NODE_ID
+        } else {
+            encode_element_id(parent_idx)
+        }
     }
 
     /// Get the node kind for a given NodeId
@@ -96,7 +452,15 @@ impl<'a> IndexedDocumentView<'a> {
 
 impl<'a> DocumentAccess for IndexedDocumentView<'a> {
     fn root_element_id(&self) -> Option<NodeId> {
-        self.index.root.map(encode_element_id)
+        // A fragment's root element is its first top-level element
+        match self.fragment_wrapper() {
+            Some(wrapper) => self
+                .index
+                .children(wrapper)
+                .find(|child| !child.is_text())
+                .map(|child| encode_element_id(child.index())),
+            None => self.index.root.map(encode_element_id),
+        }
     }
 
     /// Get a node by ID - returns None for indexed views
@@ -126,7 +490,8 @@ impl<'a> DocumentAccess for IndexedDocumentView<'a> {
     fn node_local_name(&self, id: NodeId) -> Option<&str> {
         let (is_text, idx) = decode_node_id(id);
         if is_text {
-            None
+            // A PI's local name is its target
+            self.node_name(id)
         } else {
             // Get the full name and strip prefix
             let name = self.index.element_name(idx, self.input)?;
@@ -143,10 +508,30 @@ impl<'a> DocumentAccess for IndexedDocumentView<'a> {
         None
     }
 
-    fn text_content(&self, id: NodeId) -> Option<&str> {
+    fn text_content(&self, id: NodeId) -> Option<Cow<'_, str>> {
         let (is_text, idx) = decode_node_id(id);
-        if is_text {
-            self.index.text_content(idx, self.input)
+        if !is_text {
+            return None;
+        }
+        match (self.decode_cache, self.index.get_text(idx)) {
+            (Some(cache), Some(text)) if text.needs_decode() => {
+                Some(cache.get_or_decode(idx, text.span.slice(self.input)))
+            }
+            _ => self.index.text_content(idx, self.input).map(Cow::Borrowed),
+        }
+    }
+
+    fn node_data(&self, id: NodeId) -> Option<&str> {
+        let (is_text, idx) = decode_node_id(id);
+        let text = self.index.get_text(idx).filter(|_| is_text)?;
+        if text.is_comment() {
+            text.span.as_str(self.input)
+        } else if text.is_pi() {
+            // Data follows the target and the whitespace after it
+            let target_end = text.span.span.offset as usize + text.span.slice(self.input).len();
+            let data = &self.input[target_end..self.inner_range(id)?.end];
+            let start = data.iter().position(|b| !b.is_ascii_whitespace());
+            std::str::from_utf8(&data[start.unwrap_or(data.len())..]).ok()
         } else {
             None
         }
@@ -171,9 +556,8 @@ impl<'a> DocumentAccess for IndexedDocumentView<'a> {
     }
 
     fn children_vec(&self, id: NodeId) -> Vec<NodeId> {
-        // Handle document node - its only child is the root element
         if is_document_node_id(id) {
-            return self.root_element_id().into_iter().collect();
+            return self.document_children();
         }
 
         let (is_text, idx) = decode_node_id(id);
@@ -195,24 +579,30 @@ impl<'a> DocumentAccess for IndexedDocumentView<'a> {
     }
 
     fn descendants_vec(&self, id: NodeId) -> Vec<NodeId> {
-        // Handle document node - descendants include root and all its descendants
+        // Handle document node - descendants include the root, all its
+        // descendants and any comments/PIs around it
         if is_document_node_id(id) {
-            if let Some(root_id) = self.root_element_id() {
-                // Pre-allocate for all nodes: root + all elements + all texts
-                let cap = 1 + self.index.element_count() + self.index.text_count();
-                let mut result = Vec::with_capacity(cap);
-                result.push(root_id);
-                let (_, root_idx) = decode_node_id(root_id);
-                for child in self.index.descendants(root_idx) {
+            if let Some(wrapper) = self.fragment_wrapper() {
+                return self.descendants_vec(encode_element_id(wrapper));
+            }
+            // Pre-allocate for all nodes: root + all elements + all texts
+            let cap = 1 + self.index.element_count() + self.index.text_count();
+            let mut result = Vec::with_capacity(cap);
+            for top in self.document_children() {
+                result.push(top);
+                let (is_text, idx) = decode_node_id(top);
+                if is_text {
+                    continue;
+                }
+                for child in self.index.descendants(idx) {
                     if child.is_text() {
                         result.push(encode_text_id(child.index()));
                     } else {
                         result.push(encode_element_id(child.index()));
                     }
                 }
-                return result;
             }
-            return Vec::new();
+            return result;
         }
 
         let (is_text, idx) = decode_node_id(id);
@@ -234,6 +624,38 @@ impl<'a> DocumentAccess for IndexedDocumentView<'a> {
         }
     }
 
+    fn any_descendant(&self, id: NodeId, visit: &mut dyn FnMut(NodeId) -> bool) -> bool {
+        let mut visited = 0;
+        let mut visit_child = |child: ChildRef| {
+            visited += 1;
+            visit(if child.is_text() {
+                encode_text_id(child.index())
+            } else {
+                encode_element_id(child.index())
+            })
+        };
+        let found = if is_document_node_id(id) {
+            match self.fragment_wrapper() {
+                Some(wrapper) => self.index.descendants(wrapper).any(&mut visit_child),
+                None => self.document_children().into_iter().any(|top| {
+                    let (is_text, idx) = decode_node_id(top);
+                    visit_child(if is_text {
+                        ChildRef::text(idx)
+                    } else {
+                        ChildRef::element(idx)
+                    }) || (!is_text && self.index.descendants(idx).any(&mut visit_child))
+                }),
+            }
+        } else {
+            match decode_node_id(id) {
+                (false, idx) => self.index.descendants(idx).any(&mut visit_child),
+                (true, _) => false,
+            }
+        };
+        crate::stats::record_nodes_visited(visited);
+        found
+    }
+
     // === Navigation methods for XPath axes ===
 
     fn parent_of(&self, id: NodeId) -> Option<NodeId> {
@@ -244,18 +666,21 @@ impl<'a> DocumentAccess for IndexedDocumentView<'a> {
 
         let (is_text, idx) = decode_node_id(id);
         if is_text {
-            // Text node's parent is stored in IndexText
+            // Text node's parent is stored in IndexText; comments and PIs
+            // outside the root element belong to the document node
             self.index.get_text(idx).and_then(|text| {
-                if text.parent == NO_NODE {
-                    None
+                if text.parent != NO_NODE {
+                    Some(self.parent_node_id(text.parent))
+                } else if text.is_comment() || text.is_pi() {
+                    Some(DOCUMENT_NODE_ID)
                 } else {
-                    Some(encode_element_id(text.parent))
+                    None
                 }
             })
         } else {
             // Element's parent - root element's parent is the document node
             match self.index.parent(idx) {
-                Some(parent_idx) => Some(encode_element_id(parent_idx)),
+                Some(parent_idx) => Some(self.parent_node_id(parent_idx)),
                 None => {
                     // This element has no parent in the index - it's the root element
                     // Return the document node as its parent
@@ -273,6 +698,21 @@ impl<'a> DocumentAccess for IndexedDocumentView<'a> {
         DOCUMENT_NODE_ID
     }
 
+    fn document_order_key(&self, id: NodeId) -> u64 {
+        // Elements and text nodes live in separate arrays, so order by the
+        // byte offset where each starts (the document node comes first)
+        if is_document_node_id(id) {
+            return 0;
+        }
+        let (is_text, idx) = decode_node_id(id);
+        let offset = if is_text {
+            self.index.get_text(idx).map(|t| t.span.span.offset)
+        } else {
+            self.index.get_element(idx).map(|e| e.name.offset)
+        };
+        offset.map_or(u64::MAX, |offset| offset as u64 + 1)
+    }
+
     fn next_sibling_of(&self, id: NodeId) -> Option<NodeId> {
         let (is_text, idx) = decode_node_id(id);
         if is_text {
@@ -280,7 +720,9 @@ impl<'a> DocumentAccess for IndexedDocumentView<'a> {
             // We need to find the text node in its parent's children and get the next
             self.find_next_sibling_of_text(idx)
         } else {
-            self.index.next_sibling(idx).map(encode_element_id)
+            // The element's next_sibling link skips text nodes
+            let elem = self.index.get_element(idx)?;
+            self.sibling(id, ChildRef::element(idx), elem.parent, 1)
         }
     }
 
@@ -297,86 +739,137 @@ impl<'a> DocumentAccess for IndexedDocumentView<'a> {
     fn node_kind_of(&self, id: NodeId) -> NodeKind {
         self.node_kind(id)
     }
-}
 
-impl<'a> IndexedDocumentView<'a> {
-    /// Find the next sibling of a text node
-    fn find_next_sibling_of_text(&self, text_idx: u32) -> Option<NodeId> {
-        let text = self.index.get_text(text_idx)?;
-        if text.parent == NO_NODE {
-            return None;
+    fn name_symbol(&self, name: &str) -> NameSymbol {
+        if !self.index.has_name_table() {
+            return NameSymbol::Unavailable;
+        }
+        match self.index.names.lookup(name, self.input) {
+            Some(sym) => NameSymbol::Symbol(sym),
+            None => NameSymbol::Absent,
         }
+    }
 
-        let children: Vec<_> = self.index.children(text.parent).collect();
-        let target = ChildRef::text(text_idx);
+    fn node_local_name_symbol(&self, id: NodeId) -> Option<u32> {
+        let (is_text, idx) = decode_node_id(id);
+        if is_text {
+            None
+        } else {
+            self.index.names.element_local_name(idx)
+        }
+    }
 
-        for (i, child) in children.iter().enumerate() {
-            if child.raw() == target.raw() {
-                // Found this text node, return the next sibling
-                if i + 1 < children.len() {
-                    let next = &children[i + 1];
-                    return Some(if next.is_text() {
-                        encode_text_id(next.index())
-                    } else {
-                        encode_element_id(next.index())
-                    });
-                }
-            }
+    fn get_attribute_by_symbol(&self, node_id: NodeId, symbol: u32) -> Option<&str> {
+        let (is_text, idx) = decode_node_id(node_id);
+        if is_text {
+            None
+        } else {
+            self.index.get_attribute_by_symbol(idx, symbol, self.input)
         }
-        None
     }
 
-    /// Find the previous sibling of a text node
-    fn find_prev_sibling_of_text(&self, text_idx: u32) -> Option<NodeId> {
-        let text = self.index.get_text(text_idx)?;
-        if text.parent == NO_NODE {
+    fn attribute_index_lookup(&self, name: &str, value: Option<&str>) -> Option<&[NodeId]> {
+        let attrs = self.attr_indexes?.get(name)?;
+        Some(match value {
+            Some(value) => attrs.lookup(value),
+            None => attrs.with_attribute(),
+        })
+    }
+
+    fn text_normalization(&self) -> Normalization {
+        self.normalization
+    }
+
+    fn precomputed_string_value(&self, node_id: NodeId) -> Option<&str> {
+        let (is_text, idx) = decode_node_id(node_id);
+        if is_text || is_document_node_id(node_id) {
             return None;
         }
+        self.precomputed?.element_text(idx)
+    }
+}
+
+impl<'a> IndexedDocumentView<'a> {
+    /// Children of the document node: the root element with any comments
+    /// and PIs before and after it, or the top-level nodes of a fragment
+    fn document_children(&self) -> Vec<NodeId> {
+        if let Some(wrapper) = self.fragment_wrapper() {
+            return self.children_vec(encode_element_id(wrapper));
+        }
+        let Some(root) = self.root_element_id() else {
+            return Vec::new();
+        };
+        if self.index.top_level.is_empty() {
+            return vec![root];
+        }
+        let mut children: Vec<NodeId> = self
+            .index
+            .top_level
+            .iter()
+            .map(|&idx| encode_text_id(idx))
+            .collect();
+        let root_key = self.document_order_key(root);
+        let at = children.partition_point(|&id| self.document_order_key(id) < root_key);
+        children.insert(at, root);
+        children
+    }
 
-        let children: Vec<_> = self.index.children(text.parent).collect();
-        let target = ChildRef::text(text_idx);
+    /// Sibling `step` positions away from a child of the document node
+    fn document_sibling(&self, id: NodeId, step: isize) -> Option<NodeId> {
+        if self.index.fragment {
+            return None;
+        }
+        let children = self.document_children();
+        let pos = children.iter().position(|&child| child == id)?;
+        children.get(pos.checked_add_signed(step)?).copied()
+    }
 
-        for (i, child) in children.iter().enumerate() {
-            if child.raw() == target.raw() {
-                // Found this text node, return the previous sibling
-                if i > 0 {
-                    let prev = &children[i - 1];
-                    return Some(if prev.is_text() {
-                        encode_text_id(prev.index())
-                    } else {
-                        encode_element_id(prev.index())
-                    });
-                }
-            }
+    /// Sibling `step` positions away from a node, looked up through the
+    /// index's child slots
+    fn sibling(&self, id: NodeId, child: ChildRef, parent: u32, step: isize) -> Option<NodeId> {
+        if parent == NO_NODE {
+            return self.document_sibling(id, step);
         }
-        None
+        let sibling = self.index.sibling(child, step)?;
+        Some(if sibling.is_text() {
+            encode_text_id(sibling.index())
+        } else {
+            encode_element_id(sibling.index())
+        })
+    }
+
+    /// Find the next sibling of a text node
+    fn find_next_sibling_of_text(&self, text_idx: u32) -> Option<NodeId> {
+        let text = self.index.get_text(text_idx)?;
+        self.sibling(
+            encode_text_id(text_idx),
+            ChildRef::text(text_idx),
+            text.parent,
+            1,
+        )
+    }
+
+    /// Find the previous sibling of a text node
+    fn find_prev_sibling_of_text(&self, text_idx: u32) -> Option<NodeId> {
+        let text = self.index.get_text(text_idx)?;
+        self.sibling(
+            encode_text_id(text_idx),
+            ChildRef::text(text_idx),
+            text.parent,
+            -1,
+        )
     }
 
     /// Find the previous sibling of an element
     /// Elements have next_sibling links but not prev_sibling links in the index
     fn find_prev_sibling_of_element(&self, elem_idx: u32) -> Option<NodeId> {
         let elem = self.index.get_element(elem_idx)?;
-        if elem.parent == NO_NODE {
-            return None;
-        }
-
-        let children: Vec<_> = self.index.children(elem.parent).collect();
-        let target = ChildRef::element(elem_idx);
-
-        for (i, child) in children.iter().enumerate() {
-            if child.raw() == target.raw() {
-                // Found this element, return the previous sibling
-                if i > 0 {
-                    let prev = &children[i - 1];
-                    return Some(if prev.is_text() {
-                        encode_text_id(prev.index())
-                    } else {
-                        encode_element_id(prev.index())
-                    });
-                }
-            }
-        }
-        None
+        self.sibling(
+            encode_element_id(elem_idx),
+            ChildRef::element(elem_idx),
+            elem.parent,
+            -1,
+        )
     }
 }
 
@@ -385,6 +878,37 @@ mod tests {
     use super::*;
     use crate::index::builder::build_index;
 
+    #[test]
+    fn test_outer_and_inner_ranges() {
+        let input = b"<r><a x=\"1>2\"><a>in</a> t&amp;</a><b/><![CDATA[c]]><!--n--><?p d?></r>";
+        let index = build_index(input);
+        let view = IndexedDocumentView::new(&index, input);
+        let outer = |id| &input[view.outer_range(id).unwrap()];
+        let inner = |id| &input[view.inner_range(id).unwrap()];
+
+        let root = view.root_element_id().unwrap();
+        assert_eq!(outer(root), &input[..]);
+        assert_eq!(inner(root), &input[3..input.len() - 4]);
+
+        let children = view.children_vec(root);
+        let a = children[0];
+        assert_eq!(outer(a), b"<a x=\"1>2\"><a>in</a> t&amp;</a>");
+        assert_eq!(inner(a), b"<a>in</a> t&amp;");
+        let nested = view.children_vec(a)[0];
+        assert_eq!(outer(nested), b"<a>in</a>");
+
+        assert_eq!(outer(children[1]), b"<b/>");
+        assert_eq!(inner(children[1]), b"");
+        assert_eq!(outer(children[2]), b"<![CDATA[c]]>");
+        assert_eq!(inner(children[2]), b"c");
+        assert_eq!(outer(children[3]), b"<!--n-->");
+        assert_eq!(inner(children[3]), b"n");
+        assert_eq!(outer(children[4]), b"<?p d?>");
+        assert_eq!(inner(children[4]), b"p d");
+
+        assert!(view.outer_range(encode_element_id(99)).is_none());
+    }
+
     #[test]
     fn test_node_id_encoding() {
         // Element IDs
@@ -437,7 +961,7 @@ mod tests {
         assert!(is_text_node_id(text_id));
 
         // Should have text content
-        assert_eq!(view.text_content(text_id), Some("hello"));
+        assert_eq!(view.text_content(text_id).as_deref(), Some("hello"));
 
         // Text node should have no name
         assert_eq!(view.node_name(text_id), None);
@@ -446,6 +970,28 @@ mod tests {
         assert_eq!(view.parent_of(text_id), Some(root_id));
     }
 
+    #[test]
+    fn test_decoded_text_content() {
+        let input = b"<a>x &amp; y<b>&lt;</b><![CDATA[&amp;]]></a>";
+        let index = build_index(input);
+        let cache = TextDecodeCache::default();
+        let view = IndexedDocumentView::new(&index, input).with_decode_cache(&cache);
+
+        let root = view.root_element_id().unwrap();
+        assert_eq!(crate::dom::node_string_value(&view, root), "x & y<&amp;");
+        assert_eq!(cache.len(), 2);
+
+        // Cached entries are reused rather than decoded again
+        assert_eq!(crate::dom::node_string_value(&view, root), "x & y<&amp;");
+        assert_eq!(cache.len(), 2);
+
+        // Without a cache the raw text is returned
+        let raw = IndexedDocumentView::new(&index, input);
+        assert_eq!(
+            crate::dom::node_string_value(&raw, root),
+            "x &amp; y&lt;&amp;"
+        );
+    }
     #[test]
     fn test_attributes() {
         let input = b"<root id=\"1\" class=\"test\">content</root>";
@@ -477,6 +1023,178 @@ mod tests {
         assert_eq!(descendants.len(), 4);
     }
 
+    #[test]
+    fn test_deep_nesting_does_not_recurse() {
+        let depth = 100_000;
+        let mut xml = "<a>".repeat(depth);
+        xml.push_str("<x>deep</x><y/>");
+        xml.push_str(&"</a>".repeat(depth));
+        let index = build_index(xml.as_bytes());
+        let view = IndexedDocumentView::new(&index, xml.as_bytes());
+
+        let value = crate::xpath::evaluate(&view, "string(/a)").unwrap();
+        assert_eq!(value.to_string_value(), "deep");
+        let value = crate::xpath::evaluate(&view, "count(//y/preceding::*)").unwrap();
+        assert_eq!(value.to_number(), 1.0);
+    }
+
+    #[test]
+    fn test_normalized_string_values() {
+        let input = "<names><n>cafe\u{301}</n><n>caf\u{e9}</n></names>".as_bytes();
+        let index = build_index(input);
+        let view = IndexedDocumentView::new(&index, input);
+
+        let value = crate::xpath::evaluate(&view, "count(//n[. = 'caf\u{e9}'])").unwrap();
+        assert_eq!(value.to_number(), 1.0);
+
+        let view = view.with_normalization(Normalization::Nfc);
+        let value = crate::xpath::evaluate(&view, "count(//n[. = 'caf\u{e9}'])").unwrap();
+        assert_eq!(value.to_number(), 2.0);
+        // The decomposed spelling in the query matches too
+        let value = crate::xpath::evaluate(&view, "count(//n[. = 'cafe\u{301}'])").unwrap();
+        assert_eq!(value.to_number(), 2.0);
+        let value = crate::xpath::evaluate(&view, "string(/names)").unwrap();
+        assert_eq!(value.to_string_value(), "caf\u{e9}caf\u{e9}");
+    }
+
+    #[test]
+    fn test_comments_and_pis_around_root() {
+        use crate::dom::node_string_value;
+        use crate::xpath::{evaluate, XPathValue};
+
+        let input = b"<?xml version=\"1.0\"?><?route dest=\"a\"?><!--top--><policy>\
+            <?route  dest=\"b\"?><!-- c --><rule/></policy><?tail?>";
+        let index = build_index(input);
+        let view = IndexedDocumentView::new(&index, input);
+        let nodes = |xpath| match evaluate(&view, xpath) {
+            Ok(XPathValue::NodeSet(nodes)) => nodes
+                .into_iter()
+                .map(|id| (view.node_kind_of(id), node_string_value(&view, id)))
+                .collect::<Vec<_>>(),
+            other => panic!("{xpath}: {other:?}"),
+        };
+        use NodeKind::*;
+
+        assert_eq!(
+            nodes("/node()"),
+            vec![
+                (ProcessingInstruction, "dest=\"a\"".to_string()),
+                (Comment, "top".to_string()),
+                (Element, String::new()),
+                (ProcessingInstruction, String::new()),
+            ]
+        );
+        assert_eq!(
+            nodes("//processing-instruction('route')"),
+            vec![
+                (ProcessingInstruction, "dest=\"a\"".to_string()),
+                (ProcessingInstruction, "dest=\"b\"".to_string()),
+            ]
+        );
+        assert_eq!(
+            nodes("//comment()[. = ' c ']"),
+            vec![(Comment, " c ".to_string())]
+        );
+        assert_eq!(nodes("/comment()/parent::node()").len(), 1);
+        assert_eq!(nodes("/policy/preceding-sibling::node()").len(), 2);
+        assert_eq!(
+            nodes("/policy/following-sibling::processing-instruction('tail')").len(),
+            1
+        );
+
+        let name = evaluate(&view, "local-name(/processing-instruction()[1])").unwrap();
+        assert_eq!(name.to_string_value(), "route");
+    }
+
+    #[test]
+    fn test_get_attribute_ns() {
+        let input = br#"<r xmlns:a="urn:x" xmlns:b="urn:y"><e b:id="1" id="2">
+            <f xmlns:b="urn:x" xmlns:a="" b:id="3" a:id="4" xml:lang="en"/></e></r>"#;
+        let index = build_index(input);
+        let view = IndexedDocumentView::new(&index, input);
+        let e = view.children_vec(view.root_element_id().unwrap())[0];
+        let f = view.children_vec(e)[1];
+
+        assert_eq!(view.get_attribute_ns(e, "urn:y", "id"), Some("1"));
+        assert_eq!(view.get_attribute_ns(e, "", "id"), Some("2"));
+        assert_eq!(view.get_attribute_ns(e, "urn:x", "id"), None);
+
+        // The inner declaration of b shadows the outer one; a is undeclared
+        assert_eq!(view.get_attribute_ns(f, "urn:x", "id"), Some("3"));
+        assert_eq!(view.get_attribute_ns(f, "urn:y", "id"), None);
+        assert_eq!(view.lookup_namespace_uri(f, "a"), None);
+        assert_eq!(
+            view.get_attribute_ns(f, "http://www.w3.org/XML/1998/namespace", "lang"),
+            Some("en")
+        );
+    }
+
+    #[test]
+    fn test_structural_counts() {
+        fn check(view: &IndexedDocumentView) {
+            let mut nodes = view.descendants_vec(DOCUMENT_NODE_ID);
+            nodes.push(DOCUMENT_NODE_ID);
+            for id in nodes {
+                assert_eq!(view.child_count(id), Some(view.children_vec(id).len()));
+                assert_eq!(
+                    view.descendant_count(id),
+                    Some(view.descendants_vec(id).len())
+                );
+                let ancestors = std::iter::successors(view.parent_of(id), |&p| view.parent_of(p));
+                assert_eq!(view.depth(id), Some(ancestors.count()), "{id}");
+                let preceding =
+                    std::iter::successors(view.prev_sibling_of(id), |&s| view.prev_sibling_of(s));
+                let expected = view.parent_of(id).map(|_| preceding.count() + 1);
+                assert_eq!(view.position(id), expected, "{id}");
+            }
+        }
+
+        let input = b"<?xml version=\"1.0\"?><!--top--><r a=\"1\"> <a><b>x<![CDATA[y]]></b><c/>\
+            </a>t<?p d?><d><e><f/></e></d><!--n--></r><?tail?>";
+        let index = build_index(input);
+        let view = IndexedDocumentView::new(&index, input);
+        check(&view);
+        let root = view.root_element_id().unwrap();
+        assert_eq!(view.descendant_count(root), Some(12));
+        assert_eq!(view.depth(root), Some(1));
+        assert_eq!(view.position(root), Some(2));
+        assert_eq!(view.child_count(encode_element_id(99)), None);
+        assert_eq!(view.position(encode_text_id(99)), None);
+
+        let input = crate::index::builder::wrap_fragment(b"<p>1</p> <q><r/></q>");
+        let index = crate::index::builder::build_fragment_index(&input);
+        let view = IndexedDocumentView::new(&index, &input);
+        check(&view);
+        assert_eq!(view.depth(view.root_element_id().unwrap()), Some(1));
+    }
+
+    #[test]
+    fn test_fragment_top_level_nodes() {
+        let input = crate::index::builder::wrap_fragment(
+            b"<tr><td>1</td></tr>\n<tr><td>2</td><td>3</td></tr>",
+        );
+        let index = crate::index::builder::build_fragment_index(&input);
+        let view = IndexedDocumentView::new(&index, &input);
+
+        let eval = |xpath| {
+            crate::xpath::evaluate(&view, xpath)
+                .unwrap()
+                .to_string_value()
+        };
+        assert_eq!(eval("count(/tr)"), "2");
+        assert_eq!(eval("count(/node())"), "3");
+        assert_eq!(eval("count(//*)"), "5");
+        assert_eq!(eval("string(/tr[2]/td[2])"), "3");
+        assert_eq!(eval("count(//td/ancestor::*)"), "2");
+        assert_eq!(eval("name(/*)"), "tr");
+
+        let first = view.root_element_id().unwrap();
+        assert_eq!(view.node_name(first), Some("tr"));
+        assert_eq!(view.parent_of(first), Some(DOCUMENT_NODE_ID));
+        let range = view.outer_range(DOCUMENT_NODE_ID).unwrap();
+        assert!(input[range].starts_with(b"<tr>"));
+    }
+
     #[test]
     fn test_navigation() {
         let input = b"<root><a/><b/><c/></root>";
@@ -507,4 +1225,119 @@ mod tests {
         assert_eq!(view.prev_sibling_of(b_id), Some(a_id));
         assert_eq!(view.prev_sibling_of(a_id), None);
     }
+
+    #[test]
+    fn test_interned_name_tests() {
+        use crate::xpath::eval::evaluate;
+        use crate::xpath::value::XPathValue;
+
+        let input = b"<root><item id=\"1\"/><ns:item id=\"2\"/><other/></root>";
+        let index = build_index(input);
+        let view = IndexedDocumentView::new(&index, input);
+
+        assert!(matches!(view.name_symbol("item"), NameSymbol::Symbol(_)));
+        assert_eq!(view.name_symbol("missing"), NameSymbol::Absent);
+
+        // Name tests match on local name, so ns:item is included
+        match evaluate(&view, "//item").unwrap() {
+            XPathValue::NodeSet(nodes) => assert_eq!(nodes.len(), 2),
+            other => panic!("expected node-set, got {:?}", other),
+        }
+        match evaluate(&view, "//missing").unwrap() {
+            XPathValue::NodeSet(nodes) => assert!(nodes.is_empty()),
+            other => panic!("expected node-set, got {:?}", other),
+        }
+        match evaluate(&view, "//item[@id='2']").unwrap() {
+            XPathValue::NodeSet(nodes) => {
+                assert_eq!(nodes.len(), 1);
+                assert_eq!(view.node_name(nodes[0]), Some("ns:item"));
+            }
+            other => panic!("expected node-set, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_siblings_follow_children() {
+        fn check(view: &IndexedDocumentView) {
+            let mut parents = view.descendants_vec(DOCUMENT_NODE_ID);
+            parents.push(DOCUMENT_NODE_ID);
+            for parent in parents {
+                let children = view.children_vec(parent);
+                for (i, &child) in children.iter().enumerate() {
+                    let prev = i.checked_sub(1).map(|i| children[i]);
+                    assert_eq!(view.prev_sibling_of(child), prev, "{child}");
+                    assert_eq!(view.next_sibling_of(child), children.get(i + 1).copied());
+                }
+            }
+        }
+
+        let input = b"<!--top--><r>a<b/>c<![CDATA[d]]><e><f/>g</e><?p?></r><!--end-->";
+        let index = build_index(input);
+        check(&IndexedDocumentView::new(&index, input));
+
+        let input = crate::index::builder::wrap_fragment(b"x<p>1</p> <q><r/></q>");
+        let index = crate::index::builder::build_fragment_index(&input);
+        check(&IndexedDocumentView::new(&index, &input));
+    }
+
+    #[test]
+    fn test_children_page() {
+        let input = b"<!--top--><r>a<b/>c<![CDATA[d]]><e><f/>g</e><?p?></r>";
+        let index = build_index(input);
+        let view = IndexedDocumentView::new(&index, input);
+        let root = view.root_element_id().unwrap();
+        let children = view.children_vec(root);
+
+        assert_eq!(view.children_page(root, 0, 100), Some(children.clone()));
+        assert_eq!(
+            view.children_page(root, 2, 3),
+            Some(children[2..5].to_vec())
+        );
+        assert_eq!(view.children_page(root, 5, 3), Some(children[5..].to_vec()));
+        assert_eq!(view.children_page(root, 9, 3), Some(vec![]));
+        assert_eq!(view.children_page(DOCUMENT_NODE_ID, 1, 1), Some(vec![root]));
+        assert_eq!(view.children_page(children[0], 0, 1), Some(vec![]));
+        assert_eq!(view.children_page(99_999, 0, 1), None);
+    }
+
+    /// Benchmark: reverse axes on a wide, mixed-content document
+    ///
+    /// Sibling lookups go through the index's child slots, so walking
+    /// preceding siblings is linear in the siblings walked rather than
+    /// rescanning the parent's children at every step. Run with:
+    ///   cargo test --release bench_reverse_axes -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn bench_reverse_axes() {
+        use crate::xpath::{evaluate, XPathValue};
+        use std::time::Instant;
+
+        let mut xml = String::from("<log>");
+        for i in 0..50_000 {
+            xml.push_str(&format!("<entry n=\"{i}\"><msg>line {i}</msg></entry>\n"));
+        }
+        xml.push_str("</log>");
+        let index = build_index(xml.as_bytes());
+        let view = IndexedDocumentView::new(&index, xml.as_bytes());
+
+        for (xpath, expected) in [
+            (
+                "count(/log/entry[last()]/preceding-sibling::entry)",
+                49_999.0,
+            ),
+            (
+                "count(/log/entry[last()]/preceding-sibling::node())",
+                99_998.0,
+            ),
+            ("count(//entry[last()]/msg/preceding::msg)", 49_999.0),
+            ("count((//msg)[last()]/text()/ancestor::*)", 3.0),
+        ] {
+            let start = Instant::now();
+            let XPathValue::Number(count) = evaluate(&view, xpath).unwrap() else {
+                panic!("expected a number");
+            };
+            assert_eq!(count, expected, "{xpath}");
+            println!("{xpath}: {:.2} ms", start.elapsed().as_secs_f64() * 1000.0);
+        }
+    }
 }
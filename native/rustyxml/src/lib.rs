@@ -5,7 +5,8 @@
 //! - streaming_*: Streaming parser for large files
 //! - sax_parse/1: SAX events
 
-use rustler::{Binary, Encoder, Env, NifResult, ResourceArc, Term};
+use rustler::env::OwnedEnv;
+use rustler::{Binary, Encoder, Env, LocalPid, NifResult, ResourceArc, Term};
 
 // ============================================================================
 // Pre-defined Atoms (panic-safe)
@@ -19,31 +20,178 @@ mod atoms {
         text,
         name,
         mutex_poisoned,
+        shared_document,
+        internal_parser_error,
+        parser_reset,
+        closed,
+        input,
+        index,
+        result_cache,
+        attr_indexes,
+        decoded_text,
+        precomputed_text,
+        total,
+        buffer,
+        pending,
+        skip,
+        chunk,
+        string,
+        integer,
+        float,
+        boolean,
+        list,
+        map,
+        node,
+        element,
+        xml,
+        invalid_nodeset,
+        document,
+        reverse,
+        matched,
+        cont,
+        done,
+        max_depth_exceeded,
+        memory_limit,
+        memory_tracking_disabled,
+        max_tail_size_exceeded,
+        attribute_limit_exceeded,
+        element_limit_exceeded,
+        warn,
+        unsupported_encoding,
+        nfc,
+        nfd,
+        none,
+        root,
+        version,
+        encoding,
+        standalone,
+        doctype,
+        public_id,
+        system_id,
+        rustyxml_sax,
+        events,
+        rustyxml_parse,
+        progress,
+        cancelled,
+        timeout,
+        fourth_edition,
+        fifth_edition,
+        characters,
+        bytes,
+        path,
+        message,
+        offset,
+        line,
+        column,
+        kind,
+        id,
+        role,
+        test,
+        assert,
+        report,
+        attributes,
+        limit,
+        escaped_ampersand,
+        escaped_less_than,
+        escaped_greater_than,
+        dropped_control_character,
+        replaced_invalid_utf8,
+        quoted_attribute_value,
+        added_attribute_value,
+        dropped_duplicate_attribute,
+        dropped_invalid_attribute,
+        dropped_end_tag,
+        closed_element,
+        closed_tag,
+        closed_comment,
+        closed_cdata,
+        closed_processing_instruction,
+        separated_hyphens,
+        dropped_xml_declaration,
+        wrapped_root,
+        before,
+        after,
+        first_child,
+        last_child,
+        replace,
+        self_closing,
+        expanded,
+        documents_parsed,
+        bytes_scanned,
+        xpath_queries,
+        cache_hits,
+        strict_failures,
+        avg_index_build_us,
+        width,
+        height,
+        view_box,
+        hits,
+        misses,
+        size,
+        capacity,
+        duration_us,
+        peak_alloc_bytes,
+        nodes_visited,
+        date,
+        year,
+        month,
+        day,
+        calendar,
+        struct_ = "__struct__",
+        date_module = "Elixir.Date",
+        calendar_iso = "Elixir.Calendar.ISO",
+        skip_comments,
+        skip_processing_instructions,
+        skip_whitespace,
+        max_depth,
     }
 }
 
+mod cancel;
+#[cfg(all(test, feature = "xmlconf"))]
+mod conformance;
 #[allow(dead_code)]
 mod core;
+mod doc_cache;
 mod dom;
+#[cfg(feature = "fuzzing")]
+pub mod fuzzing;
 mod index;
 #[allow(dead_code)]
 mod reader;
+mod redact;
 mod resource;
 #[allow(dead_code)]
 mod sax;
+mod schema;
+mod schematron;
+mod stats;
 #[allow(dead_code)]
 mod strategy;
+mod svg;
+mod template;
 mod term;
+mod workers;
 #[allow(dead_code)]
 mod xpath;
+mod xpointer;
 
+use core::normalize::Normalization;
 use dom::DocumentAccess;
 use resource::{
-    DocumentAccumulatorRef, IndexedDocumentRef, IndexedDocumentResource, StreamingParserRef,
+    CancelTokenRef, CancelTokenResource, DocumentAccumulatorRef, DocumentSetRef,
+    DocumentSetResource, IndexedDocumentRef, IndexedDocumentResource, MergePosition, NodeSetChunks,
+    NodeSetChunksRef, NodeSetCursor, NodeSetCursorRef, ParseTaskRef, ParseTaskResource,
+    RetainedBinary, SaxBinaryFormat, SaxSendRef, SaxSendResource, StreamingParserRef,
     StreamingParserResource, StreamingSaxParserRef, StreamingSaxParserResource,
+    StreamingValidatorRef, StreamingValidatorResource, WindowWait,
 };
-use term::{events_to_term, node_to_term, xpath_value_to_term};
-use xpath::evaluate;
+use std::collections::HashSet;
+use std::sync::Arc;
+use strategy::streaming::{BoundaryScan, IdTracker, LimitKind, LimitMode, OversizeMode, SaxLimits};
+use term::{events_to_term, node_to_term, xpath_value_to_term, BinarySource, SubspecType};
+use xpath::compiler::CompiledExpr;
+use xpath::{evaluate, ResultOrder};
 
 // ============================================================================
 // Allocator Configuration
@@ -52,11 +200,42 @@ use xpath::evaluate;
 #[cfg(feature = "memory_tracking")]
 mod tracking {
     use std::alloc::{GlobalAlloc, Layout};
+    use std::cell::Cell;
     use std::sync::atomic::{AtomicUsize, Ordering};
 
     pub static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
     pub static PEAK_ALLOCATED: AtomicUsize = AtomicUsize::new(0);
 
+    // Per-thread balance, for attributing allocations to a single NIF call.
+    // Signed because memory freed here may have been allocated elsewhere.
+    // Const-initialized `Cell`s need no lazy setup or destructor, so they
+    // are safe to touch from inside the allocator.
+    thread_local! {
+        static THREAD_ALLOCATED: Cell<isize> = const { Cell::new(0) };
+        static THREAD_PEAK: Cell<isize> = const { Cell::new(0) };
+    }
+
+    fn track_thread(delta: isize) {
+        let _ = THREAD_ALLOCATED.try_with(|allocated| {
+            let current = allocated.get() + delta;
+            allocated.set(current);
+            let _ = THREAD_PEAK.try_with(|peak| peak.set(peak.get().max(current)));
+        });
+    }
+
+    /// Start measuring this thread's peak; returns the mark to pass to
+    /// `thread_peak_since`
+    pub fn thread_peak_mark() -> isize {
+        let current = THREAD_ALLOCATED.with(Cell::get);
+        THREAD_PEAK.with(|peak| peak.set(current));
+        current
+    }
+
+    /// Largest amount this thread has held above `mark` since it was taken
+    pub fn thread_peak_since(mark: isize) -> usize {
+        THREAD_PEAK.with(|peak| (peak.get() - mark).max(0) as usize)
+    }
+
     pub struct TrackingAllocator;
 
     #[cfg(feature = "mimalloc")]
@@ -82,12 +261,14 @@ mod tracking {
                         Err(p) => peak = p,
                     }
                 }
+                track_thread(layout.size() as isize);
             }
             ptr
         }
 
         unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
             ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+            track_thread(-(layout.size() as isize));
             UNDERLYING.dealloc(ptr, layout)
         }
     }
@@ -146,42 +327,628 @@ fn reset_rust_memory_stats() -> (usize, usize) {
     (0, 0)
 }
 
+/// Heap bytes held by a document: its input, structural index, result
+/// cache, attribute indexes, decoded text and precomputed string-values,
+/// with their `total`
+#[rustler::nif]
+fn doc_memory(env: Env, doc: IndexedDocumentRef) -> NifResult<Term> {
+    let memory = doc.memory();
+    byte_counts(
+        env,
+        &[
+            (atoms::input(), memory.input),
+            (atoms::index(), memory.index),
+            (atoms::result_cache(), memory.result_cache),
+            (atoms::attr_indexes(), memory.attr_indexes),
+            (atoms::decoded_text(), memory.decoded_text),
+            (atoms::precomputed_text(), memory.precomputed_text),
+            (atoms::total(), memory.total()),
+        ],
+    )
+}
+
+/// Heap bytes held by a streaming parser (`streaming_new*` or
+/// `streaming_sax_new*`): unprocessed input in `buffer`, events, elements
+/// or IDs waiting in `pending`, and their `total`
+#[rustler::nif]
+fn streaming_memory<'a>(env: Env<'a>, parser: Term<'a>) -> NifResult<Term<'a>> {
+    let (buffer, pending) = match parser.decode::<StreamingParserRef>() {
+        Ok(parser) => parser.lock()?.memory(),
+        Err(_) => parser.decode::<StreamingSaxParserRef>()?.lock()?.memory(),
+    };
+    byte_counts(
+        env,
+        &[
+            (atoms::buffer(), buffer),
+            (atoms::pending(), pending),
+            (atoms::total(), buffer + pending),
+        ],
+    )
+}
+
+fn byte_counts<'a>(env: Env<'a>, counts: &[(rustler::Atom, usize)]) -> NifResult<Term<'a>> {
+    let pairs: Vec<(Term, Term)> = counts
+        .iter()
+        .map(|(key, bytes)| (key.encode(env), bytes.encode(env)))
+        .collect();
+    Term::map_from_pairs(env, &pairs)
+}
+
+// ============================================================================
+// Parser Statistics
+// ============================================================================
+
+/// Cumulative parser counters since load or the last reset
+///
+/// Returns a map of `documents_parsed`, `bytes_scanned`, `xpath_queries`,
+/// `cache_hits` and `strict_failures` counts, and `avg_index_build_us`.
+#[rustler::nif]
+fn get_parser_stats(env: Env) -> NifResult<Term> {
+    let stats = stats::snapshot();
+    Term::map_from_pairs(
+        env,
+        &[
+            (
+                atoms::documents_parsed().encode(env),
+                stats.documents_parsed.encode(env),
+            ),
+            (
+                atoms::bytes_scanned().encode(env),
+                stats.bytes_scanned.encode(env),
+            ),
+            (
+                atoms::xpath_queries().encode(env),
+                stats.xpath_queries.encode(env),
+            ),
+            (
+                atoms::cache_hits().encode(env),
+                stats.cache_hits.encode(env),
+            ),
+            (
+                atoms::strict_failures().encode(env),
+                stats.strict_failures.encode(env),
+            ),
+            (
+                atoms::avg_index_build_us().encode(env),
+                stats.average_index_build_us().encode(env),
+            ),
+        ],
+    )
+}
+
+/// Set the parser counters back to zero
+#[rustler::nif]
+fn reset_parser_stats() -> rustler::Atom {
+    stats::reset();
+    atoms::ok()
+}
+
+/// `%{hits, misses, size, capacity}` of the compiled XPath expression cache
+#[rustler::nif]
+fn xpath_cache_stats(env: Env) -> NifResult<Term> {
+    let stats = xpath::compiler::cache_stats();
+    Term::map_from_pairs(
+        env,
+        &[
+            (atoms::hits().encode(env), stats.hits.encode(env)),
+            (atoms::misses().encode(env), stats.misses.encode(env)),
+            (atoms::size().encode(env), stats.size.encode(env)),
+            (atoms::capacity().encode(env), stats.capacity.encode(env)),
+        ],
+    )
+}
+
+/// Drop every compiled XPath expression and zero the cache's counters
+#[rustler::nif]
+fn xpath_cache_clear() -> rustler::Atom {
+    xpath::compiler::clear_cache();
+    atoms::ok()
+}
+
+/// Set how many compiled XPath expressions are kept; 0 empties and
+/// disables the cache
+#[rustler::nif]
+fn set_xpath_cache_capacity(capacity: usize) -> rustler::Atom {
+    xpath::compiler::set_cache_capacity(capacity);
+    atoms::ok()
+}
+
+/// `{result, %{duration_us, peak_alloc_bytes, nodes_visited}}`
+fn with_usage<'a>(env: Env<'a>, result: Term<'a>, usage: stats::CallUsage) -> NifResult<Term<'a>> {
+    let usage = Term::map_from_pairs(
+        env,
+        &[
+            (
+                atoms::duration_us().encode(env),
+                (usage.duration.as_micros() as u64).encode(env),
+            ),
+            (
+                atoms::peak_alloc_bytes().encode(env),
+                usage.peak_alloc_bytes.encode(env),
+            ),
+            (
+                atoms::nodes_visited().encode(env),
+                usage.nodes_visited.encode(env),
+            ),
+        ],
+    )?;
+    Ok((result, usage).encode(env))
+}
+
+/// `parse/1`, also reporting the resources the parse used
+///
+/// `nodes_visited` is the number of nodes (elements + text nodes) indexed.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn parse_timed<'a>(env: Env<'a>, input: Binary<'a>) -> NifResult<Term<'a>> {
+    catch_panic(env, || {
+        if let Err(error) = check_memory(env, input.len()) {
+            return Ok(error);
+        }
+        let (resource, mut usage) = stats::measure(|| lenient_resource(input));
+        if let Err(error) = check_memory(env, 0) {
+            return Ok(error);
+        }
+        usage.nodes_visited = (resource.index.element_count() + resource.index.text_count()) as u64;
+        with_usage(env, IndexedDocumentRef::new(resource).encode(env), usage)
+    })
+}
+
+/// `xpath_query/2`, also reporting the resources the query used
+///
+/// Building the result term is included. Runs on a dirty scheduler, as the
+/// query's cost is not known in advance.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn xpath_query_timed<'a>(
+    env: Env<'a>,
+    doc_ref: IndexedDocumentRef,
+    xpath_str: &str,
+) -> NifResult<Term<'a>> {
+    let (result, usage) =
+        stats::measure(|| xpath_query_impl(env, &doc_ref, xpath_str, ResultOrder::Document));
+    with_usage(env, result?, usage)
+}
+
+// ============================================================================
+// Parser Limits
+// ============================================================================
+
+/// Set the maximum element nesting depth accepted by strict parsing and
+/// SimpleForm conversion (0 = unlimited)
+#[rustler::nif]
+fn set_max_depth(depth: usize) -> rustler::Atom {
+    crate::core::limits::set_max_depth(depth);
+    atoms::ok()
+}
+
+/// Current maximum element nesting depth (0 = unlimited)
+#[rustler::nif]
+fn max_depth() -> usize {
+    crate::core::limits::max_depth()
+}
+
+/// Limit the bytes the NIF may have allocated (0 = unlimited)
+///
+/// Parses and streaming feeds that would go over it return
+/// `{:error, :memory_limit}`. The allocation count comes from the
+/// `memory_tracking` feature; without it a non-zero limit returns
+/// `{:error, :memory_tracking_disabled}`.
+#[rustler::nif]
+fn set_memory_limit(env: Env, bytes: usize) -> Term {
+    if bytes > 0 && !cfg!(feature = "memory_tracking") {
+        return (atoms::error(), atoms::memory_tracking_disabled()).encode(env);
+    }
+    crate::core::limits::set_memory_limit(bytes);
+    atoms::ok().encode(env)
+}
+
+/// Current limit on bytes allocated by the NIF (0 = unlimited)
+#[rustler::nif]
+fn memory_limit() -> usize {
+    crate::core::limits::memory_limit()
+}
+
+/// Select the XML 1.0 edition whose name rules strict validation applies:
+/// `:fourth_edition` (default) or `:fifth_edition`
+#[rustler::nif]
+fn set_name_rules(edition: rustler::Atom) -> NifResult<rustler::Atom> {
+    use crate::core::unicode::NameRules;
+
+    let rules = if edition == atoms::fourth_edition() {
+        NameRules::FourthEdition
+    } else if edition == atoms::fifth_edition() {
+        NameRules::FifthEdition
+    } else {
+        return Err(rustler::Error::BadArg);
+    };
+    crate::core::unicode::set_name_rules(rules);
+    Ok(atoms::ok())
+}
+
+/// Edition whose name rules strict validation currently applies
+#[rustler::nif]
+fn name_rules() -> rustler::Atom {
+    match crate::core::unicode::name_rules() {
+        crate::core::unicode::NameRules::FourthEdition => atoms::fourth_edition(),
+        crate::core::unicode::NameRules::FifthEdition => atoms::fifth_edition(),
+    }
+}
+
+/// Choose what XPath `substring()` and `string-length()` count:
+/// `:characters` (default) or `:bytes`
+#[rustler::nif]
+fn set_xpath_string_units(units: rustler::Atom) -> NifResult<rustler::Atom> {
+    use crate::xpath::functions::StringUnits;
+
+    let units = if units == atoms::characters() {
+        StringUnits::Characters
+    } else if units == atoms::bytes() {
+        StringUnits::Bytes
+    } else {
+        return Err(rustler::Error::BadArg);
+    };
+    crate::xpath::functions::set_string_units(units);
+    Ok(atoms::ok())
+}
+
+/// Units XPath string functions currently count
+#[rustler::nif]
+fn xpath_string_units() -> rustler::Atom {
+    match crate::xpath::functions::string_units() {
+        crate::xpath::functions::StringUnits::Characters => atoms::characters(),
+        crate::xpath::functions::StringUnits::Bytes => atoms::bytes(),
+    }
+}
+
+/// `{:error, {:unsupported_encoding, name}}` for encodings that are detected
+/// but not converted, `{:error, message}` for malformed input
+fn encoding_error<'a>(env: Env<'a>, error: core::encoding::EncodingError) -> Term<'a> {
+    use core::encoding::EncodingError;
+    match error {
+        EncodingError::Unsupported(encoding) => (
+            atoms::error(),
+            (atoms::unsupported_encoding(), encoding.name()),
+        )
+            .encode(env),
+        EncodingError::UnknownCharset(label) => {
+            (atoms::error(), (atoms::unsupported_encoding(), label)).encode(env)
+        }
+        EncodingError::Invalid(msg) => (atoms::error(), msg).encode(env),
+    }
+}
+
+/// `{:error, {:max_depth_exceeded, limit}}` if an index nests deeper than
+/// the configured limit
+fn check_depth<'a>(env: Env<'a>, idx: &index::StructuralIndex) -> Result<(), Term<'a>> {
+    crate::core::limits::check_depth(idx.max_depth as usize)
+        .map_err(|limit| (atoms::error(), (atoms::max_depth_exceeded(), limit)).encode(env))
+}
+
+/// `{:error, :memory_limit}` if `additional` more bytes would take the
+/// NIF's allocations over the limit
+///
+/// Checked with the input length before building an index, and with 0
+/// after, so a document whose index pushed the count over is dropped.
+fn check_memory<'a>(env: Env<'a>, additional: usize) -> Result<(), Term<'a>> {
+    if within_memory_limit(additional) {
+        Ok(())
+    } else {
+        Err((atoms::error(), atoms::memory_limit()).encode(env))
+    }
+}
+
+/// `check_memory` for threads without an env to encode the error in
+fn within_memory_limit(additional: usize) -> bool {
+    #[cfg(feature = "memory_tracking")]
+    let allocated = tracking::ALLOCATED.load(std::sync::atomic::Ordering::Relaxed);
+    #[cfg(not(feature = "memory_tracking"))]
+    let allocated = 0;
+    crate::core::limits::check_memory(allocated, additional).is_ok()
+}
+
 // ============================================================================
 // Main Parse Path: Structural Index + XPath
 // ============================================================================
 
+/// Run a NIF body, returning `{:error, :internal_parser_error}` if it panics
+///
+/// Rustler raises a panic as an Erlang exception. Every NIF that parses
+/// input or reads a document turns it into an error tuple instead, so a
+/// parser bug on hostile input reaches callers as one more `{:error, _}` to
+/// handle; the threads behind `parse_async` and `sax_parse_send` send it as
+/// their last message. NIFs that only create, close or cancel handles, ack
+/// batches, or get and set settings and statistics go without: they never
+/// read document content.
+fn catch_panic<'a>(
+    env: Env<'a>,
+    body: impl FnOnce() -> NifResult<Term<'a>>,
+) -> NifResult<Term<'a>> {
+    std::panic::catch_unwind(std::panic::AssertUnwindSafe(body))
+        .unwrap_or_else(|_| Ok((atoms::error(), atoms::internal_parser_error()).encode(env)))
+}
+
 /// Parse XML into structural index (returns ResourceArc)
 /// Lenient mode - accepts malformed XML
 /// Full XPath support via xpath_query
 #[rustler::nif(schedule = "DirtyCpu")]
 fn parse<'a>(env: Env<'a>, input: Binary<'a>) -> NifResult<Term<'a>> {
-    let bytes = input.as_slice().to_vec();
-    let resource = IndexedDocumentResource::new(bytes);
-    let arc = ResourceArc::new(resource);
-    Ok(arc.encode(env))
+    catch_panic(env, || {
+        if let Err(error) = check_memory(env, input.len()) {
+            return Ok(error);
+        }
+        let resource = lenient_resource(input);
+        if let Err(error) = check_memory(env, 0) {
+            return Ok(error);
+        }
+        let arc = IndexedDocumentRef::new(resource);
+        Ok(arc.encode(env))
+    })
+}
+
+/// Index a document in lenient mode
+///
+/// UTF-16 input is converted like strict parsing does, but lossily, as
+/// lenient parsing never fails; UTF-8 is indexed in place past any BOM.
+/// Other encodings are indexed as they are.
+fn lenient_resource(input: Binary) -> IndexedDocumentResource {
+    use crate::core::encoding::{convert_utf16_lossy, XmlEncoding};
+
+    let slice = input.as_slice();
+    match convert_utf16_lossy(slice) {
+        Some(bytes) => IndexedDocumentResource::new(bytes),
+        None => IndexedDocumentResource::from_binary(input, XmlEncoding::Utf8.bom_len(slice)),
+    }
 }
 
 /// Parse XML in strict mode (returns {:ok, doc} or {:error, reason})
-/// Rejects malformed XML per XML 1.0 specification
+/// Rejects malformed XML per XML 1.0 specification, and documents nested
+/// deeper than the configured maximum depth
 #[rustler::nif(schedule = "DirtyCpu")]
 fn parse_strict<'a>(env: Env<'a>, input: Binary<'a>) -> NifResult<Term<'a>> {
-    let bytes = input.as_slice().to_vec();
+    catch_panic(env, || {
+        match strict_resource(env, input, dom::StrictOptions::default()) {
+            Ok(resource) => Ok((atoms::ok(), IndexedDocumentRef::new(resource)).encode(env)),
+            Err(error) => Ok(error),
+        }
+    })
+}
 
-    // Handle encoding conversion (UTF-16 → UTF-8)
-    let bytes = match crate::core::encoding::convert_to_utf8(bytes) {
-        Ok(b) => b,
-        Err(msg) => return Ok((atoms::error(), msg).encode(env)),
+/// Like `parse_strict`, also applying the Namespaces in XML checks (see
+/// `dom::namespaces::check_element`); the first violation is the error
+#[rustler::nif(schedule = "DirtyCpu")]
+fn parse_strict_namespaces<'a>(env: Env<'a>, input: Binary<'a>) -> NifResult<Term<'a>> {
+    catch_panic(env, || {
+        let options = dom::StrictOptions { namespaces: true };
+        match strict_resource(env, input, options) {
+            Ok(resource) => Ok((atoms::ok(), IndexedDocumentRef::new(resource)).encode(env)),
+            Err(error) => Ok(error),
+        }
+    })
+}
+
+/// Check a document against the Namespaces in XML 1.0 constraints
+///
+/// Returns `:ok`, or `{:error, errors}` with a `%{path: path, message:
+/// message}` map per violation, where `path` locates the element like
+/// `/feed[1]/entry[3]`.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn validate_namespaces<'a>(env: Env<'a>, input: Binary<'a>) -> NifResult<Term<'a>> {
+    catch_panic(env, || {
+        let bytes = input.as_slice();
+        let bytes = bytes.strip_prefix(&[0xEF, 0xBB, 0xBF]).unwrap_or(bytes);
+        let errors = dom::namespaces::validate_namespaces(bytes);
+        if errors.is_empty() {
+            return Ok(atoms::ok().encode(env));
+        }
+        let terms = errors
+            .iter()
+            .map(|error| {
+                Term::map_from_pairs(
+                    env,
+                    &[
+                        (atoms::path().encode(env), error.path.encode(env)),
+                        (atoms::message().encode(env), error.message.encode(env)),
+                    ],
+                )
+            })
+            .collect::<NifResult<Vec<Term<'a>>>>()?;
+        Ok((atoms::error(), terms).encode(env))
+    })
+}
+
+/// Check that a document is well-formed, as `parse_strict` would, without
+/// building an index or document
+///
+/// Returns `:ok`, or the `{:error, reason}` `parse_strict` reports for
+/// malformed input or an unsupported encoding. Stops at the first error.
+/// The depth limit is not applied, as no tree is built.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn well_formed<'a>(env: Env<'a>, input: Binary<'a>) -> NifResult<Term<'a>> {
+    use crate::core::encoding::XmlEncoding;
+
+    catch_panic(env, || {
+        let slice = input.as_slice();
+        let converted;
+        let bytes = if XmlEncoding::detect(slice) == XmlEncoding::Utf8 {
+            slice.strip_prefix(&[0xEF, 0xBB, 0xBF]).unwrap_or(slice)
+        } else {
+            match crate::core::encoding::convert_to_utf8(slice.to_vec()) {
+                Ok(bytes) => {
+                    converted = bytes;
+                    &converted[..]
+                }
+                Err(error) => return Ok(encoding_error(env, error)),
+            }
+        };
+        match dom::validate_strict(bytes) {
+            Ok(()) => Ok(atoms::ok().encode(env)),
+            Err(msg) => {
+                stats::record_strict_failure();
+                Ok((atoms::error(), msg).encode(env))
+            }
+        }
+    })
+}
+
+/// Validate and index a document in strict mode, returning the error term
+/// `parse_strict` reports on failure
+fn strict_resource<'a>(
+    env: Env<'a>,
+    input: Binary<'a>,
+    options: dom::StrictOptions,
+) -> Result<IndexedDocumentResource, Term<'a>> {
+    use crate::core::encoding::XmlEncoding;
+
+    // UTF-8 input is validated in place so large binaries can be retained
+    // rather than copied; only UTF-16 needs a converted buffer.
+    check_memory(env, input.len())?;
+    let slice = input.as_slice();
+    if XmlEncoding::detect(slice) != XmlEncoding::Utf8 {
+        // Handle encoding conversion (UTF-16 → UTF-8)
+        let bytes = crate::core::encoding::convert_to_utf8(slice.to_vec())
+            .map_err(|error| encoding_error(env, error))?;
+        return strict_converted_resource(env, bytes, options);
+    }
+    let bom_len = if slice.starts_with(&[0xEF, 0xBB, 0xBF]) {
+        3
+    } else {
+        0
     };
+    dom::validate_strict_with(&slice[bom_len..], options).map_err(|msg| {
+        stats::record_strict_failure();
+        (atoms::error(), msg).encode(env)
+    })?;
+    let resource = IndexedDocumentResource::from_binary(input, bom_len);
+    check_depth(env, &resource.index)?;
+    check_memory(env, 0)?;
+    Ok(resource)
+}
 
+/// `strict_resource` for input already converted to UTF-8
+fn strict_converted_resource<'a>(
+    env: Env<'a>,
+    bytes: Vec<u8>,
+    options: dom::StrictOptions,
+) -> Result<IndexedDocumentResource, Term<'a>> {
     // Lightweight validation — no DOM construction
-    match dom::validate_strict(&bytes) {
-        Ok(()) => {
-            let resource = IndexedDocumentResource::new(bytes);
-            let arc = ResourceArc::new(resource);
-            Ok((atoms::ok(), arc).encode(env))
+    dom::validate_strict_with(&bytes, options).map_err(|msg| {
+        stats::record_strict_failure();
+        (atoms::error(), msg).encode(env)
+    })?;
+    let resource = IndexedDocumentResource::new(bytes);
+    check_depth(env, &resource.index)?;
+    check_memory(env, 0)?;
+    Ok(resource)
+}
+
+/// Parse in strict mode, choosing the input's encoding from its BOM, the
+/// `charset` of a Content-Type value, or its XML declaration, in that order
+///
+/// Errors as `parse_strict`; an unrecognised charset is reported as
+/// `{:error, {:unsupported_encoding, label}}`.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn parse_with_charset_hint<'a>(
+    env: Env<'a>,
+    input: Binary<'a>,
+    content_type: &str,
+) -> NifResult<Term<'a>> {
+    catch_panic(env, || {
+        let resource = core::encoding::convert_with_charset_hint(input.as_slice(), content_type)
+            .map_err(|error| encoding_error(env, error))
+            .and_then(|bytes| strict_converted_resource(env, bytes, dom::StrictOptions::default()));
+        match resource {
+            Ok(resource) => Ok((atoms::ok(), IndexedDocumentRef::new(resource)).encode(env)),
+            Err(error) => Ok(error),
         }
-        Err(msg) => Ok((atoms::error(), msg).encode(env)),
-    }
+    })
+}
+
+/// Parse an XML fragment: any number of top-level elements, mixed with text
+///
+/// The content is wrapped in a hidden element, so its top-level nodes
+/// become children of the document node and XPath queries see the
+/// fragment as written. Strict unless `lenient`, with the same error
+/// reasons as `parse_strict`.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn parse_fragment<'a>(env: Env<'a>, input: Binary<'a>, lenient: bool) -> NifResult<Term<'a>> {
+    catch_panic(env, || {
+        if let Err(error) = check_memory(env, input.len()) {
+            return Ok(error);
+        }
+        let bytes = match crate::core::encoding::convert_to_utf8(input.as_slice().to_vec()) {
+            Ok(b) => b,
+            Err(error) => return Ok(encoding_error(env, error)),
+        };
+        let wrapped = index::builder::wrap_fragment(&bytes);
+
+        if !lenient {
+            if let Err(msg) = dom::validate_strict(&wrapped) {
+                stats::record_strict_failure();
+                return Ok((atoms::error(), index::builder::fragment_error(msg)).encode(env));
+            }
+        }
+
+        let resource = IndexedDocumentResource::new_fragment(wrapped);
+        if let Err(error) = check_depth(env, &resource.index).and_then(|_| check_memory(env, 0)) {
+            return Ok(error);
+        }
+        Ok((atoms::ok(), IndexedDocumentRef::new(resource)).encode(env))
+    })
+}
+
+/// Parse input that may have been cut off, such as a partial download
+///
+/// Indexes everything before the cut and closes the elements left open
+/// there; `doc_truncated` reports whether anything was cut. Only an
+/// unsupported or invalid encoding is an error.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn parse_salvage<'a>(env: Env<'a>, input: Binary<'a>) -> NifResult<Term<'a>> {
+    catch_panic(env, || {
+        use crate::core::encoding::XmlEncoding;
+
+        if let Err(error) = check_memory(env, input.len()) {
+            return Ok(error);
+        }
+        let slice = input.as_slice();
+        let resource = if XmlEncoding::detect(slice) == XmlEncoding::Utf8 {
+            let bom_len = if slice.starts_with(&[0xEF, 0xBB, 0xBF]) {
+                3
+            } else {
+                0
+            };
+            IndexedDocumentResource::salvage_binary(input, bom_len)
+        } else {
+            match crate::core::encoding::convert_to_utf8(slice.to_vec()) {
+                Ok(bytes) => IndexedDocumentResource::new_salvage(bytes),
+                Err(error) => return Ok(encoding_error(env, error)),
+            }
+        };
+        if let Err(error) = check_memory(env, 0) {
+            return Ok(error);
+        }
+        Ok((atoms::ok(), IndexedDocumentRef::new(resource)).encode(env))
+    })
+}
+
+/// Whether a document was parsed from input that was cut off
+///
+/// Always false for documents not built by `parse_salvage`.
+#[rustler::nif]
+fn doc_truncated(doc_ref: IndexedDocumentRef) -> bool {
+    doc_ref.index.truncated
+}
+
+/// Detect the encoding of XML input from its byte order mark or the
+/// pattern of its first bytes
+///
+/// Returns `{name, bom?}`, e.g. `{"UTF-16LE", true}`. Detection alone does
+/// not check that the rest of the input is valid in that encoding.
+#[rustler::nif]
+fn detect_encoding<'a>(env: Env<'a>, input: Binary<'a>) -> NifResult<Term<'a>> {
+    catch_panic(env, || {
+        let bytes = input.as_slice();
+        let encoding = crate::core::encoding::XmlEncoding::detect(bytes);
+        Ok((encoding.name(), encoding.bom_len(bytes) > 0).encode(env))
+    })
 }
 
 /// Execute XPath query on a document
@@ -191,191 +958,2389 @@ fn xpath_query<'a>(
     doc_ref: IndexedDocumentRef,
     xpath_str: &str,
 ) -> NifResult<Term<'a>> {
-    let view = doc_ref.as_view();
-    match evaluate(&view, xpath_str) {
-        Ok(value) => Ok(xpath_value_to_term(env, value, &view)),
-        Err(e) => Ok((atoms::error(), e).encode(env)),
-    }
+    xpath_query_impl(env, &doc_ref, xpath_str, ResultOrder::Document)
 }
 
-/// Execute XPath query returning XML strings for node sets (fast path)
-#[rustler::nif]
-fn xpath_query_raw<'a>(
+/// Execute XPath query on a document (dirty CPU scheduler, for large documents)
+#[rustler::nif(schedule = "DirtyCpu")]
+fn xpath_query_dirty<'a>(
     env: Env<'a>,
     doc_ref: IndexedDocumentRef,
     xpath_str: &str,
 ) -> NifResult<Term<'a>> {
-    use term::nodeset_to_xml_binaries;
-    use xpath::XPathValue;
-
-    let view = doc_ref.as_view();
-    match evaluate(&view, xpath_str) {
-        Ok(value) => match value {
-            XPathValue::NodeSet(nodes) => Ok(nodeset_to_xml_binaries(env, &nodes, &view)),
-            _ => Ok(xpath_value_to_term(env, value, &view)),
-        },
-        Err(e) => Ok((atoms::error(), e).encode(env)),
-    }
+    xpath_query_impl(env, &doc_ref, xpath_str, ResultOrder::Document)
 }
 
-/// Execute XPath query returning text values for node sets (optimized fast path)
-/// Instead of building recursive element tuples ({:element, name, attrs, children}),
-/// extracts text content directly as strings. Used when is_value: true.
+/// `xpath_query` returning a node-set in `order`: `:document`, `:reverse`
+/// or `:matched` (see `xpath::ResultOrder`)
 #[rustler::nif]
-fn xpath_text_list<'a>(
+fn xpath_query_ordered<'a>(
     env: Env<'a>,
     doc_ref: IndexedDocumentRef,
     xpath_str: &str,
+    order: rustler::Atom,
 ) -> NifResult<Term<'a>> {
-    use xpath::XPathValue;
-
-    let view = doc_ref.as_view();
-    match evaluate(&view, xpath_str) {
-        Ok(XPathValue::NodeSet(nodes)) => {
-            let mut list = Term::list_new_empty(env);
-            for &id in nodes.iter().rev() {
-                let text = dom::node_string_value(&view, id);
-                let binary = term::bytes_to_binary(env, text.as_bytes());
-                list = list.list_prepend(binary);
-            }
-            Ok(list)
-        }
-        Ok(value) => Ok(xpath_value_to_term(env, value, &view)),
-        Err(e) => Ok((atoms::error(), e).encode(env)),
-    }
-}
-
-/// Parse and immediately query (convenience function)
-#[rustler::nif(schedule = "DirtyCpu")]
-fn parse_and_xpath<'a>(env: Env<'a>, input: Binary<'a>, xpath_str: &str) -> NifResult<Term<'a>> {
-    let bytes = input.as_slice();
-    let idx = index::builder::build_index(bytes);
-    let view = index::IndexedDocumentView::new(&idx, bytes);
-
-    match evaluate(&view, xpath_str) {
-        Ok(value) => Ok(xpath_value_to_term(env, value, &view)),
-        Err(e) => Ok((atoms::error(), e).encode(env)),
-    }
+    xpath_query_impl(env, &doc_ref, xpath_str, decode_result_order(order)?)
 }
 
-/// Parse and immediately query, returning text values for node sets
-/// Optimized path for is_value: true — avoids building element tuples
+/// `xpath_query` returning at most `limit` nodes of a node-set, skipping
+/// the first `offset` in document order
+///
+/// Only the slice is converted to terms, so paging through a large result
+/// doesn't build the rest. Other results are returned as `xpath_query` does.
+/// Evaluation still visits every match, so this runs on a dirty scheduler.
 #[rustler::nif(schedule = "DirtyCpu")]
-fn parse_and_xpath_text<'a>(
+fn xpath_query_slice<'a>(
     env: Env<'a>,
-    input: Binary<'a>,
+    doc_ref: IndexedDocumentRef,
     xpath_str: &str,
+    offset: usize,
+    limit: usize,
 ) -> NifResult<Term<'a>> {
-    use xpath::XPathValue;
-
-    let bytes = input.as_slice();
-    let idx = index::builder::build_index(bytes);
-    let view = index::IndexedDocumentView::new(&idx, bytes);
-
-    match evaluate(&view, xpath_str) {
-        Ok(XPathValue::NodeSet(nodes)) => {
-            let mut list = Term::list_new_empty(env);
-            for &id in nodes.iter().rev() {
-                let text = dom::node_string_value(&view, id);
-                let binary = term::bytes_to_binary(env, text.as_bytes());
-                list = list.list_prepend(binary);
+    catch_panic(env, || {
+        use xpath::XPathValue;
+
+        let view = doc_ref.as_view();
+        let source = shared_input(env, &doc_ref);
+        match doc_ref.evaluate(xpath_str) {
+            Ok(XPathValue::NodeSet(nodes)) => {
+                let page = nodes.into_iter().skip(offset).take(limit).collect();
+                Ok(xpath_value_to_term(
+                    env,
+                    XPathValue::NodeSet(page),
+                    &view,
+                    source,
+                ))
             }
-            Ok(list)
+            Ok(value) => Ok(xpath_value_to_term(env, value, &view, source)),
+            Err(e) => Ok((atoms::error(), e).encode(env)),
         }
-        Ok(value) => Ok(xpath_value_to_term(env, value, &view)),
-        Err(e) => Ok((atoms::error(), e).encode(env)),
-    }
+    })
 }
 
-/// Get root element of a document
+/// First node an XPath query selects, in document order
+///
+/// Returns the node's term, or nil if nothing is selected. Expressions that
+/// don't select nodes return their value as `xpath_query` does. Location
+/// paths stop at the first match instead of building the whole node-set.
 #[rustler::nif]
-fn get_root<'a>(env: Env<'a>, doc_ref: IndexedDocumentRef) -> NifResult<Term<'a>> {
-    let view = doc_ref.as_view();
-    if let Some(root_id) = view.root_element_id() {
-        Ok(node_to_term(env, &view, root_id))
-    } else {
-        Ok(atoms::nil().encode(env))
-    }
+fn xpath_first<'a>(
+    env: Env<'a>,
+    doc_ref: IndexedDocumentRef,
+    xpath_str: &str,
+) -> NifResult<Term<'a>> {
+    xpath_first_impl(env, &doc_ref, xpath_str)
 }
 
-// ============================================================================
-// XPath Helpers
-// ============================================================================
-
-/// Execute parent XPath and evaluate subspecs for each result node
+/// `xpath_first` on the dirty CPU scheduler, for large documents
 #[rustler::nif(schedule = "DirtyCpu")]
-fn xpath_with_subspecs<'a>(
+fn xpath_first_dirty<'a>(
     env: Env<'a>,
-    input: Binary<'a>,
-    parent_xpath: &str,
-    subspecs: Vec<(&str, &str)>,
+    doc_ref: IndexedDocumentRef,
+    xpath_str: &str,
 ) -> NifResult<Term<'a>> {
-    use xpath::evaluate_from_node;
+    xpath_first_impl(env, &doc_ref, xpath_str)
+}
+
+fn xpath_first_impl<'a>(
+    env: Env<'a>,
+    doc_ref: &IndexedDocumentRef,
+    xpath_str: &str,
+) -> NifResult<Term<'a>> {
+    catch_panic(env, || {
+        let view = doc_ref.as_view();
+        let source = shared_input(env, doc_ref);
+        Ok(match doc_ref.evaluate_first(xpath_str) {
+            Ok(xpath::XPathValue::NodeSet(nodes)) => match nodes.first() {
+                Some(&node) => node_to_term(env, &view, node, source),
+                None => atoms::nil().encode(env),
+            },
+            Ok(value) => xpath_value_to_term(env, value, &view, source),
+            Err(e) => (atoms::error(), e).encode(env),
+        })
+    })
+}
+
+/// Whether an XPath query selects anything
+///
+/// True for a non-empty node-set or any attribute value; other expressions
+/// give their boolean value. Location paths stop at the first match.
+#[rustler::nif]
+fn xpath_exists<'a>(
+    env: Env<'a>,
+    doc_ref: IndexedDocumentRef,
+    xpath_str: &str,
+) -> NifResult<Term<'a>> {
+    xpath_exists_impl(env, &doc_ref, xpath_str)
+}
+
+/// `xpath_exists` on the dirty CPU scheduler, for large documents
+#[rustler::nif(schedule = "DirtyCpu")]
+fn xpath_exists_dirty<'a>(
+    env: Env<'a>,
+    doc_ref: IndexedDocumentRef,
+    xpath_str: &str,
+) -> NifResult<Term<'a>> {
+    xpath_exists_impl(env, &doc_ref, xpath_str)
+}
+
+fn xpath_exists_impl<'a>(
+    env: Env<'a>,
+    doc_ref: &IndexedDocumentRef,
+    xpath_str: &str,
+) -> NifResult<Term<'a>> {
+    catch_panic(env, || {
+        Ok(match doc_ref.evaluate_exists(xpath_str) {
+            Ok(exists) => exists.encode(env),
+            Err(e) => (atoms::error(), e).encode(env),
+        })
+    })
+}
+
+/// Decode a result order atom (`:document`, `:reverse` or `:matched`)
+fn decode_result_order(order: rustler::Atom) -> NifResult<ResultOrder> {
+    if order == atoms::document() {
+        Ok(ResultOrder::Document)
+    } else if order == atoms::reverse() {
+        Ok(ResultOrder::Reverse)
+    } else if order == atoms::matched() {
+        Ok(ResultOrder::Matched)
+    } else {
+        Err(rustler::Error::BadArg)
+    }
+}
+
+/// Node conversion source sharing the document's own bytes
+fn shared_input<'a>(env: Env<'a>, doc_ref: &IndexedDocumentRef) -> BinarySource<'a> {
+    BinarySource::shared(doc_ref.make_binary(env, |doc| doc.input()))
+}
+
+fn xpath_query_impl<'a>(
+    env: Env<'a>,
+    doc_ref: &IndexedDocumentRef,
+    xpath_str: &str,
+    order: ResultOrder,
+) -> NifResult<Term<'a>> {
+    catch_panic(env, || {
+        let view = doc_ref.as_view();
+        let source = shared_input(env, doc_ref);
+        match doc_ref.evaluate_ordered(xpath_str, order) {
+            Ok(value) => Ok(xpath_value_to_term(env, value, &view, source)),
+            Err(e) => Ok((atoms::error(), e).encode(env)),
+        }
+    })
+}
+
+/// Number of nodes (elements + text nodes) in a document
+///
+/// O(1). Used to decide whether a query should run on a dirty scheduler.
+#[rustler::nif]
+fn doc_node_count(doc_ref: IndexedDocumentRef) -> usize {
+    doc_ref.index.element_count() + doc_ref.index.text_count()
+}
+
+/// Root element name and prolog declarations of a document
+///
+/// Returns a map with `:root`, `:version`, `:encoding`, `:standalone` and
+/// `:doctype` (`nil` or a map of `:name`, `:public_id` and `:system_id`).
+/// Fields absent from the document are `nil`. Only the bytes before the
+/// root element are scanned.
+#[rustler::nif]
+fn doc_info<'a>(env: Env<'a>, doc_ref: IndexedDocumentRef) -> NifResult<Term<'a>> {
+    catch_panic(env, || {
+        let view = doc_ref.as_view();
+        let prolog = core::prolog::parse_prolog(doc_ref.input());
+        let optional = |bytes: Option<&[u8]>| match bytes {
+            Some(bytes) => term::bytes_to_binary(env, bytes),
+            None => atoms::nil().encode(env),
+        };
+
+        let root = match view.root_element_id().and_then(|id| view.node_name(id)) {
+            Some(name) => name.encode(env),
+            None => atoms::nil().encode(env),
+        };
+        let standalone = match prolog.standalone {
+            Some(standalone) => standalone.encode(env),
+            None => atoms::nil().encode(env),
+        };
+        let doctype = match &prolog.doctype {
+            Some(doctype) => Term::map_from_pairs(
+                env,
+                &[
+                    (
+                        atoms::name().encode(env),
+                        term::bytes_to_binary(env, doctype.name),
+                    ),
+                    (atoms::public_id().encode(env), optional(doctype.public_id)),
+                    (atoms::system_id().encode(env), optional(doctype.system_id)),
+                ],
+            )?,
+            None => atoms::nil().encode(env),
+        };
+
+        Term::map_from_pairs(
+            env,
+            &[
+                (atoms::root().encode(env), root),
+                (atoms::version().encode(env), optional(prolog.version)),
+                (atoms::encoding().encode(env), optional(prolog.encoding)),
+                (atoms::standalone().encode(env), standalone),
+                (atoms::doctype().encode(env), doctype),
+            ],
+        )
+    })
+}
+
+/// Distinct element paths (`catalog/product/price`) with their occurrence
+/// counts, in order of first occurrence
+#[rustler::nif(schedule = "DirtyCpu")]
+fn doc_tag_paths<'a>(env: Env<'a>, doc_ref: IndexedDocumentRef) -> NifResult<Term<'a>> {
+    catch_panic(env, || {
+        Ok(index::paths::tag_paths(&doc_ref.index, doc_ref.input()).encode(env))
+    })
+}
+
+/// Element ids matching `tag_name` the way the `//tag_name` node test does:
+/// an unprefixed name matches on local name, a prefixed name matches exactly.
+fn element_ids_by_tag(doc_ref: &IndexedDocumentRef, tag_name: &str) -> Vec<u32> {
+    let input = doc_ref.input();
+    let mut ids: Vec<u32> = if tag_name.contains(':') {
+        doc_ref
+            .index
+            .find_elements_by_name(tag_name, input)
+            .collect()
+    } else {
+        doc_ref
+            .index
+            .find_elements_by_local_name(tag_name, input)
+            .collect()
+    };
+    // A fragment's wrapper is not part of the document
+    if doc_ref.index.fragment {
+        ids.retain(|&id| Some(id) != doc_ref.index.root);
+    }
+    ids
+}
+
+/// Find all elements with the given tag name, in document order
+///
+/// Scans the structural index's element table directly — no XPath parsing or
+/// evaluation. Equivalent to `//tag_name`; a prefixed name (`ns:item`) is
+/// matched against the qualified name as written in the document.
+#[rustler::nif]
+fn find_elements<'a>(
+    env: Env<'a>,
+    doc_ref: IndexedDocumentRef,
+    tag_name: &str,
+) -> NifResult<Term<'a>> {
+    catch_panic(env, || {
+        let view = doc_ref.as_view();
+        let ids = element_ids_by_tag(&doc_ref, tag_name);
+
+        let source = shared_input(env, &doc_ref);
+        let mut list = Term::list_new_empty(env);
+        for &id in ids.iter().rev() {
+            list = list.list_prepend(node_to_term(env, &view, id, source));
+        }
+        Ok(list)
+    })
+}
+
+/// Count elements with the given tag name (index scan, equivalent to `count(//tag_name)`)
+#[rustler::nif]
+fn count_elements<'a>(
+    env: Env<'a>,
+    doc_ref: IndexedDocumentRef,
+    tag_name: &str,
+) -> NifResult<Term<'a>> {
+    catch_panic(env, || {
+        Ok(element_ids_by_tag(&doc_ref, tag_name).len().encode(env))
+    })
+}
+
+/// Execute XPath query returning XML strings for node sets (fast path)
+#[rustler::nif]
+fn xpath_query_raw<'a>(
+    env: Env<'a>,
+    doc_ref: IndexedDocumentRef,
+    xpath_str: &str,
+) -> NifResult<Term<'a>> {
+    xpath_query_raw_impl(env, &doc_ref, xpath_str, ResultOrder::Document)
+}
+
+/// `xpath_query_raw` returning a node-set in `order`
+#[rustler::nif]
+fn xpath_query_raw_ordered<'a>(
+    env: Env<'a>,
+    doc_ref: IndexedDocumentRef,
+    xpath_str: &str,
+    order: rustler::Atom,
+) -> NifResult<Term<'a>> {
+    xpath_query_raw_impl(env, &doc_ref, xpath_str, decode_result_order(order)?)
+}
+
+fn xpath_query_raw_impl<'a>(
+    env: Env<'a>,
+    doc_ref: &IndexedDocumentRef,
+    xpath_str: &str,
+    order: ResultOrder,
+) -> NifResult<Term<'a>> {
+    catch_panic(env, || {
+        use term::nodeset_to_xml_binaries;
+        use xpath::XPathValue;
+
+        let view = doc_ref.as_view();
+        match doc_ref.evaluate_ordered(xpath_str, order) {
+            Ok(value) => match value {
+                XPathValue::NodeSet(nodes) => Ok(nodeset_to_xml_binaries(env, &nodes, &view)),
+                _ => Ok(xpath_value_to_term(env, value, &view, BinarySource::COPY)),
+            },
+            Err(e) => Ok((atoms::error(), e).encode(env)),
+        }
+    })
+}
+
+/// Execute XPath query returning text values for node sets (optimized fast path)
+/// Instead of building recursive element tuples ({:element, name, attrs, children}),
+/// extracts text content directly as strings. Used when is_value: true.
+#[rustler::nif]
+fn xpath_text_list<'a>(
+    env: Env<'a>,
+    doc_ref: IndexedDocumentRef,
+    xpath_str: &str,
+) -> NifResult<Term<'a>> {
+    xpath_text_list_impl(env, &doc_ref, xpath_str, ResultOrder::Document)
+}
+
+/// `xpath_text_list` on the dirty CPU scheduler, for large documents
+#[rustler::nif(schedule = "DirtyCpu")]
+fn xpath_text_list_dirty<'a>(
+    env: Env<'a>,
+    doc_ref: IndexedDocumentRef,
+    xpath_str: &str,
+) -> NifResult<Term<'a>> {
+    xpath_text_list_impl(env, &doc_ref, xpath_str, ResultOrder::Document)
+}
+
+/// `xpath_text_list` returning a node-set's text values in `order`
+#[rustler::nif]
+fn xpath_text_list_ordered<'a>(
+    env: Env<'a>,
+    doc_ref: IndexedDocumentRef,
+    xpath_str: &str,
+    order: rustler::Atom,
+) -> NifResult<Term<'a>> {
+    xpath_text_list_impl(env, &doc_ref, xpath_str, decode_result_order(order)?)
+}
+
+fn xpath_text_list_impl<'a>(
+    env: Env<'a>,
+    doc_ref: &IndexedDocumentRef,
+    xpath_str: &str,
+    order: ResultOrder,
+) -> NifResult<Term<'a>> {
+    catch_panic(env, || {
+        use xpath::XPathValue;
+
+        let view = doc_ref.as_view();
+        match doc_ref.evaluate_ordered(xpath_str, order) {
+            Ok(XPathValue::NodeSet(nodes)) => {
+                let mut list = Term::list_new_empty(env);
+                for &id in nodes.iter().rev() {
+                    let text = dom::node_string_value(&view, id);
+                    let binary = term::bytes_to_binary(env, text.as_bytes());
+                    list = list.list_prepend(binary);
+                }
+                Ok(list)
+            }
+            Ok(value) => Ok(xpath_value_to_term(env, value, &view, BinarySource::COPY)),
+            Err(e) => Ok((atoms::error(), e).encode(env)),
+        }
+    })
+}
+
+/// Value of attribute `attr_name` on each node an XPath expression selects
+///
+/// Skips node-to-term conversion entirely. Values without entity references
+/// share the document's memory; nodes lacking the attribute give nil.
+#[rustler::nif]
+fn xpath_attr_list<'a>(
+    env: Env<'a>,
+    doc_ref: IndexedDocumentRef,
+    xpath_str: &str,
+    attr_name: &str,
+) -> NifResult<Term<'a>> {
+    catch_panic(env, || {
+        let nodes = match doc_ref.evaluate(xpath_str) {
+            Ok(xpath::XPathValue::NodeSet(nodes)) => nodes,
+            Ok(_) => {
+                let reason = "expression must select a node-set";
+                return Ok((atoms::error(), reason).encode(env));
+            }
+            Err(e) => return Ok((atoms::error(), e).encode(env)),
+        };
+
+        let input = doc_ref.input();
+        let name = attr_name.as_bytes();
+        let mut list = Term::list_new_empty(env);
+        for &id in nodes.iter().rev() {
+            let (is_text, idx) = index::view::decode_node_id(id);
+            let attr = doc_ref
+                .index
+                .element_attributes(idx)
+                .iter()
+                .find(|attr| !is_text && attr.name.slice(input) == name);
+            let value = match attr {
+                Some(attr) => {
+                    let raw = attr.value.slice(input);
+                    match core::entities::decode_text(raw) {
+                        std::borrow::Cow::Borrowed(_) => doc_ref
+                            .make_binary(env, |doc| attr.value.slice(doc.input()))
+                            .encode(env),
+                        std::borrow::Cow::Owned(decoded) => term::bytes_to_binary(env, &decoded),
+                    }
+                }
+                None => atoms::nil().encode(env),
+            };
+            list = list.list_prepend(value);
+        }
+        Ok(list)
+    })
+}
+
+/// String-values of an XPath result converted to `ty` (`:integer`,
+/// `:float`, `:date` or `:boolean`)
+///
+/// Returns `{values, failures}`: values has nil wherever a string-value
+/// didn't convert, and failures lists those as `{position, string}` with
+/// 0-based positions.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn xpath_values_typed<'a>(
+    env: Env<'a>,
+    doc_ref: IndexedDocumentRef,
+    xpath_str: &str,
+    ty: rustler::Atom,
+) -> NifResult<Term<'a>> {
+    catch_panic(env, || {
+        use xpath::typed::{convert, string_values, TypedValue, ValueType};
+
+        let ty = if ty == atoms::integer() {
+            ValueType::Integer
+        } else if ty == atoms::float() {
+            ValueType::Float
+        } else if ty == atoms::date() {
+            ValueType::Date
+        } else if ty == atoms::boolean() {
+            ValueType::Boolean
+        } else {
+            return Err(rustler::Error::BadArg);
+        };
+        let strings = match doc_ref.evaluate(xpath_str) {
+            Ok(value) => string_values(&doc_ref.as_view(), value),
+            Err(e) => return Ok((atoms::error(), e).encode(env)),
+        };
+
+        let mut values = Vec::with_capacity(strings.len());
+        let mut failures = Vec::new();
+        for (position, string) in strings.iter().enumerate() {
+            let value = match convert(string, ty) {
+                Some(TypedValue::Integer(i)) => i.encode(env),
+                Some(TypedValue::Float(f)) => f.encode(env),
+                Some(TypedValue::Boolean(b)) => b.encode(env),
+                Some(TypedValue::Date { year, month, day }) => Term::map_from_pairs(
+                    env,
+                    &[
+                        (
+                            atoms::struct_().encode(env),
+                            atoms::date_module().encode(env),
+                        ),
+                        (
+                            atoms::calendar().encode(env),
+                            atoms::calendar_iso().encode(env),
+                        ),
+                        (atoms::year().encode(env), year.encode(env)),
+                        (atoms::month().encode(env), month.encode(env)),
+                        (atoms::day().encode(env), day.encode(env)),
+                    ],
+                )?,
+                None => {
+                    failures.push((position, string.as_str()));
+                    atoms::nil().encode(env)
+                }
+            };
+            values.push(value);
+        }
+        Ok((values, failures).encode(env))
+    })
+}
+
+/// Distinct string-values of an XPath result, in order of first occurrence
+#[rustler::nif(schedule = "DirtyCpu")]
+fn xpath_distinct_values<'a>(
+    env: Env<'a>,
+    doc_ref: IndexedDocumentRef,
+    xpath_str: &str,
+) -> NifResult<Term<'a>> {
+    catch_panic(env, || match doc_ref.evaluate(xpath_str) {
+        Ok(value) => {
+            let values = xpath::aggregate::distinct_values(&doc_ref.as_view(), &value);
+            Ok(values.encode(env))
+        }
+        Err(e) => Ok((atoms::error(), e).encode(env)),
+    })
+}
+
+/// Group the nodes selected by `items_xpath` into a map keyed by the
+/// string-value of `key_xpath`, evaluated relative to each node
+#[rustler::nif(schedule = "DirtyCpu")]
+fn xpath_group_by<'a>(
+    env: Env<'a>,
+    doc_ref: IndexedDocumentRef,
+    items_xpath: &str,
+    key_xpath: &str,
+) -> NifResult<Term<'a>> {
+    catch_panic(env, || {
+        let key = match xpath::compiler::compile(key_xpath) {
+            Ok(key) => key,
+            Err(e) => return Ok((atoms::error(), e).encode(env)),
+        };
+        let nodes = match doc_ref.evaluate(items_xpath) {
+            Ok(xpath::XPathValue::NodeSet(nodes)) => nodes,
+            Ok(_) => {
+                let reason = "items expression must select a node-set";
+                return Ok((atoms::error(), reason).encode(env));
+            }
+            Err(e) => return Ok((atoms::error(), e).encode(env)),
+        };
+
+        let view = doc_ref.as_view();
+        let groups = match xpath::aggregate::group_by(&view, &nodes, &key) {
+            Ok(groups) => groups,
+            Err(e) => return Ok((atoms::error(), e).encode(env)),
+        };
+        let pairs: Vec<(Term, Term)> = groups
+            .into_iter()
+            .map(|(key, nodes)| {
+                let nodes = xpath::XPathValue::NodeSet(nodes);
+                let items = xpath_value_to_term(env, nodes, &view, shared_input(env, &doc_ref));
+                (key.encode(env), items)
+            })
+            .collect();
+        Term::map_from_pairs(env, &pairs)
+    })
+}
+
+/// Number of nodes an XPath expression selects, without converting them
+#[rustler::nif(schedule = "DirtyCpu")]
+fn xpath_count<'a>(
+    env: Env<'a>,
+    doc_ref: IndexedDocumentRef,
+    xpath_str: &str,
+) -> NifResult<Term<'a>> {
+    catch_panic(env, || {
+        Ok(
+            match doc_ref
+                .evaluate(xpath_str)
+                .map(|value| xpath::aggregate::count(&value))
+            {
+                Ok(Some(count)) => count.encode(env),
+                Ok(None) => (atoms::error(), "expression must select a node-set").encode(env),
+                Err(e) => (atoms::error(), e).encode(env),
+            },
+        )
+    })
+}
+
+/// Sum of the number-values selected by an XPath expression
+#[rustler::nif(schedule = "DirtyCpu")]
+fn xpath_sum<'a>(
+    env: Env<'a>,
+    doc_ref: IndexedDocumentRef,
+    xpath_str: &str,
+) -> NifResult<Term<'a>> {
+    numeric_aggregate(env, &doc_ref, xpath_str, |view, value| {
+        Some(xpath::aggregate::sum(view, value))
+    })
+}
+
+/// Smallest number-value selected by an XPath expression
+#[rustler::nif(schedule = "DirtyCpu")]
+fn xpath_min<'a>(
+    env: Env<'a>,
+    doc_ref: IndexedDocumentRef,
+    xpath_str: &str,
+) -> NifResult<Term<'a>> {
+    numeric_aggregate(env, &doc_ref, xpath_str, |view, value| {
+        xpath::aggregate::min(view, value)
+    })
+}
+
+/// Largest number-value selected by an XPath expression
+#[rustler::nif(schedule = "DirtyCpu")]
+fn xpath_max<'a>(
+    env: Env<'a>,
+    doc_ref: IndexedDocumentRef,
+    xpath_str: &str,
+) -> NifResult<Term<'a>> {
+    numeric_aggregate(env, &doc_ref, xpath_str, |view, value| {
+        xpath::aggregate::max(view, value)
+    })
+}
+
+/// Evaluate and reduce to a number; `nil` when there is nothing to reduce
+/// or the result isn't a finite number
+fn numeric_aggregate<'a>(
+    env: Env<'a>,
+    doc_ref: &IndexedDocumentRef,
+    xpath_str: &str,
+    reduce: impl FnOnce(&index::IndexedDocumentView, &xpath::XPathValue) -> Option<f64>,
+) -> NifResult<Term<'a>> {
+    catch_panic(env, || {
+        Ok(match doc_ref.evaluate(xpath_str) {
+            Ok(value) => match reduce(&doc_ref.as_view(), &value) {
+                Some(n) if n.is_finite() => n.encode(env),
+                _ => atoms::nil().encode(env),
+            },
+            Err(e) => (atoms::error(), e).encode(env),
+        })
+    })
+}
+
+/// Parse and immediately query (convenience function)
+#[rustler::nif(schedule = "DirtyCpu")]
+fn parse_and_xpath<'a>(env: Env<'a>, input: Binary<'a>, xpath_str: &str) -> NifResult<Term<'a>> {
+    parse_and_xpath_impl(env, input, xpath_str)
+}
+
+/// Parse and immediately query on the normal scheduler, for inputs small
+/// enough that moving to a dirty scheduler would cost more than the work
+#[rustler::nif]
+fn parse_and_xpath_small<'a>(
+    env: Env<'a>,
+    input: Binary<'a>,
+    xpath_str: &str,
+) -> NifResult<Term<'a>> {
+    parse_and_xpath_impl(env, input, xpath_str)
+}
+
+fn parse_and_xpath_impl<'a>(
+    env: Env<'a>,
+    input: Binary<'a>,
+    xpath_str: &str,
+) -> NifResult<Term<'a>> {
+    catch_panic(env, || {
+        let bytes = input.as_slice();
+        let idx = index::builder::build_index(bytes);
+        let decoded = index::TextDecodeCache::default();
+        let view = index::IndexedDocumentView::new(&idx, bytes).with_decode_cache(&decoded);
+
+        let source = BinarySource::shared(input);
+
+        match evaluate(&view, xpath_str) {
+            Ok(value) => Ok(xpath_value_to_term(env, value, &view, source)),
+            Err(e) => Ok((atoms::error(), e).encode(env)),
+        }
+    })
+}
+
+/// Parse and immediately query, returning text values for node sets
+/// Optimized path for is_value: true — avoids building element tuples
+#[rustler::nif(schedule = "DirtyCpu")]
+fn parse_and_xpath_text<'a>(
+    env: Env<'a>,
+    input: Binary<'a>,
+    xpath_str: &str,
+) -> NifResult<Term<'a>> {
+    parse_and_xpath_text_impl(env, input, xpath_str)
+}
+
+/// `parse_and_xpath_text` on the normal scheduler, for small inputs
+#[rustler::nif]
+fn parse_and_xpath_text_small<'a>(
+    env: Env<'a>,
+    input: Binary<'a>,
+    xpath_str: &str,
+) -> NifResult<Term<'a>> {
+    parse_and_xpath_text_impl(env, input, xpath_str)
+}
+
+fn parse_and_xpath_text_impl<'a>(
+    env: Env<'a>,
+    input: Binary<'a>,
+    xpath_str: &str,
+) -> NifResult<Term<'a>> {
+    catch_panic(env, || {
+        use xpath::XPathValue;
+
+        let bytes = input.as_slice();
+        let idx = index::builder::build_index(bytes);
+        let decoded = index::TextDecodeCache::default();
+        let view = index::IndexedDocumentView::new(&idx, bytes).with_decode_cache(&decoded);
+
+        match evaluate(&view, xpath_str) {
+            Ok(XPathValue::NodeSet(nodes)) => {
+                let mut list = Term::list_new_empty(env);
+                for &id in nodes.iter().rev() {
+                    let text = dom::node_string_value(&view, id);
+                    let binary = term::bytes_to_binary(env, text.as_bytes());
+                    list = list.list_prepend(binary);
+                }
+                Ok(list)
+            }
+            Ok(value) => Ok(xpath_value_to_term(env, value, &view, BinarySource::COPY)),
+            Err(e) => Ok((atoms::error(), e).encode(env)),
+        }
+    })
+}
+
+/// Get root element of a document
+#[rustler::nif]
+fn get_root<'a>(env: Env<'a>, doc_ref: IndexedDocumentRef) -> NifResult<Term<'a>> {
+    get_root_impl(env, &doc_ref)
+}
+
+/// Get root element of a document (dirty CPU scheduler, for large documents)
+#[rustler::nif(schedule = "DirtyCpu")]
+fn get_root_dirty<'a>(env: Env<'a>, doc_ref: IndexedDocumentRef) -> NifResult<Term<'a>> {
+    get_root_impl(env, &doc_ref)
+}
+
+fn get_root_impl<'a>(env: Env<'a>, doc_ref: &IndexedDocumentRef) -> NifResult<Term<'a>> {
+    catch_panic(env, || {
+        let view = doc_ref.as_view();
+        if let Some(root_id) = view.root_element_id() {
+            let source = shared_input(env, doc_ref);
+            Ok(node_to_term(env, &view, root_id, source))
+        } else {
+            Ok(atoms::nil().encode(env))
+        }
+    })
+}
+
+// ============================================================================
+// Closing Resources
+// ============================================================================
+
+/// Release a document now rather than when its reference is collected
+///
+/// Later calls with the reference return `{:error, :closed}`. Queries
+/// already running finish first, and sub-binaries of the input keep it
+/// alive. Closing twice is a no-op; documents from `parse_cached` are
+/// shared and refused.
+#[rustler::nif]
+fn doc_close<'a>(env: Env<'a>, doc: ResourceArc<resource::DocumentHandle>) -> Term<'a> {
+    if doc.close() {
+        atoms::ok().encode(env)
+    } else {
+        (atoms::error(), atoms::shared_document()).encode(env)
+    }
+}
+
+/// Release a streaming parser's buffers (`streaming_new*` or
+/// `streaming_sax_new*`); later calls return `{:error, :closed}`
+#[rustler::nif]
+fn streaming_close(parser: Term) -> NifResult<rustler::Atom> {
+    if let Ok(parser) = parser.decode::<StreamingParserRef>() {
+        parser.close();
+    } else {
+        parser.decode::<StreamingSaxParserRef>()?.close();
+    }
+    Ok(atoms::ok())
+}
+
+// ============================================================================
+// Shared Document Cache
+// ============================================================================
+
+static DOCUMENT_CACHE: doc_cache::DocumentCache<IndexedDocumentRef> =
+    doc_cache::DocumentCache::new(doc_cache::DEFAULT_CAPACITY);
+
+/// Parse like `parse_strict`, returning the cached document if the same
+/// input was parsed before
+///
+/// Inputs are looked up by hash and compared byte for byte, so every caller
+/// of an identical payload gets the same document. Cached documents are
+/// shared and their settings can't be changed. Malformed input is not
+/// cached.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn parse_cached<'a>(env: Env<'a>, input: Binary<'a>) -> NifResult<Term<'a>> {
+    catch_panic(env, || {
+        let key = doc_cache::key(input.as_slice());
+        if let Some(doc) = DOCUMENT_CACHE.get(key) {
+            // xxh3 isn't collision resistant: a crafted input could share
+            // another payload's hash
+            if is_cached_input(&doc, input.as_slice()) {
+                return Ok((atoms::ok(), doc).encode(env));
+            }
+        }
+        match strict_resource(env, input, dom::StrictOptions::default()) {
+            Ok(resource) => {
+                resource.mark_shared();
+                let doc = IndexedDocumentRef::new(resource);
+                DOCUMENT_CACHE.insert(key, doc.clone());
+                Ok((atoms::ok(), doc).encode(env))
+            }
+            Err(error) => Ok(error),
+        }
+    })
+}
+
+/// Whether `doc` was parsed from `input`
+///
+/// A document holds its input as `strict_resource` stored it: past a UTF-8
+/// BOM, or converted from UTF-16. `input` is brought to the same form to
+/// compare.
+fn is_cached_input(doc: &IndexedDocumentRef, input: &[u8]) -> bool {
+    use crate::core::encoding::{convert_to_utf8, XmlEncoding};
+
+    match XmlEncoding::detect(input) {
+        XmlEncoding::Utf8 => input[XmlEncoding::Utf8.bom_len(input)..] == *doc.input(),
+        _ => convert_to_utf8(input.to_vec()).is_ok_and(|bytes| bytes == doc.input()),
+    }
+}
+
+/// Set how many documents `parse_cached` keeps; 0 empties and disables
+/// the cache
+#[rustler::nif]
+fn set_cache_capacity(capacity: usize) -> rustler::Atom {
+    DOCUMENT_CACHE.set_capacity(capacity);
+    atoms::ok()
+}
+
+// ============================================================================
+// Asynchronous Parsing
+// ============================================================================
+
+/// Build a document's index on a background worker, reporting progress to
+/// `pid`
+///
+/// Returns a task handle at once; builds beyond the pool size wait for a
+/// free worker. The worker sends
+/// `{:rustyxml_parse, task, {:progress, bytes_done, total}}` each time
+/// another `interval` bytes are scanned and once more on completion, then
+/// `{:rustyxml_parse, task, {:ok, doc}}`, or
+/// `{:rustyxml_parse, task, {:error, :cancelled}}` after `parse_cancel`.
+/// Like `parse`, the input is indexed leniently and retained, not copied.
+/// The build is also abandoned if `pid` exits. The memory limit is
+/// checked before and after the build, and the depth limit after, as
+/// `parse_fragment` does; either ends the task with its error.
+#[rustler::nif]
+fn parse_async<'a>(
+    env: Env<'a>,
+    input: Binary<'a>,
+    pid: LocalPid,
+    interval: usize,
+) -> NifResult<ParseTaskRef> {
+    if interval == 0 {
+        return Err(rustler::Error::BadArg);
+    }
+    let task = ResourceArc::new(ParseTaskResource::default());
+    if task.monitor(Some(env), &pid).is_none() {
+        task.cancel();
+    }
+    // Skip a UTF-8 BOM here; UTF-16 is converted on the worker, as
+    // `lenient_resource` does
+    let bom_len = core::encoding::XmlEncoding::Utf8.bom_len(input.as_slice());
+    let input = RetainedBinary::new(input, bom_len);
+    let handle = task.clone();
+
+    /// How a build on the worker ended
+    enum Build {
+        Done(Box<index::StructuralIndex>, Option<Vec<u8>>),
+        Cancelled,
+        OverMemoryLimit,
+    }
+
+    workers::spawn_parse(move || {
+        let finished = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            // Cancelled while queued
+            if handle.is_cancelled() {
+                return Build::Cancelled;
+            }
+            if !within_memory_limit(input.as_slice().len()) {
+                return Build::OverMemoryLimit;
+            }
+            let converted = core::encoding::convert_utf16_lossy(input.as_slice());
+            let bytes = converted.as_deref().unwrap_or(input.as_slice());
+            let total = bytes.len();
+            let mut msg_env = OwnedEnv::new();
+            let mut send_progress = |done: usize| {
+                msg_env
+                    .send_and_clear(&pid, |env| {
+                        let progress = (atoms::progress(), done, total);
+                        (atoms::rustyxml_parse(), &handle, progress).encode(env)
+                    })
+                    .is_ok()
+            };
+            let index = index::builder::build_index_with_progress(bytes, interval, |done| {
+                !handle.is_cancelled() && send_progress(done)
+            });
+            match index.filter(|_| send_progress(total)) {
+                Some(index) => Build::Done(Box::new(index), converted),
+                None => Build::Cancelled,
+            }
+        }));
+
+        let _ = OwnedEnv::new().send_and_clear(&pid, |env| {
+            let result = match finished {
+                Ok(Build::Done(index, converted)) => {
+                    let resource = match converted {
+                        Some(bytes) => IndexedDocumentResource::from_owned(*index, bytes),
+                        None => IndexedDocumentResource::from_retained(*index, input),
+                    };
+                    match check_depth(env, &resource.index).and_then(|_| check_memory(env, 0)) {
+                        Ok(()) => (atoms::ok(), IndexedDocumentRef::new(resource)).encode(env),
+                        Err(error) => error,
+                    }
+                }
+                Ok(Build::Cancelled) => (atoms::error(), atoms::cancelled()).encode(env),
+                Ok(Build::OverMemoryLimit) => (atoms::error(), atoms::memory_limit()).encode(env),
+                Err(_) => (atoms::error(), atoms::internal_parser_error()).encode(env),
+            };
+            (atoms::rustyxml_parse(), &handle, result).encode(env)
+        });
+    });
+
+    Ok(task)
+}
+
+/// Abandon a `parse_async` build; a finished build is unaffected
+#[rustler::nif]
+fn parse_cancel(task: ParseTaskRef) -> rustler::Atom {
+    task.cancel();
+    atoms::ok()
+}
+
+// ============================================================================
+// Cancellation
+// ============================================================================
+
+/// New, unset cancellation token
+#[rustler::nif]
+fn cancel_token_new() -> CancelTokenRef {
+    ResourceArc::new(CancelTokenResource::default())
+}
+
+/// Set a token, stopping work running under it at its next check
+#[rustler::nif]
+fn cancel_token_cancel(token: CancelTokenRef) -> rustler::Atom {
+    token.cancel();
+    atoms::ok()
+}
+
+/// Whether a token has been set
+#[rustler::nif]
+fn cancel_token_cancelled(token: CancelTokenRef) -> bool {
+    token.is_cancelled()
+}
+
+/// `parse`, stopping between tokens once `token` is set
+///
+/// Returns `{:ok, doc}`, or `{:error, :cancelled}` if the token was set
+/// before the index was complete.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn parse_cancellable<'a>(
+    env: Env<'a>,
+    input: Binary<'a>,
+    token: CancelTokenRef,
+) -> NifResult<Term<'a>> {
+    catch_panic(env, || {
+        if let Err(error) = check_memory(env, input.len()) {
+            return Ok(error);
+        }
+        let resource = cancel::run(&token.flag, || lenient_resource(input));
+        if token.is_cancelled() {
+            return Ok((atoms::error(), atoms::cancelled()).encode(env));
+        }
+        if let Err(error) = check_memory(env, 0) {
+            return Ok(error);
+        }
+        Ok((atoms::ok(), IndexedDocumentRef::new(resource)).encode(env))
+    })
+}
+
+/// `xpath_query`, stopping between operations and context nodes once
+/// `token` is set
+///
+/// Returns the result as `xpath_query` does, or `{:error, :cancelled}`.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn xpath_query_cancellable<'a>(
+    env: Env<'a>,
+    doc_ref: IndexedDocumentRef,
+    xpath_str: &str,
+    token: CancelTokenRef,
+) -> NifResult<Term<'a>> {
+    catch_panic(env, || {
+        let result = cancel::run(&token.flag, || doc_ref.evaluate(xpath_str));
+        match result {
+            Ok(value) => {
+                let source = shared_input(env, &doc_ref);
+                Ok(xpath_value_to_term(env, value, &doc_ref.as_view(), source))
+            }
+            Err(_) if token.is_cancelled() => Ok((atoms::error(), atoms::cancelled()).encode(env)),
+            Err(e) => Ok((atoms::error(), e).encode(env)),
+        }
+    })
+}
+
+// ============================================================================
+// Cross-Document Queries
+// ============================================================================
+
+/// Execute an XPath query with additional documents reachable via `document()`
+///
+/// `docs` maps names to parsed documents; `document('name')` in the query
+/// returns that document's root node. Nodes from any of the documents can
+/// appear in the result.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn xpath_query_with_docs<'a>(
+    env: Env<'a>,
+    doc_ref: IndexedDocumentRef,
+    xpath_str: &str,
+    docs: std::collections::HashMap<String, IndexedDocumentRef>,
+) -> NifResult<Term<'a>> {
+    catch_panic(env, || {
+        // Sorted so slot assignment (and cross-document result order) is stable
+        let mut docs: Vec<_> = docs.into_iter().collect();
+        docs.sort_by(|a, b| a.0.cmp(&b.0));
+        let others = docs
+            .iter()
+            .map(|(name, other)| (name.clone(), other.as_view()))
+            .collect();
+
+        let view = match index::MultiDocumentView::new(doc_ref.as_view(), others) {
+            Ok(view) => view,
+            Err(e) => return Ok((atoms::error(), e).encode(env)),
+        };
+        let source = shared_input(env, &doc_ref);
+        match evaluate(&view, xpath_str) {
+            Ok(value) => Ok(xpath_value_to_term(env, value, &view, source)),
+            Err(e) => Ok((atoms::error(), e).encode(env)),
+        }
+    })
+}
+
+// ============================================================================
+// Document Sets
+// ============================================================================
+
+/// Create an empty document set
+#[rustler::nif]
+fn docset_new() -> DocumentSetRef {
+    ResourceArc::new(DocumentSetResource::default())
+}
+
+/// Parse a document in strict mode and add it to a set
+///
+/// Returns `{:ok, doc_id}` with the document's position in the set, or the
+/// same errors as `parse_strict`.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn docset_add<'a>(env: Env<'a>, set: DocumentSetRef, input: Binary<'a>) -> NifResult<Term<'a>> {
+    let resource = match strict_resource(env, input, dom::StrictOptions::default()) {
+        Ok(resource) => resource,
+        Err(error) => return Ok(error),
+    };
+    match set.add(resource) {
+        Some(id) => Ok((atoms::ok(), id).encode(env)),
+        None => Ok((atoms::error(), atoms::mutex_poisoned()).encode(env)),
+    }
+}
+
+/// Number of documents in a set
+#[rustler::nif]
+fn docset_size<'a>(env: Env<'a>, set: DocumentSetRef) -> NifResult<Term<'a>> {
+    match set.len() {
+        Some(len) => Ok(len.encode(env)),
+        None => Ok((atoms::error(), atoms::mutex_poisoned()).encode(env)),
+    }
+}
+
+/// Run an XPath query against every document in a set
+///
+/// The expression is compiled once and evaluated in parallel. Returns a
+/// list of `{doc_id, result}` in id order; documents whose result is an
+/// empty node-set are left out.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn docset_query<'a>(env: Env<'a>, set: DocumentSetRef, xpath_str: &str) -> NifResult<Term<'a>> {
+    catch_panic(env, || {
+        use xpath::XPathValue;
+
+        let Some(docs) = set.docs() else {
+            return Ok((atoms::error(), atoms::mutex_poisoned()).encode(env));
+        };
+        let values = match DocumentSetResource::evaluate_all(&docs, xpath_str) {
+            Ok(values) => values,
+            Err(e) => return Ok((atoms::error(), e).encode(env)),
+        };
+
+        let results: Vec<Term<'a>> = values
+            .into_iter()
+            .zip(docs.iter())
+            .enumerate()
+            .filter(
+                |(_, (value, _))| !matches!(value, XPathValue::NodeSet(nodes) if nodes.is_empty()),
+            )
+            .map(|(id, (value, doc))| {
+                let value = xpath_value_to_term(env, value, &doc.as_view(), BinarySource::COPY);
+                (id, value).encode(env)
+            })
+            .collect();
+        Ok(results.encode(env))
+    })
+}
+
+// ============================================================================
+// Per-Document Result Cache
+// ============================================================================
+
+/// Enable the node-set result cache on a document with room for `capacity`
+/// queries, or disable it with 0. Existing entries are dropped.
+/// Documents from `parse_cached` are shared and refused.
+#[rustler::nif]
+fn doc_set_result_cache<'a>(
+    env: Env<'a>,
+    doc_ref: IndexedDocumentRef,
+    capacity: usize,
+) -> NifResult<Term<'a>> {
+    if doc_ref.is_shared() {
+        return Ok((atoms::error(), atoms::shared_document()).encode(env));
+    }
+    if doc_ref.result_cache.set_capacity(capacity) {
+        Ok(atoms::ok().encode(env))
+    } else {
+        Ok((atoms::error(), atoms::mutex_poisoned()).encode(env))
+    }
+}
+
+/// Drop all cached results of a document, keeping the cache enabled
+#[rustler::nif]
+fn doc_clear_result_cache<'a>(env: Env<'a>, doc_ref: IndexedDocumentRef) -> NifResult<Term<'a>> {
+    if doc_ref.result_cache.clear() {
+        Ok(atoms::ok().encode(env))
+    } else {
+        Ok((atoms::error(), atoms::mutex_poisoned()).encode(env))
+    }
+}
+
+/// Number of queries currently cached for a document
+#[rustler::nif]
+fn doc_result_cache_size(doc_ref: IndexedDocumentRef) -> usize {
+    doc_ref.result_cache.len()
+}
+
+// ============================================================================
+// Attribute Value Indexes
+// ============================================================================
+
+/// Index the values of attribute `attr_name` across a document
+///
+/// Afterwards, queries of the form `//name[@attr_name = 'literal']` and
+/// `//name[@attr_name]` on this document are answered from the index instead
+/// of scanning every element. Building again rebuilds the index. Documents
+/// from `parse_cached` are shared and refused.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn doc_build_attr_index<'a>(
+    env: Env<'a>,
+    doc_ref: IndexedDocumentRef,
+    attr_name: &str,
+) -> NifResult<Term<'a>> {
+    catch_panic(env, || {
+        if doc_ref.is_shared() {
+            return Ok((atoms::error(), atoms::shared_document()).encode(env));
+        }
+        if doc_ref.build_attr_index(attr_name) {
+            Ok(atoms::ok().encode(env))
+        } else {
+            Ok((atoms::error(), atoms::mutex_poisoned()).encode(env))
+        }
+    })
+}
+
+/// Names of the attributes indexed on a document
+#[rustler::nif]
+fn doc_attr_indexes(doc_ref: IndexedDocumentRef) -> Vec<String> {
+    doc_ref.attr_indexes.names()
+}
+
+/// Every occurrence of attribute `attr_name` as `{element_name, value}`,
+/// in document order, from one pass over the index
+///
+/// Values are entity-decoded and put in the document's normalization form.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn doc_all_attributes<'a>(
+    env: Env<'a>,
+    doc_ref: IndexedDocumentRef,
+    attr_name: &str,
+) -> NifResult<Term<'a>> {
+    catch_panic(env, || {
+        let input = doc_ref.input();
+        let index = &doc_ref.index;
+        let normalization = doc_ref.normalization();
+        let source = shared_input(env, &doc_ref);
+        let mut list = Term::list_new_empty(env);
+        for (elem_idx, attr) in index
+            .attribute_occurrences(attr_name, input)
+            .into_iter()
+            .rev()
+        {
+            let Some(elem) = index.get_element(elem_idx) else {
+                continue;
+            };
+            let decoded = core::entities::decode_text(attr.value.slice(input));
+            let value = match normalization.apply(decoded) {
+                std::borrow::Cow::Borrowed(value) => source.binary(env, value),
+                std::borrow::Cow::Owned(value) => term::bytes_to_binary(env, &value),
+            };
+            let name = source.binary(env, elem.name.slice(input));
+            list = list.list_prepend((name, value).encode(env));
+        }
+        Ok(list)
+    })
+}
+
+// ============================================================================
+// Unicode Normalization
+// ============================================================================
+
+/// Decode a normalization form atom (`:nfc`, `:nfd` or `:none`)
+fn decode_normalization(form: rustler::Atom) -> NifResult<Normalization> {
+    if form == atoms::nfc() {
+        Ok(Normalization::Nfc)
+    } else if form == atoms::nfd() {
+        Ok(Normalization::Nfd)
+    } else if form == atoms::none() {
+        Ok(Normalization::None)
+    } else {
+        Err(rustler::Error::BadArg)
+    }
+}
+
+/// Return node string-values of a document in NFC or NFD (`:none` to turn
+/// normalization off); documents from `parse_cached` are refused
+#[rustler::nif]
+fn doc_set_normalization<'a>(
+    env: Env<'a>,
+    doc_ref: IndexedDocumentRef,
+    form: rustler::Atom,
+) -> NifResult<Term<'a>> {
+    let normalization = decode_normalization(form)?;
+    if doc_ref.is_shared() {
+        return Ok((atoms::error(), atoms::shared_document()).encode(env));
+    }
+    doc_ref.set_normalization(normalization);
+    Ok(atoms::ok().encode(env))
+}
+
+// ============================================================================
+// Precomputed String-Values
+// ============================================================================
+
+/// Lay out the string-value of every element in one buffer, so XPath
+/// string-values of elements become slice lookups (no-op if already done)
+#[rustler::nif(schedule = "DirtyCpu")]
+fn doc_precompute_text<'a>(env: Env<'a>, doc_ref: IndexedDocumentRef) -> NifResult<Term<'a>> {
+    catch_panic(env, || {
+        doc_ref.precompute_text();
+        Ok(atoms::ok().encode(env))
+    })
+}
+
+// ============================================================================
+// Cooperative Node-Set Conversion
+// ============================================================================
+
+/// Nodes converted between timeslice checks in `cursor_next`
+const CURSOR_BATCH: usize = 64;
+
+/// Wall time treated as one full scheduler timeslice (the BEAM's ~1 ms)
+const TIMESLICE_MICROS: u128 = 1000;
+
+/// Evaluate an XPath query, returning node-set results as a cursor
+///
+/// Returns `{:cont, cursor}` for node-sets, to be drained with `cursor_next/1`,
+/// or `{:done, value}` for scalar results. Large node-sets are then converted
+/// to terms over several calls instead of blocking the scheduler in one.
+#[rustler::nif]
+fn xpath_query_cursor<'a>(
+    env: Env<'a>,
+    doc_ref: IndexedDocumentRef,
+    xpath_str: &str,
+) -> NifResult<Term<'a>> {
+    catch_panic(env, || {
+        let view = doc_ref.as_view();
+        match doc_ref.evaluate(xpath_str) {
+            Ok(xpath::XPathValue::NodeSet(nodes)) => {
+                let cursor = ResourceArc::new(NodeSetCursor::new(doc_ref.clone(), nodes));
+                Ok((atoms::cont(), cursor).encode(env))
+            }
+            Ok(value) => {
+                let value = xpath_value_to_term(env, value, &view, BinarySource::COPY);
+                Ok((atoms::done(), value).encode(env))
+            }
+            Err(e) => Ok((atoms::error(), e).encode(env)),
+        }
+    })
+}
+
+/// Convert the next nodes of a cursor until the timeslice is used up
+///
+/// Returns `{:cont, nodes}` when more remain, `{:done, nodes}` when the
+/// cursor is exhausted. Always converts at least one batch, so every call
+/// makes progress.
+#[rustler::nif]
+fn cursor_next<'a>(env: Env<'a>, cursor: NodeSetCursorRef) -> NifResult<Term<'a>> {
+    catch_panic(env, || {
+        let Some(mut state) = cursor.state() else {
+            return Ok((atoms::error(), atoms::mutex_poisoned()).encode(env));
+        };
+        let (nodes, pos) = &mut *state;
+        let view = cursor.doc.as_view();
+        let source = shared_input(env, &cursor.doc);
+
+        let mut terms = Vec::with_capacity(CURSOR_BATCH.min(nodes.len() - *pos));
+        while *pos < nodes.len() {
+            let batch_start = std::time::Instant::now();
+            let end = (*pos + CURSOR_BATCH).min(nodes.len());
+            for &id in &nodes[*pos..end] {
+                terms.push(node_to_term(env, &view, id, source));
+            }
+            *pos = end;
+
+            let percent = batch_start.elapsed().as_micros() * 100 / TIMESLICE_MICROS;
+            if rustler::schedule::consume_timeslice(env, percent.clamp(1, 100) as i32) {
+                break;
+            }
+        }
+
+        let mut list = Term::list_new_empty(env);
+        for term in terms.into_iter().rev() {
+            list = list.list_prepend(term);
+        }
+        let tag = if *pos < nodes.len() {
+            atoms::cont()
+        } else {
+            atoms::done()
+        };
+        Ok((tag, list).encode(env))
+    })
+}
+
+/// Evaluate an XPath query, converting the first `chunk_size` nodes
+///
+/// Returns `{nodes, continuation}` when more nodes remain, to be resumed with
+/// `xpath_chunk_next`, or `{nodes, :done}`. Scalar results come back as
+/// `{[value], :done}`.
+#[rustler::nif]
+fn xpath_query_chunked<'a>(
+    env: Env<'a>,
+    doc_ref: IndexedDocumentRef,
+    xpath_str: &str,
+    chunk_size: usize,
+) -> NifResult<Term<'a>> {
+    catch_panic(env, || {
+        if chunk_size == 0 {
+            return Err(rustler::Error::BadArg);
+        }
+        let nodes = match doc_ref.evaluate(xpath_str) {
+            Ok(xpath::XPathValue::NodeSet(nodes)) => nodes,
+            Ok(value) => {
+                let value = xpath_value_to_term(env, value, &doc_ref.as_view(), BinarySource::COPY);
+                return Ok((vec![value], atoms::done()).encode(env));
+            }
+            Err(e) => return Ok((atoms::error(), e).encode(env)),
+        };
+        let chunks = NodeSetChunks {
+            cursor: NodeSetCursor::new(doc_ref, nodes),
+            chunk_size,
+        };
+        Ok(next_chunk(env, ResourceArc::new(chunks)))
+    })
+}
+
+/// Convert the next chunk of an `xpath_query_chunked` result
+///
+/// Returns `{nodes, continuation}` or `{nodes, :done}`; an exhausted
+/// continuation yields `{[], :done}`.
+#[rustler::nif]
+fn xpath_chunk_next<'a>(env: Env<'a>, chunks: NodeSetChunksRef) -> NifResult<Term<'a>> {
+    catch_panic(env, || Ok(next_chunk(env, chunks)))
+}
+
+fn next_chunk(env: Env<'_>, chunks: NodeSetChunksRef) -> Term<'_> {
+    let Some(mut state) = chunks.cursor.state() else {
+        return (atoms::error(), atoms::mutex_poisoned()).encode(env);
+    };
+    let (nodes, pos) = &mut *state;
+    let view = chunks.cursor.doc.as_view();
+    let source = shared_input(env, &chunks.cursor.doc);
+
+    let end = (*pos + chunks.chunk_size).min(nodes.len());
+    let mut list = Term::list_new_empty(env);
+    for &id in nodes[*pos..end].iter().rev() {
+        list = list.list_prepend(node_to_term(env, &view, id, source));
+    }
+    *pos = end;
+
+    if *pos < nodes.len() {
+        drop(state);
+        (list, chunks).encode(env)
+    } else {
+        (list, atoms::done()).encode(env)
+    }
+}
+
+// ============================================================================
+// Raw XML Extraction
+// ============================================================================
+
+/// Evaluate an XPath query, returning node ids for `node_outer_xml`/`node_inner_xml`
+///
+/// Returns `{:ok, ids}` in document order, or `{:error, reason}` if the query
+/// fails or does not select a node-set.
+#[rustler::nif]
+fn xpath_node_ids<'a>(
+    env: Env<'a>,
+    doc_ref: IndexedDocumentRef,
+    xpath_str: &str,
+) -> NifResult<Term<'a>> {
+    catch_panic(env, || match doc_ref.evaluate(xpath_str) {
+        Ok(xpath::XPathValue::NodeSet(nodes)) => Ok((atoms::ok(), nodes).encode(env)),
+        Ok(_) => Ok((atoms::error(), "XPath result is not a node-set").encode(env)),
+        Err(e) => Ok((atoms::error(), e).encode(env)),
+    })
+}
+
+/// Resolve an XPointer (shorthand or scheme-based) to node ids
+///
+/// See `xpointer` for the schemes supported.
+#[rustler::nif]
+fn resolve_xpointer<'a>(
+    env: Env<'a>,
+    doc_ref: IndexedDocumentRef,
+    pointer: &str,
+) -> NifResult<Term<'a>> {
+    catch_panic(env, || {
+        match xpointer::resolve(&doc_ref.as_view(), pointer) {
+            Ok(nodes) => Ok((atoms::ok(), nodes).encode(env)),
+            Err(e) => Ok((atoms::error(), e).encode(env)),
+        }
+    })
+}
+
+/// Raw source bytes of a node, start tag through end tag
+///
+/// Returns a binary sharing the document's memory (no copy), or nil for an
+/// unknown node id.
+#[rustler::nif]
+fn node_outer_xml<'a>(
+    env: Env<'a>,
+    doc_ref: IndexedDocumentRef,
+    node_id: u32,
+) -> NifResult<Term<'a>> {
+    catch_panic(env, || {
+        Ok(match doc_ref.as_view().outer_range(node_id) {
+            Some(range) => doc_ref
+                .make_binary(env, |doc| &doc.input()[range])
+                .encode(env),
+            None => atoms::nil().encode(env),
+        })
+    })
+}
+
+/// Raw source bytes of a node's content, between its start and end tags
+///
+/// Returns a binary sharing the document's memory (no copy), or nil for an
+/// unknown node id.
+#[rustler::nif]
+fn node_inner_xml<'a>(
+    env: Env<'a>,
+    doc_ref: IndexedDocumentRef,
+    node_id: u32,
+) -> NifResult<Term<'a>> {
+    catch_panic(env, || {
+        Ok(match doc_ref.as_view().inner_range(node_id) {
+            Some(range) => doc_ref
+                .make_binary(env, |doc| &doc.input()[range])
+                .encode(env),
+            None => atoms::nil().encode(env),
+        })
+    })
+}
+
+/// Number of child nodes of a node, or nil for an unknown node id
+#[rustler::nif]
+fn node_child_count(doc_ref: IndexedDocumentRef, node_id: u32) -> Option<usize> {
+    doc_ref.as_view().child_count(node_id)
+}
+
+/// Number of nodes below a node, or nil for an unknown node id
+#[rustler::nif]
+fn node_descendant_count(doc_ref: IndexedDocumentRef, node_id: u32) -> Option<usize> {
+    doc_ref.as_view().descendant_count(node_id)
+}
+
+/// Number of ancestors of a node (the root element is at depth 1), or nil
+/// for an unknown node id
+#[rustler::nif]
+fn node_depth(doc_ref: IndexedDocumentRef, node_id: u32) -> Option<usize> {
+    doc_ref.as_view().depth(node_id)
+}
+
+/// 1-based position of a node among its siblings, or nil for the document
+/// node and unknown node ids
+#[rustler::nif]
+fn node_position(doc_ref: IndexedDocumentRef, node_id: u32) -> Option<usize> {
+    doc_ref.as_view().position(node_id)
+}
+
+/// A node as a term, converting at most `depth` levels below it
+///
+/// Elements at the limit that have children get `[{:more, node_id}]` as
+/// their children, a handle to convert them by later. Returns nil for an
+/// unknown node id.
+#[rustler::nif]
+fn node_to_term_shallow<'a>(
+    env: Env<'a>,
+    doc_ref: IndexedDocumentRef,
+    node_id: u32,
+    depth: usize,
+) -> NifResult<Term<'a>> {
+    node_to_term_shallow_impl(env, doc_ref, node_id, depth)
+}
+
+/// `node_to_term_shallow` on the dirty CPU scheduler, for deep limits on
+/// large documents
+#[rustler::nif(schedule = "DirtyCpu")]
+fn node_to_term_shallow_dirty<'a>(
+    env: Env<'a>,
+    doc_ref: IndexedDocumentRef,
+    node_id: u32,
+    depth: usize,
+) -> NifResult<Term<'a>> {
+    node_to_term_shallow_impl(env, doc_ref, node_id, depth)
+}
+
+fn node_to_term_shallow_impl<'a>(
+    env: Env<'a>,
+    doc_ref: IndexedDocumentRef,
+    node_id: u32,
+    depth: usize,
+) -> NifResult<Term<'a>> {
+    catch_panic(env, || {
+        let view = doc_ref.as_view();
+        if view.child_count(node_id).is_none() {
+            return Ok(atoms::nil().encode(env));
+        }
+        let source = shared_input(env, &doc_ref);
+        Ok(term::node_to_term_shallow(
+            env,
+            &view,
+            node_id,
+            source,
+            Some(depth),
+        ))
+    })
+}
+
+/// A page of a node's children as terms: up to `limit` of them starting
+/// with the `offset`th (0-based)
+///
+/// Child elements are converted without their own children, which become
+/// `[{:more, node_id}]` as in `node_to_term_shallow`, so a page costs the
+/// same however large the subtrees under it are. Returns nil for an unknown
+/// node id.
+#[rustler::nif]
+fn node_children_terms<'a>(
+    env: Env<'a>,
+    doc_ref: IndexedDocumentRef,
+    node_id: u32,
+    offset: usize,
+    limit: usize,
+) -> NifResult<Term<'a>> {
+    catch_panic(env, || {
+        let view = doc_ref.as_view();
+        let Some(children) = view.children_page(node_id, offset, limit) else {
+            return Ok(atoms::nil().encode(env));
+        };
+        let source = shared_input(env, &doc_ref);
+        let mut list = Term::list_new_empty(env);
+        for &child in children.iter().rev() {
+            let term = term::node_to_term_shallow(env, &view, child, source, Some(0));
+            list = list.list_prepend(term);
+        }
+        Ok(list)
+    })
+}
+
+/// Copy an element's subtree into a new, independently indexed document
+///
+/// The new document owns a copy of the subtree's bytes, so the parent can
+/// be released while work continues on the extract. Namespace declarations
+/// inherited from ancestors are added to the new root element. Returns nil
+/// for an unknown or non-element node id.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn doc_extract_subtree<'a>(
+    env: Env<'a>,
+    doc_ref: IndexedDocumentRef,
+    node_id: u32,
+) -> NifResult<Term<'a>> {
+    catch_panic(env, || {
+        let Some(range) = doc_ref.as_view().outer_range(node_id) else {
+            return Ok(atoms::nil().encode(env));
+        };
+        if let Err(error) = check_memory(env, range.len()) {
+            return Ok(error);
+        }
+        let Some(resource) = doc_ref.extract_subtree(node_id) else {
+            return Ok(atoms::nil().encode(env));
+        };
+        if let Err(error) = check_memory(env, 0) {
+            return Ok(error);
+        }
+        Ok(IndexedDocumentRef::new(resource).encode(env))
+    })
+}
+
+fn decode_merge_position(position: rustler::Atom) -> NifResult<MergePosition> {
+    if position == atoms::before() {
+        Ok(MergePosition::Before)
+    } else if position == atoms::after() {
+        Ok(MergePosition::After)
+    } else if position == atoms::first_child() {
+        Ok(MergePosition::FirstChild)
+    } else if position == atoms::last_child() {
+        Ok(MergePosition::LastChild)
+    } else if position == atoms::replace() {
+        Ok(MergePosition::Replace)
+    } else {
+        Err(rustler::Error::BadArg)
+    }
+}
+
+/// Build a new document by splicing a node of `source_doc` into
+/// `target_doc` relative to `target_node_id`
+///
+/// Both documents are left as they are. Returns nil for unknown nodes, a
+/// document node, or a child position on a non-element target.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn doc_merge<'a>(
+    env: Env<'a>,
+    target_doc: IndexedDocumentRef,
+    target_node_id: u32,
+    source_doc: IndexedDocumentRef,
+    source_node_id: u32,
+    position: rustler::Atom,
+) -> NifResult<Term<'a>> {
+    let position = decode_merge_position(position)?;
+    catch_panic(env, || {
+        let Some(range) = source_doc.as_view().outer_range(source_node_id) else {
+            return Ok(atoms::nil().encode(env));
+        };
+        if let Err(error) = check_memory(env, target_doc.input().len() + range.len()) {
+            return Ok(error);
+        }
+        let Some(resource) =
+            target_doc.merge(target_node_id, &source_doc, source_node_id, position)
+        else {
+            return Ok(atoms::nil().encode(env));
+        };
+        if let Err(error) = check_memory(env, 0) {
+            return Ok(error);
+        }
+        Ok(IndexedDocumentRef::new(resource).encode(env))
+    })
+}
+
+/// The document as well-formed XML of at most `max_bytes` bytes
+///
+/// Trailing nodes that don't fit are dropped, leaving a `<!-- truncated -->`
+/// comment where content was cut and closing the elements still open. A
+/// DOCTYPE is always kept. Returns nil if the budget can't hold it, the
+/// root element's tags and the marker.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn truncate_xml<'a>(
+    env: Env<'a>,
+    doc_ref: IndexedDocumentRef,
+    max_bytes: usize,
+) -> NifResult<Term<'a>> {
+    catch_panic(env, || {
+        Ok(
+            match index::truncate::truncate(&doc_ref.as_view(), max_bytes) {
+                Some(output) => term::bytes_to_binary(env, &output),
+                None => atoms::nil().encode(env),
+            },
+        )
+    })
+}
+
+/// Split a document into standalone documents of up to `per_shard` of the
+/// records `record_xpath` selects
+///
+/// Each shard repeats the prolog and the records' ancestor start tags, so
+/// envelope attributes and namespace declarations carry over. Returns
+/// `{:ok, [xml]}`, or `{:error, reason}` if the expression fails or doesn't
+/// select nodes.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn split_records<'a>(
+    env: Env<'a>,
+    doc_ref: IndexedDocumentRef,
+    record_xpath: &str,
+    per_shard: usize,
+) -> NifResult<Term<'a>> {
+    if per_shard == 0 {
+        return Err(rustler::Error::BadArg);
+    }
+    catch_panic(env, || {
+        let records = match doc_ref.evaluate(record_xpath) {
+            Ok(xpath::XPathValue::NodeSet(nodes)) => nodes,
+            Ok(_) => {
+                let reason = "expression must select a node-set";
+                return Ok((atoms::error(), reason).encode(env));
+            }
+            Err(e) => return Ok((atoms::error(), e).encode(env)),
+        };
+        let shards: Vec<Term> = index::split::split(&doc_ref.as_view(), &records, per_shard)
+            .iter()
+            .map(|shard| term::bytes_to_binary(env, shard))
+            .collect();
+        Ok((atoms::ok(), shards).encode(env))
+    })
+}
+
+/// Value of an element's attribute by namespace URI and local name
+///
+/// Prefixes are resolved through the `xmlns:*` declarations in scope; an
+/// empty `uri` selects an unprefixed attribute. Returns nil when there is
+/// no such attribute or node.
+#[rustler::nif]
+fn node_attribute_ns<'a>(
+    env: Env<'a>,
+    doc_ref: IndexedDocumentRef,
+    node_id: u32,
+    uri: &str,
+    local_name: &str,
+) -> NifResult<Term<'a>> {
+    catch_panic(env, || {
+        let view = doc_ref.as_view();
+        Ok(match view.get_attribute_ns(node_id, uri, local_name) {
+            Some(value) => value.encode(env),
+            None => atoms::nil().encode(env),
+        })
+    })
+}
+
+// ============================================================================
+// Serialized Node Sets
+// ============================================================================
+
+/// Evaluate an XPath query and pack the selected node ids into a binary
+///
+/// Returns `{:ok, binary}` for `nodeset_materialize`, or `{:error, reason}`
+/// if the query fails or does not select a node-set. See `index::packed`;
+/// hashing the input is why this runs on a dirty scheduler.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn nodeset_serialize<'a>(
+    env: Env<'a>,
+    doc_ref: IndexedDocumentRef,
+    xpath_str: &str,
+) -> NifResult<Term<'a>> {
+    catch_panic(env, || match doc_ref.evaluate(xpath_str) {
+        Ok(xpath::XPathValue::NodeSet(nodes)) => {
+            let packed = index::packed::pack(&doc_ref.index, doc_ref.input(), &nodes);
+            Ok((atoms::ok(), term::bytes_to_binary(env, &packed)).encode(env))
+        }
+        Ok(_) => Ok((atoms::error(), "XPath result is not a node-set").encode(env)),
+        Err(e) => Ok((atoms::error(), e).encode(env)),
+    })
+}
+
+/// Convert a node-set packed by `nodeset_serialize` back into terms
+///
+/// `format` is `:element` for element tuples (as from `xpath_query`),
+/// `:xml` for serialized XML binaries or `:text` for string-values.
+/// Returns `{:error, :invalid_nodeset}` if the binary was not packed from
+/// this document.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn nodeset_materialize<'a>(
+    env: Env<'a>,
+    doc_ref: IndexedDocumentRef,
+    packed: Binary<'a>,
+    format: rustler::Atom,
+) -> NifResult<Term<'a>> {
+    catch_panic(env, || {
+        let Some(nodes) = index::packed::unpack(&doc_ref.index, doc_ref.input(), packed.as_slice())
+        else {
+            return Ok((atoms::error(), atoms::invalid_nodeset()).encode(env));
+        };
+        let view = doc_ref.as_view();
+        if format == atoms::element() {
+            let value = xpath::XPathValue::NodeSet(nodes);
+            Ok(xpath_value_to_term(
+                env,
+                value,
+                &view,
+                shared_input(env, &doc_ref),
+            ))
+        } else if format == atoms::xml() {
+            Ok(term::nodeset_to_xml_binaries(env, &nodes, &view))
+        } else if format == atoms::text() {
+            let texts: Vec<String> = nodes
+                .iter()
+                .map(|&id| dom::node_string_value(&view, id))
+                .collect();
+            Ok(texts.encode(env))
+        } else {
+            Err(rustler::Error::BadArg)
+        }
+    })
+}
+
+// ============================================================================
+// Text Search
+// ============================================================================
+
+/// Find `pattern` in the raw text of a document
+///
+/// Returns `[{element_id, offset}]` for each non-overlapping match inside
+/// text or CDATA content, naming the element containing it and the match's
+/// byte offset in the input. Options: `attributes: true` also searches
+/// attribute values, `limit: n` stops after `n` matches.
+///
+/// Offsets are into `doc_ref.input()`: past a UTF-8 BOM, in the UTF-8
+/// converted from UTF-16 input, and inside the wrapper of a fragment.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn doc_find_text<'a>(
+    env: Env<'a>,
+    doc_ref: IndexedDocumentRef,
+    pattern: Binary,
+    opts: Vec<(rustler::Atom, Term)>,
+) -> NifResult<Term<'a>> {
+    let mut options = index::search::SearchOptions::default();
+    for (key, value) in opts {
+        if key == atoms::attributes() {
+            options.attributes = value.decode()?;
+        } else if key == atoms::limit() {
+            options.limit = Some(value.decode()?);
+        } else {
+            return Err(rustler::Error::BadArg);
+        }
+    }
+    catch_panic(env, || {
+        let matches =
+            index::search::find_text(&doc_ref.index, doc_ref.input(), pattern.as_slice(), options);
+        let matches: Vec<(u32, usize)> = matches.iter().map(|m| (m.element, m.offset)).collect();
+        Ok(matches.encode(env))
+    })
+}
+
+/// Search the string-values of the nodes `scope_xpath` selects with a regex
+///
+/// Returns `[{node_id, [{start, length}]}]` for each node with at least one
+/// match, where ranges are byte offsets into the node's string-value.
+/// Compiled regexes are cached across calls.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn doc_regex_search<'a>(
+    env: Env<'a>,
+    doc_ref: IndexedDocumentRef,
+    pattern: &str,
+    scope_xpath: &str,
+) -> NifResult<Term<'a>> {
+    catch_panic(env, || {
+        let regex = match index::search::compile_regex(pattern) {
+            Ok(regex) => regex,
+            Err(e) => return Ok((atoms::error(), e).encode(env)),
+        };
+        let nodes = match doc_ref.evaluate(scope_xpath) {
+            Ok(xpath::XPathValue::NodeSet(nodes)) => nodes,
+            Ok(_) => {
+                let reason = "scope expression must select a node-set";
+                return Ok((atoms::error(), reason).encode(env));
+            }
+            Err(e) => return Ok((atoms::error(), e).encode(env)),
+        };
+
+        let found = index::search::regex_search(&doc_ref.as_view(), &nodes, &regex);
+        let found: Vec<(u32, Vec<(usize, usize)>)> = found
+            .into_iter()
+            .map(|(node, ranges)| {
+                let ranges = ranges.into_iter().map(|r| (r.start, r.len())).collect();
+                (node, ranges)
+            })
+            .collect();
+        Ok(found.encode(env))
+    })
+}
+
+// ============================================================================
+// Malformed Input Repair
+// ============================================================================
+
+/// Rewrite malformed input into well-formed XML
+///
+/// Returns `{fixed, repairs}` where each repair is `{kind, offset}` with a
+/// byte offset into the original input. `root` names an element to wrap the
+/// content in when there is no single top-level element.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn repair<'a>(env: Env<'a>, input: Binary<'a>, root: Option<&str>) -> NifResult<Term<'a>> {
+    catch_panic(env, || {
+        use core::repair::RepairKind;
+
+        if root.is_some_and(|name| core::tokenizer::validate_name(name.as_bytes()).is_err()) {
+            return Err(rustler::Error::BadArg);
+        }
+        let (fixed, repairs) = core::repair::repair(input.as_slice(), root);
+        let repairs: Vec<(rustler::Atom, usize)> = repairs
+            .iter()
+            .map(|r| {
+                let kind = match r.kind {
+                    RepairKind::EscapedAmpersand => atoms::escaped_ampersand(),
+                    RepairKind::EscapedLessThan => atoms::escaped_less_than(),
+                    RepairKind::EscapedGreaterThan => atoms::escaped_greater_than(),
+                    RepairKind::DroppedControlCharacter => atoms::dropped_control_character(),
+                    RepairKind::ReplacedInvalidUtf8 => atoms::replaced_invalid_utf8(),
+                    RepairKind::QuotedAttributeValue => atoms::quoted_attribute_value(),
+                    RepairKind::AddedAttributeValue => atoms::added_attribute_value(),
+                    RepairKind::DroppedDuplicateAttribute => atoms::dropped_duplicate_attribute(),
+                    RepairKind::DroppedInvalidAttribute => atoms::dropped_invalid_attribute(),
+                    RepairKind::DroppedEndTag => atoms::dropped_end_tag(),
+                    RepairKind::ClosedElement => atoms::closed_element(),
+                    RepairKind::ClosedTag => atoms::closed_tag(),
+                    RepairKind::ClosedComment => atoms::closed_comment(),
+                    RepairKind::ClosedCdata => atoms::closed_cdata(),
+                    RepairKind::ClosedProcessingInstruction => {
+                        atoms::closed_processing_instruction()
+                    }
+                    RepairKind::SeparatedHyphens => atoms::separated_hyphens(),
+                    RepairKind::DroppedXmlDeclaration => atoms::dropped_xml_declaration(),
+                    RepairKind::WrappedRoot => atoms::wrapped_root(),
+                };
+                (kind, r.offset)
+            })
+            .collect();
+        Ok((term::bytes_to_binary(env, &fixed), repairs).encode(env))
+    })
+}
+
+// ============================================================================
+// Template Rendering
+// ============================================================================
+
+/// Render a parsed template, replacing `open name close` placeholders in
+/// text and attribute values with escaped `values`
+///
+/// Returns the rendered document as a binary. Placeholders without a value
+/// are left in place.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn render_template<'a>(
+    env: Env<'a>,
+    doc_ref: IndexedDocumentRef,
+    values: std::collections::HashMap<String, String>,
+    open: &str,
+    close: &str,
+) -> NifResult<Term<'a>> {
+    catch_panic(env, || {
+        if open.is_empty() || close.is_empty() {
+            return Err(rustler::Error::BadArg);
+        }
+        let view = doc_ref.as_view();
+        let range = view
+            .outer_range(view.document_node_id())
+            .ok_or(rustler::Error::BadArg)?;
+        let rendered =
+            template::render(&doc_ref.index, doc_ref.input(), range, &values, open, close);
+        Ok(term::bytes_to_binary(env, &rendered))
+    })
+}
+
+// ============================================================================
+// Redaction
+// ============================================================================
+
+/// Replace what each XPath selects with `replacement`
+///
+/// Elements lose their content, text nodes their text, and attribute steps
+/// redact attribute values. Returns `{:ok, xml}`, or
+/// `{:error, {xpath, reason}}` for the first expression that fails, doesn't
+/// select nodes or selects something that can't be redacted.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn redact<'a>(
+    env: Env<'a>,
+    doc_ref: IndexedDocumentRef,
+    xpaths: Vec<String>,
+    replacement: &str,
+) -> NifResult<Term<'a>> {
+    catch_panic(env, || {
+        Ok(
+            match redact::redact(&doc_ref.as_view(), &xpaths, replacement) {
+                Ok(output) => (atoms::ok(), term::bytes_to_binary(env, &output)).encode(env),
+                Err(failure) => (atoms::error(), failure).encode(env),
+            },
+        )
+    })
+}
+
+// ============================================================================
+// Schema Inference
+// ============================================================================
+
+/// XSD skeleton inferred from the structure and values of sample documents
+#[rustler::nif(schedule = "DirtyCpu")]
+fn infer_schema<'a>(env: Env<'a>, docs: Vec<IndexedDocumentRef>) -> NifResult<Term<'a>> {
+    catch_panic(env, || {
+        let views: Vec<_> = docs.iter().map(|doc| doc.as_view()).collect();
+        Ok(schema::infer_schema(&views).encode(env))
+    })
+}
+
+// ============================================================================
+// Markup Normalization
+// ============================================================================
+
+/// Rewrite well-formed XML with sorted attributes, double quotes, no stray
+/// whitespace in tags, and one form for empty elements
+///
+/// `empty_elements` is `:self_closing` (`<a/>`) or `:expanded`
+/// (`<a></a>`). Returns `{:ok, xml}` or `{:error, reason}` for malformed
+/// input.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn normalize_markup<'a>(
+    env: Env<'a>,
+    input: Binary<'a>,
+    empty_elements: rustler::Atom,
+) -> NifResult<Term<'a>> {
+    use core::markup::EmptyElements;
+
+    let empty = if empty_elements == atoms::self_closing() {
+        EmptyElements::SelfClosing
+    } else if empty_elements == atoms::expanded() {
+        EmptyElements::Expanded
+    } else {
+        return Err(rustler::Error::BadArg);
+    };
+    catch_panic(env, || {
+        Ok(
+            match core::markup::normalize_markup(input.as_slice(), empty) {
+                Ok(output) => (atoms::ok(), term::bytes_to_binary(env, &output)).encode(env),
+                Err(reason) => (atoms::error(), reason).encode(env),
+            },
+        )
+    })
+}
+
+// ============================================================================
+// SVG Attributes
+// ============================================================================
+
+/// `%{width, height, view_box}` of an SVG document's root, or nil if the
+/// root isn't `svg`
+///
+/// `width` and `height` are binaries as written, `view_box` a
+/// `{min_x, min_y, width, height}` tuple of floats; each is nil when
+/// missing (or, for `view_box`, not four numbers). Scanning stops at the
+/// root start tag.
+#[rustler::nif]
+fn svg_dimensions<'a>(env: Env<'a>, input: Binary<'a>) -> NifResult<Term<'a>> {
+    catch_panic(env, || {
+        let Some(dims) = svg::dimensions(input.as_slice()) else {
+            return Ok(atoms::nil().encode(env));
+        };
+        let binary = |value: Option<std::borrow::Cow<[u8]>>| match value {
+            Some(bytes) => term::bytes_to_binary(env, &bytes),
+            None => atoms::nil().encode(env),
+        };
+        let view_box = match dims.view_box {
+            Some([x, y, w, h]) => (x, y, w, h).encode(env),
+            None => atoms::nil().encode(env),
+        };
+        Term::map_from_pairs(
+            env,
+            &[
+                (atoms::width().encode(env), binary(dims.width)),
+                (atoms::height().encode(env), binary(dims.height)),
+                (atoms::view_box().encode(env), view_box),
+            ],
+        )
+    })
+}
+
+/// The `d` attribute of every `path` element, in document order, found by
+/// scanning without building an index
+#[rustler::nif(schedule = "DirtyCpu")]
+fn svg_extract_paths<'a>(env: Env<'a>, input: Binary<'a>) -> NifResult<Term<'a>> {
+    catch_panic(env, || {
+        let paths: Vec<Term> = svg::path_data(input.as_slice())
+            .iter()
+            .map(|d| term::bytes_to_binary(env, d))
+            .collect();
+        Ok(paths.encode(env))
+    })
+}
+
+// ============================================================================
+// XPath Helpers
+// ============================================================================
+
+/// Decode a subspec: `{key, xpath}` or `{key, xpath, type}`
+fn decode_subspec<'a>(term: Term<'a>) -> NifResult<(&'a str, &'a str, Option<SubspecType>)> {
+    if let Ok((key, path)) = term.decode::<(&str, &str)>() {
+        return Ok((key, path, None));
+    }
+    let (key, path, ty): (&str, &str, rustler::Atom) = term.decode()?;
+    Ok((key, path, Some(decode_subspec_type(ty)?)))
+}
+
+/// Decode a subspec type atom (`:string`, `:integer`, ...)
+fn decode_subspec_type(ty: rustler::Atom) -> NifResult<SubspecType> {
+    if ty == atoms::string() {
+        Ok(SubspecType::String)
+    } else if ty == atoms::integer() {
+        Ok(SubspecType::Integer)
+    } else if ty == atoms::float() {
+        Ok(SubspecType::Float)
+    } else if ty == atoms::boolean() {
+        Ok(SubspecType::Boolean)
+    } else if ty == atoms::list() {
+        Ok(SubspecType::List)
+    } else if ty == atoms::node() {
+        Ok(SubspecType::Node)
+    } else {
+        Err(rustler::Error::BadArg)
+    }
+}
+
+/// Execute parent XPath and evaluate subspecs for each result node
+///
+/// Subspecs are `{key, xpath}` (raw XPath result) or `{key, xpath, type}`,
+/// where type is one of :string, :integer, :float, :boolean, :list, :node
+/// and the conversion is done here rather than in Elixir. Two untyped
+/// subspecs are shaped as maps: `"@*"` gives the node's attributes and
+/// `"./*"` its child element names mapped to their string-values.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn xpath_with_subspecs<'a>(
+    env: Env<'a>,
+    input: Binary<'a>,
+    parent_xpath: &str,
+    subspecs: Vec<Term<'a>>,
+) -> NifResult<Term<'a>> {
+    catch_panic(env, || {
+        let subspecs = subspecs
+            .into_iter()
+            .map(decode_subspec)
+            .collect::<NifResult<Vec<_>>>()?;
+
+        let bytes = input.as_slice();
+        let idx = index::builder::build_index(bytes);
+        let decoded = index::TextDecodeCache::default();
+        let view = index::IndexedDocumentView::new(&idx, bytes).with_decode_cache(&decoded);
+        let source = BinarySource::shared(input);
+
+        let parent_result = match evaluate(&view, parent_xpath) {
+            Ok(v) => v,
+            Err(e) => {
+                return Ok((atoms::error(), e).encode(env));
+            }
+        };
+
+        let nodes = match parent_result {
+            xpath::XPathValue::NodeSet(nodes) => nodes,
+            _ => return Ok(Term::list_new_empty(env)),
+        };
+
+        let mut result_list = Term::list_new_empty(env);
+
+        for &node_id in nodes.iter().rev() {
+            let mut map_pairs: Vec<(Term, Term)> = Vec::new();
+
+            for &(key, subxpath, ty) in &subspecs {
+                let key_term = key.encode(env);
+                let sub_result = match (subxpath, ty) {
+                    ("@*", None) => term::attributes_map_term(env, &view, node_id, source)?,
+                    ("./*", None) => term::child_values_map_term(env, &view, node_id)?,
+                    _ => subspec_value(env, &view, node_id, subxpath, ty, source),
+                };
+                map_pairs.push((key_term, sub_result));
+            }
+
+            if let Ok(map) = Term::map_from_pairs(env, &map_pairs) {
+                result_list = result_list.list_prepend(map);
+            }
+        }
+
+        Ok(result_list)
+    })
+}
+
+/// Result of one subspec expression evaluated from `node_id`
+fn subspec_value<'a>(
+    env: Env<'a>,
+    view: &index::IndexedDocumentView,
+    node_id: dom::NodeId,
+    subxpath: &str,
+    ty: Option<SubspecType>,
+    source: BinarySource<'a>,
+) -> Term<'a> {
+    match (xpath::evaluate_from_node(view, node_id, subxpath), ty) {
+        (Ok(v), None) => xpath_value_to_term(env, v, view, source),
+        (Ok(v), Some(ty)) => term::typed_value_to_term(env, v, ty, view, source),
+        (Err(_), _) => atoms::nil().encode(env),
+    }
+}
+
+// ============================================================================
+// Spec Decoding
+// ============================================================================
+
+/// One field of a `decode/2` spec, with its XPath compiled
+enum FieldSpec<'a> {
+    /// `{xpath, type}`, or a bare xpath converted as a string
+    Value(Arc<CompiledExpr>, SubspecType),
+    /// `{:list, xpath, spec}`: a nested map for every selected node
+    List(Arc<CompiledExpr>, Vec<(Term<'a>, FieldSpec<'a>)>),
+    /// `{:map, xpath, spec}`: a nested map for the first selected node
+    Map(Arc<CompiledExpr>, Vec<(Term<'a>, FieldSpec<'a>)>),
+}
 
-    let bytes = input.as_slice();
-    let idx = index::builder::build_index(bytes);
-    let view = index::IndexedDocumentView::new(&idx, bytes);
+/// Why a spec could not be decoded
+enum SpecError<'a> {
+    /// Not a spec map, or a field of an unknown shape or type
+    Shape,
+    /// An expression that does not compile, with the compiler's message
+    XPath(&'a str, String),
+}
 
-    let parent_result = match evaluate(&view, parent_xpath) {
-        Ok(v) => v,
-        Err(e) => {
-            return Ok((atoms::error(), e).encode(env));
-        }
-    };
+impl From<rustler::Error> for SpecError<'_> {
+    fn from(_: rustler::Error) -> Self {
+        SpecError::Shape
+    }
+}
 
-    let nodes = match parent_result {
-        xpath::XPathValue::NodeSet(nodes) => nodes,
-        _ => return Ok(Term::list_new_empty(env)),
-    };
+/// Decode a spec map into its fields, compiling every XPath up front
+fn decode_spec(spec: Term<'_>) -> Result<Vec<(Term<'_>, FieldSpec<'_>)>, SpecError<'_>> {
+    let fields = rustler::types::map::MapIterator::new(spec).ok_or(SpecError::Shape)?;
+    fields
+        .map(|(key, field)| Ok((key, decode_field(field)?)))
+        .collect()
+}
 
-    let mut result_list = Term::list_new_empty(env);
+fn decode_field(term: Term<'_>) -> Result<FieldSpec<'_>, SpecError<'_>> {
+    let compile = |path| xpath::compiler::compile(path).map_err(|e| SpecError::XPath(path, e));
 
-    for &node_id in nodes.iter().rev() {
-        let mut map_pairs: Vec<(Term, Term)> = Vec::new();
+    if let Ok(path) = term.decode::<&str>() {
+        return Ok(FieldSpec::Value(compile(path)?, SubspecType::String));
+    }
+    if let Ok((path, ty)) = term.decode::<(&str, rustler::Atom)>() {
+        return Ok(FieldSpec::Value(compile(path)?, decode_subspec_type(ty)?));
+    }
+    let (kind, path, nested): (rustler::Atom, &str, Term) = term.decode()?;
+    let (expr, nested) = (compile(path)?, decode_spec(nested)?);
+    if kind == atoms::list() {
+        Ok(FieldSpec::List(expr, nested))
+    } else if kind == atoms::map() {
+        Ok(FieldSpec::Map(expr, nested))
+    } else {
+        Err(SpecError::Shape)
+    }
+}
 
-        for (key, subxpath) in &subspecs {
-            let key_term = (*key).encode(env);
-            let sub_result = match evaluate_from_node(&view, node_id, subxpath) {
-                Ok(v) => xpath_value_to_term(env, v, &view),
-                Err(_) => atoms::nil().encode(env),
-            };
-            map_pairs.push((key_term, sub_result));
-        }
+/// Evaluate spec fields from `node`, building a map keyed like the spec
+///
+/// As with subspecs, a field whose expression fails at run time is nil.
+fn spec_to_term<'a>(
+    env: Env<'a>,
+    view: &index::IndexedDocumentView,
+    node: dom::NodeId,
+    fields: &[(Term<'a>, FieldSpec<'a>)],
+    source: BinarySource<'a>,
+) -> NifResult<Term<'a>> {
+    use xpath::eval::evaluate_precompiled_from_node;
+    use xpath::XPathValue;
 
-        if let Ok(map) = Term::map_from_pairs(env, &map_pairs) {
-            result_list = result_list.list_prepend(map);
-        }
+    let nil = atoms::nil().encode(env);
+    let mut pairs = Vec::with_capacity(fields.len());
+    for (key, field) in fields {
+        let value = match field {
+            FieldSpec::Value(expr, ty) => match evaluate_precompiled_from_node(view, node, expr) {
+                Ok(v) => term::typed_value_to_term(env, v, *ty, view, source),
+                Err(_) => nil,
+            },
+            FieldSpec::List(expr, nested) => match evaluate_precompiled_from_node(view, node, expr)
+            {
+                Ok(XPathValue::NodeSet(nodes)) => nodes
+                    .iter()
+                    .map(|&id| spec_to_term(env, view, id, nested, source))
+                    .collect::<NifResult<Vec<_>>>()?
+                    .encode(env),
+                _ => Term::list_new_empty(env),
+            },
+            FieldSpec::Map(expr, nested) => {
+                match evaluate_precompiled_from_node(view, node, expr) {
+                    Ok(XPathValue::NodeSet(nodes)) if !nodes.is_empty() => {
+                        spec_to_term(env, view, nodes[0], nested, source)?
+                    }
+                    _ => nil,
+                }
+            }
+        };
+        pairs.push((*key, value));
     }
+    Term::map_from_pairs(env, &pairs)
+}
 
-    Ok(result_list)
+/// Extract a nested, typed map from a document according to `spec`
+///
+/// `spec` maps keys to fields: an xpath (string-value), `{xpath, type}`
+/// with a subspec type, or `{:list, xpath, spec}` / `{:map, xpath, spec}`
+/// for nested maps built from the selected nodes. Top-level expressions
+/// run from the root element, nested ones from their node. Returns the map,
+/// or `{:error, {xpath, reason}}` if an expression does not compile.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn decode<'a>(env: Env<'a>, doc_ref: IndexedDocumentRef, spec: Term<'a>) -> NifResult<Term<'a>> {
+    catch_panic(env, || {
+        let fields = match decode_spec(spec) {
+            Ok(fields) => fields,
+            Err(SpecError::Shape) => return Err(rustler::Error::BadArg),
+            Err(SpecError::XPath(path, reason)) => {
+                return Ok((atoms::error(), (path, reason)).encode(env));
+            }
+        };
+        let view = doc_ref.as_view();
+        let root = view.root_element_id().unwrap_or(0);
+        spec_to_term(env, &view, root, &fields, shared_input(env, &doc_ref))
+    })
 }
 
 /// Get string value of an XPath result
 #[rustler::nif(schedule = "DirtyCpu")]
 fn xpath_string_value<'a>(env: Env<'a>, input: Binary<'a>, xpath_str: &str) -> NifResult<Term<'a>> {
-    let bytes = input.as_slice();
-    let idx = index::builder::build_index(bytes);
-    let view = index::IndexedDocumentView::new(&idx, bytes);
-
-    match evaluate(&view, xpath_str) {
-        Ok(value) => {
-            let string_val = match value {
-                xpath::XPathValue::String(s) => s,
-                xpath::XPathValue::Number(n) => n.to_string(),
-                xpath::XPathValue::Boolean(b) => b.to_string(),
-                xpath::XPathValue::NodeSet(nodes) => {
-                    if let Some(&node_id) = nodes.first() {
-                        dom::node_string_value(&view, node_id)
-                    } else {
-                        String::new()
+    catch_panic(env, || {
+        let bytes = input.as_slice();
+        let idx = index::builder::build_index(bytes);
+        let decoded = index::TextDecodeCache::default();
+        let view = index::IndexedDocumentView::new(&idx, bytes).with_decode_cache(&decoded);
+
+        match evaluate(&view, xpath_str) {
+            Ok(value) => {
+                let string_val = match value {
+                    xpath::XPathValue::String(s) => s,
+                    xpath::XPathValue::Number(n) => xpath::value::format_number(n),
+                    xpath::XPathValue::Boolean(b) => b.to_string(),
+                    xpath::XPathValue::NodeSet(nodes) => {
+                        if let Some(&node_id) = nodes.first() {
+                            dom::node_string_value(&view, node_id)
+                        } else {
+                            String::new()
+                        }
                     }
-                }
-                xpath::XPathValue::StringList(list) => list.into_iter().next().unwrap_or_default(),
-            };
-            Ok(string_val.encode(env))
+                    xpath::XPathValue::StringList(list) => {
+                        list.into_iter().next().unwrap_or_default()
+                    }
+                    value @ xpath::XPathValue::NamespaceNodes(_) => value.to_string_value(),
+                };
+                Ok(string_val.encode(env))
+            }
+            Err(e) => Ok((atoms::error(), e).encode(env)),
         }
-        Err(e) => Ok((atoms::error(), e).encode(env)),
-    }
+    })
 }
 
 /// Get string value from document reference
@@ -385,26 +3350,102 @@ fn xpath_string_value_doc<'a>(
     doc_ref: IndexedDocumentRef,
     xpath_str: &str,
 ) -> NifResult<Term<'a>> {
-    let view = doc_ref.as_view();
-    match evaluate(&view, xpath_str) {
-        Ok(value) => {
-            let string_val = match value {
-                xpath::XPathValue::String(s) => s,
-                xpath::XPathValue::Number(n) => n.to_string(),
-                xpath::XPathValue::Boolean(b) => b.to_string(),
-                xpath::XPathValue::NodeSet(nodes) => {
-                    if let Some(&node_id) = nodes.first() {
-                        dom::node_string_value(&view, node_id)
-                    } else {
-                        String::new()
+    catch_panic(env, || {
+        let view = doc_ref.as_view();
+        match doc_ref.evaluate(xpath_str) {
+            Ok(value) => {
+                let string_val = match value {
+                    xpath::XPathValue::String(s) => s,
+                    xpath::XPathValue::Number(n) => xpath::value::format_number(n),
+                    xpath::XPathValue::Boolean(b) => b.to_string(),
+                    xpath::XPathValue::NodeSet(nodes) => {
+                        if let Some(&node_id) = nodes.first() {
+                            dom::node_string_value(&view, node_id)
+                        } else {
+                            String::new()
+                        }
                     }
-                }
-                xpath::XPathValue::StringList(list) => list.into_iter().next().unwrap_or_default(),
-            };
-            Ok(string_val.encode(env))
+                    xpath::XPathValue::StringList(list) => {
+                        list.into_iter().next().unwrap_or_default()
+                    }
+                    value @ xpath::XPathValue::NamespaceNodes(_) => value.to_string_value(),
+                };
+                Ok(string_val.encode(env))
+            }
+            Err(e) => Ok((atoms::error(), e).encode(env)),
         }
+    })
+}
+
+/// Describe how an XPath expression will be evaluated
+///
+/// Returns `{:ok, lines}` with one line per compiled operation and the
+/// strategy used for it, or `{:error, reason}` if the expression is invalid.
+#[rustler::nif]
+fn xpath_explain<'a>(env: Env<'a>, xpath_str: &str) -> NifResult<Term<'a>> {
+    catch_panic(env, || match xpath::compiler::compile(xpath_str) {
+        Ok(compiled) => Ok((atoms::ok(), compiled.explain()).encode(env)),
         Err(e) => Ok((atoms::error(), e).encode(env)),
-    }
+    })
+}
+
+// ============================================================================
+// Schematron
+// ============================================================================
+
+/// Compile a Schematron schema
+///
+/// Returns `{:ok, rules}` or `{:error, reason}` if the schema is malformed,
+/// uses unsupported features or has an invalid XPath expression.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn schematron_compile<'a>(env: Env<'a>, rules_xml: Binary<'a>) -> NifResult<Term<'a>> {
+    catch_panic(env, || {
+        match schematron::compile_schema(rules_xml.as_slice()) {
+            Ok(schema) => Ok((atoms::ok(), ResourceArc::new(schema)).encode(env)),
+            Err(e) => Ok((atoms::error(), e).encode(env)),
+        }
+    })
+}
+
+/// Run a compiled Schematron schema over a document
+///
+/// Returns `{:ok, failures}` with a map per failed assert or fired report,
+/// holding its `kind` (`:assert` or `:report`), `id`, `role`, `test`, the
+/// context node's `path` and the filled-in `message`.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn schematron_validate<'a>(
+    env: Env<'a>,
+    doc_ref: IndexedDocumentRef,
+    rules: resource::SchematronRef,
+) -> NifResult<Term<'a>> {
+    catch_panic(env, || {
+        let view = doc_ref.as_view();
+        let failures = match schematron::validate(&rules, &view) {
+            Ok(failures) => failures,
+            Err(e) => return Ok((atoms::error(), e).encode(env)),
+        };
+        let terms = failures
+            .iter()
+            .map(|failure| {
+                let kind = match failure.kind {
+                    schematron::CheckKind::Assert => atoms::assert(),
+                    schematron::CheckKind::Report => atoms::report(),
+                };
+                Term::map_from_pairs(
+                    env,
+                    &[
+                        (atoms::kind().encode(env), kind.encode(env)),
+                        (atoms::id().encode(env), failure.id.encode(env)),
+                        (atoms::role().encode(env), failure.role.encode(env)),
+                        (atoms::test().encode(env), failure.test.encode(env)),
+                        (atoms::path().encode(env), failure.path.encode(env)),
+                        (atoms::message().encode(env), failure.message.encode(env)),
+                    ],
+                )
+            })
+            .collect::<NifResult<Vec<Term<'a>>>>()?;
+        Ok((atoms::ok(), terms).encode(env))
+    })
 }
 
 // ============================================================================
@@ -423,6 +3464,36 @@ fn streaming_new_with_filter(tag: Binary) -> StreamingParserRef {
     ResourceArc::new(StreamingParserResource::with_filter(tag.as_slice()))
 }
 
+/// Create a streaming parser that only counts elements named `tag`
+#[rustler::nif]
+fn streaming_new_counter(tag: Binary) -> StreamingParserRef {
+    ResourceArc::new(StreamingParserResource::counting(tag.as_slice()))
+}
+
+/// Create a streaming parser with tag filter and a per-element size limit
+///
+/// `mode` is `:skip` (drop oversized elements, report `{:oversized, name, size}`)
+/// or `:chunk` (emit them as `{:chunk, name, data}` pieces and `{:chunk_end, name, size}`).
+#[rustler::nif]
+fn streaming_new_with_limit(
+    tag: Binary,
+    max_element_size: usize,
+    mode: rustler::Atom,
+) -> NifResult<StreamingParserRef> {
+    let mode = if mode == atoms::skip() {
+        OversizeMode::Skip
+    } else if mode == atoms::chunk() {
+        OversizeMode::Chunk
+    } else {
+        return Err(rustler::Error::BadArg);
+    };
+    Ok(ResourceArc::new(StreamingParserResource::with_limit(
+        tag.as_slice(),
+        max_element_size,
+        mode,
+    )))
+}
+
 /// Feed a chunk of data to the streaming parser
 #[rustler::nif]
 fn streaming_feed<'a>(
@@ -430,13 +3501,18 @@ fn streaming_feed<'a>(
     parser: StreamingParserRef,
     chunk: Binary,
 ) -> NifResult<Term<'a>> {
-    match parser.inner.lock() {
-        Ok(mut inner) => {
-            inner.feed(chunk.as_slice());
-            Ok((inner.available_events(), inner.buffer_size()).encode(env))
+    catch_panic(env, || {
+        if let Err(error) = check_memory(env, chunk.len()) {
+            return Ok(error);
         }
-        Err(_) => Ok((atoms::error(), atoms::mutex_poisoned()).encode(env)),
-    }
+        match parser.lock() {
+            Ok(mut inner) => {
+                inner.feed(chunk.as_slice());
+                Ok((inner.available_events(), inner.buffer_size()).encode(env))
+            }
+            Err(unavailable) => Ok((atoms::error(), unavailable.reason()).encode(env)),
+        }
+    })
 }
 
 /// Take up to `max` events from the streaming parser
@@ -446,13 +3522,13 @@ fn streaming_take_events<'a>(
     parser: StreamingParserRef,
     max: usize,
 ) -> NifResult<Term<'a>> {
-    match parser.inner.lock() {
+    catch_panic(env, || match parser.lock() {
         Ok(mut inner) => {
             let events = inner.take_events(max);
             Ok(events_to_term(env, events))
         }
-        Err(_) => Ok((atoms::error(), atoms::mutex_poisoned()).encode(env)),
-    }
+        Err(unavailable) => Ok((atoms::error(), unavailable.reason()).encode(env)),
+    })
 }
 
 /// Take up to `max` complete elements from the streaming parser
@@ -462,19 +3538,18 @@ fn streaming_take_elements<'a>(
     parser: StreamingParserRef,
     max: usize,
 ) -> NifResult<Term<'a>> {
-    match parser.inner.lock() {
+    catch_panic(env, || match parser.lock() {
         Ok(mut inner) => {
             let elements = inner.take_elements(max);
 
             let mut list = Term::list_new_empty(env);
             for element in elements.into_iter().rev() {
-                let binary = term::bytes_to_binary(env, &element);
-                list = list.list_prepend(binary);
+                list = list.list_prepend(term::streamed_element_to_term(env, element));
             }
             Ok(list)
         }
-        Err(_) => Ok((atoms::error(), atoms::mutex_poisoned()).encode(env)),
-    }
+        Err(unavailable) => Ok((atoms::error(), unavailable.reason()).encode(env)),
+    })
 }
 
 /// Get number of available complete elements
@@ -483,36 +3558,96 @@ fn streaming_available_elements<'a>(
     env: Env<'a>,
     parser: StreamingParserRef,
 ) -> NifResult<Term<'a>> {
-    match parser.inner.lock() {
+    catch_panic(env, || match parser.lock() {
         Ok(inner) => Ok(inner.available_elements().encode(env)),
-        Err(_) => Ok((atoms::error(), atoms::mutex_poisoned()).encode(env)),
-    }
+        Err(unavailable) => Ok((atoms::error(), unavailable.reason()).encode(env)),
+    })
+}
+
+/// Number of target elements the streaming parser has completed
+#[rustler::nif]
+fn streaming_count_elements<'a>(env: Env<'a>, parser: StreamingParserRef) -> NifResult<Term<'a>> {
+    catch_panic(env, || match parser.lock() {
+        Ok(inner) => Ok(inner.matched().encode(env)),
+        Err(unavailable) => Ok((atoms::error(), unavailable.reason()).encode(env)),
+    })
+}
+
+/// Concatenate streamed elements into one document under a new root
+///
+/// The elements are copied as they are, not re-parsed; only the root's
+/// name and attributes are checked and escaped. Raises `ArgumentError` for
+/// an invalid root or attribute name, or an attribute given twice.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn wrap_elements<'a>(
+    env: Env<'a>,
+    elements: Vec<Binary<'a>>,
+    root_name: &str,
+    root_attrs: Vec<(String, String)>,
+) -> NifResult<Term<'a>> {
+    catch_panic(env, || {
+        let valid = |name: &str| core::tokenizer::validate_name(name.as_bytes()).is_ok();
+        if !valid(root_name) || !root_attrs.iter().all(|(name, _)| valid(name)) {
+            return Err(rustler::Error::BadArg);
+        }
+        let mut names = HashSet::with_capacity(root_attrs.len());
+        if !root_attrs.iter().all(|(name, _)| names.insert(name)) {
+            return Err(rustler::Error::BadArg);
+        }
+        let mut start_tag = Vec::with_capacity(root_name.len() + 2);
+        start_tag.push(b'<');
+        start_tag.extend_from_slice(root_name.as_bytes());
+        for (name, value) in &root_attrs {
+            start_tag.push(b' ');
+            start_tag.extend_from_slice(name.as_bytes());
+            start_tag.extend_from_slice(b"=\"");
+            template::escape_into(&mut start_tag, value, template::Context::Attribute);
+            start_tag.push(b'"');
+        }
+        start_tag.push(b'>');
+
+        let size = start_tag.len()
+            + elements.iter().map(|element| element.len()).sum::<usize>()
+            + root_name.len()
+            + 3;
+        let mut binary = rustler::NewBinary::new(env, size);
+        let mut pos = 0;
+        let slice = binary.as_mut_slice();
+        for part in std::iter::once(&start_tag[..])
+            .chain(elements.iter().map(|element| element.as_slice()))
+            .chain([&b"</"[..], root_name.as_bytes(), b">"])
+        {
+            slice[pos..pos + part.len()].copy_from_slice(part);
+            pos += part.len();
+        }
+        Ok(binary.into())
+    })
 }
 
 /// Finalize the streaming parser
 #[rustler::nif]
 fn streaming_finalize<'a>(env: Env<'a>, parser: StreamingParserRef) -> NifResult<Term<'a>> {
-    match parser.inner.lock() {
+    catch_panic(env, || match parser.lock() {
         Ok(mut inner) => {
             let events = inner.finalize();
             Ok(events_to_term(env, events))
         }
-        Err(_) => Ok((atoms::error(), atoms::mutex_poisoned()).encode(env)),
-    }
+        Err(unavailable) => Ok((atoms::error(), unavailable.reason()).encode(env)),
+    })
 }
 
 /// Get streaming parser status
 #[rustler::nif]
 fn streaming_status<'a>(env: Env<'a>, parser: StreamingParserRef) -> NifResult<Term<'a>> {
-    match parser.inner.lock() {
+    catch_panic(env, || match parser.lock() {
         Ok(inner) => Ok((
             inner.available_events(),
             inner.buffer_size(),
             inner.has_pending(),
         )
             .encode(env)),
-        Err(_) => Ok((atoms::error(), atoms::mutex_poisoned()).encode(env)),
-    }
+        Err(unavailable) => Ok((atoms::error(), unavailable.reason()).encode(env)),
+    })
 }
 
 // ============================================================================
@@ -526,33 +3661,153 @@ fn streaming_status<'a>(env: Env<'a>, parser: StreamingParserRef) -> NifResult<T
 /// {:error, reason}.
 #[rustler::nif(schedule = "DirtyCpu")]
 fn parse_to_simple_form<'a>(env: Env<'a>, input: Binary<'a>) -> NifResult<Term<'a>> {
-    let bytes = input.as_slice().to_vec();
+    build_simple_form(
+        env,
+        input.as_slice().to_vec(),
+        false,
+        Normalization::None,
+        None,
+    )
+}
+
+/// Parse XML into SimpleForm with text and attribute values normalized
+///
+/// `form` is `:nfc`, `:nfd` or `:none`.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn parse_to_simple_form_normalized<'a>(
+    env: Env<'a>,
+    input: Binary<'a>,
+    form: rustler::Atom,
+) -> NifResult<Term<'a>> {
+    let normalization = decode_normalization(form)?;
+    build_simple_form(env, input.as_slice().to_vec(), false, normalization, None)
+}
+
+/// Parse XML into SimpleForm keeping text exactly as in the source
+///
+/// Like `parse_to_simple_form`, but without entity decoding: text children
+/// are `{raw_text, {offset, length}}` and attribute values are raw. Offsets
+/// are into the input, or for UTF-16 input into its UTF-8 conversion.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn parse_to_simple_form_raw<'a>(env: Env<'a>, input: Binary<'a>) -> NifResult<Term<'a>> {
+    build_simple_form(
+        env,
+        input.as_slice().to_vec(),
+        true,
+        Normalization::None,
+        None,
+    )
+}
+
+/// Parse XML into SimpleForm converting at most `max_depth` levels below
+/// the root
+///
+/// Elements at the limit get `[{:more, node_id}]` in place of any children,
+/// where `node_id` is the element's id in the same XML passed to `parse`.
+/// `raw_text` and `form` are as in `parse_to_simple_form_raw` and
+/// `parse_to_simple_form_normalized`.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn parse_to_simple_form_shallow<'a>(
+    env: Env<'a>,
+    input: Binary<'a>,
+    max_depth: usize,
+    raw_text: bool,
+    form: rustler::Atom,
+) -> NifResult<Term<'a>> {
+    catch_panic(env, || {
+        let normalization = decode_normalization(form)?;
+        let bytes = input.as_slice().to_vec();
+        build_simple_form(env, bytes, raw_text, normalization, Some(max_depth))
+    })
+}
+
+/// Validate, index, and convert a whole document to a SimpleForm result term
+fn build_simple_form(
+    env: Env<'_>,
+    bytes: Vec<u8>,
+    raw_text: bool,
+    normalization: Normalization,
+    max_depth: Option<usize>,
+) -> NifResult<Term<'_>> {
+    catch_panic(env, || {
+        build_tree(env, bytes, |idx, bytes, root_idx, bom_len| {
+            let raw_spans = raw_text.then_some(bom_len);
+            Ok(term::node_to_simple_form_term(
+                env,
+                idx,
+                bytes,
+                root_idx,
+                raw_spans,
+                normalization,
+                max_depth,
+            ))
+        })
+    })
+}
+
+/// Validate and index a whole document, then convert it from the root
+/// element with `convert`, returning `{:ok, tree}` or `{:error, reason}`
+///
+/// `convert` gets the index, the UTF-8 bytes it refers to, the root element
+/// and the length of a stripped UTF-8 BOM.
+fn build_tree<'a>(
+    env: Env<'a>,
+    bytes: Vec<u8>,
+    convert: impl FnOnce(&index::StructuralIndex, &[u8], u32, usize) -> NifResult<Term<'a>>,
+) -> NifResult<Term<'a>> {
+    // Spans refer to the input as given, so account for a stripped UTF-8 BOM
+    let bom_len = if bytes.starts_with(&[0xEF, 0xBB, 0xBF]) {
+        3
+    } else {
+        0
+    };
 
     // Handle encoding conversion (UTF-16 → UTF-8)
     let bytes = match crate::core::encoding::convert_to_utf8(bytes) {
         Ok(b) => b,
-        Err(msg) => return Ok((atoms::error(), msg).encode(env)),
+        Err(error) => return Ok(encoding_error(env, error)),
     };
 
     // Validate strict
-    match dom::validate_strict(&bytes) {
-        Ok(()) => {}
-        Err(msg) => return Ok((atoms::error(), msg).encode(env)),
+    if let Err(msg) = dom::validate_strict(&bytes) {
+        stats::record_strict_failure();
+        return Ok((atoms::error(), msg).encode(env));
     }
 
     // Build index
     let idx = index::builder::build_index(&bytes);
+    if let Err(error) = check_depth(env, &idx) {
+        return Ok(error);
+    }
 
-    // Build SimpleForm from root element
     match idx.root {
         Some(root_idx) => {
-            let tree = term::node_to_simple_form_term(env, &idx, &bytes, root_idx);
+            let tree = convert(&idx, &bytes, root_idx, bom_len)?;
             Ok((atoms::ok(), tree).encode(env))
         }
         None => Ok((atoms::error(), "empty document").encode(env)),
     }
 }
 
+// ============================================================================
+// Map Parsing
+// ============================================================================
+
+/// Parse XML into nested maps, one per element
+///
+/// Each element becomes `%{name: name, attrs: %{name => value},
+/// children: [map], text: text | nil}`, built from the structural index
+/// like SimpleForm. `form` is `:nfc`, `:nfd` or `:none`.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn parse_to_map<'a>(env: Env<'a>, input: Binary<'a>, form: rustler::Atom) -> NifResult<Term<'a>> {
+    catch_panic(env, || {
+        let normalization = decode_normalization(form)?;
+        build_tree(env, input.as_slice().to_vec(), |idx, bytes, root_idx, _| {
+            term::node_to_map_term(env, idx, bytes, root_idx, normalization)
+        })
+    })
+}
+
 // ============================================================================
 // Document Accumulator (Streaming SimpleForm)
 // ============================================================================
@@ -564,10 +3819,60 @@ fn accumulator_new() -> DocumentAccumulatorRef {
 }
 
 /// Feed a chunk to the accumulator
+///
+/// Returns `:ok`, or `{:error, :memory_limit}` without buffering the chunk.
 #[rustler::nif]
-fn accumulator_feed(acc: DocumentAccumulatorRef, chunk: Binary) -> rustler::Atom {
+fn accumulator_feed<'a>(env: Env<'a>, acc: DocumentAccumulatorRef, chunk: Binary) -> Term<'a> {
+    if let Err(error) = check_memory(env, chunk.len()) {
+        return error;
+    }
     acc.feed(chunk.as_slice());
-    atoms::ok()
+    atoms::ok().encode(env)
+}
+
+/// Feed a chunk to the accumulator, checking well-formedness as data arrives
+///
+/// Returns `:ok`, or `{:error, reason}` as soon as the document so far is
+/// malformed (with the same messages as `parse_strict`). The failing chunk
+/// is not kept; after an error, further chunks are discarded and the error
+/// is returned again. A chunk
+/// over the memory limit is refused like in `accumulator_feed`.
+#[rustler::nif]
+fn accumulator_feed_validated<'a>(
+    env: Env<'a>,
+    acc: DocumentAccumulatorRef,
+    chunk: Binary,
+) -> NifResult<Term<'a>> {
+    catch_panic(env, || {
+        if let Err(error) = check_memory(env, chunk.len()) {
+            return Ok(error);
+        }
+        match acc.feed_validated(chunk.as_slice()) {
+            Ok(()) => Ok(atoms::ok().encode(env)),
+            Err(Some(msg)) => Ok((atoms::error(), msg).encode(env)),
+            Err(None) => Ok((atoms::error(), atoms::mutex_poisoned()).encode(env)),
+        }
+    })
+}
+
+/// Serialize an accumulator's buffer and validation state to a binary
+#[rustler::nif(schedule = "DirtyCpu")]
+fn accumulator_snapshot<'a>(env: Env<'a>, acc: DocumentAccumulatorRef) -> NifResult<Term<'a>> {
+    catch_panic(env, || match acc.snapshot() {
+        Some(bytes) => Ok((atoms::ok(), term::bytes_to_binary(env, &bytes)).encode(env)),
+        None => Ok((atoms::error(), atoms::mutex_poisoned()).encode(env)),
+    })
+}
+
+/// Create an accumulator from `accumulator_snapshot` output
+#[rustler::nif(schedule = "DirtyCpu")]
+fn accumulator_restore<'a>(env: Env<'a>, snapshot: Binary<'a>) -> NifResult<Term<'a>> {
+    catch_panic(env, || {
+        match resource::DocumentAccumulator::restore(snapshot.as_slice()) {
+            Ok(acc) => Ok((atoms::ok(), ResourceArc::new(acc)).encode(env)),
+            Err(msg) => Ok((atoms::error(), msg).encode(env)),
+        }
+    })
 }
 
 /// Validate, index, and convert accumulated data to SimpleForm
@@ -576,31 +3881,84 @@ fn accumulator_to_simple_form<'a>(
     env: Env<'a>,
     acc: DocumentAccumulatorRef,
 ) -> NifResult<Term<'a>> {
-    let bytes = acc.take_buffer();
+    build_simple_form(env, acc.take_buffer(), false, Normalization::None, None)
+}
 
-    // Handle encoding conversion (UTF-16 → UTF-8)
-    let bytes = match crate::core::encoding::convert_to_utf8(bytes) {
-        Ok(b) => b,
-        Err(msg) => return Ok((atoms::error(), msg).encode(env)),
-    };
+/// Convert accumulated data to SimpleForm with text and attribute values
+/// normalized (`form` is `:nfc`, `:nfd` or `:none`)
+#[rustler::nif(schedule = "DirtyCpu")]
+fn accumulator_to_simple_form_normalized<'a>(
+    env: Env<'a>,
+    acc: DocumentAccumulatorRef,
+    form: rustler::Atom,
+) -> NifResult<Term<'a>> {
+    let normalization = decode_normalization(form)?;
+    build_simple_form(env, acc.take_buffer(), false, normalization, None)
+}
 
-    // Validate strict
-    match dom::validate_strict(&bytes) {
-        Ok(()) => {}
-        Err(msg) => return Ok((atoms::error(), msg).encode(env)),
-    }
+/// Convert accumulated data to SimpleForm keeping text exactly as in the source
+#[rustler::nif(schedule = "DirtyCpu")]
+fn accumulator_to_simple_form_raw<'a>(
+    env: Env<'a>,
+    acc: DocumentAccumulatorRef,
+) -> NifResult<Term<'a>> {
+    build_simple_form(env, acc.take_buffer(), true, Normalization::None, None)
+}
 
-    // Build index
-    let idx = index::builder::build_index(&bytes);
+// ============================================================================
+// Streaming Validation
+// ============================================================================
 
-    // Build SimpleForm from root element
-    match idx.root {
-        Some(root_idx) => {
-            let tree = term::node_to_simple_form_term(env, &idx, &bytes, root_idx);
-            Ok((atoms::ok(), tree).encode(env))
-        }
-        None => Ok((atoms::error(), "empty document").encode(env)),
-    }
+/// Create a validator that checks well-formedness chunk by chunk
+#[rustler::nif]
+fn validator_new() -> StreamingValidatorRef {
+    ResourceArc::new(StreamingValidatorResource::default())
+}
+
+/// Check the constructs a chunk completes
+///
+/// Returns `:ok`, or `{:error, %{message, offset, line, column}}` for the
+/// first error; later chunks return the same error. Runs on a dirty
+/// scheduler, as a chunk's cost grows with its size.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn validator_feed<'a>(
+    env: Env<'a>,
+    validator: StreamingValidatorRef,
+    chunk: Binary<'a>,
+) -> NifResult<Term<'a>> {
+    catch_panic(env, || {
+        let result = validator.lock()?.feed(chunk.as_slice());
+        validation_result(env, result)
+    })
+}
+
+/// Apply the end-of-input checks and release the validator
+#[rustler::nif]
+fn validator_finish(env: Env, validator: StreamingValidatorRef) -> NifResult<Term> {
+    catch_panic(env, || {
+        let result = validator.lock()?.finish();
+        validator.close();
+        validation_result(env, result)
+    })
+}
+
+fn validation_result<'a>(
+    env: Env<'a>,
+    result: Result<(), dom::PositionedError>,
+) -> NifResult<Term<'a>> {
+    let Err(error) = result else {
+        return Ok(atoms::ok().encode(env));
+    };
+    let details = Term::map_from_pairs(
+        env,
+        &[
+            (atoms::message().encode(env), error.message.encode(env)),
+            (atoms::offset().encode(env), error.offset.encode(env)),
+            (atoms::line().encode(env), error.line.encode(env)),
+            (atoms::column().encode(env), error.column.encode(env)),
+        ],
+    )?;
+    Ok((atoms::error(), details).encode(env))
 }
 
 // ============================================================================
@@ -610,26 +3968,223 @@ fn accumulator_to_simple_form<'a>(
 /// Parse XML and return SAX events
 #[rustler::nif(schedule = "DirtyCpu")]
 fn sax_parse<'a>(env: Env<'a>, input: Binary<'a>) -> NifResult<Term<'a>> {
-    use core::unified_scanner::UnifiedScanner;
-    use sax::SaxCollector;
+    catch_panic(env, || {
+        use core::unified_scanner::UnifiedScanner;
+        use sax::SaxCollector;
 
-    let bytes = input.as_slice();
+        if let Err(error) = check_memory(env, input.len()) {
+            return Ok(error);
+        }
+        let bytes = input.as_slice();
 
-    let mut collector = SaxCollector::new();
-    let mut scanner = UnifiedScanner::new(bytes);
-    scanner.scan(&mut collector);
+        let mut collector = SaxCollector::new();
+        let mut scanner = UnifiedScanner::new(bytes);
+        scanner.scan(&mut collector);
+        if let Err(error) = check_memory(env, 0) {
+            return Ok(error);
+        }
 
-    let events = collector.events();
-    let attrs = collector.attributes();
+        Ok(sax_event_list(env, &collector, bytes, Some(input)))
+    })
+}
+
+/// `sax_parse` leaving out the event classes `opts` excludes
+///
+/// Options: `skip_comments`, `skip_processing_instructions` and
+/// `skip_whitespace` (whitespace-only text) take booleans; `max_depth: n`
+/// drops everything nested deeper than `n`, the root element being at
+/// depth 1. Events are filtered as they are scanned.
+#[rustler::nif(schedule = "DirtyCpu", name = "sax_parse")]
+fn sax_parse_filtered<'a>(
+    env: Env<'a>,
+    input: Binary<'a>,
+    opts: Vec<(rustler::Atom, Term)>,
+) -> NifResult<Term<'a>> {
+    let mut filter = sax::SaxFilter::default();
+    for (key, value) in opts {
+        if key == atoms::skip_comments() {
+            filter.skip_comments = value.decode()?;
+        } else if key == atoms::skip_processing_instructions() {
+            filter.skip_processing_instructions = value.decode()?;
+        } else if key == atoms::skip_whitespace() {
+            filter.skip_whitespace = value.decode()?;
+        } else if key == atoms::max_depth() {
+            filter.max_depth = Some(value.decode()?);
+        } else {
+            return Err(rustler::Error::BadArg);
+        }
+    }
+
+    catch_panic(env, || {
+        use core::unified_scanner::UnifiedScanner;
+
+        if let Err(error) = check_memory(env, input.len()) {
+            return Ok(error);
+        }
+        let bytes = input.as_slice();
+        let mut collector = sax::FilteringCollector::new(bytes, filter);
+        UnifiedScanner::new(bytes).scan(&mut collector);
+        if let Err(error) = check_memory(env, 0) {
+            return Ok(error);
+        }
+
+        Ok(sax_event_list(
+            env,
+            collector.collector(),
+            bytes,
+            Some(input),
+        ))
+    })
+}
 
+/// Build the event list for everything in `collector`
+///
+/// `source`, when given, is the binary `input` came from; large attribute
+/// values are then returned as sub-binaries of it.
+fn sax_event_list<'a>(
+    env: Env<'a>,
+    collector: &sax::SaxCollector,
+    input: &[u8],
+    source: Option<Binary<'a>>,
+) -> Term<'a> {
+    let attrs = collector.attributes();
     let mut list = Term::list_new_empty(env);
 
-    for event in events.iter().rev() {
-        let term = sax_event_to_term(env, event, attrs, bytes);
+    for event in collector.events().iter().rev() {
+        let term = sax_event_to_term(env, event, attrs, input, source);
         list = list.list_prepend(term);
     }
 
-    Ok(list)
+    list
+}
+
+/// Parse XML and send the SAX events to `pid` in batches
+///
+/// Returns a handle at once; a background worker then scans the input and
+/// sends `{:rustyxml_sax, handle, {:events, events}}` for every
+/// `batch_size` events (in `sax_parse/1` format), followed by
+/// `{:rustyxml_sax, handle, :done}`. Sending from an `OwnedEnv` is not
+/// allowed on scheduler threads, hence the worker. The input is retained
+/// rather than copied, and parsing stops if `pid` exits or the stream is
+/// cancelled.
+#[rustler::nif]
+fn sax_parse_send<'a>(
+    env: Env<'a>,
+    input: Binary<'a>,
+    pid: LocalPid,
+    batch_size: usize,
+) -> NifResult<SaxSendRef> {
+    spawn_sax_sender(env, input, pid, batch_size, None)
+}
+
+/// Like `sax_parse_send`, with at most `window` batches unacknowledged
+///
+/// Once `window` batches are outstanding, tokenizing pauses until the
+/// consumer calls `sax_send_ack/2`, so a slow consumer bounds both its
+/// mailbox and the parser's progress. A pause longer than `timeout_ms` ends
+/// the stream with `{:rustyxml_sax, handle, {:error, :timeout}}`, freeing
+/// the worker from a consumer that stopped acknowledging.
+#[rustler::nif]
+fn sax_parse_send_window<'a>(
+    env: Env<'a>,
+    input: Binary<'a>,
+    pid: LocalPid,
+    batch_size: usize,
+    window: usize,
+    timeout_ms: u64,
+) -> NifResult<SaxSendRef> {
+    if window == 0 {
+        return Err(rustler::Error::BadArg);
+    }
+    let timeout = std::time::Duration::from_millis(timeout_ms);
+    spawn_sax_sender(env, input, pid, batch_size, Some((window, timeout)))
+}
+
+/// Acknowledge `n` batches of a windowed `sax_parse_send` stream
+#[rustler::nif]
+fn sax_send_ack(handle: SaxSendRef, n: usize) -> NifResult<rustler::Atom> {
+    if handle.ack(n) {
+        Ok(atoms::ok())
+    } else {
+        Err(rustler::Error::Term(Box::new(atoms::mutex_poisoned())))
+    }
+}
+
+/// Stop a `sax_parse_send` stream; no batch is sent after the one in flight
+#[rustler::nif]
+fn sax_send_cancel(handle: SaxSendRef) -> rustler::Atom {
+    handle.close();
+    atoms::ok()
+}
+
+/// Queue the worker behind `sax_parse_send` and `sax_parse_send_window`
+fn spawn_sax_sender(
+    env: Env,
+    input: Binary,
+    pid: LocalPid,
+    batch_size: usize,
+    window: Option<(usize, std::time::Duration)>,
+) -> NifResult<SaxSendRef> {
+    if batch_size == 0 {
+        return Err(rustler::Error::BadArg);
+    }
+    let handle = ResourceArc::new(SaxSendResource::default());
+    if handle.monitor(Some(env), &pid).is_none() {
+        // The consumer is already gone; the thread will stop at once
+        handle.close();
+    }
+    let input = RetainedBinary::new(input, 0);
+    let stream = handle.clone();
+
+    workers::spawn_sax(move || {
+        use core::unified_scanner::UnifiedScanner;
+        use sax::{BatchingCollector, SaxCollector};
+
+        let bytes = input.as_slice();
+        let mut timed_out = false;
+        let finished = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let mut msg_env = OwnedEnv::new();
+            let mut collector = BatchingCollector::new(batch_size, |batch: &SaxCollector| {
+                stream.batch_sent();
+                let sent = msg_env.send_and_clear(&pid, |env| {
+                    let events = sax_event_list(env, batch, bytes, None);
+                    (atoms::rustyxml_sax(), &stream, (atoms::events(), events)).encode(env)
+                });
+                match window {
+                    _ if sent.is_err() => false,
+                    Some((limit, timeout)) => match stream.wait_for_window(limit, timeout) {
+                        WindowWait::Open => true,
+                        WindowWait::Closed => false,
+                        WindowWait::TimedOut => {
+                            timed_out = true;
+                            false
+                        }
+                    },
+                    None => !stream.is_closed(),
+                }
+            });
+            if !stream.is_closed() {
+                UnifiedScanner::new(bytes).scan(&mut collector);
+            }
+            collector.finish()
+        }));
+
+        let error = match finished {
+            Ok(true) => None,
+            Ok(false) if timed_out => Some(atoms::timeout()),
+            Ok(false) => return,
+            Err(_) => Some(atoms::internal_parser_error()),
+        };
+        let _ = OwnedEnv::new().send_and_clear(&pid, |env| {
+            let message = match error {
+                Some(reason) => (atoms::error(), reason).encode(env),
+                None => atoms::done().encode(env),
+            };
+            (atoms::rustyxml_sax(), &stream, message).encode(env)
+        });
+    });
+
+    Ok(handle)
 }
 
 /// Convert a SAX event to an Elixir term
@@ -638,6 +4193,7 @@ fn sax_event_to_term<'a>(
     event: &sax::CompactSaxEvent,
     attrs: &[(u32, u32, u32, u32)],
     input: &[u8],
+    source: Option<Binary<'a>>,
 ) -> Term<'a> {
     use sax::CompactSaxEvent;
 
@@ -652,7 +4208,8 @@ fn sax_event_to_term<'a>(
             if let Some(attr_slice) = attrs.get(attr_start..attr_start + attr_count) {
                 for &(no, nl, vo, vl) in attr_slice.iter().rev() {
                     let attr_name = span_to_binary(env, no as usize, nl as usize, input);
-                    let attr_value = span_to_binary(env, vo as usize, vl as usize, input);
+                    let attr_value =
+                        attr_value_binary(env, vo as usize, vl as usize, input, source);
                     attr_list = attr_list.list_prepend((attr_name, attr_value).encode(env));
                 }
             }
@@ -713,6 +4270,29 @@ fn span_to_binary<'a>(env: Env<'a>, offset: usize, len: usize, input: &[u8]) ->
     }
 }
 
+/// Create a binary for an attribute value span in the input
+///
+/// Values of at least `LARGE_VALUE_THRESHOLD` bytes become sub-binaries of
+/// `source` (the binary `input` came from) instead of copies. Small values
+/// are still copied, so that holding on to a few of them does not keep a
+/// large input alive.
+fn attr_value_binary<'a>(
+    env: Env<'a>,
+    offset: usize,
+    len: usize,
+    input: &[u8],
+    source: Option<Binary<'a>>,
+) -> Term<'a> {
+    use core::attributes::LARGE_VALUE_THRESHOLD;
+
+    if len >= LARGE_VALUE_THRESHOLD {
+        if let Some(sub) = source.and_then(|binary| binary.make_subbinary(offset, len).ok()) {
+            return sub.encode(env);
+        }
+    }
+    span_to_binary(env, offset, len, input)
+}
+
 // ============================================================================
 // SAX Parsing — Saxy Format (Tier 3)
 // ============================================================================
@@ -732,11 +4312,69 @@ fn sax_parse_saxy<'a>(
     input: Binary<'a>,
     cdata_as_chars: bool,
 ) -> NifResult<Term<'a>> {
+    catch_panic(env, || {
+        Ok(saxy_event_list(
+            env,
+            input,
+            cdata_as_chars,
+            SaxyText::Decoded(Normalization::None),
+        ))
+    })
+}
+
+/// Parse XML into Saxy-format events with text and attribute values
+/// normalized
+///
+/// Like `sax_parse_saxy`; `form` is `:nfc`, `:nfd` or `:none`.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn sax_parse_saxy_normalized<'a>(
+    env: Env<'a>,
+    input: Binary<'a>,
+    cdata_as_chars: bool,
+    form: rustler::Atom,
+) -> NifResult<Term<'a>> {
+    catch_panic(env, || {
+        let text = SaxyText::Decoded(decode_normalization(form)?);
+        Ok(saxy_event_list(env, input, cdata_as_chars, text))
+    })
+}
+
+/// Parse XML into Saxy-format events keeping text exactly as in the source
+///
+/// Like `sax_parse_saxy`, but without entity decoding: text and CDATA
+/// events carry `{raw_content, {offset, length}}` (byte positions in the
+/// input) and attribute values are raw.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn sax_parse_saxy_raw<'a>(
+    env: Env<'a>,
+    input: Binary<'a>,
+    cdata_as_chars: bool,
+) -> NifResult<Term<'a>> {
+    catch_panic(env, || {
+        Ok(saxy_event_list(env, input, cdata_as_chars, SaxyText::Raw))
+    })
+}
+
+/// How text and attribute values are delivered in Saxy-format events
+#[derive(Clone, Copy)]
+enum SaxyText {
+    /// Entity-decoded, then put in the given normalization form
+    Decoded(Normalization),
+    /// Exactly as in the source, text with its `{offset, length}` span
+    Raw,
+}
+
+/// Scan the input and build the Saxy-format event list
+fn saxy_event_list<'a>(
+    env: Env<'a>,
+    input: Binary<'a>,
+    cdata_as_chars: bool,
+    text: SaxyText,
+) -> Term<'a> {
     use core::unified_scanner::UnifiedScanner;
     use sax::SaxCollector;
 
     let bytes = input.as_slice();
-
     let mut collector = SaxCollector::new();
     let mut scanner = UnifiedScanner::new(bytes);
     scanner.scan(&mut collector);
@@ -754,20 +4392,28 @@ fn sax_parse_saxy<'a>(
         match event.tag {
             CompactSaxEvent::TAG_START_ELEMENT => {
                 depth += 1;
-                sax_event_to_saxy_terms(env, event, attrs, bytes, cdata_as_chars, &mut terms);
+                sax_event_to_saxy_terms(env, event, attrs, input, cdata_as_chars, text, &mut terms);
             }
             CompactSaxEvent::TAG_END_ELEMENT => {
-                sax_event_to_saxy_terms(env, event, attrs, bytes, cdata_as_chars, &mut terms);
+                sax_event_to_saxy_terms(env, event, attrs, input, cdata_as_chars, text, &mut terms);
                 depth = depth.saturating_sub(1);
             }
             CompactSaxEvent::TAG_TEXT | CompactSaxEvent::TAG_CDATA => {
                 if depth > 0 {
-                    sax_event_to_saxy_terms(env, event, attrs, bytes, cdata_as_chars, &mut terms);
+                    sax_event_to_saxy_terms(
+                        env,
+                        event,
+                        attrs,
+                        input,
+                        cdata_as_chars,
+                        text,
+                        &mut terms,
+                    );
                 }
                 // Skip text/cdata at document level (depth == 0)
             }
             _ => {
-                sax_event_to_saxy_terms(env, event, attrs, bytes, cdata_as_chars, &mut terms);
+                sax_event_to_saxy_terms(env, event, attrs, input, cdata_as_chars, text, &mut terms);
             }
         }
     }
@@ -778,7 +4424,7 @@ fn sax_parse_saxy<'a>(
         list = list.list_prepend(t);
     }
 
-    Ok(list)
+    list
 }
 
 /// Take events from streaming parser in Saxy-compatible format
@@ -789,19 +4435,21 @@ fn streaming_take_saxy_events<'a>(
     max: usize,
     cdata_as_chars: bool,
 ) -> NifResult<Term<'a>> {
-    match parser.inner.lock() {
-        Ok(mut inner) => {
-            let events = inner.take_events(max);
-
-            // Convert OwnedXmlEvent to Saxy format
-            let mut list = Term::list_new_empty(env);
-            for event in events.into_iter().rev() {
-                owned_event_to_saxy_terms(env, event, cdata_as_chars, &mut list);
+    catch_panic(env, || {
+        match parser.lock() {
+            Ok(mut inner) => {
+                let events = inner.take_events(max);
+
+                // Convert OwnedXmlEvent to Saxy format
+                let mut list = Term::list_new_empty(env);
+                for event in events.into_iter().rev() {
+                    owned_event_to_saxy_terms(env, event, cdata_as_chars, &mut list);
+                }
+                Ok(list)
             }
-            Ok(list)
+            Err(unavailable) => Ok((atoms::error(), unavailable.reason()).encode(env)),
         }
-        Err(_) => Ok((atoms::error(), atoms::mutex_poisoned()).encode(env)),
-    }
+    })
 }
 
 /// Convert a compact SAX event to Saxy-format term(s), pushing to output vec.
@@ -810,12 +4458,20 @@ fn sax_event_to_saxy_terms<'a>(
     env: Env<'a>,
     event: &sax::CompactSaxEvent,
     attrs: &[(u32, u32, u32, u32)],
-    input: &[u8],
+    source: Binary<'a>,
     cdata_as_chars: bool,
+    text: SaxyText,
     out: &mut Vec<Term<'a>>,
 ) {
     use sax::CompactSaxEvent;
 
+    let input = source.as_slice();
+    let normalization = match text {
+        SaxyText::Decoded(normalization) => normalization,
+        SaxyText::Raw => Normalization::None,
+    };
+    let raw_text = matches!(text, SaxyText::Raw);
+
     match event.tag {
         CompactSaxEvent::TAG_START_ELEMENT => {
             let name = span_to_binary(env, event.offset as usize, event.len as usize, input);
@@ -827,7 +4483,19 @@ fn sax_event_to_saxy_terms<'a>(
             if let Some(attr_slice) = attrs.get(attr_start..attr_start + attr_count) {
                 for &(no, nl, vo, vl) in attr_slice.iter().rev() {
                     let attr_name = span_to_binary(env, no as usize, nl as usize, input);
-                    let attr_value = decode_and_binary(env, input, vo as usize, vl as usize);
+                    let (vo, vl) = (vo as usize, vl as usize);
+                    let raw = input.get(vo..vo + vl).unwrap_or_default();
+                    let decoded = if raw_text {
+                        std::borrow::Cow::Borrowed(raw)
+                    } else {
+                        normalization.apply(crate::core::entities::decode_text(raw))
+                    };
+                    let attr_value = match decoded {
+                        std::borrow::Cow::Borrowed(_) => {
+                            attr_value_binary(env, vo, vl, input, Some(source))
+                        }
+                        std::borrow::Cow::Owned(bytes) => term::bytes_to_binary(env, &bytes),
+                    };
                     attr_list = attr_list.list_prepend((attr_name, attr_value).encode(env));
                 }
             }
@@ -839,10 +4507,13 @@ fn sax_event_to_saxy_terms<'a>(
             let name = span_to_binary(env, event.offset as usize, event.len as usize, input);
             out.push((term::end_element(), name).encode(env));
         }
+        CompactSaxEvent::TAG_TEXT if raw_text => {
+            out.push((term::characters(), raw_text_with_span(env, event, input)).encode(env));
+        }
         CompactSaxEvent::TAG_TEXT => {
             let content = if event.needs_decode() {
                 let raw = &input[event.offset as usize..(event.offset + event.len) as usize];
-                let decoded = crate::core::entities::decode_text(raw);
+                let decoded = normalization.apply(crate::core::entities::decode_text(raw));
                 match decoded {
                     std::borrow::Cow::Borrowed(_) => {
                         span_to_binary(env, event.offset as usize, event.len as usize, input)
@@ -850,13 +4521,17 @@ fn sax_event_to_saxy_terms<'a>(
                     std::borrow::Cow::Owned(bytes) => term::bytes_to_binary(env, &bytes),
                 }
             } else {
-                span_to_binary(env, event.offset as usize, event.len as usize, input)
+                normalized_span_to_binary(env, event, input, normalization)
             };
             // Saxy format: {:characters, content}
             out.push((term::characters(), content).encode(env));
         }
         CompactSaxEvent::TAG_CDATA => {
-            let content = span_to_binary(env, event.offset as usize, event.len as usize, input);
+            let content = if raw_text {
+                raw_text_with_span(env, event, input)
+            } else {
+                normalized_span_to_binary(env, event, input, normalization)
+            };
             if cdata_as_chars {
                 out.push((term::characters(), content).encode(env));
             } else {
@@ -869,6 +4544,29 @@ fn sax_event_to_saxy_terms<'a>(
     }
 }
 
+/// Binary of an event's content in the given normalization form
+fn normalized_span_to_binary<'a>(
+    env: Env<'a>,
+    event: &sax::CompactSaxEvent,
+    input: &[u8],
+    normalization: Normalization,
+) -> Term<'a> {
+    let (offset, len) = (event.offset as usize, event.len as usize);
+    match input
+        .get(offset..offset + len)
+        .map(|raw| normalization.apply(raw.into()))
+    {
+        Some(std::borrow::Cow::Owned(bytes)) => term::bytes_to_binary(env, &bytes),
+        _ => span_to_binary(env, offset, len, input),
+    }
+}
+
+/// `{raw_content, {offset, length}}` for a text or CDATA event
+fn raw_text_with_span<'a>(env: Env<'a>, event: &sax::CompactSaxEvent, input: &[u8]) -> Term<'a> {
+    let (offset, len) = (event.offset as usize, event.len as usize);
+    (span_to_binary(env, offset, len, input), (offset, len)).encode(env)
+}
+
 /// Convert an OwnedXmlEvent to Saxy format terms, prepended to list
 fn owned_event_to_saxy_terms<'a>(
     env: Env<'a>,
@@ -931,20 +4629,6 @@ fn owned_event_to_saxy_terms<'a>(
     }
 }
 
-/// Decode entities in a span and return as binary
-fn decode_and_binary<'a>(env: Env<'a>, input: &[u8], offset: usize, len: usize) -> Term<'a> {
-    if offset + len <= input.len() {
-        let raw = &input[offset..offset + len];
-        let decoded = crate::core::entities::decode_text(raw);
-        match decoded {
-            std::borrow::Cow::Borrowed(b) => term::bytes_to_binary(env, b),
-            std::borrow::Cow::Owned(bytes) => term::bytes_to_binary(env, &bytes),
-        }
-    } else {
-        term::bytes_to_binary(env, b"")
-    }
-}
-
 // ============================================================================
 // Streaming SAX Parsing (chunk-by-chunk, bounded memory)
 // ============================================================================
@@ -955,6 +4639,130 @@ fn streaming_sax_new() -> StreamingSaxParserRef {
     ResourceArc::new(StreamingSaxParserResource::new())
 }
 
+/// Select the wire format of the binaries a streaming SAX parser returns
+///
+/// `1` is the original fixed-width format, `2` the varint format with a
+/// per-call name dictionary (see `streaming_feed_sax`). Takes effect from
+/// the next call.
+#[rustler::nif]
+fn streaming_sax_set_format(
+    parser: StreamingSaxParserRef,
+    version: u8,
+) -> NifResult<rustler::Atom> {
+    let format = match version {
+        1 => SaxBinaryFormat::V1,
+        2 => SaxBinaryFormat::V2,
+        _ => return Err(rustler::Error::BadArg),
+    };
+    let mut inner = parser.lock()?;
+    inner.format = format;
+    Ok(atoms::ok())
+}
+
+/// Report elements whose `id` or `xml:id` repeats an earlier one
+///
+/// From the next call on, each such element is followed (after its
+/// start_element event) by a duplicate_id event carrying the value and its
+/// occurrence count, 2 for the first repeat. Only a hash and a count are
+/// kept per distinct value, so memory stays small on very large documents.
+#[rustler::nif]
+fn streaming_sax_track_duplicate_ids(parser: StreamingSaxParserRef) -> NifResult<rustler::Atom> {
+    let mut inner = parser.lock()?;
+    inner.duplicate_ids.get_or_insert_with(Default::default);
+    Ok(atoms::ok())
+}
+
+/// Set how many bytes of an unfinished tag a streaming SAX parser may hold
+/// between calls (0 = unlimited)
+///
+/// Once the bytes waiting for a tag's `>` exceed the limit, the feed
+/// returns `{:error, {:max_tail_size_exceeded, limit}}` and drops them. The
+/// default is `DEFAULT_MAX_TAIL_SIZE` (64 MiB).
+#[rustler::nif]
+fn streaming_sax_set_max_tail(
+    parser: StreamingSaxParserRef,
+    max_bytes: usize,
+) -> NifResult<rustler::Atom> {
+    let mut inner = parser.lock()?;
+    inner.max_tail_size = (max_bytes > 0).then_some(max_bytes);
+    Ok(atoms::ok())
+}
+
+/// Set attribute and element count limits on a streaming SAX parser
+///
+/// `max_attributes` bounds the attributes of each element and
+/// `max_elements` the elements in the document (0 = unlimited; both 0
+/// removes the limits). With `on_limit` `:error`, the feed that meets a
+/// breaking element returns `{:error, {:attribute_limit_exceeded, limit}}`
+/// or `{:error, {:element_limit_exceeded, limit}}`. With `:warn`, a
+/// limit_warning event follows the element's start_element event and
+/// parsing continues: attributes past the limit are dropped, and the
+/// element limit is reported once, by the first element past it.
+#[rustler::nif]
+fn streaming_sax_set_limits(
+    parser: StreamingSaxParserRef,
+    max_attributes: usize,
+    max_elements: usize,
+    on_limit: rustler::Atom,
+) -> NifResult<rustler::Atom> {
+    let mode = if on_limit == atoms::error() {
+        LimitMode::Error
+    } else if on_limit == atoms::warn() {
+        LimitMode::Warn
+    } else {
+        return Err(rustler::Error::BadArg);
+    };
+    let mut inner = parser.lock()?;
+    inner.limits = (max_attributes > 0 || max_elements > 0)
+        .then(|| SaxLimits::new(max_attributes, max_elements, mode));
+    Ok(atoms::ok())
+}
+
+/// Fail a feed that met an element breaking the parser's limits in
+/// `:error` mode, releasing its tail
+fn check_limits(inner: &mut resource::StreamingSaxParser) -> NifResult<()> {
+    let Some(breach) = inner.limits.as_ref().and_then(SaxLimits::stopped) else {
+        return Ok(());
+    };
+    inner.buffer = Vec::new();
+    inner.boundary_scan = BoundaryScan::default();
+    let reason = match breach.kind {
+        LimitKind::Attributes => atoms::attribute_limit_exceeded(),
+        LimitKind::Elements => atoms::element_limit_exceeded(),
+    };
+    Err(rustler::Error::Term(Box::new((reason, breach.limit))))
+}
+
+/// Fail a feed whose tail has outgrown the parser's limit, releasing it
+fn check_tail_size(inner: &mut resource::StreamingSaxParser) -> NifResult<()> {
+    match inner.max_tail_size {
+        Some(limit) if inner.buffer.len() > limit => {
+            inner.buffer = Vec::new();
+            inner.boundary_scan = BoundaryScan::default();
+            Err(rustler::Error::Term(Box::new((
+                atoms::max_tail_size_exceeded(),
+                limit,
+            ))))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Create a streaming SAX parser that emits text/CDATA in bounded pieces
+///
+/// Content longer than `chunk_size` bytes, or a text node/CDATA section that
+/// is still open at the end of a chunk, is emitted as chunk events
+/// (types 5 and 6) so a huge section never has to be buffered whole.
+#[rustler::nif]
+fn streaming_sax_new_chunked(chunk_size: usize) -> NifResult<StreamingSaxParserRef> {
+    if chunk_size == 0 {
+        return Err(rustler::Error::BadArg);
+    }
+    Ok(ResourceArc::new(
+        StreamingSaxParserResource::with_text_chunks(chunk_size),
+    ))
+}
+
 /// Feed a chunk and return SAX events as a compact binary.
 ///
 /// Two key optimizations for minimal NIF peak memory:
@@ -969,11 +4777,26 @@ fn streaming_sax_new() -> StreamingSaxParserRef {
 ///
 /// Combined NIF + BEAM peak is ~67 KB for a 2.93 MB document (64 KB chunks).
 ///
-/// Binary format:
-///   start_element: <<1, name_len::16, name, attr_count::16, [nlen::16, name, vlen::16, value]*>>
-///   end_element:   <<2, name_len::16, name>>
-///   characters:    <<3, text_len::32, text>>
-///   cdata:         <<4, text_len::32, text>>
+/// Binary format (v1, the default):
+///   start_element:   <<1, name_len::16, name, attr_count::16, [nlen::16, name, vlen::16, value]*>>
+///   end_element:     <<2, name_len::16, name>>
+///   characters:      <<3, text_len::32, text>>
+///   cdata:           <<4, text_len::32, text>>
+///   characters_chunk: <<5, final::8, text_len::32, text>>  (chunked parsers only)
+///   cdata_chunk:     <<6, final::8, text_len::32, text>>  (chunked parsers only)
+///   duplicate_id:    <<7, value_len::16, value, occurrence::32>>  (tracking parsers only)
+///   limit_warning:   <<8, kind::8, name_len::16, name, count::32>>  (`:warn` limits only;
+///                    kind 1 = attributes, 2 = elements)
+///
+/// In v2 (`streaming_sax_set_format/2`) every length and the attribute count
+/// is an unsigned LEB128 varint, and element and attribute names are name
+/// references: `<<0, len::varint, name>>` defines the next id (1, 2, ...)
+/// and `<<id::varint>>` repeats a name defined earlier in the same binary.
+///   start_element:   <<1, name_ref, attr_count::varint, [name_ref, vlen::varint, value]*>>
+///   end_element:     <<2, name_ref>>
+///   characters/cdata and chunks as in v1, with `text_len::varint`
+///   duplicate_id:    <<7, value_len::varint, value, occurrence::varint>>
+///   limit_warning:   <<8, kind::8, name_ref, count::varint>>
 #[rustler::nif]
 fn streaming_feed_sax<'a>(
     env: Env<'a>,
@@ -981,121 +4804,96 @@ fn streaming_feed_sax<'a>(
     chunk: Binary,
     cdata_as_chars: bool,
 ) -> NifResult<Term<'a>> {
-    use core::entities::decode_text;
-    use core::tokenizer::{TokenKind, Tokenizer};
-    use strategy::streaming::find_safe_boundary;
-
-    let mut inner = parser
-        .inner
-        .lock()
-        .map_err(|_| rustler::Error::Term(Box::new(atoms::mutex_poisoned())))?;
-
-    // Zero-copy fast path: if the persistent buffer is empty (common case),
-    // tokenize directly from the BEAM binary without copying 64 KB into Rust.
-    // Only when there IS leftover tail bytes do we concatenate.
-    let had_tail = !inner.buffer.is_empty();
-    if had_tail {
-        inner.buffer.extend_from_slice(chunk.as_slice());
-    }
-
-    // Scope all reads from `input` (which may alias inner.buffer) so the
-    // immutable borrow ends before we mutate inner.depth / inner.buffer.
-    let (depth, tail, buf) = {
-        let input: &[u8] = if had_tail {
-            &inner.buffer
-        } else {
-            chunk.as_slice()
-        };
-
-        let boundary = find_safe_boundary(input);
-        if boundary == 0 {
-            // Nothing processable yet — save everything as the tail.
-            if !had_tail {
-                inner.buffer.extend_from_slice(chunk.as_slice());
-            }
-            return Ok(empty_binary(env));
+    catch_panic(env, || {
+        if let Err(error) = check_memory(env, chunk.len()) {
+            return Ok(error);
+        }
+        let mut inner = parser.lock()?;
+        let inner = &mut *inner;
+
+        // Zero-copy fast path: if the persistent buffer is empty (common case),
+        // tokenize directly from the BEAM binary without copying 64 KB into Rust.
+        // Only when there IS leftover tail bytes do we concatenate.
+        let had_tail = !inner.buffer.is_empty();
+        if had_tail {
+            inner.buffer.extend_from_slice(chunk.as_slice());
         }
 
-        let mut depth = inner.depth;
-        let mut buf = BinaryWriter::new(chunk.len().max(256))?;
-        let processable = &input[..boundary];
-        let mut tokenizer = Tokenizer::new(processable);
-
-        while let Some(token) = tokenizer.next_token() {
-            match token.kind {
-                TokenKind::Eof => break,
-
-                TokenKind::StartTag => {
-                    depth += 1;
-                    if let Some(name) = token.name {
-                        buf.push(1);
-                        encode_bytes(&mut buf, name.as_ref());
-                        encode_attrs(&mut buf, processable, token.span);
-                    }
-                }
-
-                TokenKind::EndTag => {
-                    if let Some(name) = token.name {
-                        buf.push(2);
-                        encode_bytes(&mut buf, name.as_ref());
-                    }
-                    depth = depth.saturating_sub(1);
-                }
-
-                TokenKind::EmptyTag => {
-                    if let Some(name) = token.name {
-                        buf.push(1);
-                        encode_bytes(&mut buf, name.as_ref());
-                        encode_attrs(&mut buf, processable, token.span);
-                        buf.push(2);
-                        encode_bytes(&mut buf, name.as_ref());
-                    }
-                }
+        // Scope all reads from `input` (which may alias inner.buffer) so the
+        // immutable borrow ends before we mutate inner.depth / inner.buffer.
+        let (tail, buf) = {
+            let input: &[u8] = if had_tail {
+                &inner.buffer
+            } else {
+                chunk.as_slice()
+            };
 
-                TokenKind::Text => {
-                    if depth > 0 {
-                        if let Some(content) = token.content {
-                            if !content.is_empty() {
-                                let decoded = decode_text(content.as_ref());
-                                buf.push(3);
-                                encode_content(&mut buf, decoded.as_ref());
-                            }
-                        }
+            let mut buf = BinaryWriter::new(chunk.len().max(256), inner.format)?;
+
+            let consumed = if let Some(chunk_size) = inner.text_chunk_size {
+                let mut state = ChunkState {
+                    depth: inner.depth,
+                    in_cdata: inner.in_cdata,
+                    in_text: inner.in_text,
+                };
+                let mut checks = ElementChecks {
+                    ids: inner.duplicate_ids.as_mut(),
+                    limits: inner.limits.as_mut(),
+                };
+                let consumed = encode_sax_chunked(
+                    &mut buf,
+                    input,
+                    &mut state,
+                    cdata_as_chars,
+                    chunk_size,
+                    false,
+                    &mut checks,
+                );
+                inner.depth = state.depth;
+                inner.in_cdata = state.in_cdata;
+                inner.in_text = state.in_text;
+                consumed
+            } else {
+                let boundary = inner.boundary_scan.advance(input);
+                if boundary == 0 {
+                    // Nothing processable yet — save everything as the tail.
+                    if !had_tail {
+                        inner.buffer.extend_from_slice(chunk.as_slice());
                     }
+                    check_tail_size(inner)?;
+                    return Ok(empty_binary(env));
                 }
+                let mut checks = ElementChecks {
+                    ids: inner.duplicate_ids.as_mut(),
+                    limits: inner.limits.as_mut(),
+                };
+                encode_sax_tokens(
+                    &mut buf,
+                    &input[..boundary],
+                    &mut inner.depth,
+                    cdata_as_chars,
+                    None,
+                    &mut checks,
+                );
+                inner.boundary_scan.drain_to_boundary();
+                boundary
+            };
 
-                TokenKind::CData => {
-                    if depth > 0 {
-                        if let Some(content) = token.content {
-                            if cdata_as_chars {
-                                buf.push(3);
-                            } else {
-                                buf.push(4);
-                            }
-                            encode_content(&mut buf, content.as_ref());
-                        }
-                    }
-                }
+            (input[consumed..].to_vec(), buf)
+        };
+        // immutable borrow of inner.buffer is now released.
 
-                _ => {}
-            }
+        // Save only the unprocessed tail (typically ~100 bytes).
+        inner.buffer.clear();
+        if !tail.is_empty() {
+            inner.buffer.extend_from_slice(&tail);
         }
+        inner.buffer.shrink_to(1024);
+        check_limits(inner)?;
+        check_tail_size(inner)?;
 
-        let tail = input[boundary..].to_vec();
-        (depth, tail, buf)
-    };
-    // immutable borrow of inner.buffer is now released.
-
-    inner.depth = depth;
-
-    // Save only the unprocessed tail (typically ~100 bytes).
-    inner.buffer.clear();
-    if !tail.is_empty() {
-        inner.buffer.extend_from_slice(&tail);
-    }
-    inner.buffer.shrink_to(1024);
-
-    buf.into_term(env)
+        buf.into_term(env)
+    })
 }
 
 /// Process remaining bytes in the buffer after all chunks have been fed.
@@ -1105,75 +4903,150 @@ fn streaming_finalize_sax<'a>(
     parser: StreamingSaxParserRef,
     cdata_as_chars: bool,
 ) -> NifResult<Term<'a>> {
-    use core::entities::decode_text;
-    use core::tokenizer::{TokenKind, Tokenizer};
+    catch_panic(env, || {
+        let mut inner = parser.lock()?;
+        let inner = &mut *inner;
+
+        if inner.buffer.is_empty() && !inner.in_cdata && !inner.in_text {
+            return Ok(empty_binary(env));
+        }
 
-    let mut inner = parser
-        .inner
-        .lock()
-        .map_err(|_| rustler::Error::Term(Box::new(atoms::mutex_poisoned())))?;
+        let remaining = std::mem::take(&mut inner.buffer);
+        inner.boundary_scan = BoundaryScan::default();
+        let mut buf = BinaryWriter::new(remaining.len().max(256), inner.format)?;
+        let mut checks = ElementChecks {
+            ids: inner.duplicate_ids.as_mut(),
+            limits: inner.limits.as_mut(),
+        };
 
-    if inner.buffer.is_empty() {
-        return Ok(empty_binary(env));
-    }
+        if let Some(chunk_size) = inner.text_chunk_size {
+            let mut state = ChunkState {
+                depth: inner.depth,
+                in_cdata: inner.in_cdata,
+                in_text: inner.in_text,
+            };
+            encode_sax_chunked(
+                &mut buf,
+                &remaining,
+                &mut state,
+                cdata_as_chars,
+                chunk_size,
+                true,
+                &mut checks,
+            );
+            inner.depth = state.depth;
+            inner.in_cdata = false;
+            inner.in_text = false;
+        } else {
+            encode_sax_tokens(
+                &mut buf,
+                &remaining,
+                &mut inner.depth,
+                cdata_as_chars,
+                None,
+                &mut checks,
+            );
+        }
+        check_limits(inner)?;
 
-    let remaining = std::mem::take(&mut inner.buffer);
-    let mut depth = inner.depth;
-    let mut buf = BinaryWriter::new(remaining.len().max(256))?;
-    let mut tokenizer = Tokenizer::new(&remaining);
+        buf.into_term(env)
+    })
+}
+
+/// Per-element bookkeeping a streaming SAX parser has switched on
+struct ElementChecks<'a> {
+    /// ID values seen, when reporting duplicate IDs
+    ids: Option<&'a mut IdTracker>,
+    /// Attribute and element count limits
+    limits: Option<&'a mut SaxLimits>,
+}
+
+/// Tokenize `input` and encode every event into `buf`, tracking element depth.
+///
+/// With `chunk_size` set, text and CDATA content longer than the limit is
+/// written as a run of chunk events instead of a single characters/cdata event.
+/// Start tags also go through `checks` (see `encode_attrs`); encoding stops at
+/// an element that breaks a limit in `:error` mode.
+fn encode_sax_tokens(
+    buf: &mut BinaryWriter,
+    input: &[u8],
+    depth: &mut u32,
+    cdata_as_chars: bool,
+    chunk_size: Option<usize>,
+    checks: &mut ElementChecks,
+) {
+    use core::entities::decode_text;
+    use core::tokenizer::{TokenKind, Tokenizer};
+
+    let mut tokenizer = Tokenizer::new(input);
 
     while let Some(token) = tokenizer.next_token() {
         match token.kind {
             TokenKind::Eof => break,
 
             TokenKind::StartTag => {
-                depth += 1;
+                *depth += 1;
                 if let Some(name) = token.name {
                     buf.push(1);
-                    encode_bytes(&mut buf, name.as_ref());
-                    encode_attrs(&mut buf, &remaining, token.span);
+                    encode_name(buf, name.as_ref());
+                    if !encode_attrs(buf, input, token.span, name.as_ref(), checks) {
+                        break;
+                    }
                 }
             }
 
             TokenKind::EndTag => {
                 if let Some(name) = token.name {
                     buf.push(2);
-                    encode_bytes(&mut buf, name.as_ref());
+                    encode_name(buf, name.as_ref());
                 }
-                depth = depth.saturating_sub(1);
+                *depth = depth.saturating_sub(1);
             }
 
             TokenKind::EmptyTag => {
                 if let Some(name) = token.name {
                     buf.push(1);
-                    encode_bytes(&mut buf, name.as_ref());
-                    encode_attrs(&mut buf, &remaining, token.span);
+                    encode_name(buf, name.as_ref());
+                    if !encode_attrs(buf, input, token.span, name.as_ref(), checks) {
+                        break;
+                    }
                     buf.push(2);
-                    encode_bytes(&mut buf, name.as_ref());
+                    encode_name(buf, name.as_ref());
                 }
             }
 
             TokenKind::Text => {
-                if depth > 0 {
+                if *depth > 0 {
                     if let Some(content) = token.content {
                         if !content.is_empty() {
                             let decoded = decode_text(content.as_ref());
-                            buf.push(3);
-                            encode_content(&mut buf, decoded.as_ref());
+                            match chunk_size {
+                                Some(max) if decoded.len() > max => {
+                                    encode_pieces(buf, 5, decoded.as_ref(), max, true);
+                                }
+                                _ => {
+                                    buf.push(3);
+                                    encode_content(buf, decoded.as_ref());
+                                }
+                            }
                         }
                     }
                 }
             }
 
             TokenKind::CData => {
-                if depth > 0 {
+                if *depth > 0 {
                     if let Some(content) = token.content {
-                        if cdata_as_chars {
-                            buf.push(3);
-                        } else {
-                            buf.push(4);
+                        match chunk_size {
+                            Some(max) if content.len() > max => {
+                                let kind = if cdata_as_chars { 5 } else { 6 };
+                                encode_pieces(buf, kind, content.as_ref(), max, true);
+                            }
+                            _ => {
+                                buf.push(if cdata_as_chars { 3 } else { 4 });
+                                encode_content(buf, content.as_ref());
+                            }
                         }
-                        encode_content(&mut buf, content.as_ref());
                     }
                 }
             }
@@ -1181,10 +5054,154 @@ fn streaming_finalize_sax<'a>(
             _ => {}
         }
     }
+}
+
+/// Per-parser state threaded through `encode_sax_chunked`.
+struct ChunkState {
+    depth: u32,
+    in_cdata: bool,
+    in_text: bool,
+}
+
+/// Chunked variant of the streaming SAX encoder. Returns the number of bytes
+/// of `input` consumed; the rest must be kept as the tail for the next call.
+///
+/// On top of the regular boundary-based tokenization this handles the two
+/// places where a huge node would otherwise sit in the tail buffer: a CDATA
+/// section whose `]]>` has not arrived, and a text node with no `<` after it.
+/// Both are emitted as non-final pieces and continued on the next call.
+/// When `finalize` is set, open sections are closed with a final piece.
+fn encode_sax_chunked(
+    buf: &mut BinaryWriter,
+    input: &[u8],
+    state: &mut ChunkState,
+    cdata_as_chars: bool,
+    chunk_size: usize,
+    finalize: bool,
+    checks: &mut ElementChecks,
+) -> usize {
+    use core::entities::decode_text;
+    use memchr::{memchr, memmem};
+    use strategy::streaming::{
+        complete_text_len, find_safe_boundary, unterminated_section_start, utf8_complete_len,
+    };
+
+    let cdata_kind = if cdata_as_chars { 5 } else { 6 };
+    let mut pos = 0;
+
+    // 1. Continue an open CDATA section
+    if state.in_cdata {
+        match memmem::find(input, b"]]>") {
+            Some(end) => {
+                encode_pieces(buf, cdata_kind, &input[..end], chunk_size, true);
+                state.in_cdata = false;
+                pos = end + 3;
+            }
+            None if finalize => {
+                encode_pieces(buf, cdata_kind, input, chunk_size, true);
+                state.in_cdata = false;
+                return input.len();
+            }
+            None => {
+                // Hold back a trailing "]" or "]]" that may start the terminator
+                let held = input
+                    .iter()
+                    .rev()
+                    .take(2)
+                    .take_while(|&&b| b == b']')
+                    .count();
+                let ready = utf8_complete_len(&input[..input.len() - held]);
+                encode_pieces(buf, cdata_kind, &input[..ready], chunk_size, false);
+                return ready;
+            }
+        }
+    }
+
+    // 2. Continue an open text node
+    if state.in_text {
+        let rest = &input[pos..];
+        match memchr(b'<', rest) {
+            Some(lt) => {
+                let decoded = decode_text(&rest[..lt]);
+                encode_pieces(buf, 5, decoded.as_ref(), chunk_size, true);
+                state.in_text = false;
+                pos += lt;
+            }
+            None => {
+                let ready = if finalize {
+                    rest.len()
+                } else {
+                    complete_text_len(rest)
+                };
+                let decoded = decode_text(&rest[..ready]);
+                encode_pieces(buf, 5, decoded.as_ref(), chunk_size, finalize);
+                state.in_text = !finalize;
+                return pos + ready;
+            }
+        }
+    }
 
-    inner.depth = depth;
+    // 3. Regular tokenization up to the last safe boundary, stopping short of
+    //    a CDATA section, comment or PI that is still open
+    let rest = &input[pos..];
+    let mut boundary = if finalize {
+        rest.len()
+    } else {
+        find_safe_boundary(rest)
+    };
+    if !finalize {
+        if let Some(start) = unterminated_section_start(&rest[..boundary]) {
+            boundary = start;
+        }
+    }
+    encode_sax_tokens(
+        buf,
+        &rest[..boundary],
+        &mut state.depth,
+        cdata_as_chars,
+        Some(chunk_size),
+        checks,
+    );
+    pos += boundary;
+
+    if finalize || state.depth == 0 {
+        return pos;
+    }
 
-    buf.into_term(env)
+    // 4. Stream the start of an open CDATA section or a long open text node
+    let tail = &input[pos..];
+    match memchr(b'<', tail) {
+        Some(lt) if tail[lt..].starts_with(b"<![CDATA[") => {
+            if lt > 0 {
+                let decoded = decode_text(&tail[..lt]);
+                if decoded.len() > chunk_size {
+                    encode_pieces(buf, 5, decoded.as_ref(), chunk_size, true);
+                } else {
+                    buf.push(3);
+                    encode_content(buf, decoded.as_ref());
+                }
+            }
+            state.in_cdata = true;
+            let content = &tail[lt + 9..];
+            let held = content
+                .iter()
+                .rev()
+                .take(2)
+                .take_while(|&&b| b == b']')
+                .count();
+            let ready = utf8_complete_len(&content[..content.len() - held]);
+            encode_pieces(buf, cdata_kind, &content[..ready], chunk_size, false);
+            pos + lt + 9 + ready
+        }
+        None if tail.len() > chunk_size => {
+            let ready = complete_text_len(tail);
+            let decoded = decode_text(&tail[..ready]);
+            encode_pieces(buf, 5, decoded.as_ref(), chunk_size, false);
+            state.in_text = true;
+            pos + ready
+        }
+        _ => pos,
+    }
 }
 
 // --- BinaryWriter: write directly into OwnedBinary ---
@@ -1194,17 +5211,37 @@ fn streaming_finalize_sax<'a>(
 /// Events are encoded directly into the BEAM binary — no intermediate
 /// Rust Vec allocation. The binary is over-allocated with an estimate
 /// and trimmed to exact size before returning to the BEAM.
+///
+/// In the v2 format the writer also holds the name dictionary, so ids are
+/// only meaningful within the binary it produces.
 struct BinaryWriter {
     bin: rustler::OwnedBinary,
     pos: usize,
+    format: SaxBinaryFormat,
+    names: std::collections::HashMap<Box<[u8]>, u32>,
 }
 
 impl BinaryWriter {
     /// Allocate an OwnedBinary with `capacity` bytes.
-    fn new(capacity: usize) -> Result<Self, rustler::Error> {
+    fn new(capacity: usize, format: SaxBinaryFormat) -> Result<Self, rustler::Error> {
         let bin = rustler::OwnedBinary::new(capacity)
             .ok_or_else(|| rustler::Error::Term(Box::new("alloc_failed")))?;
-        Ok(BinaryWriter { bin, pos: 0 })
+        Ok(BinaryWriter {
+            bin,
+            pos: 0,
+            format,
+            names: std::collections::HashMap::new(),
+        })
+    }
+
+    /// Append an unsigned LEB128 varint.
+    #[inline]
+    fn push_varint(&mut self, mut value: usize) {
+        while value >= 0x80 {
+            self.push((value as u8 & 0x7F) | 0x80);
+            value >>= 7;
+        }
+        self.push(value as u8);
     }
 
     /// Ensure at least `additional` bytes are available, reallocating if needed.
@@ -1245,66 +5282,163 @@ impl BinaryWriter {
 
 // --- Binary encoding helpers ---
 
-/// Encode a name/short string: <<len::16, bytes>>
+/// Encode a short string: <<len::16, bytes>> (v1) or <<len::varint, bytes>> (v2)
 #[inline]
 fn encode_bytes(buf: &mut BinaryWriter, data: &[u8]) {
-    buf.extend(&(data.len() as u16).to_be_bytes());
+    match buf.format {
+        SaxBinaryFormat::V1 => buf.extend(&(data.len() as u16).to_be_bytes()),
+        SaxBinaryFormat::V2 => buf.push_varint(data.len()),
+    }
     buf.extend(data);
 }
 
-/// Encode text content: <<len::32, bytes>>
+/// Encode an attribute value: <<len::16, bytes>> (v1) or <<len::varint, bytes>> (v2)
+///
+/// A v1 value too long for 16 bits is written as <<0xFFFF::16, len::32, bytes>>.
 #[inline]
-fn encode_content(buf: &mut BinaryWriter, data: &[u8]) {
-    buf.extend(&(data.len() as u32).to_be_bytes());
-    buf.extend(data);
+fn encode_attr_value(buf: &mut BinaryWriter, value: &[u8]) {
+    if buf.format == SaxBinaryFormat::V1 && value.len() >= u16::MAX as usize {
+        buf.extend(&u16::MAX.to_be_bytes());
+        buf.extend(&(value.len() as u32).to_be_bytes());
+        buf.extend(value);
+        return;
+    }
+    encode_bytes(buf, value);
 }
 
-/// Encode attributes from a tag span into the buffer.
-fn encode_attrs(buf: &mut BinaryWriter, input: &[u8], span: (usize, usize)) {
-    use core::attributes::parse_attributes;
-
-    let (start, end) = span;
-    if end <= start || end > input.len() {
-        buf.extend(&0u16.to_be_bytes());
+/// Encode an element or attribute name; in v2, a reference into the
+/// writer's name dictionary, defining the name on first use
+#[inline]
+fn encode_name(buf: &mut BinaryWriter, name: &[u8]) {
+    if buf.format == SaxBinaryFormat::V1 {
+        return encode_bytes(buf, name);
+    }
+    if let Some(&id) = buf.names.get(name) {
+        buf.push_varint(id as usize);
         return;
     }
+    let id = buf.names.len() as u32 + 1;
+    buf.names.insert(name.into(), id);
+    buf.push(0);
+    encode_bytes(buf, name);
+}
 
-    let tag_content = &input[start..end];
+/// Encode an attribute count: <<count::16>> (v1) or <<count::varint>> (v2)
+#[inline]
+fn encode_count(buf: &mut BinaryWriter, count: usize) {
+    match buf.format {
+        SaxBinaryFormat::V1 => buf.extend(&(count as u16).to_be_bytes()),
+        SaxBinaryFormat::V2 => buf.push_varint(count),
+    }
+}
 
-    // Skip '<' and optional '/' or '?'
-    let mut pos = 1;
-    if tag_content.get(1) == Some(&b'/') || tag_content.get(1) == Some(&b'?') {
-        pos = 2;
+/// Encode text content: <<len::32, bytes>> (v1) or <<len::varint, bytes>> (v2)
+#[inline]
+fn encode_content(buf: &mut BinaryWriter, data: &[u8]) {
+    match buf.format {
+        SaxBinaryFormat::V1 => buf.extend(&(data.len() as u32).to_be_bytes()),
+        SaxBinaryFormat::V2 => buf.push_varint(data.len()),
     }
+    buf.extend(data);
+}
 
-    // Skip tag name
-    while pos < tag_content.len() {
-        let b = tag_content[pos];
-        if b == b' ' || b == b'\t' || b == b'\n' || b == b'\r' || b == b'>' || b == b'/' {
-            break;
+/// Encode content as chunk events: <<kind, final::8, len::32, bytes>>
+///
+/// Pieces are at most `chunk_size` bytes and split on UTF-8 boundaries.
+/// Only the last piece carries `final = 1`, and only when `last` is set.
+fn encode_pieces(buf: &mut BinaryWriter, kind: u8, data: &[u8], chunk_size: usize, last: bool) {
+    use strategy::streaming::utf8_split_point;
+
+    if data.is_empty() {
+        if last {
+            buf.push(kind);
+            buf.push(1);
+            encode_content(buf, data);
         }
-        pos += 1;
+        return;
     }
 
-    // Find end of attributes
-    let mut attr_end = tag_content.len();
-    if tag_content.ends_with(b"/>") || tag_content.ends_with(b"?>") {
-        attr_end -= 2;
-    } else if tag_content.ends_with(b">") {
-        attr_end -= 1;
+    let mut rest = data;
+    while !rest.is_empty() {
+        let (piece, remainder) = rest.split_at(utf8_split_point(rest, chunk_size));
+        buf.push(kind);
+        buf.push(u8::from(last && remainder.is_empty()));
+        encode_content(buf, piece);
+        rest = remainder;
     }
+}
 
-    if pos >= attr_end {
-        buf.extend(&0u16.to_be_bytes());
-        return;
+/// Encode attributes from a tag span into the buffer, followed by a
+/// duplicate_id event if `checks.ids` is set and an ID value repeats, and a
+/// limit_warning event for each limit in `checks.limits` the element breaks.
+///
+/// Returns false if the element broke a limit in `:error` mode. The tag is
+/// parsed here in one pass rather than taken from `UnifiedScanner`'s
+/// `(Span, Span)` list, whose lengths stop at 64 KiB; values must reach the
+/// BEAM whole.
+fn encode_attrs(
+    buf: &mut BinaryWriter,
+    input: &[u8],
+    span: (usize, usize),
+    name: &[u8],
+    checks: &mut ElementChecks,
+) -> bool {
+    use core::attributes::{parse_attributes, tag_attributes};
+
+    let (start, end) = span;
+    let mut attrs = Vec::new();
+    if start < end && end <= input.len() {
+        attrs = parse_attributes(tag_attributes(&input[start..end]));
     }
 
-    let attrs = parse_attributes(&tag_content[pos..attr_end]);
-    buf.extend(&(attrs.len() as u16).to_be_bytes());
+    let count = attrs.len();
+    if let Some(limits) = checks.limits.as_deref() {
+        attrs.truncate(limits.kept_attributes(count));
+    }
+    encode_count(buf, attrs.len());
     for attr in &attrs {
-        encode_bytes(buf, attr.name.as_ref());
-        encode_bytes(buf, attr.value.as_ref());
+        encode_name(buf, attr.name.as_ref());
+        encode_attr_value(buf, attr.value.as_ref());
+    }
+
+    if let Some(ids) = checks.ids.as_deref_mut() {
+        for attr in &attrs {
+            if !IdTracker::is_id_attribute(attr.name.as_ref()) {
+                continue;
+            }
+            let occurrence = ids.record(attr.value.as_ref());
+            if occurrence > 1 {
+                buf.push(7);
+                encode_bytes(buf, attr.value.as_ref());
+                match buf.format {
+                    SaxBinaryFormat::V1 => buf.extend(&occurrence.to_be_bytes()),
+                    SaxBinaryFormat::V2 => buf.push_varint(occurrence as usize),
+                }
+            }
+        }
+    }
+
+    let Some(limits) = checks.limits.as_deref_mut() else {
+        return true;
+    };
+    for breach in limits.record(count).into_iter().flatten() {
+        if limits.mode == LimitMode::Error {
+            return false;
+        }
+        buf.push(8);
+        buf.push(match breach.kind {
+            LimitKind::Attributes => 1,
+            LimitKind::Elements => 2,
+        });
+        encode_name(buf, name);
+        match buf.format {
+            SaxBinaryFormat::V1 => {
+                buf.extend(&(breach.count.min(u32::MAX as usize) as u32).to_be_bytes())
+            }
+            SaxBinaryFormat::V2 => buf.push_varint(breach.count),
+        }
     }
+    true
 }
 
 /// Return an empty BEAM binary.
@@ -4,7 +4,7 @@
 //! Input references are maintained directly in the output.
 
 use super::events::{EndElement, StartElement, XmlEvent};
-use crate::core::attributes::{parse_attributes, parse_attributes_strict};
+use crate::core::attributes::{parse_attributes, parse_attributes_strict, tag_attributes};
 use crate::core::tokenizer::{ParseError, Token, TokenKind, Tokenizer};
 
 /// Zero-copy XML reader from a byte slice
@@ -148,45 +148,7 @@ impl<'a> SliceReader<'a> {
         token: &Token<'a>,
     ) -> Vec<crate::core::attributes::Attribute<'a>> {
         let (start, end) = token.span;
-        let tag_content = &self.input[start..end];
-
-        // Find where the tag name ends (first whitespace after '<' and optional '/')
-        let mut pos = 1; // Skip '<'
-        if tag_content.get(1) == Some(&b'/') {
-            pos = 2; // Skip '</'
-        } else if tag_content.get(1) == Some(&b'?') {
-            pos = 2; // Skip '<?'
-        }
-
-        // Skip the tag name
-        while pos < tag_content.len() {
-            let b = tag_content[pos];
-            if b == b' '
-                || b == b'\t'
-                || b == b'\n'
-                || b == b'\r'
-                || b == b'>'
-                || b == b'/'
-                || b == b'?'
-            {
-                break;
-            }
-            pos += 1;
-        }
-
-        // Find end of attributes (before '>' or '/>' or '?>')
-        let mut attr_end = tag_content.len();
-        if tag_content.ends_with(b"/>") || tag_content.ends_with(b"?>") {
-            attr_end -= 2;
-        } else if tag_content.ends_with(b">") {
-            attr_end -= 1;
-        }
-
-        if pos >= attr_end {
-            return Vec::new();
-        }
-
-        let attr_content = &tag_content[pos..attr_end];
+        let attr_content = tag_attributes(&self.input[start..end]);
 
         if self.strict {
             match parse_attributes_strict(attr_content) {
@@ -0,0 +1,412 @@
+//! Redaction
+//!
+//! Replaces what XPath expressions select with a fixed string, splicing
+//! over the index's byte ranges so the rest of the document is copied as
+//! written:
+//!
+//! - elements: their content (child markup included); `<a/>` becomes
+//!   `<a>replacement</a>`
+//! - text nodes: the text; a CDATA section is replaced as a whole
+//! - attributes (`@name`, `@*`, `attribute::` steps, with or without
+//!   predicates): the value
+//!
+//! Attribute steps have no node ids in the evaluator, so redaction walks
+//! the parsed expression itself: each branch of a union is taken on its
+//! own, and an attribute step's predicates are evaluated from the owner
+//! element with the attribute's name and value filled in. Anything that
+//! can't be redacted this way (comments, processing instructions, the
+//! document node, positional attribute predicates, filters over attribute
+//! selections such as `(//@a)[1]`) fails the expression
+//! rather than being skipped. The replacement is escaped for where it
+//! lands. When selections nest, the outermost wins.
+
+use crate::dom::{DocumentAccess, NodeId, NodeKind};
+use crate::index::IndexedDocumentView;
+use crate::template::{escape_into, Context};
+use crate::xpath::axes::in_scope_namespaces;
+use crate::xpath::compiler::CompiledExpr;
+use crate::xpath::eval::evaluate_precompiled_from_node;
+use crate::xpath::parser::{parse, Axis, Expr, NodeTest, Step};
+use crate::xpath::{evaluate_precompiled, XPathValue};
+use std::ops::Range;
+
+/// A byte range to replace
+struct Splice {
+    range: Range<usize>,
+    context: Context,
+    /// Name span of a self-closing element whose `/>` the splice replaces, so
+    /// the replacement is written between new start and end tags
+    close: Option<Range<usize>>,
+}
+
+/// The document with everything `xpaths` select replaced by `replacement`
+///
+/// Fails with the first expression that doesn't compile, doesn't select
+/// nodes or selects something that can't be redacted, and the reason.
+pub fn redact(
+    view: &IndexedDocumentView,
+    xpaths: &[String],
+    replacement: &str,
+) -> Result<Vec<u8>, (String, String)> {
+    let mut splices: Vec<Splice> = Vec::new();
+    for xpath in xpaths {
+        let fail = |reason: String| (xpath.clone(), reason);
+        let expr = parse(xpath).map_err(fail)?;
+        select(view, &expr, &mut splices).map_err(fail)?;
+    }
+    splices
+        .sort_unstable_by_key(|splice| (splice.range.start, std::cmp::Reverse(splice.range.end)));
+
+    let input = view.input;
+    let Some(range) = view.outer_range(view.document_node_id()) else {
+        return Ok(Vec::new());
+    };
+    let mut output = Vec::with_capacity(range.len());
+    let mut copied = range.start;
+    for splice in splices {
+        // Inside (or the same as) a span already replaced
+        if splice.range.start < copied {
+            continue;
+        }
+        output.extend_from_slice(&input[copied..splice.range.start]);
+        match &splice.close {
+            Some(name) => {
+                output.push(b'>');
+                escape_into(&mut output, replacement, splice.context);
+                output.extend_from_slice(b"</");
+                output.extend_from_slice(&input[name.clone()]);
+                output.push(b'>');
+            }
+            None => escape_into(&mut output, replacement, splice.context),
+        }
+        copied = splice.range.end;
+    }
+    output.extend_from_slice(&input[copied..range.end]);
+    Ok(output)
+}
+
+/// Add the splices for what `expr` selects
+fn select(
+    view: &IndexedDocumentView,
+    expr: &Expr,
+    splices: &mut Vec<Splice>,
+) -> Result<(), String> {
+    match expr {
+        Expr::Union(left, right) => {
+            select(view, left, splices)?;
+            select(view, right, splices)
+        }
+        Expr::Path(base, step) if step.axis == Axis::Attribute => {
+            for owner in nodes(view, base)? {
+                attribute_splices(view, owner, step, splices)?;
+            }
+            Ok(())
+        }
+        Expr::Step(step) if step.axis == Axis::Attribute => {
+            for owner in nodes(view, &Expr::Context)? {
+                attribute_splices(view, owner, step, splices)?;
+            }
+            Ok(())
+        }
+        // The evaluator turns attributes into strings, which a filter
+        // can't select from
+        Expr::Filter(base, _) if selects_attributes(base) => {
+            Err("can't redact a filtered attribute selection".to_string())
+        }
+        _ => {
+            for node in nodes(view, expr)? {
+                splices.push(content_splice(view, node)?);
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Whether `expr` ends in an attribute step
+fn selects_attributes(expr: &Expr) -> bool {
+    match expr {
+        Expr::Path(_, step) | Expr::Step(step) => step.axis == Axis::Attribute,
+        Expr::Filter(base, _) => selects_attributes(base),
+        Expr::Union(left, right) => selects_attributes(left) || selects_attributes(right),
+        _ => false,
+    }
+}
+
+/// The node-set `expr` evaluates to, with the root element as context
+fn nodes(view: &IndexedDocumentView, expr: &Expr) -> Result<Vec<NodeId>, String> {
+    match evaluate_precompiled(view, &CompiledExpr::compile(expr))? {
+        XPathValue::NodeSet(nodes) => Ok(nodes),
+        _ => Err("expression must select nodes".to_string()),
+    }
+}
+
+fn content_splice(view: &IndexedDocumentView, node: NodeId) -> Result<Splice, String> {
+    let unredactable = |what: &str| format!("can't redact {what}");
+    let (range, close) = match view.node_kind_of(node) {
+        NodeKind::Element => {
+            let (_, idx) = crate::index::view::decode_node_id(node);
+            let element = view
+                .index
+                .get_element(idx)
+                .ok_or_else(|| unredactable("an element outside the index"))?;
+            let outer = view
+                .outer_range(node)
+                .ok_or_else(|| unredactable("an element outside the index"))?;
+            if element.is_empty() {
+                // `<a/>`, `<a />`: replace the `/>` with `>...</a>`
+                let slash = outer.end - 2;
+                let name = element.name.offset as usize;
+                (
+                    slash..outer.end,
+                    Some(name..name + element.name.len as usize),
+                )
+            } else {
+                let inner = view
+                    .inner_range(node)
+                    .ok_or_else(|| unredactable("an element outside the index"))?;
+                (inner, None)
+            }
+        }
+        NodeKind::Text => (
+            view.inner_range(node)
+                .ok_or_else(|| unredactable("a text node outside the index"))?,
+            None,
+        ),
+        NodeKind::CData => (
+            view.outer_range(node)
+                .ok_or_else(|| unredactable("a CDATA section outside the index"))?,
+            None,
+        ),
+        NodeKind::Document => return Err(unredactable("the document node")),
+        NodeKind::Comment => return Err(unredactable("comments")),
+        NodeKind::ProcessingInstruction => return Err(unredactable("processing instructions")),
+    };
+    Ok(Splice {
+        range,
+        context: Context::Text,
+        close,
+    })
+}
+
+/// Add a splice for each attribute of `owner` that `step` selects
+fn attribute_splices(
+    view: &IndexedDocumentView,
+    owner: NodeId,
+    step: &Step,
+    splices: &mut Vec<Splice>,
+) -> Result<(), String> {
+    if view.node_kind_of(owner) != NodeKind::Element {
+        return Ok(());
+    }
+    let (_, idx) = crate::index::view::decode_node_id(owner);
+    for attr in view.index.element_attributes(idx) {
+        let name = String::from_utf8_lossy(attr.name.slice(view.input));
+        if !attribute_matches(&name, &step.node_test) {
+            continue;
+        }
+        let value = crate::core::entities::decode_text(attr.value.slice(view.input));
+        let attribute = Attribute {
+            name: &name,
+            value: &String::from_utf8_lossy(&value),
+            owner,
+        };
+        let mut selected = true;
+        for predicate in &step.predicates {
+            let predicate = attribute.rewrite(view, predicate)?;
+            let compiled = CompiledExpr::compile(&predicate);
+            match evaluate_precompiled_from_node(view, owner, &compiled)? {
+                XPathValue::Number(_) => {
+                    return Err("can't redact attributes selected by position".to_string())
+                }
+                value => selected = value.to_boolean(),
+            }
+            if !selected {
+                break;
+            }
+        }
+        if selected {
+            let start = attr.value.offset as usize;
+            splices.push(Splice {
+                range: start..start + attr.value.len as usize,
+                context: Context::Attribute,
+                close: None,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Whether an attribute named `name` passes an attribute step's node test
+fn attribute_matches(name: &str, test: &NodeTest) -> bool {
+    let is_namespace_declaration = name == "xmlns" || name.starts_with("xmlns:");
+    match test {
+        NodeTest::Any | NodeTest::Node => !is_namespace_declaration,
+        NodeTest::Name(expected) => name == expected,
+        NodeTest::QName(prefix, local) => {
+            name.split_once(':') == Some((prefix.as_str(), local.as_str()))
+        }
+        NodeTest::NamespaceWildcard(prefix) => {
+            !is_namespace_declaration && name.split_once(':').is_some_and(|(p, _)| p == prefix)
+        }
+        _ => false,
+    }
+}
+
+/// An attribute standing in for the context node of its step's predicates
+struct Attribute<'a> {
+    name: &'a str,
+    value: &'a str,
+    owner: NodeId,
+}
+
+impl Attribute<'_> {
+    /// `predicate` rewritten to be evaluated from the owner element
+    ///
+    /// The attribute's string-value and name replace `.` and the
+    /// context-dependent functions, and `..` becomes the owner. Other
+    /// steps from the attribute, and `position()` and `last()`, aren't
+    /// supported and fail.
+    fn rewrite(&self, view: &IndexedDocumentView, predicate: &Expr) -> Result<Expr, String> {
+        let unsupported = || Err("unsupported predicate on an attribute step".to_string());
+        let value = || Expr::String(self.value.to_string());
+        Ok(match predicate {
+            Expr::Context => value(),
+            Expr::Parent => Expr::Context,
+            Expr::Step(step) => match (step.axis, &step.node_test) {
+                (Axis::Self_, NodeTest::Node) if step.predicates.is_empty() => value(),
+                (Axis::Parent, NodeTest::Node) if step.predicates.is_empty() => Expr::Context,
+                _ => return unsupported(),
+            },
+            // Steps after the first have their own context nodes
+            Expr::Path(base, step) => {
+                if !matches!(**base, Expr::Root | Expr::Parent | Expr::Path(..)) {
+                    return unsupported();
+                }
+                Expr::Path(Box::new(self.rewrite(view, base)?), step.clone())
+            }
+            Expr::Filter(base, filter) => {
+                Expr::Filter(Box::new(self.rewrite(view, base)?), filter.clone())
+            }
+            Expr::Function(name, args) if args.is_empty() => match name.as_str() {
+                "name" => Expr::String(self.name.to_string()),
+                "local-name" => {
+                    let local = self.name.split_once(':').map_or(self.name, |(_, l)| l);
+                    Expr::String(local.to_string())
+                }
+                "namespace-uri" => {
+                    let uri = self.name.split_once(':').and_then(|(prefix, _)| {
+                        in_scope_namespaces(view, self.owner)
+                            .into_iter()
+                            .find(|(bound, _)| *bound == prefix)
+                            .map(|(_, uri)| uri.to_string())
+                    });
+                    Expr::String(uri.unwrap_or_default())
+                }
+                "string" | "string-length" | "normalize-space" | "number" => {
+                    Expr::Function(name.clone(), vec![value()])
+                }
+                "position" | "last" => return unsupported(),
+                _ => predicate.clone(),
+            },
+            Expr::Function(name, args) => Expr::Function(
+                name.clone(),
+                args.iter()
+                    .map(|arg| self.rewrite(view, arg))
+                    .collect::<Result<_, _>>()?,
+            ),
+            Expr::Union(left, right) => Expr::Union(
+                Box::new(self.rewrite(view, left)?),
+                Box::new(self.rewrite(view, right)?),
+            ),
+            Expr::Binary(left, op, right) => Expr::Binary(
+                Box::new(self.rewrite(view, left)?),
+                *op,
+                Box::new(self.rewrite(view, right)?),
+            ),
+            Expr::Negate(inner) => Expr::Negate(Box::new(self.rewrite(view, inner)?)),
+            Expr::Root | Expr::Number(_) | Expr::String(_) | Expr::Variable(_) => predicate.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::builder::build_index;
+
+    fn redacted(xml: &str, xpaths: &[&str]) -> Result<String, (String, String)> {
+        let index = build_index(xml.as_bytes());
+        let view = IndexedDocumentView::new(&index, xml.as_bytes());
+        let xpaths: Vec<String> = xpaths.iter().map(|x| x.to_string()).collect();
+        let output = redact(&view, &xpaths, "<x>")?;
+        Ok(String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn test_redact_content_and_attributes() {
+        let xml = "<p ssn='1' xmlns:a='urn:a'><card>4111<b>1111</b></card><![CDATA[pin]]>\
+                   <note a:k=\"v\">keep</note><!-- c --></p>";
+        assert_eq!(
+            redacted(
+                xml,
+                &["//card", "//card/b", "/p/text()", "//@ssn", "//note/@*"]
+            )
+            .unwrap(),
+            "<p ssn='&lt;x&gt;' xmlns:a='urn:a'><card>&lt;x&gt;</card>&lt;x&gt;\
+             <note a:k=\"&lt;x&gt;\">keep</note><!-- c --></p>"
+        );
+        assert_eq!(
+            redacted("<r a='1'><b a='2'/></r>", &["@a", "/r//@a"]).unwrap(),
+            "<r a='&lt;x&gt;'><b a='&lt;x&gt;'/></r>"
+        );
+    }
+
+    #[test]
+    fn test_redact_attribute_predicates_and_axes() {
+        let xml = "<r card='4111' pw='s' e=''><a card='9'/></r>";
+        assert_eq!(
+            redacted(xml, &["//@card[. != '']"]).unwrap(),
+            "<r card='&lt;x&gt;' pw='s' e=''><a card='&lt;x&gt;'/></r>"
+        );
+        assert_eq!(
+            redacted(xml, &["//@*[name() = 'pw']"]).unwrap(),
+            "<r card='4111' pw='&lt;x&gt;' e=''><a card='9'/></r>"
+        );
+        assert_eq!(
+            redacted(xml, &["//r/attribute::card"]).unwrap(),
+            "<r card='&lt;x&gt;' pw='s' e=''><a card='9'/></r>"
+        );
+        assert_eq!(
+            redacted(xml, &["//@card[../self::a]", "//@*[string-length() > 3]"]).unwrap(),
+            "<r card='&lt;x&gt;' pw='s' e=''><a card='&lt;x&gt;'/></r>"
+        );
+    }
+
+    #[test]
+    fn test_redact_union_of_elements_and_attributes() {
+        assert_eq!(
+            redacted("<r card='1'><a>x</a></r>", &["//a | //@card"]).unwrap(),
+            "<r card='&lt;x&gt;'><a>&lt;x&gt;</a></r>"
+        );
+    }
+
+    #[test]
+    fn test_redact_self_closing_element() {
+        assert_eq!(
+            redacted("<r><ssn/><ssn a='1' /></r>", &["//ssn", "//ssn/@a"]).unwrap(),
+            "<r><ssn>&lt;x&gt;</ssn><ssn a='&lt;x&gt;' >&lt;x&gt;</ssn></r>"
+        );
+    }
+
+    #[test]
+    fn test_redact_reports_failing_expression() {
+        let err = redacted("<r/>", &["//r", "count(//r)"]).unwrap_err();
+        assert_eq!(err.0, "count(//r)");
+        assert!(redacted("<r/>", &["//r["]).is_err());
+        // Selections that can't be redacted fail instead of being skipped
+        assert!(redacted("<r><!-- c --></r>", &["//comment()"]).is_err());
+        assert!(redacted("<r a='1' b='2'/>", &["//@*[1]"]).is_err());
+        assert!(redacted("<r a='1'/>", &["//@a[position() = 1]"]).is_err());
+        assert!(redacted("<r a='1'/>", &["(//@a)[1]"]).is_err());
+    }
+}
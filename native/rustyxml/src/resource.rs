@@ -2,10 +2,26 @@
 //!
 //! Persistent state for streaming parsers and indexed documents.
 
-use crate::index::{IndexedDocumentView, StructuralIndex};
+use crate::core::normalize::Normalization;
+use crate::dom::{IncrementalValidator, NodeId, StreamingValidator};
+use crate::index::{
+    map_bytes, vec_bytes, AttributeValueIndex, IndexedDocumentView, PrecomputedText,
+    StructuralIndex, TextDecodeCache,
+};
+use crate::schematron::Schema;
+use crate::strategy::streaming::{BoundaryScan, IdTracker, OversizeMode, SaxLimits};
 use crate::strategy::StreamingParser;
-use rustler::{Encoder, Env, ResourceArc, Term};
-use std::sync::Mutex;
+use crate::xpath::functions::{string_units, StringUnits};
+use crate::xpath::{ResultOrder, XPathValue};
+use arc_swap::ArcSwapOption;
+use lru::LruCache;
+use rustler::env::{OwnedEnv, SavedTerm};
+use rustler::{Binary, Encoder, Env, ResourceArc, Term};
+use std::collections::HashMap;
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, OnceLock, PoisonError, RwLock, RwLockReadGuard};
+use std::time::Duration;
 
 // ============================================================================
 // Streaming SAX Parser Resource
@@ -20,9 +36,54 @@ use std::sync::Mutex;
 /// the unprocessed tail (typically ~100 bytes at a chunk boundary) is saved
 /// in `buffer`. The initial capacity is 1 KB, and it is shrunk after each
 /// call to avoid retaining excess memory.
+///
+/// With `text_chunk_size` set, text and CDATA content is emitted in pieces of
+/// at most that many bytes, and an unfinished text node or CDATA section is
+/// streamed as it arrives instead of being held in `buffer` until it closes.
+///
+/// `format` selects the wire format of the returned binaries (see
+/// `SaxBinaryFormat`).
+///
+/// `max_tail_size` bounds `buffer`: a tag that is still open after that many
+/// bytes (a crafted multi-megabyte start tag, say) fails the feed instead of
+/// being buffered until it closes. `limits` bounds attributes per element
+/// and elements per document, failing the feed or emitting warnings.
+///
+/// `boundary_scan` remembers how much of `buffer` has been searched for a
+/// safe boundary, so a tag arriving over many chunks is scanned once.
 pub struct StreamingSaxParser {
     pub buffer: Vec<u8>,
     pub depth: u32,
+    /// Max bytes per text/CDATA piece (None = emit whole nodes)
+    pub text_chunk_size: Option<usize>,
+    /// Inside a CDATA section whose `]]>` has not been seen yet
+    pub in_cdata: bool,
+    /// Inside a text node that is being emitted in pieces
+    pub in_text: bool,
+    /// Wire format of the event binaries
+    pub format: SaxBinaryFormat,
+    /// ID values seen so far, when reporting duplicate IDs
+    pub duplicate_ids: Option<IdTracker>,
+    /// Most bytes `buffer` may hold between calls (None = unlimited)
+    pub max_tail_size: Option<usize>,
+    /// Attribute and element count limits (None = unlimited)
+    pub limits: Option<SaxLimits>,
+    /// Progress of the safe-boundary search over `buffer`
+    pub boundary_scan: BoundaryScan,
+}
+
+/// Default `StreamingSaxParser::max_tail_size`
+pub const DEFAULT_MAX_TAIL_SIZE: usize = 64 * 1024 * 1024;
+
+/// Wire format of the event binaries returned by `streaming_feed_sax`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SaxBinaryFormat {
+    /// Fixed 16/32-bit big-endian lengths, names written in full
+    #[default]
+    V1,
+    /// Varint lengths; names are written once per call and then referenced
+    /// by id
+    V2,
 }
 
 impl StreamingSaxParser {
@@ -30,10 +91,56 @@ impl StreamingSaxParser {
         StreamingSaxParser {
             buffer: Vec::with_capacity(1024),
             depth: 0,
+            text_chunk_size: None,
+            in_cdata: false,
+            in_text: false,
+            format: SaxBinaryFormat::V1,
+            duplicate_ids: None,
+            max_tail_size: Some(DEFAULT_MAX_TAIL_SIZE),
+            limits: None,
+            boundary_scan: BoundaryScan::default(),
+        }
+    }
+
+    pub fn with_text_chunks(chunk_size: usize) -> Self {
+        StreamingSaxParser {
+            text_chunk_size: Some(chunk_size),
+            ..Self::new()
         }
     }
 }
 
+impl StreamingSaxParser {
+    /// Drop any buffered input and position, keeping the configuration
+    pub fn reset(&mut self) {
+        self.buffer.clear();
+        self.boundary_scan = BoundaryScan::default();
+        self.depth = 0;
+        self.in_cdata = false;
+        self.in_text = false;
+        if let Some(ids) = self.duplicate_ids.as_mut() {
+            *ids = IdTracker::default();
+        }
+        if let Some(limits) = self.limits.as_mut() {
+            limits.reset();
+        }
+    }
+
+    /// Bytes held as `(buffer, pending)`: the unprocessed tail, and the
+    /// ID table when duplicate detection is on
+    pub fn memory(&self) -> (usize, usize) {
+        let ids = self.duplicate_ids.as_ref().map_or(0, IdTracker::heap_bytes);
+        (vec_bytes(&self.buffer), ids)
+    }
+
+    /// `reset`, also freeing the buffer and ID table
+    pub fn release(&mut self) {
+        self.reset();
+        self.buffer = Vec::new();
+        self.duplicate_ids = None;
+    }
+}
+
 impl Default for StreamingSaxParser {
     fn default() -> Self {
         Self::new()
@@ -46,14 +153,35 @@ impl rustler::Resource for StreamingSaxParser {}
 /// Wrapper to hold StreamingSaxParser behind a Mutex for ResourceArc
 pub struct StreamingSaxParserResource {
     pub inner: Mutex<StreamingSaxParser>,
+    closed: AtomicBool,
 }
 
 impl StreamingSaxParserResource {
     pub fn new() -> Self {
         StreamingSaxParserResource {
             inner: Mutex::new(StreamingSaxParser::new()),
+            closed: AtomicBool::new(false),
         }
     }
+
+    pub fn with_text_chunks(chunk_size: usize) -> Self {
+        StreamingSaxParserResource {
+            inner: Mutex::new(StreamingSaxParser::with_text_chunks(chunk_size)),
+            closed: AtomicBool::new(false),
+        }
+    }
+}
+
+impl StreamingSaxParserResource {
+    /// Lock the parser (see `lock_parser`)
+    pub fn lock(&self) -> Result<MutexGuard<'_, StreamingSaxParser>, ParserUnavailable> {
+        lock_parser(&self.inner, &self.closed, StreamingSaxParser::reset)
+    }
+
+    /// Drop the parser's buffers; later calls fail with `Closed`
+    pub fn close(&self) {
+        close_parser(&self.inner, &self.closed, StreamingSaxParser::release);
+    }
 }
 
 impl Default for StreamingSaxParserResource {
@@ -68,6 +196,64 @@ impl rustler::Resource for StreamingSaxParserResource {}
 /// Type alias for streaming SAX parser ResourceArc
 pub type StreamingSaxParserRef = ResourceArc<StreamingSaxParserResource>;
 
+/// Why a streaming parser call can't proceed
+#[derive(Debug, PartialEq, Eq)]
+pub enum ParserUnavailable {
+    /// A panic poisoned the parser's mutex and the parser was reset
+    Reset,
+    /// `streaming_close` released the parser
+    Closed,
+}
+
+impl ParserUnavailable {
+    /// The reason returned to Elixir as `{:error, reason}`
+    pub fn reason(&self) -> rustler::Atom {
+        match self {
+            ParserUnavailable::Reset => crate::atoms::parser_reset(),
+            ParserUnavailable::Closed => crate::atoms::closed(),
+        }
+    }
+}
+
+impl From<ParserUnavailable> for rustler::Error {
+    fn from(unavailable: ParserUnavailable) -> Self {
+        rustler::Error::Term(Box::new(unavailable.reason()))
+    }
+}
+
+/// Lock a streaming parser's mutex
+///
+/// A panic while the lock was held (see `catch_panic` in lib.rs) leaves the
+/// parser mid-document, so rather than failing every later call the parser
+/// is reset to its configured starting state and the poison cleared. The
+/// call that finds the poison gets `Reset`; the next ones proceed.
+fn lock_parser<'a, T>(
+    mutex: &'a Mutex<T>,
+    closed: &AtomicBool,
+    reset: fn(&mut T),
+) -> Result<MutexGuard<'a, T>, ParserUnavailable> {
+    let guard = match mutex.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => {
+            reset(&mut poisoned.into_inner());
+            mutex.clear_poison();
+            return Err(ParserUnavailable::Reset);
+        }
+    };
+    if closed.load(Ordering::Acquire) {
+        return Err(ParserUnavailable::Closed);
+    }
+    Ok(guard)
+}
+
+fn close_parser<T>(mutex: &Mutex<T>, closed: &AtomicBool, release: fn(&mut T)) {
+    let mut guard = mutex.lock().unwrap_or_else(PoisonError::into_inner);
+    release(&mut guard);
+    closed.store(true, Ordering::Release);
+    drop(guard);
+    mutex.clear_poison();
+}
+
 // ============================================================================
 // Streaming Parser Resource
 // ============================================================================
@@ -75,20 +261,53 @@ pub type StreamingSaxParserRef = ResourceArc<StreamingSaxParserResource>;
 /// Wrapper for StreamingParser that can be stored in a ResourceArc
 pub struct StreamingParserResource {
     pub inner: Mutex<StreamingParser>,
+    closed: AtomicBool,
 }
 
 impl StreamingParserResource {
     pub fn new() -> Self {
         StreamingParserResource {
             inner: Mutex::new(StreamingParser::new()),
+            closed: AtomicBool::new(false),
         }
     }
 
     pub fn with_filter(tag: &[u8]) -> Self {
         StreamingParserResource {
             inner: Mutex::new(StreamingParser::with_filter(tag)),
+            closed: AtomicBool::new(false),
         }
     }
+
+    pub fn counting(tag: &[u8]) -> Self {
+        StreamingParserResource {
+            inner: Mutex::new(StreamingParser::counting(tag)),
+            closed: AtomicBool::new(false),
+        }
+    }
+
+    pub fn with_limit(tag: &[u8], max_element_size: usize, mode: OversizeMode) -> Self {
+        StreamingParserResource {
+            inner: Mutex::new(StreamingParser::with_filter_and_limit(
+                tag,
+                max_element_size,
+                mode,
+            )),
+            closed: AtomicBool::new(false),
+        }
+    }
+}
+
+impl StreamingParserResource {
+    /// Lock the parser (see `lock_parser`)
+    pub fn lock(&self) -> Result<MutexGuard<'_, StreamingParser>, ParserUnavailable> {
+        lock_parser(&self.inner, &self.closed, StreamingParser::reset)
+    }
+
+    /// Drop the parser's buffers; later calls fail with `Closed`
+    pub fn close(&self) {
+        close_parser(&self.inner, &self.closed, StreamingParser::release);
+    }
 }
 
 #[rustler::resource_impl]
@@ -103,10 +322,290 @@ impl Default for StreamingParserResource {
 /// Type alias for the ResourceArc
 pub type StreamingParserRef = ResourceArc<StreamingParserResource>;
 
+// ============================================================================
+// Streaming Validator Resource
+// ============================================================================
+
+/// Holds a StreamingValidator behind a Mutex for ResourceArc
+#[derive(Default)]
+pub struct StreamingValidatorResource {
+    inner: Mutex<StreamingValidator>,
+    closed: AtomicBool,
+}
+
+impl StreamingValidatorResource {
+    /// Lock the validator (see `lock_parser`)
+    pub fn lock(&self) -> Result<MutexGuard<'_, StreamingValidator>, ParserUnavailable> {
+        lock_parser(&self.inner, &self.closed, StreamingValidator::reset)
+    }
+
+    /// Drop the validator's buffer; later calls fail with `Closed`
+    pub fn close(&self) {
+        close_parser(&self.inner, &self.closed, StreamingValidator::reset);
+    }
+}
+
+#[rustler::resource_impl]
+impl rustler::Resource for StreamingValidatorResource {}
+
+pub type StreamingValidatorRef = ResourceArc<StreamingValidatorResource>;
+
 // ============================================================================
 // Structural Index Resource (main parse path)
 // ============================================================================
 
+/// Inputs at least this large keep a reference to the caller's binary
+/// instead of being copied into a Rust-owned buffer
+pub const RETAIN_BINARY_THRESHOLD: usize = 64 * 1024;
+
+/// A BEAM binary kept alive outside any process heap
+///
+/// The binary term is copied into a process-independent env. For refc
+/// binaries (everything above 64 bytes) that only bumps the reference count,
+/// so the bytes stay shared with the caller rather than duplicated, and the
+/// data pointer remains valid for as long as `_env` lives.
+pub struct RetainedBinary {
+    ptr: *const u8,
+    len: usize,
+    _term: SavedTerm,
+    _env: OwnedEnv,
+}
+
+// The retained bytes are immutable, and the env and saved term are never
+// touched again after construction — they only keep the binary alive until
+// the resource is dropped.
+unsafe impl Send for RetainedBinary {}
+unsafe impl Sync for RetainedBinary {}
+
+impl RetainedBinary {
+    /// Retain `binary`, exposing the bytes from `offset` onwards
+    pub fn new(binary: Binary, offset: usize) -> Self {
+        let env = OwnedEnv::new();
+        let term = env.save(binary);
+        // Take the pointer from the copy in the owned env: small heap
+        // binaries are copied there and may move on the caller's heap.
+        let (ptr, len) = env.run(|env| match term.load(env).decode::<Binary>() {
+            Ok(saved) => {
+                let bytes = saved.as_slice().get(offset..).unwrap_or(&[]);
+                (bytes.as_ptr(), bytes.len())
+            }
+            Err(_) => (std::ptr::NonNull::<u8>::dangling().as_ptr() as *const u8, 0),
+        });
+        Self {
+            ptr,
+            len,
+            _term: term,
+            _env: env,
+        }
+    }
+
+    #[inline]
+    pub fn as_slice(&self) -> &[u8] {
+        // Valid for the lifetime of `self`: see the struct docs
+        unsafe { std::slice::from_raw_parts(self.ptr, self.len) }
+    }
+}
+
+/// Per-document LRU cache of node-set results, keyed by XPath string
+///
+/// Disabled until `set_capacity` is called with a non-zero capacity; while
+/// disabled, lookups only read `enabled` and never take the lock. Only
+/// node-sets are cached — scalar results are cheap to recompute from them.
+/// Documents are immutable, so entries stay valid until cleared, except that
+/// predicates using `substring()` or `string-length()` depend on the global
+/// string units: each entry records the units it was computed under, and is
+/// a miss under any other.
+#[derive(Default)]
+pub struct ResultCache {
+    enabled: AtomicBool,
+    entries: Mutex<Option<LruCache<String, CachedNodes>>>,
+}
+
+/// A cached node-set and the string units it was computed under
+type CachedNodes = (StringUnits, Arc<[NodeId]>);
+
+impl ResultCache {
+    /// Bytes held by cached queries and their node ids
+    pub fn heap_bytes(&self) -> usize {
+        let entries = self.entries.lock().unwrap_or_else(PoisonError::into_inner);
+        let Some(entries) = entries.as_ref() else {
+            return 0;
+        };
+        entries
+            .iter()
+            .map(|(query, (_, nodes))| {
+                std::mem::size_of::<(String, CachedNodes)>()
+                    + query.capacity()
+                    + std::mem::size_of_val(&**nodes)
+            })
+            .sum()
+    }
+
+    /// Set the capacity, dropping existing entries (0 disables the cache)
+    pub fn set_capacity(&self, capacity: usize) -> bool {
+        let Ok(mut guard) = self.entries.lock() else {
+            return false;
+        };
+        *guard = NonZeroUsize::new(capacity).map(LruCache::new);
+        self.enabled.store(guard.is_some(), Ordering::Release);
+        true
+    }
+
+    /// Drop all cached entries, keeping the capacity
+    pub fn clear(&self) -> bool {
+        match self.entries.lock() {
+            Ok(mut guard) => {
+                if let Some(cache) = guard.as_mut() {
+                    cache.clear();
+                }
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// Number of cached entries
+    pub fn len(&self) -> usize {
+        match self.entries.lock() {
+            Ok(guard) => guard.as_ref().map_or(0, LruCache::len),
+            Err(_) => 0,
+        }
+    }
+
+    #[inline]
+    fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Acquire)
+    }
+
+    fn get(&self, xpath: &str) -> Option<Arc<[NodeId]>> {
+        let mut guard = self.entries.lock().ok()?;
+        match guard.as_mut()?.get(xpath) {
+            Some((units, nodes)) if *units == string_units() => Some(nodes.clone()),
+            _ => None,
+        }
+    }
+
+    /// Cache `nodes`, found with string units `units`
+    fn put(&self, xpath: &str, units: StringUnits, nodes: &[NodeId]) {
+        // A poisoned lock just means this result isn't cached
+        if let Ok(mut guard) = self.entries.lock() {
+            if let Some(cache) = guard.as_mut() {
+                cache.put(xpath.to_string(), (units, Arc::from(nodes)));
+            }
+        }
+    }
+}
+
+/// Per-document attribute value indexes, keyed by attribute name
+///
+/// Empty until `build` is called; while empty, queries only read `enabled`
+/// and never take the lock. Queries hold the read lock while evaluating, so
+/// they run concurrently with each other and only wait for a build in
+/// progress to be inserted.
+#[derive(Default)]
+pub struct AttributeIndexes {
+    enabled: AtomicBool,
+    indexes: RwLock<HashMap<String, AttributeValueIndex>>,
+}
+
+impl AttributeIndexes {
+    /// Bytes held by the built indexes
+    pub fn heap_bytes(&self) -> usize {
+        let indexes = self.indexes.read().unwrap_or_else(PoisonError::into_inner);
+        map_bytes(&indexes)
+            + indexes
+                .iter()
+                .map(|(name, index)| name.capacity() + index.heap_bytes())
+                .sum::<usize>()
+    }
+
+    /// Build (or rebuild) the index for attribute `name`
+    pub fn build(&self, index: &StructuralIndex, input: &[u8], name: &str) -> bool {
+        // Scan outside the lock so queries keep running meanwhile
+        let built = AttributeValueIndex::build(index, input, name);
+        let Ok(mut guard) = self.indexes.write() else {
+            return false;
+        };
+        guard.insert(name.to_string(), built);
+        self.enabled.store(true, Ordering::Release);
+        true
+    }
+
+    /// Names of the attributes with an index
+    pub fn names(&self) -> Vec<String> {
+        match self.indexes.read() {
+            Ok(guard) => guard.keys().cloned().collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    #[inline]
+    fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Acquire)
+    }
+}
+
+/// Backing storage for a document's input bytes
+enum InputBytes {
+    /// Copied into Rust (small inputs, or converted from UTF-16)
+    Owned(Vec<u8>),
+    /// Shared with the caller's BEAM binary (large UTF-8 inputs)
+    Retained(RetainedBinary),
+}
+
+/// Heap bytes held by a document (see `IndexedDocumentResource::memory`)
+///
+/// `input` counts a retained BEAM binary too, though it is shared with the
+/// caller's term rather than allocated by the NIF.
+pub struct DocumentMemory {
+    pub input: usize,
+    pub index: usize,
+    pub result_cache: usize,
+    pub attr_indexes: usize,
+    pub decoded_text: usize,
+    pub precomputed_text: usize,
+}
+
+impl DocumentMemory {
+    pub fn total(&self) -> usize {
+        self.input
+            + self.index
+            + self.result_cache
+            + self.attr_indexes
+            + self.decoded_text
+            + self.precomputed_text
+    }
+}
+
+/// Where `IndexedDocumentResource::merge` places the spliced node
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MergePosition {
+    Before,
+    After,
+    FirstChild,
+    LastChild,
+    Replace,
+}
+
+/// `xmlns` / `xmlns:*` declarations in scope at an element, nearest first
+/// (each name once, as the nearest declaration shadows the rest)
+fn namespace_scope<D: crate::dom::DocumentAccess>(doc: &D, node: NodeId) -> Vec<(&str, &str)> {
+    use crate::dom::NodeKind;
+
+    let mut scope: Vec<(&str, &str)> = Vec::new();
+    let mut current = Some(node);
+    while let Some(id) = current.filter(|&id| doc.node_kind_of(id) == NodeKind::Element) {
+        for (name, value) in doc.get_attribute_values(id) {
+            let declaration = name == "xmlns" || name.starts_with("xmlns:");
+            if declaration && !scope.iter().any(|&(seen, _)| seen == name) {
+                scope.push((name, value));
+            }
+        }
+        current = doc.parent_of(id);
+    }
+    scope
+}
+
 /// Structural Index document resource
 ///
 /// This is the DEFAULT parse path. Stores:
@@ -114,18 +613,67 @@ pub type StreamingParserRef = ResourceArc<StreamingParserResource>;
 /// - The input bytes (for string extraction)
 ///
 /// Memory efficient: index is ~3x input size, strings are slices not copies.
+/// Inputs of `RETAIN_BINARY_THRESHOLD` bytes or more reference the original
+/// BEAM binary instead of holding a second copy of it.
+///
+/// Immutable after construction, so every query path reads it through a
+/// shared `&self` with no locking; any number of BEAM processes (and dirty
+/// scheduler threads) can query one `ResourceArc` concurrently. The only
+/// locks are inside `result_cache`, `attr_indexes` and `decoded_text`;
+/// the first two are skipped while unused, and the last is only touched by
+/// text containing entity references.
 pub struct IndexedDocumentResource {
     /// Structural index (offsets into input)
     pub index: StructuralIndex,
     /// Original input bytes (kept for string extraction)
-    input: Vec<u8>,
+    input: InputBytes,
+    /// Node-set results of previous queries (disabled by default)
+    pub result_cache: ResultCache,
+    /// Attribute value indexes used by `[@name = 'literal']` predicates
+    pub attr_indexes: AttributeIndexes,
+    /// `Normalization` of node string-values, as `Normalization::to_u8`
+    normalization: AtomicU8,
+    /// Entity-decoded text nodes, filled in as queries reach them
+    pub decoded_text: TextDecodeCache,
+    /// Element string-values, built once by `precompute_text`
+    precomputed_text: OnceLock<PrecomputedText>,
+    /// Set once the document is handed out by the shared document cache
+    shared: AtomicBool,
 }
 
 impl IndexedDocumentResource {
     /// Create a new index from input binary
     pub fn new(input: Vec<u8>) -> Self {
-        let index = crate::index::builder::build_index(&input);
-        Self { index, input }
+        Self::build_owned(input, crate::index::builder::build_index)
+    }
+
+    /// Create from input that may be cut off (see `builder::build_salvage_index`)
+    pub fn new_salvage(input: Vec<u8>) -> Self {
+        Self::build_owned(input, crate::index::builder::build_salvage_index)
+    }
+
+    fn build_owned(input: Vec<u8>, build: fn(&[u8]) -> StructuralIndex) -> Self {
+        let index = build(&input);
+        Self::with_index(index, InputBytes::Owned(input))
+    }
+
+    /// Create from `builder::wrap_fragment` output
+    pub fn new_fragment(wrapped: Vec<u8>) -> Self {
+        let index = crate::index::builder::build_fragment_index(&wrapped);
+        Self::with_index(index, InputBytes::Owned(wrapped))
+    }
+
+    fn with_index(index: StructuralIndex, input: InputBytes) -> Self {
+        Self {
+            index,
+            input,
+            result_cache: ResultCache::default(),
+            attr_indexes: AttributeIndexes::default(),
+            normalization: AtomicU8::new(0),
+            decoded_text: TextDecodeCache::default(),
+            precomputed_text: OnceLock::new(),
+            shared: AtomicBool::new(false),
+        }
     }
 
     /// Create from borrowed input (copies the input)
@@ -134,17 +682,367 @@ impl IndexedDocumentResource {
         Self::new(input.to_vec())
     }
 
+    /// Create from a BEAM binary, indexing the bytes from `offset` onwards
+    ///
+    /// Large binaries are retained rather than copied; small ones are copied,
+    /// which is cheaper than setting up a process-independent env.
+    pub fn from_binary(binary: Binary, offset: usize) -> Self {
+        Self::build_binary(binary, offset, crate::index::builder::build_index)
+    }
+
+    /// Like `from_binary`, for input that may be cut off
+    pub fn salvage_binary(binary: Binary, offset: usize) -> Self {
+        Self::build_binary(binary, offset, crate::index::builder::build_salvage_index)
+    }
+
+    fn build_binary(binary: Binary, offset: usize, build: fn(&[u8]) -> StructuralIndex) -> Self {
+        let bytes = binary.as_slice().get(offset..).unwrap_or(&[]);
+        if bytes.len() < RETAIN_BINARY_THRESHOLD {
+            return Self::build_owned(bytes.to_vec(), build);
+        }
+        let retained = RetainedBinary::new(binary, offset);
+        let index = build(retained.as_slice());
+        Self::with_index(index, InputBytes::Retained(retained))
+    }
+
+    /// Create from an index already built over a retained binary's bytes
+    pub fn from_retained(index: StructuralIndex, input: RetainedBinary) -> Self {
+        Self::with_index(index, InputBytes::Retained(input))
+    }
+
+    /// Create from an index already built over `input`
+    pub fn from_owned(index: StructuralIndex, input: Vec<u8>) -> Self {
+        Self::with_index(index, InputBytes::Owned(input))
+    }
+
     /// Get reference to stored input
     #[inline]
-    #[allow(dead_code)]
     pub fn input(&self) -> &[u8] {
-        &self.input
+        match &self.input {
+            InputBytes::Owned(bytes) => bytes,
+            InputBytes::Retained(binary) => binary.as_slice(),
+        }
+    }
+
+    /// Bytes held by the document, by part
+    pub fn memory(&self) -> DocumentMemory {
+        DocumentMemory {
+            input: match &self.input {
+                InputBytes::Owned(bytes) => bytes.capacity(),
+                InputBytes::Retained(binary) => binary.as_slice().len(),
+            },
+            index: self.index.heap_bytes(),
+            result_cache: self.result_cache.heap_bytes(),
+            attr_indexes: self.attr_indexes.heap_bytes(),
+            decoded_text: self.decoded_text.heap_bytes(),
+            precomputed_text: self
+                .precomputed_text
+                .get()
+                .map_or(0, PrecomputedText::heap_bytes),
+        }
     }
 
     /// Get a view into the document for XPath evaluation
     #[inline]
     pub fn as_view(&self) -> IndexedDocumentView<'_> {
-        IndexedDocumentView::new(&self.index, &self.input)
+        let view = IndexedDocumentView::new(&self.index, self.input())
+            .with_normalization(self.normalization())
+            .with_decode_cache(&self.decoded_text);
+        match self.precomputed_text.get() {
+            Some(text) => view.with_precomputed_text(text),
+            None => view,
+        }
+    }
+
+    /// Lay out every element's string-value so later queries slice it
+    /// instead of walking descendants. Returns false if already done.
+    pub fn precompute_text(&self) -> bool {
+        if self.precomputed_text.get().is_some() {
+            return false;
+        }
+        let text = PrecomputedText::build(&self.index, self.input());
+        self.precomputed_text.set(text).is_ok()
+    }
+
+    /// Normalization form of node string-values
+    pub fn normalization(&self) -> Normalization {
+        Normalization::from_u8(self.normalization.load(Ordering::Relaxed))
+    }
+
+    /// Mark the document as shared between callers that don't know of
+    /// each other, freezing its settings
+    pub fn mark_shared(&self) {
+        self.shared.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether `mark_shared` was called
+    pub fn is_shared(&self) -> bool {
+        self.shared.load(Ordering::Relaxed)
+    }
+
+    /// Change the normalization form of node string-values
+    ///
+    /// Predicates compare string-values, so cached results are dropped.
+    pub fn set_normalization(&self, normalization: Normalization) {
+        let previous = self
+            .normalization
+            .swap(normalization.to_u8(), Ordering::Relaxed);
+        if previous != normalization.to_u8() {
+            self.result_cache.clear();
+        }
+    }
+
+    /// Copy an element's subtree into a new document with its own index
+    ///
+    /// Namespace declarations in scope from ancestors (and not redeclared
+    /// on the element) are added to the new root's start tag, so prefixes
+    /// keep resolving. The normalization form carries over; caches and
+    /// attribute indexes do not. Returns None for anything but an element.
+    pub fn extract_subtree(&self, node: NodeId) -> Option<Self> {
+        use crate::dom::{DocumentAccess, NodeKind};
+
+        if self.as_view().node_kind_of(node) != NodeKind::Element {
+            return None;
+        }
+        let extracted = Self::new(self.subtree_markup(node, false)?);
+        extracted.set_normalization(self.normalization());
+        Some(extracted)
+    }
+
+    /// Build a new document by splicing a node of `source` into this one
+    ///
+    /// The source node's markup is copied as it appears in its document
+    /// (carrying inherited namespace declarations, as `extract_subtree`
+    /// does) and placed relative to `target`; the rest of this document's
+    /// bytes are kept as they are. A spliced element that was in no default
+    /// namespace gets `xmlns=""` if the insertion point has one. Returns
+    /// None for unknown nodes, a document node, a child position on a
+    /// target that isn't an element, or a splice outside the root element
+    /// that would leave the document without exactly one root (only
+    /// comments and processing instructions go before or after the root,
+    /// and only an element replaces it).
+    pub fn merge(
+        &self,
+        target: NodeId,
+        source: &Self,
+        source_node: NodeId,
+        position: MergePosition,
+    ) -> Option<Self> {
+        use crate::dom::{DocumentAccess, NodeKind};
+
+        let view = self.as_view();
+        let input = self.input();
+        let as_child = matches!(
+            position,
+            MergePosition::FirstChild | MergePosition::LastChild
+        );
+        match view.node_kind_of(target) {
+            NodeKind::Document => return None,
+            NodeKind::Element => {}
+            _ if as_child => return None,
+            _ => {}
+        }
+        let outer = view.outer_range(target)?;
+
+        let parent = if as_child {
+            target
+        } else {
+            view.parent_of(target)?
+        };
+        if view.node_kind_of(parent) == NodeKind::Document {
+            let target_kind = view.node_kind_of(target);
+            let one_root = match source.as_view().node_kind_of(source_node) {
+                NodeKind::Comment | NodeKind::ProcessingInstruction => {
+                    position != MergePosition::Replace || target_kind != NodeKind::Element
+                }
+                NodeKind::Element => {
+                    position == MergePosition::Replace && target_kind == NodeKind::Element
+                }
+                _ => false,
+            };
+            if !one_root {
+                return None;
+            }
+        }
+        let default_namespace = view.node_kind_of(parent) == NodeKind::Element
+            && namespace_scope(&view, parent)
+                .iter()
+                .any(|&(name, uri)| name == "xmlns" && !uri.is_empty());
+        let markup = source.subtree_markup(source_node, default_namespace)?;
+
+        let mut bytes = Vec::with_capacity(input.len() + markup.len() + 3);
+        match position {
+            MergePosition::Before | MergePosition::After | MergePosition::Replace => {
+                let (before, after) = match position {
+                    MergePosition::Before => (outer.start, outer.start),
+                    MergePosition::After => (outer.end, outer.end),
+                    _ => (outer.start, outer.end),
+                };
+                bytes.extend_from_slice(&input[..before]);
+                bytes.extend_from_slice(&markup);
+                bytes.extend_from_slice(&input[after..]);
+            }
+            _ if input[..outer.end].ends_with(b"/>") => {
+                // `<a/>` becomes `<a>...</a>`
+                bytes.extend_from_slice(&input[..outer.end - 2]);
+                bytes.push(b'>');
+                bytes.extend_from_slice(&markup);
+                bytes.extend_from_slice(b"</");
+                bytes.extend_from_slice(view.node_name(target)?.as_bytes());
+                bytes.push(b'>');
+                bytes.extend_from_slice(&input[outer.end..]);
+            }
+            _ => {
+                let inner = view.inner_range(target)?;
+                let at = if position == MergePosition::FirstChild {
+                    inner.start
+                } else {
+                    inner.end
+                };
+                bytes.extend_from_slice(&input[..at]);
+                bytes.extend_from_slice(&markup);
+                bytes.extend_from_slice(&input[at..]);
+            }
+        }
+
+        let merged = Self::new(bytes);
+        merged.set_normalization(self.normalization());
+        Some(merged)
+    }
+
+    /// A node's markup, standalone: an element's start tag gains the
+    /// namespace declarations it inherits (plus `xmlns=""` if
+    /// `undeclare_default` and it is in no default namespace)
+    fn subtree_markup(&self, node: NodeId, undeclare_default: bool) -> Option<Vec<u8>> {
+        use crate::dom::{DocumentAccess, NodeKind};
+
+        let view = self.as_view();
+        let source = &self.input()[view.outer_range(node)?];
+        match view.node_kind_of(node) {
+            NodeKind::Document => return None,
+            NodeKind::Element => {}
+            _ => return Some(source.to_vec()),
+        }
+        let name_end = 1 + view.node_name(node)?.len();
+
+        let own = view.get_attribute_values(node);
+        let scope = namespace_scope(&view, node);
+        let mut inherited: Vec<(&str, &str)> = scope
+            .iter()
+            .copied()
+            .filter(|&(name, _)| !own.iter().any(|&(own_name, _)| own_name == name))
+            .collect();
+        if undeclare_default
+            && !scope
+                .iter()
+                .any(|&(name, uri)| name == "xmlns" && !uri.is_empty())
+        {
+            inherited.retain(|&(name, _)| name != "xmlns");
+            inherited.push(("xmlns", ""));
+        }
+
+        let extra: usize = inherited.iter().map(|(n, v)| n.len() + v.len() + 4).sum();
+        let mut bytes = Vec::with_capacity(source.len() + extra);
+        bytes.extend_from_slice(&source[..name_end]);
+        for (name, value) in inherited {
+            // Values are raw source text, so only the other quote can appear
+            let quote = if value.contains('"') { b'\'' } else { b'"' };
+            bytes.push(b' ');
+            bytes.extend_from_slice(name.as_bytes());
+            bytes.extend_from_slice(&[b'=', quote]);
+            bytes.extend_from_slice(value.as_bytes());
+            bytes.push(quote);
+        }
+        bytes.extend_from_slice(&source[name_end..]);
+        Some(bytes)
+    }
+
+    /// Build an attribute value index for `name`
+    pub fn build_attr_index(&self, name: &str) -> bool {
+        self.attr_indexes.build(&self.index, self.input(), name)
+    }
+
+    /// Evaluate an XPath expression, using the result cache and attribute
+    /// indexes if enabled
+    pub fn evaluate(&self, xpath: &str) -> Result<XPathValue, String> {
+        if !self.result_cache.is_enabled() {
+            return self.evaluate_uncached(xpath, ResultOrder::Document);
+        }
+        if let Some(nodes) = self.cached(xpath) {
+            return Ok(XPathValue::NodeSet(nodes.to_vec()));
+        }
+        let units = string_units();
+        let value = self.evaluate_uncached(xpath, ResultOrder::Document)?;
+        if let XPathValue::NodeSet(nodes) = &value {
+            self.result_cache.put(xpath, units, nodes);
+        }
+        Ok(value)
+    }
+
+    /// `evaluate`, returning a node-set result in `order`
+    ///
+    /// Cached results are in document order, so as-matched results are
+    /// always evaluated afresh.
+    pub fn evaluate_ordered(&self, xpath: &str, order: ResultOrder) -> Result<XPathValue, String> {
+        match order {
+            ResultOrder::Document => self.evaluate(xpath),
+            ResultOrder::Reverse => {
+                let mut value = self.evaluate(xpath)?;
+                if let XPathValue::NodeSet(nodes) = &mut value {
+                    nodes.reverse();
+                }
+                Ok(value)
+            }
+            ResultOrder::Matched => self.evaluate_uncached(xpath, order),
+        }
+    }
+
+    /// The first node `xpath` selects, or its value if it isn't a node-set
+    ///
+    /// Answered from the result cache when it holds the query; otherwise
+    /// evaluation stops at the first match where it can (see
+    /// `xpath::evaluate_first`) and nothing is cached.
+    pub fn evaluate_first(&self, xpath: &str) -> Result<XPathValue, String> {
+        if let Some(nodes) = self.cached(xpath) {
+            return Ok(XPathValue::NodeSet(
+                nodes.first().copied().into_iter().collect(),
+            ));
+        }
+        self.query(|view| crate::xpath::evaluate_first(view, xpath))
+    }
+
+    /// Whether `xpath` selects anything; see `xpath::evaluate_exists`
+    pub fn evaluate_exists(&self, xpath: &str) -> Result<bool, String> {
+        if let Some(nodes) = self.cached(xpath) {
+            return Ok(!nodes.is_empty());
+        }
+        self.query(|view| crate::xpath::evaluate_exists(view, xpath))
+    }
+
+    fn cached(&self, xpath: &str) -> Option<Arc<[NodeId]>> {
+        if !self.result_cache.is_enabled() {
+            return None;
+        }
+        let nodes = self.result_cache.get(xpath)?;
+        crate::stats::record_xpath_query();
+        crate::stats::record_cache_hit();
+        Some(nodes)
+    }
+
+    fn evaluate_uncached(&self, xpath: &str, order: ResultOrder) -> Result<XPathValue, String> {
+        self.query(|view| crate::xpath::eval::evaluate_ordered(view, xpath, order))
+    }
+
+    /// Run a query against a view using the attribute indexes, if any
+    fn query<T>(&self, run: impl FnOnce(&IndexedDocumentView) -> T) -> T {
+        if !self.attr_indexes.is_enabled() {
+            return run(&self.as_view());
+        }
+        // A poisoned lock only costs the index, not the query
+        match self.attr_indexes.indexes.read() {
+            Ok(indexes) => run(&self.as_view().with_attr_indexes(&indexes)),
+            Err(_) => run(&self.as_view()),
+        }
     }
 }
 
@@ -154,14 +1052,14 @@ impl IndexedDocumentResource {
     /// Get element name
     pub fn element_name_term<'a>(&self, env: Env<'a>, elem_idx: u32) -> Option<Term<'a>> {
         let elem = self.index.get_element(elem_idx)?;
-        let slice = elem.name.slice(&self.input);
+        let slice = elem.name.slice(self.input());
         Some(crate::term::bytes_to_binary(env, slice))
     }
 
     /// Get element name as string
     pub fn element_name_str(&self, elem_idx: u32) -> Option<&str> {
         let elem = self.index.get_element(elem_idx)?;
-        elem.name.as_str(&self.input)
+        elem.name.as_str(self.input())
     }
 
     /// Get attribute value by name
@@ -175,8 +1073,8 @@ impl IndexedDocumentResource {
         let name_bytes = name.as_bytes();
 
         for attr in attrs {
-            if attr.name.slice(&self.input) == name_bytes {
-                let raw = attr.value.slice(&self.input);
+            if attr.name.slice(self.input()) == name_bytes {
+                let raw = attr.value.slice(self.input());
                 let decoded = crate::core::entities::decode_text(raw);
                 match decoded {
                     std::borrow::Cow::Borrowed(b) => {
@@ -197,8 +1095,8 @@ impl IndexedDocumentResource {
         let name_bytes = name.as_bytes();
 
         for attr in attrs {
-            if attr.name.slice(&self.input) == name_bytes {
-                let raw = attr.value.slice(&self.input);
+            if attr.name.slice(self.input()) == name_bytes {
+                let raw = attr.value.slice(self.input());
                 let decoded = crate::core::entities::decode_text(raw);
                 return Some(String::from_utf8_lossy(&decoded).into_owned());
             }
@@ -212,8 +1110,8 @@ impl IndexedDocumentResource {
 
         let mut list = Term::list_new_empty(env);
         for attr in attrs.iter().rev() {
-            let name = crate::term::bytes_to_binary(env, attr.name.slice(&self.input));
-            let raw = attr.value.slice(&self.input);
+            let name = crate::term::bytes_to_binary(env, attr.name.slice(self.input()));
+            let raw = attr.value.slice(self.input());
             let decoded = crate::core::entities::decode_text(raw);
             let value = match decoded {
                 std::borrow::Cow::Borrowed(b) => crate::term::bytes_to_binary(env, b),
@@ -228,7 +1126,7 @@ impl IndexedDocumentResource {
     /// Get text content by index
     pub fn text_to_term<'a>(&self, env: Env<'a>, text_idx: u32) -> Option<Term<'a>> {
         let text = self.index.get_text(text_idx)?;
-        let raw = text.span.slice(&self.input);
+        let raw = text.span.slice(self.input());
 
         if text.needs_decode() {
             let decoded = crate::core::entities::decode_text(raw);
@@ -244,7 +1142,7 @@ impl IndexedDocumentResource {
     /// Get text content as string
     pub fn text_content_str(&self, text_idx: u32) -> Option<String> {
         let text = self.index.get_text(text_idx)?;
-        let raw = text.span.slice(&self.input);
+        let raw = text.span.slice(self.input());
 
         if text.needs_decode() {
             let decoded = crate::core::entities::decode_text(raw);
@@ -258,19 +1156,167 @@ impl IndexedDocumentResource {
 #[rustler::resource_impl]
 impl rustler::Resource for IndexedDocumentResource {}
 
-/// Type alias for indexed document ResourceArc
-pub type IndexedDocumentRef = ResourceArc<IndexedDocumentResource>;
+// Concurrent queries rely on this; fail the build if a field ever breaks it
+const _: () = {
+    const fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<IndexedDocumentResource>();
+};
+
+/// The reference Elixir holds for a parsed document
+///
+/// NIFs receive the handle and work on the document it points to. `close`
+/// empties it, so the document is freed once no query in flight and no
+/// sub-binary of its input still holds it, instead of whenever the BEAM
+/// collects the last copy of the reference. Decoding loads the pointer
+/// without locking, so queries on one handle never wait on each other.
+pub struct DocumentHandle {
+    doc: ArcSwapOption<ResourceArc<IndexedDocumentResource>>,
+}
+
+impl DocumentHandle {
+    /// Release the document, unless it is shared through the document
+    /// cache, where closing it would close it for every holder
+    pub fn close(&self) -> bool {
+        if self
+            .doc
+            .load()
+            .as_deref()
+            .is_some_and(|doc| doc.is_shared())
+        {
+            return false;
+        }
+        self.doc.store(None);
+        true
+    }
+}
+
+#[rustler::resource_impl]
+impl rustler::Resource for DocumentHandle {}
+
+/// A document as NIFs see it: decoded from a `DocumentHandle`, failing
+/// with `{:error, :closed}` once the handle is closed
+///
+/// The handle is created with the document and kept alongside it, so every
+/// encoding of one document is the same reference and compares equal.
+#[derive(Clone)]
+pub struct IndexedDocumentRef {
+    doc: ResourceArc<IndexedDocumentResource>,
+    handle: ResourceArc<DocumentHandle>,
+}
+
+impl IndexedDocumentRef {
+    pub fn new(resource: IndexedDocumentResource) -> Self {
+        let doc = ResourceArc::new(resource);
+        let handle = ResourceArc::new(DocumentHandle {
+            doc: ArcSwapOption::from_pointee(doc.clone()),
+        });
+        IndexedDocumentRef { doc, handle }
+    }
+}
+
+impl std::ops::Deref for IndexedDocumentRef {
+    type Target = ResourceArc<IndexedDocumentResource>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.doc
+    }
+}
+
+impl Encoder for IndexedDocumentRef {
+    fn encode<'a>(&self, env: Env<'a>) -> Term<'a> {
+        self.handle.encode(env)
+    }
+}
+
+impl<'a> rustler::Decoder<'a> for IndexedDocumentRef {
+    fn decode(term: Term<'a>) -> rustler::NifResult<Self> {
+        let handle: ResourceArc<DocumentHandle> = term.decode()?;
+        let doc = handle.doc.load_full();
+        match doc {
+            Some(doc) => Ok(IndexedDocumentRef {
+                doc: (*doc).clone(),
+                handle,
+            }),
+            None => Err(rustler::Error::Term(Box::new(crate::atoms::closed()))),
+        }
+    }
+}
+
+// ============================================================================
+// Document Set (corpus queries)
+// ============================================================================
+
+/// A growing collection of small documents queried together.
+///
+/// `docset_query` evaluates one expression against every document in a
+/// single NIF call, spreading the work over the rayon pool. Documents are
+/// identified by their position in the set (the id `add` returns).
+#[derive(Default)]
+pub struct DocumentSetResource {
+    docs: RwLock<Vec<IndexedDocumentResource>>,
+}
+
+impl DocumentSetResource {
+    /// Append a document and return its id, or None if the lock is poisoned
+    pub fn add(&self, doc: IndexedDocumentResource) -> Option<usize> {
+        let mut docs = self.docs.write().ok()?;
+        docs.push(doc);
+        Some(docs.len() - 1)
+    }
+
+    /// Number of documents, or None if the lock is poisoned
+    pub fn len(&self) -> Option<usize> {
+        self.docs.read().ok().map(|docs| docs.len())
+    }
+
+    /// Read access to the documents for the duration of a query
+    pub fn docs(&self) -> Option<RwLockReadGuard<'_, Vec<IndexedDocumentResource>>> {
+        self.docs.read().ok()
+    }
+
+    /// Evaluate `xpath` against every document, in id order
+    pub fn evaluate_all(
+        docs: &[IndexedDocumentResource],
+        xpath: &str,
+    ) -> Result<Vec<XPathValue>, String> {
+        use rayon::prelude::*;
+
+        let compiled = crate::xpath::compiler::compile(xpath)?;
+        let flag = crate::cancel::current();
+        docs.par_iter()
+            .map(|doc| {
+                crate::cancel::run_with(flag.clone(), || {
+                    crate::xpath::evaluate_precompiled(&doc.as_view(), &compiled)
+                })
+            })
+            .collect()
+    }
+}
+
+#[rustler::resource_impl]
+impl rustler::Resource for DocumentSetResource {}
+
+/// Type alias for document set ResourceArc
+pub type DocumentSetRef = ResourceArc<DocumentSetResource>;
 
 // ============================================================================
 // Document Accumulator (Streaming SimpleForm)
 // ============================================================================
 
+/// Leading bytes of `DocumentAccumulator::snapshot` output
+const SNAPSHOT_MAGIC: &[u8; 4] = b"RXAC";
+
+/// Snapshot format version, bumped on any layout change
+const SNAPSHOT_VERSION: u8 = 1;
+
 /// Accumulates XML chunks for streaming SimpleForm parsing.
 ///
 /// Keeps all bytes in Rust until `to_simple_form()` is called,
 /// minimizing BEAM heap usage during accumulation.
 pub struct DocumentAccumulator {
     buffer: Mutex<Vec<u8>>,
+    /// Early checks for `feed_validated` (state carried between chunks)
+    validator: Mutex<IncrementalValidator>,
 }
 
 impl DocumentAccumulator {
@@ -280,6 +1326,7 @@ impl DocumentAccumulator {
         // multiplied quickly across concurrent accumulators.
         Self {
             buffer: Mutex::new(Vec::with_capacity(4096)),
+            validator: Mutex::new(IncrementalValidator::new()),
         }
     }
 
@@ -289,6 +1336,72 @@ impl DocumentAccumulator {
         }
     }
 
+    /// Append a chunk and check the newly completed part of the document
+    ///
+    /// Checks pick up where the previous chunk's stopped, so each byte is
+    /// checked once. Once a check fails, the chunk is taken back out and it
+    /// and all later ones are dropped, returning the same error.
+    /// `Err(None)` means a lock was poisoned.
+    pub fn feed_validated(&self, chunk: &[u8]) -> Result<(), Option<String>> {
+        let mut validator = self.validator.lock().map_err(|_| None)?;
+        if let Some(err) = validator.error() {
+            return Err(Some(err.to_string()));
+        }
+        let mut buf = self.buffer.lock().map_err(|_| None)?;
+        let before = buf.len();
+        buf.extend_from_slice(chunk);
+        validator.check(&buf).map_err(|err| {
+            buf.truncate(before);
+            validator.rewind(before);
+            Some(err)
+        })
+    }
+
+    /// Serialize the buffer and validation state
+    ///
+    /// Layout: `SNAPSHOT_MAGIC`, version byte, validator state (see
+    /// `IncrementalValidator::write_state`), buffer length (u64 LE), buffer.
+    /// None if a lock was poisoned.
+    pub fn snapshot(&self) -> Option<Vec<u8>> {
+        let validator = self.validator.lock().ok()?;
+        let buf = self.buffer.lock().ok()?;
+        let mut out = Vec::with_capacity(buf.len() + 64);
+        out.extend_from_slice(SNAPSHOT_MAGIC);
+        out.push(SNAPSHOT_VERSION);
+        validator.write_state(&mut out);
+        out.extend_from_slice(&(buf.len() as u64).to_le_bytes());
+        out.extend_from_slice(&buf);
+        Some(out)
+    }
+
+    /// Rebuild an accumulator from `snapshot` output
+    pub fn restore(snapshot: &[u8]) -> Result<Self, &'static str> {
+        use crate::dom::incremental::{read_u64, take};
+
+        let mut input = snapshot;
+        if take(&mut input, SNAPSHOT_MAGIC.len()) != Some(SNAPSHOT_MAGIC.as_slice()) {
+            return Err("not an accumulator snapshot");
+        }
+        if take(&mut input, 1) != Some([SNAPSHOT_VERSION].as_slice()) {
+            return Err("unsupported accumulator snapshot version");
+        }
+        let validator =
+            IncrementalValidator::read_state(&mut input).ok_or("truncated accumulator snapshot")?;
+        let len = read_u64(&mut input)
+            .and_then(|len| usize::try_from(len).ok())
+            .ok_or("truncated accumulator snapshot")?;
+        if input.len() != len || validator.checked() > len {
+            return Err("corrupt accumulator snapshot");
+        }
+
+        let mut buffer = Vec::with_capacity(len.max(4096));
+        buffer.extend_from_slice(input);
+        Ok(Self {
+            buffer: Mutex::new(buffer),
+            validator: Mutex::new(validator),
+        })
+    }
+
     pub fn take_buffer(&self) -> Vec<u8> {
         if let Ok(mut buf) = self.buffer.lock() {
             std::mem::take(&mut buf)
@@ -309,3 +1422,555 @@ impl rustler::Resource for DocumentAccumulator {}
 
 /// Type alias for accumulator ResourceArc
 pub type DocumentAccumulatorRef = ResourceArc<DocumentAccumulator>;
+
+// ============================================================================
+// Node-Set Cursor (cooperative term building)
+// ============================================================================
+
+/// A node-set result converted to terms across several NIF calls.
+///
+/// Keeps the document alive alongside the node ids still to be converted, so
+/// `cursor_next` can stop when its timeslice is used up and resume later
+/// instead of building every term in one scheduler-blocking call.
+pub struct NodeSetCursor {
+    pub doc: IndexedDocumentRef,
+    /// (node ids, index of the next node to convert)
+    state: Mutex<(Vec<NodeId>, usize)>,
+}
+
+impl NodeSetCursor {
+    pub fn new(doc: IndexedDocumentRef, nodes: Vec<NodeId>) -> Self {
+        Self {
+            doc,
+            state: Mutex::new((nodes, 0)),
+        }
+    }
+
+    /// Lock the cursor state, or None if the mutex is poisoned
+    pub fn state(&self) -> Option<std::sync::MutexGuard<'_, (Vec<NodeId>, usize)>> {
+        self.state.lock().ok()
+    }
+}
+
+#[rustler::resource_impl]
+impl rustler::Resource for NodeSetCursor {}
+
+/// Type alias for node-set cursor ResourceArc
+pub type NodeSetCursorRef = ResourceArc<NodeSetCursor>;
+
+/// Continuation of an `xpath_query_chunked` result
+///
+/// Holds the remaining node ids (4 bytes each) rather than terms, so only
+/// one chunk of the result exists as terms at a time.
+pub struct NodeSetChunks {
+    pub cursor: NodeSetCursor,
+    /// Nodes converted per `xpath_chunk_next` call
+    pub chunk_size: usize,
+}
+
+#[rustler::resource_impl]
+impl rustler::Resource for NodeSetChunks {}
+
+/// Type alias for chunked result ResourceArc
+pub type NodeSetChunksRef = ResourceArc<NodeSetChunks>;
+
+// ============================================================================
+// SAX Push Stream
+// ============================================================================
+
+/// Handle for one `sax_parse_send` stream.
+///
+/// Every message the stream's worker sends carries this handle, so a
+/// consumer running several streams can match each batch to its source.
+/// It also tracks batches sent but not yet acknowledged, which windowed
+/// streams wait on before tokenizing further. The resource monitors the
+/// consumer: if it exits the stream is closed and its worker stops.
+/// `sax_send_cancel` closes it the same way.
+#[derive(Default)]
+pub struct SaxSendResource {
+    /// (unacknowledged batches, closed)
+    window: Mutex<(usize, bool)>,
+    acked: Condvar,
+}
+
+impl SaxSendResource {
+    /// Count a batch about to be sent
+    ///
+    /// Called before sending, so an ack can never arrive for a batch that
+    /// is not counted yet.
+    pub fn batch_sent(&self) {
+        if let Ok(mut window) = self.window.lock() {
+            window.0 += 1;
+        }
+    }
+
+    /// Acknowledge `n` batches, or return false if the mutex is poisoned
+    pub fn ack(&self, n: usize) -> bool {
+        let Ok(mut window) = self.window.lock() else {
+            return false;
+        };
+        window.0 = window.0.saturating_sub(n);
+        self.acked.notify_all();
+        true
+    }
+
+    /// Stop the stream and wake its worker
+    pub fn close(&self) {
+        if let Ok(mut window) = self.window.lock() {
+            window.1 = true;
+        }
+        self.acked.notify_all();
+    }
+
+    /// Block while `limit` or more batches are unacknowledged, for at most
+    /// `timeout`
+    ///
+    /// A stream that times out is closed, so its worker is released even if
+    /// the consumer stopped acknowledging without exiting.
+    pub fn wait_for_window(&self, limit: usize, timeout: Duration) -> WindowWait {
+        let Ok(window) = self.window.lock() else {
+            return WindowWait::Closed;
+        };
+        let Ok((mut window, result)) =
+            self.acked
+                .wait_timeout_while(window, timeout, |(outstanding, closed)| {
+                    *outstanding >= limit && !*closed
+                })
+        else {
+            return WindowWait::Closed;
+        };
+        if window.1 {
+            WindowWait::Closed
+        } else if result.timed_out() {
+            window.1 = true;
+            WindowWait::TimedOut
+        } else {
+            WindowWait::Open
+        }
+    }
+
+    /// Whether the stream has been closed
+    pub fn is_closed(&self) -> bool {
+        self.window.lock().map_or(true, |window| window.1)
+    }
+}
+
+/// Outcome of `SaxSendResource::wait_for_window`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindowWait {
+    /// Below the limit again; send the next batch
+    Open,
+    /// Closed by the consumer exiting or cancelling
+    Closed,
+    /// No acknowledgement arrived in time; the stream is now closed
+    TimedOut,
+}
+
+#[rustler::resource_impl]
+impl rustler::Resource for SaxSendResource {
+    fn down<'a>(&'a self, _env: Env<'a>, _pid: rustler::LocalPid, _monitor: rustler::Monitor) {
+        self.close();
+    }
+}
+
+/// Type alias for SAX push stream ResourceArc
+pub type SaxSendRef = ResourceArc<SaxSendResource>;
+
+/// Handle of a `parse_async` build queued on or running on a worker
+///
+/// Tags the messages sent to the caller, and carries the flag that
+/// `parse_cancel` sets and the builder checks between tokens. The caller is
+/// monitored, so a build is also abandoned when the caller exits.
+#[derive(Default)]
+pub struct ParseTaskResource {
+    cancelled: AtomicBool,
+}
+
+impl ParseTaskResource {
+    /// Ask the build to stop at its next progress check
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Release);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Acquire)
+    }
+}
+
+#[rustler::resource_impl]
+impl rustler::Resource for ParseTaskResource {
+    fn down<'a>(&'a self, _env: Env<'a>, _pid: rustler::LocalPid, _monitor: rustler::Monitor) {
+        self.cancel();
+    }
+}
+
+/// Type alias for parse_async task ResourceArc
+pub type ParseTaskRef = ResourceArc<ParseTaskResource>;
+
+/// Cancellation token handed to cancellable NIFs
+///
+/// Any process holding the token can set it; work running under it (see
+/// `crate::cancel::run`) stops at its next check. A token stays set, so one
+/// token can stop a whole batch of calls.
+#[derive(Default)]
+pub struct CancelTokenResource {
+    pub flag: Arc<AtomicBool>,
+}
+
+impl CancelTokenResource {
+    pub fn cancel(&self) {
+        self.flag.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.flag.load(Ordering::Relaxed)
+    }
+}
+
+#[rustler::resource_impl]
+impl rustler::Resource for CancelTokenResource {}
+
+/// Type alias for cancellation token ResourceArc
+pub type CancelTokenRef = ResourceArc<CancelTokenResource>;
+
+#[rustler::resource_impl]
+impl rustler::Resource for Schema {}
+
+/// Type alias for compiled Schematron schema ResourceArc
+pub type SchematronRef = ResourceArc<Schema>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_document_set_evaluates_every_document() {
+        let set = DocumentSetResource::default();
+        for i in 0..50 {
+            let xml = format!("<log><entry level=\"{}\"/></log>", i % 3);
+            assert_eq!(
+                set.add(IndexedDocumentResource::new(xml.into_bytes())),
+                Some(i)
+            );
+        }
+        assert_eq!(set.len(), Some(50));
+
+        let docs = set.docs().unwrap();
+        let results =
+            DocumentSetResource::evaluate_all(&docs, "count(//entry[@level = 0])").unwrap();
+        let matching: Vec<usize> = results
+            .iter()
+            .enumerate()
+            .filter(|(_, value)| value.to_number() == 1.0)
+            .map(|(id, _)| id)
+            .collect();
+        assert_eq!(matching, (0..50).step_by(3).collect::<Vec<_>>());
+
+        assert!(DocumentSetResource::evaluate_all(&docs, "//[").is_err());
+    }
+
+    #[test]
+    fn test_concurrent_queries_share_one_document() {
+        let items: String = (0..500)
+            .map(|i| format!("<item id=\"{i}\">{i}</item>"))
+            .collect();
+        let doc = Arc::new(IndexedDocumentResource::new(
+            format!("<root>{items}</root>").into_bytes(),
+        ));
+        doc.result_cache.set_capacity(4);
+
+        let handles: Vec<_> = (0..8)
+            .map(|t| {
+                let doc = Arc::clone(&doc);
+                thread::spawn(move || {
+                    for i in 0..50 {
+                        let xpath = format!("//item[@id='{}']", (t * 50 + i) % 500);
+                        match doc.evaluate(&xpath) {
+                            Ok(XPathValue::NodeSet(nodes)) => assert_eq!(nodes.len(), 1),
+                            other => panic!("unexpected result: {other:?}"),
+                        }
+                        match doc.evaluate("//item") {
+                            Ok(XPathValue::NodeSet(nodes)) => assert_eq!(nodes.len(), 500),
+                            other => panic!("unexpected result: {other:?}"),
+                        }
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(doc.result_cache.len(), 4);
+    }
+
+    #[test]
+    fn test_sax_send_window_blocks_until_acked() {
+        use std::sync::mpsc;
+
+        let long = Duration::from_secs(60);
+        let stream = Arc::new(SaxSendResource::default());
+        stream.batch_sent();
+        stream.batch_sent();
+        assert_eq!(stream.wait_for_window(3, long), WindowWait::Open);
+
+        let (tx, rx) = mpsc::channel();
+        let waiter = {
+            let stream = Arc::clone(&stream);
+            thread::spawn(move || tx.send(stream.wait_for_window(2, long)).unwrap())
+        };
+        assert!(rx.recv_timeout(Duration::from_millis(50)).is_err());
+        assert!(stream.ack(1));
+        assert_eq!(
+            rx.recv_timeout(Duration::from_secs(5)).unwrap(),
+            WindowWait::Open
+        );
+        waiter.join().unwrap();
+
+        // Closing releases a blocked waiter and reports the stream closed
+        stream.batch_sent();
+        let waiter = {
+            let stream = Arc::clone(&stream);
+            thread::spawn(move || stream.wait_for_window(1, long))
+        };
+        stream.close();
+        assert_eq!(waiter.join().unwrap(), WindowWait::Closed);
+        assert!(stream.is_closed());
+    }
+
+    #[test]
+    fn test_sax_send_window_times_out() {
+        let stream = SaxSendResource::default();
+        stream.batch_sent();
+        let timeout = Duration::from_millis(20);
+        assert_eq!(stream.wait_for_window(1, timeout), WindowWait::TimedOut);
+        assert!(stream.is_closed());
+        assert_eq!(stream.wait_for_window(1, timeout), WindowWait::Closed);
+    }
+
+    #[test]
+    fn test_attr_index_matches_scan() {
+        let input = b"<catalog><item sku=\"a\"/><part sku=\"a\"/><group><item sku=\"b\"/>\
+<item sku=\"a\"/><item/></group></catalog>";
+        let doc = IndexedDocumentResource::new(input.to_vec());
+        let queries = [
+            "//item[@sku = 'a']",
+            "//*[@sku = 'a']",
+            "//item[@sku]",
+            "//item[@sku = 'missing']",
+            "/descendant::item[@sku = 'b']",
+            "//group//item[@sku = 'a']",
+            "count(//item[@sku = 'a'][1])",
+        ];
+        let scanned: Vec<_> = queries
+            .iter()
+            .map(|q| format!("{:?}", doc.evaluate(q)))
+            .collect();
+
+        assert!(doc.build_attr_index("sku"));
+        assert_eq!(doc.attr_indexes.names(), ["sku"]);
+        for (query, expected) in queries.iter().zip(scanned) {
+            assert_eq!(format!("{:?}", doc.evaluate(query)), expected, "{query}");
+        }
+        let Ok(XPathValue::NodeSet(nodes)) = doc.evaluate("//*[@sku = 'a']") else {
+            panic!("expected a node-set");
+        };
+        assert_eq!(nodes, [1, 2, 5]);
+    }
+
+    #[test]
+    fn test_accumulator_snapshot_round_trip() {
+        let acc = DocumentAccumulator::new();
+        acc.feed_validated(b"<root><item>1</item><it").unwrap();
+        let snapshot = acc.snapshot().unwrap();
+
+        let restored = DocumentAccumulator::restore(&snapshot).unwrap();
+        restored.feed_validated(b"em>2</item></root>").unwrap();
+        assert_eq!(
+            restored.take_buffer(),
+            b"<root><item>1</item><item>2</item></root>"
+        );
+
+        let restored = DocumentAccumulator::restore(&snapshot).unwrap();
+        assert_eq!(
+            restored.feed_validated(b"em></root>"),
+            Err(Some("Tag mismatch: <item> closed with </root>".to_string()))
+        );
+        // The failing chunk isn't kept, and the state still round-trips
+        let failed = DocumentAccumulator::restore(&restored.snapshot().unwrap()).unwrap();
+        assert!(failed.feed_validated(b"</item>").is_err());
+        assert_eq!(restored.take_buffer(), b"<root><item>1</item><it");
+
+        assert!(DocumentAccumulator::restore(&snapshot[..snapshot.len() - 1]).is_err());
+        assert!(DocumentAccumulator::restore(b"junk").is_err());
+    }
+
+    #[test]
+    fn test_poisoned_parser_resets_once() {
+        let parser = StreamingParserResource::with_filter(b"item");
+        parser.lock().unwrap().feed(b"<items><item>1</item><item>");
+        let panicked = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let _guard = parser.lock().unwrap();
+            panic!("parser bug");
+        }));
+        assert!(panicked.is_err());
+
+        assert_eq!(parser.lock().err(), Some(ParserUnavailable::Reset));
+        let mut inner = parser.lock().unwrap();
+        assert_eq!((inner.buffer_size(), inner.available_elements()), (0, 0));
+        // The filter survives the reset
+        inner.feed(b"<items><item>2</item><other/></items>");
+        assert_eq!(inner.available_elements(), 1);
+    }
+
+    #[test]
+    fn test_document_memory_counts_caches() {
+        let doc = IndexedDocumentResource::new(b"<r><a id='1'>x</a><a id='2'>y</a></r>".to_vec());
+        let before = doc.memory();
+        assert!(before.input >= 37);
+        assert!(before.index > 0);
+        assert_eq!(before.result_cache, 0);
+        assert_eq!(before.attr_indexes, 0);
+        assert_eq!(before.precomputed_text, 0);
+
+        doc.result_cache.set_capacity(8);
+        let _ = doc.evaluate("//a").unwrap();
+        doc.build_attr_index("id");
+        doc.precompute_text();
+        let after = doc.memory();
+        assert!(after.result_cache > 0);
+        assert!(after.attr_indexes > 0);
+        assert!(after.precomputed_text > 0);
+        assert!(after.total() > before.total());
+    }
+
+    #[test]
+    fn test_extract_subtree_keeps_namespaces() {
+        let doc = IndexedDocumentResource::new(
+            b"<r xmlns='urn:d' xmlns:a=\"urn:a\"><s xmlns:a='urn:other'><x/></s>\
+              <b xmlns:c='q\"uote'><a:i c:k='1'>t</a:i></b></r>"
+                .to_vec(),
+        );
+        let nodes = match doc.evaluate("//b").unwrap() {
+            XPathValue::NodeSet(nodes) => nodes,
+            other => panic!("expected node-set, got {:?}", other),
+        };
+        let extracted = doc.extract_subtree(nodes[0]).unwrap();
+        assert_eq!(
+            extracted.input(),
+            b"<b xmlns=\"urn:d\" xmlns:a=\"urn:a\" xmlns:c='q\"uote'><a:i c:k='1'>t</a:i></b>"
+        );
+        let texts = extracted.evaluate("/b/*[name() = 'a:i']/text()").unwrap();
+        assert!(matches!(texts, XPathValue::NodeSet(ref n) if n.len() == 1));
+
+        let root = crate::dom::DocumentAccess::document_node_id(&doc.as_view());
+        assert!(doc.extract_subtree(root).is_none());
+    }
+
+    #[test]
+    fn test_merge_positions() {
+        fn first(doc: &IndexedDocumentResource, xpath: &str) -> NodeId {
+            match doc.evaluate(xpath).unwrap() {
+                XPathValue::NodeSet(nodes) => nodes[0],
+                other => panic!("expected node-set, got {:?}", other),
+            }
+        }
+        let envelope = IndexedDocumentResource::new(
+            b"<env xmlns='urn:e'><head/><body><x/></body></env>".to_vec(),
+        );
+        let payload =
+            IndexedDocumentResource::new(b"<r xmlns:p='urn:p'><p:item>1</p:item></r>".to_vec());
+        let item = first(&payload, "//*[name() = 'p:item']");
+        let body = first(&envelope, "//*[name() = 'body']");
+        let head = first(&envelope, "//*[name() = 'head']");
+
+        let cases = [
+            (
+                body,
+                MergePosition::FirstChild,
+                "<body><p:item xmlns:p=\"urn:p\" xmlns=\"\">1</p:item><x/></body>",
+            ),
+            (
+                body,
+                MergePosition::LastChild,
+                "<body><x/><p:item xmlns:p=\"urn:p\" xmlns=\"\">1</p:item></body>",
+            ),
+            (
+                head,
+                MergePosition::LastChild,
+                "<head><p:item xmlns:p=\"urn:p\" xmlns=\"\">1</p:item></head><body>",
+            ),
+            (
+                head,
+                MergePosition::Replace,
+                "'urn:e'><p:item xmlns:p=\"urn:p\" xmlns=\"\">1</p:item><body>",
+            ),
+            (
+                body,
+                MergePosition::After,
+                "</body><p:item xmlns:p=\"urn:p\" xmlns=\"\">1</p:item></env>",
+            ),
+        ];
+        for (target, position, expected) in cases {
+            let merged = envelope.merge(target, &payload, item, position).unwrap();
+            let xml = std::str::from_utf8(merged.input()).unwrap();
+            assert!(xml.contains(expected), "{:?}: {}", position, xml);
+            let count = merged.evaluate("count(//*[name() = 'p:item'])").unwrap();
+            assert!(matches!(count, XPathValue::Number(n) if n == 1.0));
+        }
+
+        let x = first(&envelope, "//*[name() = 'x']");
+        assert!(envelope
+            .merge(x, &payload, item, MergePosition::Before)
+            .is_some());
+        let root = crate::dom::DocumentAccess::document_node_id(&envelope.as_view());
+        assert!(envelope
+            .merge(root, &payload, item, MergePosition::LastChild)
+            .is_none());
+        assert!(envelope
+            .merge(body, &payload, root, MergePosition::LastChild)
+            .is_none());
+
+        // Outside the root, the document must keep exactly one root element
+        let env = first(&envelope, "/*");
+        for position in [MergePosition::Before, MergePosition::After] {
+            assert!(envelope.merge(env, &payload, item, position).is_none());
+        }
+        let replaced = envelope
+            .merge(env, &payload, item, MergePosition::Replace)
+            .unwrap();
+        assert!(replaced.input().starts_with(b"<p:item"));
+
+        let commented = IndexedDocumentResource::new(b"<!--c--><r>t</r>".to_vec());
+        let comment = first(&commented, "/comment()");
+        let text = first(&commented, "//text()");
+        assert!(envelope
+            .merge(env, &commented, comment, MergePosition::After)
+            .is_some());
+        assert!(envelope
+            .merge(env, &commented, comment, MergePosition::Replace)
+            .is_none());
+        assert!(envelope
+            .merge(env, &commented, text, MergePosition::Before)
+            .is_none());
+        assert!(commented
+            .merge(comment, &payload, item, MergePosition::Replace)
+            .is_none());
+    }
+
+    #[test]
+    fn test_closed_parser_stays_closed() {
+        let parser = StreamingSaxParserResource::new();
+        parser
+            .lock()
+            .unwrap()
+            .buffer
+            .extend_from_slice(b"<unfinished");
+        parser.close();
+
+        assert_eq!(parser.lock().err(), Some(ParserUnavailable::Closed));
+        assert_eq!(parser.inner.lock().unwrap().buffer.capacity(), 0);
+        parser.close();
+        assert_eq!(parser.lock().err(), Some(ParserUnavailable::Closed));
+    }
+}
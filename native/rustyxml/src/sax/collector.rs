@@ -56,6 +56,12 @@ impl SaxCollector {
         self.events.len()
     }
 
+    /// Drop collected events and attributes, keeping the allocations
+    pub fn clear(&mut self) {
+        self.events.clear();
+        self.attributes.clear();
+    }
+
     /// Convert compact events to rich events (for API compatibility)
     pub fn to_sax_events(&self) -> Vec<SaxEvent> {
         self.events.iter().map(|e| self.compact_to_sax(e)).collect()
@@ -180,6 +186,174 @@ impl ScanHandler for SaxCollector {
     }
 }
 
+/// Collector that hands events over in batches as scanning proceeds
+///
+/// Once at least `batch_size` events are collected, `flush` receives them
+/// and the collector is cleared (attribute offsets in a batch refer to its
+/// own `attributes()`). Empty elements add two events, so a batch can be one
+/// over the limit. If `flush` returns false the scan stops early.
+pub struct BatchingCollector<F: FnMut(&SaxCollector) -> bool> {
+    inner: SaxCollector,
+    batch_size: usize,
+    flush: F,
+    stopped: bool,
+}
+
+impl<F: FnMut(&SaxCollector) -> bool> BatchingCollector<F> {
+    pub fn new(batch_size: usize, flush: F) -> Self {
+        let batch_size = batch_size.max(1);
+        Self {
+            inner: SaxCollector::with_capacity(batch_size + 1, batch_size.min(1024)),
+            batch_size,
+            flush,
+            stopped: false,
+        }
+    }
+
+    /// Flush any remaining events; false if a flush asked to stop
+    pub fn finish(mut self) -> bool {
+        if !self.stopped && self.inner.event_count() > 0 {
+            self.stopped = !(self.flush)(&self.inner);
+        }
+        !self.stopped
+    }
+
+    #[inline]
+    fn maybe_flush(&mut self) {
+        if self.inner.event_count() >= self.batch_size {
+            self.stopped = !(self.flush)(&self.inner);
+            self.inner.clear();
+        }
+    }
+}
+
+impl<F: FnMut(&SaxCollector) -> bool> ScanHandler for BatchingCollector<F> {
+    fn start_element(&mut self, name: Span, attrs: &[(Span, Span)], is_empty: bool) {
+        self.inner.start_element(name, attrs, is_empty);
+        self.maybe_flush();
+    }
+
+    fn end_element(&mut self, name: Span) {
+        self.inner.end_element(name);
+        self.maybe_flush();
+    }
+
+    fn text(&mut self, span: Span, needs_entity_decode: bool) {
+        self.inner.text(span, needs_entity_decode);
+        self.maybe_flush();
+    }
+
+    fn cdata(&mut self, span: Span) {
+        self.inner.cdata(span);
+        self.maybe_flush();
+    }
+
+    fn comment(&mut self, span: Span) {
+        self.inner.comment(span);
+        self.maybe_flush();
+    }
+
+    fn processing_instruction(&mut self, target: Span, data: Option<Span>) {
+        self.inner.processing_instruction(target, data);
+        self.maybe_flush();
+    }
+
+    fn should_stop(&self) -> bool {
+        self.stopped
+    }
+}
+
+/// Event classes left out of a `FilteringCollector`'s output
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SaxFilter {
+    pub skip_comments: bool,
+    pub skip_processing_instructions: bool,
+    /// Drop text events made only of spaces, tabs and line breaks
+    pub skip_whitespace: bool,
+    /// Drop everything nested deeper than this; the root element and
+    /// anything else outside it are at depth 1, the root's content at 2
+    pub max_depth: Option<usize>,
+}
+
+/// Collector that drops the events a `SaxFilter` excludes as they are
+/// scanned, so they never take up space in the result
+pub struct FilteringCollector<'a> {
+    inner: SaxCollector,
+    input: &'a [u8],
+    filter: SaxFilter,
+    /// Elements currently open
+    depth: usize,
+}
+
+impl<'a> FilteringCollector<'a> {
+    pub fn new(input: &'a [u8], filter: SaxFilter) -> Self {
+        Self {
+            inner: SaxCollector::new(),
+            input,
+            filter,
+            depth: 0,
+        }
+    }
+
+    /// The events kept
+    pub fn collector(&self) -> &SaxCollector {
+        &self.inner
+    }
+
+    /// Whether an event at `depth` is within `max_depth`
+    #[inline]
+    fn within(&self, depth: usize) -> bool {
+        self.filter.max_depth.is_none_or(|max| depth <= max)
+    }
+}
+
+impl ScanHandler for FilteringCollector<'_> {
+    fn start_element(&mut self, name: Span, attrs: &[(Span, Span)], is_empty: bool) {
+        if self.within(self.depth + 1) {
+            self.inner.start_element(name, attrs, is_empty);
+        }
+        if !is_empty {
+            self.depth += 1;
+        }
+    }
+
+    fn end_element(&mut self, name: Span) {
+        if self.within(self.depth) {
+            self.inner.end_element(name);
+        }
+        self.depth = self.depth.saturating_sub(1);
+    }
+
+    fn text(&mut self, span: Span, needs_entity_decode: bool) {
+        let blank = || {
+            span.slice(self.input)
+                .iter()
+                .all(|b| matches!(b, b' ' | b'\t' | b'\r' | b'\n'))
+        };
+        if self.within(self.depth + 1) && !(self.filter.skip_whitespace && blank()) {
+            self.inner.text(span, needs_entity_decode);
+        }
+    }
+
+    fn cdata(&mut self, span: Span) {
+        if self.within(self.depth + 1) {
+            self.inner.cdata(span);
+        }
+    }
+
+    fn comment(&mut self, span: Span) {
+        if !self.filter.skip_comments && self.within(self.depth + 1) {
+            self.inner.comment(span);
+        }
+    }
+
+    fn processing_instruction(&mut self, target: Span, data: Option<Span>) {
+        if !self.filter.skip_processing_instructions && self.within(self.depth + 1) {
+            self.inner.processing_instruction(target, data);
+        }
+    }
+}
+
 /// Parse input and return SAX events
 pub fn parse_sax(input: &[u8]) -> Vec<SaxEvent> {
     use crate::core::unified_scanner::UnifiedScanner;
@@ -249,4 +423,95 @@ mod tests {
         assert_eq!(events[1].tag, CompactSaxEvent::TAG_TEXT);
         assert_eq!(events[2].tag, CompactSaxEvent::TAG_END_ELEMENT);
     }
+
+    #[test]
+    fn test_filtering_collector() {
+        use crate::core::unified_scanner::UnifiedScanner;
+
+        let input = b"<?pi x?><r>\n  <!-- c --><a>t<b>deep</b></a>\n  <c/>\n</r>";
+        let tags = |filter: SaxFilter| {
+            let mut collector = FilteringCollector::new(input, filter);
+            UnifiedScanner::new(input).scan(&mut collector);
+            let tags: Vec<u8> = collector
+                .collector()
+                .events()
+                .iter()
+                .map(|e| e.tag)
+                .collect();
+            tags
+        };
+        use CompactSaxEvent as E;
+
+        let all = tags(SaxFilter::default());
+        assert_eq!(
+            all,
+            parse_sax_compact(input)
+                .0
+                .iter()
+                .map(|e| e.tag)
+                .collect::<Vec<_>>()
+        );
+
+        let filter = SaxFilter {
+            skip_comments: true,
+            skip_processing_instructions: true,
+            skip_whitespace: true,
+            max_depth: Some(2),
+        };
+        assert_eq!(
+            tags(filter),
+            [
+                E::TAG_START_ELEMENT, // r
+                E::TAG_START_ELEMENT, // a
+                E::TAG_END_ELEMENT,
+                E::TAG_START_ELEMENT, // c
+                E::TAG_END_ELEMENT,
+                E::TAG_END_ELEMENT,
+            ]
+        );
+
+        let filter = SaxFilter {
+            max_depth: Some(1),
+            ..SaxFilter::default()
+        };
+        assert_eq!(
+            tags(filter),
+            [E::TAG_PI, E::TAG_START_ELEMENT, E::TAG_END_ELEMENT]
+        );
+    }
+
+    #[test]
+    fn test_batching_collector() {
+        use crate::core::unified_scanner::UnifiedScanner;
+
+        let input = b"<r><a x=\"1\">t</a><b y=\"2\"/><c/></r>";
+        let mut batches: Vec<(Vec<u8>, Vec<u32>)> = Vec::new();
+        let mut collector = BatchingCollector::new(3, |batch: &SaxCollector| {
+            let tags = batch.events().iter().map(|e| e.tag).collect();
+            let names = batch.attributes().iter().map(|a| a.0).collect();
+            batches.push((tags, names));
+            true
+        });
+        UnifiedScanner::new(input).scan(&mut collector);
+        assert!(collector.finish());
+
+        let total: usize = batches.iter().map(|(tags, _)| tags.len()).sum();
+        assert_eq!(total, 9);
+        assert!(batches.iter().all(|(tags, _)| tags.len() <= 4));
+        // Attribute offsets restart with each batch
+        let (tags, attrs) = &batches[1];
+        assert_eq!(tags[0], CompactSaxEvent::TAG_END_ELEMENT);
+        assert_eq!(attrs.len(), 1);
+        assert_eq!(input[attrs[0] as usize], b'y');
+
+        // Returning false from the flush stops the scan
+        let mut flushes = 0;
+        let mut collector = BatchingCollector::new(1, |_: &SaxCollector| {
+            flushes += 1;
+            false
+        });
+        UnifiedScanner::new(input).scan(&mut collector);
+        assert!(!collector.finish());
+        assert_eq!(flushes, 1);
+    }
 }
@@ -32,5 +32,5 @@ pub mod collector;
 pub mod events;
 
 // Re-export only what's needed externally
-pub use collector::SaxCollector;
+pub use collector::{BatchingCollector, FilteringCollector, SaxCollector, SaxFilter};
 pub use events::CompactSaxEvent;
@@ -0,0 +1,564 @@
+//! Schema Inference
+//!
+//! `infer_schema` writes an XSD skeleton for a set of sample documents, as
+//! a starting point for a hand-written schema rather than a finished one.
+//! Each element name under a given parent becomes one declaration, and
+//! the samples decide:
+//!
+//! - the hierarchy, with children in order of first appearance (an
+//!   `xs:choice` when the samples disagree on the order)
+//! - optionality (`minOccurs="0"`) and repetition (`maxOccurs="unbounded"`)
+//! - required and optional attributes
+//! - value types: `xs:boolean`, `xs:integer`, `xs:decimal`, `xs:date`,
+//!   `xs:dateTime`, or `xs:string` when values disagree
+//!
+//! Declarations use local names. The namespace of the first root element,
+//! default or prefixed, becomes the target namespace; prefixed attributes
+//! and namespace declarations are left out. Dates and times must be real
+//! ones (`2024-02-30` is a string). The samples are valid against the
+//! result.
+
+use crate::dom::{node_string_value, DocumentAccess, NodeId, NodeKind};
+use crate::index::IndexedDocumentView;
+use crate::xpath::datetime::days_in_month;
+use std::collections::HashMap;
+
+/// Parent of root declarations
+const NO_DECL: usize = usize::MAX;
+
+/// The narrowest XSD type seen values fit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ValueType {
+    /// Only empty values (an element with no content)
+    Empty,
+    Boolean,
+    Integer,
+    Decimal,
+    Date,
+    DateTime,
+    String,
+}
+
+impl ValueType {
+    fn of(value: &str) -> Self {
+        let value = value.trim();
+        let unsigned = value.strip_prefix(['+', '-']).unwrap_or(value);
+        let digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+        if value.is_empty() {
+            ValueType::Empty
+        } else if value == "true" || value == "false" {
+            ValueType::Boolean
+        } else if digits(unsigned) {
+            ValueType::Integer
+        } else if unsigned.split_once('.').is_some_and(|(int, frac)| {
+            (int.is_empty() || digits(int)) && (frac.is_empty() || digits(frac)) && unsigned != "."
+        }) {
+            ValueType::Decimal
+        } else if is_date(value) {
+            ValueType::Date
+        } else if is_date_time(value) {
+            ValueType::DateTime
+        } else {
+            ValueType::String
+        }
+    }
+
+    fn merge(self, other: ValueType) -> ValueType {
+        match (self, other) {
+            (a, b) if a == b => a,
+            (ValueType::Integer, ValueType::Decimal) | (ValueType::Decimal, ValueType::Integer) => {
+                ValueType::Decimal
+            }
+            _ => ValueType::String,
+        }
+    }
+
+    fn xsd(self) -> &'static str {
+        match self {
+            ValueType::Boolean => "xs:boolean",
+            ValueType::Integer => "xs:integer",
+            ValueType::Decimal => "xs:decimal",
+            ValueType::Date => "xs:date",
+            ValueType::DateTime => "xs:dateTime",
+            ValueType::Empty | ValueType::String => "xs:string",
+        }
+    }
+}
+
+/// A `YYYY-MM-DD` date that exists (year 0000 doesn't, in XSD 1.0)
+fn is_date(value: &str) -> bool {
+    let b = value.as_bytes();
+    let shaped = b.len() == 10
+        && b.iter().enumerate().all(|(i, &c)| match i {
+            4 | 7 => c == b'-',
+            _ => c.is_ascii_digit(),
+        });
+    if !shaped {
+        return false;
+    }
+    let (year, month, day) = (number(&b[..4]), number(&b[5..7]), number(&b[8..]));
+    year > 0 && (1..=12).contains(&month) && (1..=days_in_month(year.into(), month)).contains(&day)
+}
+
+/// The value of ASCII digits
+fn number(digits: &[u8]) -> u32 {
+    digits.iter().fold(0, |n, &d| n * 10 + u32::from(d - b'0'))
+}
+
+/// `YYYY-MM-DDThh:mm:ss`, then optional fractional seconds and zone, all
+/// in range (hours to 23, zones to ±14:00)
+fn is_date_time(value: &str) -> bool {
+    let b = value.as_bytes();
+    if b.len() < 19 || !b[..19].is_ascii() || !is_date(&value[..10]) || b[10] != b'T' {
+        return false;
+    }
+    let time = b[11..19].iter().enumerate().all(|(i, &c)| match i {
+        2 | 5 => c == b':',
+        _ => c.is_ascii_digit(),
+    }) && number(&b[11..13]) < 24
+        && number(&b[14..16]) < 60
+        && number(&b[17..19]) < 60;
+    let mut rest = &value[19..];
+    if let Some(frac) = rest.strip_prefix('.') {
+        let len = frac.bytes().take_while(u8::is_ascii_digit).count();
+        if len == 0 {
+            return false;
+        }
+        rest = &frac[len..];
+    }
+    let zone = match rest.as_bytes() {
+        [] | [b'Z'] => true,
+        [b'+' | b'-', h1, h2, b':', m1, m2] => {
+            let digits = [*h1, *h2, *m1, *m2];
+            let (hours, minutes) = (number(&digits[..2]), number(&digits[2..]));
+            digits.iter().all(u8::is_ascii_digit)
+                && minutes < 60
+                && (hours < 14 || (hours == 14 && minutes == 0))
+        }
+        _ => false,
+    };
+    time && zone
+}
+
+/// One element name under one parent declaration
+struct Decl {
+    name: String,
+    /// Elements seen
+    count: usize,
+    /// Parent elements with at least one of these, and the most in one
+    present_in: usize,
+    max_per_parent: usize,
+    /// Child declarations in order of first appearance
+    children: Vec<usize>,
+    /// Children appeared out of that order somewhere
+    unordered: bool,
+    attributes: Vec<AttributeDecl>,
+    /// Type of the content of elements without element children
+    value: Option<ValueType>,
+    /// Non-whitespace text beside element children
+    mixed: bool,
+}
+
+struct AttributeDecl {
+    name: String,
+    count: usize,
+    value: ValueType,
+}
+
+#[derive(Default)]
+struct Inference {
+    decls: Vec<Decl>,
+    ids: HashMap<(usize, String), usize>,
+    roots: Vec<usize>,
+    target_namespace: Option<String>,
+}
+
+/// An XSD describing the structure `views` have in common
+pub fn infer_schema(views: &[IndexedDocumentView]) -> String {
+    let mut inference = Inference::default();
+    for view in views {
+        inference.add(view);
+    }
+    inference.write()
+}
+
+impl Inference {
+    fn add(&mut self, view: &IndexedDocumentView) {
+        let mut stack = Vec::new();
+        self.visit_children(view, view.document_node_id(), NO_DECL, &mut stack);
+        while let Some((node, decl)) = stack.pop() {
+            self.decls[decl].count += 1;
+            for (name, value) in view.get_attribute_values(node) {
+                self.add_attribute(decl, name, value);
+            }
+            let (has_elements, has_text) = self.visit_children(view, node, decl, &mut stack);
+            let decl = &mut self.decls[decl];
+            if has_elements {
+                decl.mixed |= has_text;
+            } else {
+                let value = ValueType::of(&node_string_value(view, node));
+                decl.value = Some(decl.value.map_or(value, |seen| seen.merge(value)));
+            }
+        }
+    }
+
+    /// Record the element children of `node` under `parent`, queueing them
+    /// on `stack`; returns whether it has element children and whether it
+    /// has non-whitespace text
+    fn visit_children(
+        &mut self,
+        view: &IndexedDocumentView,
+        node: NodeId,
+        parent: usize,
+        stack: &mut Vec<(NodeId, usize)>,
+    ) -> (bool, bool) {
+        let mut counts: Vec<(usize, usize)> = Vec::new();
+        let mut last_position = 0;
+        let mut has_text = false;
+        let first = stack.len();
+        for child in view.children_vec(node) {
+            match view.node_kind_of(child) {
+                NodeKind::Element => {}
+                NodeKind::Text | NodeKind::CData => {
+                    has_text |= !node_string_value(view, child).trim().is_empty();
+                    continue;
+                }
+                _ => continue,
+            }
+            let name = view.node_name(child).unwrap_or_default();
+            if parent == NO_DECL && self.decls.is_empty() {
+                self.target_namespace = match name.split_once(':') {
+                    Some((prefix, _)) => view.lookup_namespace_uri(child, prefix),
+                    None => view.get_attribute(child, "xmlns"),
+                }
+                .filter(|uri| !uri.is_empty())
+                .map(str::to_string);
+            }
+            let local = name.rsplit(':').next().unwrap_or(name);
+            let decl = self.decl(parent, local);
+            match counts.iter_mut().find(|(seen, _)| *seen == decl) {
+                Some((_, count)) => *count += 1,
+                None => counts.push((decl, 1)),
+            }
+            if parent != NO_DECL {
+                let position = self.decls[parent]
+                    .children
+                    .iter()
+                    .position(|&d| d == decl)
+                    .unwrap_or(0);
+                self.decls[parent].unordered |= position < last_position;
+                last_position = position;
+            }
+            stack.push((child, decl));
+        }
+        // Children first in document order, so grandchildren are met in order
+        stack[first..].reverse();
+        for &(decl, count) in &counts {
+            let decl = &mut self.decls[decl];
+            decl.present_in += 1;
+            decl.max_per_parent = decl.max_per_parent.max(count);
+        }
+        (!counts.is_empty(), has_text)
+    }
+
+    fn decl(&mut self, parent: usize, name: &str) -> usize {
+        if let Some(&id) = self.ids.get(&(parent, name.to_string())) {
+            return id;
+        }
+        let id = self.decls.len();
+        self.decls.push(Decl {
+            name: name.to_string(),
+            count: 0,
+            present_in: 0,
+            max_per_parent: 0,
+            children: Vec::new(),
+            unordered: false,
+            attributes: Vec::new(),
+            value: None,
+            mixed: false,
+        });
+        self.ids.insert((parent, name.to_string()), id);
+        match self.decls.get_mut(parent) {
+            Some(parent) => parent.children.push(id),
+            None => self.roots.push(id),
+        }
+        id
+    }
+
+    fn add_attribute(&mut self, decl: usize, name: &str, value: &str) {
+        if name == "xmlns" || name.contains(':') {
+            return;
+        }
+        let value = ValueType::of(value);
+        let attributes = &mut self.decls[decl].attributes;
+        match attributes.iter_mut().find(|attr| attr.name == name) {
+            Some(attr) => {
+                attr.count += 1;
+                attr.value = attr.value.merge(value);
+            }
+            None => attributes.push(AttributeDecl {
+                name: name.to_string(),
+                count: 1,
+                value,
+            }),
+        }
+    }
+
+    fn write(&self) -> String {
+        let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        out.push_str("<xs:schema xmlns:xs=\"http://www.w3.org/2001/XMLSchema\"");
+        out.push_str(" elementFormDefault=\"qualified\"");
+        if let Some(uri) = &self.target_namespace {
+            let uri = escape(uri);
+            out.push_str(&format!(" targetNamespace=\"{uri}\" xmlns=\"{uri}\""));
+        }
+        out.push_str(">\n");
+        for &root in &self.roots {
+            self.write_element(&mut out, root, 1, "");
+        }
+        out.push_str("</xs:schema>\n");
+        out
+    }
+
+    fn write_element(&self, out: &mut String, id: usize, depth: usize, occurs: &str) {
+        let decl = &self.decls[id];
+        let pad = "  ".repeat(depth);
+        let open = format!("{pad}<xs:element name=\"{}\"{occurs}", escape(&decl.name));
+        let value = decl.value.filter(|&v| v != ValueType::Empty);
+
+        if decl.children.is_empty() && decl.attributes.is_empty() {
+            match value {
+                Some(value) => out.push_str(&format!("{open} type=\"{}\"/>\n", value.xsd())),
+                None => out.push_str(&format!(
+                    "{open}>\n{pad}  <xs:complexType/>\n{pad}</xs:element>\n"
+                )),
+            }
+            return;
+        }
+
+        out.push_str(&format!("{open}>\n"));
+        if decl.children.is_empty() {
+            match value {
+                Some(value) => {
+                    out.push_str(&format!(
+                        "{pad}  <xs:complexType>\n{pad}    <xs:simpleContent>\n"
+                    ));
+                    out.push_str(&format!(
+                        "{pad}      <xs:extension base=\"{}\">\n",
+                        value.xsd()
+                    ));
+                    self.write_attributes(out, decl, depth + 4);
+                    out.push_str(&format!(
+                        "{pad}      </xs:extension>\n{pad}    </xs:simpleContent>\n"
+                    ));
+                }
+                None => {
+                    out.push_str(&format!("{pad}  <xs:complexType>\n"));
+                    self.write_attributes(out, decl, depth + 2);
+                }
+            }
+        } else {
+            let mixed = if decl.mixed || value.is_some() {
+                " mixed=\"true\""
+            } else {
+                ""
+            };
+            out.push_str(&format!("{pad}  <xs:complexType{mixed}>\n"));
+            if decl.unordered {
+                out.push_str(&format!(
+                    "{pad}    <xs:choice minOccurs=\"0\" maxOccurs=\"unbounded\">\n"
+                ));
+            } else {
+                out.push_str(&format!("{pad}    <xs:sequence>\n"));
+            }
+            for &child in &decl.children {
+                let occurs = if decl.unordered {
+                    String::new()
+                } else {
+                    self.occurs(decl, child)
+                };
+                self.write_element(out, child, depth + 3, &occurs);
+            }
+            let group = if decl.unordered { "choice" } else { "sequence" };
+            out.push_str(&format!("{pad}    </xs:{group}>\n"));
+            self.write_attributes(out, decl, depth + 2);
+        }
+        out.push_str(&format!("{pad}  </xs:complexType>\n{pad}</xs:element>\n"));
+    }
+
+    /// `minOccurs`/`maxOccurs` of a child declaration within its parent
+    fn occurs(&self, parent: &Decl, child: usize) -> String {
+        let child = &self.decls[child];
+        let mut occurs = String::new();
+        if child.present_in < parent.count {
+            occurs.push_str(" minOccurs=\"0\"");
+        }
+        if child.max_per_parent > 1 {
+            occurs.push_str(" maxOccurs=\"unbounded\"");
+        }
+        occurs
+    }
+
+    fn write_attributes(&self, out: &mut String, decl: &Decl, depth: usize) {
+        let pad = "  ".repeat(depth);
+        for attr in &decl.attributes {
+            let used = if attr.count == decl.count {
+                " use=\"required\""
+            } else {
+                ""
+            };
+            out.push_str(&format!(
+                "{pad}<xs:attribute name=\"{}\" type=\"{}\"{used}/>\n",
+                escape(&attr.name),
+                attr.value.xsd()
+            ));
+        }
+    }
+}
+
+fn escape(value: &str) -> String {
+    let mut out = Vec::with_capacity(value.len());
+    crate::template::escape_into(&mut out, value, crate::template::Context::Attribute);
+    String::from_utf8(out).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::builder::build_index;
+
+    fn schema(docs: &[&str]) -> String {
+        let indexes: Vec<_> = docs.iter().map(|xml| build_index(xml.as_bytes())).collect();
+        let views: Vec<_> = indexes
+            .iter()
+            .zip(docs)
+            .map(|(index, xml)| IndexedDocumentView::new(index, xml.as_bytes()))
+            .collect();
+        infer_schema(&views)
+    }
+
+    #[test]
+    fn test_value_types() {
+        let types: Vec<ValueType> = [
+            "",
+            " 42 ",
+            "-7",
+            "3.14",
+            ".5",
+            "true",
+            "2024-02-29",
+            "2024-02-29T10:00:00Z",
+            "2024-02-29T10:00:00.5+01:00",
+            "1e5",
+            ".",
+            "2024-2-29",
+            "2023-02-29",
+            "2024-13-01",
+            "0000-01-01",
+            "2024-02-29T24:00:00",
+            "2024-02-29T10:60:00",
+            "2024-02-29T10:00:00+15:00",
+            "2024-02-29T23:59:59-14:00",
+        ]
+        .iter()
+        .map(|v| ValueType::of(v))
+        .collect();
+        use ValueType::*;
+        assert_eq!(
+            types,
+            [
+                Empty, Integer, Integer, Decimal, Decimal, Boolean, Date, DateTime, DateTime,
+                String, String, String, String, String, String, String, String, String, DateTime
+            ]
+        );
+        assert_eq!(Integer.merge(Decimal), Decimal);
+        assert_eq!(Integer.merge(Empty), String);
+        assert_eq!(Date.merge(Date), Date);
+    }
+
+    #[test]
+    fn test_infer_schema_from_samples() {
+        let xsd = schema(&[
+            "<catalog xmlns='urn:c' v='1'><product id='1' sale='true'><name>A</name>\
+             <price>1</price><tag>x</tag><tag>y</tag></product></catalog>",
+            "<catalog xmlns='urn:c' v='2'><product id='2'><name>B</name><price>2.5</price>\
+             </product><product id='3'><name>C &amp; D</name><price>3</price><note/>\
+             </product></catalog>",
+        ]);
+        assert_eq!(
+            xsd,
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<xs:schema xmlns:xs="http://www.w3.org/2001/XMLSchema" elementFormDefault="qualified" targetNamespace="urn:c" xmlns="urn:c">
+  <xs:element name="catalog">
+    <xs:complexType>
+      <xs:sequence>
+        <xs:element name="product" maxOccurs="unbounded">
+          <xs:complexType>
+            <xs:sequence>
+              <xs:element name="name" type="xs:string"/>
+              <xs:element name="price" type="xs:decimal"/>
+              <xs:element name="tag" minOccurs="0" maxOccurs="unbounded" type="xs:string"/>
+              <xs:element name="note" minOccurs="0">
+                <xs:complexType/>
+              </xs:element>
+            </xs:sequence>
+            <xs:attribute name="id" type="xs:integer" use="required"/>
+            <xs:attribute name="sale" type="xs:boolean"/>
+          </xs:complexType>
+        </xs:element>
+      </xs:sequence>
+      <xs:attribute name="v" type="xs:integer" use="required"/>
+    </xs:complexType>
+  </xs:element>
+</xs:schema>
+"#
+        );
+    }
+
+    #[test]
+    fn test_prefixed_root_namespace() {
+        let xsd = schema(&["<c:catalog xmlns:c='urn:c'><c:item/></c:catalog>"]);
+        assert!(
+            xsd.contains(r#"targetNamespace="urn:c" xmlns="urn:c""#),
+            "{xsd}"
+        );
+        assert!(xsd.contains(r#"<xs:element name="catalog">"#), "{xsd}");
+    }
+
+    #[test]
+    fn test_infer_schema_unordered_and_mixed() {
+        let xsd = schema(&["<r><p>a <b>b</b> <i/></p><p><i/><b k='v'>e</b></p></r>"]);
+        assert_eq!(
+            xsd,
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<xs:schema xmlns:xs="http://www.w3.org/2001/XMLSchema" elementFormDefault="qualified">
+  <xs:element name="r">
+    <xs:complexType>
+      <xs:sequence>
+        <xs:element name="p" maxOccurs="unbounded">
+          <xs:complexType mixed="true">
+            <xs:choice minOccurs="0" maxOccurs="unbounded">
+              <xs:element name="b">
+                <xs:complexType>
+                  <xs:simpleContent>
+                    <xs:extension base="xs:string">
+                      <xs:attribute name="k" type="xs:string"/>
+                    </xs:extension>
+                  </xs:simpleContent>
+                </xs:complexType>
+              </xs:element>
+              <xs:element name="i">
+                <xs:complexType/>
+              </xs:element>
+            </xs:choice>
+          </xs:complexType>
+        </xs:element>
+      </xs:sequence>
+    </xs:complexType>
+  </xs:element>
+</xs:schema>
+"#
+        );
+        assert_eq!(schema(&[]).lines().count(), 3);
+    }
+}
@@ -0,0 +1,452 @@
+//! Schematron Validation
+//!
+//! Compiles the rule-based subset of ISO Schematron: patterns of rules, each
+//! with a `context` and `assert`/`report` checks whose tests and message
+//! `value-of` selections run on the XPath engine. Schema elements are
+//! matched by local name, so both the ISO and the 1.5 namespace work.
+//!
+//! As in Schematron, a node is checked by at most one rule per pattern: the
+//! first one whose context matches it. Contexts are XSLT patterns, so a
+//! relative one such as `entry[@id]` matches at any depth (`//entry[@id]`).
+//! `let` variables, phases, abstract patterns and `extends` are not
+//! supported; abstract rules are skipped.
+//!
+//! Documents are queried without namespace resolution, so a prefixed name
+//! test could never match and a schema would pass without checking
+//! anything. Schemas declaring prefixes with `<ns>`, and expressions using
+//! prefixed names (`x:order`, `x:*`), are rejected; match namespaced nodes
+//! by `local-name()` instead.
+
+use crate::core::entities::decode_text;
+use crate::dom::{self, DocumentAccess, NodeId, NodeKind};
+use crate::index::builder::build_index;
+use crate::index::IndexedDocumentView;
+use crate::xpath::compiler::{compile, CompiledExpr};
+use crate::xpath::eval::{evaluate_precompiled, evaluate_precompiled_from_node};
+use crate::xpath::parser::{parse, Expr, NodeTest, Step};
+use crate::xpath::XPathValue;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+/// A compiled Schematron schema
+pub struct Schema {
+    patterns: Vec<Pattern>,
+}
+
+struct Pattern {
+    rules: Vec<Rule>,
+}
+
+struct Rule {
+    context: Arc<CompiledExpr>,
+    checks: Vec<Check>,
+}
+
+struct Check {
+    kind: CheckKind,
+    test: String,
+    compiled: Arc<CompiledExpr>,
+    id: Option<String>,
+    role: Option<String>,
+    message: Vec<MessagePart>,
+}
+
+/// Whether a result comes from a failed `assert` or a fired `report`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckKind {
+    Assert,
+    Report,
+}
+
+enum MessagePart {
+    Text(String),
+    ValueOf(Arc<CompiledExpr>),
+    /// `<name/>`, of the context node or of the first node `path` selects
+    Name(Option<Arc<CompiledExpr>>),
+}
+
+/// A failed assertion or fired report
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Failure {
+    pub kind: CheckKind,
+    pub id: Option<String>,
+    pub role: Option<String>,
+    /// The check's `test` expression
+    pub test: String,
+    /// Location of the context node, like `/feed[1]/entry[3]`
+    pub path: String,
+    /// The check's message with `value-of` and `name` filled in
+    pub message: String,
+}
+
+/// Compile a Schematron schema from its XML
+pub fn compile_schema(rules_xml: &[u8]) -> Result<Schema, String> {
+    dom::validate_strict(rules_xml)?;
+    let index = build_index(rules_xml);
+    let doc = IndexedDocumentView::new(&index, rules_xml);
+    let root = doc
+        .root_element_id()
+        .filter(|&id| doc.node_local_name(id) == Some("schema"))
+        .ok_or("root element must be a Schematron schema")?;
+
+    if child_elements(&doc, root, "ns").next().is_some() {
+        return Err(
+            "ns declarations are not supported; match namespaced nodes by local-name()".to_string(),
+        );
+    }
+
+    let mut patterns = Vec::new();
+    for pattern in child_elements(&doc, root, "pattern") {
+        if doc.get_attribute(pattern, "abstract") == Some("true") {
+            continue;
+        }
+        let mut rules = Vec::new();
+        for rule in child_elements(&doc, pattern, "rule") {
+            if doc.get_attribute(rule, "abstract") == Some("true") {
+                continue;
+            }
+            rules.push(compile_rule(&doc, rule)?);
+        }
+        patterns.push(Pattern { rules });
+    }
+    Ok(Schema { patterns })
+}
+
+fn compile_rule<D: DocumentAccess>(doc: &D, rule: NodeId) -> Result<Rule, String> {
+    let context = attribute(doc, rule, "context").ok_or("rule without a context attribute")?;
+    let mut checks = Vec::new();
+    for child in doc.children_vec(rule) {
+        let kind = match element_local_name(doc, child) {
+            Some("assert") => CheckKind::Assert,
+            Some("report") => CheckKind::Report,
+            Some("let") => return Err("let variables are not supported".to_string()),
+            _ => continue,
+        };
+        let test =
+            attribute(doc, child, "test").ok_or("assert or report without a test attribute")?;
+        checks.push(Check {
+            kind,
+            compiled: compile_xpath(&test)?,
+            test,
+            id: attribute(doc, child, "id"),
+            role: attribute(doc, child, "role"),
+            message: compile_message(doc, child)?,
+        });
+    }
+    Ok(Rule {
+        context: compile_xpath(&context_xpath(&context))?,
+        checks,
+    })
+}
+
+fn compile_message<D: DocumentAccess>(doc: &D, check: NodeId) -> Result<Vec<MessagePart>, String> {
+    let mut parts = Vec::new();
+    for child in doc.children_vec(check) {
+        match doc.node_kind_of(child) {
+            NodeKind::Text | NodeKind::CData => {
+                parts.push(MessagePart::Text(dom::node_string_value(doc, child)));
+            }
+            NodeKind::Element => match element_local_name(doc, child) {
+                Some("value-of") => {
+                    let select = attribute(doc, child, "select")
+                        .ok_or("value-of without a select attribute")?;
+                    parts.push(MessagePart::ValueOf(compile_xpath(&select)?));
+                }
+                Some("name") => {
+                    let path = attribute(doc, child, "path").map(|path| compile_xpath(&path));
+                    parts.push(MessagePart::Name(path.transpose()?));
+                }
+                // emph, dir, span: keep their text
+                _ => parts.push(MessagePart::Text(dom::node_string_value(doc, child))),
+            },
+            _ => {}
+        }
+    }
+    Ok(parts)
+}
+
+fn compile_xpath(xpath: &str) -> Result<Arc<CompiledExpr>, String> {
+    let fail = |e: String| format!("{}: {}", xpath, e);
+    if uses_prefix(&parse(xpath).map_err(fail)?) {
+        return Err(fail(
+            "prefixed names are not supported; use local-name()".to_string(),
+        ));
+    }
+    compile(xpath).map_err(fail)
+}
+
+fn uses_prefix(expr: &Expr) -> bool {
+    let step_uses_prefix = |step: &Step| {
+        matches!(
+            step.node_test,
+            NodeTest::QName(..) | NodeTest::NamespaceWildcard(_)
+        ) || step.predicates.iter().any(uses_prefix)
+    };
+    match expr {
+        Expr::Union(a, b) | Expr::Filter(a, b) | Expr::Binary(a, _, b) => {
+            uses_prefix(a) || uses_prefix(b)
+        }
+        Expr::Path(base, step) => uses_prefix(base) || step_uses_prefix(step),
+        Expr::Step(step) => step_uses_prefix(step),
+        Expr::Function(_, args) => args.iter().any(uses_prefix),
+        Expr::Negate(inner) => uses_prefix(inner),
+        _ => false,
+    }
+}
+
+/// XPath selecting the nodes an XSLT match pattern matches: alternatives
+/// that aren't absolute match at any depth
+fn context_xpath(pattern: &str) -> String {
+    let mut alternatives = Vec::new();
+    let (mut depth, mut quote, mut start) = (0i32, None, 0);
+    for (i, c) in pattern.char_indices() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '\'' | '"') => quote = Some(c),
+            (None, '[' | '(') => depth += 1,
+            (None, ']' | ')') => depth -= 1,
+            (None, '|') if depth == 0 => {
+                alternatives.push(&pattern[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    alternatives.push(&pattern[start..]);
+    alternatives
+        .iter()
+        .map(|alternative| {
+            let alternative = alternative.trim();
+            if alternative.starts_with('/') {
+                alternative.to_string()
+            } else {
+                format!("//{}", alternative)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" | ")
+}
+
+/// Run a schema's checks over a document, returning failed assertions and
+/// fired reports in pattern, rule and document order
+pub fn validate<D: DocumentAccess>(schema: &Schema, doc: &D) -> Result<Vec<Failure>, String> {
+    let mut failures = Vec::new();
+    for pattern in &schema.patterns {
+        let mut checked = HashSet::new();
+        for rule in &pattern.rules {
+            let XPathValue::NodeSet(nodes) = evaluate_precompiled(doc, &rule.context)? else {
+                return Err("rule context must select nodes".to_string());
+            };
+            for node in nodes {
+                if !checked.insert(node) {
+                    continue;
+                }
+                for check in &rule.checks {
+                    let passed = evaluate_precompiled_from_node(doc, node, &check.compiled)
+                        .map_err(|e| format!("{}: {}", check.test, e))?
+                        .to_boolean();
+                    // An assert reports when its test fails, a report when it holds
+                    if passed == (check.kind == CheckKind::Report) {
+                        failures.push(Failure {
+                            kind: check.kind,
+                            id: check.id.clone(),
+                            role: check.role.clone(),
+                            test: check.test.clone(),
+                            path: node_path(doc, node),
+                            message: message(doc, node, &check.message)?,
+                        });
+                    }
+                }
+            }
+        }
+    }
+    Ok(failures)
+}
+
+/// A check's message for `node`, with whitespace collapsed
+fn message<D: DocumentAccess>(
+    doc: &D,
+    node: NodeId,
+    parts: &[MessagePart],
+) -> Result<String, String> {
+    let mut text = String::new();
+    for part in parts {
+        match part {
+            MessagePart::Text(s) => text.push_str(s),
+            MessagePart::ValueOf(select) => {
+                let value = evaluate_precompiled_from_node(doc, node, select)?;
+                text.push_str(&string_value(doc, &value));
+            }
+            MessagePart::Name(path) => {
+                let target = match path {
+                    Some(path) => match evaluate_precompiled_from_node(doc, node, path)? {
+                        XPathValue::NodeSet(nodes) => nodes.first().copied(),
+                        _ => None,
+                    },
+                    None => Some(node),
+                };
+                text.push_str(target.and_then(|id| doc.node_name(id)).unwrap_or(""));
+            }
+        }
+    }
+    Ok(text.split_whitespace().collect::<Vec<_>>().join(" "))
+}
+
+/// XPath `string()` of a value
+fn string_value<D: DocumentAccess>(doc: &D, value: &XPathValue) -> String {
+    match value {
+        XPathValue::NodeSet(nodes) => nodes
+            .first()
+            .map(|&id| dom::node_string_value(doc, id))
+            .unwrap_or_default(),
+        XPathValue::StringList(values) => values.first().cloned().unwrap_or_default(),
+        _ => value.to_string_value(),
+    }
+}
+
+/// XPath locating a node from the document node, like `/feed[1]/entry[3]`
+fn node_path<D: DocumentAccess>(doc: &D, id: NodeId) -> String {
+    let mut steps = Vec::new();
+    let mut current = Some(id);
+    while let Some(node) = current.filter(|&node| node != doc.document_node_id()) {
+        let kind = doc.node_kind_of(node);
+        let test = match kind {
+            NodeKind::Element => doc.node_name(node).unwrap_or("*").to_string(),
+            NodeKind::Text | NodeKind::CData => "text()".to_string(),
+            NodeKind::Comment => "comment()".to_string(),
+            NodeKind::ProcessingInstruction => "processing-instruction()".to_string(),
+            NodeKind::Document => break,
+        };
+        let mut position = 1;
+        let mut sibling = doc.prev_sibling_of(node);
+        while let Some(prev) = sibling {
+            let same = match kind {
+                NodeKind::Element => {
+                    doc.node_kind_of(prev) == kind && doc.node_name(prev) == doc.node_name(node)
+                }
+                NodeKind::Text | NodeKind::CData => {
+                    matches!(doc.node_kind_of(prev), NodeKind::Text | NodeKind::CData)
+                }
+                _ => doc.node_kind_of(prev) == kind,
+            };
+            position += usize::from(same);
+            sibling = doc.prev_sibling_of(prev);
+        }
+        steps.push(format!("{}[{}]", test, position));
+        current = doc.parent_of(node);
+    }
+    steps.reverse();
+    format!("/{}", steps.join("/"))
+}
+
+/// An attribute's value with entity references decoded
+fn attribute<D: DocumentAccess>(doc: &D, id: NodeId, name: &str) -> Option<String> {
+    let raw = doc.get_attribute(id, name)?;
+    Some(String::from_utf8_lossy(&decode_text(raw.as_bytes())).into_owned())
+}
+
+fn element_local_name<D: DocumentAccess>(doc: &D, id: NodeId) -> Option<&str> {
+    match doc.node_kind_of(id) {
+        NodeKind::Element => doc.node_local_name(id),
+        _ => None,
+    }
+}
+
+fn child_elements<'d, D: DocumentAccess>(
+    doc: &'d D,
+    parent: NodeId,
+    local_name: &'d str,
+) -> impl Iterator<Item = NodeId> + 'd {
+    doc.children_vec(parent)
+        .into_iter()
+        .filter(move |&id| element_local_name(doc, id) == Some(local_name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCHEMA: &[u8] = br#"<schema xmlns="http://purl.oclc.org/dsdl/schematron">
+      <pattern>
+        <rule context="patient[@status = 'deceased']">
+          <assert test="deathDate">A deceased patient needs a deathDate</assert>
+        </rule>
+        <rule context="patient">
+          <assert test="@id" id="pat-1" role="error">Patient <name/> needs an id</assert>
+          <report test="count(name) &gt; 1">Patient <value-of select="@id"/> has
+            <value-of select="count(name)"/> names</report>
+        </rule>
+      </pattern>
+    </schema>"#;
+
+    fn run(xml: &[u8]) -> Vec<Failure> {
+        let schema = compile_schema(SCHEMA).unwrap();
+        let index = build_index(xml);
+        validate(&schema, &IndexedDocumentView::new(&index, xml)).unwrap()
+    }
+
+    #[test]
+    fn test_asserts_and_reports() {
+        let failures = run(br#"<records><patient id="7"><name/></patient>
+            <patient><name/></patient><patient id="9"><name/><name/></patient></records>"#);
+
+        assert_eq!(failures.len(), 2);
+        assert_eq!(failures[0].kind, CheckKind::Assert);
+        assert_eq!(failures[0].id.as_deref(), Some("pat-1"));
+        assert_eq!(failures[0].role.as_deref(), Some("error"));
+        assert_eq!(failures[0].path, "/records[1]/patient[2]");
+        assert_eq!(failures[0].message, "Patient patient needs an id");
+        assert_eq!(failures[1].kind, CheckKind::Report);
+        assert_eq!(failures[1].path, "/records[1]/patient[3]");
+        assert_eq!(failures[1].message, "Patient 9 has 2 names");
+    }
+
+    #[test]
+    fn test_first_matching_rule_wins() {
+        // The deceased patient has no id, but only the first rule checks it
+        let failures = run(br#"<records><patient status="deceased"/></records>"#);
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].message, "A deceased patient needs a deathDate");
+    }
+
+    #[test]
+    fn test_context_xpath() {
+        assert_eq!(context_xpath("a[@x = '|'] | /b"), "//a[@x = '|'] | /b");
+        assert_eq!(context_xpath("/"), "/");
+    }
+
+    #[test]
+    fn test_compile_errors() {
+        assert!(compile_schema(b"<rules/>").is_err());
+        assert!(compile_schema(b"<schema><pattern><rule/></pattern></schema>").is_err());
+        let bad_test = br#"<schema><pattern><rule context="a">
+            <assert test="((">x</assert></rule></pattern></schema>"#;
+        assert!(compile_schema(bad_test).err().unwrap().starts_with("(("));
+    }
+
+    #[test]
+    fn test_rejects_prefixes() {
+        let with_ns = br#"<schema><ns prefix="x" uri="urn:x"/><pattern><rule context="a">
+            <assert test="b">x</assert></rule></pattern></schema>"#;
+        assert!(compile_schema(with_ns)
+            .err()
+            .unwrap()
+            .starts_with("ns declarations"));
+        for xpath in ["x:order", "a[x:b]", "@x:id", "x:*", "count(//a/x:b) > 0"] {
+            let schema = format!(
+                r#"<schema><pattern><rule context="a"><assert test="{xpath}">x</assert>
+                </rule></pattern></schema>"#
+            );
+            let err = compile_schema(schema.as_bytes()).err().unwrap();
+            assert!(err.contains("prefixed names"), "{xpath}: {err}");
+        }
+        let local_name = br#"<schema><pattern><rule context="*[local-name() = 'order']">
+            <assert test="*[local-name() = 'id']">x</assert></rule></pattern></schema>"#;
+        let schema = compile_schema(local_name).unwrap();
+        let xml = br#"<x:orders xmlns:x="urn:x"><x:order/></x:orders>"#;
+        let index = build_index(xml);
+        let failures = validate(&schema, &IndexedDocumentView::new(&index, xml)).unwrap();
+        assert_eq!(failures.len(), 1);
+    }
+}
@@ -0,0 +1,188 @@
+//! Parser Statistics
+//!
+//! Process-wide counters for export to metrics systems. Each counter is a
+//! relaxed atomic: totals are exact, but a snapshot taken while other
+//! threads are parsing may combine values from slightly different moments.
+//!
+//! `measure` reports on a single call instead: its duration, the peak
+//! memory it held and how many nodes it visited. Both of the latter are
+//! tracked per thread, so work handed to the rayon pool is not included.
+
+use std::cell::Cell;
+use std::sync::atomic::{AtomicU64, Ordering::Relaxed};
+use std::time::{Duration, Instant};
+
+static DOCUMENTS_PARSED: AtomicU64 = AtomicU64::new(0);
+static BYTES_SCANNED: AtomicU64 = AtomicU64::new(0);
+static INDEX_BUILD_NANOS: AtomicU64 = AtomicU64::new(0);
+static XPATH_QUERIES: AtomicU64 = AtomicU64::new(0);
+static CACHE_HITS: AtomicU64 = AtomicU64::new(0);
+static STRICT_FAILURES: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    static NODES_VISITED: Cell<u64> = const { Cell::new(0) };
+}
+
+/// Counter values at one point in time
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ParserStats {
+    /// Structural indexes built
+    pub documents_parsed: u64,
+    /// Input bytes those indexes were built from
+    pub bytes_scanned: u64,
+    /// Total time spent building them
+    pub index_build_nanos: u64,
+    /// XPath expressions run, each string passed to a query counting once
+    pub xpath_queries: u64,
+    /// Queries answered from a document's result cache
+    pub cache_hits: u64,
+    /// Inputs rejected by strict parsing
+    pub strict_failures: u64,
+}
+
+impl ParserStats {
+    /// Mean index build time in microseconds (0 before any build)
+    pub fn average_index_build_us(&self) -> f64 {
+        if self.documents_parsed == 0 {
+            return 0.0;
+        }
+        self.index_build_nanos as f64 / self.documents_parsed as f64 / 1000.0
+    }
+}
+
+/// Count a structural index built from `bytes` of input in `elapsed`
+pub fn record_index_build(bytes: usize, elapsed: Duration) {
+    DOCUMENTS_PARSED.fetch_add(1, Relaxed);
+    BYTES_SCANNED.fetch_add(bytes as u64, Relaxed);
+    INDEX_BUILD_NANOS.fetch_add(elapsed.as_nanos().min(u64::MAX as u128) as u64, Relaxed);
+}
+
+#[inline]
+pub fn record_xpath_query() {
+    XPATH_QUERIES.fetch_add(1, Relaxed);
+}
+
+#[inline]
+pub fn record_cache_hit() {
+    CACHE_HITS.fetch_add(1, Relaxed);
+}
+
+#[inline]
+pub fn record_strict_failure() {
+    STRICT_FAILURES.fetch_add(1, Relaxed);
+}
+
+/// Count nodes examined while answering a query on this thread
+#[inline]
+pub fn record_nodes_visited(count: usize) {
+    NODES_VISITED.with(|visited| visited.set(visited.get() + count as u64));
+}
+
+/// Resources used by one call, from `measure`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CallUsage {
+    pub duration: Duration,
+    /// Most memory held above what was allocated at the start (0 without
+    /// the `memory_tracking` feature)
+    pub peak_alloc_bytes: usize,
+    pub nodes_visited: u64,
+}
+
+/// Run `f`, reporting the resources it used on the current thread
+pub fn measure<T>(f: impl FnOnce() -> T) -> (T, CallUsage) {
+    #[cfg(feature = "memory_tracking")]
+    let mark = crate::tracking::thread_peak_mark();
+    let visited_before = NODES_VISITED.with(Cell::get);
+    let started = Instant::now();
+
+    let result = f();
+
+    let duration = started.elapsed();
+    #[cfg(feature = "memory_tracking")]
+    let peak_alloc_bytes = crate::tracking::thread_peak_since(mark);
+    #[cfg(not(feature = "memory_tracking"))]
+    let peak_alloc_bytes = 0;
+    let usage = CallUsage {
+        duration,
+        peak_alloc_bytes,
+        nodes_visited: NODES_VISITED.with(Cell::get) - visited_before,
+    };
+    (result, usage)
+}
+
+/// Current counter values
+pub fn snapshot() -> ParserStats {
+    ParserStats {
+        documents_parsed: DOCUMENTS_PARSED.load(Relaxed),
+        bytes_scanned: BYTES_SCANNED.load(Relaxed),
+        index_build_nanos: INDEX_BUILD_NANOS.load(Relaxed),
+        xpath_queries: XPATH_QUERIES.load(Relaxed),
+        cache_hits: CACHE_HITS.load(Relaxed),
+        strict_failures: STRICT_FAILURES.load(Relaxed),
+    }
+}
+
+/// Set every counter back to zero
+pub fn reset() {
+    for counter in [
+        &DOCUMENTS_PARSED,
+        &BYTES_SCANNED,
+        &INDEX_BUILD_NANOS,
+        &XPATH_QUERIES,
+        &CACHE_HITS,
+        &STRICT_FAILURES,
+    ] {
+        counter.store(0, Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Other tests update the counters concurrently, so only check that
+    // they grew by at least the amounts recorded here
+    #[test]
+    fn test_counters_accumulate() {
+        let before = snapshot();
+        crate::index::builder::build_index(b"<r><a/></r>");
+        crate::xpath::compiler::compile("/r/a").unwrap();
+        record_strict_failure();
+        let after = snapshot();
+
+        assert!(after.documents_parsed > before.documents_parsed);
+        assert!(after.bytes_scanned >= before.bytes_scanned + 11);
+        assert!(after.xpath_queries > before.xpath_queries);
+        assert!(after.strict_failures > before.strict_failures);
+    }
+
+    #[test]
+    fn test_average_index_build_time() {
+        let stats = ParserStats {
+            documents_parsed: 4,
+            index_build_nanos: 10_000,
+            ..ParserStats::default()
+        };
+        assert_eq!(stats.average_index_build_us(), 2.5);
+        assert_eq!(ParserStats::default().average_index_build_us(), 0.0);
+    }
+
+    #[test]
+    fn test_measure_reports_call_usage() {
+        let input = b"<r><a/><b/><a/></r>";
+        let index = crate::index::builder::build_index(input);
+        let view = crate::index::IndexedDocumentView::new(&index, input);
+
+        let (value, usage) = measure(|| crate::xpath::evaluate(&view, "/r/a"));
+        assert!(matches!(value, Ok(crate::xpath::XPathValue::NodeSet(ref n)) if n.len() == 2));
+        // The root element, then its three children
+        assert_eq!(usage.nodes_visited, 4);
+
+        let (buffer, usage) = measure(|| vec![0u8; 1 << 20]);
+        drop(buffer);
+        if cfg!(feature = "memory_tracking") {
+            assert!(usage.peak_alloc_bytes >= 1 << 20);
+        }
+        assert_eq!(usage.nodes_visited, 0);
+    }
+}
@@ -13,9 +13,10 @@ pub fn evaluate_parallel<D: DocumentAccess + Sync>(
     doc: &D,
     xpaths: &[&str],
 ) -> Vec<Result<XPathValue, String>> {
+    let flag = crate::cancel::current();
     xpaths
         .par_iter()
-        .map(|xpath| evaluate(doc, xpath))
+        .map(|xpath| crate::cancel::run_with(flag.clone(), || evaluate(doc, xpath)))
         .collect()
 }
 
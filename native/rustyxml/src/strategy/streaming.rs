@@ -2,9 +2,10 @@
 //!
 //! Stateful parser that processes XML in chunks with bounded memory.
 
-use crate::core::attributes::parse_attributes;
+use crate::core::attributes::{parse_attributes, tag_attributes};
 use crate::core::tokenizer::{TokenKind, Tokenizer};
-use memchr::memchr_iter;
+use crate::index::{map_bytes, vec_bytes};
+use memchr::{memchr, memchr3, memmem};
 
 /// Stateful streaming XML parser
 pub struct StreamingParser {
@@ -13,7 +14,7 @@ pub struct StreamingParser {
     /// Parsed events ready to be consumed
     events: Vec<OwnedXmlEvent>,
     /// Complete elements ready to be consumed (faster path - no event rebuild)
-    complete_elements: Vec<Vec<u8>>,
+    complete_elements: Vec<StreamedElement>,
     /// Builder for current element being captured (start_pos in current chunk, accumulated bytes)
     element_builder: Option<ElementBuilder>,
     /// Whether we're inside an element (tracking quote state, etc.)
@@ -26,6 +27,38 @@ pub struct StreamingParser {
     inside_target_depth: usize,
     /// Skip event generation (when only complete_elements are needed)
     elements_only: bool,
+    /// Per-element size limit in bytes (None = unlimited)
+    max_element_size: Option<usize>,
+    /// What to do with elements that exceed `max_element_size`
+    oversize_mode: OversizeMode,
+    /// Count target elements without capturing their bytes
+    count_only: bool,
+    /// Target elements completed so far
+    matched: u64,
+    /// Progress of the safe-boundary search over `buffer`
+    boundary_scan: BoundaryScan,
+}
+
+/// Handling for target elements larger than the configured size limit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OversizeMode {
+    /// Drop the element's bytes and report `Oversized` when it closes
+    Skip,
+    /// Emit the element as a sequence of `Chunk`s followed by `ChunkEnd`
+    Chunk,
+}
+
+/// Item produced by the element fast path (`take_elements`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StreamedElement {
+    /// A complete target element as XML bytes
+    Complete(Vec<u8>),
+    /// A target element that exceeded the size limit and was skipped
+    Oversized { name: Vec<u8>, size: usize },
+    /// A piece of an oversized element (chunked mode)
+    Chunk { name: Vec<u8>, data: Vec<u8> },
+    /// Marks the end of a chunked element, with its total size in bytes
+    ChunkEnd { name: Vec<u8>, size: usize },
 }
 
 /// Builder for capturing complete elements
@@ -36,6 +69,12 @@ struct ElementBuilder {
     start_in_buffer: usize,
     /// Depth when we started (to know when we're done)
     start_depth: usize,
+    /// Element name (reported for oversized elements)
+    name: Vec<u8>,
+    /// Bytes already dropped or emitted as chunks
+    flushed: usize,
+    /// Element exceeded the size limit
+    oversized: bool,
 }
 
 /// Owned version of XmlEvent for storage
@@ -61,50 +100,373 @@ pub enum OwnedXmlEvent {
     },
 }
 
+impl OwnedXmlEvent {
+    /// Bytes held by the event's name, attributes and content
+    fn heap_bytes(&self) -> usize {
+        match self {
+            OwnedXmlEvent::StartElement { name, attributes }
+            | OwnedXmlEvent::EmptyElement { name, attributes } => {
+                vec_bytes(name)
+                    + vec_bytes(attributes)
+                    + attributes
+                        .iter()
+                        .map(|(key, value)| vec_bytes(key) + vec_bytes(value))
+                        .sum::<usize>()
+            }
+            OwnedXmlEvent::EndElement { name } => vec_bytes(name),
+            OwnedXmlEvent::Text(data)
+            | OwnedXmlEvent::CData(data)
+            | OwnedXmlEvent::Comment(data) => vec_bytes(data),
+            OwnedXmlEvent::ProcessingInstruction { target, data } => {
+                vec_bytes(target) + vec_bytes(data)
+            }
+        }
+    }
+}
+
+impl StreamedElement {
+    fn heap_bytes(&self) -> usize {
+        match self {
+            StreamedElement::Complete(data) => vec_bytes(data),
+            StreamedElement::Oversized { name, .. } | StreamedElement::ChunkEnd { name, .. } => {
+                vec_bytes(name)
+            }
+            StreamedElement::Chunk { name, data } => vec_bytes(name) + vec_bytes(data),
+        }
+    }
+}
+
 /// Find a safe boundary in a buffer (last `>` not inside quotes).
 ///
 /// Returns the byte offset just past the last valid `>`, or 0 if none found.
 /// Shared by `StreamingParser::process_buffer` and the streaming SAX NIFs.
 pub fn find_safe_boundary(buf: &[u8]) -> usize {
-    let len = buf.len();
-
-    if len < 1024 {
-        // Single-pass: scan once, track quotes, find last valid '>'
-        let mut last_valid_gt = 0;
-        let mut in_single = false;
-        let mut in_double = false;
-
-        for (i, &b) in buf.iter().enumerate() {
-            match b {
-                b'"' if !in_single => in_double = !in_double,
-                b'\'' if !in_double => in_single = !in_single,
-                b'>' if !in_single && !in_double => last_valid_gt = i + 1,
+    BoundaryScan::default().advance(buf)
+}
+
+/// A `find_safe_boundary` search that picks up where it left off
+///
+/// A buffer holding the start of a tag that hasn't finished arriving (a
+/// start tag with thousands of attributes, say) gets a chunk appended on
+/// every feed. Searching it from the start each time would make the tag
+/// cost quadratic time in its number of chunks; resuming scans each byte
+/// once.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct BoundaryScan {
+    /// Bytes of the buffer scanned so far
+    scanned: usize,
+    in_single: bool,
+    in_double: bool,
+    /// Offset just past the last `>` outside quotes, or 0
+    boundary: usize,
+}
+
+impl BoundaryScan {
+    /// The safe boundary of `buf`, which must be the buffer scanned before
+    /// with bytes appended (or a fresh one, after a reset)
+    pub fn advance(&mut self, buf: &[u8]) -> usize {
+        if buf.len() < self.scanned {
+            *self = Self::default();
+        }
+        let mut pos = self.scanned;
+        while let Some(i) = memchr3(b'"', b'\'', b'>', &buf[pos..]) {
+            pos += i;
+            match buf[pos] {
+                b'"' if !self.in_single => self.in_double = !self.in_double,
+                b'\'' if !self.in_double => self.in_single = !self.in_single,
+                b'>' if !self.in_single && !self.in_double => self.boundary = pos + 1,
                 _ => {}
             }
+            pos += 1;
+        }
+        self.scanned = buf.len();
+        self.boundary
+    }
+
+    /// Account for the buffer having been drained up to the boundary
+    /// `advance` returned. Bytes past it keep the quote state they were
+    /// scanned with, as no `>` outside quotes follows.
+    pub fn drain_to_boundary(&mut self) {
+        self.scanned -= self.boundary;
+        self.boundary = 0;
+    }
+}
+
+/// Find a CDATA section, comment or PI in `input` whose terminator is not
+/// in `input`.
+///
+/// A `>` inside such a section can produce a safe boundary mid-section;
+/// callers that stream CDATA incrementally cut the processable region at
+/// this offset. Markup is walked from the start, so a `<![CDATA[` inside a
+/// comment, PI or attribute value doesn't count as a section.
+pub fn unterminated_section_start(input: &[u8]) -> Option<usize> {
+    let mut pos = 0;
+    while let Some(i) = memchr(b'<', &input[pos..]) {
+        let start = pos + i;
+        let rest = &input[start..];
+        let (open, close): (&[u8], &[u8]) = if rest.starts_with(b"<![CDATA[") {
+            (b"<![CDATA[", b"]]>")
+        } else if rest.starts_with(b"<!--") {
+            (b"<!--", b"-->")
+        } else if rest.starts_with(b"<?") {
+            (b"<?", b"?>")
+        } else {
+            // A tag (or markup declaration): skip to its `>`, past quoted values
+            pos = start + tag_end(rest)?;
+            continue;
+        };
+        match memmem::find(&rest[open.len()..], close) {
+            Some(end) => pos = start + open.len() + end + close.len(),
+            None => return Some(start),
         }
-        last_valid_gt
+    }
+    None
+}
+
+/// Offset just past the `>` closing the tag at the start of `tag`, skipping
+/// quoted attribute values
+fn tag_end(tag: &[u8]) -> Option<usize> {
+    let mut quote = None;
+    let mut pos = 0;
+    while let Some(i) = memchr3(b'"', b'\'', b'>', &tag[pos..]) {
+        pos += i;
+        match (quote, tag[pos]) {
+            (None, b'>') => return Some(pos + 1),
+            (None, q @ (b'"' | b'\'')) => quote = Some(q),
+            (Some(q), b) if b == q => quote = None,
+            _ => {}
+        }
+        pos += 1;
+    }
+    None
+}
+
+/// Length of the longest prefix of `data` that does not end mid UTF-8 sequence.
+pub fn utf8_complete_len(data: &[u8]) -> usize {
+    let len = data.len();
+    // Walk back over at most 3 continuation bytes to the lead byte
+    let mut i = len;
+    while i > 0 && len - i < 3 && (data[i - 1] & 0xC0) == 0x80 {
+        i -= 1;
+    }
+    if i == 0 {
+        return len;
+    }
+    let lead = data[i - 1];
+    let width = match lead {
+        0x00..=0x7F => 1,
+        0xC0..=0xDF => 2,
+        0xE0..=0xEF => 3,
+        0xF0..=0xF7 => 4,
+        _ => return len, // Continuation run or invalid byte: leave as-is
+    };
+    if i - 1 + width > len {
+        i - 1
     } else {
-        // For large buffers, use memchr SIMD to find '>' positions
-        let mut last_valid_gt = 0;
-        let mut in_single = false;
-        let mut in_double = false;
-        let mut pos = 0;
-
-        for gt_pos in memchr_iter(b'>', buf) {
-            for &b in &buf[pos..gt_pos] {
-                match b {
-                    b'"' if !in_single => in_double = !in_double,
-                    b'\'' if !in_double => in_single = !in_single,
-                    _ => {}
-                }
-            }
-            pos = gt_pos + 1;
+        len
+    }
+}
+
+/// Length of the prefix of unterminated text that can be emitted now: it must
+/// not end inside an entity reference (`&...` without `;`) or a UTF-8 sequence.
+pub fn complete_text_len(text: &[u8]) -> usize {
+    let len = utf8_complete_len(text);
+    match memchr::memrchr(b'&', &text[..len]) {
+        Some(amp) if memchr(b';', &text[amp..len]).is_none() => amp,
+        _ => len,
+    }
+}
+
+/// Largest split point `<= max` in `data` that falls on a UTF-8 boundary.
+///
+/// When the first character alone is longer than `max`, the split point is
+/// the end of that character, so callers taking pieces always progress.
+pub fn utf8_split_point(data: &[u8], max: usize) -> usize {
+    if data.len() <= max {
+        return data.len();
+    }
+    let is_continuation = |b: &u8| (b & 0xC0) == 0x80;
+    let mut cut = max;
+    while cut > 0 && is_continuation(&data[cut]) {
+        cut -= 1;
+    }
+    if cut == 0 {
+        cut = 1 + data[1..].iter().take_while(|b| is_continuation(b)).count();
+    }
+    cut
+}
+
+/// ID attribute values seen so far by a streaming parser
+///
+/// Values are stored as 64-bit hashes with an occurrence count, so memory
+/// grows by a fixed amount per distinct ID however long the values are.
+/// Two different values with the same hash would be reported as a
+/// duplicate, which only becomes likely past billions of distinct IDs.
+#[derive(Debug, Default)]
+pub struct IdTracker {
+    seen: std::collections::HashMap<u64, u32>,
+}
+
+impl IdTracker {
+    /// Whether an attribute name declares an ID (`id` or `xml:id`)
+    #[inline]
+    pub fn is_id_attribute(name: &[u8]) -> bool {
+        name == b"id" || name == b"xml:id"
+    }
+
+    /// Bytes held by the table of seen values
+    pub fn heap_bytes(&self) -> usize {
+        map_bytes(&self.seen)
+    }
+
+    /// Record an occurrence of `value`, returning how many times it has
+    /// now been seen (1 for the first)
+    pub fn record(&mut self, value: &[u8]) -> u32 {
+        use std::hash::{DefaultHasher, Hash, Hasher};
+
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        let count = self.seen.entry(hasher.finish()).or_insert(0);
+        *count = count.saturating_add(1);
+        *count
+    }
+}
+
+/// What a streaming SAX parser does with an element that breaks a limit
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum LimitMode {
+    /// Stop parsing with an error
+    #[default]
+    Error,
+    /// Report a warning and continue: excess attributes are dropped, and
+    /// elements past the element limit are still emitted
+    Warn,
+}
+
+/// Which limit an element broke
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitKind {
+    Attributes,
+    Elements,
+}
+
+/// A limit broken by an element: the limit and the count that broke it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LimitBreach {
+    pub kind: LimitKind,
+    pub limit: usize,
+    pub count: usize,
+}
+
+/// Attribute and element count limits for a streaming SAX parser
+///
+/// The attribute limit applies to each element; the element limit to the
+/// whole document, and is reported once, by the first element past it.
+#[derive(Debug, Default)]
+pub struct SaxLimits {
+    /// Most attributes per element (0 = unlimited)
+    pub max_attributes: usize,
+    /// Most elements per document (0 = unlimited)
+    pub max_elements: usize,
+    pub mode: LimitMode,
+    /// Elements seen so far
+    elements: usize,
+    /// The breach that stopped the parse, in `Error` mode
+    stopped: Option<LimitBreach>,
+}
+
+impl SaxLimits {
+    pub fn new(max_attributes: usize, max_elements: usize, mode: LimitMode) -> Self {
+        SaxLimits {
+            max_attributes,
+            max_elements,
+            mode,
+            ..Self::default()
+        }
+    }
+
+    /// How many of an element's `count` attributes to emit
+    #[inline]
+    pub fn kept_attributes(&self, count: usize) -> usize {
+        match (self.mode, self.max_attributes) {
+            (LimitMode::Warn, max) if max > 0 => count.min(max),
+            _ => count,
+        }
+    }
+
+    /// Count an element with `attributes` attributes, returning the limits
+    /// it breaks
+    ///
+    /// In `Error` mode the first breach is kept for `stopped` and no more
+    /// are reported.
+    pub fn record(&mut self, attributes: usize) -> [Option<LimitBreach>; 2] {
+        self.elements = self.elements.saturating_add(1);
+        if self.stopped.is_some() {
+            return [None, None];
+        }
+        let attributes =
+            (self.max_attributes > 0 && attributes > self.max_attributes).then_some(LimitBreach {
+                kind: LimitKind::Attributes,
+                limit: self.max_attributes,
+                count: attributes,
+            });
+        let elements = (self.max_elements > 0 && self.elements == self.max_elements + 1).then_some(
+            LimitBreach {
+                kind: LimitKind::Elements,
+                limit: self.max_elements,
+                count: self.elements,
+            },
+        );
+        if self.mode == LimitMode::Error {
+            self.stopped = attributes.or(elements);
+        }
+        [attributes, elements]
+    }
+
+    /// The breach that stopped the parse, if any
+    #[inline]
+    pub fn stopped(&self) -> Option<LimitBreach> {
+        self.stopped
+    }
+
+    /// Forget the elements counted so far, keeping the limits
+    pub fn reset(&mut self) {
+        self.elements = 0;
+        self.stopped = None;
+    }
+}
 
-            if !in_single && !in_double {
-                last_valid_gt = gt_pos + 1;
+/// Queue a finished target element, applying the size limit if configured.
+///
+/// `flushed` is the number of bytes of this element already dropped or
+/// emitted as chunks; `oversized` is set once the limit was crossed.
+fn push_element(
+    queue: &mut Vec<StreamedElement>,
+    max_element_size: Option<usize>,
+    mode: OversizeMode,
+    name: Vec<u8>,
+    element: Vec<u8>,
+    flushed: usize,
+    oversized: bool,
+) {
+    let size = flushed + element.len();
+    let over = oversized || max_element_size.is_some_and(|max| size > max);
+    if !over {
+        queue.push(StreamedElement::Complete(element));
+        return;
+    }
+
+    match mode {
+        OversizeMode::Skip => queue.push(StreamedElement::Oversized { name, size }),
+        OversizeMode::Chunk => {
+            if !element.is_empty() {
+                queue.push(StreamedElement::Chunk {
+                    name: name.clone(),
+                    data: element,
+                });
             }
+            queue.push(StreamedElement::ChunkEnd { name, size });
         }
-        last_valid_gt
     }
 }
 
@@ -121,6 +483,11 @@ impl StreamingParser {
             tag_filter: None,
             inside_target_depth: 0,
             elements_only: false,
+            max_element_size: None,
+            oversize_mode: OversizeMode::Skip,
+            count_only: false,
+            matched: 0,
+            boundary_scan: BoundaryScan::default(),
         }
     }
 
@@ -138,9 +505,80 @@ impl StreamingParser {
             tag_filter: Some(tag.to_vec()),
             inside_target_depth: 0,
             elements_only: true,
+            max_element_size: None,
+            oversize_mode: OversizeMode::Skip,
+            count_only: false,
+            matched: 0,
+            boundary_scan: BoundaryScan::default(),
         }
     }
 
+    /// Create a filtered parser that bounds the size of each buffered element.
+    ///
+    /// Target elements larger than `max_element_size` bytes are never held in
+    /// memory whole: depending on `mode` they are either dropped and reported
+    /// as `StreamedElement::Oversized`, or emitted incrementally as chunks.
+    pub fn with_filter_and_limit(tag: &[u8], max_element_size: usize, mode: OversizeMode) -> Self {
+        let mut parser = Self::with_filter(tag);
+        parser.max_element_size = Some(max_element_size);
+        parser.oversize_mode = mode;
+        parser
+    }
+
+    /// Create a filtered parser that only counts target elements.
+    ///
+    /// Nothing is captured: text is dropped as soon as it is seen, so memory
+    /// stays at the tail buffer however large the elements are, and
+    /// `matched` is the only result.
+    pub fn counting(tag: &[u8]) -> Self {
+        let mut parser = Self::with_filter(tag);
+        parser.count_only = true;
+        parser
+    }
+
+    /// Drop buffered input, pending events and elements, keeping the filter
+    /// and size limit
+    pub fn reset(&mut self) {
+        self.buffer.clear();
+        self.events.clear();
+        self.complete_elements.clear();
+        self.element_builder = None;
+        self.in_quote = false;
+        self.depth = 0;
+        self.inside_target_depth = 0;
+        self.matched = 0;
+        self.boundary_scan = BoundaryScan::default();
+    }
+
+    /// `reset`, also freeing the buffers
+    pub fn release(&mut self) {
+        self.reset();
+        self.buffer = Vec::new();
+        self.events = Vec::new();
+        self.complete_elements = Vec::new();
+    }
+
+    /// Bytes held as `(buffer, pending)`: unconsumed input including a
+    /// partially captured element, and events and elements not yet taken
+    pub fn memory(&self) -> (usize, usize) {
+        let builder = self.element_builder.as_ref().map_or(0, |builder| {
+            vec_bytes(&builder.accumulated) + vec_bytes(&builder.name)
+        });
+        let events = vec_bytes(&self.events)
+            + self
+                .events
+                .iter()
+                .map(OwnedXmlEvent::heap_bytes)
+                .sum::<usize>();
+        let elements = vec_bytes(&self.complete_elements)
+            + self
+                .complete_elements
+                .iter()
+                .map(StreamedElement::heap_bytes)
+                .sum::<usize>();
+        (vec_bytes(&self.buffer) + builder, events + elements)
+    }
+
     /// Feed a chunk of data to the parser
     pub fn feed(&mut self, chunk: &[u8]) {
         self.buffer.extend_from_slice(chunk);
@@ -150,10 +588,17 @@ impl StreamingParser {
     /// Process the buffer to extract complete events
     fn process_buffer(&mut self) {
         // Find the last complete element boundary
-        let boundary = self.find_safe_boundary();
+        let boundary = self.boundary_scan.advance(&self.buffer);
 
         if boundary == 0 {
-            return; // Not enough data
+            // Not enough data, but a huge text node may still need trimming
+            if self.max_element_size.is_some() {
+                self.enforce_size_limit();
+            }
+            if self.count_only {
+                self.drop_leading_text();
+            }
+            return;
         }
 
         // Process directly from buffer slice - avoids allocation for the common case
@@ -173,6 +618,14 @@ impl StreamingParser {
         // Remove processed bytes efficiently using drain (no reallocation needed,
         // just moves remaining bytes to front)
         self.buffer.drain(..boundary);
+        self.boundary_scan.drain_to_boundary();
+
+        if self.max_element_size.is_some() {
+            self.enforce_size_limit();
+        }
+        if self.count_only {
+            self.drop_leading_text();
+        }
 
         // Only shrink if excess capacity is significant (> 4x needed).
         // Avoids grow-shrink-grow churn in active streaming while still
@@ -202,12 +655,17 @@ impl StreamingParser {
                             self.is_target_tag(&name_bytes) && self.inside_target_depth == 0;
                         if is_entering_target {
                             self.inside_target_depth = self.depth;
+                        }
+                        if is_entering_target && !self.count_only {
                             // Start building element - capture from token start
                             let start_pos = token.span.0;
                             self.element_builder = Some(ElementBuilder {
                                 accumulated: Vec::new(),
                                 start_in_buffer: start_pos,
                                 start_depth: self.depth,
+                                name: name_bytes.clone(),
+                                flushed: 0,
+                                oversized: false,
                             });
                         }
 
@@ -235,6 +693,7 @@ impl StreamingParser {
                         // Check if we're leaving the target element
                         if self.depth == self.inside_target_depth {
                             self.inside_target_depth = 0;
+                            self.matched += 1;
 
                             // Complete the element!
                             if let Some(builder) = self.element_builder.take() {
@@ -243,7 +702,15 @@ impl StreamingParser {
                                 element.extend_from_slice(
                                     &self.buffer[builder.start_in_buffer..end_pos],
                                 );
-                                self.complete_elements.push(element);
+                                push_element(
+                                    &mut self.complete_elements,
+                                    self.max_element_size,
+                                    self.oversize_mode,
+                                    builder.name,
+                                    element,
+                                    builder.flushed,
+                                    builder.oversized,
+                                );
                             }
                         }
 
@@ -259,12 +726,24 @@ impl StreamingParser {
                         let is_target_at_top =
                             self.is_target_tag(&name_bytes) && self.inside_target_depth == 0;
 
-                        // If this is a target empty element, add it directly as complete
                         if is_target_at_top {
+                            self.matched += 1;
+                        }
+
+                        // If this is a target empty element, add it directly as complete
+                        if is_target_at_top && !self.count_only {
                             let start_pos = token.span.0;
                             let end_pos = token.span.1;
-                            self.complete_elements
-                                .push(self.buffer[start_pos..end_pos].to_vec());
+                            let element = self.buffer[start_pos..end_pos].to_vec();
+                            push_element(
+                                &mut self.complete_elements,
+                                self.max_element_size,
+                                self.oversize_mode,
+                                name_bytes.clone(),
+                                element,
+                                0,
+                                false,
+                            );
                         }
 
                         // Emit event if inside target OR if this IS a target empty element
@@ -315,6 +794,64 @@ impl StreamingParser {
         }
     }
 
+    /// Keep a partially captured target element within `max_element_size`.
+    ///
+    /// Runs after the processed prefix has been drained. Text that follows the
+    /// last safe boundary cannot contain markup before the next `<`, so when an
+    /// element is already over the limit (or that text would push it over) the
+    /// text is moved out of the tail buffer too. Otherwise a large text node,
+    /// such as a base64 blob, would sit in `buffer` until its end tag arrived.
+    fn enforce_size_limit(&mut self) {
+        let Some(max) = self.max_element_size else {
+            return;
+        };
+        let Some(builder) = self.element_builder.as_mut() else {
+            return;
+        };
+
+        let text_len = memchr(b'<', &self.buffer).unwrap_or(self.buffer.len());
+        let captured = builder.flushed + builder.accumulated.len();
+        if text_len > 0 && (builder.oversized || captured + text_len > max) {
+            builder
+                .accumulated
+                .extend_from_slice(&self.buffer[..text_len]);
+            self.buffer.drain(..text_len);
+            self.boundary_scan = BoundaryScan::default();
+        }
+
+        if !builder.oversized && builder.flushed + builder.accumulated.len() <= max {
+            return;
+        }
+        builder.oversized = true;
+        builder.flushed += builder.accumulated.len();
+
+        match self.oversize_mode {
+            OversizeMode::Skip => {
+                // Release the memory instead of just truncating
+                builder.accumulated = Vec::new();
+            }
+            OversizeMode::Chunk => {
+                if !builder.accumulated.is_empty() {
+                    let data = std::mem::take(&mut builder.accumulated);
+                    self.complete_elements.push(StreamedElement::Chunk {
+                        name: builder.name.clone(),
+                        data,
+                    });
+                }
+            }
+        }
+    }
+
+    /// Drop the text at the start of the buffer (everything before the next
+    /// `<`), which a counting parser has no use for
+    fn drop_leading_text(&mut self) {
+        let text_len = memchr(b'<', &self.buffer).unwrap_or(self.buffer.len());
+        if text_len > 0 {
+            self.buffer.drain(..text_len);
+            self.boundary_scan = BoundaryScan::default();
+        }
+    }
+
     /// Extract attributes from buffer directly (avoids copy)
     fn extract_attributes_from_buffer(
         &self,
@@ -324,11 +861,6 @@ impl StreamingParser {
         self.extract_attributes(&self.buffer[..boundary], span)
     }
 
-    /// Find a safe boundary in the buffer (delegates to standalone fn)
-    fn find_safe_boundary(&self) -> usize {
-        find_safe_boundary(&self.buffer)
-    }
-
     /// Check if this is a target tag we're looking for
     fn is_target_tag(&self, tag: &[u8]) -> bool {
         match &self.tag_filter {
@@ -344,36 +876,7 @@ impl StreamingParser {
             return Vec::new();
         }
 
-        let tag_content = &input[start..end];
-
-        // Skip '<' and optional '?' or '/'
-        let mut pos = 1;
-        if tag_content.get(1) == Some(&b'/') || tag_content.get(1) == Some(&b'?') {
-            pos = 2;
-        }
-
-        // Skip tag name
-        while pos < tag_content.len() {
-            let b = tag_content[pos];
-            if b == b' ' || b == b'\t' || b == b'\n' || b == b'\r' || b == b'>' || b == b'/' {
-                break;
-            }
-            pos += 1;
-        }
-
-        // Find end of attributes
-        let mut attr_end = tag_content.len();
-        if tag_content.ends_with(b"/>") || tag_content.ends_with(b"?>") {
-            attr_end -= 2;
-        } else if tag_content.ends_with(b">") {
-            attr_end -= 1;
-        }
-
-        if pos >= attr_end {
-            return Vec::new();
-        }
-
-        let attrs = parse_attributes(&tag_content[pos..attr_end]);
+        let attrs = parse_attributes(tag_attributes(&input[start..end]));
         attrs
             .into_iter()
             .map(|a| (a.name.into_owned(), a.value.into_owned()))
@@ -399,8 +902,9 @@ impl StreamingParser {
     }
 
     /// Take up to `max` complete elements (faster path - no event rebuild needed)
-    /// Returns complete XML strings for target elements
-    pub fn take_elements(&mut self, max: usize) -> Vec<Vec<u8>> {
+    /// Returns complete XML strings for target elements, plus oversized
+    /// markers or chunks when a size limit is configured
+    pub fn take_elements(&mut self, max: usize) -> Vec<StreamedElement> {
         let count = max.min(self.complete_elements.len());
         if count == self.complete_elements.len() {
             std::mem::take(&mut self.complete_elements)
@@ -424,6 +928,12 @@ impl StreamingParser {
         self.complete_elements.len()
     }
 
+    /// Number of target elements completed so far, whether or not they
+    /// have been taken
+    pub fn matched(&self) -> u64 {
+        self.matched
+    }
+
     /// Get buffer size
     pub fn buffer_size(&self) -> usize {
         self.buffer.len()
@@ -439,6 +949,7 @@ impl StreamingParser {
         // Process remaining buffer as-is
         if !self.buffer.is_empty() {
             let remaining = std::mem::take(&mut self.buffer);
+            self.boundary_scan = BoundaryScan::default();
             let mut tokenizer = Tokenizer::new(&remaining);
 
             while let Some(token) = tokenizer.next_token() {
@@ -494,6 +1005,136 @@ mod tests {
         assert!(!events.is_empty());
     }
 
+    #[test]
+    fn test_boundary_scan_resumes() {
+        let xml = b"<r a='>' b=\"'>\"><x y='1'/>text '<z/></r>";
+        let mut scan = BoundaryScan::default();
+        let mut buffer = Vec::new();
+        for piece in xml.chunks(3) {
+            buffer.extend_from_slice(piece);
+            let boundary = scan.advance(&buffer);
+            assert_eq!(boundary, find_safe_boundary(&buffer));
+            // Drain at some boundaries, as the streaming parsers do
+            if boundary > 0 && buffer.len() % 2 == 0 {
+                buffer.drain(..boundary);
+                scan.drain_to_boundary();
+                assert_eq!(scan.advance(&buffer), find_safe_boundary(&buffer));
+            }
+        }
+    }
+
+    #[test]
+    fn test_finalize_resets_boundary_scan() {
+        let mut parser = StreamingParser::new();
+        parser.feed(b"<root><item a='1");
+        parser.finalize();
+        // Longer than the unfinished tag, so a stale scan would resume
+        // inside its quote
+        parser.feed(b"<next attribute='value'/>");
+        let events = parser.take_events(10);
+        assert!(events
+            .iter()
+            .any(|e| matches!(e, OwnedXmlEvent::EmptyElement { name, .. } if name == b"next")));
+    }
+
+    /// Benchmark: one element with thousands of attributes
+    ///
+    /// Streams a machine-generated-SVG-like `<path>` in 4 KB chunks and
+    /// checks it strictly; time per attribute should stay flat as the count
+    /// grows. Run with:
+    ///   cargo test --release bench_many_attributes -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn bench_many_attributes() {
+        use crate::dom::{validate_strict, validate_strict_with, StrictOptions};
+        use crate::index::builder::build_index;
+        use std::time::Instant;
+
+        fn per_attribute<F: FnMut()>(count: usize, mut f: F) -> f64 {
+            (0..3)
+                .map(|_| {
+                    let start = Instant::now();
+                    f();
+                    start.elapsed().as_nanos() as f64 / count as f64
+                })
+                .fold(f64::MAX, f64::min)
+        }
+
+        for count in [2_000, 8_000, 32_000] {
+            let attributes: String = (0..count)
+                .map(|i| format!(" data-p{i}=\"M{i} {i}L{} {}z\"", i + 1, i + 2))
+                .collect();
+            let xml = format!("<svg><path{attributes}/></svg>");
+            let input = xml.as_bytes();
+
+            let streamed = per_attribute(count, || {
+                let mut parser = StreamingParser::with_filter(b"path");
+                for chunk in input.chunks(4096) {
+                    parser.feed(chunk);
+                }
+                assert_eq!(parser.take_elements(1).len(), 1);
+            });
+            let strict = per_attribute(count, || validate_strict(input).unwrap());
+            let namespaced = xml.replacen("<svg>", "<svg xmlns:data=\"urn:d\">", 1);
+            let namespaced = namespaced.replace(" data-", " data:");
+            let options = StrictOptions { namespaces: true };
+            let strict_ns = per_attribute(count, || {
+                validate_strict_with(namespaced.as_bytes(), options).unwrap()
+            });
+            let indexed = per_attribute(count, || {
+                assert_eq!(build_index(input).attribute_count(), count);
+            });
+            println!(
+                "{count:>6} attributes ({} KB): streamed {streamed:.0} ns, \
+                 strict {strict:.0} ns, strict with namespaces {strict_ns:.0} ns, \
+                 indexed {indexed:.0} ns per attribute",
+                input.len() / 1024
+            );
+        }
+    }
+
+    #[test]
+    fn test_id_tracker_counts_occurrences() {
+        let mut ids = IdTracker::default();
+        assert_eq!(ids.record(b"a"), 1);
+        assert_eq!(ids.record(b"b"), 1);
+        assert_eq!(ids.record(b"a"), 2);
+        assert_eq!(ids.record(b"a"), 3);
+        assert!(IdTracker::is_id_attribute(b"xml:id"));
+        assert!(!IdTracker::is_id_attribute(b"idref"));
+    }
+
+    #[test]
+    fn test_sax_limits() {
+        let mut limits = SaxLimits::new(2, 3, LimitMode::Warn);
+        assert_eq!(limits.record(2), [None, None]);
+        assert_eq!(limits.kept_attributes(5), 2);
+        let [attributes, elements] = limits.record(5);
+        assert_eq!(
+            attributes,
+            Some(LimitBreach {
+                kind: LimitKind::Attributes,
+                limit: 2,
+                count: 5
+            })
+        );
+        assert_eq!(elements, None);
+        assert_eq!(limits.record(0), [None, None]);
+        // The element limit is reported by the first element past it only
+        let [_, elements] = limits.record(0);
+        assert_eq!(elements.map(|breach| breach.count), Some(4));
+        assert_eq!(limits.record(0), [None, None]);
+        assert_eq!(limits.stopped(), None);
+
+        let mut limits = SaxLimits::new(1, 0, LimitMode::Error);
+        assert_eq!(limits.kept_attributes(5), 5);
+        let [attributes, _] = limits.record(3);
+        assert_eq!(limits.stopped(), attributes);
+        assert_eq!(limits.record(3), [None, None]);
+        limits.reset();
+        assert_eq!(limits.stopped(), None);
+    }
+
     #[test]
     fn test_with_filter() {
         let mut parser = StreamingParser::with_filter(b"item");
@@ -502,8 +1143,127 @@ mod tests {
         // with_filter uses elements_only mode (no events generated)
         let elements = parser.take_elements(10);
         assert_eq!(elements.len(), 2);
-        assert_eq!(elements[0], b"<item/>");
-        assert_eq!(elements[1], b"<item/>");
+        assert_eq!(elements[0], StreamedElement::Complete(b"<item/>".to_vec()));
+        assert_eq!(elements[1], StreamedElement::Complete(b"<item/>".to_vec()));
         assert_eq!(parser.available_events(), 0);
     }
+
+    #[test]
+    fn test_counting_holds_no_elements() {
+        let mut parser = StreamingParser::counting(b"record");
+        parser.feed(b"<db><record id='1'>");
+        parser.feed(&[b'x'; 100_000]);
+        assert!(parser.buffer_size() < 100);
+        parser.feed(b"</record><record/><other><record><record/></record></other>");
+        parser.feed(b"<record>open");
+
+        assert_eq!(parser.matched(), 3);
+        assert_eq!(parser.available_elements(), 0);
+        assert!(parser.memory().0 < 16 * 1024);
+        parser.reset();
+        assert_eq!(parser.matched(), 0);
+    }
+
+    #[test]
+    fn test_memory_tracks_pending_elements() {
+        let mut parser = StreamingParser::with_filter(b"item");
+        parser.feed(b"<root><item>first</item><item>sec");
+        let (buffer, pending) = parser.memory();
+        assert!(buffer >= 8192);
+        assert!(pending >= b"<item>first</item>".len());
+
+        parser.take_elements(10);
+        assert_eq!(parser.memory().1, 0);
+    }
+
+    #[test]
+    fn test_unterminated_section_start() {
+        assert_eq!(unterminated_section_start(b"<a><![CDATA[x > y"), Some(3));
+        assert_eq!(unterminated_section_start(b"<a><![CDATA[x]]></a>"), None);
+        assert_eq!(unterminated_section_start(b"<a>text</a>"), None);
+
+        // Markers that aren't sections
+        assert_eq!(
+            unterminated_section_start(b"<a><!-- <![CDATA[ --></a>"),
+            None
+        );
+        assert_eq!(
+            unterminated_section_start(b"<a><?pi <![CDATA[ ?><b/>"),
+            None
+        );
+        assert_eq!(unterminated_section_start(b"<a x='<![CDATA['>"), None);
+        // An open comment or PI is held back like an open CDATA section
+        assert_eq!(
+            unterminated_section_start(b"<a><!-- x > <![CDATA["),
+            Some(3)
+        );
+        assert_eq!(unterminated_section_start(b"<a><?pi x > y"), Some(3));
+    }
+
+    #[test]
+    fn test_text_split_points() {
+        // "é" is 0xC3 0xA9: never split between the two bytes
+        assert_eq!(utf8_complete_len(b"ab\xC3"), 2);
+        assert_eq!(utf8_complete_len(b"ab\xC3\xA9"), 4);
+        assert_eq!(utf8_split_point(b"a\xC3\xA9b", 2), 1);
+        assert_eq!(utf8_split_point(b"abc", 8), 3);
+        // A character longer than `max` is kept whole
+        assert_eq!(utf8_split_point(b"\xE2\x82\xACx", 2), 3);
+        assert_eq!(utf8_split_point(b"\xC3\xA9", 1), 2);
+        assert_eq!(complete_text_len(b"x &am"), 2);
+        assert_eq!(complete_text_len(b"x &amp; y"), 9);
+    }
+
+    #[test]
+    fn test_size_limit_skip() {
+        let mut parser = StreamingParser::with_filter_and_limit(b"item", 16, OversizeMode::Skip);
+        parser.feed(b"<root><item>ok</item><item>");
+        parser.feed(&[b'x'; 100]);
+        assert!(parser.buffer_size() < 100);
+        parser.feed(b"</item><item/></root>");
+
+        let elements = parser.take_elements(10);
+        assert_eq!(
+            elements,
+            vec![
+                StreamedElement::Complete(b"<item>ok</item>".to_vec()),
+                StreamedElement::Oversized {
+                    name: b"item".to_vec(),
+                    size: 113,
+                },
+                StreamedElement::Complete(b"<item/>".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_size_limit_chunked() {
+        let mut parser = StreamingParser::with_filter_and_limit(b"item", 16, OversizeMode::Chunk);
+        parser.feed(b"<root><item a=\"1\">");
+        parser.feed(&[b'x'; 40]);
+        parser.feed(b"</item></root>");
+
+        let elements = parser.take_elements(10);
+        let mut data = Vec::new();
+        for element in &elements[..elements.len() - 1] {
+            match element {
+                StreamedElement::Chunk { name, data: chunk } => {
+                    assert_eq!(name, b"item");
+                    data.extend_from_slice(chunk);
+                }
+                other => panic!("unexpected {:?}", other),
+            }
+        }
+        let mut expected = b"<item a=\"1\">".to_vec();
+        expected.extend_from_slice(&[b'x'; 40]);
+        expected.extend_from_slice(b"</item>");
+        assert_eq!(data, expected);
+        assert_eq!(
+            elements.last(),
+            Some(&StreamedElement::ChunkEnd {
+                name: b"item".to_vec(),
+                size: expected.len(),
+            })
+        );
+    }
 }
@@ -0,0 +1,189 @@
+//! SVG Attributes
+//!
+//! Reads an SVG's size and path data with the scanner alone, without
+//! building an index. `dimensions` stops at the root start tag, so reading
+//! an icon's size costs the same however much it draws.
+//!
+//! Elements are matched by local name, so `<svg:path>` in a document
+//! embedding SVG under a prefix is found too. Attributes are matched
+//! exactly: SVG attributes are unprefixed, and a prefixed one is another
+//! attribute. Values have entity references decoded.
+
+use crate::core::entities::decode_text;
+use crate::core::unified_scanner::{ScanHandler, UnifiedScanner};
+use crate::index::Span;
+use std::borrow::Cow;
+
+/// Size attributes of an SVG root element
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dimensions<'a> {
+    /// `width` as written (units included)
+    pub width: Option<Cow<'a, [u8]>>,
+    /// `height` as written (units included)
+    pub height: Option<Cow<'a, [u8]>>,
+    /// `viewBox` as `[min_x, min_y, width, height]`, if it holds four numbers
+    pub view_box: Option<[f64; 4]>,
+}
+
+/// The root element's size attributes, or None if the root isn't `svg`
+pub fn dimensions(input: &[u8]) -> Option<Dimensions<'_>> {
+    let mut handler = RootHandler {
+        input,
+        dimensions: None,
+        done: false,
+    };
+    UnifiedScanner::new(input).scan(&mut handler);
+    handler.dimensions
+}
+
+/// The `d` attribute of every `path` element, in document order
+pub fn path_data(input: &[u8]) -> Vec<Cow<'_, [u8]>> {
+    let mut handler = PathHandler {
+        input,
+        paths: Vec::new(),
+    };
+    UnifiedScanner::new(input).scan(&mut handler);
+    handler.paths
+}
+
+/// Parse a `viewBox`: four numbers separated by whitespace and/or a comma
+fn parse_view_box(value: &[u8]) -> Option<[f64; 4]> {
+    let value = std::str::from_utf8(value).ok()?;
+    let mut numbers = value
+        .split(|c: char| c == ',' || c.is_ascii_whitespace())
+        .filter(|part| !part.is_empty())
+        .map(|part| part.parse::<f64>().ok().filter(|n| n.is_finite()));
+    let view_box = [
+        numbers.next()??,
+        numbers.next()??,
+        numbers.next()??,
+        numbers.next()??,
+    ];
+    numbers.next().is_none().then_some(view_box)
+}
+
+fn local_name(name: &[u8]) -> &[u8] {
+    match memchr::memchr(b':', name) {
+        Some(colon) => &name[colon + 1..],
+        None => name,
+    }
+}
+
+/// An attribute value with entities decoded
+///
+/// Spans hold at most 64 KB, so the end of a longer value (path data for a
+/// detailed drawing, say) is found from its closing quote.
+fn value<'a>(input: &'a [u8], span: Span) -> Cow<'a, [u8]> {
+    let start = span.offset as usize;
+    let raw = if span.len < u16::MAX || start == 0 {
+        span.slice(input)
+    } else {
+        let quote = input[start - 1];
+        let rest = &input[start..];
+        &rest[..memchr::memchr(quote, rest).unwrap_or(rest.len())]
+    };
+    decode_text(raw)
+}
+
+struct RootHandler<'a> {
+    input: &'a [u8],
+    dimensions: Option<Dimensions<'a>>,
+    done: bool,
+}
+
+impl ScanHandler for RootHandler<'_> {
+    fn start_element(&mut self, name: Span, attrs: &[(Span, Span)], _is_empty: bool) {
+        self.done = true;
+        if local_name(name.slice(self.input)) != b"svg" {
+            return;
+        }
+        let mut dimensions = Dimensions {
+            width: None,
+            height: None,
+            view_box: None,
+        };
+        for &(attr_name, attr_value) in attrs {
+            match attr_name.slice(self.input) {
+                b"width" => dimensions.width = Some(value(self.input, attr_value)),
+                b"height" => dimensions.height = Some(value(self.input, attr_value)),
+                b"viewBox" => {
+                    dimensions.view_box = parse_view_box(&value(self.input, attr_value));
+                }
+                _ => {}
+            }
+        }
+        self.dimensions = Some(dimensions);
+    }
+
+    fn end_element(&mut self, _name: Span) {}
+    fn text(&mut self, _span: Span, _needs_entity_decode: bool) {}
+    fn cdata(&mut self, _span: Span) {}
+    fn comment(&mut self, _span: Span) {}
+    fn processing_instruction(&mut self, _target: Span, _data: Option<Span>) {}
+
+    fn should_stop(&self) -> bool {
+        self.done
+    }
+}
+
+struct PathHandler<'a> {
+    input: &'a [u8],
+    paths: Vec<Cow<'a, [u8]>>,
+}
+
+impl ScanHandler for PathHandler<'_> {
+    fn start_element(&mut self, name: Span, attrs: &[(Span, Span)], _is_empty: bool) {
+        if local_name(name.slice(self.input)) != b"path" {
+            return;
+        }
+        let d = attrs
+            .iter()
+            .find(|(attr_name, _)| attr_name.slice(self.input) == b"d");
+        if let Some(&(_, attr_value)) = d {
+            self.paths.push(value(self.input, attr_value));
+        }
+    }
+
+    fn end_element(&mut self, _name: Span) {}
+    fn text(&mut self, _span: Span, _needs_entity_decode: bool) {}
+    fn cdata(&mut self, _span: Span) {}
+    fn comment(&mut self, _span: Span) {}
+    fn processing_instruction(&mut self, _target: Span, _data: Option<Span>) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dimensions() {
+        let svg = br#"<?xml version="1.0"?><!-- icon -->
+            <svg xmlns="http://www.w3.org/2000/svg" width="24px" height='24'
+                 viewBox="0,0 24 24"><path d="M0 0h24v24H0z"/></svg>"#;
+        let dims = dimensions(svg).unwrap();
+        assert_eq!(dims.width.as_deref(), Some(&b"24px"[..]));
+        assert_eq!(dims.height.as_deref(), Some(&b"24"[..]));
+        assert_eq!(dims.view_box, Some([0.0, 0.0, 24.0, 24.0]));
+
+        let dims = dimensions(b"<s:svg xmlns:s='urn:s' viewBox='0 0 1'/>").unwrap();
+        assert_eq!((dims.width, dims.view_box), (None, None));
+        assert_eq!(dimensions(b"<html><svg width='1'/></html>"), None);
+        assert_eq!(dimensions(b""), None);
+    }
+
+    #[test]
+    fn test_path_data() {
+        let svg = b"<svg><defs><path id='a' d='M1 1&#10;L2 2'/></defs>\
+                    <g><path d=\"M0 0z\"></path><path/><circle d='x'/></g></svg>";
+        let paths = path_data(svg);
+        assert_eq!(paths, [&b"M1 1\nL2 2"[..], b"M0 0z"]);
+
+        // Longer than a span can describe
+        let long = "L1 1".repeat(20_000);
+        let svg = format!("<svg><path d='{long}'/><path d='z'/></svg>");
+        let paths = path_data(svg.as_bytes());
+        assert_eq!(paths.len(), 2);
+        assert_eq!(paths[0].len(), long.len());
+        assert_eq!(&*paths[1], b"z");
+    }
+}
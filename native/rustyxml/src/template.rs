@@ -0,0 +1,146 @@
+//! Template Rendering
+//!
+//! Substitutes `{{name}}` placeholders (or another pair of delimiters) in a
+//! parsed document's text and attribute values, escaping each value for
+//! where it lands. Everything else - markup, comments, CDATA sections,
+//! processing instructions - is copied byte for byte, so a template is
+//! indexed once and rendered any number of times without re-parsing.
+//!
+//! Whitespace around a name is ignored (`{{ id }}`). Placeholders with no
+//! value are left as they are.
+
+use crate::index::StructuralIndex;
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// Where a span of substitutable bytes sits, which decides its escaping
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Context {
+    Text,
+    Attribute,
+}
+
+/// Render `input` (the bytes `index` was built from) with placeholders in
+/// `range` replaced by `values`
+pub fn render(
+    index: &StructuralIndex,
+    input: &[u8],
+    range: Range<usize>,
+    values: &HashMap<String, String>,
+    open: &str,
+    close: &str,
+) -> Vec<u8> {
+    let mut spans: Vec<(Range<usize>, Context)> = index
+        .texts
+        .iter()
+        .filter(|text| !text.is_cdata() && !text.is_comment() && !text.is_pi())
+        .map(|text| {
+            let start = text.span.span.offset as usize;
+            (start..start + text.span.slice(input).len(), Context::Text)
+        })
+        .chain(index.attributes.iter().map(|attr| {
+            let start = attr.value.offset as usize;
+            (start..start + attr.value.len as usize, Context::Attribute)
+        }))
+        .filter(|(span, _)| span.start >= range.start && span.end <= range.end)
+        .collect();
+    spans.sort_unstable_by_key(|(span, _)| span.start);
+
+    let mut output = Vec::with_capacity(range.len());
+    let mut copied = range.start;
+    if !open.is_empty() && !close.is_empty() {
+        for (span, context) in spans {
+            let mut pos = span.start;
+            while let Some((token, name)) = find_placeholder(input, pos..span.end, open, close) {
+                pos = token.end;
+                let Some(value) = values.get(name) else {
+                    continue;
+                };
+                output.extend_from_slice(&input[copied..token.start]);
+                escape_into(&mut output, value, context);
+                copied = token.end;
+            }
+        }
+    }
+    output.extend_from_slice(&input[copied..range.end]);
+    output
+}
+
+/// The next `open name close` token within `range`, and its trimmed name
+fn find_placeholder<'a>(
+    input: &'a [u8],
+    range: Range<usize>,
+    open: &str,
+    close: &str,
+) -> Option<(Range<usize>, &'a str)> {
+    let haystack = &input[range.clone()];
+    let start = memchr::memmem::find(haystack, open.as_bytes())?;
+    let name_start = start + open.len();
+    let name_len = memchr::memmem::find(&haystack[name_start..], close.as_bytes())?;
+    let name = std::str::from_utf8(&haystack[name_start..name_start + name_len]).ok()?;
+    let end = name_start + name_len + close.len();
+    Some((range.start + start..range.start + end, name.trim()))
+}
+
+/// Append `value` escaped for text content or a (single- or double-quoted)
+/// attribute value
+///
+/// Whitespace other than spaces is written as character references in
+/// attributes, as attribute-value normalization would turn it into spaces.
+pub fn escape_into(output: &mut Vec<u8>, value: &str, context: Context) {
+    let attribute = context == Context::Attribute;
+    let mut plain = 0;
+    for (i, b) in value.bytes().enumerate() {
+        let escaped: &[u8] = match b {
+            b'&' => b"&amp;",
+            b'<' => b"&lt;",
+            b'>' => b"&gt;",
+            b'"' if attribute => b"&quot;",
+            b'\'' if attribute => b"&apos;",
+            b'\t' if attribute => b"&#9;",
+            b'\n' if attribute => b"&#10;",
+            b'\r' => b"&#13;",
+            _ => continue,
+        };
+        output.extend_from_slice(&value.as_bytes()[plain..i]);
+        output.extend_from_slice(escaped);
+        plain = i + 1;
+    }
+    output.extend_from_slice(&value.as_bytes()[plain..]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::builder::build_index;
+
+    fn render_str(xml: &str, values: &[(&str, &str)], open: &str, close: &str) -> String {
+        let index = build_index(xml.as_bytes());
+        let values = values
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        let output = render(&index, xml.as_bytes(), 0..xml.len(), &values, open, close);
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_render_escapes_by_context() {
+        let xml = "<req id='{{id}}' x=\"{{ q }}\"><!-- {{id}} --><a>{{q}} and {{missing}}</a>\
+                   <![CDATA[{{id}}]]><b>{{id}}{{id}}</b></req>";
+        assert_eq!(
+            render_str(xml, &[("id", "7"), ("q", "a<b & \"c\"")], "{{", "}}"),
+            "<req id='7' x=\"a&lt;b &amp; &quot;c&quot;\"><!-- {{id}} -->\
+             <a>a&lt;b &amp; \"c\" and {{missing}}</a><![CDATA[{{id}}]]><b>77</b></req>"
+        );
+    }
+
+    #[test]
+    fn test_render_custom_delimiters() {
+        let xml = "<r a='${x}'>${x} {{x}} ${unclosed</r>";
+        assert_eq!(
+            render_str(xml, &[("x", "1\n2")], "${", "}"),
+            "<r a='1&#10;2'>1\n2 {{x}} ${unclosed</r>"
+        );
+    }
+}
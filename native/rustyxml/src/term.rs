@@ -2,12 +2,15 @@
 //!
 //! Converts Rust XML structures to Elixir terms.
 
+use crate::core::normalize::Normalization;
 use crate::dom::{DocumentAccess, NodeId, NodeKind};
-use crate::index::element::text_flags;
+use crate::index::element::{text_flags, ChildRef};
+use crate::index::view::encode_element_id;
 use crate::index::StructuralIndex;
-use crate::strategy::streaming::OwnedXmlEvent;
+use crate::strategy::streaming::{OwnedXmlEvent, StreamedElement};
 use crate::xpath::XPathValue;
-use rustler::{Encoder, Env, NewBinary, Term};
+use rustler::{Binary, Encoder, Env, NewBinary, NifResult, Term};
+use std::borrow::Cow;
 
 // Pre-defined atoms for efficiency - created once at compile time
 rustler::atoms! {
@@ -21,6 +24,55 @@ rustler::atoms! {
     cdata,
     characters,
     processing_instruction,
+    oversized,
+    more,
+    chunk,
+    chunk_end,
+    name,
+    attrs,
+    children,
+}
+
+/// Where node conversion gets its binaries from
+///
+/// With a shared source, strings that lie inside the source binary (names,
+/// attribute values and text not changed by entity decoding) become
+/// sub-binaries of it instead of copies, so a converted subtree references
+/// the document's bytes rather than duplicating them. Strings shorter than
+/// `SHARE_MIN_LEN` are still copied: a sub-binary header would be no smaller.
+#[derive(Clone, Copy)]
+pub struct BinarySource<'a> {
+    shared: Option<Binary<'a>>,
+}
+
+impl<'a> BinarySource<'a> {
+    /// Below this length a BEAM heap binary is cheaper than a sub-binary
+    pub const SHARE_MIN_LEN: usize = 64;
+
+    /// Copy every string into a fresh binary
+    pub const COPY: Self = BinarySource { shared: None };
+
+    /// Share bytes of `input`, which must hold the document being converted
+    pub fn shared(input: Binary<'a>) -> Self {
+        BinarySource {
+            shared: Some(input),
+        }
+    }
+
+    /// Binary term holding `bytes`
+    pub fn binary(&self, env: Env<'a>, bytes: &[u8]) -> Term<'a> {
+        if let Some(input) = self.shared.filter(|_| bytes.len() >= Self::SHARE_MIN_LEN) {
+            let base = input.as_slice().as_ptr() as usize;
+            let start = bytes.as_ptr() as usize;
+            // Decoded text lives outside the input and has to be copied
+            if start >= base && start + bytes.len() <= base + input.len() {
+                if let Ok(sub) = input.make_subbinary(start - base, bytes.len()) {
+                    return sub.encode(env);
+                }
+            }
+        }
+        bytes_to_binary(env, bytes)
+    }
 }
 
 /// Convert an XPath value to an Elixir term
@@ -28,13 +80,14 @@ pub fn xpath_value_to_term<'a, D: DocumentAccess>(
     env: Env<'a>,
     value: XPathValue,
     doc: &D,
+    source: BinarySource<'a>,
 ) -> Term<'a> {
     match value {
         XPathValue::NodeSet(nodes) => {
             // Convert node set to list of node terms
             let mut list = Term::list_new_empty(env);
             for &id in nodes.iter().rev() {
-                let node_term = node_to_term(env, doc, id);
+                let node_term = node_to_term(env, doc, id, source);
                 list = list.list_prepend(node_term);
             }
             list
@@ -50,70 +103,148 @@ pub fn xpath_value_to_term<'a, D: DocumentAccess>(
             }
             term_list
         }
+        // Namespace nodes come back as their URIs
+        XPathValue::NamespaceNodes(nodes) => {
+            let mut term_list = Term::list_new_empty(env);
+            for ns in nodes.iter().rev() {
+                term_list = term_list.list_prepend(ns.uri.encode(env));
+            }
+            term_list
+        }
     }
 }
 
 /// Convert a node to an Elixir term (simplified representation)
-pub fn node_to_term<'a, D: DocumentAccess>(env: Env<'a>, doc: &D, node_id: NodeId) -> Term<'a> {
-    let kind = doc.node_kind_of(node_id);
+///
+/// Elements become `{:element, name, attrs, children}`, comments
+/// `{:comment, text}` and processing instructions `{:pi, target, data}`;
+/// text and CDATA are plain binaries. Uses an explicit
+/// work stack, like `node_to_simple_form_term`, so deeply nested documents
+/// can't overflow the native stack. Strings come from `source`.
+pub fn node_to_term<'a, D: DocumentAccess>(
+    env: Env<'a>,
+    doc: &D,
+    node_id: NodeId,
+    source: BinarySource<'a>,
+) -> Term<'a> {
+    node_to_term_shallow(env, doc, node_id, source, None)
+}
 
-    match kind {
-        NodeKind::Element => {
-            // Return as {:element, name, attrs, children}
-            let name = doc.node_name(node_id).unwrap_or("");
-            let name_term = str_to_binary(env, name);
+/// `node_to_term` converting at most `max_depth` levels below the node
+///
+/// An element at the limit that has children gets `[{:more, node_id}]` as
+/// its children instead, a handle to expand it by later.
+pub fn node_to_term_shallow<'a, D: DocumentAccess>(
+    env: Env<'a>,
+    doc: &D,
+    node_id: NodeId,
+    source: BinarySource<'a>,
+    max_depth: Option<usize>,
+) -> Term<'a> {
+    enum Work<'b> {
+        /// Convert a node at a depth below the start: leaves go straight to
+        /// the output stack
+        Enter(NodeId, usize),
+        /// Close an element: pop child_count terms, build tuple, push result
+        Close {
+            name_term: Term<'b>,
+            attrs_term: Term<'b>,
+            child_count: usize,
+        },
+    }
 
-            // Get attributes as list of {name, value} tuples
-            let attr_pairs = doc.get_attribute_values(node_id);
-            let mut attrs = Term::list_new_empty(env);
-            for (attr_name, attr_value) in attr_pairs.into_iter().rev() {
-                let attr_tuple = (
-                    str_to_binary(env, attr_name),
-                    str_to_binary(env, attr_value),
-                );
-                attrs = attrs.list_prepend(attr_tuple.encode(env));
-            }
+    let node_id = match doc.node_kind_of(node_id) {
+        // The document node converts as its root element
+        NodeKind::Document => match doc.root_element_id() {
+            Some(root_id) => root_id,
+            None => return rustler::types::atom::nil().encode(env),
+        },
+        _ => node_id,
+    };
 
-            // Build children list
-            let children_vec = doc.children_vec(node_id);
-            let mut children = Term::list_new_empty(env);
-            for cid in children_vec.into_iter().rev() {
-                let child_term = node_to_term(env, doc, cid);
-                children = children.list_prepend(child_term);
-            }
+    let mut output: Vec<Term<'a>> = Vec::new();
+    let mut stack: Vec<Work<'a>> = vec![Work::Enter(node_id, 0)];
 
-            (element(), name_term, attrs, children).encode(env)
-        }
-        NodeKind::Text | NodeKind::CData => {
-            let content = doc.text_content(node_id).unwrap_or("");
-            str_to_binary(env, content)
-        }
-        NodeKind::Comment => {
-            let content = doc.text_content(node_id).unwrap_or("");
-            (comment(), str_to_binary(env, content)).encode(env)
-        }
-        NodeKind::ProcessingInstruction => {
-            let target = doc.node_name(node_id).unwrap_or("");
-            (pi(), str_to_binary(env, target)).encode(env)
-        }
-        NodeKind::Document => {
-            // Return root element
-            if let Some(root_id) = doc.root_element_id() {
-                node_to_term(env, doc, root_id)
-            } else {
-                rustler::types::atom::nil().encode(env)
+    while let Some(item) = stack.pop() {
+        match item {
+            Work::Enter(id, depth) => match doc.node_kind_of(id) {
+                NodeKind::Element => {
+                    let name = doc.node_name(id).unwrap_or("");
+                    let name_term = source.binary(env, name.as_bytes());
+
+                    // Get attributes as list of {name, value} tuples
+                    let attr_pairs = doc.get_attribute_values(id);
+                    let mut attrs = Term::list_new_empty(env);
+                    for (attr_name, attr_value) in attr_pairs.into_iter().rev() {
+                        let attr_tuple = (
+                            source.binary(env, attr_name.as_bytes()),
+                            source.binary(env, attr_value.as_bytes()),
+                        );
+                        attrs = attrs.list_prepend(attr_tuple.encode(env));
+                    }
+
+                    if max_depth.is_some_and(|max| depth >= max) {
+                        let mut children = Term::list_new_empty(env);
+                        if !doc.children_vec(id).is_empty() {
+                            children = children.list_prepend((more(), id).encode(env));
+                        }
+                        output.push((element(), name_term, attrs, children).encode(env));
+                        continue;
+                    }
+                    let children = doc.children_vec(id);
+                    stack.push(Work::Close {
+                        name_term,
+                        attrs_term: attrs,
+                        child_count: children.len(),
+                    });
+                    stack.extend(
+                        children
+                            .into_iter()
+                            .rev()
+                            .map(|child| Work::Enter(child, depth + 1)),
+                    );
+                }
+                NodeKind::Text | NodeKind::CData => {
+                    let content = doc.text_content(id).unwrap_or_default();
+                    output.push(source.binary(env, content.as_bytes()));
+                }
+                NodeKind::Comment => {
+                    let content = doc.node_data(id).unwrap_or("");
+                    output.push((comment(), source.binary(env, content.as_bytes())).encode(env));
+                }
+                NodeKind::ProcessingInstruction => {
+                    let target = doc.node_name(id).unwrap_or("");
+                    let data = doc.node_data(id).unwrap_or("");
+                    let term = (
+                        pi(),
+                        source.binary(env, target.as_bytes()),
+                        source.binary(env, data.as_bytes()),
+                    );
+                    output.push(term.encode(env));
+                }
+                NodeKind::Document => {
+                    output.push(rustler::types::atom::nil().encode(env));
+                }
+            },
+            Work::Close {
+                name_term,
+                attrs_term,
+                child_count,
+            } => {
+                let children_start = output.len() - child_count;
+                let mut children = Term::list_new_empty(env);
+                for &child in output[children_start..].iter().rev() {
+                    children = children.list_prepend(child);
+                }
+                output.truncate(children_start);
+                output.push((element(), name_term, attrs_term, children).encode(env));
             }
         }
     }
-}
 
-/// Convert a string to a binary term (more efficient than .encode())
-#[inline]
-fn str_to_binary<'a>(env: Env<'a>, s: &str) -> Term<'a> {
-    let bytes = s.as_bytes();
-    let mut binary = NewBinary::new(env, bytes.len());
-    binary.as_mut_slice().copy_from_slice(bytes);
-    binary.into()
+    output
+        .pop()
+        .unwrap_or_else(|| rustler::types::atom::nil().encode(env))
 }
 
 /// Convert streaming events to Elixir terms
@@ -167,6 +298,167 @@ pub fn event_to_term<'a>(env: Env<'a>, event: OwnedXmlEvent) -> Term<'a> {
     }
 }
 
+/// Target type for a typed subspec (`{key, xpath, type}`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubspecType {
+    String,
+    Integer,
+    Float,
+    Boolean,
+    List,
+    Node,
+}
+
+/// Convert an XPath value to a term of the requested subspec type.
+///
+/// Scalar types use the first result (string-value for nodes) and yield `nil`
+/// when there is no result or it cannot be converted. `:list` yields the
+/// string-value of every result; `:node` yields the first node as a term.
+pub fn typed_value_to_term<'a, D: DocumentAccess>(
+    env: Env<'a>,
+    value: XPathValue,
+    ty: SubspecType,
+    doc: &D,
+    source: BinarySource<'a>,
+) -> Term<'a> {
+    let nil = rustler::types::atom::nil().encode(env);
+
+    match ty {
+        SubspecType::String => first_string(&value, doc).map_or(nil, |s| s.encode(env)),
+        SubspecType::Integer => match value {
+            XPathValue::Number(n) if n.is_finite() => (n.trunc() as i64).encode(env),
+            XPathValue::Number(_) => nil,
+            _ => first_string(&value, doc)
+                .and_then(|s| parse_integer_prefix(&s))
+                .map_or(nil, |i| i.encode(env)),
+        },
+        SubspecType::Float => match value {
+            XPathValue::Number(n) if !n.is_nan() => n.encode(env),
+            XPathValue::Number(_) => nil,
+            _ => first_string(&value, doc)
+                .and_then(|s| s.trim().parse::<f64>().ok())
+                .filter(|f| f.is_finite())
+                .map_or(nil, |f| f.encode(env)),
+        },
+        SubspecType::Boolean => match value {
+            XPathValue::Boolean(b) => b.encode(env),
+            XPathValue::Number(_) => value.to_boolean().encode(env),
+            _ => match first_string(&value, doc).as_deref().map(str::trim) {
+                Some("true") | Some("1") => true.encode(env),
+                Some("false") | Some("0") => false.encode(env),
+                _ => nil,
+            },
+        },
+        SubspecType::List => {
+            let strings: Vec<String> = match value {
+                XPathValue::NodeSet(nodes) => nodes
+                    .iter()
+                    .map(|&id| crate::dom::node_string_value(doc, id))
+                    .collect(),
+                XPathValue::StringList(list) => list,
+                XPathValue::NamespaceNodes(nodes) => nodes.into_iter().map(|ns| ns.uri).collect(),
+                other => vec![other.to_string_value()],
+            };
+            strings.encode(env)
+        }
+        SubspecType::Node => match value {
+            XPathValue::NodeSet(nodes) => nodes
+                .first()
+                .map_or(nil, |&id| node_to_term(env, doc, id, source)),
+            other => xpath_value_to_term(env, other, doc, source),
+        },
+    }
+}
+
+/// Map of an element's attribute names to values (`"@*"` subspecs)
+///
+/// If a lenient document repeats an attribute, the first one wins.
+pub fn attributes_map_term<'a, D: DocumentAccess>(
+    env: Env<'a>,
+    doc: &D,
+    node_id: NodeId,
+    source: BinarySource<'a>,
+) -> NifResult<Term<'a>> {
+    let mut map = Term::map_new(env);
+    for (name, value) in doc.get_attribute_values(node_id).into_iter().rev() {
+        map = map.map_put(
+            source.binary(env, name.as_bytes()),
+            source.binary(env, value.as_bytes()),
+        )?;
+    }
+    Ok(map)
+}
+
+/// Map of child element names to their string-values (`"./*"` subspecs)
+///
+/// When several children share a name, the first one wins.
+pub fn child_values_map_term<'a, D: DocumentAccess>(
+    env: Env<'a>,
+    doc: &D,
+    node_id: NodeId,
+) -> NifResult<Term<'a>> {
+    let mut map = Term::map_new(env);
+    for child in doc.children_vec(node_id).into_iter().rev() {
+        if doc.node_kind_of(child) != NodeKind::Element {
+            continue;
+        }
+        let name = doc.node_name(child).unwrap_or("");
+        let value = crate::dom::node_string_value(doc, child);
+        map = map.map_put(
+            bytes_to_binary(env, name.as_bytes()),
+            bytes_to_binary(env, value.as_bytes()),
+        )?;
+    }
+    Ok(map)
+}
+
+/// String-value of the first result, or None for an empty result
+fn first_string<D: DocumentAccess>(value: &XPathValue, doc: &D) -> Option<String> {
+    match value {
+        XPathValue::NodeSet(nodes) => nodes
+            .first()
+            .map(|&id| crate::dom::node_string_value(doc, id)),
+        XPathValue::StringList(list) => list.first().cloned(),
+        other => Some(other.to_string_value()),
+    }
+}
+
+/// Parse a leading integer (optional sign + digits), ignoring any trailing
+/// characters, like Elixir's `Integer.parse/1`
+fn parse_integer_prefix(s: &str) -> Option<i64> {
+    let s = s.trim();
+    let digits_start = usize::from(s.starts_with(['-', '+']));
+    let end = s[digits_start..]
+        .find(|c: char| !c.is_ascii_digit())
+        .map_or(s.len(), |i| digits_start + i);
+    if end == digits_start {
+        return None;
+    }
+    s[..end].parse().ok()
+}
+
+/// Convert a streamed element to an Elixir term
+///
+/// Complete elements become plain XML binaries; size-limited elements become
+/// `{:oversized, name, size}`, `{:chunk, name, data}` or `{:chunk_end, name, size}`.
+pub fn streamed_element_to_term<'a>(env: Env<'a>, element: StreamedElement) -> Term<'a> {
+    match element {
+        StreamedElement::Complete(xml) => bytes_to_binary(env, &xml),
+        StreamedElement::Oversized { name, size } => {
+            (oversized(), bytes_to_binary(env, &name), size).encode(env)
+        }
+        StreamedElement::Chunk { name, data } => (
+            chunk(),
+            bytes_to_binary(env, &name),
+            bytes_to_binary(env, &data),
+        )
+            .encode(env),
+        StreamedElement::ChunkEnd { name, size } => {
+            (chunk_end(), bytes_to_binary(env, &name), size).encode(env)
+        }
+    }
+}
+
 /// Create a binary from bytes
 pub fn bytes_to_binary<'a>(env: Env<'a>, bytes: &[u8]) -> Term<'a> {
     let mut binary = NewBinary::new(env, bytes.len());
@@ -249,17 +541,17 @@ fn serialize_node_to_xml<D: DocumentAccess>(doc: &D, node_id: NodeId) -> String
                         }
                     }
                     NodeKind::Text => {
-                        let content = doc.text_content(current_id).unwrap_or("");
-                        escape_xml_to_buf(content, &mut buf);
+                        let content = doc.text_content(current_id).unwrap_or_default();
+                        escape_xml_to_buf(&content, &mut buf);
                     }
                     NodeKind::CData => {
-                        let content = doc.text_content(current_id).unwrap_or("");
+                        let content = doc.text_content(current_id).unwrap_or_default();
                         buf.push_str("<![CDATA[");
-                        buf.push_str(content);
+                        buf.push_str(&content);
                         buf.push_str("]]>");
                     }
                     NodeKind::Comment => {
-                        let content = doc.text_content(current_id).unwrap_or("");
+                        let content = doc.node_data(current_id).unwrap_or("");
                         buf.push_str("<!--");
                         buf.push_str(content);
                         buf.push_str("-->");
@@ -268,6 +560,10 @@ fn serialize_node_to_xml<D: DocumentAccess>(doc: &D, node_id: NodeId) -> String
                         let target = doc.node_name(current_id).unwrap_or("");
                         buf.push_str("<?");
                         buf.push_str(target);
+                        if let Some(data) = doc.node_data(current_id).filter(|d| !d.is_empty()) {
+                            buf.push(' ');
+                            buf.push_str(data);
+                        }
                         buf.push_str("?>");
                     }
                     NodeKind::Document => {
@@ -288,15 +584,27 @@ fn serialize_node_to_xml<D: DocumentAccess>(doc: &D, node_id: NodeId) -> String
 /// Produces `{name, attrs, children}` tuples directly — no `:element` atom.
 /// Text/CData children have entities decoded. Comments and PIs are skipped.
 /// Uses iterative stack-based approach to avoid stack overflow on deep XML.
+///
+/// With `raw_spans` set, text and attribute values are kept exactly as in
+/// the source, and each text child is `{text, {offset, length}}` with the
+/// offset shifted by the given base (e.g. the length of a stripped BOM).
+/// Otherwise decoded text and attribute values are put in `normalization`.
+///
+/// With `max_depth`, elements that many levels below the root get
+/// `[{:more, node_id}]` in place of any children.
 pub fn node_to_simple_form_term<'a>(
     env: Env<'a>,
     index: &StructuralIndex,
     input: &[u8],
     root_idx: u32,
+    raw_spans: Option<usize>,
+    normalization: Normalization,
+    max_depth: Option<usize>,
 ) -> Term<'a> {
     enum Work<'b> {
-        /// Enter an element: build name/attrs, push children + Close
-        Enter(u32),
+        /// Enter an element at a depth below the root: build name/attrs,
+        /// push children + Close
+        Enter(u32, usize),
         /// Emit a pre-built text term onto the output stack
         Leaf(Term<'b>),
         /// Close an element: pop child_count terms, build tuple, push result
@@ -310,14 +618,14 @@ pub fn node_to_simple_form_term<'a>(
     let mut output: Vec<Term<'a>> = Vec::with_capacity(256);
     let mut stack: Vec<Work<'a>> = Vec::with_capacity(64);
 
-    stack.push(Work::Enter(root_idx));
+    stack.push(Work::Enter(root_idx, 0));
 
     while let Some(item) = stack.pop() {
         match item {
             Work::Leaf(term) => {
                 output.push(term);
             }
-            Work::Enter(elem_idx) => {
+            Work::Enter(elem_idx, depth) => {
                 let elem = match index.get_element(elem_idx) {
                     Some(e) => e,
                     None => continue,
@@ -332,37 +640,51 @@ pub fn node_to_simple_form_term<'a>(
                 for a in attrs.iter().rev() {
                     let attr_name = bytes_to_binary(env, a.name.slice(input));
                     let raw_val = a.value.slice(input);
-                    let decoded = crate::core::entities::decode_text(raw_val);
-                    let attr_val = match decoded {
-                        std::borrow::Cow::Borrowed(b) => bytes_to_binary(env, b),
-                        std::borrow::Cow::Owned(ref bytes) => bytes_to_binary(env, bytes),
+                    let attr_val = if raw_spans.is_some() {
+                        bytes_to_binary(env, raw_val)
+                    } else {
+                        let decoded = crate::core::entities::decode_text(raw_val);
+                        bytes_to_binary(env, &normalization.apply(decoded))
                     };
                     attr_list = attr_list.list_prepend((attr_name, attr_val).encode(env));
                 }
 
                 // Collect visible children (skip comments and PIs)
+                let visible = |child_ref: &ChildRef| {
+                    !child_ref.is_text()
+                        || index.texts[child_ref.index() as usize].flags
+                            & (text_flags::IS_COMMENT | text_flags::IS_PI)
+                            == 0
+                };
+                if max_depth.is_some_and(|max| depth >= max) {
+                    let mut children = Term::list_new_empty(env);
+                    if index
+                        .children(elem_idx)
+                        .any(|child_ref| visible(&child_ref))
+                    {
+                        let handle = (more(), encode_element_id(elem_idx));
+                        children = children.list_prepend(handle.encode(env));
+                    }
+                    output.push((name_term, attr_list, children).encode(env));
+                    continue;
+                }
                 let mut child_items: Vec<Work<'a>> = Vec::new();
-                for child_ref in index.children(elem_idx) {
+                for child_ref in index.children(elem_idx).filter(visible) {
                     if child_ref.is_text() {
                         let text = &index.texts[child_ref.index() as usize];
-                        if text.flags & text_flags::IS_COMMENT != 0
-                            || text.flags & text_flags::IS_PI != 0
-                        {
-                            continue;
-                        }
                         let raw = text.span.slice(input);
-                        let term = if text.needs_decode() {
+                        let term = if let Some(base) = raw_spans {
+                            let span = (base + text.span.span.offset as usize, raw.len());
+                            (bytes_to_binary(env, raw), span).encode(env)
+                        } else if text.needs_decode() {
                             let decoded = crate::core::entities::decode_text(raw);
-                            match decoded {
-                                std::borrow::Cow::Borrowed(b) => bytes_to_binary(env, b),
-                                std::borrow::Cow::Owned(ref bytes) => bytes_to_binary(env, bytes),
-                            }
+                            bytes_to_binary(env, &normalization.apply(decoded))
                         } else {
-                            bytes_to_binary(env, raw)
+                            bytes_to_binary(env, &normalization.apply(Cow::Borrowed(raw)))
                         };
                         child_items.push(Work::Leaf(term));
                     } else {
-                        child_items.push(Work::Enter(child_ref.index()));
+                        child_items.push(Work::Enter(child_ref.index(), depth + 1));
                     }
                 }
 
@@ -404,6 +726,95 @@ pub fn node_to_simple_form_term<'a>(
         .unwrap_or_else(|| rustler::types::atom::nil().encode(env))
 }
 
+/// Build a map tree from StructuralIndex
+///
+/// Each element becomes `%{name: name, attrs: %{name => value},
+/// children: [map], text: text}`. `children` holds child elements only;
+/// `text` is the element's own text and CDATA content, decoded and
+/// concatenated, or nil when it has none. Comments and PIs are skipped.
+pub fn node_to_map_term<'a>(
+    env: Env<'a>,
+    index: &StructuralIndex,
+    input: &[u8],
+    root_idx: u32,
+    normalization: Normalization,
+) -> NifResult<Term<'a>> {
+    enum Work {
+        Enter(u32),
+        /// Build an element's map once its child_count children are done
+        Close(u32, usize),
+    }
+
+    let nil = rustler::types::atom::nil().encode(env);
+    let mut output: Vec<Term<'a>> = Vec::with_capacity(256);
+    let mut stack = vec![Work::Enter(root_idx)];
+
+    while let Some(item) = stack.pop() {
+        match item {
+            Work::Enter(elem_idx) => {
+                let child_elements: Vec<u32> = index
+                    .children(elem_idx)
+                    .filter(|child| !child.is_text())
+                    .map(|child| child.index())
+                    .collect();
+                stack.push(Work::Close(elem_idx, child_elements.len()));
+                stack.extend(child_elements.into_iter().rev().map(Work::Enter));
+            }
+            Work::Close(elem_idx, child_count) => {
+                let Some(elem) = index.get_element(elem_idx) else {
+                    continue;
+                };
+
+                let mut attr_map = Term::map_new(env);
+                for a in index.element_attributes(elem_idx) {
+                    let decoded = crate::core::entities::decode_text(a.value.slice(input));
+                    attr_map = attr_map.map_put(
+                        bytes_to_binary(env, a.name.slice(input)),
+                        bytes_to_binary(env, &normalization.apply(decoded)),
+                    )?;
+                }
+
+                let mut content: Option<Cow<[u8]>> = None;
+                for child in index.children(elem_idx).filter(|child| child.is_text()) {
+                    let text = &index.texts[child.index() as usize];
+                    if text.flags & (text_flags::IS_COMMENT | text_flags::IS_PI) != 0 {
+                        continue;
+                    }
+                    let raw = text.span.slice(input);
+                    let decoded = if text.needs_decode() {
+                        crate::core::entities::decode_text(raw)
+                    } else {
+                        Cow::Borrowed(raw)
+                    };
+                    content = Some(match content {
+                        None => decoded,
+                        Some(previous) => Cow::Owned([&*previous, &*decoded].concat()),
+                    });
+                }
+                let text_term = content.map_or(nil, |content| {
+                    bytes_to_binary(env, &normalization.apply(content))
+                });
+
+                let children_start = output.len() - child_count;
+                let mut children_list = Term::list_new_empty(env);
+                for &child in output[children_start..].iter().rev() {
+                    children_list = children_list.list_prepend(child);
+                }
+                output.truncate(children_start);
+
+                let map = Term::map_new(env)
+                    .map_put(name(), bytes_to_binary(env, elem.name.slice(input)))?
+                    .map_put(attrs(), attr_map)?
+                    .map_put(children(), children_list)?
+                    .map_put(text(), text_term)?;
+                output.push(map);
+            }
+        }
+    }
+
+    Ok(output.pop().unwrap_or(nil))
+}
+
 /// Escape XML special characters to buffer
 #[inline]
 fn escape_xml_to_buf(s: &str, buf: &mut String) {
@@ -0,0 +1,56 @@
+//! Background Workers
+//!
+//! `parse_async` and `sax_parse_send` return at once and do their work off
+//! the schedulers. It runs on fixed pools rather than a thread per call, so
+//! a burst of calls queues instead of exhausting OS threads. SAX streams get
+//! a pool of their own: a windowed stream waits on its consumer, and that
+//! must not hold up index builds.
+
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use std::sync::LazyLock;
+
+/// Threads building `parse_async` indexes, one per core
+static PARSE_POOL: LazyLock<ThreadPool> = LazyLock::new(|| pool("rustyxml-parse"));
+
+/// Threads scanning `sax_parse_send` streams, one per core
+static SAX_POOL: LazyLock<ThreadPool> = LazyLock::new(|| pool("rustyxml-sax"));
+
+fn pool(name: &'static str) -> ThreadPool {
+    let threads = std::thread::available_parallelism().map_or(2, |n| n.get());
+    ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .thread_name(move |i| format!("{name}-{i}"))
+        .build()
+        .expect("failed to start background worker threads")
+}
+
+/// Queue `job` for the next free parse worker
+pub fn spawn_parse(job: impl FnOnce() + Send + 'static) {
+    PARSE_POOL.spawn(job);
+}
+
+/// Queue `job` for the next free SAX worker
+pub fn spawn_sax(job: impl FnOnce() + Send + 'static) {
+    SAX_POOL.spawn(job);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    #[test]
+    fn test_parse_jobs_share_a_bounded_pool() {
+        let (tx, rx) = mpsc::channel();
+        for _ in 0..64 {
+            let tx = tx.clone();
+            spawn_parse(move || tx.send(std::thread::current().id()).unwrap());
+        }
+        drop(tx);
+        let mut threads: Vec<_> = rx.iter().collect();
+        assert_eq!(threads.len(), 64);
+        threads.sort_by_key(|id| format!("{id:?}"));
+        threads.dedup();
+        assert!(threads.len() <= PARSE_POOL.current_num_threads());
+    }
+}
@@ -0,0 +1,206 @@
+//! Reductions Over Query Results
+//!
+//! Collapse a large result into a small one before it crosses into the
+//! BEAM, so callers don't build a term per node only to fold them away.
+
+use super::compiler::CompiledExpr;
+use super::eval::evaluate_precompiled_from_node;
+use super::functions::resolve_string;
+use super::value::XPathValue;
+use crate::dom::{node_string_value, DocumentAccess, NodeId};
+use std::collections::{HashMap, HashSet};
+
+/// Distinct string-values of a result, in order of first occurrence
+///
+/// Node-sets contribute the string-value of each node; any other value is
+/// a single string.
+pub fn distinct_values<D: DocumentAccess>(doc: &D, value: &XPathValue) -> Vec<String> {
+    let mut seen = HashSet::new();
+    let mut distinct = Vec::new();
+    let mut add = |s: String| {
+        if seen.insert(s.clone()) {
+            distinct.push(s);
+        }
+    };
+    match value {
+        XPathValue::NodeSet(nodes) => {
+            for &node in nodes {
+                add(node_string_value(doc, node));
+            }
+        }
+        XPathValue::StringList(list) => list.iter().cloned().for_each(add),
+        XPathValue::NamespaceNodes(nodes) => nodes.iter().for_each(|ns| add(ns.uri.clone())),
+        _ => add(value.to_string_value()),
+    }
+    distinct
+}
+
+/// Group `nodes` by the string-value of `key` evaluated from each of them
+///
+/// Groups come in order of first occurrence and keep their nodes in the
+/// order given. A key selecting nothing groups its node under `""`.
+pub fn group_by<D: DocumentAccess>(
+    doc: &D,
+    nodes: &[NodeId],
+    key: &CompiledExpr,
+) -> Result<Vec<(String, Vec<NodeId>)>, String> {
+    let mut positions: HashMap<String, usize> = HashMap::new();
+    let mut groups: Vec<(String, Vec<NodeId>)> = Vec::new();
+    for &node in nodes {
+        let value = evaluate_precompiled_from_node(doc, node, key)?;
+        let key = resolve_string(&value, doc);
+        match positions.get(&key) {
+            Some(&i) => groups[i].1.push(node),
+            None => {
+                positions.insert(key.clone(), groups.len());
+                groups.push((key, vec![node]));
+            }
+        }
+    }
+    Ok(groups)
+}
+
+/// Number of items in a node-set or attribute value list
+///
+/// Returns None for scalar results, which XPath's `count()` rejects too.
+pub fn count(value: &XPathValue) -> Option<usize> {
+    match value {
+        XPathValue::NodeSet(nodes) => Some(nodes.len()),
+        XPathValue::StringList(list) => Some(list.len()),
+        XPathValue::NamespaceNodes(nodes) => Some(nodes.len()),
+        _ => None,
+    }
+}
+
+/// Sum of the number-values of a result, NaN if any item isn't a number
+pub fn sum<D: DocumentAccess>(doc: &D, value: &XPathValue) -> f64 {
+    let mut total = 0.0;
+    for_each_number(doc, value, |n| total += n);
+    total
+}
+
+/// Smallest number-value of a result; None if it is empty, NaN if any
+/// item isn't a number
+pub fn min<D: DocumentAccess>(doc: &D, value: &XPathValue) -> Option<f64> {
+    extreme(doc, value, f64::min)
+}
+
+/// Largest number-value of a result; None if it is empty, NaN if any item
+/// isn't a number
+pub fn max<D: DocumentAccess>(doc: &D, value: &XPathValue) -> Option<f64> {
+    extreme(doc, value, f64::max)
+}
+
+fn extreme<D: DocumentAccess>(
+    doc: &D,
+    value: &XPathValue,
+    pick: fn(f64, f64) -> f64,
+) -> Option<f64> {
+    let mut result: Option<f64> = None;
+    for_each_number(doc, value, |n| {
+        // f64::min/max ignore NaN, but one non-number spoils the result
+        result = Some(match result {
+            Some(r) if r.is_nan() || n.is_nan() => f64::NAN,
+            Some(r) => pick(r, n),
+            None => n,
+        });
+    });
+    result
+}
+
+/// Call `f` with the XPath number-value of each item of a result
+fn for_each_number<D: DocumentAccess>(doc: &D, value: &XPathValue, mut f: impl FnMut(f64)) {
+    let parse = |s: &str| s.trim().parse().unwrap_or(f64::NAN);
+    match value {
+        XPathValue::NodeSet(nodes) => {
+            for &node in nodes {
+                f(parse(&node_string_value(doc, node)));
+            }
+        }
+        XPathValue::StringList(list) => list.iter().for_each(|s| f(parse(s))),
+        XPathValue::NamespaceNodes(nodes) => nodes.iter().for_each(|ns| f(parse(&ns.uri))),
+        _ => f(value.to_number()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::builder::build_index;
+    use crate::index::IndexedDocumentView;
+    use crate::xpath::compiler::compile;
+    use crate::xpath::evaluate;
+
+    const ORDERS: &[u8] = b"<orders>\
+        <order id=\"1\"><region>EU</region></order>\
+        <order id=\"2\"><region>US</region></order>\
+        <order id=\"3\"><region>EU</region></order>\
+        <order id=\"4\"/>\
+        </orders>";
+
+    #[test]
+    fn test_distinct_values() {
+        let index = build_index(ORDERS);
+        let view = IndexedDocumentView::new(&index, ORDERS);
+
+        let regions = evaluate(&view, "//region").unwrap();
+        assert_eq!(distinct_values(&view, &regions), vec!["EU", "US"]);
+
+        let count = evaluate(&view, "count(//order)").unwrap();
+        assert_eq!(distinct_values(&view, &count), vec!["4"]);
+
+        let none = evaluate(&view, "//missing").unwrap();
+        assert!(distinct_values(&view, &none).is_empty());
+    }
+
+    #[test]
+    fn test_numeric_aggregates() {
+        let input = b"<t><r v=\"3\">1.5</r><r v=\"-2\">4</r><r v=\"10\"> 2 </r></t>";
+        let index = build_index(input);
+        let view = IndexedDocumentView::new(&index, input);
+        let eval = |xpath: &str| evaluate(&view, xpath).unwrap();
+
+        assert_eq!(count(&eval("//r")), Some(3));
+        assert_eq!(count(&eval("//r/@v")), Some(3));
+        assert_eq!(count(&eval("//none")), Some(0));
+        assert_eq!(count(&eval("1 + 1")), None);
+
+        assert_eq!(sum(&view, &eval("//r")), 7.5);
+        assert_eq!(sum(&view, &eval("//r/@v")), 11.0);
+        assert_eq!(sum(&view, &eval("//none")), 0.0);
+        assert!(sum(&view, &eval("/t")).is_nan());
+
+        assert_eq!(min(&view, &eval("//r")), Some(1.5));
+        assert_eq!(max(&view, &eval("//r")), Some(4.0));
+        assert_eq!(min(&view, &eval("//r/@v")), Some(-2.0));
+        assert_eq!(max(&view, &eval("//none")), None);
+        assert!(max(&view, &eval("//r | /t")).unwrap().is_nan());
+    }
+
+    #[test]
+    fn test_group_by() {
+        let index = build_index(ORDERS);
+        let view = IndexedDocumentView::new(&index, ORDERS);
+
+        let Ok(XPathValue::NodeSet(orders)) = evaluate(&view, "//order") else {
+            panic!("expected a node-set");
+        };
+        let key = compile("region").unwrap();
+        let groups = group_by(&view, &orders, &key).unwrap();
+
+        let ids = |nodes: &[NodeId]| -> Vec<&str> {
+            nodes
+                .iter()
+                .map(|&n| view.get_attribute(n, "id").unwrap())
+                .collect()
+        };
+        let summary: Vec<(&str, Vec<&str>)> = groups
+            .iter()
+            .map(|(key, nodes)| (key.as_str(), ids(nodes)))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![("EU", vec!["1", "3"]), ("US", vec!["2"]), ("", vec!["4"])]
+        );
+    }
+}
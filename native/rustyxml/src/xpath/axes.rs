@@ -11,11 +11,11 @@
 use super::parser::Axis;
 #[cfg(test)]
 use crate::dom::XmlDocument;
-use crate::dom::{DocumentAccess, NodeId, NodeKind};
+use crate::dom::{DocumentAccess, NameSymbol, NodeId, NodeKind};
 
 /// Navigate along an axis from a context node
 pub fn navigate<D: DocumentAccess>(doc: &D, context: NodeId, axis: Axis) -> Vec<NodeId> {
-    match axis {
+    let nodes = match axis {
         Axis::Child => child_axis(doc, context),
         Axis::Descendant => descendant_axis(doc, context),
         Axis::DescendantOrSelf => descendant_or_self_axis(doc, context),
@@ -28,8 +28,11 @@ pub fn navigate<D: DocumentAccess>(doc: &D, context: NodeId, axis: Axis) -> Vec<
         Axis::Preceding => preceding_axis(doc, context),
         Axis::Self_ => self_axis(context),
         Axis::Attribute => attribute_axis(doc, context),
-        Axis::Namespace => namespace_axis(doc, context),
-    }
+        // Namespace nodes have no NodeId; see `in_scope_namespaces`
+        Axis::Namespace => Vec::new(),
+    };
+    crate::stats::record_nodes_visited(nodes.len());
+    nodes
 }
 
 /// child:: axis - all child nodes
@@ -134,48 +137,24 @@ fn following_axis<D: DocumentAccess>(doc: &D, context: NodeId) -> Vec<NodeId> {
 }
 
 /// preceding:: axis - all nodes before in document order (not ancestors)
+///
+/// Walks preceding siblings of the context and of each ancestor, so only
+/// the nodes returned are visited (reverse document order).
 fn preceding_axis<D: DocumentAccess>(doc: &D, context: NodeId) -> Vec<NodeId> {
     let mut result = Vec::new();
-    let ancestors: std::collections::HashSet<NodeId> =
-        ancestor_axis(doc, context).into_iter().collect();
-
-    // Collect all nodes in document order that come before context
-    // and are not ancestors
-    fn collect_preceding<D: DocumentAccess>(
-        doc: &D,
-        node_id: NodeId,
-        context: NodeId,
-        ancestors: &std::collections::HashSet<NodeId>,
-        result: &mut Vec<NodeId>,
-        found_context: &mut bool,
-    ) {
-        if node_id == context {
-            *found_context = true;
-            return;
-        }
-
-        if *found_context {
-            return;
-        }
 
-        if !ancestors.contains(&node_id) {
-            result.push(node_id);
-        }
-
-        // Visit children
-        for child in doc.children_vec(node_id) {
-            collect_preceding(doc, child, context, ancestors, result, found_context);
-            if *found_context {
-                return;
-            }
+    let mut current = Some(context);
+    while let Some(node_id) = current {
+        let mut sibling = doc.prev_sibling_of(node_id);
+        while let Some(sib_id) = sibling {
+            // The sibling's subtree, last descendant first
+            result.extend(doc.descendants_vec(sib_id).into_iter().rev());
+            result.push(sib_id);
+            sibling = doc.prev_sibling_of(sib_id);
         }
+        current = doc.parent_of(node_id);
     }
 
-    let mut found_context = false;
-    collect_preceding(doc, 0, context, &ancestors, &mut result, &mut found_context);
-
-    // Reverse to get proper preceding order
-    result.reverse();
     result
 }
 
@@ -192,18 +171,54 @@ fn attribute_axis<D: DocumentAccess>(doc: &D, _context: NodeId) -> Vec<NodeId> {
     Vec::new()
 }
 
-/// namespace:: axis - namespace nodes
+/// Namespace bound to the `xml` prefix, implicitly in scope on every element
+pub const XML_NAMESPACE: &str = "http://www.w3.org/XML/1998/namespace";
+
+/// In-scope namespaces of an element, as `(prefix, uri)` pairs
 ///
-/// The namespace axis requires returning namespace nodes — a node type that
-/// doesn't exist in our `NodeKind` enum or `NodeId` encoding scheme. Adding
-/// a third node type would require changes to the ID encoding, `NodeKind`,
-/// and many downstream consumers. This is deferred.
+/// This backs the namespace:: axis. Namespace nodes have no `NodeId` in our
+/// node model, so like the attribute axis the evaluator works with their
+/// values directly rather than going through `navigate`.
 ///
-/// Users who need namespace information should use the `namespace-uri()`
-/// function instead.
-fn namespace_axis<D: DocumentAccess>(doc: &D, _context: NodeId) -> Vec<NodeId> {
-    let _ = doc;
-    Vec::new()
+/// Walks the ancestor-or-self chain collecting `xmlns` and `xmlns:prefix`
+/// attributes; the nearest declaration of a prefix wins, and `xmlns=""`
+/// undeclares the default namespace. The default namespace has an empty
+/// prefix. The implicit `xml` binding comes first, then the rest in the
+/// order their nearest declarations are found going outward. Non-element
+/// nodes have no namespaces.
+pub fn in_scope_namespaces<D: DocumentAccess>(doc: &D, context: NodeId) -> Vec<(&str, &str)> {
+    if doc.node_kind_of(context) != NodeKind::Element {
+        return Vec::new();
+    }
+
+    let mut seen: Vec<&str> = vec!["xml"];
+    let mut result = vec![("xml", XML_NAMESPACE)];
+    let mut current = Some(context);
+    while let Some(node) = current {
+        if doc.node_kind_of(node) != NodeKind::Element {
+            break;
+        }
+        for (name, uri) in doc.get_attribute_values(node) {
+            let prefix = match name.strip_prefix("xmlns") {
+                Some("") => "",
+                Some(rest) => match rest.strip_prefix(':') {
+                    Some(prefix) => prefix,
+                    None => continue,
+                },
+                None => continue,
+            };
+            if seen.contains(&prefix) {
+                continue;
+            }
+            seen.push(prefix);
+            // An empty default declaration hides outer ones but is not a node
+            if !(prefix.is_empty() && uri.is_empty()) {
+                result.push((prefix, uri));
+            }
+        }
+        current = doc.parent_of(node);
+    }
+    result
 }
 
 /// Check if a node matches a node test
@@ -260,6 +275,36 @@ pub fn matches_node_test<D: DocumentAccess>(
     }
 }
 
+/// Resolve a `Name` node test to the document's interned symbol
+///
+/// Done once per step so each candidate check is a `u32` comparison. Other
+/// node tests, and documents without a name table, yield `Unavailable`.
+pub fn resolve_node_test<D: DocumentAccess>(
+    doc: &D,
+    node_test: &super::compiler::CompiledNodeTest,
+) -> NameSymbol {
+    match node_test {
+        super::compiler::CompiledNodeTest::Name(name) => doc.name_symbol(name),
+        _ => NameSymbol::Unavailable,
+    }
+}
+
+/// `matches_node_test` with the name already resolved by `resolve_node_test`
+#[inline]
+pub fn matches_resolved_node_test<D: DocumentAccess>(
+    doc: &D,
+    node_id: NodeId,
+    node_test: &super::compiler::CompiledNodeTest,
+    resolved: NameSymbol,
+) -> bool {
+    match resolved {
+        // Only elements carry a local-name symbol
+        NameSymbol::Symbol(sym) => doc.node_local_name_symbol(node_id) == Some(sym),
+        NameSymbol::Absent => false,
+        NameSymbol::Unavailable => matches_node_test(doc, node_id, node_test),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -293,13 +338,24 @@ mod tests {
     }
 
     #[test]
-    fn test_namespace_axis_returns_empty() {
-        // The namespace axis requires namespace node types not in our node model.
-        // Returns empty — use namespace-uri() for namespace information.
-        let doc = XmlDocument::parse(b"<root xmlns:ns=\"http://example.com\"><ns:child/></root>");
+    fn test_in_scope_namespaces() {
+        let doc = XmlDocument::parse(
+            b"<root xmlns=\"urn:d\" xmlns:a=\"urn:a\"><mid xmlns:a=\"urn:a2\" xmlns=\"\"><leaf/></mid></root>",
+        );
         let root = doc.root_element_id().unwrap();
-        let result = namespace_axis(&doc, root);
-        assert!(result.is_empty());
+        let mid = doc.children_vec(root)[0];
+        let leaf = doc.children_vec(mid)[0];
+
+        assert_eq!(
+            in_scope_namespaces(&doc, root),
+            vec![("xml", XML_NAMESPACE), ("", "urn:d"), ("a", "urn:a")]
+        );
+        // Nearest declaration wins; xmlns="" removes the default namespace
+        assert_eq!(
+            in_scope_namespaces(&doc, leaf),
+            vec![("xml", XML_NAMESPACE), ("a", "urn:a2")]
+        );
+        assert!(in_scope_namespaces(&doc, doc.document_node_id()).is_empty());
     }
 
     #[test]
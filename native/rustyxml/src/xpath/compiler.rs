@@ -6,17 +6,120 @@
 use super::parser::{Axis, BinaryOp, Expr, NodeTest, Step};
 use lru::LruCache;
 use std::num::NonZeroUsize;
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
 
 /// Global LRU cache for compiled XPath expressions.
 /// Using Arc<CompiledExpr> to avoid deep cloning on cache hits —
 /// each hit is now a cheap Arc pointer bump instead of cloning
 /// all Vec<Op>, Strings, and Box<CompiledExpr> recursively.
-static XPATH_CACHE: Mutex<Option<LruCache<String, Arc<CompiledExpr>>>> = Mutex::new(None);
+static XPATH_CACHE: CompileCache = CompileCache::new(CACHE_CAPACITY);
 
-/// Cache capacity - tuned for typical XPath usage patterns
+/// Cache capacity until `set_cache_capacity` is called - tuned for typical
+/// XPath usage patterns
 const CACHE_CAPACITY: usize = 256;
 
+/// An LRU map from expression strings to compiled expressions, counting
+/// lookups
+pub struct CompileCache {
+    state: Mutex<CacheState>,
+}
+
+struct CacheState {
+    capacity: usize,
+    hits: u64,
+    misses: u64,
+    /// Created on first insert, dropped when the capacity is set to 0
+    entries: Option<LruCache<String, Arc<CompiledExpr>>>,
+}
+
+/// Counters and occupancy of a `CompileCache`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub size: usize,
+    pub capacity: usize,
+}
+
+impl CompileCache {
+    pub const fn new(capacity: usize) -> Self {
+        CompileCache {
+            state: Mutex::new(CacheState {
+                capacity,
+                hits: 0,
+                misses: 0,
+                entries: None,
+            }),
+        }
+    }
+
+    /// Every update leaves the state consistent, so a panic while the lock
+    /// was held doesn't invalidate it
+    fn state(&self) -> MutexGuard<'_, CacheState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// The cached compilation of `xpath`, compiling and caching it on a
+    /// miss. The lock is not held while compiling.
+    pub fn get_or_compile(&self, xpath: &str) -> Result<Arc<CompiledExpr>, String> {
+        {
+            let mut state = self.state();
+            let hit = state.entries.as_mut().and_then(|e| e.get(xpath).cloned());
+            if let Some(compiled) = hit {
+                state.hits += 1;
+                return Ok(compiled);
+            }
+            state.misses += 1;
+        }
+
+        let expr = super::parser::parse(xpath)?;
+        let compiled = Arc::new(CompiledExpr::compile(&expr));
+
+        let mut state = self.state();
+        if let Some(capacity) = NonZeroUsize::new(state.capacity) {
+            state
+                .entries
+                .get_or_insert_with(|| LruCache::new(capacity))
+                .put(xpath.to_string(), Arc::clone(&compiled));
+        }
+        Ok(compiled)
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let state = self.state();
+        CacheStats {
+            hits: state.hits,
+            misses: state.misses,
+            size: state.entries.as_ref().map_or(0, LruCache::len),
+            capacity: state.capacity,
+        }
+    }
+
+    /// Drop every cached expression and zero the counters, keeping the
+    /// capacity
+    pub fn clear(&self) {
+        let mut state = self.state();
+        state.entries = None;
+        state.hits = 0;
+        state.misses = 0;
+    }
+
+    /// Change how many expressions are kept, evicting the least recently
+    /// used ones beyond it; 0 empties and disables the cache
+    pub fn set_capacity(&self, capacity: usize) {
+        let mut state = self.state();
+        state.capacity = capacity;
+        match NonZeroUsize::new(capacity) {
+            Some(capacity) => {
+                if let Some(entries) = state.entries.as_mut() {
+                    entries.resize(capacity);
+                }
+            }
+            None => state.entries = None,
+        }
+    }
+}
+
 /// Compiled XPath expression
 #[derive(Debug, Clone)]
 pub struct CompiledExpr {
@@ -34,10 +137,15 @@ pub enum Op {
     Parent,
     /// Navigate along axis with node test
     Navigate(Axis, CompiledNodeTest),
+    /// Navigate along axis with node test, then apply predicate ops to each
+    /// context node's axis result (positions count along the axis)
+    NavigateFiltered(Axis, CompiledNodeTest, Vec<Op>),
     /// Apply predicate filter (general case)
     Predicate(Box<CompiledExpr>),
     /// Fast path: predicate [@attr = 'value']
     PredicateAttrEq(String, String),
+    /// Fast path: predicate [@attr]
+    PredicateAttrExists(String),
     /// Fast path: predicate [position]
     PredicatePosition(usize),
     /// Union two node sets
@@ -147,16 +255,84 @@ impl CompiledExpr {
             }
         };
 
-        ops.push(Op::Navigate(step.axis, node_test));
+        let predicates: Vec<Op> = step
+            .predicates
+            .iter()
+            .map(|pred| {
+                // Try to use fast-path predicates for common patterns
+                Self::try_optimize_predicate(pred)
+                    .unwrap_or_else(|| Op::Predicate(Box::new(CompiledExpr::compile(pred))))
+            })
+            .collect();
 
-        for pred in &step.predicates {
-            // Try to use fast-path predicates for common patterns
-            if let Some(op) = Self::try_optimize_predicate(pred) {
-                ops.push(op);
+        // Attribute steps yield strings, not nodes, so their predicates keep
+        // filtering the combined result. Predicates that don't
+        // depend on position give the same result on the combined node-set.
+        if predicates.iter().all(Self::is_position_independent) || step.axis == Axis::Attribute {
+            // `//name` (descendant-or-self::node()/child::name) selects the
+            // same nodes as descendant::name, in one scan instead of a child
+            // lookup per node
+            let axis = if step.axis == Axis::Child
+                && matches!(
+                    ops.last(),
+                    Some(Op::Navigate(Axis::DescendantOrSelf, CompiledNodeTest::Node))
+                ) {
+                ops.pop();
+                Axis::Descendant
             } else {
-                let pred_compiled = CompiledExpr::compile(pred);
-                ops.push(Op::Predicate(Box::new(pred_compiled)));
+                step.axis
+            };
+            ops.push(Op::Navigate(axis, node_test));
+            ops.extend(predicates);
+        } else {
+            ops.push(Op::NavigateFiltered(step.axis, node_test, predicates));
+        }
+    }
+
+    /// Whether a predicate op keeps the same nodes regardless of the context
+    /// position and size (no `[n]`, `position()`, `last()`, or numeric result)
+    pub(crate) fn is_position_independent(op: &Op) -> bool {
+        match op {
+            Op::PredicateAttrEq(..) | Op::PredicateAttrExists(_) => true,
+            Op::Predicate(expr) => {
+                // Nested predicates have their own context, so only calls at
+                // this level matter
+                let uses_position = expr.ops.iter().any(
+                    |op| matches!(op, Op::Call(name, _) if name == "position" || name == "last"),
+                );
+                let boolean_or_nodes = match expr.ops.last() {
+                    Some(Op::Binary(op)) => matches!(
+                        op,
+                        BinaryOp::Or
+                            | BinaryOp::And
+                            | BinaryOp::Eq
+                            | BinaryOp::NotEq
+                            | BinaryOp::Lt
+                            | BinaryOp::LtEq
+                            | BinaryOp::Gt
+                            | BinaryOp::GtEq
+                    ),
+                    Some(Op::Call(name, _)) => matches!(
+                        name.as_str(),
+                        "not" | "true" | "false" | "boolean" | "contains" | "starts-with" | "lang"
+                    ),
+                    Some(
+                        Op::Root
+                        | Op::Context
+                        | Op::Parent
+                        | Op::Navigate(..)
+                        | Op::NavigateFiltered(..)
+                        | Op::Predicate(_)
+                        | Op::PredicateAttrEq(..)
+                        | Op::PredicateAttrExists(_)
+                        | Op::PredicatePosition(_)
+                        | Op::Union,
+                    ) => true,
+                    _ => false,
+                };
+                !uses_position && boolean_or_nodes
             }
+            _ => false,
         }
     }
 
@@ -187,6 +363,9 @@ impl CompiledExpr {
                 None
             }
 
+            // Pattern: [@attr] - attribute existence
+            Expr::Step(_) => Self::extract_attribute_name(pred).map(Op::PredicateAttrExists),
+
             _ => None,
         }
     }
@@ -213,38 +392,145 @@ impl CompiledExpr {
     }
 }
 
-/// Cache capacity - guaranteed non-zero at compile time
-const CACHE_CAPACITY_NONZERO: NonZeroUsize = match NonZeroUsize::new(CACHE_CAPACITY) {
-    Some(n) => n,
-    None => panic!("CACHE_CAPACITY must be non-zero"),
-};
+// ============================================================================
+// Explain
+// ============================================================================
+
+impl CompiledExpr {
+    /// Describe the evaluation plan, one line per operation
+    ///
+    /// Each line is the operation followed by the strategy used to evaluate
+    /// it in brackets. Predicate sub-plans are indented below their step.
+    pub fn explain(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        self.explain_into(0, &mut lines);
+        lines
+    }
+
+    fn explain_into(&self, depth: usize, lines: &mut Vec<String>) {
+        let indent = "  ".repeat(depth);
+        for (i, op) in self.ops.iter().enumerate() {
+            let after_root = i > 0 && matches!(self.ops[i - 1], Op::Root);
+            // `//name[@attr ...]` from the root can be answered by an
+            // attribute value index when one was built for the document
+            let attr_lookup = after_root
+                && matches!(
+                    self.ops.get(i + 1),
+                    Some(Op::PredicateAttrEq(..) | Op::PredicateAttrExists(_))
+                );
+            let (what, strategy) = match op {
+                Op::Root => ("root".to_string(), "document-node"),
+                Op::Context => ("context".to_string(), "context-node"),
+                Op::Parent => ("parent::node()".to_string(), "parent-links"),
+                Op::Navigate(axis @ (Axis::Descendant | Axis::DescendantOrSelf), test)
+                    if attr_lookup =>
+                {
+                    (
+                        format!("{}::{}", axis.as_str(), test),
+                        "attribute-index-or-full-scan",
+                    )
+                }
+                Op::Navigate(axis, test) => (
+                    format!("{}::{}", axis.as_str(), test),
+                    axis_strategy(*axis, after_root),
+                ),
+                Op::NavigateFiltered(axis, test, _) => (
+                    format!("{}::{}", axis.as_str(), test),
+                    axis_strategy(*axis, after_root),
+                ),
+                Op::Predicate(_) => ("predicate".to_string(), "evaluate-per-node"),
+                Op::PredicateAttrEq(name, value) => (
+                    format!("predicate [@{} = '{}']", name, value),
+                    "attribute-compare",
+                ),
+                Op::PredicateAttrExists(name) => {
+                    (format!("predicate [@{}]", name), "attribute-compare")
+                }
+                Op::PredicatePosition(pos) => (format!("predicate [{}]", pos), "position-index"),
+                Op::Union => ("union".to_string(), "merge-document-order"),
+                Op::Number(n) => (super::value::format_number(*n), "literal"),
+                Op::String(s) => (format!("'{}'", s), "literal"),
+                Op::Call(name, argc) => (format!("{}()/{}", name, argc), "function"),
+                Op::Binary(op) => (op.as_str().to_string(), "operator"),
+                Op::Negate => ("negate".to_string(), "operator"),
+                Op::Variable(name) => (format!("${}", name), "unsupported"),
+            };
+            match op {
+                Op::NavigateFiltered(..) => lines.push(format!(
+                    "{indent}{what} [{strategy}, predicates per context node]"
+                )),
+                _ => lines.push(format!("{indent}{what} [{strategy}]")),
+            }
+
+            match op {
+                Op::NavigateFiltered(_, _, predicates) => {
+                    CompiledExpr {
+                        ops: predicates.clone(),
+                    }
+                    .explain_into(depth + 1, lines);
+                }
+                Op::Predicate(expr) => expr.explain_into(depth + 1, lines),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Strategy label for navigating an axis
+fn axis_strategy(axis: Axis, from_root: bool) -> &'static str {
+    match axis {
+        Axis::Child => "child-walk",
+        Axis::Descendant | Axis::DescendantOrSelf if from_root => "full-descendant-scan",
+        Axis::Descendant | Axis::DescendantOrSelf => "subtree-scan",
+        Axis::Parent | Axis::Ancestor | Axis::AncestorOrSelf => "parent-links",
+        Axis::FollowingSibling | Axis::PrecedingSibling => "sibling-links",
+        Axis::Following | Axis::Preceding => "document-order-scan",
+        Axis::Self_ => "self",
+        Axis::Attribute => "attribute-lookup",
+        Axis::Namespace => "namespace-scope",
+    }
+}
+
+impl std::fmt::Display for CompiledNodeTest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CompiledNodeTest::Any => write!(f, "*"),
+            CompiledNodeTest::Name(name) => write!(f, "{}", name),
+            CompiledNodeTest::QName(prefix, local) => write!(f, "{}:{}", prefix, local),
+            CompiledNodeTest::NamespaceWildcard(prefix) => write!(f, "{}:*", prefix),
+            CompiledNodeTest::Node => write!(f, "node()"),
+            CompiledNodeTest::Text => write!(f, "text()"),
+            CompiledNodeTest::Comment => write!(f, "comment()"),
+            CompiledNodeTest::ProcessingInstruction(None) => write!(f, "processing-instruction()"),
+            CompiledNodeTest::ProcessingInstruction(Some(target)) => {
+                write!(f, "processing-instruction('{}')", target)
+            }
+        }
+    }
+}
 
 /// Compile an XPath expression string (with caching).
 ///
 /// Returns `Arc<CompiledExpr>` — cache hits are a cheap pointer bump
 /// instead of a deep clone of all operations, strings, and predicates.
 pub fn compile(xpath: &str) -> Result<Arc<CompiledExpr>, String> {
-    // Try to get from cache first
-    if let Ok(mut guard) = XPATH_CACHE.lock() {
-        let cache = guard.get_or_insert_with(|| LruCache::new(CACHE_CAPACITY_NONZERO));
-
-        if let Some(compiled) = cache.get(xpath) {
-            return Ok(Arc::clone(compiled));
-        }
-    }
-    // If mutex is poisoned, just skip the cache and compile directly
+    crate::stats::record_xpath_query();
+    XPATH_CACHE.get_or_compile(xpath)
+}
 
-    // Not in cache - parse and compile
-    let expr = super::parser::parse(xpath)?;
-    let compiled = Arc::new(CompiledExpr::compile(&expr));
+/// Hits, misses, size and capacity of the global expression cache
+pub fn cache_stats() -> CacheStats {
+    XPATH_CACHE.stats()
+}
 
-    // Store in cache (if mutex is available)
-    if let Ok(mut guard) = XPATH_CACHE.lock() {
-        let cache = guard.get_or_insert_with(|| LruCache::new(CACHE_CAPACITY_NONZERO));
-        cache.put(xpath.to_string(), Arc::clone(&compiled));
-    }
+/// Empty the global expression cache and zero its counters
+pub fn clear_cache() {
+    XPATH_CACHE.clear();
+}
 
-    Ok(compiled)
+/// Set how many expressions the global cache keeps; 0 disables it
+pub fn set_cache_capacity(capacity: usize) {
+    XPATH_CACHE.set_capacity(capacity);
 }
 
 /// Compile an XPath expression string without caching (for testing)
@@ -258,6 +544,39 @@ pub fn compile_uncached(xpath: &str) -> Result<CompiledExpr, String> {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_compile_cache_counts_and_evicts() {
+        let cache = CompileCache::new(2);
+        let stats = |hits, misses, size, capacity| CacheStats {
+            hits,
+            misses,
+            size,
+            capacity,
+        };
+
+        cache.get_or_compile("//a").unwrap();
+        cache.get_or_compile("//b").unwrap();
+        cache.get_or_compile("//a").unwrap();
+        assert!(cache.get_or_compile("//[").is_err());
+        assert_eq!(cache.stats(), stats(1, 3, 2, 2));
+
+        // `//b` is the least recently used
+        cache.get_or_compile("//c").unwrap();
+        cache.get_or_compile("//b").unwrap();
+        assert_eq!(cache.stats(), stats(1, 5, 2, 2));
+
+        cache.set_capacity(1);
+        assert_eq!(cache.stats().size, 1);
+        cache.set_capacity(0);
+        cache.get_or_compile("//b").unwrap();
+        assert_eq!(cache.stats(), stats(1, 6, 0, 0));
+
+        cache.set_capacity(4);
+        cache.get_or_compile("//b").unwrap();
+        cache.clear();
+        assert_eq!(cache.stats(), stats(0, 0, 0, 4));
+    }
+
     #[test]
     fn test_compile_simple() {
         let compiled = compile("/root").unwrap();
@@ -270,4 +589,64 @@ mod tests {
         let compiled = compile("//item").unwrap();
         assert!(!compiled.ops.is_empty());
     }
+
+    #[test]
+    fn test_descendant_child_step_is_fused() {
+        let compiled = compile_uncached("//item[@id = 'x']").unwrap();
+        assert!(matches!(
+            compiled.ops.as_slice(),
+            [
+                Op::Root,
+                Op::Navigate(Axis::Descendant, CompiledNodeTest::Name(_)),
+                Op::PredicateAttrEq(..)
+            ]
+        ));
+
+        // Positional predicates must still count per parent
+        let compiled = compile_uncached("//item[1]").unwrap();
+        assert!(matches!(
+            compiled.ops.as_slice(),
+            [
+                Op::Root,
+                Op::Navigate(Axis::DescendantOrSelf, CompiledNodeTest::Node),
+                Op::NavigateFiltered(Axis::Child, _, _)
+            ]
+        ));
+        let compiled = compile_uncached("//item[position() < 3]").unwrap();
+        assert!(matches!(compiled.ops[2], Op::NavigateFiltered(..)));
+    }
+
+    #[test]
+    fn test_explain() {
+        let plan = compile_uncached("/a/b[c > 1]//d[2]").unwrap().explain();
+        assert_eq!(
+            plan,
+            [
+                "root [document-node]",
+                "child::a [child-walk]",
+                "child::b [child-walk]",
+                "predicate [evaluate-per-node]",
+                "  context [context-node]",
+                "  child::c [child-walk]",
+                "  1 [literal]",
+                "  > [operator]",
+                "descendant-or-self::node() [subtree-scan]",
+                "child::d [child-walk, predicates per context node]",
+                "  predicate [2] [position-index]",
+            ]
+        );
+
+        let plan = compile_uncached("//item").unwrap().explain();
+        assert_eq!(plan[1], "descendant::item [full-descendant-scan]");
+
+        let plan = compile_uncached("//item[@sku]").unwrap().explain();
+        assert_eq!(
+            plan,
+            [
+                "root [document-node]",
+                "descendant::item [attribute-index-or-full-scan]",
+                "predicate [@sku] [attribute-compare]",
+            ]
+        );
+    }
 }
@@ -0,0 +1,158 @@
+//! XPath 1.0 Conformance Cases
+//!
+//! Expression/result pairs in the style of the W3C/OASIS XPath test suites,
+//! run against both the DOM and the structural index view. Node-sets are
+//! compared as a description of each node in result order, so document-order
+//! and position() differences show up as a failing string diff.
+//!
+//! Node descriptions: `/` for the document node, `name(string-value)` for
+//! elements, `"text"` for text nodes.
+
+use super::{evaluate, XPathValue};
+use crate::dom::{self, DocumentAccess, NodeKind, XmlDocument};
+use crate::index::builder::build_index;
+use crate::index::IndexedDocumentView;
+
+const BOOK: &[u8] = b"<doc>\
+<chapter id=\"c1\" n=\"1\"><title>One</title><para>p1</para><para>p2</para></chapter>\
+<chapter id=\"c2\" n=\"2\"><title>Two</title><para>p3</para><section><para>p4</para></section></chapter>\
+<appendix id=\"a1\"><para>p5</para></appendix>\
+</doc>";
+
+/// (expression, expected result)
+const CASES: &[(&str, &str)] = &[
+    // Positional predicates apply per step, to each context node's children
+    ("//para[1]", "para(p1) para(p3) para(p4) para(p5)"),
+    ("(//para)[1]", "para(p1)"),
+    ("//para[last()]", "para(p2) para(p3) para(p4) para(p5)"),
+    ("(//para)[last()]", "para(p5)"),
+    ("//para[position() = last() - 1]", "para(p1)"),
+    ("//chapter[2]/para[1]", "para(p3)"),
+    ("/descendant::para[3]", "para(p3)"),
+    (
+        "//para[. != 'p1'][1]",
+        "para(p2) para(p3) para(p4) para(p5)",
+    ),
+    ("//para[2][. = 'p2']", "para(p2)"),
+    // Nested predicates see their own context size and position
+    ("//chapter[para[last()] = 'p2']/@id", "c1"),
+    ("//chapter[para[position() = 1] = 'p3']/@id", "c2"),
+    ("//chapter[count(para[position() > 1]) = 1]/@id", "c1"),
+    ("//*[para[2]]", "chapter(Onep1p2)"),
+    // Reverse axes count positions nearest-first, results stay in document order
+    (
+        "//section/para/preceding::para",
+        "para(p1) para(p2) para(p3)",
+    ),
+    ("//section/para/preceding::para[1]", "para(p3)"),
+    ("//section/para/preceding::*[last()]", "chapter(Onep1p2)"),
+    ("//section/para/ancestor::*[1]", "section(p4)"),
+    (
+        "//section/para/ancestor::*[last()]",
+        "doc(Onep1p2Twop3p4p5)",
+    ),
+    (
+        "//para[. = 'p4']/ancestor-or-self::*",
+        "doc(Onep1p2Twop3p4p5) chapter(Twop3p4) section(p4) para(p4)",
+    ),
+    ("//section/preceding-sibling::*", "title(Two) para(p3)"),
+    ("//section/preceding-sibling::*[1]", "para(p3)"),
+    ("//para[. = 'p1']/following::para[1]", "para(p2)"),
+    ("//title/following::para[1]", "para(p1) para(p3)"),
+    (
+        "//title/following-sibling::*[last()]",
+        "para(p2) section(p4)",
+    ),
+    ("//appendix/preceding::title", "title(One) title(Two)"),
+    // Unions are deduplicated and in document order
+    (
+        "//appendix | //title | //chapter[1]",
+        "chapter(Onep1p2) title(One) title(Two) appendix(p5)",
+    ),
+    (
+        "//para[1] | //para[1]",
+        "para(p1) para(p3) para(p4) para(p5)",
+    ),
+    (
+        "//title/text() | //chapter",
+        "chapter(Onep1p2) \"One\" chapter(Twop3p4) \"Two\"",
+    ),
+    (
+        "//para/..",
+        "chapter(Onep1p2) chapter(Twop3p4) section(p4) appendix(p5)",
+    ),
+    ("/ | /doc", "/ doc(Onep1p2Twop3p4p5)"),
+    ("count(//para | //title | //para)", "7"),
+    // Strings, numbers, booleans
+    ("string(//chapter[last()]/@id)", "c2"),
+    ("name(/*)", "doc"),
+    ("string((//para)[4])", "p4"),
+    ("string(//para[3])", ""),
+    ("count(//chapter[@n > 1])", "1"),
+    ("1 div 0", "Infinity"),
+    ("-1 div 0", "-Infinity"),
+    ("0 div 0 = 0 div 0", "false"),
+    ("0 div 0 != 0 div 0", "true"),
+    ("string(-0)", "0"),
+    ("7 mod 3 + 0.5", "1.5"),
+    ("string(1 div 3 > 0.3)", "true"),
+];
+
+fn describe<D: DocumentAccess>(doc: &D, value: &XPathValue) -> String {
+    match value {
+        XPathValue::NodeSet(nodes) => nodes
+            .iter()
+            .map(|&id| {
+                if id == doc.document_node_id() {
+                    "/".to_string()
+                } else if doc.node_kind_of(id) == NodeKind::Element {
+                    format!(
+                        "{}({})",
+                        doc.node_name(id).unwrap_or_default(),
+                        dom::node_string_value(doc, id)
+                    )
+                } else {
+                    format!("{:?}", dom::node_string_value(doc, id))
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" "),
+        XPathValue::StringList(values) => values.join(" "),
+        other => other.to_string_value(),
+    }
+}
+
+fn run_cases<D: DocumentAccess>(doc: &D, label: &str) {
+    let mut failures = Vec::new();
+    for &(xpath, expected) in CASES {
+        let actual = match evaluate(doc, xpath) {
+            Ok(value) => describe(doc, &value),
+            Err(e) => format!("error: {}", e),
+        };
+        if actual != expected {
+            failures.push(format!(
+                "{xpath}\n    expected: {expected}\n    actual:   {actual}"
+            ));
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "{} conformance failures ({}):\n{}",
+        label,
+        failures.len(),
+        failures.join("\n")
+    );
+}
+
+#[test]
+fn conformance_dom() {
+    let doc = XmlDocument::parse(BOOK);
+    run_cases(&doc, "DOM");
+}
+
+#[test]
+fn conformance_indexed_view() {
+    let index = build_index(BOOK);
+    let view = IndexedDocumentView::new(&index, BOOK);
+    run_cases(&view, "indexed view");
+}
@@ -0,0 +1,208 @@
+//! ISO 8601 Date/Time Extension Functions
+//!
+//! XPath 1.0 has no date type, so timestamps in documents can only be
+//! compared as strings. These functions, under the `rx:` prefix, convert
+//! them to seconds since the Unix epoch so predicates can compare them as
+//! numbers:
+//!
+//! - `rx:dt(s)` / `rx:parse-datetime(s)`: seconds since 1970-01-01T00:00:00Z,
+//!   or NaN (which every comparison rejects) if `s` isn't a date/time
+//! - `rx:dateTime-greater-than(a, b)`, `rx:dateTime-less-than(a, b)`,
+//!   `rx:dateTime-equal(a, b)`: false unless both parse
+//!
+//! Accepted forms are the ISO 8601 extended format profile of RFC 3339:
+//! `YYYY-MM-DD`, optionally followed by `T` (or a space) and `hh:mm`,
+//! `hh:mm:ss` or `hh:mm:ss.fff`, then `Z`, `±hh:mm`, `±hhmm` or `±hh`.
+//! Values without an offset are taken as UTC; a date alone is midnight.
+
+/// Seconds since the Unix epoch of an ISO 8601 date or date/time
+pub fn parse_datetime(s: &str) -> Option<f64> {
+    let s = s.trim_matches([' ', '\t', '\r', '\n']).as_bytes();
+    let mut cursor = Cursor { input: s, pos: 0 };
+
+    let year = cursor.digits(4)? as i64;
+    cursor.expect(b'-')?;
+    let month = cursor.digits(2)?;
+    cursor.expect(b'-')?;
+    let day = cursor.digits(2)?;
+    if !(1..=12).contains(&month) || day == 0 || day > days_in_month(year, month) {
+        return None;
+    }
+    let mut seconds = days_from_civil(year, month, day) as f64 * 86_400.0;
+    if cursor.done() {
+        return Some(seconds);
+    }
+
+    if !matches!(cursor.next()?, b'T' | b't' | b' ') {
+        return None;
+    }
+    let hour = cursor.digits(2)?;
+    cursor.expect(b':')?;
+    let minute = cursor.digits(2)?;
+    let mut second = 0.0;
+    if cursor.peek() == Some(b':') {
+        cursor.pos += 1;
+        second = f64::from(cursor.digits(2)?);
+        if matches!(cursor.peek(), Some(b'.' | b',')) {
+            cursor.pos += 1;
+            second += cursor.fraction()?;
+        }
+    }
+    // 24:00:00 is the end of the day; second 60 is a leap second
+    let end_of_day = hour == 24 && minute == 0 && second == 0.0;
+    if (hour > 23 && !end_of_day) || minute > 59 || second >= 61.0 {
+        return None;
+    }
+    seconds += f64::from(hour * 3600 + minute * 60) + second;
+
+    let offset = match cursor.next() {
+        None => 0,
+        Some(b'Z' | b'z') => 0,
+        Some(sign @ (b'+' | b'-')) => {
+            let hours = cursor.digits(2)?;
+            if cursor.peek() == Some(b':') {
+                cursor.pos += 1;
+            }
+            let minutes = if cursor.done() { 0 } else { cursor.digits(2)? };
+            if hours > 23 || minutes > 59 {
+                return None;
+            }
+            let offset = i64::from(hours * 3600 + minutes * 60);
+            if sign == b'-' {
+                -offset
+            } else {
+                offset
+            }
+        }
+        Some(_) => return None,
+    };
+    if !cursor.done() {
+        return None;
+    }
+    Some(seconds - offset as f64)
+}
+
+struct Cursor<'a> {
+    input: &'a [u8],
+    pos: usize,
+}
+
+impl Cursor<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.input.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<u8> {
+        let b = self.peek()?;
+        self.pos += 1;
+        Some(b)
+    }
+
+    fn done(&self) -> bool {
+        self.pos == self.input.len()
+    }
+
+    fn expect(&mut self, b: u8) -> Option<()> {
+        (self.next()? == b).then_some(())
+    }
+
+    /// Exactly `n` ASCII digits
+    fn digits(&mut self, n: usize) -> Option<u32> {
+        let digits = self.input.get(self.pos..self.pos + n)?;
+        if !digits.iter().all(u8::is_ascii_digit) {
+            return None;
+        }
+        self.pos += n;
+        Some(
+            digits
+                .iter()
+                .fold(0, |acc, &d| acc * 10 + u32::from(d - b'0')),
+        )
+    }
+
+    /// One or more digits after a decimal point, as a fraction
+    fn fraction(&mut self) -> Option<f64> {
+        let start = self.pos;
+        while self.peek().is_some_and(|b| b.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        let digits = std::str::from_utf8(&self.input[start..self.pos]).ok()?;
+        if digits.is_empty() {
+            return None;
+        }
+        format!("0.{}", digits).parse().ok()
+    }
+}
+
+fn is_leap_year(year: i64) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+pub(crate) fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Days from 1970-01-01 to a proleptic Gregorian date (Howard Hinnant's
+/// `days_from_civil`)
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = i64::from(month);
+    let day_of_year =
+        (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_datetime_forms() {
+        let cases = [
+            ("1970-01-01", 0.0),
+            ("1970-01-01T00:00:00Z", 0.0),
+            ("2024-01-01T00:00:00Z", 1_704_067_200.0),
+            ("2024-01-01 00:00:00z", 1_704_067_200.0),
+            ("2024-01-01T00:00", 1_704_067_200.0),
+            ("2024-01-01T01:30:00+01:30", 1_704_067_200.0),
+            ("2023-12-31T19:00:00-0500", 1_704_067_200.0),
+            ("2024-01-01T02:00:00+02", 1_704_067_200.0),
+            ("2023-12-31T24:00:00Z", 1_704_067_200.0),
+            ("2024-02-29T12:00:00.250Z", 1_709_208_000.25),
+            ("  1969-12-31T23:59:59Z\n", -1.0),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(parse_datetime(input), Some(expected), "{input}");
+        }
+    }
+
+    #[test]
+    fn test_parse_datetime_rejects_invalid() {
+        for input in [
+            "",
+            "2024",
+            "2024-1-01",
+            "2023-02-29",
+            "2024-13-01",
+            "2024-04-31",
+            "2024-01-01T25:00:00Z",
+            "2024-01-01T24:00:01Z",
+            "2024-01-01T10:60:00Z",
+            "2024-01-01T10:00:00.Z",
+            "2024-01-01T10:00:00+25:00",
+            "2024-01-01T10:00:00Q",
+            "2024-01-01T10:00:00Z trailing",
+            "yesterday",
+        ] {
+            assert_eq!(parse_datetime(input), None, "{input}");
+        }
+    }
+}
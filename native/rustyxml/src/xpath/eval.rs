@@ -2,14 +2,14 @@
 //!
 //! Evaluates compiled XPath expressions against an XML document.
 
-use super::axes::{matches_node_test, navigate};
+use super::axes::{in_scope_namespaces, matches_resolved_node_test, navigate, resolve_node_test};
 use super::compiler::{CompiledExpr, CompiledNodeTest, Op};
 use super::functions;
-use super::parser::BinaryOp;
-use super::value::XPathValue;
+use super::parser::{Axis, BinaryOp};
+use super::value::{format_number, NamespaceNode, XPathValue};
 #[cfg(test)]
 use crate::dom::XmlDocument;
-use crate::dom::{self, DocumentAccess, NodeId};
+use crate::dom::{self, DocumentAccess, NameSymbol, NodeId};
 use std::collections::HashSet;
 
 /// Evaluation context - generic over document type
@@ -18,19 +18,67 @@ pub struct EvalContext<'a, D: DocumentAccess> {
     pub context_node: NodeId,
     pub context_position: usize,
     pub context_size: usize,
+    /// Namespace node a predicate is testing; `context_node` is then the
+    /// element it belongs to
+    pub namespace: Option<&'a NamespaceNode>,
+}
+
+/// Order of the nodes in a node-set result
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ResultOrder {
+    /// Document order, as XPath 1.0 defines node-sets
+    #[default]
+    Document,
+    /// Reverse document order
+    Reverse,
+    /// The order the last step matched them in: context node by context
+    /// node along its axis, and the left operand of `|` before the right
+    Matched,
 }
 
 /// Evaluate an XPath expression against any document type
 #[must_use = "XPath evaluation result should be used"]
 pub fn evaluate<D: DocumentAccess>(doc: &D, xpath: &str) -> Result<XPathValue, String> {
     let compiled = super::compiler::compile(xpath)?;
+    evaluate_precompiled(doc, &compiled)
+}
+
+/// Evaluate an expression compiled with `compiler::compile`, with the root
+/// element as context node
+///
+/// Running one expression over many documents this way skips the compile
+/// cache lookup for each of them.
+#[must_use = "XPath evaluation result should be used"]
+pub fn evaluate_precompiled<D: DocumentAccess>(
+    doc: &D,
+    compiled: &CompiledExpr,
+) -> Result<XPathValue, String> {
     let context = EvalContext {
         doc,
         context_node: doc.root_element_id().unwrap_or(0),
         context_position: 1,
         context_size: 1,
+        namespace: None,
     };
-    evaluate_compiled(&compiled, &context)
+    evaluate_compiled(compiled, &context)
+}
+
+/// Evaluate an XPath expression, returning a node-set result in `order`
+#[must_use = "XPath evaluation result should be used"]
+pub fn evaluate_ordered<D: DocumentAccess>(
+    doc: &D,
+    xpath: &str,
+    order: ResultOrder,
+) -> Result<XPathValue, String> {
+    let compiled = super::compiler::compile(xpath)?;
+    let context = EvalContext {
+        doc,
+        context_node: doc.root_element_id().unwrap_or(0),
+        context_position: 1,
+        context_size: 1,
+        namespace: None,
+    };
+    evaluate_compiled_ordered(&compiled, &context, order)
 }
 
 /// Evaluate an XPath expression from a specific context node
@@ -41,32 +89,136 @@ pub fn evaluate_from_node<D: DocumentAccess>(
     xpath: &str,
 ) -> Result<XPathValue, String> {
     let compiled = super::compiler::compile(xpath)?;
+    evaluate_precompiled_from_node(doc, context_node, &compiled)
+}
+
+/// Evaluate a compiled expression from a specific context node
+///
+/// For running one relative expression from many context nodes.
+#[must_use = "XPath evaluation result should be used"]
+pub fn evaluate_precompiled_from_node<D: DocumentAccess>(
+    doc: &D,
+    context_node: NodeId,
+    compiled: &CompiledExpr,
+) -> Result<XPathValue, String> {
     let context = EvalContext {
         doc,
         context_node,
         context_position: 1,
         context_size: 1,
+        namespace: None,
     };
-    evaluate_compiled(&compiled, &context)
+    evaluate_compiled(compiled, &context)
+}
+
+/// Evaluate an XPath expression for the first node it selects in document
+/// order, with the root element as context node
+///
+/// A node-set result holds at most that node; other values are returned as
+/// they are. Location paths stop at the first match where `find_first`
+/// allows it.
+#[must_use = "XPath evaluation result should be used"]
+pub fn evaluate_first<D: DocumentAccess>(doc: &D, xpath: &str) -> Result<XPathValue, String> {
+    let compiled = super::compiler::compile(xpath)?;
+    let context = EvalContext {
+        doc,
+        context_node: doc.root_element_id().unwrap_or(0),
+        context_position: 1,
+        context_size: 1,
+        namespace: None,
+    };
+    if let Some(found) = find_first(&compiled, &context, Search::First)? {
+        return Ok(XPathValue::NodeSet(found.into_iter().collect()));
+    }
+    let mut value = evaluate_compiled(&compiled, &context)?;
+    if let XPathValue::NodeSet(nodes) = &mut value {
+        nodes.truncate(1);
+    }
+    Ok(value)
+}
+
+/// Whether an XPath expression selects anything, with the root element as
+/// context node
+///
+/// True for a non-empty node-set or any attribute value; expressions that
+/// don't select nodes give their boolean value. Location paths stop at the
+/// first match where `find_first` allows it.
+#[must_use = "XPath evaluation result should be used"]
+pub fn evaluate_exists<D: DocumentAccess>(doc: &D, xpath: &str) -> Result<bool, String> {
+    let compiled = super::compiler::compile(xpath)?;
+    let context = EvalContext {
+        doc,
+        context_node: doc.root_element_id().unwrap_or(0),
+        context_position: 1,
+        context_size: 1,
+        namespace: None,
+    };
+    if let Some(found) = find_first(&compiled, &context, Search::Any)? {
+        return Ok(found.is_some());
+    }
+    let selects_attributes = matches!(
+        compiled.ops.last(),
+        Some(Op::Navigate(Axis::Attribute | Axis::Namespace, _))
+    );
+    Ok(match evaluate_compiled(&compiled, &context)? {
+        XPathValue::NodeSet(nodes) => !nodes.is_empty(),
+        XPathValue::StringList(values) => !values.is_empty(),
+        XPathValue::String(_) if selects_attributes => true,
+        value => value.to_boolean(),
+    })
 }
 
 /// Evaluate a compiled expression
 pub fn evaluate_compiled<'a, D: DocumentAccess>(
     expr: &CompiledExpr,
     ctx: &EvalContext<'a, D>,
+) -> Result<XPathValue, String> {
+    evaluate_compiled_ordered(expr, ctx, ResultOrder::Document)
+}
+
+/// Evaluate a compiled expression, returning a node-set result in `order`
+///
+/// Every step deduplicates its nodes and sorts them into document order,
+/// which predicates and later steps rely on. Only the last step skips the
+/// sort for `ResultOrder::Matched`, and predicates after it filter by
+/// document position while keeping the matched order; `Reverse` turns the
+/// final node-set around.
+pub fn evaluate_compiled_ordered<'a, D: DocumentAccess>(
+    expr: &CompiledExpr,
+    ctx: &EvalContext<'a, D>,
+    order: ResultOrder,
+) -> Result<XPathValue, String> {
+    evaluate_ops(&expr.ops, ctx, order)
+}
+
+/// Run a sequence of compiled ops, leaving the value of the last
+fn evaluate_ops<D: DocumentAccess>(
+    ops: &[Op],
+    ctx: &EvalContext<'_, D>,
+    order: ResultOrder,
 ) -> Result<XPathValue, String> {
     let mut stack: Vec<XPathValue> = Vec::new();
+    // Set when an attribute index already applied the next predicate op
+    let mut skip_predicate = false;
+    // The last op that isn't a predicate; under `Matched`, nothing from it on sorts
+    let last_step = ops.iter().rposition(|op| !is_predicate_op(op));
 
-    for op in &expr.ops {
+    for (i, op) in ops.iter().enumerate() {
+        if std::mem::take(&mut skip_predicate) {
+            continue;
+        }
+        let sort = order != ResultOrder::Matched || last_step.is_some_and(|last| i < last);
+        crate::cancel::check()?;
         match op {
             Op::Root => {
                 // Root is the document node (for XPath absolute paths like /root)
                 stack.push(XPathValue::single_node(ctx.doc.document_node_id()));
             }
 
-            Op::Context => {
-                stack.push(XPathValue::single_node(ctx.context_node));
-            }
+            Op::Context => match ctx.namespace {
+                Some(ns) => stack.push(XPathValue::NamespaceNodes(vec![ns.clone()])),
+                None => stack.push(XPathValue::single_node(ctx.context_node)),
+            },
 
             Op::Parent => {
                 let current = stack
@@ -83,7 +235,14 @@ pub fn evaluate_compiled<'a, D: DocumentAccess>(
                             }
                         }
                     }
-                    parents.sort_unstable(); // Document order
+                    if sort {
+                        sort_document_order(ctx.doc, &mut parents);
+                    }
+                    stack.push(XPathValue::NodeSet(parents));
+                } else if let XPathValue::NamespaceNodes(nodes) = current {
+                    // A namespace node's parent is its element
+                    let mut parents: Vec<NodeId> = nodes.iter().map(|ns| ns.parent).collect();
+                    parents.dedup();
                     stack.push(XPathValue::NodeSet(parents));
                 } else {
                     stack.push(XPathValue::empty_nodeset());
@@ -94,9 +253,29 @@ pub fn evaluate_compiled<'a, D: DocumentAccess>(
                 let current = stack
                     .pop()
                     .unwrap_or(XPathValue::single_node(ctx.context_node));
+                let current = match current {
+                    XPathValue::NamespaceNodes(nodes) if *axis == Axis::Self_ => {
+                        stack.push(namespace_self(nodes, node_test, &[], ctx)?);
+                        continue;
+                    }
+                    other => other,
+                };
+                let (current, axis) = from_namespace_nodes(current, *axis);
+                let axis = &axis;
                 if let XPathValue::NodeSet(nodes) = current {
-                    // Special handling for attribute axis
-                    if *axis == super::parser::Axis::Attribute {
+                    if let Some(result) =
+                        indexed_descendants(ctx.doc, &nodes, *axis, node_test, ops.get(i + 1))
+                    {
+                        skip_predicate = true;
+                        stack.push(XPathValue::NodeSet(result));
+                        continue;
+                    }
+                    if *axis == Axis::Namespace {
+                        stack.push(namespace_step(&nodes, node_test, &[], ctx)?);
+                    } else if *axis == Axis::Attribute {
+                        // Attributes have no NodeId, so results are their
+                        // string-values
+                        let attr_symbol = resolve_node_test(ctx.doc, node_test);
                         let mut attr_values: Vec<String> = Vec::new();
                         for node in nodes {
                             match node_test {
@@ -108,7 +287,9 @@ pub fn evaluate_compiled<'a, D: DocumentAccess>(
                                 }
                                 CompiledNodeTest::Name(name) => {
                                     // @name - specific attribute
-                                    if let Some(value) = ctx.doc.get_attribute(node, name) {
+                                    if let Some(value) =
+                                        attribute_value(ctx.doc, node, name, attr_symbol)
+                                    {
                                         attr_values.push(value.to_string());
                                     }
                                 }
@@ -135,18 +316,22 @@ pub fn evaluate_compiled<'a, D: DocumentAccess>(
                         // HashSet pre-allocation for 120K-node descendant traversals).
                         let mut seen = HashSet::with_capacity(nodes.len());
                         let mut result = Vec::with_capacity(nodes.len());
+                        let resolved = resolve_node_test(ctx.doc, node_test);
                         for node in nodes {
+                            crate::cancel::check()?;
                             let axis_nodes = navigate(ctx.doc, node, *axis);
                             for candidate in axis_nodes {
-                                if matches_node_test(ctx.doc, candidate, node_test)
-                                    && seen.insert(candidate)
+                                if matches_resolved_node_test(
+                                    ctx.doc, candidate, node_test, resolved,
+                                ) && seen.insert(candidate)
                                 {
                                     result.push(candidate);
                                 }
                             }
                         }
-                        // Sort by document order (node IDs are assigned in document order)
-                        result.sort_unstable();
+                        if sort {
+                            sort_document_order(ctx.doc, &mut result);
+                        }
                         stack.push(XPathValue::NodeSet(result));
                     }
                 } else {
@@ -154,65 +339,68 @@ pub fn evaluate_compiled<'a, D: DocumentAccess>(
                 }
             }
 
-            Op::Predicate(pred_expr) => {
-                let current = stack.pop().unwrap_or(XPathValue::empty_nodeset());
-                if let XPathValue::NodeSet(nodes) = current {
-                    let size = nodes.len();
-                    let mut filtered = Vec::new();
-
-                    for (i, &node) in nodes.iter().enumerate() {
-                        let pred_ctx = EvalContext {
-                            doc: ctx.doc,
-                            context_node: node,
-                            context_position: i + 1,
-                            context_size: size,
-                        };
-
-                        let pred_result = evaluate_compiled(pred_expr, &pred_ctx)?;
-
-                        let include = match pred_result {
-                            XPathValue::Number(n) => (i + 1) as f64 == n,
-                            _ => pred_result.to_boolean(),
-                        };
-
-                        if include {
-                            filtered.push(node);
-                        }
+            Op::NavigateFiltered(axis, node_test, predicates) => {
+                let current = stack
+                    .pop()
+                    .unwrap_or(XPathValue::single_node(ctx.context_node));
+                let current = match current {
+                    XPathValue::NamespaceNodes(nodes) if *axis == Axis::Self_ => {
+                        stack.push(namespace_self(nodes, node_test, predicates, ctx)?);
+                        continue;
                     }
-
-                    stack.push(XPathValue::NodeSet(filtered));
-                } else {
-                    stack.push(XPathValue::empty_nodeset());
-                }
-            }
-
-            // Fast path: [@attr = 'value'] - direct attribute lookup
-            Op::PredicateAttrEq(attr_name, value) => {
-                let current = stack.pop().unwrap_or(XPathValue::empty_nodeset());
-                if let XPathValue::NodeSet(nodes) = current {
-                    let mut filtered = Vec::with_capacity(nodes.len() / 10); // Assume sparse matches
-                    for &node in &nodes {
-                        if let Some(attr_value) = ctx.doc.get_attribute(node, attr_name) {
-                            if attr_value == value {
-                                filtered.push(node);
+                    other => other,
+                };
+                let (current, axis) = from_namespace_nodes(current, *axis);
+                let axis = &axis;
+                if let (Axis::Namespace, XPathValue::NodeSet(nodes)) = (axis, &current) {
+                    stack.push(namespace_step(nodes, node_test, predicates, ctx)?);
+                } else if let XPathValue::NodeSet(nodes) = current {
+                    let mut seen = HashSet::with_capacity(nodes.len());
+                    let mut result = Vec::with_capacity(nodes.len());
+                    let resolved = resolve_node_test(ctx.doc, node_test);
+                    for node in nodes {
+                        crate::cancel::check()?;
+                        // Keep axis order (reverse document order for reverse
+                        // axes) so positions are proximity positions
+                        let mut candidates: Vec<NodeId> = navigate(ctx.doc, node, *axis)
+                            .into_iter()
+                            .filter(|&candidate| {
+                                matches_resolved_node_test(ctx.doc, candidate, node_test, resolved)
+                            })
+                            .collect();
+                        for predicate in predicates {
+                            candidates = filter_nodes(predicate, candidates, ctx)?;
+                        }
+                        for candidate in candidates {
+                            if seen.insert(candidate) {
+                                result.push(candidate);
                             }
                         }
                     }
-                    stack.push(XPathValue::NodeSet(filtered));
+                    if sort {
+                        sort_document_order(ctx.doc, &mut result);
+                    }
+                    stack.push(XPathValue::NodeSet(result));
                 } else {
                     stack.push(XPathValue::empty_nodeset());
                 }
             }
 
-            // Fast path: [n] - position predicate
-            Op::PredicatePosition(pos) => {
+            Op::Predicate(_)
+            | Op::PredicateAttrEq(..)
+            | Op::PredicateAttrExists(_)
+            | Op::PredicatePosition(_) => {
                 let current = stack.pop().unwrap_or(XPathValue::empty_nodeset());
                 if let XPathValue::NodeSet(nodes) = current {
-                    if *pos > 0 && *pos <= nodes.len() {
-                        stack.push(XPathValue::NodeSet(vec![nodes[*pos - 1]]));
+                    let filtered = if sort {
+                        filter_nodes(op, nodes, ctx)?
                     } else {
-                        stack.push(XPathValue::empty_nodeset());
-                    }
+                        filter_keeping_order(op, nodes, ctx)?
+                    };
+                    stack.push(XPathValue::NodeSet(filtered));
+                } else if let XPathValue::NamespaceNodes(nodes) = current {
+                    let filtered = filter_namespace_nodes(op, nodes, ctx)?;
+                    stack.push(namespace_nodes_value(filtered));
                 } else {
                     stack.push(XPathValue::empty_nodeset());
                 }
@@ -233,8 +421,9 @@ pub fn evaluate_compiled<'a, D: DocumentAccess>(
                                 result.push(node);
                             }
                         }
-                        // Sort by document order
-                        result.sort_unstable();
+                        if sort {
+                            sort_document_order(ctx.doc, &mut result);
+                        }
                         stack.push(XPathValue::NodeSet(result));
                     }
                     _ => {
@@ -289,6 +478,22 @@ pub fn evaluate_compiled<'a, D: DocumentAccess>(
                     args.push(stack.pop().unwrap_or(XPathValue::String(String::new())));
                 }
                 args.reverse();
+                // A namespace node has no NodeId, so functions that default to
+                // the context node are given it as their argument
+                if let (Some(ns), true) = (ctx.namespace, args.is_empty()) {
+                    if matches!(
+                        name.as_str(),
+                        "name"
+                            | "local-name"
+                            | "namespace-uri"
+                            | "string"
+                            | "string-length"
+                            | "normalize-space"
+                            | "number"
+                    ) {
+                        args.push(XPathValue::NamespaceNodes(vec![ns.clone()]));
+                    }
+                }
 
                 let result = functions::call(
                     name,
@@ -304,7 +509,430 @@ pub fn evaluate_compiled<'a, D: DocumentAccess>(
         }
     }
 
-    Ok(stack.pop().unwrap_or(XPathValue::empty_nodeset()))
+    let mut value = stack.pop().unwrap_or(XPathValue::empty_nodeset());
+    if let (ResultOrder::Reverse, XPathValue::NodeSet(nodes)) = (order, &mut value) {
+        nodes.reverse();
+    }
+    Ok(value)
+}
+
+/// Which match `find_first` is after
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Search {
+    /// The first in document order
+    First,
+    /// Any one
+    Any,
+}
+
+/// Evaluate a location path whose last step is `axis::test` with only
+/// position-independent predicates, stopping at the first match
+///
+/// The steps before the last are evaluated as usual for the context nodes.
+/// The last step then tests each context node's axis lazily (descendant
+/// axes through `any_descendant`), and skips the remaining context nodes
+/// once no later one can select an earlier node. Returns None for other
+/// expressions, which must be evaluated in full.
+fn find_first<D: DocumentAccess>(
+    expr: &CompiledExpr,
+    ctx: &EvalContext<'_, D>,
+    search: Search,
+) -> Result<Option<Option<NodeId>>, String> {
+    let ops = &expr.ops;
+    let predicate_count = ops
+        .iter()
+        .rev()
+        .take_while(|op| CompiledExpr::is_position_independent(op))
+        .count();
+    let Some(step) = ops.len().checked_sub(predicate_count + 1) else {
+        return Ok(None);
+    };
+    let Op::Navigate(axis, node_test) = &ops[step] else {
+        return Ok(None);
+    };
+    if matches!(axis, Axis::Attribute | Axis::Namespace) {
+        return Ok(None);
+    }
+    let predicates = &ops[step + 1..];
+    let contexts = if step == 0 {
+        vec![ctx.context_node]
+    } else {
+        match evaluate_ops(&ops[..step], ctx, ResultOrder::Document)? {
+            XPathValue::NodeSet(nodes) => nodes,
+            _ => return Ok(None),
+        }
+    };
+
+    // An attribute index answers the first predicate in document order
+    if let Some(nodes) =
+        indexed_descendants(ctx.doc, &contexts, *axis, node_test, ops.get(step + 1))
+    {
+        for node in nodes {
+            if passes_predicates(node, &predicates[1..], ctx)? {
+                return Ok(Some(Some(node)));
+            }
+        }
+        return Ok(Some(None));
+    }
+
+    let resolved = resolve_node_test(ctx.doc, node_test);
+    let passes = |node: NodeId| -> Result<bool, String> {
+        Ok(
+            matches_resolved_node_test(ctx.doc, node, node_test, resolved)
+                && passes_predicates(node, predicates, ctx)?,
+        )
+    };
+    // Along these axes, the first context node (in document order) with a
+    // match selects the first node overall
+    let ordered = matches!(
+        axis,
+        Axis::Descendant | Axis::DescendantOrSelf | Axis::Self_
+    );
+    let mut best: Option<NodeId> = None;
+    for &context in &contexts {
+        let found = if matches!(axis, Axis::Descendant | Axis::DescendantOrSelf) {
+            let mut found = Ok(None);
+            let mut visit = |node| match passes(node) {
+                Ok(false) => false,
+                Ok(true) => {
+                    found = Ok(Some(node));
+                    true
+                }
+                Err(e) => {
+                    found = Err(e);
+                    true
+                }
+            };
+            if !(*axis == Axis::DescendantOrSelf && visit(context)) {
+                ctx.doc.any_descendant(context, &mut visit);
+            }
+            found?
+        } else {
+            let mut nodes = navigate(ctx.doc, context, *axis);
+            // Reverse axes list the nearest node first
+            if matches!(
+                axis,
+                Axis::Parent
+                    | Axis::Ancestor
+                    | Axis::AncestorOrSelf
+                    | Axis::Preceding
+                    | Axis::PrecedingSibling
+            ) {
+                nodes.reverse();
+            }
+            let mut found = None;
+            for node in nodes {
+                if passes(node)? {
+                    found = Some(node);
+                    break;
+                }
+            }
+            found
+        };
+        let Some(found) = found else {
+            continue;
+        };
+        if search == Search::Any || ordered {
+            return Ok(Some(Some(found)));
+        }
+        best = match best {
+            Some(best) if ctx.doc.document_order_key(best) < ctx.doc.document_order_key(found) => {
+                Some(best)
+            }
+            _ => Some(found),
+        };
+    }
+    Ok(Some(best))
+}
+
+/// Select the namespace nodes of each element in `nodes` matching
+/// `node_test`, filtering each element's in turn by `predicates`
+///
+/// A namespace node's name is its prefix, so only `*`, `node()` and a
+/// plain name test can match.
+fn namespace_step<D: DocumentAccess>(
+    nodes: &[NodeId],
+    node_test: &CompiledNodeTest,
+    predicates: &[Op],
+    ctx: &EvalContext<'_, D>,
+) -> Result<XPathValue, String> {
+    let mut result = Vec::new();
+    for &node in nodes {
+        let mut candidates: Vec<NamespaceNode> = in_scope_namespaces(ctx.doc, node)
+            .into_iter()
+            .filter(|(prefix, _)| match node_test {
+                CompiledNodeTest::Any | CompiledNodeTest::Node => true,
+                CompiledNodeTest::Name(name) => name == prefix,
+                _ => false,
+            })
+            .map(|(prefix, uri)| NamespaceNode {
+                parent: node,
+                prefix: prefix.to_string(),
+                uri: uri.to_string(),
+            })
+            .collect();
+        for predicate in predicates {
+            candidates = filter_namespace_nodes(predicate, candidates, ctx)?;
+        }
+        result.extend(candidates);
+    }
+    Ok(namespace_nodes_value(result))
+}
+
+/// The self axis from namespace nodes: `self::node()` keeps each node,
+/// any other test selects nothing since the axis's principal node type is
+/// element
+fn namespace_self<D: DocumentAccess>(
+    nodes: Vec<NamespaceNode>,
+    node_test: &CompiledNodeTest,
+    predicates: &[Op],
+    ctx: &EvalContext<'_, D>,
+) -> Result<XPathValue, String> {
+    if !matches!(node_test, CompiledNodeTest::Node) {
+        return Ok(XPathValue::empty_nodeset());
+    }
+    let mut result = Vec::with_capacity(nodes.len());
+    for ns in nodes {
+        let mut candidates = vec![ns];
+        for predicate in predicates {
+            candidates = filter_namespace_nodes(predicate, candidates, ctx)?;
+        }
+        result.extend(candidates);
+    }
+    Ok(namespace_nodes_value(result))
+}
+
+/// Step from namespace nodes through their elements
+///
+/// A namespace node's parent is its element, so the parent and ancestor
+/// axes become the self and ancestor-or-self axes of the elements. Every
+/// other axis is empty from a namespace node.
+fn from_namespace_nodes(current: XPathValue, axis: Axis) -> (XPathValue, Axis) {
+    let XPathValue::NamespaceNodes(nodes) = current else {
+        return (current, axis);
+    };
+    let axis = match axis {
+        Axis::Parent => Axis::Self_,
+        Axis::Ancestor => Axis::AncestorOrSelf,
+        _ => return (XPathValue::empty_nodeset(), axis),
+    };
+    let mut parents: Vec<NodeId> = nodes.iter().map(|ns| ns.parent).collect();
+    parents.dedup();
+    (XPathValue::NodeSet(parents), axis)
+}
+
+/// Namespace nodes as a value; none at all is the empty node-set
+fn namespace_nodes_value(nodes: Vec<NamespaceNode>) -> XPathValue {
+    if nodes.is_empty() {
+        XPathValue::empty_nodeset()
+    } else {
+        XPathValue::NamespaceNodes(nodes)
+    }
+}
+
+/// Apply a predicate op to namespace nodes, positions counting from 1
+fn filter_namespace_nodes<D: DocumentAccess>(
+    op: &Op,
+    nodes: Vec<NamespaceNode>,
+    ctx: &EvalContext<'_, D>,
+) -> Result<Vec<NamespaceNode>, String> {
+    match op {
+        Op::Predicate(pred_expr) => {
+            let size = nodes.len();
+            let mut filtered = Vec::new();
+            for (i, ns) in nodes.iter().enumerate() {
+                let pred_ctx = EvalContext {
+                    doc: ctx.doc,
+                    context_node: ns.parent,
+                    context_position: i + 1,
+                    context_size: size,
+                    namespace: Some(ns),
+                };
+                let include = match evaluate_compiled(pred_expr, &pred_ctx)? {
+                    XPathValue::Number(n) => (i + 1) as f64 == n,
+                    other => other.to_boolean(),
+                };
+                if include {
+                    filtered.push(ns.clone());
+                }
+            }
+            Ok(filtered)
+        }
+        Op::PredicatePosition(pos) => {
+            if *pos > 0 && *pos <= nodes.len() {
+                Ok(vec![nodes[*pos - 1].clone()])
+            } else {
+                Ok(Vec::new())
+            }
+        }
+        // Namespace nodes have no attributes
+        Op::PredicateAttrEq(..) | Op::PredicateAttrExists(_) => Ok(Vec::new()),
+        _ => Ok(nodes),
+    }
+}
+
+/// Whether a node passes every predicate op on its own
+fn passes_predicates<D: DocumentAccess>(
+    node: NodeId,
+    predicates: &[Op],
+    ctx: &EvalContext<'_, D>,
+) -> Result<bool, String> {
+    for op in predicates {
+        if filter_nodes(op, vec![node], ctx)?.is_empty() {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Whether an op filters the node-set before it
+fn is_predicate_op(op: &Op) -> bool {
+    matches!(
+        op,
+        Op::Predicate(_)
+            | Op::PredicateAttrEq(..)
+            | Op::PredicateAttrExists(_)
+            | Op::PredicatePosition(_)
+    )
+}
+
+/// Apply a predicate op to unsorted nodes: positions count in document
+/// order, and the nodes kept stay in the order they came in
+fn filter_keeping_order<D: DocumentAccess>(
+    op: &Op,
+    mut nodes: Vec<NodeId>,
+    ctx: &EvalContext<'_, D>,
+) -> Result<Vec<NodeId>, String> {
+    let mut sorted = nodes.clone();
+    sort_document_order(ctx.doc, &mut sorted);
+    let kept: HashSet<NodeId> = filter_nodes(op, sorted, ctx)?.into_iter().collect();
+    nodes.retain(|node| kept.contains(node));
+    Ok(nodes)
+}
+
+/// Apply a predicate op to nodes in their current order, positions counting from 1
+fn filter_nodes<D: DocumentAccess>(
+    op: &Op,
+    nodes: Vec<NodeId>,
+    ctx: &EvalContext<'_, D>,
+) -> Result<Vec<NodeId>, String> {
+    match op {
+        Op::Predicate(pred_expr) => {
+            let size = nodes.len();
+            let mut filtered = Vec::new();
+
+            for (i, &node) in nodes.iter().enumerate() {
+                crate::cancel::check()?;
+                let pred_ctx = EvalContext {
+                    doc: ctx.doc,
+                    context_node: node,
+                    context_position: i + 1,
+                    context_size: size,
+                    namespace: None,
+                };
+
+                let pred_result = evaluate_compiled(pred_expr, &pred_ctx)?;
+
+                let include = match pred_result {
+                    XPathValue::Number(n) => (i + 1) as f64 == n,
+                    _ => pred_result.to_boolean(),
+                };
+
+                if include {
+                    filtered.push(node);
+                }
+            }
+
+            Ok(filtered)
+        }
+
+        // Fast path: [@attr = 'value'] - direct attribute lookup
+        Op::PredicateAttrEq(attr_name, value) => {
+            let mut filtered = Vec::with_capacity(nodes.len() / 10); // Assume sparse matches
+            let attr_symbol = ctx.doc.name_symbol(attr_name);
+            for &node in &nodes {
+                if let Some(attr_value) = attribute_value(ctx.doc, node, attr_name, attr_symbol) {
+                    if attr_value == value {
+                        filtered.push(node);
+                    }
+                }
+            }
+            Ok(filtered)
+        }
+
+        // Fast path: [@attr] - direct attribute lookup
+        Op::PredicateAttrExists(attr_name) => {
+            let attr_symbol = ctx.doc.name_symbol(attr_name);
+            Ok(nodes
+                .into_iter()
+                .filter(|&node| attribute_value(ctx.doc, node, attr_name, attr_symbol).is_some())
+                .collect())
+        }
+
+        // Fast path: [n] - position predicate
+        Op::PredicatePosition(pos) => {
+            if *pos > 0 && *pos <= nodes.len() {
+                Ok(vec![nodes[*pos - 1]])
+            } else {
+                Ok(Vec::new())
+            }
+        }
+
+        _ => Ok(nodes),
+    }
+}
+
+/// Answer `descendant::test[@attr ...]` from the document node with an
+/// attribute value index, if the document has one for the attribute
+///
+/// The index lists every element with the attribute in document order, which
+/// is exactly the descendant axis of the document node filtered by the
+/// predicate; only the node test is left to apply.
+fn indexed_descendants<D: DocumentAccess>(
+    doc: &D,
+    nodes: &[NodeId],
+    axis: Axis,
+    node_test: &CompiledNodeTest,
+    next_op: Option<&Op>,
+) -> Option<Vec<NodeId>> {
+    if !matches!(axis, Axis::Descendant | Axis::DescendantOrSelf)
+        || nodes != [doc.document_node_id()]
+    {
+        return None;
+    }
+    let candidates = match next_op? {
+        Op::PredicateAttrEq(name, value) => doc.attribute_index_lookup(name, Some(value))?,
+        Op::PredicateAttrExists(name) => doc.attribute_index_lookup(name, None)?,
+        _ => return None,
+    };
+    crate::stats::record_nodes_visited(candidates.len());
+    let resolved = resolve_node_test(doc, node_test);
+    Some(
+        candidates
+            .iter()
+            .copied()
+            .filter(|&node| matches_resolved_node_test(doc, node, node_test, resolved))
+            .collect(),
+    )
+}
+
+/// Sort nodes into document order
+fn sort_document_order<D: DocumentAccess>(doc: &D, nodes: &mut [NodeId]) {
+    nodes.sort_unstable_by_key(|&id| doc.document_order_key(id));
+}
+
+/// Look up an attribute by its interned symbol when the document has one
+fn attribute_value<'d, D: DocumentAccess>(
+    doc: &'d D,
+    node: NodeId,
+    name: &str,
+    symbol: NameSymbol,
+) -> Option<&'d str> {
+    match symbol {
+        NameSymbol::Symbol(sym) => doc.get_attribute_by_symbol(node, sym),
+        NameSymbol::Absent => None,
+        NameSymbol::Unavailable => doc.get_attribute(node, name),
+    }
 }
 
 /// Compare two XPath values for equality per XPath 1.0 spec.
@@ -341,8 +969,10 @@ where
             XPathValue::Boolean(false)
         }
         (XPathValue::NodeSet(nodes), other) | (other, XPathValue::NodeSet(nodes)) => {
-            // Node-set vs other: compare each node's string-value against the other value
+            // Node-set vs other: compare each node's string-value against the other
+            // value, brought to the same normalization form
             let other_str = other.to_string_value();
+            let other_str = doc.text_normalization().apply_str(&other_str);
             for &n in nodes {
                 let ns = node_string_value(doc, n);
                 if cmp(&ns, &other_str) {
@@ -351,6 +981,10 @@ where
             }
             XPathValue::Boolean(false)
         }
+        (XPathValue::NamespaceNodes(nodes), other) | (other, XPathValue::NamespaceNodes(nodes)) => {
+            let other_str = other.to_string_value();
+            XPathValue::Boolean(nodes.iter().any(|ns| cmp(&ns.uri, &other_str)))
+        }
         (XPathValue::Boolean(_), _) | (_, XPathValue::Boolean(_)) => XPathValue::Boolean(cmp(
             &left.to_boolean().to_string(),
             &right.to_boolean().to_string(),
@@ -358,7 +992,11 @@ where
         (XPathValue::Number(_), _) | (_, XPathValue::Number(_)) => {
             let ln = left.to_number();
             let rn = right.to_number();
-            XPathValue::Boolean(cmp(&ln.to_string(), &rn.to_string()))
+            if ln.is_nan() || rn.is_nan() {
+                // NaN equals nothing: compare two strings that always differ
+                return XPathValue::Boolean(cmp("NaN", ""));
+            }
+            XPathValue::Boolean(cmp(&format_number(ln), &format_number(rn)))
         }
         (XPathValue::String(ls), XPathValue::String(rs)) => XPathValue::Boolean(cmp(ls, rs)),
         // Handle StringList and any other combinations
@@ -414,6 +1052,145 @@ mod tests {
         assert_eq!(result.as_nodeset().unwrap().len(), 1);
     }
 
+    #[test]
+    fn test_namespace_axis() {
+        let doc = XmlDocument::parse(
+            b"<root xmlns=\"urn:d\" xmlns:a=\"urn:a\"><child xmlns:a=\"urn:a2\"/></root>",
+        );
+        let uris = |xpath: &str| match evaluate(&doc, xpath).unwrap() {
+            XPathValue::NamespaceNodes(nodes) => nodes.into_iter().map(|ns| ns.uri).collect(),
+            XPathValue::NodeSet(nodes) if nodes.is_empty() => Vec::new(),
+            other => panic!("expected namespace nodes, got {other:?}"),
+        };
+        let string = |xpath: &str| evaluate(&doc, xpath).unwrap().to_string_value();
+
+        assert_eq!(uris("/*/*/namespace::a"), ["urn:a2"]);
+        let xml = super::super::axes::XML_NAMESPACE;
+        assert_eq!(uris("/*/namespace::*"), [xml, "urn:d", "urn:a"]);
+        assert!(uris("/*/namespace::missing").is_empty());
+
+        // Namespace nodes are nodes: counted, named by prefix, filtered
+        assert_eq!(string("count(/*/namespace::*)"), "3");
+        assert_eq!(string("count(//namespace::a)"), "2");
+        assert_eq!(string("name(/*/namespace::*[3])"), "a");
+        assert_eq!(string("local-name(/*/namespace::*[2])"), "");
+        assert_eq!(string("namespace-uri(/*/namespace::a)"), "");
+        assert_eq!(string("string(/*/*/namespace::a)"), "urn:a2");
+        assert_eq!(uris("/*/namespace::*[name() = 'a']"), ["urn:a"]);
+        assert_eq!(uris("/*/namespace::*[. = 'urn:d']"), ["urn:d"]);
+        assert_eq!(uris("/*/namespace::*[last()]"), ["urn:a"]);
+        assert_eq!(string("/*/namespace::*[position() = 1] = 'urn:d'"), "false");
+        assert_eq!(string("/*/namespace::* = 'urn:d'"), "true");
+
+        // Positions count within each element's namespace nodes
+        assert_eq!(uris("//*/namespace::*[2]"), ["urn:d", "urn:a2"]);
+        assert_eq!(string("name(/*/*/namespace::a/..)"), "child");
+
+        // The self axis keeps namespace nodes only for node()
+        assert_eq!(string("count(/*/namespace::*[self::node()])"), "3");
+        assert_eq!(string("count(/*/namespace::*[self::*])"), "0");
+        assert_eq!(uris("/*/namespace::a/self::node()"), ["urn:a"]);
+        assert_eq!(uris("/*/namespace::*/self::node()[. = 'urn:d']"), ["urn:d"]);
+        assert!(uris("/*/namespace::a/self::a").is_empty());
+    }
+
+    #[test]
+    fn test_result_order() {
+        let doc = XmlDocument::parse(b"<r><a><b/></a><b/><c/></r>");
+        let names = |xpath: &str, order: ResultOrder| -> Vec<String> {
+            let value = evaluate_ordered(&doc, xpath, order).unwrap();
+            let nodes = value.as_nodeset().unwrap();
+            nodes
+                .iter()
+                .map(|&id| doc.node_name(id).unwrap().to_string())
+                .collect()
+        };
+
+        assert_eq!(names("//c | //a", ResultOrder::Document), ["a", "c"]);
+        assert_eq!(names("//c | //a", ResultOrder::Reverse), ["c", "a"]);
+        assert_eq!(names("//c | //a", ResultOrder::Matched), ["c", "a"]);
+        // A reverse axis matches nearest first
+        assert_eq!(
+            names("//c/preceding::*", ResultOrder::Document),
+            ["a", "b", "b"]
+        );
+        assert_eq!(
+            names("//c/preceding::*", ResultOrder::Matched),
+            ["b", "b", "a"]
+        );
+        // Each node only once, where it was first matched
+        assert_eq!(names("//b/ancestor::*", ResultOrder::Matched), ["a", "r"]);
+        // Predicates still see document order
+        assert_eq!(names("(//b | //a)[1]", ResultOrder::Matched), ["a"]);
+        // ... but keep the order the last step matched in
+        assert_eq!(
+            names("(//c | //a)[self::*]", ResultOrder::Matched),
+            ["c", "a"]
+        );
+        assert_eq!(
+            names("(//c/preceding::*)[position() < 3]", ResultOrder::Matched),
+            ["b", "a"]
+        );
+        assert_eq!(
+            names("(//c/preceding::*)[position() < 3]", ResultOrder::Document),
+            ["a", "b"]
+        );
+    }
+
+    #[test]
+    fn test_first_and_exists_match_full_evaluation() {
+        use crate::index::{builder::build_index, IndexedDocumentView};
+
+        let input = b"<r><a id='1'><a><b n='x'/></a><b n='y'>t</b></a><c><b/><!--k--></c>\
+                      <a><b n='x'/></a></r>";
+        let index = build_index(input);
+        let view = IndexedDocumentView::new(&index, input);
+        let doc = XmlDocument::parse(input);
+        let queries = [
+            "//b",
+            "//a/b",
+            "//b[@n = 'x']",
+            "//a[b]/b[@n]",
+            "/r/c/b/following::b",
+            "//b/ancestor::a",
+            "//b[@n = 'y']/preceding::b",
+            "//comment()",
+            "(//a)[2]",
+            "//missing",
+            "//b[@n][not(@n = 'x')]",
+            "//a[@id]//b",
+            "count(//b)",
+            "//b/@n",
+            "//b/@missing",
+            "string(//missing)",
+        ];
+        fn check<D: DocumentAccess>(doc: &D, query: &str) {
+            let full = evaluate(doc, query).unwrap();
+            let first = evaluate_first(doc, query).unwrap();
+            let exists = match &full {
+                XPathValue::NodeSet(nodes) => {
+                    assert_eq!(first.as_nodeset().unwrap(), &nodes[..nodes.len().min(1)]);
+                    !nodes.is_empty()
+                }
+                XPathValue::String(_) if query.contains('@') => true,
+                value => {
+                    assert_eq!(format!("{first:?}"), format!("{value:?}"));
+                    value.to_boolean()
+                }
+            };
+            assert_eq!(evaluate_exists(doc, query).unwrap(), exists);
+        }
+        for query in queries {
+            check(&view, query);
+            check(&doc, query);
+        }
+        assert!(evaluate_first(&view, "//[").is_err());
+
+        // The scan stops at the first match
+        let (_, usage) = crate::stats::measure(|| evaluate_exists(&view, "//b").unwrap());
+        assert!(usage.nodes_visited < 5, "{}", usage.nodes_visited);
+    }
+
     #[test]
     fn test_descendant() {
         let doc = XmlDocument::parse(b"<root><a><b/></a></root>");
@@ -430,6 +1207,13 @@ mod tests {
         assert_eq!(result.as_nodeset().unwrap().len(), 1);
     }
 
+    #[test]
+    fn test_attribute_exists_predicate() {
+        let doc = XmlDocument::parse(b"<root><a id=\"\"/><a/><a id=\"x\"/></root>");
+        let result = evaluate(&doc, "count(/root/a[@id])").unwrap();
+        assert_eq!(result.to_number(), 2.0);
+    }
+
     #[test]
     fn test_count() {
         let doc = XmlDocument::parse(b"<root><a/><b/><c/></root>");
@@ -514,4 +1298,24 @@ mod tests {
             "NodeSet with numeric text '42.5' should be < 100"
         );
     }
+
+    #[test]
+    fn datetime_extension_filters_by_timestamp() {
+        let doc = XmlDocument::parse(
+            b"<log><event at='2023-12-31T23:30:00-01:00'>a</event>\
+              <event at='2023-12-31T23:30:00Z'>b</event>\
+              <event at='soon'>c</event></log>",
+        );
+        let query = "//event[rx:dt(@at) > rx:dt('2024-01-01T00:00:00Z')]";
+        let XPathValue::NodeSet(nodes) = evaluate(&doc, query).unwrap() else {
+            panic!("expected a node-set");
+        };
+        assert_eq!(nodes.len(), 1);
+        assert_eq!(crate::dom::node_string_value(&doc, nodes[0]), "a");
+
+        let query = "count(//event[rx:dateTime-less-than(@at, '2024-01-01')])";
+        assert_eq!(evaluate(&doc, query).unwrap().to_number(), 1.0);
+        let query = "rx:dateTime-equal('2024-01-01T01:00:00+01:00', '2024-01-01')";
+        assert!(evaluate(&doc, query).unwrap().to_boolean());
+    }
 }
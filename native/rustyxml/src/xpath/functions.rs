@@ -15,11 +15,47 @@
 //!
 //! Number Functions:
 //! - number(), sum(), floor(), ceiling(), round()
+//!
+//! Plus document() (from XSLT) for documents preloaded into a multi-document
+//! view, and the `rx:` date/time extensions in [`super::datetime`].
+//!
+//! String positions and lengths count characters (code points), as the
+//! spec requires. `set_string_units(StringUnits::Bytes)` counts UTF-8 bytes
+//! instead, for callers written against byte offsets.
 
+use super::datetime;
 use super::value::XPathValue;
 #[cfg(test)]
 use crate::dom::XmlDocument;
 use crate::dom::{self, DocumentAccess, NodeId};
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// What `substring()` and `string-length()` count
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StringUnits {
+    /// Unicode code points (XPath 1.0)
+    #[default]
+    Characters,
+    /// UTF-8 bytes; a substring that splits a character gets U+FFFD for
+    /// each partial one
+    Bytes,
+}
+
+static STRING_UNITS: AtomicU8 = AtomicU8::new(StringUnits::Characters as u8);
+
+/// Units string functions currently count
+#[inline]
+pub fn string_units() -> StringUnits {
+    match STRING_UNITS.load(Ordering::Relaxed) {
+        1 => StringUnits::Bytes,
+        _ => StringUnits::Characters,
+    }
+}
+
+/// Select the units for queries evaluated from now on, in every process
+pub fn set_string_units(units: StringUnits) {
+    STRING_UNITS.store(units as u8, Ordering::Relaxed);
+}
 
 /// Evaluate a function call
 pub fn call<D: DocumentAccess>(
@@ -39,6 +75,7 @@ pub fn call<D: DocumentAccess>(
         "namespace-uri" => fn_namespace_uri(args, doc, context),
         "name" => fn_name(args, doc, context),
         "id" => fn_id(args),
+        "document" => fn_document(args, doc),
 
         // String Functions
         "string" => fn_string(args, doc, context),
@@ -66,6 +103,12 @@ pub fn call<D: DocumentAccess>(
         "ceiling" => fn_ceiling(args),
         "round" => fn_round(args),
 
+        // Date/Time Extensions
+        "rx:dt" | "rx:parse-datetime" => fn_parse_datetime(args, doc),
+        "rx:dateTime-greater-than" => fn_compare_datetimes(args, doc, name, |a, b| a > b),
+        "rx:dateTime-less-than" => fn_compare_datetimes(args, doc, name, |a, b| a < b),
+        "rx:dateTime-equal" => fn_compare_datetimes(args, doc, name, |a, b| a == b),
+
         _ => Err(format!("Unknown function: {}", name)),
     }
 }
@@ -78,6 +121,7 @@ fn fn_count(args: Vec<XPathValue>) -> Result<XPathValue, String> {
     }
     match &args[0] {
         XPathValue::NodeSet(nodes) => Ok(XPathValue::Number(nodes.len() as f64)),
+        XPathValue::NamespaceNodes(nodes) => Ok(XPathValue::Number(nodes.len() as f64)),
         _ => Err("count() argument must be a node-set".to_string()),
     }
 }
@@ -93,6 +137,11 @@ fn fn_local_name<D: DocumentAccess>(
         match &args[0] {
             XPathValue::NodeSet(nodes) if !nodes.is_empty() => nodes[0],
             XPathValue::NodeSet(_) => return Ok(XPathValue::String(String::new())),
+            // A namespace node's name is its prefix, with no namespace URI
+            XPathValue::NamespaceNodes(nodes) => {
+                let prefix = nodes.first().map(|ns| ns.prefix.clone());
+                return Ok(XPathValue::String(prefix.unwrap_or_default()));
+            }
             _ => return Err("local-name() argument must be a node-set".to_string()),
         }
     };
@@ -111,7 +160,9 @@ fn fn_namespace_uri<D: DocumentAccess>(
     } else {
         match &args[0] {
             XPathValue::NodeSet(nodes) if !nodes.is_empty() => nodes[0],
-            XPathValue::NodeSet(_) => return Ok(XPathValue::String(String::new())),
+            XPathValue::NodeSet(_) | XPathValue::NamespaceNodes(_) => {
+                return Ok(XPathValue::String(String::new()))
+            }
             _ => return Err("namespace-uri() argument must be a node-set".to_string()),
         }
     };
@@ -131,6 +182,10 @@ fn fn_name<D: DocumentAccess>(
         match &args[0] {
             XPathValue::NodeSet(nodes) if !nodes.is_empty() => nodes[0],
             XPathValue::NodeSet(_) => return Ok(XPathValue::String(String::new())),
+            XPathValue::NamespaceNodes(nodes) => {
+                let prefix = nodes.first().map(|ns| ns.prefix.clone());
+                return Ok(XPathValue::String(prefix.unwrap_or_default()));
+            }
             _ => return Err("name() argument must be a node-set".to_string()),
         }
     };
@@ -146,6 +201,41 @@ fn fn_id(_args: Vec<XPathValue>) -> Result<XPathValue, String> {
     )
 }
 
+/// document(name) - document node of a preloaded document
+///
+/// A node-set argument looks up the string-value of each node, returning the
+/// union of the matching documents. Only names registered with the query are
+/// available; nothing is loaded from the filesystem or network.
+fn fn_document<D: DocumentAccess>(args: Vec<XPathValue>, doc: &D) -> Result<XPathValue, String> {
+    if args.len() != 1 {
+        return Err("document() requires exactly 1 argument".to_string());
+    }
+    let names = match &args[0] {
+        XPathValue::NodeSet(nodes) => nodes
+            .iter()
+            .map(|&node| dom::node_string_value(doc, node))
+            .collect(),
+        XPathValue::StringList(list) => list.clone(),
+        other => vec![other.to_string_value()],
+    };
+
+    let mut result = Vec::with_capacity(names.len());
+    for name in &names {
+        match doc.document_by_name(name) {
+            Some(node) => result.push(node),
+            None => {
+                return Err(format!(
+                    "document(): no preloaded document named '{}'",
+                    name
+                ))
+            }
+        }
+    }
+    result.sort_unstable();
+    result.dedup();
+    Ok(XPathValue::NodeSet(result))
+}
+
 // String Functions
 
 fn fn_string<D: DocumentAccess>(
@@ -193,20 +283,45 @@ fn fn_substring<D: DocumentAccess>(args: Vec<XPathValue>, doc: &D) -> Result<XPa
     }
 
     let s = resolve_string(&args[0], doc);
-    let start = args[1].to_number().round() as i64 - 1; // XPath is 1-indexed
-    let start = start.max(0) as usize;
-
-    let chars: Vec<char> = s.chars().collect();
-
-    let result = if args.len() == 3 {
-        let len = args[2].to_number().round() as usize;
-        let end = (start + len).min(chars.len());
-        chars[start.min(chars.len())..end].iter().collect()
-    } else {
-        chars[start.min(chars.len())..].iter().collect()
+    let start = xpath_round(args[1].to_number());
+    let end = match args.get(2) {
+        Some(length) => start + xpath_round(length.to_number()),
+        None => f64::INFINITY,
     };
+    Ok(XPathValue::String(substring(
+        &s,
+        start,
+        end,
+        string_units(),
+    )))
+}
 
-    Ok(XPathValue::String(result))
+/// The units of `s` at 1-based positions p with `start <= p < end`
+///
+/// Compared as doubles, so NaN bounds keep nothing and infinite ones
+/// everything, as the spec's examples require.
+fn substring(s: &str, start: f64, end: f64, units: StringUnits) -> String {
+    let kept = |index: usize| {
+        let position = index as f64 + 1.0;
+        position >= start && position < end
+    };
+    match units {
+        StringUnits::Characters => s
+            .chars()
+            .enumerate()
+            .filter(|&(i, _)| kept(i))
+            .map(|(_, c)| c)
+            .collect(),
+        StringUnits::Bytes => {
+            let bytes: Vec<u8> = s
+                .bytes()
+                .enumerate()
+                .filter(|&(i, _)| kept(i))
+                .map(|(_, b)| b)
+                .collect();
+            String::from_utf8_lossy(&bytes).into_owned()
+        }
+    }
 }
 
 fn fn_substring_before<D: DocumentAccess>(
@@ -260,7 +375,11 @@ fn fn_string_length<D: DocumentAccess>(
     } else {
         resolve_string(&args[0], doc)
     };
-    Ok(XPathValue::Number(s.chars().count() as f64))
+    let length = match string_units() {
+        StringUnits::Characters => s.chars().count(),
+        StringUnits::Bytes => s.len(),
+    };
+    Ok(XPathValue::Number(length as f64))
 }
 
 fn fn_normalize_space<D: DocumentAccess>(
@@ -276,7 +395,13 @@ fn fn_normalize_space<D: DocumentAccess>(
         return Err("normalize-space() requires 0 or 1 arguments".to_string());
     };
 
-    let normalized: String = s.split_whitespace().collect::<Vec<_>>().join(" ");
+    // Only XML whitespace separates words; NBSP, U+3000 and the like are
+    // content
+    let normalized = s
+        .split([' ', '\t', '\r', '\n'])
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ");
 
     Ok(XPathValue::String(normalized))
 }
@@ -410,14 +535,48 @@ fn fn_round(args: Vec<XPathValue>) -> Result<XPathValue, String> {
     if args.len() != 1 {
         return Err("round() requires exactly 1 argument".to_string());
     }
-    let n = args[0].to_number();
-    // XPath round: rounds towards positive infinity for .5
-    let rounded = if n.fract() == 0.5 || n.fract() == -0.5 {
+    Ok(XPathValue::Number(xpath_round(args[0].to_number())))
+}
+
+/// XPath round: rounds towards positive infinity for .5
+fn xpath_round(n: f64) -> f64 {
+    if n.fract() == 0.5 || n.fract() == -0.5 {
         n.ceil()
     } else {
         n.round()
-    };
-    Ok(XPathValue::Number(rounded))
+    }
+}
+
+// Date/Time Extensions
+
+fn fn_parse_datetime<D: DocumentAccess>(
+    args: Vec<XPathValue>,
+    doc: &D,
+) -> Result<XPathValue, String> {
+    if args.len() != 1 {
+        return Err("rx:dt() requires exactly 1 argument".to_string());
+    }
+    let s = resolve_string(&args[0], doc);
+    Ok(XPathValue::Number(
+        datetime::parse_datetime(&s).unwrap_or(f64::NAN),
+    ))
+}
+
+fn fn_compare_datetimes<D: DocumentAccess>(
+    args: Vec<XPathValue>,
+    doc: &D,
+    name: &str,
+    compare: fn(f64, f64) -> bool,
+) -> Result<XPathValue, String> {
+    if args.len() != 2 {
+        return Err(format!("{}() requires exactly 2 arguments", name));
+    }
+    let a = datetime::parse_datetime(&resolve_string(&args[0], doc));
+    let b = datetime::parse_datetime(&resolve_string(&args[1], doc));
+    Ok(XPathValue::Boolean(match (a, b) {
+        (Some(a), Some(b)) => compare(a, b),
+        _ => false,
+    }))
 }
 
 /// Convert an XPath value to a string, using document access for NodeSets.
@@ -425,7 +584,7 @@ fn fn_round(args: Vec<XPathValue>) -> Result<XPathValue, String> {
 /// Per XPath 1.0 spec, the string-value of a node-set is the string-value
 /// of the first node in document order. This requires document access to
 /// extract actual text content (unlike `XPathValue::to_string_value()`).
-fn resolve_string<D: DocumentAccess>(val: &XPathValue, doc: &D) -> String {
+pub(crate) fn resolve_string<D: DocumentAccess>(val: &XPathValue, doc: &D) -> String {
     match val {
         XPathValue::NodeSet(nodes) => {
             if let Some(&first) = nodes.first() {
@@ -476,12 +635,70 @@ mod tests {
         assert_eq!(result.to_string_value(), "ell");
     }
 
+    #[test]
+    fn test_substring_edge_cases() {
+        // The examples of XPath 1.0 section 4.2
+        let doc = XmlDocument::parse(b"<r/>");
+        let cases = [
+            (1.5, Some(2.6), "234"),
+            (0.0, Some(3.0), "12"),
+            (f64::NAN, Some(3.0), ""),
+            (1.0, Some(f64::NAN), ""),
+            (-42.0, Some(f64::INFINITY), "12345"),
+            (f64::NEG_INFINITY, Some(f64::INFINITY), ""),
+            (4.0, None, "45"),
+        ];
+        for (start, length, expected) in cases {
+            let mut args = vec![
+                XPathValue::String("12345".to_string()),
+                XPathValue::Number(start),
+            ];
+            args.extend(length.map(XPathValue::Number));
+            let result = fn_substring(args, &doc).unwrap();
+            assert_eq!(result.to_string_value(), expected, "{start} {length:?}");
+        }
+    }
+
+    #[test]
+    fn test_string_functions_count_characters() {
+        let xml = "<r><a>日本語テキスト</a><b>a😀b👍🏽c</b></r>";
+        let doc = XmlDocument::parse(xml.as_bytes());
+        let cases = [
+            ("string-length(/r/a)", "7"),
+            ("string-length(/r/b)", "6"),
+            ("substring(/r/a, 2, 3)", "本語テ"),
+            ("substring(/r/b, 2, 1)", "😀"),
+            ("substring(/r/b, 4, 2)", "👍🏽"),
+            ("translate(/r/b, '😀👍', 'xy')", "axby🏽c"),
+            ("translate(/r/a, '語', '')", "日本テキスト"),
+            ("substring-before(/r/b, '👍')", "a😀b"),
+        ];
+        for (query, expected) in cases {
+            let value = crate::xpath::evaluate(&doc, query).unwrap();
+            assert_eq!(value.to_string_value(), expected, "{query}");
+        }
+    }
+
+    #[test]
+    fn test_substring_in_bytes() {
+        let s = "aé😀";
+        assert_eq!(substring(s, 2.0, 4.0, StringUnits::Bytes), "é");
+        assert_eq!(substring(s, 2.0, 3.0, StringUnits::Bytes), "\u{FFFD}");
+        assert_eq!(substring(s, 4.0, f64::INFINITY, StringUnits::Bytes), "😀");
+        assert_eq!(substring(s, 2.0, 4.0, StringUnits::Characters), "é😀");
+    }
+
     #[test]
     fn test_normalize_space() {
         let doc = XmlDocument::parse(b"<r/>");
         let args = vec![XPathValue::String("  hello   world  ".to_string())];
         let result = fn_normalize_space(args, &doc, 0).unwrap();
         assert_eq!(result.to_string_value(), "hello world");
+
+        // NBSP and ideographic spaces are not XML whitespace
+        let args = vec![XPathValue::String("\t日本\u{3000}語\u{A0} x\n".to_string())];
+        let result = fn_normalize_space(args, &doc, 0).unwrap();
+        assert_eq!(result.to_string_value(), "日本\u{3000}語\u{A0} x");
     }
 
     #[test]
@@ -491,6 +708,13 @@ mod tests {
         assert!(result.unwrap_err().contains("not supported"));
     }
 
+    #[test]
+    fn document_without_preloaded_documents_errors() {
+        let doc = XmlDocument::parse(b"<root/>");
+        let result = fn_document(vec![XPathValue::String("other.xml".to_string())], &doc);
+        assert!(result.unwrap_err().contains("other.xml"));
+    }
+
     #[test]
     fn lang_matches_xml_lang_attribute() {
         let doc = XmlDocument::parse(b"<root xml:lang=\"en\"><child/></root>");
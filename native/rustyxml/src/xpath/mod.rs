@@ -5,13 +5,21 @@
 //! - 27+ functions
 //! - Compiled expression caching
 
+pub mod aggregate;
 pub mod axes;
 pub mod compiler;
+#[cfg(test)]
+mod conformance;
+pub mod datetime;
 pub mod eval;
 pub mod functions;
 pub mod lexer;
 pub mod parser;
+pub mod typed;
 pub mod value;
 
-pub use eval::{evaluate, evaluate_from_node};
+pub use eval::{
+    evaluate, evaluate_exists, evaluate_first, evaluate_from_node, evaluate_precompiled,
+    ResultOrder,
+};
 pub use value::XPathValue;
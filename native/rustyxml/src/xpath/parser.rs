@@ -98,6 +98,44 @@ impl Axis {
             _ => None,
         }
     }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Axis::Child => "child",
+            Axis::Descendant => "descendant",
+            Axis::DescendantOrSelf => "descendant-or-self",
+            Axis::Parent => "parent",
+            Axis::Ancestor => "ancestor",
+            Axis::AncestorOrSelf => "ancestor-or-self",
+            Axis::FollowingSibling => "following-sibling",
+            Axis::PrecedingSibling => "preceding-sibling",
+            Axis::Following => "following",
+            Axis::Preceding => "preceding",
+            Axis::Self_ => "self",
+            Axis::Attribute => "attribute",
+            Axis::Namespace => "namespace",
+        }
+    }
+}
+
+impl BinaryOp {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BinaryOp::Or => "or",
+            BinaryOp::And => "and",
+            BinaryOp::Eq => "=",
+            BinaryOp::NotEq => "!=",
+            BinaryOp::Lt => "<",
+            BinaryOp::LtEq => "<=",
+            BinaryOp::Gt => ">",
+            BinaryOp::GtEq => ">=",
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "div",
+            BinaryOp::Mod => "mod",
+        }
+    }
 }
 
 /// Node test in a location step
@@ -464,6 +502,19 @@ impl<'a> Parser<'a> {
                     Ok(Expr::Step(Box::new(step)))
                 }
             }
+            Token::NameTest(qname) => {
+                let qname = qname.clone();
+                if !qname.ends_with(":*") && matches!(self.peek(), Token::LeftParen) {
+                    // Prefixed function call (extension functions such as rx:dt)
+                    self.advance();
+                    self.advance(); // Skip (
+                    let args = self.parse_function_args()?;
+                    Ok(Expr::Function(qname, args))
+                } else {
+                    let step = self.parse_step()?;
+                    Ok(Expr::Step(Box::new(step)))
+                }
+            }
             Token::NodeType(name) => {
                 let name = name.clone();
                 self.advance();
@@ -557,6 +608,21 @@ impl<'a> Parser<'a> {
             self.advance();
         }
 
+        // Abbreviated steps after a slash: . is self::node(), .. is parent::node()
+        if matches!(self.current, Token::Dot | Token::DoubleDot) {
+            let axis = if matches!(self.current, Token::Dot) {
+                Axis::Self_
+            } else {
+                Axis::Parent
+            };
+            self.advance();
+            return Ok(Step {
+                axis,
+                node_test: NodeTest::Node,
+                predicates: Vec::new(),
+            });
+        }
+
         let node_test = match &self.current {
             Token::Star => {
                 self.advance();
@@ -678,4 +744,12 @@ mod tests {
         let expr = parse("count(//item)").unwrap();
         assert!(matches!(expr, Expr::Function(name, _) if name == "count"));
     }
+
+    #[test]
+    fn test_prefixed_function() {
+        let expr = parse("rx:dt(.)").unwrap();
+        assert!(matches!(expr, Expr::Function(name, args) if name == "rx:dt" && args.len() == 1));
+        let expr = parse("rx:dt").unwrap();
+        assert!(matches!(expr, Expr::Step(_)));
+    }
 }
@@ -0,0 +1,134 @@
+//! Typed Values
+//!
+//! Converts the string-values of a query result to integers, floats, dates
+//! or booleans in Rust, so pulling numbers out of a document doesn't mean
+//! sending every string to the BEAM only to parse it there.
+
+use super::datetime::days_in_month;
+use super::value::XPathValue;
+use crate::dom::{node_string_value, DocumentAccess};
+
+/// What to convert string-values to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueType {
+    Integer,
+    Float,
+    Date,
+    Boolean,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TypedValue {
+    Integer(i64),
+    Float(f64),
+    Date { year: u32, month: u32, day: u32 },
+    Boolean(bool),
+}
+
+/// String-values of a result, one per node
+///
+/// Any value other than a node-set or a list of strings is a single string.
+pub fn string_values<D: DocumentAccess>(doc: &D, value: XPathValue) -> Vec<String> {
+    match value {
+        XPathValue::NodeSet(nodes) => nodes
+            .iter()
+            .map(|&node| node_string_value(doc, node))
+            .collect(),
+        XPathValue::StringList(list) => list,
+        XPathValue::NamespaceNodes(nodes) => nodes.into_iter().map(|ns| ns.uri).collect(),
+        other => vec![other.to_string_value()],
+    }
+}
+
+/// `s` as a value of type `ty`, or None if it isn't one
+///
+/// Surrounding whitespace is ignored. The lexical forms follow XML Schema:
+/// integers are decimal digits with an optional sign, floats are finite
+/// decimals or exponent forms (integers included), dates are `YYYY-MM-DD`
+/// without a timezone, and booleans are `true`, `false`, `1` or `0`.
+pub fn convert(s: &str, ty: ValueType) -> Option<TypedValue> {
+    let s = s.trim();
+    match ty {
+        ValueType::Integer => s.parse().ok().map(TypedValue::Integer),
+        ValueType::Float => {
+            // Rust also accepts "inf" and "NaN", which aren't numbers here
+            let numeric = s
+                .bytes()
+                .all(|b| b.is_ascii_digit() || b".+-eE".contains(&b));
+            s.parse::<f64>()
+                .ok()
+                .filter(|f| numeric && f.is_finite())
+                .map(TypedValue::Float)
+        }
+        ValueType::Date => parse_date(s),
+        ValueType::Boolean => match s {
+            "true" | "1" => Some(TypedValue::Boolean(true)),
+            "false" | "0" => Some(TypedValue::Boolean(false)),
+            _ => None,
+        },
+    }
+}
+
+fn parse_date(s: &str) -> Option<TypedValue> {
+    let mut parts = s.split('-');
+    let (year, month, day) = (parts.next()?, parts.next()?, parts.next()?);
+    let digits = |part: &str, len: usize| {
+        let valid = part.len() == len && part.bytes().all(|b| b.is_ascii_digit());
+        valid.then(|| part.parse().ok()).flatten()
+    };
+    let year: u32 = digits(year, 4)?;
+    let month: u32 = digits(month, 2)?;
+    let day: u32 = digits(day, 2)?;
+    let valid = parts.next().is_none()
+        && year > 0
+        && (1..=12).contains(&month)
+        && (1..=days_in_month(year.into(), month)).contains(&day);
+    valid.then_some(TypedValue::Date { year, month, day })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_convert_lexical_forms() {
+        use TypedValue::*;
+        assert_eq!(convert(" 42\n", ValueType::Integer), Some(Integer(42)));
+        assert_eq!(convert("-7", ValueType::Integer), Some(Integer(-7)));
+        assert_eq!(convert("4.2", ValueType::Integer), None);
+        assert_eq!(convert("12abc", ValueType::Integer), None);
+        assert_eq!(convert("", ValueType::Integer), None);
+
+        assert_eq!(convert("2.5", ValueType::Float), Some(Float(2.5)));
+        assert_eq!(convert("3", ValueType::Float), Some(Float(3.0)));
+        assert_eq!(convert("1e3", ValueType::Float), Some(Float(1000.0)));
+        assert_eq!(convert("NaN", ValueType::Float), None);
+        assert_eq!(convert("inf", ValueType::Float), None);
+        assert_eq!(convert("1e999", ValueType::Float), None);
+
+        assert_eq!(convert("1", ValueType::Boolean), Some(Boolean(true)));
+        assert_eq!(convert(" false ", ValueType::Boolean), Some(Boolean(false)));
+        assert_eq!(convert("yes", ValueType::Boolean), None);
+    }
+
+    #[test]
+    fn test_convert_dates() {
+        let date = |s| convert(s, ValueType::Date);
+        assert_eq!(
+            date("2024-02-29"),
+            Some(TypedValue::Date {
+                year: 2024,
+                month: 2,
+                day: 29
+            })
+        );
+        assert_eq!(date("2023-02-29"), None);
+        assert_eq!(date("1900-02-29"), None);
+        assert!(date("2000-02-29").is_some());
+        assert_eq!(date("2024-04-31"), None);
+        assert_eq!(date("2024-13-01"), None);
+        assert_eq!(date("2024-1-01"), None);
+        assert_eq!(date("2024-01-01Z"), None);
+        assert_eq!(date("2024-01-01T00:00:00"), None);
+    }
+}
@@ -18,6 +18,23 @@ pub enum XPathValue {
     String(String),
     /// List of strings (for attribute values)
     StringList(Vec<String>),
+    /// Namespace nodes, grouped by the element they belong to
+    NamespaceNodes(Vec<NamespaceNode>),
+}
+
+/// A namespace node: one in-scope binding of an element
+///
+/// Namespace nodes have no `NodeId`, so they carry what XPath can ask of
+/// them. The name of a namespace node is its prefix (empty for the default
+/// namespace) and its string-value is the URI.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NamespaceNode {
+    /// Element the binding is in scope on
+    pub parent: NodeId,
+    /// Bound prefix, empty for the default namespace
+    pub prefix: String,
+    /// Namespace URI
+    pub uri: String,
 }
 
 impl XPathValue {
@@ -39,6 +56,7 @@ impl XPathValue {
             XPathValue::Number(n) => *n != 0.0 && !n.is_nan(),
             XPathValue::String(s) => !s.is_empty(),
             XPathValue::StringList(list) => !list.is_empty(),
+            XPathValue::NamespaceNodes(nodes) => !nodes.is_empty(),
         }
     }
 
@@ -65,6 +83,9 @@ impl XPathValue {
                     list[0].trim().parse().unwrap_or(f64::NAN)
                 }
             }
+            XPathValue::NamespaceNodes(nodes) => nodes
+                .first()
+                .map_or(f64::NAN, |ns| ns.uri.trim().parse().unwrap_or(f64::NAN)),
         }
     }
 
@@ -84,17 +105,7 @@ impl XPathValue {
                 String::new()
             }
             XPathValue::Boolean(b) => if *b { "true" } else { "false" }.to_string(),
-            XPathValue::Number(n) => {
-                if n.is_nan() {
-                    "NaN".to_string()
-                } else if n.is_infinite() {
-                    if *n > 0.0 { "Infinity" } else { "-Infinity" }.to_string()
-                } else if *n == n.trunc() && n.abs() < 1e15 {
-                    format!("{}", *n as i64)
-                } else {
-                    format!("{}", n)
-                }
-            }
+            XPathValue::Number(n) => format_number(*n),
             XPathValue::String(s) => s.clone(),
             XPathValue::StringList(list) => {
                 if list.is_empty() {
@@ -103,6 +114,9 @@ impl XPathValue {
                     list[0].clone()
                 }
             }
+            XPathValue::NamespaceNodes(nodes) => {
+                nodes.first().map(|ns| ns.uri.clone()).unwrap_or_default()
+            }
         }
     }
 
@@ -177,6 +191,27 @@ impl From<&str> for XPathValue {
     }
 }
 
+/// Convert a number to a string per XPath 1.0 (section 4.2, `string()`)
+///
+/// - NaN, Infinity, -Infinity spelled out
+/// - Both zeros are "0"
+/// - Integers have no decimal point
+/// - Otherwise the shortest decimal that round-trips, never in exponent
+///   notation (1e21 is "1000000000000000000000", 1e-7 is "0.0000001")
+pub fn format_number(n: f64) -> String {
+    if n.is_nan() {
+        "NaN".to_string()
+    } else if n.is_infinite() {
+        if n > 0.0 { "Infinity" } else { "-Infinity" }.to_string()
+    } else if n == 0.0 {
+        "0".to_string()
+    } else {
+        // f64's Display is already shortest round-trip without an exponent,
+        // and prints integral values without a fractional part
+        format!("{}", n)
+    }
+}
+
 impl From<Vec<NodeId>> for XPathValue {
     fn from(nodes: Vec<NodeId>) -> Self {
         XPathValue::NodeSet(nodes)
@@ -207,6 +242,20 @@ mod tests {
         assert!(XPathValue::String("abc".to_string()).to_number().is_nan());
     }
 
+    #[test]
+    fn test_format_number() {
+        assert_eq!(format_number(1.0), "1");
+        assert_eq!(format_number(-2.0), "-2");
+        assert_eq!(format_number(0.5), "0.5");
+        assert_eq!(format_number(-0.0), "0");
+        assert_eq!(format_number(1e21), "1000000000000000000000");
+        assert_eq!(format_number(1.5e-7), "0.00000015");
+        assert_eq!(format_number(123456789012345680.0), "123456789012345680");
+        assert_eq!(format_number(f64::NAN), "NaN");
+        assert_eq!(format_number(f64::INFINITY), "Infinity");
+        assert_eq!(format_number(f64::NEG_INFINITY), "-Infinity");
+    }
+
     #[test]
     fn test_string_conversion() {
         assert_eq!(XPathValue::Boolean(true).to_string_value(), "true");
@@ -0,0 +1,210 @@
+//! XPointer Resolution
+//!
+//! Resolves fragment identifiers under the XPointer Framework to the nodes
+//! they identify:
+//!
+//! - Shorthand pointers (`intro`): the element whose `xml:id` or `id`
+//!   attribute has that value. With DTD processing disabled, these are the
+//!   only attributes recognised as IDs.
+//! - `element()`: a child sequence (`element(/1/3/2)`), optionally starting
+//!   from an element found by ID (`element(intro/2)`)
+//! - `xpointer()` and `xpath1()`: an XPath 1.0 expression selecting a
+//!   node-set (the location-set extensions of `xpointer()` are not
+//!   supported)
+//!
+//! Scheme-based parts are tried left to right and the first one that
+//! identifies any nodes wins; unknown schemes are skipped, as the framework
+//! requires. `xmlns()` parts are skipped too: their bindings are not
+//! applied, and prefixes in expressions match the document's own prefixes,
+//! as in all queries here. `^` escapes `(`, `)` and `^` in scheme data.
+
+use crate::dom::{DocumentAccess, NodeId, NodeKind};
+use crate::xpath::{evaluate, XPathValue};
+
+/// Nodes identified by an XPointer, in document order
+///
+/// Returns an empty list when the pointer is well-formed but identifies
+/// nothing, and an error for syntax errors.
+pub fn resolve<D: DocumentAccess>(doc: &D, pointer: &str) -> Result<Vec<NodeId>, String> {
+    let pointer = pointer.trim();
+    if is_ncname(pointer) {
+        return Ok(element_by_id(doc, pointer).into_iter().collect());
+    }
+    for (scheme, data) in parse_parts(pointer)? {
+        let nodes = match scheme.as_str() {
+            "element" => element_scheme(doc, &data)?,
+            "xpointer" | "xpath1" => xpath_scheme(doc, &data)?,
+            _ => continue,
+        };
+        if !nodes.is_empty() {
+            return Ok(nodes);
+        }
+    }
+    Ok(Vec::new())
+}
+
+/// Split a scheme-based pointer into `(scheme, unescaped data)` parts
+fn parse_parts(pointer: &str) -> Result<Vec<(String, String)>, String> {
+    let mut parts = Vec::new();
+    let mut chars = pointer.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_ascii_whitespace()).is_some() {}
+        if chars.peek().is_none() {
+            break;
+        }
+
+        let mut scheme = String::new();
+        while let Some(c) = chars.next_if(|&c| c != '(') {
+            scheme.push(c);
+        }
+        if chars.next().is_none() || !is_qname(&scheme) {
+            return Err(format!("Invalid XPointer: {}", pointer));
+        }
+
+        let mut data = String::new();
+        let mut depth = 1;
+        loop {
+            match chars.next() {
+                Some('^') => match chars.next() {
+                    Some(c @ ('(' | ')' | '^')) => data.push(c),
+                    _ => return Err(format!("Invalid escape in XPointer: {}", pointer)),
+                },
+                Some('(') => {
+                    depth += 1;
+                    data.push('(');
+                }
+                Some(')') => {
+                    depth -= 1;
+                    if depth == 0 {
+                        break;
+                    }
+                    data.push(')');
+                }
+                Some(c) => data.push(c),
+                None => return Err(format!("Unbalanced parentheses in XPointer: {}", pointer)),
+            }
+        }
+        parts.push((scheme, data));
+    }
+    if parts.is_empty() {
+        return Err("Empty XPointer".to_string());
+    }
+    Ok(parts)
+}
+
+/// `element()`: an optional ID followed by 1-based element child steps
+fn element_scheme<D: DocumentAccess>(doc: &D, data: &str) -> Result<Vec<NodeId>, String> {
+    let invalid = || format!("Invalid element() pointer: {}", data);
+    let mut steps = data.split('/');
+    let first = steps.next().unwrap_or_default();
+    let mut node = if first.is_empty() {
+        if data.is_empty() {
+            return Err(invalid());
+        }
+        doc.document_node_id()
+    } else if is_ncname(first) {
+        match element_by_id(doc, first) {
+            Some(node) => node,
+            None => return Ok(Vec::new()),
+        }
+    } else {
+        return Err(invalid());
+    };
+
+    for step in steps {
+        let n: usize = match step.parse() {
+            Ok(n) if n > 0 && step.bytes().all(|b| b.is_ascii_digit()) => n,
+            _ => return Err(invalid()),
+        };
+        let child = doc
+            .children_vec(node)
+            .into_iter()
+            .filter(|&child| doc.node_kind_of(child) == NodeKind::Element)
+            .nth(n - 1);
+        match child {
+            Some(child) => node = child,
+            None => return Ok(Vec::new()),
+        }
+    }
+    Ok(vec![node])
+}
+
+/// `xpointer()` / `xpath1()`: the node-set an XPath expression selects
+fn xpath_scheme<D: DocumentAccess>(doc: &D, expr: &str) -> Result<Vec<NodeId>, String> {
+    match evaluate(doc, expr)? {
+        XPathValue::NodeSet(nodes) => Ok(nodes),
+        _ => Ok(Vec::new()),
+    }
+}
+
+/// The first element whose `xml:id` or `id` attribute is `id`
+fn element_by_id<D: DocumentAccess>(doc: &D, id: &str) -> Option<NodeId> {
+    let root = doc.root_element_id()?;
+    std::iter::once(root)
+        .chain(doc.descendants_vec(root))
+        .filter(|&node| doc.node_kind_of(node) == NodeKind::Element)
+        .find(|&node| {
+            doc.get_attribute(node, "xml:id") == Some(id)
+                || doc.get_attribute(node, "id") == Some(id)
+        })
+}
+
+fn is_ncname(s: &str) -> bool {
+    let mut chars = s.chars();
+    chars.next().is_some_and(|c| c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '.' | '\u{B7}'))
+}
+
+fn is_qname(s: &str) -> bool {
+    match s.split_once(':') {
+        Some((prefix, local)) => is_ncname(prefix) && is_ncname(local),
+        None => is_ncname(s),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dom::XmlDocument;
+
+    const XML: &[u8] = b"<doc><head id='h'><title>T</title></head>\
+        <body><p>one</p><!-- c --><p xml:id='two'>two</p><p>three</p></body></doc>";
+
+    fn values(doc: &XmlDocument, pointer: &str) -> Vec<String> {
+        resolve(doc, pointer)
+            .unwrap()
+            .into_iter()
+            .map(|node| crate::dom::node_string_value(doc, node))
+            .collect()
+    }
+
+    #[test]
+    fn test_shorthand_and_element_scheme() {
+        let doc = XmlDocument::parse(XML);
+        assert_eq!(values(&doc, "two"), ["two"]);
+        assert_eq!(values(&doc, "element(/1/2/3)"), ["three"]);
+        assert_eq!(values(&doc, "element(h/1)"), ["T"]);
+        assert_eq!(values(&doc, "element(two)"), ["two"]);
+        assert!(values(&doc, "missing").is_empty());
+        assert!(values(&doc, "element(/1/9)").is_empty());
+        assert!(resolve(&doc, "element(/1/x)").is_err());
+        assert!(resolve(&doc, "element()").is_err());
+    }
+
+    #[test]
+    fn test_scheme_parts_fall_through() {
+        let doc = XmlDocument::parse(XML);
+        assert_eq!(
+            values(&doc, "xmlns(x=urn:x) element(nope) xpointer(//p[2])"),
+            ["two"]
+        );
+        assert_eq!(values(&doc, "other(^)^() xpath1(//p[last()])"), ["three"]);
+        assert_eq!(
+            values(&doc, "xpointer(//p[contains(., 'o^)')]) element(/1/1)"),
+            ["T"]
+        );
+        assert!(resolve(&doc, "element(/1").is_err());
+        assert!(resolve(&doc, "element(^x)").is_err());
+        assert!(resolve(&doc, "1bad(x)").is_err());
+    }
+}
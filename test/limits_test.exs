@@ -0,0 +1,209 @@
+defmodule RustyXML.LimitsTest do
+  # Limits are global, so these tests must not run alongside others
+  use ExUnit.Case, async: false
+
+  setup do
+    previous = RustyXML.Native.max_depth()
+    rules = RustyXML.Native.name_rules()
+    memory = RustyXML.Native.memory_limit()
+    units = RustyXML.Native.xpath_string_units()
+
+    on_exit(fn ->
+      RustyXML.Native.set_max_depth(previous)
+      RustyXML.Native.set_name_rules(rules)
+      RustyXML.Native.set_memory_limit(memory)
+      RustyXML.Native.set_xpath_string_units(units)
+    end)
+  end
+
+  describe "Native.set_max_depth/1" do
+    test "changes the depth accepted by strict parsing" do
+      assert RustyXML.Native.set_max_depth(2) == :ok
+      assert RustyXML.Native.max_depth() == 2
+
+      assert {:ok, _} = RustyXML.parse_document("<a><b/></a>")
+
+      assert RustyXML.parse_document("<a><b><c/></b></a>") ==
+               {:error, {:max_depth_exceeded, 2}}
+
+      assert RustyXML.Native.parse_to_simple_form("<a><b><c/></b></a>") ==
+               {:error, {:max_depth_exceeded, 2}}
+    end
+
+    test "ends a parse_async build that is too deep" do
+      assert RustyXML.Native.set_max_depth(2) == :ok
+      task = RustyXML.parse_async("<a><b><c/></b></a>")
+      assert_receive {:rustyxml_parse, ^task, {:error, {:max_depth_exceeded, 2}}}
+    end
+
+    test "0 disables the limit" do
+      assert RustyXML.Native.set_max_depth(0) == :ok
+      xml = String.duplicate("<a>", 20_000) <> String.duplicate("</a>", 20_000)
+      assert {:ok, _} = RustyXML.parse_document(xml)
+    end
+  end
+
+  describe "Native.set_name_rules/1" do
+    test "fifth edition accepts names the fourth edition tables reject" do
+      # Sinhala letters are not in the XML 1.0 Fourth Edition tables
+      xml = "<\u0D85\u0DCF a\u203Fb=\"1\"/>"
+
+      assert RustyXML.Native.name_rules() == :fourth_edition
+      assert {:error, _} = RustyXML.parse_document(xml)
+
+      assert RustyXML.Native.set_name_rules(:fifth_edition) == :ok
+      assert {:ok, doc} = RustyXML.parse_document(xml)
+      assert RustyXML.xpath(doc, "string(/*/@*)") == "1"
+      assert {:error, _} = RustyXML.parse_document("<a\u3000b/>")
+    end
+
+    test "rejects unknown editions" do
+      assert_raise ArgumentError, fn -> RustyXML.Native.set_name_rules(:sixth_edition) end
+    end
+  end
+
+  describe "Native.set_memory_limit/1" do
+    test "refuses parses and streaming chunks over the limit" do
+      xml = "<root>" <> String.duplicate("<item>x</item>", 1000) <> "</root>"
+      parser = RustyXML.Native.streaming_new()
+      limit = RustyXML.Native.get_rust_memory() + 1024
+
+      assert RustyXML.Native.set_memory_limit(limit) == :ok
+      assert RustyXML.Native.memory_limit() == limit
+
+      assert RustyXML.Native.parse(xml) == {:error, :memory_limit}
+      assert RustyXML.Native.parse_strict(xml) == {:error, :memory_limit}
+      assert RustyXML.parse_document(xml) == {:error, :memory_limit}
+      assert_raise RustyXML.ParseError, fn -> RustyXML.parse(xml, lenient: true) end
+      assert RustyXML.Native.streaming_feed(parser, xml) == {:error, :memory_limit}
+    end
+
+    test "also covers timed, cancellable, async and SAX parses and accumulators" do
+      xml = "<root>" <> String.duplicate("<item>x</item>", 1000) <> "</root>"
+      acc = RustyXML.Native.accumulator_new()
+      assert RustyXML.Native.set_memory_limit(RustyXML.Native.get_rust_memory() + 1024) == :ok
+
+      assert RustyXML.Native.parse_timed(xml) == {:error, :memory_limit}
+
+      assert RustyXML.Native.parse_cancellable(xml, RustyXML.cancel_token()) ==
+               {:error, :memory_limit}
+
+      assert RustyXML.Native.sax_parse(xml) == {:error, :memory_limit}
+      assert RustyXML.Native.sax_parse(xml, skip_whitespace: true) == {:error, :memory_limit}
+      assert RustyXML.Native.accumulator_feed(acc, xml) == {:error, :memory_limit}
+      assert RustyXML.Native.accumulator_feed_validated(acc, xml) == {:error, :memory_limit}
+      assert RustyXML.SimpleForm.parse_stream([xml]) == {:error, :memory_limit}
+
+      task = RustyXML.parse_async(xml)
+      assert_receive {:rustyxml_parse, ^task, {:error, :memory_limit}}
+    end
+
+    test "0 disables the limit" do
+      assert RustyXML.Native.set_memory_limit(0) == :ok
+      assert {:ok, _} = RustyXML.parse_document("<root/>")
+    end
+  end
+
+  describe "Native.set_xpath_string_units/1" do
+    test "switches substring and string-length between characters and bytes" do
+      doc = RustyXML.parse("<r>日本語</r>")
+
+      assert RustyXML.Native.xpath_string_units() == :characters
+      assert RustyXML.xpath(doc, "string-length(/r)") == 3.0
+      assert RustyXML.xpath(doc, "substring(/r, 2, 1)") == "本"
+
+      assert RustyXML.Native.set_xpath_string_units(:bytes) == :ok
+      assert RustyXML.xpath(doc, "string-length(/r)") == 9.0
+      assert RustyXML.xpath(doc, "substring(/r, 4, 3)") == "本"
+    end
+
+    test "cached node-sets are not reused under other units" do
+      doc = RustyXML.parse("<r><w>日本</w><w>abcdef</w></r>")
+      :ok = RustyXML.Native.doc_set_result_cache(doc, 8)
+      xpath = "//w[string-length() = 6]"
+
+      assert RustyXML.Native.xpath_text_list(doc, xpath) == ["abcdef"]
+      assert RustyXML.Native.set_xpath_string_units(:bytes) == :ok
+      assert RustyXML.Native.xpath_text_list(doc, xpath) == ["日本", "abcdef"]
+      assert RustyXML.Native.set_xpath_string_units(:characters) == :ok
+      assert RustyXML.Native.xpath_text_list(doc, xpath) == ["abcdef"]
+    end
+
+    test "rejects unknown units" do
+      assert_raise ArgumentError, fn -> RustyXML.Native.set_xpath_string_units(:words) end
+    end
+  end
+
+  describe "million-level documents" do
+    @describetag :slow
+    @describetag timeout: 300_000
+    @depth 1_000_000
+    @seeds [1, 2, 3]
+
+    test "return an error under the default limit" do
+      RustyXML.Native.set_max_depth(10_000)
+
+      for seed <- @seeds do
+        xml = deep_document(@depth, seed)
+        error = {:error, {:max_depth_exceeded, 10_000}}
+
+        assert RustyXML.SimpleForm.parse_string(xml) == error
+        assert RustyXML.SimpleForm.parse_string(xml, raw_text: true) == error
+        assert RustyXML.SimpleForm.parse_stream([xml]) == error
+        assert RustyXML.parse_document(xml) == error
+
+        # Unclosed documents fail well-formedness first
+        truncated = binary_part(xml, 0, byte_size(xml) - 4)
+        assert {:error, reason} = RustyXML.SimpleForm.parse_string(truncated)
+        assert is_binary(reason)
+      end
+    end
+
+    test "build the full tree without a limit" do
+      RustyXML.Native.set_max_depth(0)
+
+      for seed <- @seeds do
+        xml = deep_document(@depth, seed)
+
+        assert {:ok, tree} = RustyXML.SimpleForm.parse_string(xml)
+        assert element_depth(tree, 1) == @depth
+
+        assert {:ok, raw_tree} = RustyXML.SimpleForm.parse_string(xml, raw_text: true)
+        assert element_depth(raw_tree, 1) == @depth
+
+        assert {:ok, ^tree} = RustyXML.SimpleForm.parse_stream(chunked(xml))
+      end
+    end
+  end
+
+  # Every level picks one of a few shapes, so the builder sees attributes,
+  # entity-decoded text, comments and CDATA at every depth
+  defp deep_document(depth, seed) do
+    :rand.seed(:exsss, {seed, seed, seed})
+    opens = for i <- 1..depth, do: open_tag(i, :rand.uniform(4))
+    IO.iodata_to_binary([opens, "leaf", List.duplicate("</e>", depth)])
+  end
+
+  defp open_tag(_level, 1), do: "<e>"
+  defp open_tag(level, 2), do: ["<e n=\"", Integer.to_string(level), "\">"]
+  defp open_tag(_level, 3), do: "<e>t&amp;"
+  defp open_tag(_level, 4), do: "<e><!--c--><![CDATA[d]]>"
+
+  defp element_depth({_name, _attrs, children}, depth) do
+    case Enum.find(children, &match?({_, _, _}, &1)) do
+      nil -> depth
+      child -> element_depth(child, depth + 1)
+    end
+  end
+
+  defp chunked(xml) do
+    Stream.unfold(xml, fn
+      "" ->
+        nil
+
+      rest ->
+        size = min(65_536, byte_size(rest))
+        {binary_part(rest, 0, size), binary_part(rest, size, byte_size(rest) - size)}
+    end)
+  end
+end
@@ -44,6 +44,15 @@ defmodule RustyXMLTest do
       doc = RustyXML.parse(~c"<root><item/></root>")
       assert is_reference(doc)
     end
+
+    test "queries large input after the caller's binary is released" do
+      items = for i <- 1..5_000, into: "", do: ~s(<item id="#{i}">v#{i}</item>)
+      doc = RustyXML.parse("\uFEFF<root>" <> items <> "</root>")
+      :erlang.garbage_collect()
+
+      assert RustyXML.xpath(doc, ~x"count(//item)"i) == 5_000
+      assert RustyXML.xpath(doc, ~x"//item[@id='4999']/text()"s) == "v4999"
+    end
   end
 
   describe "xpath/2 with raw XML" do
@@ -83,6 +92,22 @@ defmodule RustyXMLTest do
       assert is_list(result)
       assert length(result) == 1
     end
+
+    test "small and large inputs give the same results" do
+      small = "<root><a n='1'>x</a><a n='2'>y</a></root>"
+      large = "<root>" <> String.duplicate("<a n='1'>x</a>", 200) <> "</root>"
+
+      for xml <- [small, large], path <- ["//a", "//a/@n", "count(//a)"] do
+        assert RustyXML.xpath(xml, path) == RustyXML.Native.parse_and_xpath(xml, path)
+      end
+
+      assert RustyXML.Native.parse_and_xpath_small(small, "//a") ==
+               RustyXML.Native.parse_and_xpath(small, "//a")
+
+      assert RustyXML.Native.parse_and_xpath_text_small(small, "//a") == ["x", "y"]
+      assert RustyXML.xpath(small, ~x"//a/text()"ls) == ["x", "y"]
+      assert {:error, _} = RustyXML.Native.parse_and_xpath_small(small, "///invalid[[[")
+    end
   end
 
   describe "xpath/2 with parsed document" do
@@ -101,6 +126,17 @@ defmodule RustyXMLTest do
       assert length(RustyXML.xpath(doc, "//c")) == 1
       assert length(RustyXML.xpath(doc, "/root/*")) == 3
     end
+
+    test "string-values decode entity references, repeatedly" do
+      doc = RustyXML.parse("<a>x &amp; y<b>&lt;&#x41;</b></a>")
+
+      for _ <- 1..3 do
+        assert RustyXML.xpath(doc, ~x"string(/a)"s) == "x & y<A"
+        assert RustyXML.xpath(doc, ~x"/a/b/text()"s) == "<A"
+      end
+
+      assert RustyXML.Native.xpath_string_value("<a>1 &lt; 2</a>", "/a") == "1 < 2"
+    end
   end
 
   describe "xpath/2 with sigil" do
@@ -208,6 +244,31 @@ defmodule RustyXMLTest do
       result = RustyXML.xpath("<root/>", "not(false())")
       assert result == true
     end
+
+    test "rx:dt compares ISO 8601 date/times" do
+      xml = """
+      <log>
+        <event at="2023-12-31T23:30:00-01:00">late</event>
+        <event at="2023-12-31T23:30:00Z">early</event>
+        <event at="unknown">none</event>
+      </log>
+      """
+
+      spec = ~x"//event[rx:dt(@at) > rx:dt('2024-01-01T00:00:00Z')]/text()"ls
+      assert RustyXML.xpath(xml, spec) == ["late"]
+      assert RustyXML.xpath("<r/>", "rx:dt('1970-01-02')") == 86_400.0
+      assert RustyXML.xpath("<r/>", "rx:dateTime-less-than('2024-01-01', '2024-01-02')")
+      refute RustyXML.xpath("<r/>", "rx:dateTime-equal('2024-01-01', 'soon')")
+    end
+
+    test "namespace axis selects namespace nodes" do
+      xml = ~s(<r xmlns="urn:d" xmlns:a="urn:a"><c xmlns:a="urn:a2"/></r>)
+
+      assert RustyXML.xpath(xml, "count(/*/namespace::*)") == 3.0
+      assert RustyXML.xpath(xml, "name(/*/namespace::*[3])") == "a"
+      assert RustyXML.xpath(xml, "/*/*/namespace::a") == ["urn:a2"]
+      assert RustyXML.xpath(xml, "/*/namespace::*[name() != 'xml']") == ["urn:d", "urn:a"]
+    end
   end
 
   describe "~x sigil" do
@@ -329,287 +390,1891 @@ defmodule RustyXMLTest do
     end
   end
 
-  describe "root/1" do
-    test "returns root element" do
-      doc = RustyXML.parse("<root><child/></root>")
-      root = RustyXML.root(doc)
-      assert root != nil
-    end
+  describe "find_elements/2 and count_elements/2" do
+    test "match //tag without XPath" do
+      doc =
+        RustyXML.parse(
+          ~s(<root xmlns:ns="urn:ns"><item>a</item><x><item>b</item></x><ns:item/></root>)
+        )
 
-    test "returns tuple with element info" do
-      doc = RustyXML.parse("<myroot attr=\"val\"><child/></myroot>")
-      root = RustyXML.root(doc)
-      assert is_tuple(root)
-      assert elem(root, 0) == :element
+      assert RustyXML.Native.find_elements(doc, "item") ==
+               RustyXML.Native.xpath_query(doc, "//item")
+      assert RustyXML.Native.count_elements(doc, "item") == 3
+      assert RustyXML.Native.count_elements(doc, "ns:item") == 1
+      assert RustyXML.Native.find_elements(doc, "missing") == []
     end
   end
 
-  # ==========================================================================
-  # Strict Parsing & Error Cases
-  # ==========================================================================
+  describe "dirty scheduler variants" do
+    test "match their normal-scheduler counterparts" do
+      doc = RustyXML.parse("<root><item>a</item><item>b</item></root>")
 
-  describe "Native.parse_strict/1" do
-    test "returns {:ok, ref} for well-formed XML" do
-      assert {:ok, doc} = RustyXML.Native.parse_strict("<root><child/></root>")
-      assert is_reference(doc)
-    end
+      assert RustyXML.Native.doc_node_count(doc) == 5
 
-    test "returns {:error, reason} for malformed XML" do
-      assert {:error, reason} = RustyXML.Native.parse_strict("<1invalid/>")
-      assert is_binary(reason)
-    end
+      assert RustyXML.Native.xpath_query_dirty(doc, "//item") ==
+               RustyXML.Native.xpath_query(doc, "//item")
 
-    test "returns {:error, reason} for unclosed element" do
-      assert {:error, _reason} = RustyXML.Native.parse_strict("<root><unclosed>")
+      assert RustyXML.Native.xpath_text_list_dirty(doc, "//item") == ["a", "b"]
+      assert RustyXML.Native.get_root_dirty(doc) == RustyXML.Native.get_root(doc)
     end
+  end
 
-    test "returns {:error, reason} for mismatched tags" do
-      assert {:error, _reason} = RustyXML.Native.parse_strict("<a></b>")
+  describe "concurrent queries" do
+    test "many processes query one document" do
+      items = Enum.map_join(1..200, fn i -> ~s(<item id="#{i}">#{i}</item>) end)
+      doc = RustyXML.parse("<root>#{items}</root>")
+
+      results =
+        1..50
+        |> Task.async_stream(
+          fn i ->
+            id = rem(i, 200) + 1
+            count = RustyXML.xpath(doc, ~x"count(//item)"i)
+            {count, RustyXML.xpath(doc, ~x"//item[@id='#{id}']/text()"s)}
+          end,
+          max_concurrency: 16
+        )
+        |> Enum.map(fn {:ok, result} -> result end)
+
+      assert results == Enum.map(1..50, fn i -> {200, Integer.to_string(rem(i, 200) + 1)} end)
     end
   end
 
-  describe "parse_document/1" do
-    test "returns {:ok, doc} for valid XML" do
-      assert {:ok, doc} = RustyXML.parse_document("<root/>")
-      assert is_reference(doc)
-    end
+  describe "xpath_query_with_docs/3" do
+    test "document() reaches preloaded documents" do
+      doc = RustyXML.parse(~s(<orders><order ref="b"/><order ref="x"/></orders>))
+      lookup = RustyXML.parse("<items><item><id>a</id></item><item><id>b</id></item></items>")
+      docs = %{"lookup.xml" => lookup}
 
-    test "returns {:error, reason} for malformed XML" do
-      assert {:error, reason} = RustyXML.parse_document("<1bad/>")
-      assert is_binary(reason)
-    end
+      assert [{:element, "order", [{"ref", "b"}], []}] =
+               RustyXML.Native.xpath_query_with_docs(
+                 doc,
+                 "//order[@ref = document('lookup.xml')//id]",
+                 docs
+               )
 
-    test "accepts charlist input" do
-      assert {:ok, doc} = RustyXML.parse_document(~c"<root>ok</root>")
-      assert is_reference(doc)
-    end
+      count = "count(document('lookup.xml')//item)"
+      assert RustyXML.Native.xpath_query_with_docs(doc, count, docs) == 2.0
 
-    test "rejects malformed charlist input" do
-      assert {:error, _reason} = RustyXML.parse_document(~c"<1invalid/>")
+      assert {:error, _} =
+               RustyXML.Native.xpath_query_with_docs(doc, "document('nope.xml')", docs)
     end
   end
 
-  describe "parse/2 error cases" do
-    test "raises ParseError for malformed XML in strict mode" do
-      assert_raise RustyXML.ParseError, fn ->
-        RustyXML.parse("<1invalid/>")
-      end
-    end
+  describe "result cache" do
+    test "caches node-set results per document" do
+      doc = RustyXML.parse("<root><item>a</item><item>b</item></root>")
+      uncached = RustyXML.Native.xpath_query(doc, "//item")
 
-    @tag :lenient_parsing
-    test "accepts malformed XML in lenient mode" do
-      doc = RustyXML.parse("<1invalid/>", lenient: true)
-      assert is_reference(doc)
-    end
+      assert RustyXML.Native.doc_set_result_cache(doc, 2) == :ok
+      assert RustyXML.Native.xpath_query(doc, "//item") == uncached
+      assert RustyXML.Native.xpath_query(doc, "//item") == uncached
+      assert RustyXML.Native.xpath_text_list(doc, "//item") == ["a", "b"]
+      assert RustyXML.Native.xpath_query(doc, "count(//item)") == 2.0
+      assert RustyXML.Native.doc_result_cache_size(doc) == 1
 
-    @tag :lenient_parsing
-    test "handles empty input" do
-      doc = RustyXML.parse("", lenient: true)
-      assert is_reference(doc)
-    end
-  end
+      RustyXML.Native.xpath_query(doc, "//root")
+      RustyXML.Native.xpath_query(doc, "/root/item[1]")
+      assert RustyXML.Native.doc_result_cache_size(doc) == 2
 
-  # ==========================================================================
-  # Native XPath Variants
-  # ==========================================================================
+      assert RustyXML.Native.doc_clear_result_cache(doc) == :ok
+      assert RustyXML.Native.doc_result_cache_size(doc) == 0
 
-  describe "Native.xpath_query_raw/2" do
-    test "returns list of XML binaries for node sets" do
-      doc = RustyXML.Native.parse("<root><item>text</item><item>more</item></root>")
-      result = RustyXML.Native.xpath_query_raw(doc, "//item")
-      assert is_list(result)
-      assert length(result) == 2
-      assert Enum.all?(result, &is_binary/1)
+      assert RustyXML.Native.doc_set_result_cache(doc, 0) == :ok
+      RustyXML.Native.xpath_query(doc, "//item")
+      assert RustyXML.Native.doc_result_cache_size(doc) == 0
     end
+  end
 
-    test "returns scalar for non-node-set queries" do
-      doc = RustyXML.Native.parse("<root><a/><b/></root>")
-      result = RustyXML.Native.xpath_query_raw(doc, "count(//a)")
-      assert result == 3.0 or is_number(result)
+  describe "attribute indexes" do
+    test "answer attribute predicates with the same results as a scan" do
+      doc =
+        RustyXML.parse("""
+        <catalog>
+          <product sku="A-1"><name>One</name></product>
+          <group><product sku="B-2"><name>Two</name></product></group>
+          <product><name>None</name></product>
+          <part sku="A-1"/>
+        </catalog>
+        """)
+
+      queries = [
+        "//product[@sku = 'A-1']/name",
+        "//*[@sku = 'A-1']",
+        "//product[@sku]",
+        "//product[@sku = 'missing']",
+        "count(//product[@sku = 'B-2'])"
+      ]
+
+      scanned = Enum.map(queries, &RustyXML.Native.xpath_query(doc, &1))
+
+      assert RustyXML.Native.doc_attr_indexes(doc) == []
+      assert RustyXML.Native.doc_build_attr_index(doc, "sku") == :ok
+      assert RustyXML.Native.doc_attr_indexes(doc) == ["sku"]
+      assert Enum.map(queries, &RustyXML.Native.xpath_query(doc, &1)) == scanned
+      assert RustyXML.Native.xpath_text_list(doc, "//product[@sku = 'B-2']/name") == ["Two"]
     end
   end
 
-  describe "Native.xpath_string_value/2" do
-    test "returns text content of first node" do
-      result = RustyXML.Native.xpath_string_value("<root>hello</root>", "/root")
-      assert result == "hello"
-    end
+  describe "Native.doc_all_attributes/2" do
+    test "collects every occurrence with its element name" do
+      doc =
+        RustyXML.parse(
+          ~s(<html><head><link href="a.css"/></head><body><a href="/x?a=1&amp;b=2">x</a>) <>
+            ~s(<img src="i.png"/><svg xmlns:xl="urn:xl"><use xl:href="#s"/></svg></body></html>)
+        )
 
-    test "returns string for string expressions" do
-      result = RustyXML.Native.xpath_string_value("<root/>", "concat('a','b')")
-      assert result == "ab"
-    end
+      assert RustyXML.Native.doc_all_attributes(doc, "href") == [
+               {"link", "a.css"},
+               {"a", "/x?a=1&b=2"}
+             ]
 
-    test "returns empty string for empty node set" do
-      result = RustyXML.Native.xpath_string_value("<root/>", "//nonexistent")
-      assert result == ""
+      assert RustyXML.Native.doc_all_attributes(doc, "xl:href") == [{"use", "#s"}]
+      assert RustyXML.Native.doc_all_attributes(doc, "src") == [{"img", "i.png"}]
+      assert RustyXML.Native.doc_all_attributes(doc, "missing") == []
     end
   end
 
-  describe "Native.xpath_string_value_doc/2" do
-    test "returns string value from document reference" do
-      doc = RustyXML.Native.parse("<root>world</root>")
-      result = RustyXML.Native.xpath_string_value_doc(doc, "/root")
-      assert result == "world"
+  describe "Native.xpath_attr_list/3" do
+    test "plucks an attribute from each selected node" do
+      doc = RustyXML.parse(~s(<r><item id="a1"/><item/><item id="a &amp; b"/><x id="z"/></r>))
+
+      assert RustyXML.Native.xpath_attr_list(doc, "//item", "id") == ["a1", nil, "a & b"]
+      assert RustyXML.Native.xpath_attr_list(doc, "//item", "missing") == [nil, nil, nil]
+      assert RustyXML.Native.xpath_attr_list(doc, "//none", "id") == []
+      assert RustyXML.Native.xpath_attr_list(doc, "//item/text() | //x", "id") == ["z"]
     end
 
-    test "returns concatenated text for elements with children" do
-      doc = RustyXML.Native.parse("<root><a>hello</a> <b>world</b></root>")
-      result = RustyXML.Native.xpath_string_value_doc(doc, "/root")
-      assert is_binary(result)
-      assert String.contains?(result, "hello")
-      assert String.contains?(result, "world")
+    test "rejects non-node-set results" do
+      doc = RustyXML.parse("<r/>")
+      assert {:error, _} = RustyXML.Native.xpath_attr_list(doc, "count(//r)", "id")
+      assert {:error, _} = RustyXML.Native.xpath_attr_list(doc, "//[", "id")
     end
   end
 
-  # ==========================================================================
-  # xpath/3 with Subspecs
-  # ==========================================================================
-
-  describe "xpath/3 with subspecs" do
-    test "extracts nested values from raw XML" do
+  describe "Native.xpath_values_typed/3" do
+    setup do
       xml = """
-      <items>
-        <item id="1"><name>A</name></item>
-        <item id="2"><name>B</name></item>
-      </items>
+      <orders>
+        <order placed="2024-02-29" paid="true"><qty> 3 </qty><price>9.5</price></order>
+        <order placed="2024-02-30" paid="0"><qty>n/a</qty><price>12</price></order>
+      </orders>
       """
 
-      result =
-        RustyXML.xpath(xml, ~x"//item"l, id: ~x"./@id"s, name: ~x"./name/text()"s)
-
-      assert is_list(result)
-      assert length(result) == 2
+      %{doc: RustyXML.parse(xml)}
     end
 
-    test "extracts nested values from document ref" do
-      xml = """
-      <items>
-        <item id="1"><name>A</name></item>
-        <item id="2"><name>B</name></item>
-      </items>
-      """
+    test "converts string-values and reports failures", %{doc: doc} do
+      assert RustyXML.Native.xpath_values_typed(doc, "//qty", :integer) ==
+               {[3, nil], [{1, "n/a"}]}
 
-      doc = RustyXML.parse(xml)
+      assert RustyXML.Native.xpath_values_typed(doc, "//price", :float) == {[9.5, 12.0], []}
 
-      result =
-        RustyXML.xpath(doc, ~x"//item"l, id: ~x"./@id"s, name: ~x"./name/text()"s)
+      assert RustyXML.Native.xpath_values_typed(doc, "//order/@paid", :boolean) ==
+               {[true, false], []}
 
-      assert is_list(result)
-      assert length(result) == 2
+      assert RustyXML.Native.xpath_values_typed(doc, "//order/@placed", :date) ==
+               {[~D[2024-02-29], nil], [{1, "2024-02-30"}]}
 
-      # Each entry should be a map with atom keys
-      [first | _] = result
-      assert is_map(first)
-      assert Map.has_key?(first, :id)
-      assert Map.has_key?(first, :name)
-      assert first.id == "1"
-      assert first.name == "A"
+      assert RustyXML.Native.xpath_values_typed(doc, "count(//order)", :integer) == {[2], []}
+      assert RustyXML.Native.xpath_values_typed(doc, "//none", :integer) == {[], []}
     end
 
-    test "works with single result (no l modifier)" do
-      xml = "<items><item id=\"1\"><name>A</name></item></items>"
-      doc = RustyXML.parse(xml)
+    test "rejects bad expressions and types", %{doc: doc} do
+      assert {:error, _} = RustyXML.Native.xpath_values_typed(doc, "//[", :integer)
 
-      result =
-        RustyXML.xpath(doc, ~x"//item", id: ~x"./@id"s, name: ~x"./name/text()"s)
+      assert_raise ArgumentError, fn ->
+        RustyXML.Native.xpath_values_typed(doc, "//qty", :decimal)
+      end
+    end
+  end
 
-      assert is_map(result)
-      assert result.id == "1"
-      assert result.name == "A"
+  describe "Native.xpath_distinct_values/2 and Native.xpath_group_by/3" do
+    @orders """
+    <orders>
+      <order id="1"><region>EU</region></order>
+      <order id="2"><region>US</region></order>
+      <order id="3"><region>EU</region></order>
+      <order id="4"/>
+    </orders>
+    """
+
+    test "distinct values keep first-occurrence order" do
+      doc = RustyXML.parse(@orders)
+      assert RustyXML.Native.xpath_distinct_values(doc, "//region") == ["EU", "US"]
+      assert RustyXML.Native.xpath_distinct_values(doc, "//order/@id") == ["1", "2", "3", "4"]
+      assert RustyXML.Native.xpath_distinct_values(doc, "//missing") == []
+      assert RustyXML.Native.xpath_distinct_values(doc, "count(//order)") == ["4"]
+      assert {:error, _} = RustyXML.Native.xpath_distinct_values(doc, "//[")
+    end
+
+    test "groups nodes by a relative key" do
+      doc = RustyXML.parse(@orders)
+      groups = RustyXML.Native.xpath_group_by(doc, "//order", "region")
+
+      assert groups |> Map.keys() |> Enum.sort() == ["", "EU", "US"]
+      ids = fn nodes ->
+        Enum.map(nodes, fn {:element, "order", attrs, _} -> :proplists.get_value("id", attrs) end)
+      end
+
+      assert ids.(groups["EU"]) == ["1", "3"]
+      assert ids.(groups["US"]) == ["2"]
+      assert ids.(groups[""]) == ["4"]
+    end
+
+    test "group_by reports invalid expressions" do
+      doc = RustyXML.parse(@orders)
+      assert {:error, _} = RustyXML.Native.xpath_group_by(doc, "count(//order)", "region")
+      assert {:error, _} = RustyXML.Native.xpath_group_by(doc, "//order", "region[")
     end
   end
 
-  # ==========================================================================
-  # Soft Cast Runtime Behaviour
-  # ==========================================================================
+  describe "Native.xpath_count/2, xpath_sum/2, xpath_min/2 and xpath_max/2" do
+    @rows ~s(<t><r v="3">1.5</r><r v="-2">4</r><r v="10"> 2 </r><note>n/a</note></t>)
 
-  describe "soft cast modifiers (S, I, F)" do
-    test "S returns nil for missing element" do
-      result = RustyXML.xpath("<root/>", ~x"//missing/text()"S)
-      assert result == nil
+    test "aggregate node-sets without converting them" do
+      doc = RustyXML.parse(@rows)
+
+      assert RustyXML.Native.xpath_count(doc, "//r") == 3
+      assert RustyXML.Native.xpath_count(doc, "//r/@v") == 3
+      assert RustyXML.Native.xpath_count(doc, "//missing") == 0
+      assert RustyXML.Native.xpath_sum(doc, "//r") == 7.5
+      assert RustyXML.Native.xpath_sum(doc, "//r/@v") == 11.0
+      assert RustyXML.Native.xpath_min(doc, "//r") == 1.5
+      assert RustyXML.Native.xpath_max(doc, "//r/@v") == 10.0
     end
 
-    test "I returns nil for non-numeric text" do
-      result = RustyXML.xpath("<root><v>abc</v></root>", ~x"//v/text()"I)
-      assert result == nil
+    test "empty and non-numeric input" do
+      doc = RustyXML.parse(@rows)
+
+      assert RustyXML.Native.xpath_sum(doc, "//missing") == 0.0
+      assert RustyXML.Native.xpath_min(doc, "//missing") == nil
+      assert RustyXML.Native.xpath_sum(doc, "//note") == nil
+      assert RustyXML.Native.xpath_max(doc, "//r | //note") == nil
     end
 
-    test "I parses valid integer" do
-      result = RustyXML.xpath("<root><v>42</v></root>", ~x"//v/text()"I)
-      assert result == 42
+    test "errors" do
+      doc = RustyXML.parse(@rows)
+
+      assert {:error, _} = RustyXML.Native.xpath_count(doc, "1 + 1")
+      assert {:error, _} = RustyXML.Native.xpath_sum(doc, "//[")
+    end
+  end
+
+  describe "stream_xpath/2" do
+    test "yields the same nodes as xpath_query" do
+      items = Enum.map_join(1..500, fn i -> "<item>#{i}</item>" end)
+      doc = RustyXML.parse("<root>#{items}</root>")
+
+      assert Enum.to_list(RustyXML.stream_xpath(doc, "//item")) ==
+               RustyXML.Native.xpath_query(doc, "//item")
     end
 
-    test "F returns nil for non-numeric text" do
-      result = RustyXML.xpath("<root><v>abc</v></root>", ~x"//v/text()"F)
-      assert result == nil
+    test "emits scalar results as a single element" do
+      doc = RustyXML.parse("<root><item/><item/></root>")
+      assert Enum.to_list(RustyXML.stream_xpath(doc, "count(//item)")) == [2.0]
     end
 
-    test "F parses valid float" do
-      result = RustyXML.xpath("<root><v>3.14</v></root>", ~x"//v/text()"F)
-      assert result == 3.14
+    test "cursor_next drains a node-set" do
+      doc = RustyXML.parse("<root><a/><a/></root>")
+      assert {:cont, cursor} = RustyXML.Native.xpath_query_cursor(doc, "//a")
+      assert {:done, [_, _]} = RustyXML.Native.cursor_next(cursor)
     end
 
-    test "hard i modifier raises on non-numeric text" do
-      assert_raise ArgumentError, fn ->
-        RustyXML.xpath("<root><v>abc</v></root>", ~x"//v/text()"i)
+    test ":chunk_size streams through chunked continuations" do
+      items = Enum.map_join(1..25, fn i -> "<item>#{i}</item>" end)
+      doc = RustyXML.parse("<root>#{items}</root>")
+
+      assert Enum.to_list(RustyXML.stream_xpath(doc, "//item", chunk_size: 10)) ==
+               RustyXML.Native.xpath_query(doc, "//item")
+
+      assert Enum.to_list(RustyXML.stream_xpath(doc, ~x"count(//item)", chunk_size: 10)) ==
+               [25.0]
+
+      assert_raise RustyXML.XPathError, fn ->
+        RustyXML.stream_xpath(doc, "//[", chunk_size: 10) |> Enum.to_list()
       end
     end
 
-    test "hard f modifier raises on non-numeric text" do
-      assert_raise ArgumentError, fn ->
-        RustyXML.xpath("<root><v>abc</v></root>", ~x"//v/text()"f)
-      end
+    test "xpath_query_chunked/3 and xpath_chunk_next/1" do
+      doc = RustyXML.parse("<root><a/><a/><a/></root>")
+
+      assert {[_, _], cont} = RustyXML.Native.xpath_query_chunked(doc, "//a", 2)
+      assert is_reference(cont)
+      assert {[_], :done} = RustyXML.Native.xpath_chunk_next(cont)
+      assert {[], :done} = RustyXML.Native.xpath_chunk_next(cont)
+
+      assert {[_, _, _], :done} = RustyXML.Native.xpath_query_chunked(doc, "//a", 3)
+      assert {[], :done} = RustyXML.Native.xpath_query_chunked(doc, "//b", 3)
+      assert {[3.0], :done} = RustyXML.Native.xpath_query_chunked(doc, "count(//a)", 3)
+      assert {:error, _} = RustyXML.Native.xpath_query_chunked(doc, "//[", 3)
     end
   end
 
-  # ==========================================================================
-  # transform_by/2 and add_namespace/3
-  # ==========================================================================
+  describe "xpath_first/2 and xpath_exists?/2" do
+    test "agree with xpath/2" do
+      doc = RustyXML.parse(~s(<r><a id="1"><b>x</b></a><a id="2"><b>y</b></a></r>))
 
-  describe "transform_by/2" do
-    test "applies transform function to result" do
-      spec = RustyXML.transform_by(~x"//item/text()"s, &String.upcase/1)
-      result = RustyXML.xpath("<root><item>hello</item></root>", spec)
-      assert result == "HELLO"
+      for path <- ["//b", "//a[@id = '2']/b", "//a/b/text()", "//missing", "(//a)[2]"] do
+        assert RustyXML.xpath_first(doc, path) == List.first(RustyXML.xpath(doc, path))
+        assert RustyXML.xpath_exists?(doc, path) == (RustyXML.xpath(doc, path) != [])
+      end
+
+      assert RustyXML.xpath_first(doc, ~x"//b"l) == {:element, "b", [], ["x"]}
+      assert RustyXML.xpath_first(doc, "count(//a)") == 2.0
+      assert RustyXML.xpath_exists?(doc, "//a/@id")
+      refute RustyXML.xpath_exists?(doc, "//a/@missing")
+      refute RustyXML.xpath_exists?(doc, "count(//missing)")
     end
 
-    test "applies transform after cast" do
-      spec = RustyXML.transform_by(~x"//n/text()"i, &(&1 * 2))
-      result = RustyXML.xpath("<root><n>5</n></root>", spec)
-      assert result == 10
+    test "raise for invalid expressions" do
+      doc = RustyXML.parse("<r/>")
+      assert_raise RustyXML.XPathError, fn -> RustyXML.xpath_first(doc, "//[") end
+      assert_raise RustyXML.XPathError, fn -> RustyXML.xpath_exists?(doc, "//[") end
     end
   end
 
-  describe "add_namespace/3" do
-    test "returns SweetXpath with namespace binding" do
-      spec = RustyXML.add_namespace(~x"//ns:item"l, "ns", "http://example.com")
-      assert %RustyXML.SweetXpath{} = spec
-      assert {"ns", "http://example.com"} in spec.namespaces
+  describe "node structural counts" do
+    test "match the equivalent XPath counts" do
+      doc = RustyXML.parse("<r><!--c--><a><b>x</b><b/></a>text<d/></r>")
+      {:ok, [a]} = RustyXML.Native.xpath_node_ids(doc, "/r/a")
+
+      assert RustyXML.Native.node_child_count(doc, a) == 2
+      assert RustyXML.Native.node_descendant_count(doc, a) == 3
+      assert RustyXML.Native.node_depth(doc, a) == 2
+      assert RustyXML.Native.node_position(doc, a) == 2
+
+      {:ok, [d]} = RustyXML.Native.xpath_node_ids(doc, "//d")
+      assert RustyXML.Native.node_position(doc, d) == 4
+      assert RustyXML.Native.node_child_count(doc, d) == 0
+      assert RustyXML.Native.node_child_count(doc, 99_999) == nil
     end
   end
 
-  # ==========================================================================
-  # Streaming Element-Based APIs
-  # ==========================================================================
+  describe "depth-limited conversion" do
+    @shallow_xml "<r><!--c--><a><b>x</b><b/></a>text<d/></r>"
 
-  describe "Native.streaming_take_elements/2" do
-    test "returns list of XML binaries for complete elements" do
-      parser = RustyXML.Native.streaming_new_with_filter("item")
-      RustyXML.Native.streaming_feed(parser, "<root><item>A</item><item>B</item></root>")
-      elements = RustyXML.Native.streaming_take_elements(parser, 100)
-      assert is_list(elements)
-      assert length(elements) == 2
-      assert Enum.all?(elements, &is_binary/1)
-    end
+    test "Native.node_to_term_shallow/3 leaves handles below the limit" do
+      doc = RustyXML.parse(@shallow_xml)
+      {:ok, [r, a]} = RustyXML.Native.xpath_node_ids(doc, "/r | /r/a")
 
-    test "respects max parameter" do
-      parser = RustyXML.Native.streaming_new_with_filter("item")
-      RustyXML.Native.streaming_feed(parser, "<root><item/><item/><item/></root>")
-      elements = RustyXML.Native.streaming_take_elements(parser, 1)
-      assert length(elements) == 1
+      assert RustyXML.Native.node_to_term_shallow(doc, r, 0) == {:element, "r", [], [{:more, r}]}
+
+      assert {:element, "r", [], children} = RustyXML.Native.node_to_term_shallow(doc, r, 1)
+
+      assert children == [
+               {:comment, "c"},
+               {:element, "a", [], [{:more, a}]},
+               "text",
+               {:element, "d", [], []}
+             ]
+
+      assert RustyXML.Native.node_to_term_shallow(doc, a, 1) ==
+               {:element, "a", [], [{:element, "b", [], ["x"]}, {:element, "b", [], []}]}
+
+      assert RustyXML.Native.node_to_term_shallow(doc, 99_999, 1) == nil
     end
-  end
+
+    test "expand/3 matches both scheduler variants" do
+      doc = RustyXML.parse(@shallow_xml)
+      {:ok, [r]} = RustyXML.Native.xpath_node_ids(doc, "/r")
+
+      assert RustyXML.expand(doc, r, 1) == RustyXML.Native.node_to_term_shallow(doc, r, 1)
+
+      assert RustyXML.Native.node_to_term_shallow_dirty(doc, r, 1) ==
+               RustyXML.Native.node_to_term_shallow(doc, r, 1)
+    end
+
+    test "Native.node_children_terms/4 converts one page of children" do
+      doc = RustyXML.parse(@shallow_xml)
+      {:ok, [r, a]} = RustyXML.Native.xpath_node_ids(doc, "/r | /r/a")
+
+      assert RustyXML.Native.node_children_terms(doc, r, 1, 2) ==
+               [{:element, "a", [], [{:more, a}]}, "text"]
+
+      assert RustyXML.Native.node_children_terms(doc, r, 3, 10) == [{:element, "d", [], []}]
+      assert RustyXML.Native.node_children_terms(doc, r, 4, 10) == []
+
+      {:ok, [b | _]} = RustyXML.Native.xpath_node_ids(doc, "//b")
+
+      assert RustyXML.Native.node_children_terms(doc, a, 0, 1) ==
+               [{:element, "b", [], [{:more, b}]}]
+
+      assert RustyXML.Native.node_children_terms(doc, 99_999, 0, 1) == nil
+    end
+
+    test "SimpleForm.parse_string/2 with :max_depth uses the ids of parse/1" do
+      {:ok, [a]} = RustyXML.Native.xpath_node_ids(RustyXML.parse(@shallow_xml), "/r/a")
+
+      assert RustyXML.SimpleForm.parse_string(@shallow_xml, max_depth: 1) ==
+               {:ok, {"r", [], [{"a", [], [{:more, a}]}, "text", {"d", [], []}]}}
+
+      assert RustyXML.SimpleForm.parse_string(@shallow_xml, max_depth: 2) ==
+               RustyXML.SimpleForm.parse_string(@shallow_xml)
+    end
+  end
+
+  describe "node_outer_xml/2 and node_inner_xml/2" do
+    test "return the source bytes of selected nodes" do
+      xml = ~s(<root><item id="1">a &amp; <b>b</b></item><item id="2"/></root>)
+      doc = RustyXML.parse(xml)
+
+      assert {:ok, [first, second]} = RustyXML.Native.xpath_node_ids(doc, "//item")
+
+      assert RustyXML.Native.node_outer_xml(doc, first) ==
+               ~s(<item id="1">a &amp; <b>b</b></item>)
+
+      assert RustyXML.Native.node_inner_xml(doc, first) == "a &amp; <b>b</b>"
+      assert RustyXML.Native.node_outer_xml(doc, second) == ~s(<item id="2"/>)
+      assert RustyXML.Native.node_inner_xml(doc, second) == ""
+    end
+
+    test "xpath_node_ids/2 rejects scalar results" do
+      doc = RustyXML.parse("<root/>")
+      assert {:error, _} = RustyXML.Native.xpath_node_ids(doc, "count(//item)")
+      assert RustyXML.Native.node_outer_xml(doc, 12_345) == nil
+    end
+
+    test "doc_extract_subtree/2 copies a subtree into a new document" do
+      xml = ~s(<r xmlns:a="urn:a"><big/><sec n="1"><a:i>x</a:i><a:i>y</a:i></sec></r>)
+      doc = RustyXML.parse(xml)
+      {:ok, [sec]} = RustyXML.Native.xpath_node_ids(doc, "//sec")
+
+      sub = RustyXML.Native.doc_extract_subtree(doc, sec)
+      {:ok, [root]} = RustyXML.Native.xpath_node_ids(sub, "/sec")
+
+      assert RustyXML.Native.node_outer_xml(sub, root) ==
+               ~s(<sec xmlns:a="urn:a" n="1"><a:i>x</a:i><a:i>y</a:i></sec>)
+
+      assert RustyXML.xpath(sub, ~x"count(/sec/*)"i) == 2
+      assert RustyXML.xpath(sub, ~x"/sec/@n"s) == "1"
+      assert RustyXML.xpath(sub, ~x"count(//big)"i) == 0
+      assert RustyXML.Native.doc_extract_subtree(doc, 12_345) == nil
+    end
+
+    test "truncate_xml/2 keeps output well-formed within the budget" do
+      xml = "<log><a>one</a><b><c>two</c><d>three</d></b></log>"
+      doc = RustyXML.parse(xml)
+
+      assert RustyXML.Native.truncate_xml(doc, byte_size(xml)) == xml
+
+      for budget <- 29..(byte_size(xml) - 1) do
+        truncated = RustyXML.Native.truncate_xml(doc, budget)
+        assert byte_size(truncated) <= budget
+        assert truncated =~ "<!-- truncated -->"
+        assert {:ok, _} = RustyXML.parse_document(truncated)
+      end
+
+      assert RustyXML.Native.truncate_xml(doc, 40) == "<log><a>one</a><!-- truncated --></log>"
+      assert RustyXML.Native.truncate_xml(doc, 10) == nil
+    end
+
+    test "doc_merge/5 splices a node from another document" do
+      envelope = RustyXML.parse(~s(<env><head/><body>&amp;<x/></body></env>))
+      payload = RustyXML.parse(~s(<data><row>1</row></data>))
+      {:ok, [body]} = RustyXML.Native.xpath_node_ids(envelope, "//body")
+      {:ok, [head]} = RustyXML.Native.xpath_node_ids(envelope, "//head")
+      {:ok, [row]} = RustyXML.Native.xpath_node_ids(payload, "//row")
+
+      merged = RustyXML.Native.doc_merge(envelope, body, payload, row, :last_child)
+      {:ok, [root]} = RustyXML.Native.xpath_node_ids(merged, "/env")
+
+      assert RustyXML.Native.node_outer_xml(merged, root) ==
+               "<env><head/><body>&amp;<x/><row>1</row></body></env>"
+
+      merged = RustyXML.Native.doc_merge(envelope, head, payload, row, :first_child)
+      assert RustyXML.xpath(merged, ~x"/env/head/row/text()"s) == "1"
+
+      merged = RustyXML.Native.doc_merge(envelope, head, payload, row, :replace)
+      assert RustyXML.xpath(merged, ~x"count(/env/head)"i) == 0
+      assert RustyXML.xpath(merged, ~x"/env/*[1]/text()"s) == "1"
+
+      assert RustyXML.Native.doc_merge(envelope, 12_345, payload, row, :after) == nil
+
+      {:ok, [env]} = RustyXML.Native.xpath_node_ids(envelope, "/env")
+      assert RustyXML.Native.doc_merge(envelope, env, payload, row, :before) == nil
+      assert RustyXML.Native.doc_merge(envelope, env, payload, row, :after) == nil
+      merged = RustyXML.Native.doc_merge(envelope, env, payload, row, :replace)
+      assert RustyXML.xpath(merged, ~x"/row/text()"s) == "1"
+
+      assert_raise ArgumentError, fn ->
+        RustyXML.Native.doc_merge(envelope, body, payload, row, :inside)
+      end
+    end
+  end
+
+  describe "render_template/3" do
+    test "substitutes escaped values in text and attributes" do
+      template =
+        RustyXML.parse(~s(<req id="{{id}}"><!-- {{id}} --><q>{{ query }} {{other}}</q></req>))
+
+      assert RustyXML.render_template(template, %{id: 7, query: ~s(a<b & "c")}) ==
+               ~s(<req id="7"><!-- {{id}} --><q>a&lt;b &amp; "c" {{other}}</q></req>)
+
+      assert RustyXML.render_template(template, %{"id" => ~s("x")}) =~ ~s(id="&quot;x&quot;")
+    end
+
+    test "accepts custom delimiters" do
+      template = RustyXML.parse("<req a='${id}'>${id} {{id}}</req>")
+
+      assert RustyXML.render_template(template, %{id: 1}, delimiters: {"${", "}"}) ==
+               "<req a='1'>1 {{id}}</req>"
+
+      assert_raise ArgumentError, fn ->
+        RustyXML.render_template(template, %{}, delimiters: {"", "}"})
+      end
+    end
+  end
+
+  describe "resolve_xpointer/2" do
+    @pointed ~s(<d><h id="h"><title>T</title></h><b><p>1</p><p xml:id="two">2</p></b></d>)
+
+    test "resolves shorthand and element() pointers" do
+      doc = RustyXML.parse(@pointed)
+
+      assert {:ok, [p]} = RustyXML.resolve_xpointer(doc, "two")
+      assert RustyXML.Native.node_outer_xml(doc, p) == ~s(<p xml:id="two">2</p>)
+      assert RustyXML.resolve_xpointer(doc, "element(/1/2/2)") == {:ok, [p]}
+
+      assert {:ok, [title]} = RustyXML.resolve_xpointer(doc, "element(h/1)")
+      assert RustyXML.Native.node_outer_xml(doc, title) == "<title>T</title>"
+      assert RustyXML.resolve_xpointer(doc, "missing") == {:ok, []}
+    end
+
+    test "falls through scheme parts and rejects malformed pointers" do
+      doc = RustyXML.parse(@pointed)
+
+      assert {:ok, [_, _]} = RustyXML.resolve_xpointer(doc, "element(/9) xpointer(//p)")
+      assert {:ok, [_]} = RustyXML.resolve_xpointer(doc, "unknown(x) xpath1(//title)")
+      assert {:error, _} = RustyXML.resolve_xpointer(doc, "element(/1")
+    end
+  end
+
+  describe "Native.node_attribute_ns/4" do
+    test "resolves attribute prefixes from ancestor declarations" do
+      xml = ~s(<r xmlns:x="urn:x" xmlns:y="urn:y"><e x:id="1" y:id="2" id="3"/></r>)
+      doc = RustyXML.parse(xml)
+      {:ok, [e]} = RustyXML.Native.xpath_node_ids(doc, "//e")
+
+      assert RustyXML.Native.node_attribute_ns(doc, e, "urn:x", "id") == "1"
+      assert RustyXML.Native.node_attribute_ns(doc, e, "urn:y", "id") == "2"
+      assert RustyXML.Native.node_attribute_ns(doc, e, "", "id") == "3"
+      assert RustyXML.Native.node_attribute_ns(doc, e, "urn:z", "id") == nil
+      assert RustyXML.Native.node_attribute_ns(doc, 12_345, "urn:x", "id") == nil
+    end
+  end
+
+  describe "Native.doc_find_text/3" do
+    @haystack ~s(<doc title="needle"><p>a needle</p><!-- needle --><q>needle</q></doc>)
+
+    test "maps matches to the containing element and byte offset" do
+      doc = RustyXML.parse(@haystack)
+
+      assert [{p, p_offset}, {q, q_offset}] = RustyXML.Native.doc_find_text(doc, "needle", [])
+      assert binary_part(@haystack, p_offset, 6) == "needle"
+      assert binary_part(@haystack, q_offset, 6) == "needle"
+      assert RustyXML.Native.node_outer_xml(doc, p) == "<p>a needle</p>"
+      assert RustyXML.Native.node_outer_xml(doc, q) == "<q>needle</q>"
+    end
+
+    test "options" do
+      doc = RustyXML.parse(@haystack)
+
+      assert [{root, 12} | _] = RustyXML.Native.doc_find_text(doc, "needle", attributes: true)
+      assert RustyXML.Native.node_outer_xml(doc, root) == @haystack
+      assert [_] = RustyXML.Native.doc_find_text(doc, "needle", limit: 1)
+      assert RustyXML.Native.doc_find_text(doc, "absent", []) == []
+
+      assert_raise ArgumentError, fn ->
+        RustyXML.Native.doc_find_text(doc, "needle", unknown: true)
+      end
+    end
+
+    test "offsets don't count a byte order mark" do
+      doc = RustyXML.parse(<<0xEF, 0xBB, 0xBF>> <> @haystack)
+      plain = RustyXML.parse(@haystack)
+
+      assert RustyXML.Native.doc_find_text(doc, "needle", []) ==
+               RustyXML.Native.doc_find_text(plain, "needle", [])
+    end
+
+    test "offsets in UTF-16 documents are into the converted UTF-8" do
+      utf16 = <<0xFF, 0xFE>> <> :unicode.characters_to_binary(@haystack, :utf8, {:utf16, :little})
+      doc = RustyXML.parse(utf16)
+
+      assert [{_, offset}, _] = RustyXML.Native.doc_find_text(doc, "needle", [])
+      assert binary_part(@haystack, offset, 6) == "needle"
+    end
+  end
+
+  describe "Native.doc_regex_search/3" do
+    @licenses "<files><f>MIT &amp; GPL-2.0</f><f>none</f><f>GPL-3.0 <b>only</b></f></files>"
+
+    test "returns match ranges in each scoped node's string-value" do
+      doc = RustyXML.parse(@licenses)
+
+      assert [{first, [{6, 7}]}, {third, [{0, 7}]}] =
+               RustyXML.Native.doc_regex_search(doc, ~S"GPL-\d\.\d", "//f")
+
+      assert RustyXML.Native.node_outer_xml(doc, first) == "<f>MIT &amp; GPL-2.0</f>"
+      assert RustyXML.Native.node_outer_xml(doc, third) == "<f>GPL-3.0 <b>only</b></f>"
+      assert [{_, [{0, 3}, {6, 3}]}] = RustyXML.Native.doc_regex_search(doc, "[A-Z]{3}", "//f[1]")
+      assert RustyXML.Native.doc_regex_search(doc, "BSD", "//f") == []
+    end
+
+    test "reports invalid input" do
+      doc = RustyXML.parse(@licenses)
+
+      assert {:error, _} = RustyXML.Native.doc_regex_search(doc, "(", "//f")
+      assert {:error, _} = RustyXML.Native.doc_regex_search(doc, "x", "//[")
+      assert {:error, _} = RustyXML.Native.doc_regex_search(doc, "x", "count(//f)")
+    end
+  end
+
+  describe "root/1" do
+    test "returns root element" do
+      doc = RustyXML.parse("<root><child/></root>")
+      root = RustyXML.root(doc)
+      assert root != nil
+    end
+
+    test "returns tuple with element info" do
+      doc = RustyXML.parse("<myroot attr=\"val\"><child/></myroot>")
+      root = RustyXML.root(doc)
+      assert is_tuple(root)
+      assert elem(root, 0) == :element
+    end
+  end
+
+  # ==========================================================================
+  # Strict Parsing & Error Cases
+  # ==========================================================================
+
+  describe "Native.parse_strict/1" do
+    test "returns {:ok, ref} for well-formed XML" do
+      assert {:ok, doc} = RustyXML.Native.parse_strict("<root><child/></root>")
+      assert is_reference(doc)
+    end
+
+    test "returns {:error, reason} for malformed XML" do
+      assert {:error, reason} = RustyXML.Native.parse_strict("<1invalid/>")
+      assert is_binary(reason)
+    end
+
+    test "returns {:error, reason} for unclosed element" do
+      assert {:error, _reason} = RustyXML.Native.parse_strict("<root><unclosed>")
+    end
+
+    test "returns {:error, reason} for mismatched tags" do
+      assert {:error, _reason} = RustyXML.Native.parse_strict("<a></b>")
+    end
+  end
+
+  describe "parse_document/1" do
+    test "returns {:ok, doc} for valid XML" do
+      assert {:ok, doc} = RustyXML.parse_document("<root/>")
+      assert is_reference(doc)
+    end
+
+    test "returns {:error, reason} for malformed XML" do
+      assert {:error, reason} = RustyXML.parse_document("<1bad/>")
+      assert is_binary(reason)
+    end
+
+    test "accepts charlist input" do
+      assert {:ok, doc} = RustyXML.parse_document(~c"<root>ok</root>")
+      assert is_reference(doc)
+    end
+
+    test "rejects malformed charlist input" do
+      assert {:error, _reason} = RustyXML.parse_document(~c"<1invalid/>")
+    end
+  end
+
+  describe "well_formed?/2" do
+    test "agrees with parse_document/1" do
+      assert RustyXML.well_formed?("<root><a x=\"1\"/></root>")
+      assert RustyXML.well_formed?(~c"<root>ok</root>")
+      refute RustyXML.well_formed?("<root><a></root>")
+
+      {:error, reason} = RustyXML.parse_document("<1bad/>")
+      assert RustyXML.well_formed?("<1bad/>", details: true) == {:error, reason}
+      assert RustyXML.well_formed?("<root/>", details: true) == true
+    end
+
+    test "decodes UTF-16 input" do
+      body = :unicode.characters_to_binary("<r>é</r>", :utf8, {:utf16, :little})
+      assert RustyXML.well_formed?(<<0xFF, 0xFE>> <> body)
+    end
+  end
+
+  describe "validate_stream/1" do
+    test "accepts a well-formed document split anywhere" do
+      xml = "<root>" <> String.duplicate("<item a=\"1\">text</item>\n", 200) <> "</root>"
+      chunks = for <<chunk::binary-size(7) <- xml>>, do: chunk
+      rest = binary_part(xml, length(chunks) * 7, rem(byte_size(xml), 7))
+
+      assert RustyXML.validate_stream(chunks ++ [rest]) == :ok
+    end
+
+    test "reports the first error with its position" do
+      chunks = ["<root>\n  <a>", "\n  </b>\n", "</root>"]
+
+      assert RustyXML.validate_stream(chunks) ==
+               {:error,
+                %{message: "Tag mismatch: <a> closed with </b>", offset: 15, line: 3, column: 3}}
+    end
+
+    test "checks the end of input and closes the validator" do
+      validator = RustyXML.Native.validator_new()
+      assert RustyXML.Native.validator_feed(validator, "<root><a/>") == :ok
+      assert {:error, %{message: "Unclosed tag: <root>"}} =
+               RustyXML.Native.validator_finish(validator)
+
+      assert RustyXML.Native.validator_feed(validator, "</root>") == {:error, :closed}
+    end
+  end
+
+  describe "Native.get_parser_stats/0" do
+    # Other tests run concurrently, so counters are only checked to grow
+    test "counts parses, queries, cache hits and strict failures" do
+      before = RustyXML.Native.get_parser_stats()
+
+      doc = RustyXML.parse("<r><a/></r>")
+      :ok = RustyXML.Native.doc_set_result_cache(doc, 4)
+      RustyXML.xpath(doc, ~x"//a"l)
+      RustyXML.xpath(doc, ~x"//a"l)
+      {:error, _} = RustyXML.parse_document("<r>")
+
+      after_stats = RustyXML.Native.get_parser_stats()
+      assert after_stats.documents_parsed > before.documents_parsed
+      assert after_stats.bytes_scanned >= before.bytes_scanned + 11
+      assert after_stats.xpath_queries >= before.xpath_queries + 2
+      assert after_stats.cache_hits > before.cache_hits
+      assert after_stats.strict_failures > before.strict_failures
+      assert is_float(after_stats.avg_index_build_us)
+    end
+
+    test "reset_parser_stats/0 returns :ok" do
+      assert RustyXML.Native.reset_parser_stats() == :ok
+      assert is_integer(RustyXML.Native.get_parser_stats().documents_parsed)
+    end
+  end
+
+  describe "Native.xpath_cache_stats/0" do
+    # The cache is shared with concurrent tests, so counts are only checked
+    # to grow, and the capacity is never lowered
+    test "counts hits and misses of compiled expressions" do
+      doc = RustyXML.Native.parse("<r><a/></r>")
+      xpath = "//a[#{System.unique_integer([:positive])}]"
+
+      before = RustyXML.Native.xpath_cache_stats()
+      RustyXML.Native.xpath_query(doc, xpath)
+      RustyXML.Native.xpath_query(doc, xpath)
+      after_stats = RustyXML.Native.xpath_cache_stats()
+
+      assert after_stats.misses >= before.misses + 1
+      assert after_stats.hits >= before.hits + 1
+      assert after_stats.size in 1..after_stats.capacity
+    end
+
+    test "capacity can be set and the cache cleared" do
+      capacity = RustyXML.Native.xpath_cache_stats().capacity
+      assert RustyXML.Native.set_xpath_cache_capacity(capacity + 1) == :ok
+      assert RustyXML.Native.xpath_cache_stats().capacity == capacity + 1
+      assert RustyXML.Native.set_xpath_cache_capacity(capacity) == :ok
+
+      assert RustyXML.Native.xpath_cache_clear() == :ok
+      assert %{size: size} = RustyXML.Native.xpath_cache_stats()
+      assert is_integer(size)
+    end
+  end
+
+  describe "Native.parse_timed/1 and Native.xpath_query_timed/2" do
+    test "report duration, peak allocation and nodes visited" do
+      xml = "<r>" <> String.duplicate("<a/><b/>", 100) <> "</r>"
+
+      {doc, usage} = RustyXML.Native.parse_timed(xml)
+      assert RustyXML.Native.xpath_query(doc, "count(//a)") == 100.0
+      assert usage.nodes_visited == 201
+      assert is_integer(usage.duration_us) and usage.duration_us >= 0
+      assert is_integer(usage.peak_alloc_bytes)
+
+      {result, usage} = RustyXML.Native.xpath_query_timed(doc, "/r/a")
+      assert length(result) == 100
+      # The root element, then its 200 children
+      assert usage.nodes_visited == 201
+    end
+
+    test "invalid expressions still report usage" do
+      doc = RustyXML.Native.parse("<r/>")
+      assert {{:error, _}, %{nodes_visited: 0}} = RustyXML.Native.xpath_query_timed(doc, "//[")
+    end
+  end
+
+  describe "parse_async/2" do
+    @async_xml "<r>" <> String.duplicate("<item>text</item>", 2000) <> "</r>"
+
+    test "reports progress, then the document" do
+      task = RustyXML.parse_async(@async_xml, progress_interval: 4096)
+      total = byte_size(@async_xml)
+
+      assert_receive {:rustyxml_parse, ^task, {:progress, first, ^total}}
+      assert first >= 4096 and first < total
+      assert_receive {:rustyxml_parse, ^task, {:progress, ^total, ^total}}
+      assert_receive {:rustyxml_parse, ^task, {:ok, doc}}
+      assert RustyXML.xpath(doc, ~x"count(//item)"i) == 2000
+    end
+
+    test "sends progress to another process" do
+      parent = self()
+      receiver = spawn(fn -> receive(do: (msg -> send(parent, {:forwarded, msg}))) end)
+      task = RustyXML.parse_async("<r/>", pid: receiver)
+      assert_receive {:forwarded, {:rustyxml_parse, ^task, {:progress, 4, 4}}}
+    end
+
+    test "parse_cancel/1 abandons the build" do
+      # Checked every 64 bytes, so the flag is seen long before the end
+      task = RustyXML.parse_async(@async_xml, progress_interval: 64)
+      assert RustyXML.parse_cancel(task) == :ok
+
+      assert_receive {:rustyxml_parse, ^task, {:error, :cancelled}}
+      refute_received {:rustyxml_parse, ^task, {:ok, _}}
+    end
+
+    test "skips a BOM and converts UTF-16" do
+      utf16 = :unicode.characters_to_binary("<r>é</r>", :utf8, {:utf16, :little})
+
+      for xml <- [<<0xEF, 0xBB, 0xBF, "<r>é</r>">>, <<0xFF, 0xFE>> <> utf16] do
+        task = RustyXML.parse_async(xml)
+        assert_receive {:rustyxml_parse, ^task, {:progress, 9, 9}}
+        assert_receive {:rustyxml_parse, ^task, {:ok, doc}}
+        assert RustyXML.xpath(doc, ~x"string(/r)"s) == "é"
+      end
+    end
+
+    test "rejects a zero interval" do
+      assert_raise ArgumentError, fn -> RustyXML.parse_async("<r/>", progress_interval: 0) end
+    end
+
+    test "builds beyond the worker count queue and all finish" do
+      tasks = for _ <- 1..(System.schedulers_online() * 4), do: RustyXML.parse_async(@async_xml)
+
+      for task <- tasks do
+        assert_receive {:rustyxml_parse, ^task, {:ok, _doc}}, 5000
+      end
+    end
+  end
+
+  describe "cancel_token/0" do
+    test "cancellable parse and query run until the token is set" do
+      token = RustyXML.cancel_token()
+      xml = "<r>" <> String.duplicate("<a/>", 100) <> "</r>"
+
+      assert {:ok, doc} = RustyXML.Native.parse_cancellable(xml, token)
+      assert RustyXML.Native.xpath_query_cancellable(doc, "count(//a)", token) == 100.0
+      assert {:error, _} = RustyXML.Native.xpath_query_cancellable(doc, "//[", token)
+      refute RustyXML.Native.cancel_token_cancelled(token)
+
+      assert RustyXML.cancel(token) == :ok
+      assert RustyXML.Native.cancel_token_cancelled(token)
+      assert RustyXML.Native.parse_cancellable(xml, token) == {:error, :cancelled}
+
+      assert RustyXML.Native.xpath_query_cancellable(doc, "count(//a)", token) ==
+               {:error, :cancelled}
+
+      # The document itself is unaffected
+      assert RustyXML.Native.xpath_query(doc, "count(//a)") == 100.0
+    end
+
+    test "parse_stream/4 stops before the next chunk" do
+      defmodule CancelOnFirst do
+        @behaviour RustyXML.Handler
+
+        @impl true
+        def handle_event(:start_element, _data, token) do
+          RustyXML.cancel(token)
+          {:ok, token}
+        end
+
+        def handle_event(_type, _data, token), do: {:ok, token}
+      end
+
+      token = RustyXML.cancel_token()
+      chunks = ["<r><a/>", "<b/>", "</r>"]
+
+      assert RustyXML.parse_stream(chunks, CancelOnFirst, token, cancel_token: token) ==
+               {:error, :cancelled}
+
+      assert {:ok, _} = RustyXML.parse_stream(chunks, CancelOnFirst, RustyXML.cancel_token())
+    end
+  end
+
+  describe "node term sharing" do
+    test "long strings reference the document instead of copying it" do
+      long = String.duplicate("x", 200)
+      xml = ~s(<doc><p id="#{long}">#{long}</p><q>short</q><r>a &amp; #{long}</r></doc>)
+      doc = RustyXML.Native.parse(xml)
+
+      {:element, "doc", [], [p, q, r]} = RustyXML.Native.get_root(doc)
+      {:element, "p", [{"id", id}], [text]} = p
+      assert text == long
+      assert :binary.referenced_byte_size(text) == byte_size(xml)
+      assert :binary.referenced_byte_size(id) == byte_size(xml)
+
+      # Short strings and decoded text are copied
+      {:element, "q", [], [short]} = q
+      assert :binary.referenced_byte_size(short) == 5
+      {:element, "r", [], [decoded]} = r
+      assert decoded == "a & " <> long
+      assert :binary.referenced_byte_size(decoded) == byte_size(decoded)
+
+      [{:element, "p", _, [text]}] = RustyXML.Native.xpath_query(doc, "//p")
+      assert :binary.referenced_byte_size(text) == byte_size(xml)
+    end
+  end
+
+  describe "parse_to_map/2" do
+    test "builds a map per element" do
+      xml = ~s(<order id="7" note="a &amp; b"><item sku="x">Tea</item><item/></order>)
+
+      assert RustyXML.parse_to_map(xml) == %{
+               name: "order",
+               attrs: %{"id" => "7", "note" => "a & b"},
+               children: [
+                 %{name: "item", attrs: %{"sku" => "x"}, children: [], text: "Tea"},
+                 %{name: "item", attrs: %{}, children: [], text: nil}
+               ],
+               text: nil
+             }
+
+      assert get_in(RustyXML.parse_to_map(xml), [:attrs, "id"]) == "7"
+    end
+
+    test "joins an element's own text and skips comments" do
+      map = RustyXML.parse_to_map("<p>one <b>two</b><!-- c --> &lt;three<![CDATA[&]]></p>")
+
+      assert map.text == "one  <three&"
+      assert [%{name: "b", text: "two"}] = map.children
+    end
+
+    test "normalizes text and attribute values" do
+      map = RustyXML.parse_to_map(~s(<r a="e\u0301">e\u0301</r>), normalize: :nfc)
+      assert map.text == "\u00e9"
+      assert map.attrs == %{"a" => "\u00e9"}
+    end
+
+    test "raises on malformed XML" do
+      assert_raise RustyXML.ParseError, fn -> RustyXML.parse_to_map("<r><a></r>") end
+      assert_raise RustyXML.ParseError, fn -> RustyXML.parse_to_map("") end
+    end
+  end
+
+  describe "decode/2" do
+    @order """
+    <order id="42">
+      <customer><name>Ada</name><vip>true</vip></customer>
+      <item sku="a"><qty>2</qty><price>1.50</price></item>
+      <item sku="b"><qty>x</qty><price>3</price></item>
+    </order>
+    """
+
+    test "builds nested maps with converted types" do
+      spec = %{
+        id: {"@id", :integer},
+        customer: {:map, "customer", %{name: "name", vip: {"vip", :boolean}}},
+        items:
+          {:list, "//item",
+           %{sku: "@sku", qty: {"qty", :integer}, price: {"price", :float}}},
+        skus: {"item/@sku", :list}
+      }
+
+      assert RustyXML.decode(@order, spec) == %{
+               id: 42,
+               customer: %{name: "Ada", vip: true},
+               items: [
+                 %{sku: "a", qty: 2, price: 1.5},
+                 %{sku: "b", qty: nil, price: 3.0}
+               ],
+               skus: ["a", "b"]
+             }
+    end
+
+    test "missing nodes give nil or an empty list" do
+      doc = RustyXML.parse(@order)
+      spec = %{note: "note", gift: {:map, "gift", %{to: "@to"}}, tags: {:list, "tag", %{}}}
+      assert RustyXML.decode(doc, spec) == %{note: nil, gift: nil, tags: []}
+    end
+
+    test "invalid expressions raise" do
+      assert_raise RustyXML.XPathError, fn -> RustyXML.decode(@order, %{a: "//["}) end
+      assert_raise ArgumentError, fn -> RustyXML.decode(@order, %{a: {"@id", :date}}) end
+    end
+  end
+
+  describe "parse_cached/1" do
+    test "identical input returns the same document" do
+      xml = "<config><key>#{System.unique_integer()}</key></config>"
+      doc = RustyXML.parse_cached(xml)
+
+      assert RustyXML.parse_cached(xml) == doc
+      assert Task.await(Task.async(fn -> RustyXML.parse_cached(xml) end)) == doc
+      assert RustyXML.parse_cached(xml <> " ") != doc
+      assert RustyXML.xpath(doc, ~x"//key/text()"s) =~ ~r/^-?\d+$/
+    end
+
+    test "inputs with a BOM or in UTF-16 hit the cache too" do
+      xml = "<config><key>#{System.unique_integer()}</key></config>"
+      utf16 = :unicode.characters_to_binary(xml, :utf8, {:utf16, :little})
+
+      for input <- [<<0xEF, 0xBB, 0xBF>> <> xml, <<0xFF, 0xFE>> <> utf16] do
+        doc = RustyXML.parse_cached(input)
+        assert RustyXML.parse_cached(input) == doc
+      end
+    end
+
+    test "malformed input raises" do
+      assert_raise RustyXML.ParseError, fn -> RustyXML.parse_cached("<a><b></a>") end
+    end
+
+    test "settings of shared documents can't be changed" do
+      doc = RustyXML.parse_cached("<config id=\"#{System.unique_integer()}\"/>")
+      shared = {:error, :shared_document}
+
+      assert RustyXML.Native.doc_set_normalization(doc, :nfc) == shared
+      assert RustyXML.Native.doc_set_result_cache(doc, 8) == shared
+      assert RustyXML.Native.doc_build_attr_index(doc, "id") == shared
+      assert RustyXML.Native.doc_attr_indexes(doc) == []
+      assert RustyXML.Native.doc_close(doc) == shared
+      assert RustyXML.xpath(doc, ~x"count(/config)"i) == 1
+    end
+  end
+
+  describe "Native.nodeset_serialize/2" do
+    test "packed node-sets materialize in each format" do
+      doc = RustyXML.parse(~s(<r><item id="1">a</item><item>b &amp; c</item></r>))
+      {:ok, packed} = RustyXML.Native.nodeset_serialize(doc, "//item")
+
+      assert byte_size(packed) == 20 + 4 * 2
+      table = :ets.new(:nodesets, [])
+      :ets.insert(table, {:items, packed})
+      [{:items, stored}] = :ets.lookup(table, :items)
+
+      assert RustyXML.Native.nodeset_materialize(doc, stored, :element) ==
+               RustyXML.Native.xpath_query(doc, "//item")
+
+      assert RustyXML.Native.nodeset_materialize(doc, stored, :xml) ==
+               [~s(<item id="1">a</item>), "<item>b &amp; c</item>"]
+
+      assert RustyXML.Native.nodeset_materialize(doc, stored, :text) == ["a", "b & c"]
+    end
+
+    test "rejects other documents and non-node-set queries" do
+      doc = RustyXML.parse("<r><a/><a/></r>")
+      other = RustyXML.parse("<r><a/></r>")
+      {:ok, packed} = RustyXML.Native.nodeset_serialize(doc, "//a")
+
+      assert RustyXML.Native.nodeset_materialize(other, packed, :text) ==
+               {:error, :invalid_nodeset}
+
+      same_shape = RustyXML.parse("<s><b/><b/></s>")
+
+      assert RustyXML.Native.nodeset_materialize(same_shape, packed, :text) ==
+               {:error, :invalid_nodeset}
+
+      assert RustyXML.Native.nodeset_materialize(doc, "junk", :text) ==
+               {:error, :invalid_nodeset}
+
+      assert {:error, _} = RustyXML.Native.nodeset_serialize(doc, "count(//a)")
+    end
+  end
+
+  describe "result ordering" do
+    alias RustyXML.Native
+
+    setup do
+      {:ok, doc: RustyXML.parse("<r><a>1</a><b>2</b><c>3</c></r>")}
+    end
+
+    test "node-sets come back in document, reverse or matched order", %{doc: doc} do
+      assert Native.xpath_text_list_ordered(doc, "//c | //a", :document) == ["1", "3"]
+      assert Native.xpath_text_list_ordered(doc, "//c | //a", :reverse) == ["3", "1"]
+      assert Native.xpath_text_list_ordered(doc, "//c | //a", :matched) == ["3", "1"]
+
+      assert Native.xpath_text_list_ordered(doc, "//c/preceding-sibling::*", :matched) ==
+               ["2", "1"]
+
+      assert Native.xpath_query_raw_ordered(doc, "/r/*", :reverse) ==
+               ["<c>3</c>", "<b>2</b>", "<a>1</a>"]
+
+      assert [{:element, "b", _, _}, {:element, "a", _, _}] =
+               Native.xpath_query_ordered(doc, "/r/*[position() < 3]", :reverse)
+    end
+
+    test "predicates and non-node-set results are unaffected", %{doc: doc} do
+      assert Native.xpath_text_list_ordered(doc, "(//c | //a)[1]", :matched) == ["1"]
+      assert Native.xpath_query_ordered(doc, "count(//*)", :reverse) == 4.0
+
+      assert_raise ArgumentError, fn -> Native.xpath_query_ordered(doc, "//a", :sideways) end
+    end
+  end
+
+  describe "Native.xpath_query_slice/4" do
+    test "converts only the requested page of a node-set" do
+      rows = for i <- 1..100, do: "<row>#{i}</row>"
+      doc = RustyXML.parse("<t>#{Enum.join(rows)}</t>")
+
+      assert RustyXML.Native.xpath_query_slice(doc, "//row", 40, 3) == [
+               {:element, "row", [], ["41"]},
+               {:element, "row", [], ["42"]},
+               {:element, "row", [], ["43"]}
+             ]
+
+      assert length(RustyXML.Native.xpath_query_slice(doc, "//row", 98, 10)) == 2
+      assert RustyXML.Native.xpath_query_slice(doc, "//row", 100, 10) == []
+      assert RustyXML.Native.xpath_query_slice(doc, "count(//row)", 5, 1) == 100.0
+    end
+  end
+
+  describe "schematron" do
+    @schema """
+    <schema xmlns="http://purl.oclc.org/dsdl/schematron">
+      <pattern>
+        <rule context="item">
+          <assert test="@id" id="item-id">Item <value-of select="name"/> needs an id</assert>
+          <report test="price &lt; 0" role="warning">Negative price on <name/></report>
+        </rule>
+      </pattern>
+    </schema>
+    """
+
+    test "reports failed assertions and fired reports with their paths" do
+      {:ok, rules} = RustyXML.schematron_compile(@schema)
+
+      xml = """
+      <items><item id="a"><name>A</name><price>1</price></item>
+      <item><name>B</name><price>-2</price></item></items>
+      """
+
+      assert {:ok, [assertion, report]} = RustyXML.schematron_validate(xml, rules)
+
+      assert assertion == %{
+               kind: :assert,
+               id: "item-id",
+               role: nil,
+               test: "@id",
+               path: "/items[1]/item[2]",
+               message: "Item B needs an id"
+             }
+
+      assert %{kind: :report, role: "warning", message: "Negative price on item"} = report
+    end
+
+    test "accepts parsed documents and passes valid ones" do
+      {:ok, rules} = RustyXML.schematron_compile(@schema)
+      doc = RustyXML.parse(~s(<items><item id="a"><price>3</price></item></items>))
+
+      assert RustyXML.schematron_validate(doc, rules) == {:ok, []}
+    end
+
+    test "rejects malformed schemas" do
+      assert {:error, _} = RustyXML.schematron_compile("<rules/>")
+
+      assert {:error, _} =
+               RustyXML.schematron_compile(
+                 ~s(<schema><pattern><rule context="a["/></pattern></schema>)
+               )
+    end
+
+    test "rejects namespace prefixes, which could never match" do
+      assert {:error, "ns declarations" <> _} =
+               RustyXML.schematron_compile(
+                 ~s(<schema><ns prefix="x" uri="urn:x"/><pattern/></schema>)
+               )
+
+      assert {:error, _} =
+               RustyXML.schematron_compile(
+                 ~s(<schema><pattern><rule context="x:order"><assert test="@id">id</assert>) <>
+                   "</rule></pattern></schema>"
+               )
+    end
+  end
+
+  describe "closing resources" do
+    test "doc_close/1 releases the document for every later call" do
+      doc = RustyXML.parse("<root><item>a</item></root>")
+      assert RustyXML.Native.xpath_text_list(doc, "//item") == ["a"]
+
+      assert RustyXML.Native.doc_close(doc) == :ok
+      assert RustyXML.Native.xpath_query(doc, "//item") == {:error, :closed}
+      assert RustyXML.Native.doc_node_count(doc) == {:error, :closed}
+      assert RustyXML.Native.doc_close(doc) == :ok
+    end
+
+    test "streaming_close/1 releases both streaming parsers" do
+      parser = RustyXML.Native.streaming_new_with_filter("item")
+      RustyXML.Native.streaming_feed(parser, "<items><item>1</item><item>")
+      assert RustyXML.Native.streaming_close(parser) == :ok
+      assert RustyXML.Native.streaming_feed(parser, "</item>") == {:error, :closed}
+
+      sax = RustyXML.Native.streaming_sax_new()
+      assert RustyXML.Native.streaming_close(sax) == :ok
+      assert RustyXML.Native.streaming_feed_sax(sax, "<a/>", false) == {:error, :closed}
+    end
+  end
+
+  describe "memory footprint" do
+    test "doc_memory/1 counts the input, index and caches" do
+      doc = RustyXML.parse("<root><item id=\"1\">a</item><item id=\"2\">b</item></root>")
+      before = RustyXML.Native.doc_memory(doc)
+
+      assert before.input >= 51
+      assert before.index > 0
+      assert before.attr_indexes == 0
+
+      RustyXML.Native.doc_build_attr_index(doc, "id")
+      after_index = RustyXML.Native.doc_memory(doc)
+      assert after_index.attr_indexes > 0
+      assert after_index.total == Enum.sum(Map.values(Map.delete(after_index, :total)))
+
+      RustyXML.Native.doc_close(doc)
+      assert RustyXML.Native.doc_memory(doc) == {:error, :closed}
+    end
+
+    test "streaming_memory/1 reports buffered and pending bytes" do
+      parser = RustyXML.Native.streaming_new_with_filter("item")
+      RustyXML.Native.streaming_feed(parser, "<items><item>1</item><item>")
+      assert %{buffer: buffer, pending: pending, total: total} =
+               RustyXML.Native.streaming_memory(parser)
+
+      assert pending > 0
+      assert total == buffer + pending
+
+      sax = RustyXML.Native.streaming_sax_new()
+      assert %{total: _} = RustyXML.Native.streaming_memory(sax)
+    end
+  end
+
+  describe "parse_salvage/2" do
+    @cut "<feed><entry id=\"1\">a</entry><entry id=\"2\">b</entry><entry id=\"3\">c"
+
+    test "recovers the records before the cut point" do
+      doc = RustyXML.parse_salvage(@cut <> "</ent")
+
+      assert RustyXML.doc_truncated?(doc)
+      assert RustyXML.xpath(doc, ~x"//entry/@id"ls) == ["1", "2", "3"]
+      assert RustyXML.xpath(doc, ~x"//entry/text()"ls) == ["a", "b", "c"]
+    end
+
+    test "drops a start tag cut off midway" do
+      doc = RustyXML.parse_salvage("<feed><entry>a</entry><entry id=\"2")
+
+      assert RustyXML.doc_truncated?(doc)
+      assert RustyXML.xpath(doc, ~x"count(//entry)"i) == 1
+    end
+
+    test "complete documents are not truncated" do
+      refute RustyXML.doc_truncated?(RustyXML.parse_salvage("<feed><entry/></feed>"))
+      refute RustyXML.doc_truncated?(RustyXML.parse("<feed><entry/></feed>"))
+    end
+  end
+
+  describe "wrap_elements/3" do
+    test "joins streamed elements under a new root" do
+      xml = "<feed><item id=\"1\">a</item><skip/><item id=\"2\">b &amp; c</item></feed>"
+      elements = [xml] |> RustyXML.stream_tags(:item) |> Enum.to_list()
+
+      wrapped = RustyXML.wrap_elements(elements, :items, source: "feed & co")
+
+      assert wrapped ==
+               ~s(<items source="feed &amp; co"><item id="1">a</item>) <>
+                 ~s(<item id="2">b &amp; c</item></items>)
+
+      assert RustyXML.xpath(RustyXML.parse(wrapped), ~x"count(/items/item)"i) == 2
+      assert RustyXML.wrap_elements([], "empty") == "<empty></empty>"
+    end
+
+    test "rejects invalid names" do
+      assert_raise ArgumentError, fn -> RustyXML.wrap_elements(["<a/>"], "1root") end
+      assert_raise ArgumentError, fn -> RustyXML.wrap_elements(["<a/>"], "r", [{"a b", "v"}]) end
+
+      assert_raise ArgumentError, fn ->
+        RustyXML.wrap_elements(["<a/>"], "r", [{:id, "1"}, {"id", "2"}])
+      end
+    end
+
+    test "joins the chunks of oversized elements" do
+      big = "<item>#{String.duplicate("x", 100)}</item>"
+      xml = "<feed><item>a</item>#{big}</feed>"
+
+      elements =
+        [xml]
+        |> RustyXML.stream_tags(:item, max_element_size: 32, on_oversized: :chunk)
+        |> Enum.to_list()
+
+      assert Enum.any?(elements, &match?({:chunk, _, _}, &1))
+      assert RustyXML.wrap_elements(elements, :items) == "<items><item>a</item>#{big}</items>"
+
+      skipped = [xml] |> RustyXML.stream_tags(:item, max_element_size: 32) |> Enum.to_list()
+      assert RustyXML.wrap_elements(skipped, :items) == "<items><item>a</item></items>"
+    end
+  end
+
+  describe "split/3" do
+    test "wraps each shard of records in the original envelope" do
+      xml =
+        ~s(<?xml version="1.0"?><x:export xmlns:x="urn:x"><b n="1"><item>a</item>) <>
+          ~s(<item>b</item></b><b n="2"><item>c</item></b></x:export>)
+
+      doc = RustyXML.parse(xml)
+
+      assert [first, second] = RustyXML.split(doc, "//item", 2)
+
+      assert first ==
+               ~s(<?xml version="1.0"?><x:export xmlns:x="urn:x"><b n="1"><item>a</item>) <>
+                 ~s(<item>b</item></b></x:export>)
+
+      assert second ==
+               ~s(<?xml version="1.0"?><x:export xmlns:x="urn:x"><b n="2"><item>c</item></b>) <>
+                 "</x:export>"
+
+      assert RustyXML.xpath(RustyXML.parse(second), ~x"count(//item)"i) == 1
+      assert RustyXML.split(doc, "//none", 2) == []
+    end
+
+    test "raises for expressions that don't select nodes" do
+      doc = RustyXML.parse("<r/>")
+      assert_raise RustyXML.XPathError, fn -> RustyXML.split(doc, "count(//r)", 1) end
+    end
+  end
+
+  describe "redact/3" do
+    test "replaces selected content and attribute values" do
+      xml = ~s(<pay card="4111"><ssn>078<b>05</b></ssn><![CDATA[pin]]><note>ok</note></pay>)
+      doc = RustyXML.parse(xml)
+
+      assert RustyXML.redact(doc, ["//ssn", "//@card", "/pay/text()"], "<x>") ==
+               ~s(<pay card="&lt;x&gt;"><ssn>&lt;x&gt;</ssn>&lt;x&gt;<note>ok</note></pay>)
+
+      assert RustyXML.redact(doc, [], "***") == xml
+    end
+
+    test "raises for expressions that don't select nodes" do
+      doc = RustyXML.parse("<r/>")
+
+      error = assert_raise RustyXML.XPathError, fn -> RustyXML.redact(doc, ["count(//r)"], "") end
+      assert error.xpath == "count(//r)"
+    end
+
+    test "redacts attribute predicates, unions and empty elements" do
+      doc = RustyXML.parse(~s(<r card="4111" pw="s"><ssn/><a>x</a></r>))
+
+      assert RustyXML.redact(doc, ["//@card[. != '']", "//@*[name() = 'pw']"], "*") ==
+               ~s(<r card="*" pw="*"><ssn/><a>x</a></r>)
+
+      assert RustyXML.redact(doc, ["//r/attribute::card | //a", "//ssn"], "*") ==
+               ~s(<r card="*" pw="s"><ssn>*</ssn><a>*</a></r>)
+
+      assert_raise RustyXML.XPathError, fn -> RustyXML.redact(doc, ["//@*[1]"], "*") end
+    end
+  end
+
+  describe "infer_schema/1" do
+    test "describes structure and types across samples" do
+      docs = [
+        RustyXML.parse("<order id='1'><line>2</line><line>3</line></order>"),
+        RustyXML.parse("<order id='2'><when>2024-05-01</when></order>")
+      ]
+
+      xsd = RustyXML.infer_schema(docs)
+      assert xsd =~ ~s(<xs:element name="line" minOccurs="0" maxOccurs="unbounded" type="xs:)
+      assert xsd =~ ~s(<xs:element name="when" minOccurs="0" type="xs:date"/>)
+      assert xsd =~ ~s(<xs:attribute name="id" type="xs:integer" use="required"/>)
+
+      # The schema is a document itself
+      assert RustyXML.xpath(RustyXML.parse(xsd), ~x"count(//*[local-name() = 'element'])"i) == 3
+      assert RustyXML.infer_schema(hd(docs)) =~ ~s(maxOccurs="unbounded" type="xs:integer")
+    end
+  end
+
+  describe "normalize/2" do
+    test "serializations of the same document normalize identically" do
+      a = ~s(<r xmlns:p="urn:p" b="2" a='1'><p:e x="1"></p:e><!-- k='v' --></r>)
+      b = ~s(<r a="1"  b = '2'\n  xmlns:p='urn:p' ><p:e x='1' /><!-- k='v' --></r >)
+
+      assert RustyXML.normalize(a) == RustyXML.normalize(b)
+
+      assert RustyXML.normalize(a) ==
+               ~s(<r xmlns:p="urn:p" a="1" b="2"><p:e x="1"/><!-- k='v' --></r>)
+    end
+
+    test "writes empty elements expanded and rejects malformed input" do
+      assert RustyXML.normalize("<r><e/></r>", empty_elements: :expanded) == "<r><e></e></r>"
+      assert_raise RustyXML.ParseError, fn -> RustyXML.normalize("<r><e></r>") end
+    end
+  end
+
+  describe "RustyXML.SVG" do
+    @icon ~s(<?xml version="1.0"?>\n<svg xmlns="http://www.w3.org/2000/svg" width="24px" ) <>
+            ~s(height="24" viewBox="0,0 24 24"><defs><path d="M1 1&#10;L2 2"/></defs>) <>
+            ~s(<g><path d="M0 0h24v24H0z"/><path id="empty"/></g></svg>)
+
+    test "dimensions/1 reads the root's size attributes" do
+      assert RustyXML.SVG.dimensions(@icon) ==
+               %{width: "24px", height: "24", view_box: {0.0, 0.0, 24.0, 24.0}}
+
+      assert RustyXML.SVG.dimensions(~s(<svg viewBox="0 0 1"/>)) ==
+               %{width: nil, height: nil, view_box: nil}
+
+      assert RustyXML.SVG.dimensions("<html><svg width='1'/></html>") == nil
+    end
+
+    test "extract_paths/1 returns path data in document order" do
+      assert RustyXML.SVG.extract_paths(@icon) == ["M1 1\nL2 2", "M0 0h24v24H0z"]
+      assert RustyXML.SVG.extract_paths("<svg/>") == []
+    end
+  end
+
+  describe "repair/2" do
+    test "fixes malformed input so it parses" do
+      {fixed, repairs} = RustyXML.repair("<list><item id=1 done>Fish & Chips<item>a < b</list>")
+
+      assert fixed ==
+               ~s(<list><item id="1" done="done">Fish &amp; Chips) <>
+                 "<item>a &lt; b</item></item></list>"
+
+      assert Enum.map(repairs, &elem(&1, 0)) == [
+               :quoted_attribute_value,
+               :added_attribute_value,
+               :escaped_ampersand,
+               :escaped_less_than,
+               :closed_element,
+               :closed_element
+             ]
+
+      assert {:ok, _doc} = RustyXML.parse_document(fixed)
+    end
+
+    test "returns well-formed input unchanged" do
+      xml = ~s(<?xml version="1.0"?>\n<r a='1'><!-- c --><b>&amp;</b></r>)
+      assert RustyXML.repair(xml) == {xml, []}
+    end
+
+    test "wraps multiple top-level elements with :root" do
+      assert RustyXML.repair("<a/><b/>", root: "items") ==
+               {"<items><a/><b/></items>", [{:wrapped_root, 0}]}
+
+      assert RustyXML.repair("<a/><b/>") == {"<a/><b/>", []}
+    end
+  end
+
+  describe "parse_fragment/2" do
+    @rows "<tr><td>1</td></tr>\n<tr><td>2</td><td>3</td></tr>"
+
+    test "queries see the top-level elements as written" do
+      doc = RustyXML.parse_fragment(@rows)
+
+      assert RustyXML.xpath(doc, ~x"count(/tr)"i) == 2
+      assert RustyXML.xpath(doc, ~x"//td/text()"ls) == ["1", "2", "3"]
+      assert RustyXML.xpath(doc, ~x"/tr[2]/td[last()]/text()"s) == "3"
+      assert RustyXML.Native.count_elements(doc, "rustyxml-fragment") == 0
+      assert {:element, "tr", [], _} = RustyXML.root(doc)
+    end
+
+    test "accepts text, a single element and an XML declaration" do
+      doc = RustyXML.parse_fragment("a<b>x</b>c")
+      assert RustyXML.xpath(doc, ~x"/text()"ls) == ["a", "c"]
+      assert RustyXML.xpath(doc, ~x"string(/b)"s) == "x"
+
+      assert RustyXML.xpath(RustyXML.parse_fragment("<r/>"), ~x"count(/r)"i) == 1
+
+      doc = RustyXML.parse_fragment(~s(<?xml version="1.0"?><a/><b/>))
+      assert RustyXML.xpath(doc, ~x"count(/*)"i) == 2
+    end
+
+    test "malformed fragments raise unless lenient" do
+      assert_raise RustyXML.ParseError, "Unclosed tag: <a>", fn ->
+        RustyXML.parse_fragment("<a><b/>")
+      end
+
+      assert_raise RustyXML.ParseError, ~r/without matching start tag/, fn ->
+        RustyXML.parse_fragment("<a/></b>")
+      end
+
+      assert {:error, "Unclosed tag: <a>"} = RustyXML.Native.parse_fragment("<a>", false)
+      assert {:ok, _doc} = RustyXML.Native.parse_fragment("<a>", true)
+    end
+  end
+
+  describe "parse/2 error cases" do
+    test "raises ParseError for malformed XML in strict mode" do
+      assert_raise RustyXML.ParseError, fn ->
+        RustyXML.parse("<1invalid/>")
+      end
+    end
+
+    @tag :lenient_parsing
+    test "accepts malformed XML in lenient mode" do
+      doc = RustyXML.parse("<1invalid/>", lenient: true)
+      assert is_reference(doc)
+    end
+
+    @tag :lenient_parsing
+    test "handles empty input" do
+      doc = RustyXML.parse("", lenient: true)
+      assert is_reference(doc)
+    end
+  end
+
+  defp nested(depth) do
+    String.duplicate("<a>", depth) <> "x" <> String.duplicate("</a>", depth)
+  end
+
+  defp utf16(xml, :le), do: :unicode.characters_to_binary(xml, :utf8, {:utf16, :little})
+  defp utf16(xml, :be), do: :unicode.characters_to_binary(xml, :utf8, {:utf16, :big})
+  defp utf32(xml), do: :unicode.characters_to_binary(xml, :utf8, {:utf32, :big})
+
+  describe "encoding detection" do
+    test "Native.detect_encoding/1 reports the encoding and BOM" do
+      assert RustyXML.Native.detect_encoding("<r/>") == {"UTF-8", false}
+      assert RustyXML.Native.detect_encoding(<<0xEF, 0xBB, 0xBF, "<r/>">>) == {"UTF-8", true}
+      assert RustyXML.Native.detect_encoding(<<0xFF, 0xFE>> <> utf16("<r/>", :le)) ==
+               {"UTF-16LE", true}
+
+      assert RustyXML.Native.detect_encoding(utf16("\n<r/>", :be)) == {"UTF-16BE", false}
+      assert RustyXML.Native.detect_encoding(utf32("<r/>")) == {"UTF-32BE", false}
+      assert RustyXML.Native.detect_encoding(<<0x4C, 0x6F, 0xA7, 0x94>>) == {"EBCDIC", false}
+    end
+
+    test "UTF-16 without a BOM is converted" do
+      doc = RustyXML.parse(utf16("  <r>caf\u00E9</r>", :le))
+      assert RustyXML.xpath(doc, ~x"string(/r)"s) == "caf\u00E9"
+    end
+
+    test "lenient parsing converts UTF-16, tolerating malformed code units" do
+      xml = "<r><a>caf\u00E9</a></r>"
+
+      for input <- [<<0xFF, 0xFE>> <> utf16(xml, :le), utf16(xml, :be)] do
+        doc = RustyXML.parse(input, lenient: true)
+        assert RustyXML.xpath(doc, ~x"//a/text()"s) == "caf\u00E9"
+      end
+
+      # An unpaired surrogate and an odd trailing byte
+      xml = utf16("<r>a", :le) <> <<0x00, 0xD8>> <> utf16("</r>", :le) <> "x"
+      assert RustyXML.xpath(RustyXML.parse(xml, lenient: true), ~x"string(/r)"s) == "a\uFFFD"
+    end
+
+    test "parse_with_charset_hint/2 follows BOM, charset, then declaration" do
+      latin1 = <<"<?xml version='1.0' encoding='ISO-8859-1'?><r>caf", 0xE9, "</r>">>
+
+      assert {:ok, doc} = RustyXML.parse_with_charset_hint(latin1, "text/xml")
+      assert RustyXML.xpath(doc, ~x"string(/r)"s) == "caf\u00E9"
+
+      content_type = "text/xml; charset=latin1"
+      assert {:ok, doc} = RustyXML.parse_with_charset_hint(<<"<r>", 0xE9, "</r>">>, content_type)
+      assert RustyXML.xpath(doc, ~x"string(/r)"s) == "\u00E9"
+
+      assert {:error, _} = RustyXML.parse_with_charset_hint(latin1, "text/xml; charset=utf-8")
+
+      bom = <<0xFF, 0xFE>> <> utf16("<r>\u00E9</r>", :le)
+      assert {:ok, doc} = RustyXML.parse_with_charset_hint(bom, "text/xml; charset=iso-8859-1")
+      assert RustyXML.xpath(doc, ~x"string(/r)"s) == "\u00E9"
+
+      assert RustyXML.parse_with_charset_hint("<r/>", "text/xml; charset=klingon") ==
+               {:error, {:unsupported_encoding, "klingon"}}
+    end
+
+    test "UTF-32 and EBCDIC input is rejected with a structured error" do
+      assert RustyXML.parse_document(utf32("<r/>")) ==
+               {:error, {:unsupported_encoding, "UTF-32BE"}}
+
+      assert RustyXML.SimpleForm.parse_string(<<0x4C, 0x6F, 0xA7, 0x94, 0x93>>) ==
+               {:error, {:unsupported_encoding, "EBCDIC"}}
+
+      assert_raise RustyXML.ParseError, "Unsupported encoding: UTF-32BE", fn ->
+        RustyXML.parse(utf32("<r/>"))
+      end
+    end
+  end
+
+  describe "deeply nested documents" do
+    test "are rejected beyond the default maximum depth" do
+      xml = nested(10_001)
+
+      assert RustyXML.parse_document(xml) == {:error, {:max_depth_exceeded, 10_000}}
+      assert RustyXML.SimpleForm.parse_string(xml) == {:error, {:max_depth_exceeded, 10_000}}
+
+      assert_raise RustyXML.ParseError, ~r/maximum depth of 10000/, fn ->
+        RustyXML.parse(xml)
+      end
+
+      assert {:ok, _doc} = RustyXML.parse_document(nested(10_000))
+    end
+
+    test "convert to terms without recursing on the native stack" do
+      doc = RustyXML.parse(nested(100_000), lenient: true)
+
+      assert [{:element, "a", [], [{:element, "a", [], _}]}] =
+               RustyXML.Native.xpath_query(doc, "/a")
+
+      assert RustyXML.Native.xpath_query(doc, "string(/a)") == "x"
+    end
+  end
+
+  # ==========================================================================
+  # Native XPath Variants
+  # ==========================================================================
+
+  describe "Native.xpath_query_raw/2" do
+    test "returns list of XML binaries for node sets" do
+      doc = RustyXML.Native.parse("<root><item>text</item><item>more</item></root>")
+      result = RustyXML.Native.xpath_query_raw(doc, "//item")
+      assert is_list(result)
+      assert length(result) == 2
+      assert Enum.all?(result, &is_binary/1)
+    end
+
+    test "returns scalar for non-node-set queries" do
+      doc = RustyXML.Native.parse("<root><a/><b/></root>")
+      result = RustyXML.Native.xpath_query_raw(doc, "count(//a)")
+      assert result == 3.0 or is_number(result)
+    end
+  end
+
+  describe "Native.xpath_string_value/2" do
+    test "returns text content of first node" do
+      result = RustyXML.Native.xpath_string_value("<root>hello</root>", "/root")
+      assert result == "hello"
+    end
+
+    test "returns string for string expressions" do
+      result = RustyXML.Native.xpath_string_value("<root/>", "concat('a','b')")
+      assert result == "ab"
+    end
+
+    test "returns empty string for empty node set" do
+      result = RustyXML.Native.xpath_string_value("<root/>", "//nonexistent")
+      assert result == ""
+    end
+  end
+
+  describe "Native.xpath_string_value_doc/2" do
+    test "returns string value from document reference" do
+      doc = RustyXML.Native.parse("<root>world</root>")
+      result = RustyXML.Native.xpath_string_value_doc(doc, "/root")
+      assert result == "world"
+    end
+
+    test "returns concatenated text for elements with children" do
+      doc = RustyXML.Native.parse("<root><a>hello</a> <b>world</b></root>")
+      result = RustyXML.Native.xpath_string_value_doc(doc, "/root")
+      assert is_binary(result)
+      assert String.contains?(result, "hello")
+      assert String.contains?(result, "world")
+    end
+  end
+
+  # ==========================================================================
+  # xpath/3 with Subspecs
+  # ==========================================================================
+
+  describe "xpath/3 with subspecs" do
+    test "extracts nested values from raw XML" do
+      xml = """
+      <items>
+        <item id="1"><name>A</name></item>
+        <item id="2"><name>B</name></item>
+      </items>
+      """
+
+      result =
+        RustyXML.xpath(xml, ~x"//item"l, id: ~x"./@id"s, name: ~x"./name/text()"s)
+
+      assert is_list(result)
+      assert length(result) == 2
+    end
+
+    test "extracts nested values from document ref" do
+      xml = """
+      <items>
+        <item id="1"><name>A</name></item>
+        <item id="2"><name>B</name></item>
+      </items>
+      """
+
+      doc = RustyXML.parse(xml)
+
+      result =
+        RustyXML.xpath(doc, ~x"//item"l, id: ~x"./@id"s, name: ~x"./name/text()"s)
+
+      assert is_list(result)
+      assert length(result) == 2
+
+      # Each entry should be a map with atom keys
+      [first | _] = result
+      assert is_map(first)
+      assert Map.has_key?(first, :id)
+      assert Map.has_key?(first, :name)
+      assert first.id == "1"
+      assert first.name == "A"
+    end
+
+    test "works with single result (no l modifier)" do
+      xml = "<items><item id=\"1\"><name>A</name></item></items>"
+      doc = RustyXML.parse(xml)
+
+      result =
+        RustyXML.xpath(doc, ~x"//item", id: ~x"./@id"s, name: ~x"./name/text()"s)
+
+      assert is_map(result)
+      assert result.id == "1"
+      assert result.name == "A"
+    end
+
+    test "typed subspecs are converted in Rust" do
+      xml = """
+      <items>
+        <item id="1" active="true"><price>9.5</price><tag>a</tag><tag>b</tag></item>
+        <item id="2" active="0"><price>x</price></item>
+      </items>
+      """
+
+      for source <- [xml, RustyXML.parse(xml)] do
+        result =
+          RustyXML.xpath(source, ~x"//item"l, [
+            {:id, "./@id", :integer},
+            {:active, "./@active", :boolean},
+            {:price, "./price", :float},
+            {:tags, "./tag", :list},
+            {:name, ~x"./tag/text()"s}
+          ])
+
+        assert [
+                 %{id: 1, active: true, price: 9.5, tags: ["a", "b"], name: "a"},
+                 %{id: 2, active: false, price: nil, tags: []}
+               ] = result
+      end
+    end
+
+    test "@* and ./* subspecs return maps" do
+      xml = """
+      <items>
+        <item id="1" kind="a &amp; b"><name>A</name><qty>2</qty><name>A2</name></item>
+        <item><!-- none --></item>
+      </items>
+      """
+
+      for source <- [xml, RustyXML.parse(xml)] do
+        assert RustyXML.xpath(source, ~x"//item"l, attrs: "@*", fields: "./*") == [
+                 %{
+                   attrs: %{"id" => "1", "kind" => "a & b"},
+                   fields: %{"name" => "A", "qty" => "2"}
+                 },
+                 %{attrs: %{}, fields: %{}}
+               ]
+      end
+
+      assert [%{"attrs" => %{"id" => "1"}} | _] =
+               RustyXML.Native.xpath_with_subspecs(xml, "//item", [{"attrs", "@*"}])
+    end
+
+    test "typed subspecs reject unknown types" do
+      assert_raise ArgumentError, fn ->
+        RustyXML.xpath("<a><b/></a>", ~x"//b"l, [{:x, "./@x", :date}])
+      end
+    end
+  end
+
+  # ==========================================================================
+  # Soft Cast Runtime Behaviour
+  # ==========================================================================
+
+  describe "soft cast modifiers (S, I, F)" do
+    test "S returns nil for missing element" do
+      result = RustyXML.xpath("<root/>", ~x"//missing/text()"S)
+      assert result == nil
+    end
+
+    test "I returns nil for non-numeric text" do
+      result = RustyXML.xpath("<root><v>abc</v></root>", ~x"//v/text()"I)
+      assert result == nil
+    end
+
+    test "I parses valid integer" do
+      result = RustyXML.xpath("<root><v>42</v></root>", ~x"//v/text()"I)
+      assert result == 42
+    end
+
+    test "F returns nil for non-numeric text" do
+      result = RustyXML.xpath("<root><v>abc</v></root>", ~x"//v/text()"F)
+      assert result == nil
+    end
+
+    test "F parses valid float" do
+      result = RustyXML.xpath("<root><v>3.14</v></root>", ~x"//v/text()"F)
+      assert result == 3.14
+    end
+
+    test "hard i modifier raises on non-numeric text" do
+      assert_raise ArgumentError, fn ->
+        RustyXML.xpath("<root><v>abc</v></root>", ~x"//v/text()"i)
+      end
+    end
+
+    test "hard f modifier raises on non-numeric text" do
+      assert_raise ArgumentError, fn ->
+        RustyXML.xpath("<root><v>abc</v></root>", ~x"//v/text()"f)
+      end
+    end
+  end
+
+  # ==========================================================================
+  # transform_by/2 and add_namespace/3
+  # ==========================================================================
+
+  describe "transform_by/2" do
+    test "applies transform function to result" do
+      spec = RustyXML.transform_by(~x"//item/text()"s, &String.upcase/1)
+      result = RustyXML.xpath("<root><item>hello</item></root>", spec)
+      assert result == "HELLO"
+    end
+
+    test "applies transform after cast" do
+      spec = RustyXML.transform_by(~x"//n/text()"i, &(&1 * 2))
+      result = RustyXML.xpath("<root><n>5</n></root>", spec)
+      assert result == 10
+    end
+  end
+
+  describe "add_namespace/3" do
+    test "returns SweetXpath with namespace binding" do
+      spec = RustyXML.add_namespace(~x"//ns:item"l, "ns", "http://example.com")
+      assert %RustyXML.SweetXpath{} = spec
+      assert {"ns", "http://example.com"} in spec.namespaces
+    end
+  end
+
+  # ==========================================================================
+  # Streaming Element-Based APIs
+  # ==========================================================================
+
+  describe "Native.streaming_take_elements/2" do
+    test "returns list of XML binaries for complete elements" do
+      parser = RustyXML.Native.streaming_new_with_filter("item")
+      RustyXML.Native.streaming_feed(parser, "<root><item>A</item><item>B</item></root>")
+      elements = RustyXML.Native.streaming_take_elements(parser, 100)
+      assert is_list(elements)
+      assert length(elements) == 2
+      assert Enum.all?(elements, &is_binary/1)
+    end
+
+    test "respects max parameter" do
+      parser = RustyXML.Native.streaming_new_with_filter("item")
+      RustyXML.Native.streaming_feed(parser, "<root><item/><item/><item/></root>")
+      elements = RustyXML.Native.streaming_take_elements(parser, 1)
+      assert length(elements) == 1
+    end
+  end
 
   describe "Native.streaming_available_elements/1" do
     test "returns count of available elements" do
@@ -627,10 +2292,375 @@ defmodule RustyXMLTest do
     end
   end
 
+  # ==========================================================================
+  # Raw Text Mode
+  # ==========================================================================
+
+  describe "raw_text mode" do
+    defmodule RawTextCollector do
+      @behaviour RustyXML.Handler
+
+      def handle_event(type, data, acc) when type in [:start_element, :characters, :cdata],
+        do: {:ok, [{type, data} | acc]}
+
+      def handle_event(_type, _data, acc), do: {:ok, acc}
+    end
+
+    @raw_xml ~s(<r a="x &amp; y">1 &lt; 2<![CDATA[<b>]]></r>)
+
+    test "parse_string/4 delivers undecoded text with source spans" do
+      {:ok, events} = RustyXML.parse_string(@raw_xml, RawTextCollector, [], raw_text: true)
+
+      assert Enum.reverse(events) == [
+               {:start_element, {"r", [{"a", "x &amp; y"}]}},
+               {:characters, {"1 &lt; 2", {17, 8}}},
+               {:cdata, {"<b>", {34, 3}}}
+             ]
+
+      assert binary_part(@raw_xml, 17, 8) == "1 &lt; 2"
+    end
+
+    test "SimpleForm.parse_string/2 keeps text as in the source" do
+      assert RustyXML.SimpleForm.parse_string(@raw_xml, raw_text: true) ==
+               {:ok, {"r", [{"a", "x &amp; y"}], [{"1 &lt; 2", {17, 8}}, {"<b>", {34, 3}}]}}
+
+      assert RustyXML.SimpleForm.parse_string(@raw_xml) ==
+               {:ok, {"r", [{"a", "x & y"}], ["1 < 2", "<b>"]}}
+    end
+
+    test "spans account for a UTF-8 BOM" do
+      xml = <<0xEF, 0xBB, 0xBF>> <> "<r>a&amp;b</r>"
+
+      assert RustyXML.SimpleForm.parse_string(xml, raw_text: true) ==
+               {:ok, {"r", [], [{"a&amp;b", {6, 7}}]}}
+    end
+
+    test "spans of UTF-16 input are positions in the converted UTF-8" do
+      utf8 = "<r>é&amp;b</r>"
+      xml = <<0xFF, 0xFE>> <> :unicode.characters_to_binary(utf8, :utf8, {:utf16, :little})
+
+      assert {:ok, {"r", [], [{"é&amp;b", {3, 8} = span}]}} =
+               RustyXML.SimpleForm.parse_string(xml, raw_text: true)
+
+      assert :binary.part(utf8, span) == "é&amp;b"
+    end
+
+    test "SimpleForm.parse_stream/2 offsets span chunks" do
+      chunks = ["<r><a>x&amp;", "y</a></r>"]
+
+      assert RustyXML.SimpleForm.parse_stream(chunks, raw_text: true) ==
+               {:ok, {"r", [], [{"a", [], [{"x&amp;y", {6, 7}}]}]}}
+    end
+  end
+
+  # ==========================================================================
+  # Unicode Normalization
+  # ==========================================================================
+
+  describe "normalize option" do
+    alias RustyXMLTest.RawTextCollector
+
+    # "café" spelled with a combining acute accent, and precomposed
+    @nfd "cafe\u0301"
+    @nfc "caf\u00E9"
+    @mixed_xml ~s(<r n="#{@nfd}"><a>#{@nfd}</a><a>#{@nfc}</a><![CDATA[#{@nfd}]]></r>)
+
+    test "parse_string/4 normalizes text, CDATA and attribute values" do
+      {:ok, events} = RustyXML.parse_string(@mixed_xml, RawTextCollector, [], normalize: :nfc)
+
+      assert Enum.reverse(events) == [
+               {:start_element, {"r", [{"n", @nfc}]}},
+               {:start_element, {"a", []}},
+               {:characters, @nfc},
+               {:start_element, {"a", []}},
+               {:characters, @nfc},
+               {:cdata, @nfc}
+             ]
+
+      {:ok, events} = RustyXML.parse_string(@mixed_xml, RawTextCollector, [], normalize: :nfd)
+
+      assert {:characters, @nfd} in events
+      refute {:characters, @nfc} in events
+    end
+
+    test "parse_string/4 rejects :normalize with :raw_text" do
+      assert_raise ArgumentError, fn ->
+        RustyXML.parse_string(@mixed_xml, RawTextCollector, [], raw_text: true, normalize: :nfc)
+      end
+    end
+
+    test "parse_stream/4 rejects :normalize" do
+      assert_raise ArgumentError, fn ->
+        RustyXML.parse_stream([@mixed_xml], RawTextCollector, [], normalize: :nfc)
+      end
+    end
+
+    test "SimpleForm normalizes text and attribute values" do
+      expected = {:ok, {"r", [{"n", @nfc}], [{"a", [], [@nfc]}, {"a", [], [@nfc]}, @nfc]}}
+
+      assert RustyXML.SimpleForm.parse_string(@mixed_xml, normalize: :nfc) == expected
+
+      chunks = for <<byte <- @mixed_xml>>, do: <<byte>>
+      assert RustyXML.SimpleForm.parse_stream(chunks, normalize: :nfc) == expected
+
+      assert {:ok, {"r", _, [{"a", [], [@nfd]}, {"a", [], [@nfd]}, @nfd]}} =
+               RustyXML.SimpleForm.parse_string(@mixed_xml, normalize: :nfd)
+    end
+
+    test "parse/2 normalizes string values and XPath comparisons" do
+      doc = RustyXML.parse(@mixed_xml)
+      assert RustyXML.xpath(doc, ~x"count(//a[. = '#{@nfc}'])"i) == 1
+
+      doc = RustyXML.parse(@mixed_xml, normalize: :nfc)
+      assert RustyXML.xpath(doc, ~x"count(//a[. = '#{@nfc}'])"i) == 2
+      assert RustyXML.xpath(doc, ~x"count(//a[. = '#{@nfd}'])"i) == 2
+      assert RustyXML.Native.xpath_text_list(doc, "//a") == [@nfc, @nfc]
+      assert RustyXML.Native.xpath_string_value_doc(doc, "/r") == @nfc <> @nfc <> @nfc
+
+      assert RustyXML.Native.doc_set_normalization(doc, :none) == :ok
+      assert RustyXML.Native.xpath_text_list(doc, "//a") == [@nfd, @nfc]
+    end
+  end
+
   # ==========================================================================
   # XPath Error Handling
   # ==========================================================================
 
+  describe "precompute_text option" do
+    @catalog ~s(<c><p id="1">A &amp; <b>B</b><!-- x --></p><p id="2"><![CDATA[<C>]]></p><p/></c>)
+
+    test "string-values match the tree walk" do
+      plain = RustyXML.parse(@catalog)
+      precomputed = RustyXML.parse(@catalog, precompute_text: true)
+
+      for path <- ["string(/c)", "string(/c/p[1])", "string(/c/p[2])", "string(/c/p[3])"] do
+        expected = RustyXML.Native.xpath_query(plain, path)
+        assert RustyXML.Native.xpath_query(precomputed, path) == expected
+      end
+
+      assert RustyXML.Native.xpath_query(precomputed, "string(/c)") == "A & B<C>"
+      assert RustyXML.Native.xpath_text_list(precomputed, "//p") == ["A & B", "<C>", ""]
+      assert RustyXML.xpath(precomputed, ~x"//p[. = 'A & B']/@id"s) == "1"
+    end
+
+    test "combines with normalization and fragments" do
+      doc = RustyXML.parse("<r>cafe\u0301</r>", precompute_text: true, normalize: :nfc)
+      assert RustyXML.Native.xpath_query(doc, "string(/r)") == "caf\u00E9"
+
+      doc = RustyXML.parse_fragment("<a>1</a>x<a>2</a>", precompute_text: true)
+      assert RustyXML.xpath(doc, ~x"/a/text()"ls) == ["1", "2"]
+      assert RustyXML.Native.xpath_query(doc, "string(/a[2])") == "2"
+    end
+
+    test "doc_precompute_text/1 can be called again" do
+      doc = RustyXML.parse("<r><a>x</a></r>")
+      assert :ok = RustyXML.Native.doc_precompute_text(doc)
+      assert :ok = RustyXML.Native.doc_precompute_text(doc)
+      assert RustyXML.Native.xpath_query(doc, "string(/r)") == "x"
+    end
+  end
+
+  describe "Native.xpath_explain/1" do
+    test "describes the plan and strategy of each step" do
+      assert {:ok, plan} = RustyXML.Native.xpath_explain("//item[@id = 'a']/name")
+
+      assert plan == [
+               "root [document-node]",
+               "descendant::item [attribute-index-or-full-scan]",
+               "predicate [@id = 'a'] [attribute-compare]",
+               "child::name [child-walk]"
+             ]
+    end
+
+    test "returns an error for invalid expressions" do
+      assert {:error, _} = RustyXML.Native.xpath_explain("///invalid[[[")
+    end
+  end
+
+  describe "processing instructions and comments" do
+    setup do
+      xml = """
+      <?xml version="1.0"?>
+      <?route dest="billing"?>
+      <!-- generated -->
+      <policy><?route dest="audit"?><!-- rules --><rule/></policy>
+      """
+
+      %{doc: RustyXML.parse(xml)}
+    end
+
+    test "matches processing-instruction('target') anywhere in the document", %{doc: doc} do
+      assert [{:pi, "route", ~s(dest="billing")}, {:pi, "route", ~s(dest="audit")}] =
+               RustyXML.Native.xpath_query(doc, "//processing-instruction('route')")
+
+      assert RustyXML.Native.xpath_query(doc, "string(/processing-instruction('route'))") ==
+               ~s(dest="billing")
+
+      assert RustyXML.Native.xpath_query(doc, "string(/policy/processing-instruction())") ==
+               ~s(dest="audit")
+
+      assert RustyXML.Native.xpath_query(doc, "count(//processing-instruction('xml'))") == 0.0
+    end
+
+    test "matches comment() and uses comment content as string-value", %{doc: doc} do
+      assert RustyXML.Native.xpath_query(doc, "count(//comment())") == 2.0
+      assert RustyXML.Native.xpath_query(doc, "string(/comment())") == " generated "
+      assert RustyXML.Native.xpath_query(doc, "count(//comment()[contains(., 'rules')])") == 1.0
+    end
+
+    test "comments and PIs convert to terms with their text", %{doc: doc} do
+      assert RustyXML.Native.xpath_query(doc, "//comment()") ==
+               [{:comment, " generated "}, {:comment, " rules "}]
+
+      assert RustyXML.Native.xpath_text_list(doc, "//comment() | //processing-instruction()") ==
+               [~s(dest="billing"), " generated ", ~s(dest="audit"), " rules "]
+
+      assert RustyXML.xpath(doc, ~x"//comment()"ls) == [" generated ", " rules "]
+      assert RustyXML.xpath(doc, ~x"/policy"s) == ""
+
+      assert {:element, "policy", [], [{:pi, "route", _}, {:comment, " rules "}, _]} =
+               RustyXML.root(doc)
+
+      assert RustyXML.Native.xpath_query_raw(doc, "/policy/node()[position() < 3]") ==
+               [~s(<?route dest="audit"?>), "<!-- rules -->"]
+    end
+  end
+
+  describe "Native.doc_info/1" do
+    test "reports the XML declaration and DOCTYPE" do
+      xml = """
+      <?xml version="1.0" encoding="UTF-8" standalone="no"?>
+      <!DOCTYPE html PUBLIC "-//W3C//DTD XHTML 1.0 Strict//EN" "xhtml1-strict.dtd">
+      <html><body/></html>
+      """
+
+      assert RustyXML.Native.doc_info(RustyXML.parse(xml)) == %{
+               root: "html",
+               version: "1.0",
+               encoding: "UTF-8",
+               standalone: false,
+               doctype: %{
+                 name: "html",
+                 public_id: "-//W3C//DTD XHTML 1.0 Strict//EN",
+                 system_id: "xhtml1-strict.dtd"
+               }
+             }
+    end
+
+    test "leaves undeclared fields nil" do
+      assert RustyXML.Native.doc_info(RustyXML.parse("<ns:feed xmlns:ns='urn:x'/>")) == %{
+               root: "ns:feed",
+               version: nil,
+               encoding: nil,
+               standalone: nil,
+               doctype: nil
+             }
+
+      fragment = RustyXML.parse_fragment("<tr/><tr/>")
+      assert %{root: "tr", doctype: nil} = RustyXML.Native.doc_info(fragment)
+    end
+  end
+
+  describe "Native.doc_tag_paths/1" do
+    test "counts each distinct element path" do
+      doc =
+        RustyXML.parse(
+          "<catalog><product><name>a</name><price>1</price></product>" <>
+            "<product><price>2</price></product><meta><name/></meta></catalog>"
+        )
+
+      assert RustyXML.Native.doc_tag_paths(doc) == [
+               {"catalog", 1},
+               {"catalog/product", 2},
+               {"catalog/product/name", 1},
+               {"catalog/product/price", 2},
+               {"catalog/meta", 1},
+               {"catalog/meta/name", 1}
+             ]
+
+      fragment = RustyXML.parse_fragment("<tr><td/></tr><tr/>")
+      assert RustyXML.Native.doc_tag_paths(fragment) == [{"tr", 2}, {"tr/td", 1}]
+    end
+  end
+
+  describe "parse/2 with namespaces: true" do
+    test "rejects attributes duplicated through different prefixes" do
+      xml = ~s(<r xmlns:a="urn:x" xmlns:b="urn:x"><e a:id="1" b:id="2"/></r>)
+
+      assert {:ok, _} = RustyXML.parse_document(xml)
+
+      error = assert_raise RustyXML.ParseError, fn -> RustyXML.parse(xml, namespaces: true) end
+      assert error.message =~ "Duplicate attribute: a:id and b:id"
+    end
+
+    test "accepts the same local name in different namespaces" do
+      xml = ~s(<r xmlns:a="urn:x" xmlns:b="urn:y" a:id="1" b:id="2"/>)
+      assert {:ok, _} = RustyXML.Native.parse_strict_namespaces(xml)
+    end
+
+    test "rejects undeclared prefixes" do
+      assert {:ok, _} = RustyXML.parse_document("<a:r/>")
+
+      assert RustyXML.Native.parse_strict_namespaces("<a:r/>") ==
+               {:error, "Undeclared namespace prefix 'a' on element <a:r>"}
+    end
+  end
+
+  describe "Native.validate_namespaces/1" do
+    test "reports every violation with its element path" do
+      xml = """
+      <feed xmlns:a="urn:a">
+        <entry a:id="1"/>
+        <entry xmlns:c="" b:id="2"/>
+      </feed>
+      """
+
+      assert {:error, errors} = RustyXML.Native.validate_namespaces(xml)
+
+      assert [
+               %{path: "/feed[1]/entry[2]", message: "Namespace prefix 'c' cannot" <> _},
+               %{path: "/feed[1]/entry[2]", message: "Undeclared namespace prefix 'b'" <> _}
+             ] = errors
+    end
+
+    test "accepts namespace-well-formed documents" do
+      xml = ~s(<r xmlns="urn:d" xml:lang="en"><x:a xmlns:x="urn:x" x:b="1"/></r>)
+      assert RustyXML.Native.validate_namespaces(xml) == :ok
+    end
+  end
+
+  describe "document sets" do
+    test "queries every document in one call" do
+      set = RustyXML.Native.docset_new()
+
+      ids =
+        for level <- ["info", "error", "info", "error"] do
+          xml = ~s(<log level="#{level}"><msg>m</msg></log>)
+          {:ok, id} = RustyXML.Native.docset_add(set, xml)
+          id
+        end
+
+      assert ids == [0, 1, 2, 3]
+      assert RustyXML.Native.docset_size(set) == 4
+
+      assert [{1, [_]}, {3, [_]}] = RustyXML.Native.docset_query(set, "/log[@level = 'error']")
+      levels = RustyXML.Native.docset_query(set, "string(/log/@level)")
+      assert [{0, "info"}, {1, "error"}, {2, "info"}, {3, "error"}] = levels
+    end
+
+    test "rejects malformed documents and XPath" do
+      set = RustyXML.Native.docset_new()
+
+      assert {:error, _} = RustyXML.Native.docset_add(set, "<a><b></a>")
+      assert RustyXML.Native.docset_size(set) == 0
+      assert RustyXML.Native.docset_query(set, "//a") == []
+
+      {:ok, 0} = RustyXML.Native.docset_add(set, "<a/>")
+      assert {:error, reason} = RustyXML.Native.docset_query(set, "///invalid[[[")
+      assert is_binary(reason)
+    end
+  end
+
   describe "XPath error handling" do
     test "returns {:error, reason} for malformed XPath on raw XML" do
       result = RustyXML.Native.parse_and_xpath("<root/>", "///invalid[[[")
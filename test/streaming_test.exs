@@ -71,6 +71,396 @@ defmodule RustyXML.StreamingTest do
       # elements_only mode: no events generated (avoids memory bloat)
       assert RustyXML.Native.streaming_available_elements(parser) == 0
     end
+
+    test "size limit reports oversized elements in skip mode" do
+      parser = RustyXML.Native.streaming_new_with_limit("item", 16, :skip)
+
+      RustyXML.Native.streaming_feed(parser, "<root><item>ok</item><item>")
+      RustyXML.Native.streaming_feed(parser, String.duplicate("x", 100))
+      RustyXML.Native.streaming_feed(parser, "</item></root>")
+
+      assert RustyXML.Native.streaming_take_elements(parser, 100) == [
+               "<item>ok</item>",
+               {:oversized, "item", 113}
+             ]
+    end
+
+    test "size limit streams oversized elements in chunk mode" do
+      parser = RustyXML.Native.streaming_new_with_limit("item", 16, :chunk)
+
+      RustyXML.Native.streaming_feed(parser, "<root><item>")
+      RustyXML.Native.streaming_feed(parser, String.duplicate("x", 40))
+      RustyXML.Native.streaming_feed(parser, "</item></root>")
+
+      elements = RustyXML.Native.streaming_take_elements(parser, 100)
+      {chunks, [{:chunk_end, "item", size}]} = Enum.split(elements, -1)
+
+      xml = Enum.map_join(chunks, fn {:chunk, "item", data} -> data end)
+      assert xml == "<item>" <> String.duplicate("x", 40) <> "</item>"
+      assert size == byte_size(xml)
+    end
+  end
+
+  describe "parse_stream/4 with :text_chunk_size" do
+    defmodule ChunkCollector do
+      @behaviour RustyXML.Handler
+
+      @impl true
+      def handle_event(type, data, acc) when type in [:characters_chunk, :cdata_chunk],
+        do: {:ok, [{type, data} | acc]}
+
+      def handle_event(:cdata, data, acc), do: {:ok, [{:cdata, data} | acc]}
+      def handle_event(_type, _data, acc), do: {:ok, acc}
+    end
+
+    test "emits a CDATA section split across chunks in bounded pieces" do
+      payload = String.duplicate("QUJD", 50)
+      chunks = ["<doc><data><![CDATA[", payload, "]]></data></doc>"]
+
+      {:ok, events} = RustyXML.parse_stream(chunks, ChunkCollector, [], text_chunk_size: 64)
+      events = Enum.reverse(events)
+
+      assert Enum.all?(events, fn {type, {data, _}} ->
+               type == :cdata_chunk and byte_size(data) <= 64
+             end)
+
+      assert {_, {_, true}} = List.last(events)
+      assert Enum.map_join(events, fn {_, {data, _}} -> data end) == payload
+    end
+
+    test "small CDATA sections stay regular events" do
+      {:ok, events} =
+        RustyXML.parse_stream(["<doc><![CDATA[tiny]]></doc>"], ChunkCollector, [],
+          text_chunk_size: 64
+        )
+
+      assert events == [{:cdata, "tiny"}]
+    end
+
+    test "CDATA markers inside comments and PIs don't open a section" do
+      chunks = [
+        "<doc><!-- <![CDATA[ --><a>te",
+        "xt</a><?pi <![CDATA[ ?><b>more</b></doc>"
+      ]
+
+      assert RustyXML.parse_stream(chunks, ChunkCollector, [], text_chunk_size: 64) == {:ok, []}
+
+      assert RustyXML.parse_stream(chunks, __MODULE__.EventCollector, [], text_chunk_size: 64) ==
+               RustyXML.parse_stream(chunks, __MODULE__.EventCollector, [])
+    end
+  end
+
+  describe "parse_stream/4 with event_format: :v2" do
+    defmodule EventCollector do
+      @behaviour RustyXML.Handler
+
+      @impl true
+      def handle_event(type, data, acc), do: {:ok, [{type, data} | acc]}
+    end
+
+    test "delivers the same events as the v1 format" do
+      rows = for i <- 1..300, do: ~s(<row id="#{i}" kind="k"><cell>v&amp;#{i}</cell></row>)
+      xml = "<table>" <> Enum.join(rows) <> "<![CDATA[<raw>]]></table>"
+      chunks = for <<chunk::binary-size(512) <- xml>>, do: chunk
+      chunks = chunks ++ [binary_part(xml, length(chunks) * 512, rem(byte_size(xml), 512))]
+
+      assert RustyXML.parse_stream(chunks, EventCollector, [], event_format: :v2) ==
+               RustyXML.parse_stream(chunks, EventCollector, [])
+
+      assert RustyXML.parse_stream(chunks, EventCollector, [],
+               event_format: :v2,
+               text_chunk_size: 4
+             ) == RustyXML.parse_stream(chunks, EventCollector, [], text_chunk_size: 4)
+    end
+
+    test "encodes repeated names once per binary with varint lengths" do
+      xml = "<root>" <> String.duplicate(~s(<item name="x"/>), 100) <> "</root>"
+
+      v1 = RustyXML.Native.streaming_sax_new()
+      v2 = RustyXML.Native.streaming_sax_new()
+      assert :ok = RustyXML.Native.streaming_sax_set_format(v2, 2)
+
+      v1_size =
+        byte_size(RustyXML.Native.streaming_feed_sax(v1, xml, false)) +
+          byte_size(RustyXML.Native.streaming_finalize_sax(v1, false))
+
+      encoded = RustyXML.Native.streaming_feed_sax(v2, xml, false)
+      assert <<1, 0, 4, "root", 0, _::binary>> = encoded
+
+      v2_size = byte_size(encoded) + byte_size(RustyXML.Native.streaming_finalize_sax(v2, false))
+      assert v2_size * 2 < v1_size
+    end
+
+    test "rejects unknown formats" do
+      assert_raise ArgumentError, fn ->
+        RustyXML.Native.streaming_sax_set_format(RustyXML.Native.streaming_sax_new(), 3)
+      end
+
+      assert_raise ArgumentError, ~r/:event_format/, fn ->
+        RustyXML.parse_stream(["<a/>"], EventCollector, [], event_format: :v3)
+      end
+    end
+  end
+
+  describe "Native.sax_parse/2" do
+    @xml "<?pi x?><r>\n  <!-- c --><a>t<b>deep</b></a>\n  <c/>\n</r>"
+
+    test "with no options returns every event" do
+      assert RustyXML.Native.sax_parse(@xml, []) == RustyXML.Native.sax_parse(@xml)
+    end
+
+    test "leaves out the excluded event classes" do
+      opts = [skip_comments: true, skip_processing_instructions: true, skip_whitespace: true]
+
+      assert RustyXML.Native.sax_parse(@xml, [{:max_depth, 2} | opts]) == [
+               {:start_element, "r", []},
+               {:start_element, "a", []},
+               {:end_element, "a"},
+               {:start_element, "c", []},
+               {:end_element, "c"},
+               {:end_element, "r"}
+             ]
+
+      events = RustyXML.Native.sax_parse(@xml, opts)
+      assert {:characters, "deep"} in events
+      refute Enum.any?(events, &match?({:characters, "\n" <> _}, &1))
+
+      assert RustyXML.Native.sax_parse(@xml, max_depth: 1) == [
+               {:processing_instruction, "pi", "x"},
+               {:start_element, "r", []},
+               {:end_element, "r"}
+             ]
+    end
+
+    test "rejects unknown options" do
+      assert_raise ArgumentError, fn -> RustyXML.Native.sax_parse(@xml, skip_text: true) end
+    end
+  end
+
+  describe "large attribute values" do
+    defmodule AttrCollector do
+      @behaviour RustyXML.Handler
+
+      @impl true
+      def handle_event(:start_element, {_name, attrs}, acc), do: {:ok, [attrs | acc]}
+      def handle_event(_type, _data, acc), do: {:ok, acc}
+    end
+
+    @image String.duplicate("iVBORw0KGgo", 20_000)
+
+    test "parse_stream/4 delivers values longer than 64 KiB intact" do
+      xml = ~s(<doc><img src="#{@image}" alt="a"/><img src="small"/></doc>)
+      chunks = for <<chunk::binary-size(4096) <- xml>>, do: chunk
+      chunks = chunks ++ [binary_part(xml, length(chunks) * 4096, rem(byte_size(xml), 4096))]
+
+      for format <- [:v1, :v2] do
+        {:ok, events} = RustyXML.parse_stream(chunks, AttrCollector, [], event_format: format)
+
+        assert Enum.reverse(events) ==
+                 [[], [{"src", @image}, {"alt", "a"}], [{"src", "small"}]]
+      end
+    end
+
+    test "sax_parse/1 returns large values as sub-binaries of the input" do
+      xml = ~s(<doc><img src="#{@image}" alt="a"/></doc>)
+
+      [_, {:start_element, "img", [{"src", src}, {"alt", alt}]} | _] =
+        RustyXML.Native.sax_parse(xml)
+
+      assert src == @image
+      assert :binary.referenced_byte_size(src) == byte_size(xml)
+      assert :binary.referenced_byte_size(alt) == 1
+
+      [_, {:start_element, {"img", [{"src", src}, _]}} | _] =
+        RustyXML.Native.sax_parse_saxy(xml, false)
+
+      assert :binary.referenced_byte_size(src) == byte_size(xml)
+    end
+  end
+
+  describe "parse_stream/4 with :duplicate_ids" do
+    defmodule DuplicateCollector do
+      @behaviour RustyXML.Handler
+
+      @impl true
+      def handle_event(:start_element, {name, _attrs}, acc), do: {:ok, [name | acc]}
+      def handle_event(:duplicate_id, data, acc), do: {:ok, [data | acc]}
+      def handle_event(_type, _data, acc), do: {:ok, acc}
+    end
+
+    @ids_xml ~s(<r><a id="x"/><b xml:id="y"><c id="x">t</c></b><d id="y"/><e id="x"/></r>)
+
+    test "reports each repeat after the element carrying it" do
+      chunks = for <<byte::binary-size(1) <- @ids_xml>>, do: byte
+
+      for format <- [:v1, :v2], input <- [[@ids_xml], chunks] do
+        {:ok, events} =
+          RustyXML.parse_stream(input, DuplicateCollector, [],
+            duplicate_ids: true,
+            event_format: format
+          )
+
+        assert Enum.reverse(events) ==
+                 ["r", "a", "b", "c", {"x", 2}, "d", {"y", 2}, "e", {"x", 3}]
+      end
+    end
+
+    test "is off by default" do
+      {:ok, events} = RustyXML.parse_stream([@ids_xml], DuplicateCollector, [])
+      assert Enum.all?(events, &is_binary/1)
+    end
+  end
+
+  describe "parse_stream/4 with :max_tail_size" do
+    @handler __MODULE__.EventCollector
+
+    test "stops on a tag that outgrows the limit" do
+      attrs = for i <- 1..100, do: ~s( a#{i}="#{String.duplicate("x", 20)}")
+      chunks = ["<r><item"] ++ attrs ++ ["/></r>"]
+
+      assert RustyXML.parse_stream(chunks, @handler, [], max_tail_size: 512) ==
+               {:error, {:max_tail_size_exceeded, 512}}
+
+      assert {:ok, _} = RustyXML.parse_stream(chunks, @handler, [], max_tail_size: 0)
+    end
+
+    test "does not limit content spread over many chunks" do
+      items = for i <- 1..200, do: ~s(<item id="#{i}">#{i}</item>)
+      chunks = ["<r>"] ++ items ++ ["</r>"]
+
+      assert {:ok, events} = RustyXML.parse_stream(chunks, @handler, [], max_tail_size: 64)
+      assert length(events) > 200
+    end
+  end
+
+  describe "parse_stream/4 with :max_attributes and :max_elements" do
+    @handler __MODULE__.EventCollector
+    @limits_xml ~s(<r><a x="1" y="2" z="3"/><b x="1"/><c/></r>)
+
+    test "stops on an element over a limit" do
+      assert RustyXML.parse_stream([@limits_xml], @handler, [], max_attributes: 2) ==
+               {:error, {:attribute_limit_exceeded, 2}}
+
+      assert RustyXML.parse_stream([@limits_xml], @handler, [], max_elements: 3) ==
+               {:error, {:element_limit_exceeded, 3}}
+
+      assert {:ok, _} =
+               RustyXML.parse_stream([@limits_xml], @handler, [],
+                 max_attributes: 3,
+                 max_elements: 4
+               )
+    end
+
+    test "reports warnings and continues with :on_limit :warn" do
+      chunks = for <<byte::binary-size(1) <- @limits_xml>>, do: byte
+
+      for format <- [:v1, :v2], input <- [[@limits_xml], chunks] do
+        {:ok, events} =
+          RustyXML.parse_stream(input, @handler, [],
+            max_attributes: 2,
+            max_elements: 3,
+            on_limit: :warn,
+            event_format: format
+          )
+
+        events = Enum.reverse(events)
+
+        assert {:start_element, {"a", [{"x", "1"}, {"y", "2"}]}} in events
+        assert {:warning, {:attribute_limit_exceeded, "a", 3}} in events
+        assert {:warning, {:element_limit_exceeded, "c", 4}} in events
+        assert List.last(events) == {:end_document, {}}
+      end
+    end
+
+    test "parse_string/4 applies the limits too" do
+      assert RustyXML.parse_string(@limits_xml, @handler, [], max_attributes: 2) ==
+               {:error, {:attribute_limit_exceeded, 2}}
+
+      assert {:ok, events} =
+               RustyXML.parse_string(@limits_xml, @handler, [], max_elements: 3, on_limit: :warn)
+
+      assert {:warning, {:element_limit_exceeded, "c", 4}} in events
+
+      assert_raise ArgumentError, fn ->
+        RustyXML.parse_string(@limits_xml, @handler, [], max_elements: 3, raw_text: true)
+      end
+    end
+
+    test "rejects an unknown :on_limit" do
+      assert_raise ArgumentError, fn ->
+        RustyXML.parse_stream([@limits_xml], @handler, [], on_limit: :ignore)
+      end
+    end
+  end
+
+  describe "Native.sax_parse_send/3" do
+    test "sends event batches followed by :done" do
+      xml = "<items>" <> String.duplicate("<item id=\"1\">x</item>", 10) <> "</items>"
+      handle = RustyXML.Native.sax_parse_send(xml, self(), 8)
+
+      batches = collect_sax_batches(handle, [])
+      assert length(batches) == 4
+      assert Enum.all?(batches, &(length(&1) <= 9))
+
+      assert List.flatten(batches) == RustyXML.Native.sax_parse(xml)
+    end
+
+    test "rejects a zero batch size" do
+      assert_raise ArgumentError, fn -> RustyXML.Native.sax_parse_send("<a/>", self(), 0) end
+    end
+  end
+
+  describe "Native.sax_parse_send_window/4" do
+    test "pauses until batches are acknowledged" do
+      xml = "<items>" <> String.duplicate("<item/>", 20) <> "</items>"
+      handle = RustyXML.Native.sax_parse_send_window(xml, self(), 4, 2)
+
+      assert_receive {:rustyxml_sax, ^handle, {:events, first}}, 5_000
+      assert_receive {:rustyxml_sax, ^handle, {:events, second}}, 5_000
+      refute_receive {:rustyxml_sax, ^handle, _}, 100
+
+      assert RustyXML.Native.sax_send_ack(handle, 2) == :ok
+      rest = collect_sax_batches(handle, [], ack: true)
+
+      assert first ++ second ++ List.flatten(rest) == RustyXML.Native.sax_parse(xml)
+    end
+
+    test "rejects a zero window" do
+      assert_raise ArgumentError, fn ->
+        RustyXML.Native.sax_parse_send_window("<a/>", self(), 1, 0)
+      end
+    end
+
+    test "ends with a timeout when batches go unacknowledged" do
+      xml = "<items>" <> String.duplicate("<item/>", 20) <> "</items>"
+      handle = RustyXML.Native.sax_parse_send_window(xml, self(), 4, 1, 50)
+
+      assert_receive {:rustyxml_sax, ^handle, {:events, _}}, 5_000
+      assert_receive {:rustyxml_sax, ^handle, {:error, :timeout}}, 5_000
+      refute_receive {:rustyxml_sax, ^handle, _}, 100
+    end
+
+    test "sax_send_cancel/1 stops a paused stream" do
+      xml = "<items>" <> String.duplicate("<item/>", 20) <> "</items>"
+      handle = RustyXML.Native.sax_parse_send_window(xml, self(), 4, 1)
+
+      assert_receive {:rustyxml_sax, ^handle, {:events, _}}, 5_000
+      assert RustyXML.Native.sax_send_cancel(handle) == :ok
+      refute_receive {:rustyxml_sax, ^handle, _}, 200
+    end
+  end
+
+  defp collect_sax_batches(handle, acc, opts \\ []) do
+    receive do
+      {:rustyxml_sax, ^handle, {:events, events}} ->
+        if opts[:ack], do: :ok = RustyXML.Native.sax_send_ack(handle, 1)
+        collect_sax_batches(handle, [events | acc], opts)
+
+      {:rustyxml_sax, ^handle, :done} ->
+        Enum.reverse(acc)
+    after
+      5_000 -> flunk("timed out waiting for SAX batches")
+    end
   end
 
   describe "stream_tags/3 high-level API" do
@@ -115,5 +505,89 @@ defmodule RustyXML.StreamingTest do
 
       assert is_list(events)
     end
+
+    test "count_tags counts without materializing elements", %{path: path} do
+      assert RustyXML.count_tags(path, :item) == 3
+      assert RustyXML.count_tags(path, "name", chunk_size: 7) == 3
+
+      big = String.duplicate("x", 100_000)
+      chunks = ["<db><item>", big, "</item><item/><other><item/></other>", "<item>open"]
+      assert RustyXML.count_tags(chunks, :item) == 3
+      assert RustyXML.count_tags("<db><item/></db>", :missing) == 0
+    end
+
+    test "streaming_count_elements counts taken and pending elements" do
+      parser = RustyXML.Native.streaming_new_with_filter("item")
+      RustyXML.Native.streaming_feed(parser, "<r><item>1</item><item/><item>")
+      assert [_, _] = RustyXML.Native.streaming_take_elements(parser, 10)
+      RustyXML.Native.streaming_feed(parser, "3</item>")
+      assert RustyXML.Native.streaming_count_elements(parser) == 3
+    end
+
+    test "max_element_size skips oversized elements" do
+      big = String.duplicate("x", 1000)
+      xml = "<root><item>1</item><item>#{big}</item><item>3</item></root>"
+
+      result =
+        [xml]
+        |> RustyXML.stream_tags(:item, max_element_size: 100)
+        |> Enum.to_list()
+
+      assert [{:item, "<item>1</item>"}, {:oversized, "item", _}, {:item, "<item>3</item>"}] =
+               result
+    end
+  end
+
+  describe "SimpleForm.parse_stream/2 with :validate_early" do
+    test "stops consuming the stream at the first malformed chunk" do
+      test_pid = self()
+
+      chunks =
+        Stream.map(["<root><a>", "</b>", "<c/>", "</root>"], fn chunk ->
+          send(test_pid, {:chunk, chunk})
+          chunk
+        end)
+
+      assert {:error, "Tag mismatch: <a> closed with </b>"} =
+               RustyXML.SimpleForm.parse_stream(chunks, validate_early: true)
+
+      assert_received {:chunk, "</b>"}
+      refute_received {:chunk, "<c/>"}
+    end
+
+    test "parses well-formed streams" do
+      chunks = ["<root a=", "\"1\"><child>te", "xt</child></ro", "ot>"]
+
+      assert RustyXML.SimpleForm.parse_stream(chunks, validate_early: true) ==
+               RustyXML.SimpleForm.parse_string(Enum.join(chunks))
+    end
+
+    test "accumulator_feed_validated returns the same error after failing" do
+      acc = RustyXML.Native.accumulator_new()
+
+      assert RustyXML.Native.accumulator_feed_validated(acc, "<a x='1' x='2'>") ==
+               {:error, "Duplicate attribute: x"}
+
+      assert RustyXML.Native.accumulator_feed_validated(acc, "</a>") ==
+               {:error, "Duplicate attribute: x"}
+    end
+  end
+
+  describe "accumulator snapshots" do
+    test "restore continues where the snapshot left off" do
+      acc = RustyXML.Native.accumulator_new()
+      :ok = RustyXML.Native.accumulator_feed_validated(acc, "<root><item>1</item><it")
+      {:ok, snapshot} = RustyXML.Native.accumulator_snapshot(acc)
+
+      {:ok, restored} = RustyXML.Native.accumulator_restore(snapshot)
+      :ok = RustyXML.Native.accumulator_feed_validated(restored, "em>2</item></root>")
+
+      assert {:ok, {"root", [], [{"item", [], ["1"]}, {"item", [], ["2"]}]}} =
+               RustyXML.Native.accumulator_to_simple_form(restored)
+    end
+
+    test "rejects invalid snapshots" do
+      assert {:error, _} = RustyXML.Native.accumulator_restore("not a snapshot")
+    end
   end
 end
@@ -1 +1 @@
-ExUnit.start()
+ExUnit.start(exclude: [:slow])